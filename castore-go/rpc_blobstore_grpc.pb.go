@@ -22,9 +22,10 @@ import (
 const _ = grpc.SupportPackageIsVersion7
 
 const (
-	BlobService_Stat_FullMethodName = "/tvix.castore.v1.BlobService/Stat"
-	BlobService_Read_FullMethodName = "/tvix.castore.v1.BlobService/Read"
-	BlobService_Put_FullMethodName  = "/tvix.castore.v1.BlobService/Put"
+	BlobService_Stat_FullMethodName    = "/tvix.castore.v1.BlobService/Stat"
+	BlobService_Read_FullMethodName    = "/tvix.castore.v1.BlobService/Read"
+	BlobService_Put_FullMethodName     = "/tvix.castore.v1.BlobService/Put"
+	BlobService_HasMany_FullMethodName = "/tvix.castore.v1.BlobService/HasMany"
 )
 
 // BlobServiceClient is the client API for BlobService service.
@@ -47,6 +48,8 @@ type BlobServiceClient interface {
 	// The server may decide on whatever chunking it may seem fit as a size for
 	// the individual BlobChunk sent in the response stream, this is mostly to
 	// keep individual messages at a manageable size.
+	// `offset` and `length` in the `ReadBlobRequest` can be used to only read
+	// a range of the blob.
 	Read(ctx context.Context, in *ReadBlobRequest, opts ...grpc.CallOption) (BlobService_ReadClient, error)
 	// Put uploads a Blob, by reading a stream of bytes.
 	//
@@ -54,6 +57,11 @@ type BlobServiceClient interface {
 	// the stream has no effect on how the server ends up chunking blobs up, if
 	// it does at all.
 	Put(ctx context.Context, opts ...grpc.CallOption) (BlobService_PutClient, error)
+	// HasMany checks for the existence of many blobs (or chunks) at once.
+	// For every HasBlobRequest sent, a HasBlobResponse is returned, in the same
+	// order.
+	// This saves a roundtrip per blob compared to individual `Stat` requests.
+	HasMany(ctx context.Context, opts ...grpc.CallOption) (BlobService_HasManyClient, error)
 }
 
 type blobServiceClient struct {
@@ -139,6 +147,37 @@ func (x *blobServicePutClient) CloseAndRecv() (*PutBlobResponse, error) {
 	return m, nil
 }
 
+func (c *blobServiceClient) HasMany(ctx context.Context, opts ...grpc.CallOption) (BlobService_HasManyClient, error) {
+	stream, err := c.cc.NewStream(ctx, &BlobService_ServiceDesc.Streams[2], BlobService_HasMany_FullMethodName, opts...)
+	if err != nil {
+		return nil, err
+	}
+	x := &blobServiceHasManyClient{stream}
+	return x, nil
+}
+
+type BlobService_HasManyClient interface {
+	Send(*HasBlobRequest) error
+	Recv() (*HasBlobResponse, error)
+	grpc.ClientStream
+}
+
+type blobServiceHasManyClient struct {
+	grpc.ClientStream
+}
+
+func (x *blobServiceHasManyClient) Send(m *HasBlobRequest) error {
+	return x.ClientStream.SendMsg(m)
+}
+
+func (x *blobServiceHasManyClient) Recv() (*HasBlobResponse, error) {
+	m := new(HasBlobResponse)
+	if err := x.ClientStream.RecvMsg(m); err != nil {
+		return nil, err
+	}
+	return m, nil
+}
+
 // BlobServiceServer is the server API for BlobService service.
 // All implementations must embed UnimplementedBlobServiceServer
 // for forward compatibility
@@ -159,6 +198,8 @@ type BlobServiceServer interface {
 	// The server may decide on whatever chunking it may seem fit as a size for
 	// the individual BlobChunk sent in the response stream, this is mostly to
 	// keep individual messages at a manageable size.
+	// `offset` and `length` in the `ReadBlobRequest` can be used to only read
+	// a range of the blob.
 	Read(*ReadBlobRequest, BlobService_ReadServer) error
 	// Put uploads a Blob, by reading a stream of bytes.
 	//
@@ -166,6 +207,11 @@ type BlobServiceServer interface {
 	// the stream has no effect on how the server ends up chunking blobs up, if
 	// it does at all.
 	Put(BlobService_PutServer) error
+	// HasMany checks for the existence of many blobs (or chunks) at once.
+	// For every HasBlobRequest sent, a HasBlobResponse is returned, in the same
+	// order.
+	// This saves a roundtrip per blob compared to individual `Stat` requests.
+	HasMany(BlobService_HasManyServer) error
 	mustEmbedUnimplementedBlobServiceServer()
 }
 
@@ -182,6 +228,9 @@ func (UnimplementedBlobServiceServer) Read(*ReadBlobRequest, BlobService_ReadSer
 func (UnimplementedBlobServiceServer) Put(BlobService_PutServer) error {
 	return status.Errorf(codes.Unimplemented, "method Put not implemented")
 }
+func (UnimplementedBlobServiceServer) HasMany(BlobService_HasManyServer) error {
+	return status.Errorf(codes.Unimplemented, "method HasMany not implemented")
+}
 func (UnimplementedBlobServiceServer) mustEmbedUnimplementedBlobServiceServer() {}
 
 // UnsafeBlobServiceServer may be embedded to opt out of forward compatibility for this service.
@@ -260,6 +309,32 @@ func (x *blobServicePutServer) Recv() (*BlobChunk, error) {
 	return m, nil
 }
 
+func _BlobService_HasMany_Handler(srv interface{}, stream grpc.ServerStream) error {
+	return srv.(BlobServiceServer).HasMany(&blobServiceHasManyServer{stream})
+}
+
+type BlobService_HasManyServer interface {
+	Send(*HasBlobResponse) error
+	Recv() (*HasBlobRequest, error)
+	grpc.ServerStream
+}
+
+type blobServiceHasManyServer struct {
+	grpc.ServerStream
+}
+
+func (x *blobServiceHasManyServer) Send(m *HasBlobResponse) error {
+	return x.ServerStream.SendMsg(m)
+}
+
+func (x *blobServiceHasManyServer) Recv() (*HasBlobRequest, error) {
+	m := new(HasBlobRequest)
+	if err := x.ServerStream.RecvMsg(m); err != nil {
+		return nil, err
+	}
+	return m, nil
+}
+
 // BlobService_ServiceDesc is the grpc.ServiceDesc for BlobService service.
 // It's only intended for direct use with grpc.RegisterService,
 // and not to be introspected or modified (even as a copy)
@@ -283,6 +358,12 @@ var BlobService_ServiceDesc = grpc.ServiceDesc{
 			Handler:       _BlobService_Put_Handler,
 			ClientStreams: true,
 		},
+		{
+			StreamName:    "HasMany",
+			Handler:       _BlobService_HasMany_Handler,
+			ServerStreams: true,
+			ClientStreams: true,
+		},
 	},
 	Metadata: "tvix/castore/protos/rpc_blobstore.proto",
 }
@@ -0,0 +1,74 @@
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use parking_lot::RwLock;
+use std::io;
+use std::{collections::HashMap, sync::Arc};
+use tonic::async_trait;
+use tracing::instrument;
+
+use super::BlobMetadataService;
+use crate::composition::{CompositionContext, ServiceBuilder};
+use crate::proto::stat_blob_response::ChunkMeta;
+use crate::{B3Digest, Error};
+
+#[derive(Clone, Default)]
+pub struct MemoryBlobMetadataService {
+    db: Arc<RwLock<HashMap<B3Digest, Vec<ChunkMeta>>>>,
+}
+
+#[async_trait]
+impl BlobMetadataService for MemoryBlobMetadataService {
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn chunks(&self, digest: &B3Digest) -> io::Result<Option<Vec<ChunkMeta>>> {
+        Ok(self.db.read().get(digest).cloned())
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest, chunk.count=chunks.len()))]
+    async fn put(&self, digest: B3Digest, chunks: Vec<ChunkMeta>) -> io::Result<()> {
+        self.db.write().insert(digest, chunks);
+
+        Ok(())
+    }
+
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        // Take a snapshot of the keys, so we don't hold the lock while the
+        // stream is consumed.
+        let digests: Vec<B3Digest> = self.db.read().keys().cloned().collect();
+
+        futures::stream::iter(digests.into_iter().map(Ok)).boxed()
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn delete(&self, digest: &B3Digest) -> io::Result<()> {
+        self.db.write().remove(digest);
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryBlobMetadataServiceConfig {}
+
+impl TryFrom<url::Url> for MemoryBlobMetadataServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // memory doesn't support host or path in the URL.
+        if url.has_host() || !url.path().is_empty() {
+            return Err(Error::StorageError("invalid url".to_string()).into());
+        }
+        Ok(MemoryBlobMetadataServiceConfig {})
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for MemoryBlobMetadataServiceConfig {
+    type Output = dyn BlobMetadataService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn BlobMetadataService>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        Ok(Arc::new(MemoryBlobMetadataService::default()))
+    }
+}
@@ -0,0 +1,79 @@
+use std::io;
+
+use auto_impl::auto_impl;
+use futures::stream::BoxStream;
+use tonic::async_trait;
+
+use crate::composition::{Registry, ServiceBuilder};
+use crate::proto::stat_blob_response::ChunkMeta;
+use crate::B3Digest;
+
+mod memory;
+mod object_store;
+
+#[cfg(test)]
+pub mod tests;
+
+pub use self::memory::{MemoryBlobMetadataService, MemoryBlobMetadataServiceConfig};
+pub use self::object_store::{
+    ObjectStoreBlobMetadataService, ObjectStoreBlobMetadataServiceConfig,
+};
+
+pub(crate) use self::object_store::derive_blob_path;
+
+/// The base trait all BlobMetadataService services need to implement.
+///
+/// It only keeps track of which chunks a blob consists of, the chunks
+/// themselves are stored in a [crate::chunkservice::ChunkService].
+/// [crate::blobservice::ChunkedBlobService] combines both into a
+/// [crate::blobservice::BlobService].
+#[async_trait]
+#[auto_impl(&, &mut, Arc, Box)]
+pub trait BlobMetadataService: Send + Sync {
+    /// Check if the service knows about the blob, by its content hash.
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        Ok(self.chunks(digest).await?.is_some())
+    }
+
+    /// Return the list of chunks for a given blob, or Ok(None) if the blob is
+    /// not known.
+    /// This has the same semantics as [crate::blobservice::BlobService::chunks]:
+    /// An empty list means the blob is not split up any further, and stored as
+    /// a single chunk with the blob digest.
+    async fn chunks(&self, digest: &B3Digest) -> io::Result<Option<Vec<ChunkMeta>>>;
+
+    /// Store the list of chunks for a given blob.
+    /// Callers need to ensure the chunks have been persisted first.
+    async fn put(&self, digest: B3Digest, chunks: Vec<ChunkMeta>) -> io::Result<()>;
+
+    /// Iterate over the digests of all blobs the service knows about.
+    /// Implementations can decide to disallow listing, which is what the
+    /// default implementation does.
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        Box::pin(futures::stream::once(async {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "listing is not supported",
+            ))
+        }))
+    }
+
+    /// Remove the list of chunks for a given blob.
+    /// This does not delete the chunks themselves, these need to be deleted
+    /// from the [crate::chunkservice::ChunkService] individually.
+    /// Deleting a blob that is not known is not an error.
+    /// Implementations can decide to disallow deletion, which is what the
+    /// default implementation does.
+    async fn delete(&self, _digest: &B3Digest) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "deletion is not supported",
+        ))
+    }
+}
+
+/// Registers the builtin BlobMetadataService implementations with the registry
+pub(crate) fn register_blob_metadata_services(reg: &mut Registry) {
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobMetadataService>>, super::blobmetadataservice::ObjectStoreBlobMetadataServiceConfig>("objectstore");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobMetadataService>>, super::blobmetadataservice::MemoryBlobMetadataServiceConfig>("memory");
+}
@@ -0,0 +1,175 @@
+use std::{collections::HashMap, io, sync::Arc};
+
+use async_stream::try_stream;
+use data_encoding::HEXLOWER;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use object_store::{path::Path, ObjectStore};
+use prost::Message;
+use tonic::async_trait;
+use tracing::{debug, instrument, Level};
+
+use crate::{
+    blobservice::digest_from_path,
+    composition::{CompositionContext, ServiceBuilder},
+    proto::{stat_blob_response::ChunkMeta, StatBlobResponse},
+    B3Digest,
+};
+
+use super::BlobMetadataService;
+
+/// Uses any object storage supported by the [object_store] crate to provide a
+/// tvix-castore [BlobMetadataService].
+///
+/// The list of chunks for each blob is stored as a serialized
+/// [StatBlobResponse] at `${base_path}/blobs/b3/$digest_key`, which is the same
+/// layout [crate::blobservice::ObjectStoreBlobService] uses, so both can share
+/// the same object store.
+#[derive(Clone)]
+pub struct ObjectStoreBlobMetadataService {
+    object_store: Arc<dyn ObjectStore>,
+    base_path: Path,
+}
+
+impl ObjectStoreBlobMetadataService {
+    pub fn new(object_store: Arc<dyn ObjectStore>, base_path: Path) -> Self {
+        Self {
+            object_store,
+            base_path,
+        }
+    }
+}
+
+#[instrument(level=Level::TRACE, skip_all,fields(base_path=%base_path,blob.digest=%digest),ret(Display))]
+pub(crate) fn derive_blob_path(base_path: &Path, digest: &B3Digest) -> Path {
+    base_path
+        .child("blobs")
+        .child("b3")
+        .child(HEXLOWER.encode(&digest.as_slice()[..2]))
+        .child(HEXLOWER.encode(digest.as_slice()))
+}
+
+#[async_trait]
+impl BlobMetadataService for ObjectStoreBlobMetadataService {
+    #[instrument(skip_all, ret(level = Level::TRACE), err, fields(blob.digest=%digest))]
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        match self
+            .object_store
+            .head(&derive_blob_path(&self.base_path, digest))
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e)?,
+        }
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn chunks(&self, digest: &B3Digest) -> io::Result<Option<Vec<ChunkMeta>>> {
+        match self
+            .object_store
+            .get(&derive_blob_path(&self.base_path, digest))
+            .await
+        {
+            Ok(get_result) => {
+                // fetch the data at the blob path, and parse into StatBlobResponse
+                let blob_data = get_result.bytes().await?;
+                let stat_blob_response = StatBlobResponse::decode(blob_data)?;
+
+                debug!(
+                    chunk.count = stat_blob_response.chunks.len(),
+                    "found blob metadata"
+                );
+
+                Ok(Some(stat_blob_response.chunks))
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest, chunk.count=chunks.len()))]
+    async fn put(&self, digest: B3Digest, chunks: Vec<ChunkMeta>) -> io::Result<()> {
+        let stat_blob_response = StatBlobResponse {
+            chunks,
+            bao: "".into(), // still todo
+        };
+
+        self.object_store
+            .put(
+                &derive_blob_path(&self.base_path, &digest),
+                stat_blob_response.encode_to_vec().into(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        let object_store = self.object_store.clone();
+        let prefix = self.base_path.child("blobs").child("b3");
+
+        Box::pin(try_stream! {
+            let mut objects = object_store.list(Some(&prefix));
+
+            while let Some(object_meta) = objects.try_next().await? {
+                yield digest_from_path(&object_meta.location)?;
+            }
+        })
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn delete(&self, digest: &B3Digest) -> io::Result<()> {
+        match self
+            .object_store
+            .delete(&derive_blob_path(&self.base_path, digest))
+            .await
+        {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e)?,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectStoreBlobMetadataServiceConfig {
+    object_store_url: String,
+    object_store_options: HashMap<String, String>,
+}
+
+impl TryFrom<url::Url> for ObjectStoreBlobMetadataServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    /// Constructs a new [ObjectStoreBlobMetadataService] from a [url::Url] supported
+    /// by [object_store].
+    /// Any path suffix becomes the base path of the object store.
+    /// additional options, the same as in [object_store::parse_url_opts] can
+    /// be passed.
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        let (object_store_url, object_store_options) = crate::objectstore_url::parse(&url)?;
+        Ok(ObjectStoreBlobMetadataServiceConfig {
+            object_store_url,
+            object_store_options,
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for ObjectStoreBlobMetadataServiceConfig {
+    type Output = dyn BlobMetadataService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn BlobMetadataService>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        let (object_store, path) = object_store::parse_url_opts(
+            &self.object_store_url.parse()?,
+            &self.object_store_options,
+        )?;
+        Ok(Arc::new(ObjectStoreBlobMetadataService::new(
+            Arc::new(object_store),
+            path,
+        )))
+    }
+}
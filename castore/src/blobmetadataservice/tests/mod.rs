@@ -0,0 +1,107 @@
+//! This contains test scenarios that a given [BlobMetadataService] needs to pass.
+//! We use [rstest] and [rstest_reuse] to provide all services we want to test
+//! against, and then apply this template to all test functions.
+
+use futures::TryStreamExt;
+use rstest::*;
+use rstest_reuse::{self, *};
+
+use super::{BlobMetadataService, MemoryBlobMetadataService, ObjectStoreBlobMetadataService};
+use crate::fixtures::{BLOB_A_DIGEST, BLOB_B_DIGEST, DUMMY_DIGEST};
+use crate::proto::stat_blob_response::ChunkMeta;
+
+fn objectstore_memory() -> ObjectStoreBlobMetadataService {
+    ObjectStoreBlobMetadataService::new(
+        std::sync::Arc::new(object_store::memory::InMemory::new()),
+        object_store::path::Path::default(),
+    )
+}
+
+/// This produces a template, which will be applied to all individual test functions.
+/// See https://github.com/la10736/rstest/issues/130#issuecomment-968864832
+#[template]
+#[rstest]
+#[case::memory(MemoryBlobMetadataService::default())]
+#[case::objectstore_memory(objectstore_memory())]
+pub fn blob_metadata_services(#[case] blob_metadata_service: impl BlobMetadataService) {}
+
+/// Asking for an unknown blob should return Ok(None).
+#[apply(blob_metadata_services)]
+#[tokio::test]
+async fn not_found(blob_metadata_service: impl BlobMetadataService) {
+    assert!(!blob_metadata_service
+        .has(&BLOB_A_DIGEST)
+        .await
+        .expect("must not fail"));
+    assert!(blob_metadata_service
+        .chunks(&BLOB_A_DIGEST)
+        .await
+        .expect("must not fail")
+        .is_none());
+}
+
+/// Put the chunk list for a blob, and get it back.
+#[apply(blob_metadata_services)]
+#[tokio::test]
+async fn put_get(blob_metadata_service: impl BlobMetadataService) {
+    let chunks = vec![
+        ChunkMeta {
+            digest: BLOB_A_DIGEST.clone().into(),
+            size: 2,
+        },
+        ChunkMeta {
+            digest: BLOB_B_DIGEST.clone().into(),
+            size: 42,
+        },
+    ];
+
+    blob_metadata_service
+        .put(DUMMY_DIGEST.clone(), chunks.clone())
+        .await
+        .expect("put must succeed");
+
+    assert!(blob_metadata_service
+        .has(&DUMMY_DIGEST)
+        .await
+        .expect("must not fail"));
+    assert_eq!(
+        Some(chunks),
+        blob_metadata_service
+            .chunks(&DUMMY_DIGEST)
+            .await
+            .expect("must not fail")
+    );
+}
+
+/// Put the chunk list for a blob, ensure it shows up in
+/// [BlobMetadataService::list], then delete it again.
+#[apply(blob_metadata_services)]
+#[tokio::test]
+async fn put_list_delete(blob_metadata_service: impl BlobMetadataService) {
+    blob_metadata_service
+        .put(DUMMY_DIGEST.clone(), vec![])
+        .await
+        .expect("put must succeed");
+
+    let digests: Vec<_> = blob_metadata_service
+        .list()
+        .try_collect()
+        .await
+        .expect("list must succeed");
+    assert_eq!(vec![DUMMY_DIGEST.clone()], digests);
+
+    blob_metadata_service
+        .delete(&DUMMY_DIGEST)
+        .await
+        .expect("delete must succeed");
+    assert!(!blob_metadata_service
+        .has(&DUMMY_DIGEST)
+        .await
+        .expect("must not fail"));
+
+    // Deleting a blob that isn't known is not an error.
+    blob_metadata_service
+        .delete(&DUMMY_DIGEST)
+        .await
+        .expect("delete must succeed");
+}
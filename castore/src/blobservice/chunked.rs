@@ -0,0 +1,377 @@
+use std::{io, pin::pin, sync::Arc, task::Poll};
+
+use bytes::Bytes;
+use fastcdc::v2020::AsyncStreamCDC;
+use futures::stream::BoxStream;
+use futures::Future;
+use pin_project_lite::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use tonic::async_trait;
+use tracing::instrument;
+
+use crate::blobmetadataservice::BlobMetadataService;
+use crate::chunkservice::ChunkService;
+use crate::composition::{CompositionContext, ServiceBuilder};
+use crate::proto::stat_blob_response::ChunkMeta;
+use crate::{B3Digest, B3HashingReader, Error};
+
+use super::{BlobReader, BlobService, BlobWriter, ChunkedReader};
+
+/// Provides a [BlobService] on top of a [BlobMetadataService], keeping track
+/// of which chunks a blob consists of, and a [ChunkService], storing these
+/// chunks.
+/// Blobs are read using a [ChunkedReader], fetching individual chunks from the
+/// [ChunkService] on demand.
+/// Blobs written are split into chunks using FastCDC.
+pub struct ChunkedBlobService<MS, CS> {
+    metadata_service: MS,
+    chunk_service: CS,
+
+    /// Average chunk size for FastCDC, in bytes.
+    /// min value is half, max value double of that number.
+    avg_chunk_size: u32,
+}
+
+impl<MS, CS> ChunkedBlobService<MS, CS> {
+    pub fn new(metadata_service: MS, chunk_service: CS, avg_chunk_size: u32) -> Self {
+        Self {
+            metadata_service,
+            chunk_service,
+            avg_chunk_size,
+        }
+    }
+}
+
+#[async_trait]
+impl<MS, CS> BlobService for ChunkedBlobService<MS, CS>
+where
+    MS: AsRef<dyn BlobMetadataService> + Clone + Send + Sync + 'static,
+    CS: AsRef<dyn ChunkService> + Clone + Send + Sync + 'static,
+{
+    #[instrument(skip(self, digest), fields(blob.digest=%digest), err)]
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        Ok(self.metadata_service.as_ref().has(digest).await?
+            || self.chunk_service.as_ref().has(digest).await?)
+    }
+
+    #[instrument(skip(self, digest), fields(blob.digest=%digest), err)]
+    async fn open_read(&self, digest: &B3Digest) -> io::Result<Option<Box<dyn BlobReader>>> {
+        // handle reading the empty blob.
+        if digest.as_slice() == blake3::hash(b"").as_bytes() {
+            return Ok(Some(Box::new(io::Cursor::new(b"")) as Box<dyn BlobReader>));
+        }
+
+        match self.metadata_service.as_ref().chunks(digest).await? {
+            Some(chunks) if !chunks.is_empty() => {
+                let chunked_reader =
+                    ChunkedReader::try_from_chunk_metas(chunks, self.chunk_service.clone())?;
+                Ok(Some(Box::new(chunked_reader)))
+            }
+            // The blob is not split up any further (or the digest refers to
+            // a chunk), so it can be read from the chunk service directly.
+            _ => Ok(self
+                .chunk_service
+                .as_ref()
+                .get(digest)
+                .await?
+                .map(|data| Box::new(io::Cursor::new(data)) as Box<dyn BlobReader>)),
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn open_write(&self) -> Box<dyn BlobWriter> {
+        // ChunkingBlobWriter implements AsyncWrite, but all the chunking
+        // needs an AsyncRead, so we create a pipe here.
+        let (w, r) = tokio::io::duplex(self.avg_chunk_size as usize * 10);
+
+        let metadata_service = self.metadata_service.clone();
+        let chunk_service = self.chunk_service.clone();
+        let avg_chunk_size = self.avg_chunk_size;
+
+        Box::new(ChunkingBlobWriter::new(
+            w,
+            Box::pin(async move {
+                let (blob_digest, chunks) = chunk_and_put(
+                    r,
+                    chunk_service.as_ref(),
+                    avg_chunk_size / 2,
+                    avg_chunk_size,
+                    avg_chunk_size * 2,
+                )
+                .await?;
+
+                metadata_service
+                    .as_ref()
+                    .put(blob_digest.clone(), chunks)
+                    .await?;
+
+                Ok(blob_digest)
+            }),
+        ))
+    }
+
+    #[instrument(skip(self, digest), fields(blob.digest=%digest), err)]
+    async fn chunks(&self, digest: &B3Digest) -> io::Result<Option<Vec<ChunkMeta>>> {
+        match self.metadata_service.as_ref().chunks(digest).await? {
+            Some(chunks) => Ok(Some(chunks)),
+            // If there's only a chunk, we must return the empty vec here, rather than None.
+            None if self.chunk_service.as_ref().has(digest).await? => Ok(Some(vec![])),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        // Blobs consisting of a single chunk show up in both services, so
+        // their digest is returned twice.
+        Box::pin(
+            self.metadata_service
+                .as_ref()
+                .list()
+                .chain(self.chunk_service.as_ref().list()),
+        )
+    }
+
+    #[instrument(skip(self, digest), fields(blob.digest=%digest), err)]
+    async fn delete(&self, digest: &B3Digest) -> io::Result<()> {
+        // The digest might refer to a blob, a chunk, or both (in case the blob
+        // consists of a single chunk), so remove it from both services.
+        self.metadata_service.as_ref().delete(digest).await?;
+        self.chunk_service.as_ref().delete(digest).await
+    }
+}
+
+pub(crate) fn default_avg_chunk_size() -> u32 {
+    256 * 1024
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ChunkedBlobServiceConfig {
+    /// Name of the [BlobMetadataService] to use.
+    metadata: String,
+    /// Name of the [ChunkService] to use.
+    chunks: String,
+    #[serde(default = "default_avg_chunk_size")]
+    avg_chunk_size: u32,
+}
+
+impl TryFrom<url::Url> for ChunkedBlobServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(_url: url::Url) -> Result<Self, Self::Error> {
+        Err(Error::StorageError(
+            "Instantiating a ChunkedBlobService from a url is not supported".into(),
+        )
+        .into())
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for ChunkedBlobServiceConfig {
+    type Output = dyn BlobService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        context: &CompositionContext,
+    ) -> Result<Arc<dyn BlobService>, Box<dyn std::error::Error + Send + Sync>> {
+        let (metadata_service, chunk_service) = futures::join!(
+            context.resolve::<dyn BlobMetadataService>(self.metadata.clone()),
+            context.resolve::<dyn ChunkService>(self.chunks.clone())
+        );
+        Ok(Arc::new(ChunkedBlobService {
+            metadata_service: metadata_service?,
+            chunk_service: chunk_service?,
+            avg_chunk_size: self.avg_chunk_size,
+        }))
+    }
+}
+
+/// Reads blob contents from a AsyncRead, chunks them using FastCDC, and puts
+/// the chunks into the passed [ChunkService].
+/// On success, returns the digest of the blob, and the list of chunks it
+/// consists of. If the blob was not split up, this list is empty.
+#[instrument(skip_all, fields(min_chunk_size, avg_chunk_size, max_chunk_size), err)]
+pub(crate) async fn chunk_and_put<R: AsyncRead + Unpin>(
+    r: R,
+    chunk_service: &dyn ChunkService,
+    min_chunk_size: u32,
+    avg_chunk_size: u32,
+    max_chunk_size: u32,
+) -> io::Result<(B3Digest, Vec<ChunkMeta>)> {
+    // wrap reader with something calculating the blake3 hash of all data read.
+    let mut b3_r = B3HashingReader::from(r);
+    // Use a fastcdc chunker to produce a stream of chunks, and put these
+    // into the chunk service.
+    // This is a plain loop rather than a `.then(…)` on the stream, as rustc
+    // spits higher-ranked lifetime errors at us for the borrowed chunk service
+    // otherwise.
+    let mut chunks = Vec::new();
+    {
+        let mut chunker =
+            AsyncStreamCDC::new(&mut b3_r, min_chunk_size, avg_chunk_size, max_chunk_size);
+        let mut chunk_stream = pin!(chunker.as_stream());
+        while let Some(chunk_data) = chunk_stream.next().await {
+            let chunk_data = chunk_data?;
+            let chunk_size = chunk_data.data.len() as u64;
+            let chunk_digest = chunk_service.put(Bytes::from(chunk_data.data)).await?;
+
+            chunks.push(ChunkMeta {
+                digest: chunk_digest.into(),
+                size: chunk_size,
+            });
+        }
+    }
+
+    let chunks = if chunks.len() < 2 {
+        // The chunker returned only one chunk, which is the entire blob.
+        // According to the protocol, we must return an empty list of chunks
+        // when the blob is not split up further.
+        vec![]
+    } else {
+        chunks
+    };
+
+    Ok((b3_r.digest().into(), chunks))
+}
+
+pin_project! {
+    /// Takes care of blob uploads.
+    /// All writes are relayed to self.writer, and we continuously poll the
+    /// future (which will internally read from the other side of the pipe and
+    /// upload chunks).
+    /// Our BlobWriter::close() needs to drop self.writer, so the other side
+    /// will read EOF and can finalize the blob.
+    /// The future should then resolve and return the blob digest.
+    pub(crate) struct ChunkingBlobWriter<W, Fut>
+    where
+        W: AsyncWrite,
+        Fut: Future,
+    {
+        #[pin]
+        writer: Option<W>,
+
+        #[pin]
+        fut: Option<Fut>,
+
+        fut_output: Option<io::Result<B3Digest>>
+    }
+}
+
+impl<W, Fut> ChunkingBlobWriter<W, Fut>
+where
+    W: AsyncWrite,
+    Fut: Future,
+{
+    /// Constructs a new [ChunkingBlobWriter], relaying writes to `writer`,
+    /// and polling `fut` to do the actual chunking and uploading.
+    pub(crate) fn new(writer: W, fut: Fut) -> Self {
+        Self {
+            writer: Some(writer),
+            fut: Some(fut),
+            fut_output: None,
+        }
+    }
+}
+
+impl<W, Fut> tokio::io::AsyncWrite for ChunkingBlobWriter<W, Fut>
+where
+    W: AsyncWrite + Send + Unpin,
+    Fut: Future,
+{
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<Result<usize, io::Error>> {
+        let this = self.project();
+        // poll the future.
+        let fut = this.fut.as_pin_mut().expect("not future");
+        let fut_p = fut.poll(cx);
+        // if it's ready, the only way this could have happened is that the
+        // upload failed, because we're only closing `self.writer` after all
+        // writes happened.
+        if fut_p.is_ready() {
+            return Poll::Ready(Err(io::Error::other("upload failed")));
+        }
+
+        // write to the underlying writer
+        this.writer
+            .as_pin_mut()
+            .expect("writer must be some")
+            .poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), io::Error>> {
+        let this = self.project();
+        // poll the future.
+        let fut = this.fut.as_pin_mut().expect("not future");
+        let fut_p = fut.poll(cx);
+        // if it's ready, the only way this could have happened is that the
+        // upload failed, because we're only closing `self.writer` after all
+        // writes happened.
+        if fut_p.is_ready() {
+            return Poll::Ready(Err(io::Error::other("upload failed")));
+        }
+
+        // Call poll_flush on the writer
+        this.writer
+            .as_pin_mut()
+            .expect("writer must be some")
+            .poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), io::Error>> {
+        // There's nothing to do on shutdown. We might have written some chunks
+        // that are nowhere else referenced, but cleaning them up here would be racy.
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
+impl<W, Fut> BlobWriter for ChunkingBlobWriter<W, Fut>
+where
+    W: AsyncWrite + Send + Unpin,
+    Fut: Future<Output = io::Result<B3Digest>> + Send + Unpin,
+{
+    async fn close(&mut self) -> io::Result<B3Digest> {
+        match self.writer.take() {
+            Some(mut writer) => {
+                // shut down the writer, so the other side will read EOF.
+                writer.shutdown().await?;
+
+                // take out the future.
+                let fut = self.fut.take().expect("fut must be some");
+                // await it.
+                let resp = pin!(fut).await;
+
+                match resp.as_ref() {
+                    // In the case of an Ok value, we store it in self.fut_output,
+                    // so future calls to close can return that.
+                    Ok(b3_digest) => {
+                        self.fut_output = Some(Ok(b3_digest.clone()));
+                    }
+                    Err(e) => {
+                        // for the error type, we need to cheat a bit, as
+                        // they're not clone-able.
+                        // Simply store a sloppy clone, with the same ErrorKind and message there.
+                        self.fut_output = Some(Err(std::io::Error::new(e.kind(), e.to_string())))
+                    }
+                }
+                resp
+            }
+            None => {
+                // called a second time, return self.fut_output.
+                match self.fut_output.as_ref().unwrap() {
+                    Ok(ref b3_digest) => Ok(b3_digest.clone()),
+                    Err(e) => Err(std::io::Error::new(e.kind(), e.to_string())),
+                }
+            }
+        }
+    }
+}
@@ -1,22 +1,24 @@
-use futures::{ready, TryStreamExt};
+use futures::ready;
 use pin_project_lite::pin_project;
-use tokio::io::{AsyncRead, AsyncSeekExt};
+use tokio::io::AsyncRead;
 use tokio_stream::StreamExt;
-use tokio_util::io::{ReaderStream, StreamReader};
+use tokio_util::io::StreamReader;
 use tracing::{instrument, trace, warn};
 
+use crate::chunkservice::ChunkService;
+use crate::proto::stat_blob_response::ChunkMeta;
 use crate::B3Digest;
-use std::{cmp::Ordering, pin::Pin};
+use std::{cmp::Ordering, io, pin::Pin};
 
-use super::{BlobReader, BlobService};
+use super::BlobReader;
 
 pin_project! {
     /// ChunkedReader provides a chunk-aware [BlobReader], so allows reading and
     /// seeking into a blob.
     /// It internally holds a [ChunkedBlob], which is storing chunk information
     /// able to emit a reader seeked to a specific position whenever we need to seek.
-    pub struct ChunkedReader<BS> {
-        chunked_blob: ChunkedBlob<BS>,
+    pub struct ChunkedReader<CS> {
+        chunked_blob: ChunkedBlob<CS>,
 
         #[pin]
         r: Box<dyn AsyncRead + Unpin + Send>,
@@ -25,14 +27,18 @@ pin_project! {
     }
 }
 
-impl<BS> ChunkedReader<BS>
+impl<CS> ChunkedReader<CS>
 where
-    BS: AsRef<dyn BlobService> + Clone + 'static + Send,
+    CS: AsRef<dyn ChunkService> + Clone + 'static + Send,
 {
     /// Construct a new [ChunkedReader], by retrieving a list of chunks (their
-    /// blake3 digests and chunk sizes)
-    pub fn from_chunks(chunks_it: impl Iterator<Item = (B3Digest, u64)>, blob_service: BS) -> Self {
-        let chunked_blob = ChunkedBlob::from_iter(chunks_it, blob_service);
+    /// blake3 digests and chunk sizes), which are fetched from the passed
+    /// [ChunkService] when needed.
+    pub fn from_chunks(
+        chunks_it: impl Iterator<Item = (B3Digest, u64)>,
+        chunk_service: CS,
+    ) -> Self {
+        let chunked_blob = ChunkedBlob::from_iter(chunks_it, chunk_service);
         let r = chunked_blob.reader_skipped_offset(0);
 
         Self {
@@ -41,14 +47,31 @@ where
             pos: 0,
         }
     }
+
+    /// Construct a new [ChunkedReader] from a list of [ChunkMeta], as
+    /// returned by [super::BlobService::chunks].
+    /// Fails if one of the chunk digests doesn't have the right length.
+    pub fn try_from_chunk_metas(chunks: Vec<ChunkMeta>, chunk_service: CS) -> io::Result<Self> {
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| {
+                let digest = B3Digest::try_from(chunk.digest).map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("invalid chunk: {e}"))
+                })?;
+                Ok((digest, chunk.size))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Self::from_chunks(chunks.into_iter(), chunk_service))
+    }
 }
 
 /// ChunkedReader implements BlobReader.
-impl<BS> BlobReader for ChunkedReader<BS> where BS: Send + Clone + 'static + AsRef<dyn BlobService> {}
+impl<CS> BlobReader for ChunkedReader<CS> where CS: Send + Clone + 'static + AsRef<dyn ChunkService> {}
 
-impl<BS> tokio::io::AsyncRead for ChunkedReader<BS>
+impl<CS> tokio::io::AsyncRead for ChunkedReader<CS>
 where
-    BS: AsRef<dyn BlobService> + Clone + 'static,
+    CS: AsRef<dyn ChunkService> + Clone + 'static,
 {
     fn poll_read(
         self: std::pin::Pin<&mut Self>,
@@ -69,9 +92,9 @@ where
     }
 }
 
-impl<BS> tokio::io::AsyncSeek for ChunkedReader<BS>
+impl<CS> tokio::io::AsyncSeek for ChunkedReader<CS>
 where
-    BS: AsRef<dyn BlobService> + Clone + Send + 'static,
+    CS: AsRef<dyn ChunkService> + Clone + Send + 'static,
 {
     #[instrument(skip(self), err(Debug))]
     fn start_seek(self: Pin<&mut Self>, position: std::io::SeekFrom) -> std::io::Result<()> {
@@ -135,19 +158,19 @@ where
 /// Is able to construct a Reader that seeked to a certain offset, which
 /// is useful to construct a BlobReader (that implements AsyncSeek).
 /// - the current chunk index, and a Custor<Vec<u8>> holding the data of that chunk.
-struct ChunkedBlob<BS> {
-    blob_service: BS,
+struct ChunkedBlob<CS> {
+    chunk_service: CS,
     chunks: Vec<(u64, u64, B3Digest)>,
 }
 
-impl<BS> ChunkedBlob<BS>
+impl<CS> ChunkedBlob<CS>
 where
-    BS: AsRef<dyn BlobService> + Clone + 'static + Send,
+    CS: AsRef<dyn ChunkService> + Clone + 'static + Send,
 {
     /// Constructs [Self] from a list of blake3 digests of chunks and their
-    /// sizes, and a reference to a blob service.
+    /// sizes, and a reference to a chunk service.
     /// Initializing it with an empty list is disallowed.
-    fn from_iter(chunks_it: impl Iterator<Item = (B3Digest, u64)>, blob_service: BS) -> Self {
+    fn from_iter(chunks_it: impl Iterator<Item = (B3Digest, u64)>, chunk_service: CS) -> Self {
         let mut chunks = Vec::new();
         let mut offset: u64 = 0;
 
@@ -162,7 +185,7 @@ where
         );
 
         Self {
-            blob_service,
+            chunk_service,
             chunks,
         }
     }
@@ -212,40 +235,40 @@ where
 
         let skip_first_chunk_bytes = (offset - self.chunks[start_chunk_idx].0) as usize;
 
-        let blob_service = self.blob_service.clone();
+        let chunk_service = self.chunk_service.clone();
         let chunks: Vec<_> = self.chunks[start_chunk_idx..].to_vec();
-        let readers_stream = tokio_stream::iter(chunks.into_iter().enumerate()).map(
+        let bytes_stream = tokio_stream::iter(chunks.into_iter().enumerate()).then(
             move |(nth_chunk, (_chunk_start_offset, chunk_size, chunk_digest))| {
-                let chunk_digest = chunk_digest.to_owned();
-                let blob_service = blob_service.clone();
+                let chunk_service = chunk_service.clone();
                 async move {
-                    trace!(chunk_size=%chunk_size, chunk_digest=%chunk_digest, "open_read on chunk in stream");
-                    let mut blob_reader = blob_service
+                    trace!(chunk_size=%chunk_size, chunk_digest=%chunk_digest, "get on chunk in stream");
+                    let chunk_data = chunk_service
                         .as_ref()
-                        .open_read(&chunk_digest.to_owned())
+                        .get(&chunk_digest)
                         .await?
                         .ok_or_else(|| {
                             warn!(chunk.digest = %chunk_digest, "chunk not found");
                             std::io::Error::new(std::io::ErrorKind::NotFound, "chunk not found")
                         })?;
 
+                    if chunk_data.len() as u64 != chunk_size {
+                        warn!(chunk.digest = %chunk_digest, chunk.size = chunk_data.len(), chunk.expected_size = chunk_size, "unexpected chunk size");
+                        Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "unexpected chunk size",
+                        ))?
+                    }
+
                     // iff this is the first chunk in the stream, skip by skip_first_chunk_bytes
                     if nth_chunk == 0 && skip_first_chunk_bytes > 0 {
-                        blob_reader
-                            .seek(std::io::SeekFrom::Start(skip_first_chunk_bytes as u64))
-                            .await?;
+                        Ok::<_, std::io::Error>(chunk_data.slice(skip_first_chunk_bytes..))
+                    } else {
+                        Ok(chunk_data)
                     }
-                    Ok::<_, std::io::Error>(blob_reader)
                 }
             },
         );
 
-        // convert the stream of readers to a stream of streams of byte chunks
-        let bytes_streams = readers_stream.then(|elem| async { elem.await.map(ReaderStream::new) });
-
-        // flatten into one stream of byte chunks
-        let bytes_stream = bytes_streams.try_flatten();
-
         // convert into AsyncRead
         Box::new(StreamReader::new(Box::pin(bytes_stream)))
     }
@@ -259,7 +282,8 @@ mod test {
     };
 
     use crate::{
-        blobservice::{chunked_reader::ChunkedReader, BlobService, MemoryBlobService},
+        blobservice::chunked_reader::ChunkedReader,
+        chunkservice::{ChunkService, MemoryChunkService},
         B3Digest,
     };
     use hex_literal::hex;
@@ -299,7 +323,7 @@ mod test {
     fn from_iter() {
         let cb = ChunkedBlob::from_iter(
             BLOB_1_LIST.clone().into_iter(),
-            Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>,
+            Arc::new(MemoryChunkService::default()) as Arc<dyn ChunkService>,
         );
 
         assert_eq!(
@@ -320,7 +344,7 @@ mod test {
     fn from_iter_empty() {
         ChunkedBlob::from_iter(
             [].into_iter(),
-            Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>,
+            Arc::new(MemoryChunkService::default()) as Arc<dyn ChunkService>,
         );
     }
 
@@ -329,7 +353,7 @@ mod test {
     fn chunk_idx_for_position() {
         let cb = ChunkedBlob::from_iter(
             BLOB_1_LIST.clone().into_iter(),
-            Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>,
+            Arc::new(MemoryChunkService::default()) as Arc<dyn ChunkService>,
         );
 
         assert_eq!(Some(0), cb.get_chunk_idx_for_position(0), "start of blob");
@@ -362,33 +386,32 @@ mod test {
         );
     }
 
-    /// returns a blobservice with all chunks in BLOB_1 present.
-    async fn gen_blobservice_blob1() -> Arc<dyn BlobService> {
-        let blob_service = Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>;
+    /// returns a chunkservice with all chunks in BLOB_1 present.
+    async fn gen_chunkservice_blob1() -> Arc<dyn ChunkService> {
+        let chunk_service = Arc::new(MemoryChunkService::default()) as Arc<dyn ChunkService>;
 
-        // seed blob service with all chunks
-        for blob_contents in [
+        // seed chunk service with all chunks
+        for chunk_contents in [
             CHUNK_1.to_vec(),
             CHUNK_2.to_vec(),
             CHUNK_3.to_vec(),
             CHUNK_4.to_vec(),
             CHUNK_5.to_vec(),
         ] {
-            let mut bw = blob_service.open_write().await;
-            tokio::io::copy(&mut std::io::Cursor::new(blob_contents), &mut bw)
+            chunk_service
+                .put(chunk_contents.into())
                 .await
-                .expect("writing blob");
-            bw.close().await.expect("close blobwriter");
+                .expect("putting chunk");
         }
 
-        blob_service
+        chunk_service
     }
 
     #[tokio::test]
     async fn test_read() {
-        let blob_service = gen_blobservice_blob1().await;
+        let chunk_service = gen_chunkservice_blob1().await;
         let mut chunked_reader =
-            ChunkedReader::from_chunks(BLOB_1_LIST.clone().into_iter(), blob_service);
+            ChunkedReader::from_chunks(BLOB_1_LIST.clone().into_iter(), chunk_service);
 
         // read all data
         let mut buf = Vec::new();
@@ -405,9 +428,9 @@ mod test {
 
     #[tokio::test]
     async fn test_seek() {
-        let blob_service = gen_blobservice_blob1().await;
+        let chunk_service = gen_chunkservice_blob1().await;
         let mut chunked_reader =
-            ChunkedReader::from_chunks(BLOB_1_LIST.clone().into_iter(), blob_service);
+            ChunkedReader::from_chunks(BLOB_1_LIST.clone().into_iter(), chunk_service);
 
         // seek to the end
         // expect to read 0 bytes
@@ -457,24 +480,22 @@ mod test {
         }
     }
 
-    // seeds a blob service with only the first two chunks, reads a bit in the
+    // seeds a chunk service with only the first two chunks, reads a bit in the
     // front (which succeeds), but then tries to seek past and read more (which
     // should fail).
     #[tokio::test]
     async fn test_read_missing_chunks() {
-        let blob_service = Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>;
+        let chunk_service = Arc::new(MemoryChunkService::default()) as Arc<dyn ChunkService>;
 
-        for blob_contents in [CHUNK_1.to_vec(), CHUNK_2.to_vec()] {
-            let mut bw = blob_service.open_write().await;
-            tokio::io::copy(&mut std::io::Cursor::new(blob_contents), &mut bw)
+        for chunk_contents in [CHUNK_1.to_vec(), CHUNK_2.to_vec()] {
+            chunk_service
+                .put(chunk_contents.into())
                 .await
-                .expect("writing blob");
-
-            bw.close().await.expect("close blobwriter");
+                .expect("putting chunk");
         }
 
         let mut chunked_reader =
-            ChunkedReader::from_chunks(BLOB_1_LIST.clone().into_iter(), blob_service);
+            ChunkedReader::from_chunks(BLOB_1_LIST.clone().into_iter(), chunk_service);
 
         // read a bit from the front (5 bytes out of 6 available)
         let mut buf = [0b0; 5];
@@ -1,8 +1,11 @@
 use std::sync::Arc;
 
+use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tonic::async_trait;
 use tracing::instrument;
 
+use crate::chunkservice::ChunkService;
 use crate::composition::{CompositionContext, ServiceBuilder};
 use crate::{B3Digest, Error};
 
@@ -20,6 +23,12 @@ pub struct CombinedBlobService<BL, BR> {
     remote: BR,
 }
 
+impl<BL, BR> CombinedBlobService<BL, BR> {
+    pub fn new(local: BL, remote: BR) -> Self {
+        Self { local, remote }
+    }
+}
+
 impl<BL, BR> Clone for CombinedBlobService<BL, BR>
 where
     BL: Clone,
@@ -44,6 +53,31 @@ where
         Ok(self.local.as_ref().has(digest).await? || self.remote.as_ref().has(digest).await?)
     }
 
+    #[instrument(skip_all, fields(blob.count=digests.len()), err)]
+    async fn has_many(&self, digests: &[B3Digest]) -> std::io::Result<Vec<bool>> {
+        let mut exists = self.local.as_ref().has_many(digests).await?;
+
+        // Ask remote for everything not present locally.
+        let (missing_idxs, missing_digests): (Vec<usize>, Vec<B3Digest>) = exists
+            .iter()
+            .zip(digests)
+            .enumerate()
+            .filter(|(_, (exists, _))| !**exists)
+            .map(|(i, (_, digest))| (i, digest.clone()))
+            .unzip();
+
+        if !missing_digests.is_empty() {
+            for (i, remote_exists) in missing_idxs
+                .into_iter()
+                .zip(self.remote.as_ref().has_many(&missing_digests).await?)
+            {
+                exists[i] = remote_exists;
+            }
+        }
+
+        Ok(exists)
+    }
+
     #[instrument(skip(self, digest), fields(blob.digest=%digest), err)]
     async fn open_read(&self, digest: &B3Digest) -> std::io::Result<Option<Box<dyn BlobReader>>> {
         if self.local.as_ref().has(digest).await? {
@@ -76,7 +110,10 @@ where
                                 chunk.size,
                             )
                         }),
-                        Arc::new(self.clone()) as Arc<dyn BlobService>,
+                        Arc::new(CombinedChunks {
+                            local: self.local.clone(),
+                            remote: self.remote.clone(),
+                        }) as Arc<dyn ChunkService>,
                     );
                     Ok(Some(Box::new(chunked_reader)))
                 }
@@ -91,6 +128,54 @@ where
     }
 }
 
+/// Provides individual chunks to the [ChunkedReader] used in
+/// [CombinedBlobService::open_read], by reading them from the local
+/// BlobService, falling back to the remote one.
+/// Chunks are not split up any further, so unlike [CombinedBlobService], this
+/// doesn't need to ask the remote BlobService for more granular chunks.
+struct CombinedChunks<BL, BR> {
+    local: BL,
+    remote: BR,
+}
+
+#[async_trait]
+impl<BL, BR> ChunkService for CombinedChunks<BL, BR>
+where
+    BL: AsRef<dyn BlobService> + Send + Sync,
+    BR: AsRef<dyn BlobService> + Send + Sync,
+{
+    #[instrument(skip(self, digest), fields(chunk.digest=%digest), err)]
+    async fn get(&self, digest: &B3Digest) -> std::io::Result<Option<Bytes>> {
+        for blob_service in [self.local.as_ref(), self.remote.as_ref()] {
+            if let Some(mut reader) = blob_service.open_read(digest).await? {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf).await?;
+
+                // ensure the chunk contents match the digest, as required by
+                // the ChunkService contract.
+                if *digest != blake3::hash(&buf).as_bytes().into() {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "chunk contents invalid",
+                    ))?;
+                }
+
+                return Ok(Some(buf.into()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    #[instrument(skip_all, err)]
+    async fn put(&self, data: Bytes) -> std::io::Result<B3Digest> {
+        // direct writes to the local one.
+        let mut writer = self.local.as_ref().open_write().await;
+        writer.write_all(&data).await?;
+        writer.close().await
+    }
+}
+
 #[derive(serde::Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct CombinedBlobServiceConfig {
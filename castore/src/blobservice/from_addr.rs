@@ -35,8 +35,14 @@ pub async fn from_addr(
 
 #[cfg(test)]
 mod tests {
+    use std::sync::LazyLock;
+
     use super::from_addr;
     use rstest::rstest;
+    use tempfile::TempDir;
+
+    static TMPDIR_FS_1: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
+    static TMPDIR_FS_2: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
 
     #[rstest]
     /// This uses an unsupported scheme.
@@ -49,6 +55,18 @@ mod tests {
     #[case::memory_invalid_root_path("memory:///", false)]
     /// This sets a memory url path to "/foo", which is invalid.
     #[case::memory_invalid_root_path_foo("memory:///foo", false)]
+    /// This configures fs with a valid path, which should succeed.
+    #[case::fs_valid_path(&format!("fs://{}", &TMPDIR_FS_1.path().join("foo").to_str().unwrap()), true)]
+    /// This configures fs with a valid path, and enables compression.
+    #[case::fs_valid_path_compress(&format!("fs://{}?compress=true", &TMPDIR_FS_2.path().join("foo").to_str().unwrap()), true)]
+    /// This configures fs with an unknown query parameter, which should fail.
+    #[case::fs_invalid_query("fs:///foo?bar=baz", false)]
+    /// This configures fs without a path, which should fail.
+    #[case::fs_invalid_no_path("fs://", false)]
+    /// This configures fs with /, which should fail.
+    #[case::fs_invalid_root("fs:///", false)]
+    /// This configures fs with a host, not path, which should fail.
+    #[case::fs_invalid_host("fs://foo.example", false)]
     /// Correct scheme to connect to a unix socket.
     #[case::grpc_valid_unix_socket("grpc+unix:///path/to/somewhere", true)]
     /// Correct scheme for unix socket, but setting a host too, which is invalid.
@@ -0,0 +1,382 @@
+use std::{
+    io::{self, Cursor, Seek},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use prost::Message;
+use tonic::async_trait;
+use tracing::{debug, instrument, Level};
+
+use super::chunked::{chunk_and_put, default_avg_chunk_size, ChunkingBlobWriter};
+use super::{BlobReader, BlobService, BlobWriter, ChunkedReader};
+use crate::chunkservice::ChunkService;
+use crate::composition::{CompositionContext, ServiceBuilder};
+use crate::proto::{stat_blob_response::ChunkMeta, StatBlobResponse};
+use crate::sharded_fs::ShardedFs;
+use crate::{B3Digest, Error};
+
+/// Content type of chunks stored as-is.
+const CONTENT_TYPE_RAW: &str = "application/octet-stream";
+/// Content type of zstd-compressed chunks.
+const CONTENT_TYPE_ZSTD: &str = "application/zstd";
+/// Content type of blob index files, holding a serialized [StatBlobResponse].
+const CONTENT_TYPE_BLOB_INDEX: &str = "application/vnd.tvix-castore.stat-blob-response+protobuf";
+
+/// Stores blobs in a local directory.
+///
+/// Blobs are chunked with FastCDC. Each chunk is stored at
+/// `${root}/chunks/b3/$digest_key`. Blobs consisting of a single chunk are
+/// only stored there, for all others, the list of chunks is stored as a
+/// serialized [StatBlobResponse] at `${root}/blobs/b3/$digest_key`.
+///
+/// The digest key is the blake3 digest encoded in lower hex, sharded after the
+/// second byte. All files are written atomically, by renaming them into place
+/// from `${root}/tmp`.
+///
+/// The content type of each file is stored in the `user.mime_type` extended
+/// attribute, so the underlying filesystem needs to support user xattrs.
+/// Chunks are either stored as-is (`application/octet-stream`), or
+/// zstd-compressed (`application/zstd`), depending on the `compress` setting
+/// at the time they were written. Both can be read back, so the setting can be
+/// changed at any time.
+/// Uncompressed blobs consisting of a single chunk are served straight from
+/// the file, without loading them into memory, after checking their digest.
+#[derive(Clone)]
+pub struct FsBlobService {
+    fs: ShardedFs,
+
+    /// Average chunk size for FastCDC, in bytes.
+    /// min value is half, max value double of that number.
+    avg_chunk_size: u32,
+
+    /// Whether to zstd-compress chunks written.
+    compress: bool,
+}
+
+impl FsBlobService {
+    /// Opens (and creates, if it doesn't exist) a [FsBlobService] at the given
+    /// path.
+    pub async fn new(root: PathBuf, avg_chunk_size: u32, compress: bool) -> io::Result<Self> {
+        Ok(Self {
+            fs: ShardedFs::open(root).await?,
+            avg_chunk_size,
+            compress,
+        })
+    }
+
+    /// Returns a [ChunkService] operating on the same directory, which is
+    /// used for reading and writing chunks.
+    fn chunk_service(&self) -> FsChunks {
+        FsChunks {
+            fs: self.fs.clone(),
+            compress: self.compress,
+        }
+    }
+
+    /// Reads the list of chunks of a blob from its index file, if present.
+    async fn read_blob_index(&self, digest: &B3Digest) -> io::Result<Option<Vec<ChunkMeta>>> {
+        match self.fs.get("blobs", digest).await? {
+            Some((data, content_type)) if content_type == CONTENT_TYPE_BLOB_INDEX => {
+                let stat_blob_response = StatBlobResponse::decode(Bytes::from(data))?;
+                Ok(Some(stat_blob_response.chunks))
+            }
+            Some((_, content_type)) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected content type for blob index: {}", content_type),
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl BlobService for FsBlobService {
+    #[instrument(skip_all, ret(level = Level::TRACE), err, fields(blob.digest=%digest))]
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        Ok(self.fs.exists("blobs", digest).await? || self.fs.exists("chunks", digest).await?)
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn open_read(&self, digest: &B3Digest) -> io::Result<Option<Box<dyn BlobReader>>> {
+        if let Some(chunks) = self.read_blob_index(digest).await? {
+            let chunked_reader = ChunkedReader::try_from_chunk_metas(
+                chunks,
+                Arc::new(self.chunk_service()) as Arc<dyn ChunkService>,
+            )?;
+
+            return Ok(Some(Box::new(chunked_reader)));
+        }
+
+        // The blob is a single chunk (or a chunk was requested).
+        match self.fs.open_read("chunks", digest).await? {
+            Some((mut file, content_type)) if content_type == CONTENT_TYPE_RAW => {
+                // Ensure the file contents match the digest before handing
+                // it out, like [FsChunks::get] does for the other chunks.
+                let digest = digest.clone();
+                let file = tokio::task::spawn_blocking(move || {
+                    let mut hasher = blake3::Hasher::new();
+                    hasher.update_reader(&mut file)?;
+                    if digest != hasher.finalize().as_bytes().into() {
+                        Err(io::Error::other("chunk contents invalid"))?;
+                    }
+                    file.rewind()?;
+                    Ok::<_, io::Error>(file)
+                })
+                .await??;
+
+                Ok(Some(Box::new(tokio::fs::File::from_std(file))))
+            }
+            Some(_) => Ok(self
+                .chunk_service()
+                .get(digest)
+                .await?
+                .map(|data| Box::new(Cursor::new(data)) as Box<dyn BlobReader>)),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn open_write(&self) -> Box<dyn BlobWriter> {
+        // ChunkingBlobWriter implements AsyncWrite, but all the chunking
+        // needs an AsyncRead, so we create a pipe here.
+        let (w, r) = tokio::io::duplex(self.avg_chunk_size as usize * 10);
+
+        let fs = self.fs.clone();
+        let chunk_service = self.chunk_service();
+        let avg_chunk_size = self.avg_chunk_size;
+
+        Box::new(ChunkingBlobWriter::new(
+            w,
+            Box::pin(async move {
+                let (blob_digest, chunks) = chunk_and_put(
+                    r,
+                    &chunk_service,
+                    avg_chunk_size / 2,
+                    avg_chunk_size,
+                    avg_chunk_size * 2,
+                )
+                .await?;
+
+                // The chunker doesn't emit any chunks for the empty blob, so
+                // store it explicitly.
+                if blob_digest.as_slice() == blake3::hash(b"").as_bytes() {
+                    chunk_service.put(Bytes::new()).await?;
+                }
+
+                // Blobs consisting of a single chunk don't need an index.
+                if !chunks.is_empty() {
+                    let stat_blob_response = StatBlobResponse {
+                        chunks,
+                        bao: "".into(), // still todo
+                    };
+
+                    fs.put(
+                        "blobs",
+                        &blob_digest,
+                        stat_blob_response.encode_to_vec(),
+                        CONTENT_TYPE_BLOB_INDEX,
+                    )
+                    .await?;
+                }
+
+                Ok(blob_digest)
+            }),
+        ))
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn chunks(&self, digest: &B3Digest) -> io::Result<Option<Vec<ChunkMeta>>> {
+        match self.read_blob_index(digest).await? {
+            Some(chunks) => {
+                debug!(chunk.count = chunks.len(), "found blob index");
+                Ok(Some(chunks))
+            }
+            // If there's only a chunk, we must return the empty vec here, rather than None.
+            None if self.fs.exists("chunks", digest).await? => Ok(Some(vec![])),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip_all)]
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        // Blobs consisting of a single chunk are only stored in chunks/, so
+        // every digest is returned once.
+        Box::pin(self.fs.list("blobs").chain(self.fs.list("chunks")))
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn delete(&self, digest: &B3Digest) -> io::Result<()> {
+        // The digest might refer to a blob index, a chunk, or both.
+        self.fs.delete("blobs", digest).await?;
+        self.fs.delete("chunks", digest).await
+    }
+}
+
+/// Reads and writes the chunks of a [FsBlobService].
+struct FsChunks {
+    fs: ShardedFs,
+    compress: bool,
+}
+
+#[async_trait]
+impl ChunkService for FsChunks {
+    #[instrument(skip_all, ret(level = Level::TRACE), err, fields(chunk.digest=%digest))]
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        self.fs.exists("chunks", digest).await
+    }
+
+    #[instrument(skip_all, err, fields(chunk.digest=%digest))]
+    async fn get(&self, digest: &B3Digest) -> io::Result<Option<Bytes>> {
+        let Some((data, content_type)) = self.fs.get("chunks", digest).await? else {
+            return Ok(None);
+        };
+
+        let chunk_contents = match content_type.as_str() {
+            CONTENT_TYPE_RAW => data,
+            // FUTUREWORK: use zstd::bulk to prevent decompression bombs
+            CONTENT_TYPE_ZSTD => zstd::stream::decode_all(Cursor::new(data))?,
+            content_type => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected content type for chunk: {}", content_type),
+            ))?,
+        };
+
+        if *digest != blake3::hash(&chunk_contents).as_bytes().into() {
+            Err(io::Error::other("chunk contents invalid"))?;
+        }
+
+        Ok(Some(chunk_contents.into()))
+    }
+
+    /// Writes the chunk, if it doesn't exist yet.
+    #[instrument(skip_all, fields(chunk.digest, chunk.size = data.len()), err)]
+    async fn put(&self, data: Bytes) -> io::Result<B3Digest> {
+        let chunk_digest: B3Digest = blake3::hash(&data).as_bytes().into();
+        tracing::Span::current().record("chunk.digest", chunk_digest.to_string());
+
+        if self.fs.exists("chunks", &chunk_digest).await? {
+            debug!("chunk already exists");
+            return Ok(chunk_digest);
+        }
+
+        if self.compress {
+            let chunk_data_compressed =
+                zstd::encode_all(Cursor::new(data), zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            self.fs
+                .put(
+                    "chunks",
+                    &chunk_digest,
+                    chunk_data_compressed,
+                    CONTENT_TYPE_ZSTD,
+                )
+                .await?;
+        } else {
+            self.fs
+                .put("chunks", &chunk_digest, data.into(), CONTENT_TYPE_RAW)
+                .await?;
+        }
+
+        Ok(chunk_digest)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FsBlobServiceConfig {
+    root: PathBuf,
+    #[serde(default = "default_avg_chunk_size")]
+    avg_chunk_size: u32,
+    #[serde(default)]
+    compress: bool,
+}
+
+impl TryFrom<url::Url> for FsBlobServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    /// Parses `fs:///path/to/blobs`. Chunks are compressed if the `compress`
+    /// query parameter is set to `true`.
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // fs doesn't support host, and requires a path.
+        if url.has_host() {
+            return Err(Error::StorageError("no host allowed".to_string()).into());
+        }
+        if url.path().is_empty() {
+            return Err(Error::StorageError("path required".to_string()).into());
+        }
+
+        let mut compress = false;
+        for (k, v) in url.query_pairs() {
+            match k.as_ref() {
+                "compress" => compress = v.parse()?,
+                _ => {
+                    return Err(
+                        Error::StorageError(format!("unknown query parameter: {}", k)).into(),
+                    )
+                }
+            }
+        }
+
+        Ok(FsBlobServiceConfig {
+            root: url.path().into(),
+            avg_chunk_size: default_avg_chunk_size(),
+            compress,
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for FsBlobServiceConfig {
+    type Output = dyn BlobService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn BlobService>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        Ok(Arc::new(
+            FsBlobService::new(self.root.clone(), self.avg_chunk_size, self.compress).await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use data_encoding::HEXLOWER;
+    use tempfile::TempDir;
+    use tokio::io::AsyncWriteExt;
+
+    use super::FsBlobService;
+    use crate::blobservice::BlobService;
+    use crate::fixtures::{BLOB_A, BLOB_A_DIGEST};
+
+    /// Uncompressed single-chunk blobs are served straight from the file,
+    /// ensure modified contents are detected there as well.
+    #[tokio::test]
+    async fn open_read_corrupted() {
+        let tmpdir = TempDir::new().unwrap();
+        let blob_service = FsBlobService::new(tmpdir.path().to_owned(), 1024, false)
+            .await
+            .unwrap();
+
+        let mut writer = blob_service.open_write().await;
+        writer.write_all(&BLOB_A).await.unwrap();
+        assert_eq!(*BLOB_A_DIGEST, writer.close().await.unwrap());
+
+        // Overwrite the chunk contents in place, keeping the content type.
+        let chunk_path = tmpdir
+            .path()
+            .join("chunks/b3")
+            .join(HEXLOWER.encode(&BLOB_A_DIGEST.as_slice()[..2]))
+            .join(HEXLOWER.encode(BLOB_A_DIGEST.as_slice()));
+        std::fs::write(&chunk_path, b"corrupted").unwrap();
+
+        let err = blob_service
+            .open_read(&BLOB_A_DIGEST)
+            .await
+            .err()
+            .expect("must fail");
+        assert_eq!("chunk contents invalid", err.to_string());
+    }
+}
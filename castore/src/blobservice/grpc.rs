@@ -1,17 +1,19 @@
-use super::{BlobReader, BlobService, BlobWriter, ChunkedReader};
+use super::{has_concurrently, BlobReader, BlobService, BlobWriter, ChunkedReader};
+use crate::chunkservice::ChunkService;
 use crate::composition::{CompositionContext, ServiceBuilder};
 use crate::{
     proto::{self, stat_blob_response::ChunkMeta},
     B3Digest,
 };
-use futures::sink::SinkExt;
+use async_stream::try_stream;
+use futures::{ready, sink::SinkExt, stream::BoxStream};
 use std::{
-    io::{self, Cursor},
+    io::{self, SeekFrom},
     pin::pin,
     sync::Arc,
     task::Poll,
 };
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWriteExt, ReadBuf};
 use tokio::task::JoinHandle;
 use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tokio_util::{
@@ -61,52 +63,69 @@ where
         }
     }
 
+    #[instrument(skip_all, fields(blob.count=digests.len()), err)]
+    async fn has_many(&self, digests: &[B3Digest]) -> io::Result<Vec<bool>> {
+        let requests: Vec<_> = digests
+            .iter()
+            .map(|digest| proto::HasBlobRequest {
+                digest: digest.clone().into(),
+            })
+            .collect();
+
+        let exists: Vec<bool> = match self
+            .grpc_client
+            .clone()
+            .has_many(tokio_stream::iter(requests))
+            .await
+        {
+            Ok(resp) => resp
+                .into_inner()
+                .map(|resp| resp.map(|resp| resp.exists))
+                .collect::<Result<_, Status>>()
+                .await
+                .map_err(io::Error::other)?,
+            // Older servers don't support batching.
+            Err(e) if e.code() == Code::Unimplemented => {
+                return has_concurrently(self, digests).await
+            }
+            Err(e) => Err(io::Error::other(e))?,
+        };
+
+        if exists.len() != digests.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "got {} responses for {} digests",
+                    exists.len(),
+                    digests.len()
+                ),
+            ));
+        }
+
+        Ok(exists)
+    }
+
     #[instrument(skip(self, digest), fields(blob.digest=%digest), err)]
     async fn open_read(&self, digest: &B3Digest) -> io::Result<Option<Box<dyn BlobReader>>> {
         // First try to get a list of chunks. In case there's only one chunk returned,
-        // buffer its data into a Vec, otherwise use a ChunkedReader.
-        // We previously used NaiveSeeker here, but userland likes to seek backwards too often,
-        // and without store composition this will get very noisy.
+        // read it with a GRPCBlobReader, which turns seeks into ranged reads.
+        // Otherwise use a ChunkedReader.
         // FUTUREWORK: use CombinedBlobService and store composition.
         match self.chunks(digest).await {
             Ok(None) => Ok(None),
             Ok(Some(chunks)) => {
                 if chunks.is_empty() || chunks.len() == 1 {
                     // No more granular chunking info, treat this as an individual chunk.
-                    // Get a stream of [proto::BlobChunk], or return an error if the blob
-                    // doesn't exist.
-                    return match self
-                        .grpc_client
-                        .clone()
-                        .read(proto::ReadBlobRequest {
-                            digest: digest.clone().into(),
-                        })
-                        .await
-                    {
-                        Ok(stream) => {
-                            let data_stream = stream.into_inner().map(|e| {
-                                e.map(|c| c.data)
-                                    .map_err(|s| std::io::Error::new(io::ErrorKind::InvalidData, s))
-                            });
-
-                            // Use StreamReader::new to convert to an AsyncRead.
-                            let mut data_reader = tokio_util::io::StreamReader::new(data_stream);
-
-                            let mut buf = Vec::new();
-                            // TODO: only do this up to a certain limit.
-                            tokio::io::copy(&mut data_reader, &mut buf).await?;
-
-                            Ok(Some(Box::new(Cursor::new(buf))))
-                        }
-                        Err(e) if e.code() == Code::NotFound => Ok(None),
-                        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
-                    };
+                    return Ok(Some(Box::new(GRPCBlobReader::new(
+                        self.grpc_client.clone(),
+                        digest.clone(),
+                        chunks.first().map(|chunk| chunk.size),
+                    ))));
                 }
 
-                // The chunked case. Let ChunkedReader do individual reads.
-                // TODO: we should store the chunking data in some local cache,
-                // so `ChunkedReader` doesn't call `self.chunks` *again* for every chunk.
-                // Think about how store composition will fix this.
+                // The chunked case. Let ChunkedReader do individual reads,
+                // fetching each chunk directly, without asking for more
+                // granular chunks again.
                 let chunked_reader = ChunkedReader::from_chunks(
                     chunks.into_iter().map(|chunk| {
                         (
@@ -114,7 +133,9 @@ where
                             chunk.size,
                         )
                     }),
-                    Arc::new(self.clone()) as Arc<dyn BlobService>,
+                    Arc::new(GRPCChunks {
+                        grpc_client: self.grpc_client.clone(),
+                    }) as Arc<dyn ChunkService>,
                 );
                 Ok(Some(Box::new(chunked_reader)))
             }
@@ -181,6 +202,332 @@ where
     }
 }
 
+/// Reads an entire blob (or chunk) into memory, using a single
+/// [proto::ReadBlobRequest].
+/// Returns None if the remote doesn't have it.
+async fn read_blob<T>(
+    mut grpc_client: proto::blob_service_client::BlobServiceClient<T>,
+    digest: &B3Digest,
+) -> io::Result<Option<Vec<u8>>>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Send + Sync + Clone + 'static,
+    T::ResponseBody: tonic::codegen::Body<Data = tonic::codegen::Bytes> + Send + 'static,
+    <T::ResponseBody as tonic::codegen::Body>::Error: Into<tonic::codegen::StdError> + Send,
+    T::Future: Send,
+{
+    match grpc_client
+        .read(proto::ReadBlobRequest {
+            digest: digest.clone().into(),
+            ..Default::default()
+        })
+        .await
+    {
+        Ok(stream) => {
+            let data_stream = stream.into_inner().map(|e| {
+                e.map(|c| c.data)
+                    .map_err(|s| std::io::Error::new(io::ErrorKind::InvalidData, s))
+            });
+
+            // Use StreamReader::new to convert to an AsyncRead.
+            let mut data_reader = tokio_util::io::StreamReader::new(data_stream);
+
+            let mut buf = Vec::new();
+            // TODO: only do this up to a certain limit.
+            tokio::io::copy(&mut data_reader, &mut buf).await?;
+
+            Ok(Some(buf))
+        }
+        Err(e) if e.code() == Code::NotFound => Ok(None),
+        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+    }
+}
+
+/// Reads a blob from a remote BlobService.
+///
+/// Data is streamed from a single [proto::ReadBlobRequest], which is only
+/// sent on the first read. Seeking to another position drops it, and the next
+/// read sends a new request, starting at that offset.
+///
+/// If the blob is read from the start until its end, the data is checked
+/// against its digest, and an error is returned at EOF if it doesn't match.
+pub struct GRPCBlobReader<T> {
+    grpc_client: proto::blob_service_client::BlobServiceClient<T>,
+    digest: B3Digest,
+
+    /// The size of the blob, if known. It's needed for [SeekFrom::End], and
+    /// learned once the blob was read until its end.
+    size: Option<u64>,
+
+    /// The position of the next byte returned.
+    pos: u64,
+
+    /// Reader for the response of the currently active read request, if any,
+    /// and the offset it was sent with.
+    reader: Option<(Box<dyn AsyncRead + Send + Unpin>, u64)>,
+
+    /// Hashes the data read so far, as long as it was read in order, from
+    /// the start of the blob.
+    hasher: Option<blake3::Hasher>,
+
+    /// The offset of a [SeekFrom::End] waiting for the size of the blob to be
+    /// learned.
+    pending_seek_end: Option<i64>,
+}
+
+// The gRPC client is never pinned.
+impl<T> Unpin for GRPCBlobReader<T> {}
+
+impl<T> GRPCBlobReader<T>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Send + Sync + Clone + 'static,
+    T::ResponseBody: tonic::codegen::Body<Data = tonic::codegen::Bytes> + Send + 'static,
+    <T::ResponseBody as tonic::codegen::Body>::Error: Into<tonic::codegen::StdError> + Send,
+    T::Future: Send,
+{
+    pub fn new(
+        grpc_client: proto::blob_service_client::BlobServiceClient<T>,
+        digest: B3Digest,
+        size: Option<u64>,
+    ) -> Self {
+        Self {
+            grpc_client,
+            digest,
+            size,
+            pos: 0,
+            reader: None,
+            hasher: Some(blake3::Hasher::new()),
+            pending_seek_end: None,
+        }
+    }
+
+    /// Moves to another position. The next read sends a new request.
+    fn set_pos(&mut self, new_pos: u64) {
+        if new_pos != self.pos {
+            self.reader = None;
+            self.pos = new_pos;
+            self.hasher = (new_pos == 0).then(blake3::Hasher::new);
+        }
+    }
+
+    /// Reads into `buf`, starting at the current position, and keeps track of
+    /// the position. Once the end is reached, the size of the blob is known,
+    /// and its digest is checked if everything was read in order.
+    fn poll_read_at_pos(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let (reader, offset) = match self.reader {
+            Some(ref mut reader) => reader,
+            None => self.reader.insert((self.read_from_pos(), self.pos)),
+        };
+        let offset = *offset;
+
+        let filled_before = buf.filled().len();
+        ready!(pin!(reader).poll_read(cx, buf))?;
+        let data = &buf.filled()[filled_before..];
+        let n = data.len() as u64;
+
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(data);
+        }
+
+        // If we reached the end after reading some data, we now know the size
+        // of the blob. Requests starting past the end don't return any data.
+        if n == 0 && buf.remaining() != 0 && (self.pos > offset || self.pos == 0) {
+            self.size = Some(self.pos);
+
+            if let Some(hasher) = self.hasher.take() {
+                if self.digest != hasher.finalize().as_bytes().into() {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "blob contents invalid",
+                    )));
+                }
+            }
+        }
+        self.pos += n;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Returns a reader for the blob, starting at the current position.
+    fn read_from_pos(&self) -> Box<dyn AsyncRead + Send + Unpin> {
+        let mut grpc_client = self.grpc_client.clone();
+        let request = proto::ReadBlobRequest {
+            digest: self.digest.clone().into(),
+            offset: self.pos,
+            length: 0,
+        };
+
+        let data_stream = try_stream! {
+            let mut stream = match grpc_client.read(request).await {
+                Ok(resp) => Some(resp.into_inner()),
+                // We're past the end of the blob.
+                Err(e) if e.code() == Code::OutOfRange => None,
+                Err(e) if e.code() == Code::NotFound => {
+                    Err(io::Error::new(io::ErrorKind::NotFound, e))?
+                }
+                Err(e) => Err(io::Error::other(e))?,
+            };
+
+            if let Some(stream) = stream.as_mut() {
+                while let Some(blob_chunk) = stream
+                    .message()
+                    .await
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                {
+                    yield blob_chunk.data;
+                }
+            }
+        };
+
+        Box::new(tokio_util::io::StreamReader::new(
+            Box::pin(data_stream) as BoxStream<'static, io::Result<bytes::Bytes>>
+        ))
+    }
+}
+
+impl<T> AsyncRead for GRPCBlobReader<T>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Send + Sync + Clone + 'static,
+    T::ResponseBody: tonic::codegen::Body<Data = tonic::codegen::Bytes> + Send + 'static,
+    <T::ResponseBody as tonic::codegen::Body>::Error: Into<tonic::codegen::StdError> + Send,
+    T::Future: Send,
+{
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.poll_read_at_pos(cx, buf)
+    }
+}
+
+impl<T> AsyncSeek for GRPCBlobReader<T>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Send + Sync + Clone + 'static,
+    T::ResponseBody: tonic::codegen::Body<Data = tonic::codegen::Bytes> + Send + 'static,
+    <T::ResponseBody as tonic::codegen::Body>::Error: Into<tonic::codegen::StdError> + Send,
+    T::Future: Send,
+{
+    fn start_seek(mut self: std::pin::Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let new_pos = match position {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(offset) => match self.size {
+                Some(size) => size.checked_add_signed(offset),
+                None => {
+                    // The size is learned in poll_complete.
+                    self.pending_seek_end = Some(offset);
+                    return Ok(());
+                }
+            },
+        }
+        .ok_or_else(invalid_seek)?;
+
+        self.set_pos(new_pos);
+
+        Ok(())
+    }
+
+    fn poll_complete(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<io::Result<u64>> {
+        let this = &mut *self;
+        if let Some(offset) = this.pending_seek_end {
+            // The size isn't known yet, so read until the end to learn it,
+            // without keeping the data around.
+            // Unless the blob is being read from the start already, start
+            // over, so the size is learned (and the digest checked) for sure.
+            if this.hasher.is_none() && this.size.is_none() {
+                this.reader = None;
+                this.pos = 0;
+                this.hasher = Some(blake3::Hasher::new());
+            }
+
+            let mut scratch = [0u8; 8192];
+            let size = loop {
+                if let Some(size) = this.size {
+                    break size;
+                }
+                ready!(this.poll_read_at_pos(cx, &mut ReadBuf::new(&mut scratch)))?;
+            };
+
+            this.pending_seek_end = None;
+            this.set_pos(size.checked_add_signed(offset).ok_or_else(invalid_seek)?);
+        }
+
+        Poll::Ready(Ok(this.pos))
+    }
+}
+
+fn invalid_seek() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "invalid seek to a negative or overflowing position",
+    )
+}
+
+impl<T> BlobReader for GRPCBlobReader<T>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Send + Sync + Clone + 'static,
+    T::ResponseBody: tonic::codegen::Body<Data = tonic::codegen::Bytes> + Send + 'static,
+    <T::ResponseBody as tonic::codegen::Body>::Error: Into<tonic::codegen::StdError> + Send,
+    T::Future: Send,
+{
+}
+
+/// Exposes the individual chunks of a remote BlobService as a [ChunkService],
+/// used by the [ChunkedReader] in [GRPCBlobService::open_read].
+struct GRPCChunks<T> {
+    grpc_client: proto::blob_service_client::BlobServiceClient<T>,
+}
+
+#[async_trait]
+impl<T> ChunkService for GRPCChunks<T>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Send + Sync + Clone + 'static,
+    T::ResponseBody: tonic::codegen::Body<Data = tonic::codegen::Bytes> + Send + 'static,
+    <T::ResponseBody as tonic::codegen::Body>::Error: Into<tonic::codegen::StdError> + Send,
+    T::Future: Send,
+{
+    #[instrument(skip(self, digest), fields(chunk.digest=%digest), err)]
+    async fn get(&self, digest: &B3Digest) -> io::Result<Option<bytes::Bytes>> {
+        match read_blob(self.grpc_client.clone(), digest).await? {
+            None => Ok(None),
+            Some(buf) => {
+                // don't trust the remote, ensure the chunk contents match the
+                // digest, as required by the ChunkService contract.
+                if *digest != blake3::hash(&buf).as_bytes().into() {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "chunk contents invalid",
+                    ))?;
+                }
+
+                Ok(Some(buf.into()))
+            }
+        }
+    }
+
+    #[instrument(skip_all, fields(chunk.size=data.len()), err)]
+    async fn put(&self, data: bytes::Bytes) -> io::Result<B3Digest> {
+        let resp = self
+            .grpc_client
+            .clone()
+            .put(tokio_stream::once(proto::BlobChunk { data }))
+            .await
+            .map_err(io::Error::other)?;
+
+        resp.into_inner()
+            .digest
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid digest returned"))
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct GRPCBlobServiceConfig {
@@ -0,0 +1,970 @@
+use std::{
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use redb::{Database, ReadableTable, TableDefinition};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tonic::async_trait;
+use tracing::{debug, instrument, warn};
+
+use crate::chunkservice::ChunkService;
+use crate::composition::{CompositionContext, ServiceBuilder};
+use crate::proto::stat_blob_response::ChunkMeta;
+use crate::{digests, B3Digest, Error};
+
+use super::{BlobReader, BlobService, BlobWriter, ChunkedReader};
+
+/// Maps the digest of each cached blob or chunk to its access tick and size.
+const ENTRIES_TABLE: TableDefinition<[u8; digests::B3_LEN], (u64, u64)> =
+    TableDefinition::new("entries");
+/// Maps access ticks to the digest accessed, so the first entry is the least
+/// recently used one.
+const ACCESS_TABLE: TableDefinition<u64, [u8; digests::B3_LEN]> = TableDefinition::new("access");
+/// Maps the digest of each cached blob or chunk to the digests of the objects
+/// it's stored as in the local BlobService, concatenated. These are its chunks
+/// if the local BlobService split it up, or the entry itself otherwise.
+const HOLDS_TABLE: TableDefinition<[u8; digests::B3_LEN], &[u8]> = TableDefinition::new("holds");
+/// Counts how many cached blobs or chunks are stored using each object in the
+/// local BlobService. Chunks can be shared, so they're only deleted once
+/// nothing refers to them anymore.
+const REFS_TABLE: TableDefinition<[u8; digests::B3_LEN], u64> = TableDefinition::new("refs");
+const META_TABLE: TableDefinition<&str, u64> = TableDefinition::new("meta");
+const TOTAL_SIZE_KEY: &str = "total_size";
+
+/// Caches blobs from a "remote" BlobService in a "local" one, keeping the
+/// total size of the local BlobService below `max_size` by evicting the least
+/// recently used chunks.
+///
+/// Like [super::CombinedBlobService], blobs that are not cached yet are read
+/// chunk by chunk, so chunks shared with other cached blobs are served
+/// locally. Every chunk read from the remote is copied to the local
+/// BlobService. Blobs the remote doesn't provide more granular chunks for are
+/// copied as a whole.
+///
+/// What's cached, and the order in which it was last accessed, is tracked in a
+/// redb index. It should be kept on disk whenever the local BlobService is
+/// persistent, as everything not in the index is neither served nor evicted.
+///
+/// If the local BlobService splits up what's written to it into chunks, these
+/// chunks are tracked as well, and deleted once no cached blob or chunk
+/// consists of them anymore, so `max_size` bounds the actual disk usage.
+///
+/// As the local BlobService only holds copies, writes go to the remote
+/// BlobService. The local BlobService needs to support
+/// [BlobService::delete].
+pub struct LruCacheBlobService<BL, BR> {
+    local: BL,
+    remote: BR,
+    index: Arc<LruIndex>,
+}
+
+impl<BL, BR> LruCacheBlobService<BL, BR> {
+    pub fn new(local: BL, remote: BR, index: LruIndex) -> Self {
+        Self {
+            local,
+            remote,
+            index: Arc::new(index),
+        }
+    }
+}
+
+impl<BL, BR> Clone for LruCacheBlobService<BL, BR>
+where
+    BL: Clone,
+    BR: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            local: self.local.clone(),
+            remote: self.remote.clone(),
+            index: self.index.clone(),
+        }
+    }
+}
+
+impl<BL, BR> LruCacheBlobService<BL, BR>
+where
+    BL: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+    BR: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+{
+    /// Runs a (blocking) operation on the index.
+    async fn with_index<T, F>(&self, f: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&LruIndex) -> Result<T, Error> + Send + 'static,
+    {
+        let index = self.index.clone();
+        Ok(tokio::task::spawn_blocking(move || f(&index)).await??)
+    }
+
+    /// Opens a reader for a cached blob or chunk, marking it as recently used.
+    /// Returns None if it's not cached.
+    async fn open_read_local(&self, digest: &B3Digest) -> io::Result<Option<Box<dyn BlobReader>>> {
+        let key = digest.clone();
+        if !self.with_index(move |index| index.touch(&key)).await? {
+            return Ok(None);
+        }
+
+        match self.local.as_ref().open_read(digest).await? {
+            Some(reader) => Ok(Some(reader)),
+            None => {
+                // The local BlobService lost it, so stop tracking it.
+                warn!(blob.digest=%digest, "cached blob missing in local store");
+                let key = digest.clone();
+                let unused = self.with_index(move |index| index.remove(&key)).await?;
+                self.delete_local(unused).await;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Adds a blob or chunk that was just written to the local BlobService to
+    /// the index, and evicts the least recently used entries if the cache
+    /// grew too large.
+    async fn record(&self, digest: &B3Digest, size: u64) -> io::Result<()> {
+        // If the local BlobService split it up into chunks, keep track of
+        // these, deleting the blob alone would leave them behind.
+        let holds = match self.local.as_ref().chunks(digest).await? {
+            Some(chunks) if !chunks.is_empty() => chunks
+                .into_iter()
+                .map(|chunk| B3Digest::try_from(chunk.digest))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk digest"))?,
+            _ => vec![digest.clone()],
+        };
+
+        let key = digest.clone();
+        let unused = self
+            .with_index(move |index| index.insert(&key, size, &holds))
+            .await?;
+        self.delete_local(unused).await;
+
+        Ok(())
+    }
+
+    /// Deletes blobs and chunks no longer used by the cache from the local
+    /// BlobService.
+    async fn delete_local(&self, digests: Vec<B3Digest>) {
+        for digest in digests {
+            debug!(blob.digest=%digest, "evicting");
+            if let Err(e) = self.local.as_ref().delete(&digest).await {
+                warn!(blob.digest=%digest, err=%e, "unable to evict");
+            }
+        }
+    }
+
+    /// Copies a blob from the remote to the local BlobService, without
+    /// loading it into memory. Returns false if the remote doesn't have it.
+    async fn fetch(&self, digest: &B3Digest) -> io::Result<bool> {
+        let Some(mut reader) = self.remote.as_ref().open_read(digest).await? else {
+            return Ok(false);
+        };
+
+        let mut writer = self.local.as_ref().open_write().await;
+        let size = tokio::io::copy(&mut reader, &mut writer).await?;
+        let local_digest = writer.close().await?;
+
+        if local_digest != *digest {
+            // Don't keep untracked data around.
+            let _ = self.local.as_ref().delete(&local_digest).await;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("requested blob {}, but got {}", digest, local_digest),
+            ));
+        }
+
+        self.record(digest, size).await?;
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl<BL, BR> BlobService for LruCacheBlobService<BL, BR>
+where
+    BL: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+    BR: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+{
+    #[instrument(skip(self, digest), fields(blob.digest=%digest))]
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        let key = digest.clone();
+        Ok(self.with_index(move |index| index.contains(&key)).await?
+            || self.remote.as_ref().has(digest).await?)
+    }
+
+    #[instrument(skip(self, digest), fields(blob.digest=%digest), err)]
+    async fn open_read(&self, digest: &B3Digest) -> io::Result<Option<Box<dyn BlobReader>>> {
+        if let Some(reader) = self.open_read_local(digest).await? {
+            return Ok(Some(reader));
+        }
+
+        match self.remote.as_ref().chunks(digest).await? {
+            // blob doesn't exist on the remote side either, nothing we can do.
+            None => Ok(None),
+            Some(remote_chunks) if remote_chunks.is_empty() => {
+                // There's no more granular chunks, so cache the blob as a
+                // whole, and read it from the local BlobService.
+                if self.fetch(digest).await? {
+                    if let Some(reader) = self.local.as_ref().open_read(digest).await? {
+                        return Ok(Some(reader));
+                    }
+                }
+
+                // It got evicted again right away (because it's larger than
+                // the cache), or disappeared on the remote side in the
+                // meantime.
+                self.remote.as_ref().open_read(digest).await
+            }
+            Some(remote_chunks) => {
+                let chunked_reader = ChunkedReader::try_from_chunk_metas(
+                    remote_chunks,
+                    Arc::new(LruCacheChunks(self.clone())) as Arc<dyn ChunkService>,
+                )?;
+                Ok(Some(Box::new(chunked_reader)))
+            }
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn open_write(&self) -> Box<dyn BlobWriter> {
+        // Everything in the local BlobService might be evicted, so writes
+        // need to go to the remote one.
+        self.remote.as_ref().open_write().await
+    }
+
+    #[instrument(skip(self, digest), fields(blob.digest=%digest), err)]
+    async fn chunks(&self, digest: &B3Digest) -> io::Result<Option<Vec<ChunkMeta>>> {
+        // The local BlobService might chunk differently, or only hold some
+        // of the chunks.
+        self.remote.as_ref().chunks(digest).await
+    }
+}
+
+/// Provides individual chunks to the [ChunkedReader] used in
+/// [LruCacheBlobService::open_read], reading them from the local
+/// BlobService, or fetching them into it from the remote one.
+struct LruCacheChunks<BL, BR>(LruCacheBlobService<BL, BR>);
+
+#[async_trait]
+impl<BL, BR> ChunkService for LruCacheChunks<BL, BR>
+where
+    BL: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+    BR: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+{
+    #[instrument(skip(self, digest), fields(chunk.digest=%digest), err)]
+    async fn get(&self, digest: &B3Digest) -> io::Result<Option<Bytes>> {
+        let mut buf = Vec::new();
+
+        if let Some(mut reader) = self.0.open_read_local(digest).await? {
+            reader.read_to_end(&mut buf).await?;
+            if *digest == blake3::hash(&buf).as_bytes().into() {
+                return Ok(Some(buf.into()));
+            }
+
+            // The local copy got corrupted, drop it and fetch it again.
+            warn!(chunk.digest=%digest, "cached chunk contents invalid");
+            let key = digest.clone();
+            let unused = self.0.with_index(move |index| index.remove(&key)).await?;
+            self.0.delete_local(unused).await;
+            buf.clear();
+        }
+
+        let Some(mut reader) = self.0.remote.as_ref().open_read(digest).await? else {
+            return Ok(None);
+        };
+        reader.read_to_end(&mut buf).await?;
+
+        if *digest != blake3::hash(&buf).as_bytes().into() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk {} contents invalid", digest),
+            ));
+        }
+
+        let mut writer = self.0.local.as_ref().open_write().await;
+        writer.write_all(&buf).await?;
+        writer.close().await?;
+        self.0.record(digest, buf.len() as u64).await?;
+
+        Ok(Some(buf.into()))
+    }
+
+    #[instrument(skip_all, err)]
+    async fn put(&self, data: Bytes) -> io::Result<B3Digest> {
+        let mut writer = self.0.open_write().await;
+        writer.write_all(&data).await?;
+        writer.close().await
+    }
+}
+
+/// Keeps track of the blobs and chunks stored in the local BlobService of a
+/// [LruCacheBlobService], their sizes, and the order they were last accessed
+/// in.
+///
+/// Accesses are ordered by a counter rather than wall-clock time, which is
+/// stored along with the entries, so the order survives restarts.
+pub struct LruIndex {
+    // We wrap the db in an Arc to be able to move it into spawn_blocking,
+    // as discussed in https://github.com/cberner/redb/issues/789
+    db: Arc<Database>,
+    max_size: u64,
+    next_tick: AtomicU64,
+}
+
+impl LruIndex {
+    /// Opens (or creates) the index at the given path.
+    pub async fn new(path: PathBuf, max_size: u64) -> Result<Self, Error> {
+        if path == std::path::Path::new("/") {
+            return Err(Error::StorageError(
+                "cowardly refusing to open / with redb".to_string(),
+            ));
+        }
+
+        let db = tokio::task::spawn_blocking(|| redb::Database::create(path)).await??;
+
+        Self::from_db(db, max_size)
+    }
+
+    /// Constructs a new index using the in-memory backend.
+    pub fn new_temporary(max_size: u64) -> Result<Self, Error> {
+        let db =
+            redb::Database::builder().create_with_backend(redb::backends::InMemoryBackend::new())?;
+
+        Self::from_db(db, max_size)
+    }
+
+    /// Ensures all tables are present, and picks up the access counter where
+    /// it was left.
+    fn from_db(db: Database, max_size: u64) -> Result<Self, Error> {
+        let txn = db.begin_write()?;
+        txn.open_table(ENTRIES_TABLE)?;
+        txn.open_table(HOLDS_TABLE)?;
+        txn.open_table(REFS_TABLE)?;
+        let next_tick = match txn.open_table(ACCESS_TABLE)?.last()? {
+            Some((tick, _)) => tick.value() + 1,
+            None => 0,
+        };
+        txn.open_table(META_TABLE)?;
+        txn.commit()?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            max_size,
+            next_tick: AtomicU64::new(next_tick),
+        })
+    }
+
+    /// Returns the total size of everything in the cache.
+    pub fn total_size(&self) -> Result<u64, Error> {
+        let txn = self.db.begin_read()?;
+        let meta = txn.open_table(META_TABLE)?;
+        Ok(meta.get(TOTAL_SIZE_KEY)?.map(|v| v.value()).unwrap_or(0))
+    }
+
+    fn contains(&self, digest: &B3Digest) -> Result<bool, Error> {
+        let txn = self.db.begin_read()?;
+        let entries = txn.open_table(ENTRIES_TABLE)?;
+        Ok(entries
+            .get(Into::<[u8; digests::B3_LEN]>::into(digest.clone()))?
+            .is_some())
+    }
+
+    /// Marks an entry as most recently used.
+    /// Returns false if there's no such entry.
+    fn touch(&self, digest: &B3Digest) -> Result<bool, Error> {
+        let key: [u8; digests::B3_LEN] = digest.clone().into();
+
+        let txn = self.db.begin_write()?;
+        {
+            let mut entries = txn.open_table(ENTRIES_TABLE)?;
+            let mut access = txn.open_table(ACCESS_TABLE)?;
+
+            let Some((old_tick, size)) = entries.get(key)?.map(|v| v.value()) else {
+                return Ok(false);
+            };
+
+            let tick = self.next_tick.fetch_add(1, Ordering::Relaxed);
+            access.remove(old_tick)?;
+            access.insert(tick, key)?;
+            entries.insert(key, (tick, size))?;
+        }
+        txn.commit()?;
+
+        Ok(true)
+    }
+
+    /// Adds (or updates) an entry, marking it as most recently used.
+    /// `holds` are the digests of the objects in the local BlobService the
+    /// entry is stored as.
+    /// If this grows the cache beyond its maximum size, the least recently
+    /// used entries are removed. The digests of all objects in the local
+    /// BlobService no longer used by any entry are returned.
+    fn insert(
+        &self,
+        digest: &B3Digest,
+        size: u64,
+        holds: &[B3Digest],
+    ) -> Result<Vec<B3Digest>, Error> {
+        let key: [u8; digests::B3_LEN] = digest.clone().into();
+        let mut unused;
+
+        let txn = self.db.begin_write()?;
+        {
+            let mut entries = txn.open_table(ENTRIES_TABLE)?;
+            let mut access = txn.open_table(ACCESS_TABLE)?;
+            let mut holds_table = txn.open_table(HOLDS_TABLE)?;
+            let mut refs = txn.open_table(REFS_TABLE)?;
+            let mut meta = txn.open_table(META_TABLE)?;
+
+            let mut total_size = meta.get(TOTAL_SIZE_KEY)?.map(|v| v.value()).unwrap_or(0);
+
+            unused = release(&mut holds_table, &mut refs, key)?;
+            if let Some((old_tick, old_size)) = entries.remove(key)?.map(|v| v.value()) {
+                access.remove(old_tick)?;
+                total_size -= old_size;
+            }
+
+            let tick = self.next_tick.fetch_add(1, Ordering::Relaxed);
+            entries.insert(key, (tick, size))?;
+            access.insert(tick, key)?;
+            acquire(&mut holds_table, &mut refs, key, holds)?;
+            total_size += size;
+
+            while total_size > self.max_size {
+                let Some(evicted_key) = access.pop_first()?.map(|(_, v)| v.value()) else {
+                    break;
+                };
+                if let Some((_, evicted_size)) = entries.remove(evicted_key)?.map(|v| v.value()) {
+                    total_size -= evicted_size;
+                }
+                unused.push(evicted_key);
+                unused.extend(release(&mut holds_table, &mut refs, evicted_key)?);
+            }
+
+            meta.insert(TOTAL_SIZE_KEY, total_size)?;
+
+            unused = filter_unused(&entries, &refs, unused)?;
+        }
+        txn.commit()?;
+
+        Ok(unused.iter().map(B3Digest::from).collect())
+    }
+
+    /// Removes an entry, if present.
+    /// The digests of all objects in the local BlobService no longer used by
+    /// any entry are returned.
+    fn remove(&self, digest: &B3Digest) -> Result<Vec<B3Digest>, Error> {
+        let key: [u8; digests::B3_LEN] = digest.clone().into();
+        let unused;
+
+        let txn = self.db.begin_write()?;
+        {
+            let mut entries = txn.open_table(ENTRIES_TABLE)?;
+            let mut access = txn.open_table(ACCESS_TABLE)?;
+            let mut holds_table = txn.open_table(HOLDS_TABLE)?;
+            let mut refs = txn.open_table(REFS_TABLE)?;
+            let mut meta = txn.open_table(META_TABLE)?;
+
+            let removed = entries.remove(key)?.map(|v| v.value());
+            if let Some((tick, size)) = removed {
+                access.remove(tick)?;
+                let total_size = meta.get(TOTAL_SIZE_KEY)?.map(|v| v.value()).unwrap_or(0);
+                meta.insert(TOTAL_SIZE_KEY, total_size - size)?;
+            }
+
+            let released = release(&mut holds_table, &mut refs, key)?;
+            unused = filter_unused(&entries, &refs, released)?;
+        }
+        txn.commit()?;
+
+        Ok(unused.iter().map(B3Digest::from).collect())
+    }
+}
+
+/// Records the objects in the local BlobService an entry is stored as, and
+/// increments their reference counts.
+fn acquire(
+    holds_table: &mut redb::Table<[u8; digests::B3_LEN], &'static [u8]>,
+    refs: &mut redb::Table<[u8; digests::B3_LEN], u64>,
+    key: [u8; digests::B3_LEN],
+    holds: &[B3Digest],
+) -> Result<(), Error> {
+    let mut held = Vec::with_capacity(holds.len() * digests::B3_LEN);
+    for digest in holds {
+        let held_key: [u8; digests::B3_LEN] = digest.clone().into();
+        let count = refs.get(held_key)?.map(|v| v.value()).unwrap_or(0);
+        refs.insert(held_key, count + 1)?;
+        held.extend_from_slice(&held_key);
+    }
+    holds_table.insert(key, held.as_slice())?;
+
+    Ok(())
+}
+
+/// Decrements the reference counts of the objects in the local BlobService an
+/// entry is stored as, and returns their digests.
+fn release(
+    holds_table: &mut redb::Table<[u8; digests::B3_LEN], &'static [u8]>,
+    refs: &mut redb::Table<[u8; digests::B3_LEN], u64>,
+    key: [u8; digests::B3_LEN],
+) -> Result<Vec<[u8; digests::B3_LEN]>, Error> {
+    let Some(held) = holds_table.remove(key)?.map(|v| v.value().to_vec()) else {
+        return Ok(vec![]);
+    };
+
+    let mut released = Vec::new();
+    for held_key in held.chunks_exact(digests::B3_LEN) {
+        let held_key: [u8; digests::B3_LEN] = held_key.try_into().expect("chunk has B3_LEN");
+        let count = refs.get(held_key)?.map(|v| v.value()).unwrap_or(0);
+        if count > 1 {
+            refs.insert(held_key, count - 1)?;
+        } else {
+            refs.remove(held_key)?;
+        }
+        released.push(held_key);
+    }
+
+    Ok(released)
+}
+
+/// Returns the digests which are neither an entry, nor used to store one,
+/// deduplicated.
+fn filter_unused(
+    entries: &redb::Table<[u8; digests::B3_LEN], (u64, u64)>,
+    refs: &redb::Table<[u8; digests::B3_LEN], u64>,
+    mut candidates: Vec<[u8; digests::B3_LEN]>,
+) -> Result<Vec<[u8; digests::B3_LEN]>, Error> {
+    candidates.sort();
+    candidates.dedup();
+
+    let mut unused = Vec::with_capacity(candidates.len());
+    for key in candidates {
+        if entries.get(key)?.is_none() && refs.get(key)?.is_none() {
+            unused.push(key);
+        }
+    }
+
+    Ok(unused)
+}
+
+/// Parses a size in bytes, optionally followed by a decimal (`KB`, `MB`, …)
+/// or binary (`KiB`, `MiB`, …) unit, like `20GiB`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+
+    let number: u64 = number
+        .parse()
+        .map_err(|e| format!("invalid size {}: {}", s, e))?;
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "KB" => 1000,
+        "MB" => 1000u64.pow(2),
+        "GB" => 1000u64.pow(3),
+        "TB" => 1000u64.pow(4),
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        unit => return Err(format!("invalid unit in size {}: {}", s, unit)),
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size {} too large", s))
+}
+
+/// Accepts both a plain number of bytes, and a string parsed by [parse_size].
+fn deserialize_size<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        String(String),
+    }
+
+    match <Size as serde::Deserialize>::deserialize(deserializer)? {
+        Size::Bytes(size) => Ok(size),
+        Size::String(s) => parse_size(&s).map_err(serde::de::Error::custom),
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LruCacheBlobServiceConfig {
+    local: String,
+    remote: String,
+    /// The maximum total size of everything cached in the local BlobService.
+    #[serde(deserialize_with = "deserialize_size")]
+    max_size: u64,
+    /// Path to the redb database holding the index.
+    /// If not set, the index is kept in memory only.
+    #[serde(default)]
+    index_path: Option<PathBuf>,
+}
+
+impl TryFrom<url::Url> for LruCacheBlobServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(_url: url::Url) -> Result<Self, Self::Error> {
+        Err(Error::StorageError(
+            "Instantiating a LruCacheBlobService from a url is not supported".into(),
+        )
+        .into())
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for LruCacheBlobServiceConfig {
+    type Output = dyn BlobService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        context: &CompositionContext,
+    ) -> Result<Arc<dyn BlobService>, Box<dyn std::error::Error + Send + Sync>> {
+        let (local, remote) = futures::join!(
+            context.resolve(self.local.clone()),
+            context.resolve(self.remote.clone())
+        );
+        let index = match &self.index_path {
+            Some(path) => LruIndex::new(path.clone(), self.max_size).await?,
+            None => LruIndex::new_temporary(self.max_size)?,
+        };
+
+        Ok(Arc::new(LruCacheBlobService::new(local?, remote?, index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rstest::rstest;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{parse_size, LruCacheBlobService, LruIndex};
+    use crate::blobservice::tests::utils::CorruptingBlobService;
+    use crate::blobservice::{BlobService, FsBlobService, MemoryBlobService};
+    use crate::fixtures::{BLOB_A, BLOB_A_DIGEST, BLOB_B, BLOB_B_DIGEST};
+    use crate::B3Digest;
+
+    #[rstest]
+    #[case::bytes("1234", Ok(1234))]
+    #[case::bytes_unit("1234B", Ok(1234))]
+    #[case::kilobytes("2KB", Ok(2000))]
+    #[case::gibibytes("20GiB", Ok(20 * 1024 * 1024 * 1024))]
+    #[case::space("20 MiB", Ok(20 * 1024 * 1024))]
+    #[case::unknown_unit("20GiBs", Err(()))]
+    #[case::no_number("GiB", Err(()))]
+    #[case::overflow("20000000TiB", Err(()))]
+    fn test_parse_size(#[case] s: &str, #[case] expected: Result<u64, ()>) {
+        assert_eq!(expected, parse_size(s).map_err(|_| ()));
+    }
+
+    async fn read_blob(blob_service: &impl BlobService, digest: &B3Digest) -> Vec<u8> {
+        let mut buf = Vec::new();
+        blob_service
+            .open_read(digest)
+            .await
+            .expect("must not fail")
+            .expect("must be some")
+            .read_to_end(&mut buf)
+            .await
+            .expect("must not fail");
+        buf
+    }
+
+    async fn put_blob(blob_service: &impl BlobService, data: &[u8]) -> B3Digest {
+        let mut writer = blob_service.open_write().await;
+        writer.write_all(data).await.unwrap();
+        writer.close().await.unwrap()
+    }
+
+    /// Reading blobs copies them into the local BlobService, evicting the
+    /// least recently used ones once the cache is full.
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        let local = Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>;
+        let remote = Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>;
+
+        let blob_c = vec![0x42; BLOB_A.len()];
+        let blob_c_digest = put_blob(&remote, &blob_c).await;
+        put_blob(&remote, &BLOB_A).await;
+
+        // Only BLOB_A and blob_c fit into the cache.
+        let cache = LruCacheBlobService::new(
+            local.clone(),
+            remote.clone(),
+            LruIndex::new_temporary(BLOB_A.len() as u64 * 2).unwrap(),
+        );
+
+        // Writes go to the remote BlobService.
+        put_blob(&cache, &BLOB_B).await;
+        assert!(remote.has(&BLOB_B_DIGEST).await.unwrap());
+        assert!(!local.has(&BLOB_B_DIGEST).await.unwrap());
+
+        assert_eq!(BLOB_A.to_vec(), read_blob(&cache, &BLOB_A_DIGEST).await);
+        assert_eq!(blob_c, read_blob(&cache, &blob_c_digest).await);
+        assert!(local.has(&BLOB_A_DIGEST).await.unwrap());
+        assert!(local.has(&blob_c_digest).await.unwrap());
+
+        // Access BLOB_A again, so blob_c becomes the least recently used one.
+        assert_eq!(BLOB_A.to_vec(), read_blob(&cache, &BLOB_A_DIGEST).await);
+
+        // BLOB_B is larger than the cache, so it evicts both, and itself.
+        assert_eq!(BLOB_B.to_vec(), read_blob(&cache, &BLOB_B_DIGEST).await);
+        assert!(!local.has(&BLOB_A_DIGEST).await.unwrap());
+        assert!(!local.has(&blob_c_digest).await.unwrap());
+        assert!(!local.has(&BLOB_B_DIGEST).await.unwrap());
+        assert_eq!(0, cache.index.total_size().unwrap());
+
+        // Read blob_c and BLOB_A again, then another blob of the same size.
+        // This must evict blob_c, which was used least recently.
+        read_blob(&cache, &blob_c_digest).await;
+        read_blob(&cache, &BLOB_A_DIGEST).await;
+        read_blob(&cache, &BLOB_A_DIGEST).await;
+        let blob_d = vec![0x23; BLOB_A.len()];
+        let blob_d_digest = put_blob(&remote, &blob_d).await;
+        read_blob(&cache, &blob_d_digest).await;
+
+        assert!(local.has(&BLOB_A_DIGEST).await.unwrap());
+        assert!(!local.has(&blob_c_digest).await.unwrap());
+        assert!(local.has(&blob_d_digest).await.unwrap());
+        assert_eq!(BLOB_A.len() as u64 * 2, cache.index.total_size().unwrap());
+    }
+
+    /// The index keeps track of sizes and access order across restarts.
+    #[tokio::test]
+    async fn index_persists() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let index_path = tmpdir.path().join("index.redb");
+        let digests: Vec<B3Digest> = (0u8..3)
+            .map(|i| blake3::hash(&[i]).as_bytes().into())
+            .collect();
+
+        {
+            let index = LruIndex::new(index_path.clone(), 3).await.unwrap();
+            for digest in &digests {
+                assert!(index
+                    .insert(digest, 1, std::slice::from_ref(digest))
+                    .unwrap()
+                    .is_empty());
+            }
+            // Make the first one the most recently used.
+            assert!(index.touch(&digests[0]).unwrap());
+        }
+
+        let index = LruIndex::new(index_path, 3).await.unwrap();
+        assert_eq!(3, index.total_size().unwrap());
+        assert!(index.contains(&digests[2]).unwrap());
+
+        let new_digest: B3Digest = blake3::hash(b"new").as_bytes().into();
+        assert_eq!(
+            vec![digests[1].clone()],
+            index
+                .insert(&new_digest, 1, std::slice::from_ref(&new_digest))
+                .unwrap()
+        );
+    }
+
+    /// Returns some pseudo-random data, which FastCDC splits up into chunks.
+    fn random_blob(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    /// Returns the number of bytes in all files below the given path.
+    fn disk_usage(path: &std::path::Path) -> u64 {
+        std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let metadata = entry.metadata().unwrap();
+                if metadata.is_dir() {
+                    disk_usage(&entry.path())
+                } else {
+                    metadata.len()
+                }
+            })
+            .sum()
+    }
+
+    /// If the local BlobService chunks blobs, evicting a blob must delete its
+    /// chunks as well.
+    #[tokio::test]
+    async fn evicts_local_chunks() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let local = Arc::new(
+            FsBlobService::new(tmpdir.path().to_owned(), 1024, false)
+                .await
+                .unwrap(),
+        ) as Arc<dyn BlobService>;
+        let remote = Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>;
+
+        let blob = random_blob(64 * 1024, 1);
+        let blob_digest = put_blob(&remote, &blob).await;
+        put_blob(&remote, &BLOB_A).await;
+
+        // Only one of the blobs fits into the cache.
+        let cache = LruCacheBlobService::new(
+            local.clone(),
+            remote.clone(),
+            LruIndex::new_temporary(blob.len() as u64).unwrap(),
+        );
+
+        assert_eq!(blob, read_blob(&cache, &blob_digest).await);
+        assert!(local.chunks(&blob_digest).await.unwrap().unwrap().len() > 1);
+        let usage_cached = disk_usage(&tmpdir.path().join("chunks"));
+        assert!(usage_cached >= blob.len() as u64);
+
+        // Reading BLOB_A evicts the blob, and all of its chunks.
+        assert_eq!(BLOB_A.to_vec(), read_blob(&cache, &BLOB_A_DIGEST).await);
+        assert!(!local.has(&blob_digest).await.unwrap());
+        assert_eq!(
+            BLOB_A.len() as u64,
+            disk_usage(&tmpdir.path().join("chunks"))
+        );
+        assert_eq!(0, disk_usage(&tmpdir.path().join("blobs")));
+    }
+
+    /// Chunks shared between cached blobs are kept until the last of them is
+    /// evicted.
+    #[tokio::test]
+    async fn keeps_shared_local_chunks() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let local = Arc::new(
+            FsBlobService::new(tmpdir.path().to_owned(), 1024, false)
+                .await
+                .unwrap(),
+        ) as Arc<dyn BlobService>;
+        let remote = Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>;
+
+        // blob_2 shares most of its chunks with blob_1.
+        let blob_1 = random_blob(64 * 1024, 1);
+        let mut blob_2 = blob_1.clone();
+        blob_2.extend(random_blob(4 * 1024, 2));
+        let blob_1_digest = put_blob(&remote, &blob_1).await;
+        let blob_2_digest = put_blob(&remote, &blob_2).await;
+        put_blob(&remote, &BLOB_A).await;
+
+        // Both fit into the cache, but not together with BLOB_A.
+        let cache = LruCacheBlobService::new(
+            local.clone(),
+            remote.clone(),
+            LruIndex::new_temporary((blob_1.len() + blob_2.len()) as u64).unwrap(),
+        );
+
+        read_blob(&cache, &blob_1_digest).await;
+        read_blob(&cache, &blob_2_digest).await;
+
+        // Reading BLOB_A evicts blob_1, which was used least recently.
+        read_blob(&cache, &BLOB_A_DIGEST).await;
+        assert!(!local.has(&blob_1_digest).await.unwrap());
+
+        // blob_2 can still be read from the local BlobService.
+        assert_eq!(blob_2, read_blob(&local, &blob_2_digest).await);
+    }
+
+    /// Blobs the remote BlobService has more granular chunks for are cached
+    /// chunk by chunk, configured through the composition.
+    #[tokio::test]
+    async fn caches_chunks() {
+        use crate::blobmetadataservice::BlobMetadataService;
+        use crate::chunkservice::ChunkService;
+        use crate::composition::{with_registry, Composition, REG};
+
+        let mut composition = Composition::new(&REG);
+        with_registry(&REG, || -> Result<(), serde_json::Error> {
+            composition.extend_with_configs::<dyn BlobService>(serde_json::from_value(
+                serde_json::json!({
+                    "default": {
+                        "type": "lru_cache",
+                        "local": "local",
+                        "remote": "remote",
+                        "max_size": "20GiB",
+                    },
+                    "local": {"type": "memory"},
+                    "remote": {"type": "chunked", "metadata": "default", "chunks": "default"},
+                }),
+            )?);
+            composition.extend_with_configs::<dyn BlobMetadataService>(serde_json::from_value(
+                serde_json::json!({"default": {"type": "memory"}}),
+            )?);
+            composition.extend_with_configs::<dyn ChunkService>(serde_json::from_value(
+                serde_json::json!({"default": {"type": "memory"}}),
+            )?);
+            Ok(())
+        })
+        .unwrap();
+
+        let cache = composition
+            .build::<dyn BlobService>("default")
+            .await
+            .expect("must build");
+        let local = composition
+            .build::<dyn BlobService>("local")
+            .await
+            .expect("must build");
+
+        assert_eq!(*BLOB_B_DIGEST, put_blob(&cache, &BLOB_B).await);
+        let chunks = cache.chunks(&BLOB_B_DIGEST).await.unwrap().unwrap();
+        assert!(chunks.len() > 1);
+
+        assert_eq!(BLOB_B.to_vec(), read_blob(&cache, &BLOB_B_DIGEST).await);
+
+        // All chunks, but not the blob itself, are in the local BlobService now.
+        assert!(!local.has(&BLOB_B_DIGEST).await.unwrap());
+        for chunk in chunks {
+            assert!(local.has(&chunk.digest.try_into().unwrap()).await.unwrap());
+        }
+    }
+
+    /// Chunks served from the local BlobService are verified. Corrupted ones
+    /// are dropped, and fetched from the remote again.
+    #[tokio::test]
+    async fn refetches_corrupted_chunks() {
+        use crate::blobmetadataservice::{BlobMetadataService, MemoryBlobMetadataService};
+        use crate::blobservice::ChunkedBlobService;
+        use crate::chunkservice::{ChunkService, MemoryChunkService};
+
+        let local = CorruptingBlobService::default();
+        let remote = Arc::new(ChunkedBlobService::new(
+            Arc::new(MemoryBlobMetadataService::default()) as Arc<dyn BlobMetadataService>,
+            Arc::new(MemoryChunkService::default()) as Arc<dyn ChunkService>,
+            crate::blobservice::chunked::default_avg_chunk_size(),
+        )) as Arc<dyn BlobService>;
+
+        assert_eq!(*BLOB_B_DIGEST, put_blob(&remote, &BLOB_B).await);
+        let chunks = remote.chunks(&BLOB_B_DIGEST).await.unwrap().unwrap();
+        assert!(chunks.len() > 1);
+        let chunk_digest: B3Digest = chunks[0].digest.clone().try_into().unwrap();
+
+        let cache = LruCacheBlobService::new(
+            Arc::new(local.clone()) as Arc<dyn BlobService>,
+            remote,
+            LruIndex::new_temporary(1024 * 1024 * 1024).unwrap(),
+        );
+
+        // This caches all chunks in the local BlobService.
+        assert_eq!(BLOB_B.to_vec(), read_blob(&cache, &BLOB_B_DIGEST).await);
+        assert!(local.has(&chunk_digest).await.unwrap());
+
+        *local.corrupt.lock() = Some(chunk_digest.clone());
+        assert_eq!(BLOB_B.to_vec(), read_blob(&cache, &BLOB_B_DIGEST).await);
+    }
+}
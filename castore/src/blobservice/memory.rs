@@ -1,3 +1,5 @@
+use futures::stream::BoxStream;
+use futures::StreamExt;
 use parking_lot::RwLock;
 use std::io::{self, Cursor, Write};
 use std::task::Poll;
@@ -36,6 +38,21 @@ impl BlobService for MemoryBlobService {
     async fn open_write(&self) -> Box<dyn BlobWriter> {
         Box::new(MemoryBlobWriter::new(self.db.clone()))
     }
+
+    #[instrument(skip_all)]
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        // Take a snapshot of the keys, so we don't hold the lock while the
+        // stream is consumed.
+        let digests: Vec<B3Digest> = self.db.read().keys().cloned().collect();
+
+        futures::stream::iter(digests.into_iter().map(Ok)).boxed()
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn delete(&self, digest: &B3Digest) -> io::Result<()> {
+        self.db.write().remove(digest);
+        Ok(())
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -1,29 +1,39 @@
 use std::io;
 
 use auto_impl::auto_impl;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
 use tonic::async_trait;
 
 use crate::composition::{Registry, ServiceBuilder};
 use crate::proto::stat_blob_response::ChunkMeta;
 use crate::B3Digest;
 
+mod chunked;
 mod chunked_reader;
 mod combinator;
 mod from_addr;
+mod fs;
 mod grpc;
+mod lru_cache;
 mod memory;
 mod object_store;
 
 #[cfg(test)]
 pub mod tests;
 
+pub use self::chunked::{ChunkedBlobService, ChunkedBlobServiceConfig};
 pub use self::chunked_reader::ChunkedReader;
 pub use self::combinator::{CombinedBlobService, CombinedBlobServiceConfig};
 pub use self::from_addr::from_addr;
-pub use self::grpc::{GRPCBlobService, GRPCBlobServiceConfig};
+pub use self::fs::{FsBlobService, FsBlobServiceConfig};
+pub use self::grpc::{GRPCBlobReader, GRPCBlobService, GRPCBlobServiceConfig};
+pub use self::lru_cache::{LruCacheBlobService, LruCacheBlobServiceConfig, LruIndex};
 pub use self::memory::{MemoryBlobService, MemoryBlobServiceConfig};
 pub use self::object_store::{ObjectStoreBlobService, ObjectStoreBlobServiceConfig};
 
+pub(crate) use self::object_store::digest_from_path;
+
 /// The base trait all BlobService services need to implement.
 /// It provides functions to check whether a given blob exists,
 /// a way to read (and seek) a blob, and a method to create a blobwriter handle,
@@ -36,6 +46,14 @@ pub trait BlobService: Send + Sync {
     /// On implementations returning chunks, this must also work for chunks.
     async fn has(&self, digest: &B3Digest) -> io::Result<bool>;
 
+    /// Check if the service has each of the given blobs, returning the
+    /// results in the same order.
+    /// The default implementation calls [BlobService::has] concurrently,
+    /// implementations able to batch these checks should override it.
+    async fn has_many(&self, digests: &[B3Digest]) -> io::Result<Vec<bool>> {
+        has_concurrently(self, digests).await
+    }
+
     /// Request a blob from the store, by its content hash.
     /// On implementations returning chunks, this must also work for chunks.
     async fn open_read(&self, digest: &B3Digest) -> io::Result<Option<Box<dyn BlobReader>>>;
@@ -60,6 +78,34 @@ pub trait BlobService: Send + Sync {
         // granular chunks available.
         Ok(Some(vec![]))
     }
+
+    /// Iterate over the digests of all blobs in the store.
+    /// On implementations storing chunks separately, this must also return
+    /// the digests of all chunks.
+    /// Implementations can decide to disallow listing, which is what the
+    /// default implementation does.
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        Box::pin(futures::stream::once(async {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "listing is not supported",
+            ))
+        }))
+    }
+
+    /// Remove a blob from the store, by its content hash.
+    /// On implementations returning chunks, this must also work for chunks.
+    /// Deleting a blob does not delete the chunks it consists of, these need
+    /// to be deleted individually.
+    /// Deleting a blob that is not present is not an error.
+    /// Implementations can decide to disallow deletion, which is what the
+    /// default implementation does.
+    async fn delete(&self, _digest: &B3Digest) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "deletion is not supported",
+        ))
+    }
 }
 
 /// A [tokio::io::AsyncWrite] that the user needs to close() afterwards for persist.
@@ -73,6 +119,25 @@ pub trait BlobWriter: tokio::io::AsyncWrite + Send + Unpin {
     async fn close(&mut self) -> io::Result<B3Digest>;
 }
 
+/// The number of concurrent requests issued by [has_concurrently].
+const HAS_CONCURRENCY: usize = 16;
+
+/// Calls [BlobService::has] for each of the given digests, with some
+/// concurrency, returning the results in the same order.
+pub(crate) async fn has_concurrently<BS>(
+    blob_service: &BS,
+    digests: &[B3Digest],
+) -> io::Result<Vec<bool>>
+where
+    BS: BlobService + ?Sized,
+{
+    futures::stream::iter(digests.iter().cloned())
+        .map(|digest| async move { blob_service.has(&digest).await })
+        .buffered(HAS_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
 /// BlobReader is a [tokio::io::AsyncRead] that also allows seeking.
 pub trait BlobReader: tokio::io::AsyncRead + tokio::io::AsyncSeek + Send + Unpin + 'static {}
 
@@ -87,6 +152,9 @@ impl BlobReader for tokio::fs::File {}
 pub(crate) fn register_blob_services(reg: &mut Registry) {
     reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobService>>, super::blobservice::ObjectStoreBlobServiceConfig>("objectstore");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobService>>, super::blobservice::MemoryBlobServiceConfig>("memory");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobService>>, super::blobservice::FsBlobServiceConfig>("fs");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobService>>, super::blobservice::CombinedBlobServiceConfig>("combined");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobService>>, super::blobservice::LruCacheBlobServiceConfig>("lru_cache");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobService>>, super::blobservice::ChunkedBlobServiceConfig>("chunked");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn BlobService>>, super::blobservice::GRPCBlobServiceConfig>("grpc");
 }
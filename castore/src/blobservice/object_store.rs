@@ -1,29 +1,28 @@
 use std::{
     collections::HashMap,
     io::{self, Cursor},
-    pin::pin,
     sync::Arc,
-    task::Poll,
 };
 
+use async_stream::try_stream;
 use data_encoding::HEXLOWER;
-use fastcdc::v2020::AsyncStreamCDC;
-use futures::Future;
+use futures::stream::BoxStream;
 use object_store::{path::Path, ObjectStore};
-use pin_project_lite::pin_project;
 use prost::Message;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::io::AsyncRead;
 use tokio_stream::StreamExt;
 use tonic::async_trait;
 use tracing::{debug, instrument, trace, Level};
-use url::Url;
 
 use crate::{
+    blobmetadataservice::derive_blob_path,
+    chunkservice::{derive_chunk_path, ChunkService, ObjectStoreChunkService},
     composition::{CompositionContext, ServiceBuilder},
     proto::{stat_blob_response::ChunkMeta, StatBlobResponse},
-    B3Digest, B3HashingReader, Error,
+    B3Digest,
 };
 
+use super::chunked::{chunk_and_put, default_avg_chunk_size, ChunkingBlobWriter};
 use super::{BlobReader, BlobService, BlobWriter, ChunkedReader};
 
 /// Uses any object storage supported by the [object_store] crate to provide a
@@ -72,24 +71,27 @@ pub struct ObjectStoreBlobService {
     avg_chunk_size: u32,
 }
 
-#[instrument(level=Level::TRACE, skip_all,fields(base_path=%base_path,blob.digest=%digest),ret(Display))]
-fn derive_blob_path(base_path: &Path, digest: &B3Digest) -> Path {
-    base_path
-        .child("blobs")
-        .child("b3")
-        .child(HEXLOWER.encode(&digest.as_slice()[..2]))
-        .child(HEXLOWER.encode(digest.as_slice()))
+impl ObjectStoreBlobService {
+    /// Returns a [ObjectStoreChunkService] operating on the same object store,
+    /// which is used for reading and writing chunks.
+    fn chunk_service(&self) -> ObjectStoreChunkService {
+        ObjectStoreChunkService::new(self.object_store.clone(), self.base_path.clone())
+    }
 }
 
-#[instrument(level=Level::TRACE, skip_all,fields(base_path=%base_path,chunk.digest=%digest),ret(Display))]
-fn derive_chunk_path(base_path: &Path, digest: &B3Digest) -> Path {
-    base_path
-        .child("chunks")
-        .child("b3")
-        .child(HEXLOWER.encode(&digest.as_slice()[..2]))
-        .child(HEXLOWER.encode(digest.as_slice()))
+/// Parses the digest back from a path produced by [derive_blob_path] or
+/// [derive_chunk_path].
+pub(crate) fn digest_from_path(path: &Path) -> io::Result<B3Digest> {
+    path.filename()
+        .and_then(|filename| HEXLOWER.decode(filename.as_bytes()).ok())
+        .and_then(|digest| B3Digest::try_from(digest).ok())
+        .ok_or_else(|| io::Error::other(format!("unexpected key in object store: {}", path)))
 }
 
+/// The number of concurrent HEAD requests issued by
+/// [ObjectStoreBlobService::has_many].
+const HAS_MANY_CONCURRENCY: usize = 64;
+
 #[async_trait]
 impl BlobService for ObjectStoreBlobService {
     #[instrument(skip_all, ret(level = Level::TRACE), err, fields(blob.digest=%digest))]
@@ -112,33 +114,37 @@ impl BlobService for ObjectStoreBlobService {
         }
     }
 
+    /// Object stores don't provide batch HEAD requests, so this is not a real
+    /// batch lookup: it still issues one HEAD request per digest (two for
+    /// digests that aren't blobs), just with up to [HAS_MANY_CONCURRENCY] of
+    /// them in flight at a time, rather than one after the other.
+    #[instrument(skip_all, err, fields(blob.count=digests.len()))]
+    async fn has_many(&self, digests: &[B3Digest]) -> io::Result<Vec<bool>> {
+        futures::StreamExt::buffered(
+            futures::stream::iter(
+                digests
+                    .iter()
+                    .cloned()
+                    .map(|digest| async move { self.has(&digest).await }),
+            ),
+            HAS_MANY_CONCURRENCY,
+        )
+        .collect::<io::Result<_>>()
+        .await
+    }
+
     #[instrument(skip_all, err, fields(blob.digest=%digest))]
     async fn open_read(&self, digest: &B3Digest) -> io::Result<Option<Box<dyn BlobReader>>> {
         // handle reading the empty blob.
         if digest.as_slice() == blake3::hash(b"").as_bytes() {
             return Ok(Some(Box::new(Cursor::new(b"")) as Box<dyn BlobReader>));
         }
-        match self
-            .object_store
-            .get(&derive_chunk_path(&self.base_path, digest))
-            .await
-        {
-            Ok(res) => {
-                // handle reading blobs that are small enough to fit inside a single chunk:
-                // fetch the entire chunk into memory, decompress, ensure the b3 digest matches,
-                // and return a io::Cursor over that data.
-                // FUTUREWORK: use zstd::bulk to prevent decompression bombs
-
-                let chunk_raw_bytes = res.bytes().await?;
-                let chunk_contents = zstd::stream::decode_all(Cursor::new(chunk_raw_bytes))?;
-
-                if *digest != blake3::hash(&chunk_contents).as_bytes().into() {
-                    Err(io::Error::other("chunk contents invalid"))?;
-                }
-
-                Ok(Some(Box::new(Cursor::new(chunk_contents))))
-            }
-            Err(object_store::Error::NotFound { .. }) => {
+        let chunk_service = self.chunk_service();
+        match chunk_service.get(digest).await? {
+            // handle reading blobs that are small enough to fit inside a single chunk:
+            // return a io::Cursor over the chunk data.
+            Some(chunk_contents) => Ok(Some(Box::new(Cursor::new(chunk_contents)))),
+            None => {
                 // NOTE: For public-facing things, we would want to stop here.
                 // Clients should fetch granularly, so they can make use of
                 // chunks they have locally.
@@ -156,7 +162,7 @@ impl BlobService for ObjectStoreBlobService {
                                 chunk.size,
                             )
                         }),
-                        Arc::new(self.clone()) as Arc<dyn BlobService>,
+                        Arc::new(chunk_service) as Arc<dyn ChunkService>,
                     );
 
                     Ok(Some(Box::new(chunked_reader)))
@@ -165,31 +171,29 @@ impl BlobService for ObjectStoreBlobService {
                     Ok(None)
                 }
             }
-            Err(e) => Err(e.into()),
         }
     }
 
     #[instrument(skip_all)]
     async fn open_write(&self) -> Box<dyn BlobWriter> {
-        // ObjectStoreBlobWriter implements AsyncWrite, but all the chunking
+        // ChunkingBlobWriter implements AsyncWrite, but all the chunking
         // needs an AsyncRead, so we create a pipe here.
-        // In its `AsyncWrite` implementation, `ObjectStoreBlobWriter` delegates
+        // In its `AsyncWrite` implementation, `ChunkingBlobWriter` delegates
         // writes to w. It periodically polls the future that's reading from the
         // other side.
         let (w, r) = tokio::io::duplex(self.avg_chunk_size as usize * 10);
 
-        Box::new(ObjectStoreBlobWriter {
-            writer: Some(w),
-            fut: Some(Box::pin(chunk_and_upload(
+        Box::new(ChunkingBlobWriter::new(
+            w,
+            Box::pin(chunk_and_upload(
                 r,
                 self.object_store.clone(),
                 self.base_path.clone(),
                 self.avg_chunk_size / 2,
                 self.avg_chunk_size,
                 self.avg_chunk_size * 2,
-            ))),
-            fut_output: None,
-        })
+            )),
+        ))
     }
 
     #[instrument(skip_all, err, fields(blob.digest=%digest))]
@@ -242,10 +246,42 @@ impl BlobService for ObjectStoreBlobService {
             Err(err) => Err(err.into()),
         }
     }
-}
 
-fn default_avg_chunk_size() -> u32 {
-    256 * 1024
+    #[instrument(skip_all)]
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        let object_store = self.object_store.clone();
+        let base_path = self.base_path.clone();
+
+        // Blobs consisting of a single chunk show up both in blobs/ and
+        // chunks/, so their digest is returned twice.
+        Box::pin(try_stream! {
+            for prefix in ["blobs", "chunks"] {
+                let prefix = base_path.child(prefix).child("b3");
+                let mut objects = object_store.list(Some(&prefix));
+
+                while let Some(object_meta) = objects.try_next().await? {
+                    yield digest_from_path(&object_meta.location)?;
+                }
+            }
+        })
+    }
+
+    #[instrument(skip_all, err, fields(blob.digest=%digest))]
+    async fn delete(&self, digest: &B3Digest) -> io::Result<()> {
+        // The digest might refer to a blob, a chunk, or both (in case the blob
+        // consists of a single chunk), so try to remove both.
+        for p in [
+            derive_blob_path(&self.base_path, digest),
+            derive_chunk_path(&self.base_path, digest),
+        ] {
+            match self.object_store.delete(&p).await {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+                Err(e) => Err(e)?,
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -259,31 +295,16 @@ pub struct ObjectStoreBlobServiceConfig {
 
 impl TryFrom<url::Url> for ObjectStoreBlobServiceConfig {
     type Error = Box<dyn std::error::Error + Send + Sync>;
-    /// Constructs a new [ObjectStoreBlobService] from a [Url] supported by
+    /// Constructs a new [ObjectStoreBlobService] from a [url::Url] supported by
     /// [object_store].
     /// Any path suffix becomes the base path of the object store.
     /// additional options, the same as in [object_store::parse_url_opts] can
     /// be passed.
     fn try_from(url: url::Url) -> Result<Self, Self::Error> {
-        // We need to convert the URL to string, strip the prefix there, and then
-        // parse it back as url, as Url::set_scheme() rejects some of the transitions we want to do.
-        let trimmed_url = {
-            let s = url.to_string();
-            let mut url = Url::parse(
-                s.strip_prefix("objectstore+")
-                    .ok_or(Error::StorageError("Missing objectstore uri".into()))?,
-            )?;
-            // trim the query pairs, they might contain credentials or local settings we don't want to send as-is.
-            url.set_query(None);
-            url
-        };
+        let (object_store_url, object_store_options) = crate::objectstore_url::parse(&url)?;
         Ok(ObjectStoreBlobServiceConfig {
-            object_store_url: trimmed_url.into(),
-            object_store_options: url
-                .query_pairs()
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect(),
+            object_store_url,
+            object_store_options,
             avg_chunk_size: 256 * 1024,
         })
     }
@@ -310,7 +331,8 @@ impl ServiceBuilder for ObjectStoreBlobServiceConfig {
 }
 
 /// Reads blob contents from a AsyncRead, chunks and uploads them.
-/// On success, returns a [StatBlobResponse] pointing to the individual chunks.
+/// On success, returns the digest of the blob, after uploading a
+/// [StatBlobResponse] pointing to the individual chunks.
 #[instrument(skip_all, fields(base_path=%base_path, min_chunk_size, avg_chunk_size, max_chunk_size), err)]
 async fn chunk_and_upload<R: AsyncRead + Unpin>(
     r: R,
@@ -320,43 +342,15 @@ async fn chunk_and_upload<R: AsyncRead + Unpin>(
     avg_chunk_size: u32,
     max_chunk_size: u32,
 ) -> io::Result<B3Digest> {
-    // wrap reader with something calculating the blake3 hash of all data read.
-    let mut b3_r = B3HashingReader::from(r);
-    // set up a fastcdc chunker
-    let mut chunker =
-        AsyncStreamCDC::new(&mut b3_r, min_chunk_size, avg_chunk_size, max_chunk_size);
-
-    /// This really should just belong into the closure at
-    /// `chunker.as_stream().then(|_| { … })``, but if we try to, rustc spits
-    /// higher-ranked lifetime errors at us.
-    async fn fastcdc_chunk_uploader(
-        resp: Result<fastcdc::v2020::ChunkData, fastcdc::v2020::Error>,
-        base_path: Path,
-        object_store: Arc<dyn ObjectStore>,
-    ) -> std::io::Result<ChunkMeta> {
-        let chunk_data = resp?;
-        let chunk_digest: B3Digest = blake3::hash(&chunk_data.data).as_bytes().into();
-        let chunk_path = derive_chunk_path(&base_path, &chunk_digest);
-
-        upload_chunk(object_store, chunk_digest, chunk_path, chunk_data.data).await
-    }
-
-    // Use the fastcdc chunker to produce a stream of chunks, and upload these
-    // that don't exist to the backend.
-    let chunks = chunker
-        .as_stream()
-        .then(|resp| fastcdc_chunk_uploader(resp, base_path.clone(), object_store.clone()))
-        .collect::<io::Result<Vec<ChunkMeta>>>()
-        .await?;
-
-    let chunks = if chunks.len() < 2 {
-        // The chunker returned only one chunk, which is the entire blob.
-        // According to the protocol, we must return an empty list of chunks
-        // when the blob is not split up further.
-        vec![]
-    } else {
-        chunks
-    };
+    let chunk_service = ObjectStoreChunkService::new(object_store.clone(), base_path.clone());
+    let (blob_digest, chunks) = chunk_and_put(
+        r,
+        &chunk_service,
+        min_chunk_size,
+        avg_chunk_size,
+        max_chunk_size,
+    )
+    .await?;
 
     let stat_blob_response = StatBlobResponse {
         chunks,
@@ -364,7 +358,6 @@ async fn chunk_and_upload<R: AsyncRead + Unpin>(
     };
 
     // check for Blob, if it doesn't exist, persist.
-    let blob_digest: B3Digest = b3_r.digest().into();
     let blob_path = derive_blob_path(&base_path, &blob_digest);
 
     match object_store.head(&blob_path).await {
@@ -396,169 +389,6 @@ async fn chunk_and_upload<R: AsyncRead + Unpin>(
     Ok(blob_digest)
 }
 
-/// upload chunk if it doesn't exist yet.
-#[instrument(skip_all, fields(chunk.digest = %chunk_digest, chunk.size = chunk_data.len(), chunk.path = %chunk_path), err)]
-async fn upload_chunk(
-    object_store: Arc<dyn ObjectStore>,
-    chunk_digest: B3Digest,
-    chunk_path: Path,
-    chunk_data: Vec<u8>,
-) -> std::io::Result<ChunkMeta> {
-    let chunk_size = chunk_data.len();
-    match object_store.head(&chunk_path).await {
-        // chunk already exists, nothing to do
-        Ok(_) => {
-            debug!("chunk already exists");
-        }
-
-        // chunk does not yet exist, compress and upload.
-        Err(object_store::Error::NotFound { .. }) => {
-            let chunk_data_compressed =
-                zstd::encode_all(Cursor::new(chunk_data), zstd::DEFAULT_COMPRESSION_LEVEL)?;
-
-            debug!(chunk.compressed_size=%chunk_data_compressed.len(), "uploading chunk");
-
-            object_store
-                .as_ref()
-                .put(&chunk_path, chunk_data_compressed.into())
-                .await?;
-        }
-        // other error
-        Err(err) => Err(err)?,
-    }
-
-    Ok(ChunkMeta {
-        digest: chunk_digest.into(),
-        size: chunk_size as u64,
-    })
-}
-
-pin_project! {
-    /// Takes care of blob uploads.
-    /// All writes are relayed to self.writer, and we continuously poll the
-    /// future (which will internally read from the other side of the pipe and
-    /// upload chunks).
-    /// Our BlobWriter::close() needs to drop self.writer, so the other side
-    /// will read EOF and can finalize the blob.
-    /// The future should then resolve and return the blob digest.
-    pub struct ObjectStoreBlobWriter<W, Fut>
-    where
-        W: AsyncWrite,
-        Fut: Future,
-    {
-        #[pin]
-        writer: Option<W>,
-
-        #[pin]
-        fut: Option<Fut>,
-
-        fut_output: Option<io::Result<B3Digest>>
-    }
-}
-
-impl<W, Fut> tokio::io::AsyncWrite for ObjectStoreBlobWriter<W, Fut>
-where
-    W: AsyncWrite + Send + Unpin,
-    Fut: Future,
-{
-    fn poll_write(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-        buf: &[u8],
-    ) -> std::task::Poll<Result<usize, io::Error>> {
-        let this = self.project();
-        // poll the future.
-        let fut = this.fut.as_pin_mut().expect("not future");
-        let fut_p = fut.poll(cx);
-        // if it's ready, the only way this could have happened is that the
-        // upload failed, because we're only closing `self.writer` after all
-        // writes happened.
-        if fut_p.is_ready() {
-            return Poll::Ready(Err(io::Error::other("upload failed")));
-        }
-
-        // write to the underlying writer
-        this.writer
-            .as_pin_mut()
-            .expect("writer must be some")
-            .poll_write(cx, buf)
-    }
-
-    fn poll_flush(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Result<(), io::Error>> {
-        let this = self.project();
-        // poll the future.
-        let fut = this.fut.as_pin_mut().expect("not future");
-        let fut_p = fut.poll(cx);
-        // if it's ready, the only way this could have happened is that the
-        // upload failed, because we're only closing `self.writer` after all
-        // writes happened.
-        if fut_p.is_ready() {
-            return Poll::Ready(Err(io::Error::other("upload failed")));
-        }
-
-        // Call poll_flush on the writer
-        this.writer
-            .as_pin_mut()
-            .expect("writer must be some")
-            .poll_flush(cx)
-    }
-
-    fn poll_shutdown(
-        self: std::pin::Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Result<(), io::Error>> {
-        // There's nothing to do on shutdown. We might have written some chunks
-        // that are nowhere else referenced, but cleaning them up here would be racy.
-        std::task::Poll::Ready(Ok(()))
-    }
-}
-
-#[async_trait]
-impl<W, Fut> BlobWriter for ObjectStoreBlobWriter<W, Fut>
-where
-    W: AsyncWrite + Send + Unpin,
-    Fut: Future<Output = io::Result<B3Digest>> + Send + Unpin,
-{
-    async fn close(&mut self) -> io::Result<B3Digest> {
-        match self.writer.take() {
-            Some(mut writer) => {
-                // shut down the writer, so the other side will read EOF.
-                writer.shutdown().await?;
-
-                // take out the future.
-                let fut = self.fut.take().expect("fut must be some");
-                // await it.
-                let resp = pin!(fut).await;
-
-                match resp.as_ref() {
-                    // In the case of an Ok value, we store it in self.fut_output,
-                    // so future calls to close can return that.
-                    Ok(b3_digest) => {
-                        self.fut_output = Some(Ok(b3_digest.clone()));
-                    }
-                    Err(e) => {
-                        // for the error type, we need to cheat a bit, as
-                        // they're not clone-able.
-                        // Simply store a sloppy clone, with the same ErrorKind and message there.
-                        self.fut_output = Some(Err(std::io::Error::new(e.kind(), e.to_string())))
-                    }
-                }
-                resp
-            }
-            None => {
-                // called a second time, return self.fut_output.
-                match self.fut_output.as_ref().unwrap() {
-                    Ok(ref b3_digest) => Ok(b3_digest.clone()),
-                    Err(e) => Err(std::io::Error::new(e.kind(), e.to_string())),
-                }
-            }
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::{chunk_and_upload, default_avg_chunk_size};
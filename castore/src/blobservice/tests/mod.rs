@@ -2,6 +2,7 @@
 //! We use [rstest] and [rstest_reuse] to provide all services we want to test
 //! against, and then apply this template to all test functions.
 
+use futures::TryStreamExt;
 use rstest::*;
 use rstest_reuse::{self, *};
 use std::io;
@@ -14,9 +15,13 @@ use crate::fixtures::BLOB_A;
 use crate::fixtures::BLOB_A_DIGEST;
 use crate::fixtures::BLOB_B;
 use crate::fixtures::BLOB_B_DIGEST;
+use crate::B3Digest;
 
-mod utils;
-use self::utils::make_grpc_blob_service_client;
+pub(crate) mod utils;
+use self::utils::{
+    make_chunked_blob_service, make_fs_blob_service, make_grpc_blob_service_client,
+    make_grpc_client, make_lru_cache_blob_service, CorruptingBlobService,
+};
 
 /// This produces a template, which will be applied to all individual test functions.
 /// See https://github.com/la10736/rstest/issues/130#issuecomment-968864832
@@ -25,6 +30,10 @@ use self::utils::make_grpc_blob_service_client;
 #[case::grpc(make_grpc_blob_service_client().await)]
 #[case::memory(blobservice::from_addr("memory://").await.unwrap())]
 #[case::objectstore_memory(blobservice::from_addr("objectstore+memory://").await.unwrap())]
+#[case::chunked_memory(make_chunked_blob_service())]
+#[case::fs(make_fs_blob_service(false).await)]
+#[case::fs_compressed(make_fs_blob_service(true).await)]
+#[case::lru_cache(make_lru_cache_blob_service())]
 pub fn blob_services(#[case] blob_service: impl BlobService) {}
 
 /// Using [BlobService::has] on a non-existing blob should return false.
@@ -61,6 +70,66 @@ async fn not_found_read(blob_service: impl BlobService) {
         .is_none())
 }
 
+/// Put a blob in the store, check [BlobService::has_many] returns the
+/// existence of each requested digest, in order.
+#[apply(blob_services)]
+#[tokio::test]
+async fn put_has_many(blob_service: impl BlobService) {
+    let mut w = blob_service.open_write().await;
+    tokio::io::copy(&mut io::Cursor::new(&*BLOB_A), &mut w)
+        .await
+        .expect("copy must succeed");
+    assert_eq!(*BLOB_A_DIGEST, w.close().await.expect("close must succeed"));
+
+    assert_eq!(
+        vec![true, false, true],
+        blob_service
+            .has_many(&[
+                BLOB_A_DIGEST.clone(),
+                BLOB_B_DIGEST.clone(),
+                BLOB_A_DIGEST.clone()
+            ])
+            .await
+            .expect("must not fail")
+    );
+
+    assert!(blob_service
+        .has_many(&[])
+        .await
+        .expect("must not fail")
+        .is_empty());
+}
+
+/// Request more digests with [BlobService::has_many] than fit in a single
+/// batch, and ensure the answers are still returned in order.
+#[apply(blob_services)]
+#[tokio::test]
+async fn put_has_many_large(blob_service: impl BlobService) {
+    let mut w = blob_service.open_write().await;
+    tokio::io::copy(&mut io::Cursor::new(&*BLOB_A), &mut w)
+        .await
+        .expect("copy must succeed");
+    assert_eq!(*BLOB_A_DIGEST, w.close().await.expect("close must succeed"));
+
+    let digests: Vec<B3Digest> = (0..2500)
+        .map(|i| {
+            if i % 3 == 0 {
+                BLOB_A_DIGEST.clone()
+            } else {
+                BLOB_B_DIGEST.clone()
+            }
+        })
+        .collect();
+
+    assert_eq!(
+        (0..2500).map(|i| i % 3 == 0).collect::<Vec<_>>(),
+        blob_service
+            .has_many(&digests)
+            .await
+            .expect("must not fail")
+    );
+}
+
 /// Put a blob in the store, check has, get it back.
 #[apply(blob_services)]
 // #[case::small(&fixtures::BLOB_A, &fixtures::BLOB_A_DIGEST)]
@@ -251,3 +320,210 @@ async fn put_seek(blob_service: impl BlobService) {
     // r.seek(io::SeekFrom::End(0))
     //     .expect_err("SeekFrom::End(_) expected to fail");
 }
+
+/// Put a blob in the store, ensure it shows up in [BlobService::list], then
+/// delete it again.
+/// Not all implementations support listing and deletion, so this only runs
+/// against the ones that do.
+#[rstest]
+#[case::memory(blobservice::from_addr("memory://").await.unwrap())]
+#[case::objectstore_memory(blobservice::from_addr("objectstore+memory://").await.unwrap())]
+#[case::chunked_memory(make_chunked_blob_service())]
+#[case::fs(make_fs_blob_service(false).await)]
+#[tokio::test]
+async fn put_list_delete(#[case] blob_service: impl BlobService) {
+    let mut w = blob_service.open_write().await;
+    tokio::io::copy(&mut io::Cursor::new(&*BLOB_A), &mut w)
+        .await
+        .expect("copy must succeed");
+    assert_eq!(*BLOB_A_DIGEST, w.close().await.expect("close must succeed"));
+
+    let digests: Vec<_> = blob_service
+        .list()
+        .try_collect()
+        .await
+        .expect("list must succeed");
+    assert!(digests.contains(&BLOB_A_DIGEST));
+
+    blob_service
+        .delete(&BLOB_A_DIGEST)
+        .await
+        .expect("delete must succeed");
+    assert!(!blob_service
+        .has(&BLOB_A_DIGEST)
+        .await
+        .expect("must not fail"));
+
+    // Deleting a blob that doesn't exist is not an error.
+    blob_service
+        .delete(&BLOB_A_DIGEST)
+        .await
+        .expect("delete must succeed");
+}
+
+/// Read ranges of a blob via gRPC, using the offset and length fields in the
+/// [crate::proto::ReadBlobRequest].
+/// Reading past the end either returns no data, or fails with OUT_OF_RANGE.
+#[rstest]
+#[case::memory(Box::<blobservice::MemoryBlobService>::default())]
+#[case::chunked(make_chunked_blob_service())]
+#[tokio::test]
+async fn grpc_read_range(#[case] blob_service: Box<dyn BlobService>) {
+    use crate::proto::ReadBlobRequest;
+
+    let mut w = blob_service.open_write().await;
+    tokio::io::copy(&mut io::Cursor::new(&*BLOB_B), &mut w)
+        .await
+        .expect("copy must succeed");
+    assert_eq!(*BLOB_B_DIGEST, w.close().await.expect("close must succeed"));
+
+    let mut grpc_client = make_grpc_client(blob_service).await;
+
+    for (offset, length, expected) in [
+        (0, 0, &BLOB_B[..]),
+        (0, 10, &BLOB_B[..10]),
+        (12345, 0, &BLOB_B[12345..]),
+        (12345, 512 * 1024, &BLOB_B[12345..12345 + 512 * 1024]),
+        (BLOB_B.len() as u64 - 5, 10, &BLOB_B[BLOB_B.len() - 5..]),
+    ] {
+        let mut stream = grpc_client
+            .read(ReadBlobRequest {
+                digest: BLOB_B_DIGEST.clone().into(),
+                offset,
+                length,
+            })
+            .await
+            .expect("must succeed")
+            .into_inner();
+
+        let mut buf = Vec::new();
+        while let Some(blob_chunk) = stream.message().await.expect("must succeed") {
+            buf.extend_from_slice(&blob_chunk.data);
+        }
+        assert_eq!(expected, buf, "offset {}, length {}", offset, length);
+    }
+
+    match grpc_client
+        .read(ReadBlobRequest {
+            digest: BLOB_B_DIGEST.clone().into(),
+            offset: BLOB_B.len() as u64 + 1,
+            length: 0,
+        })
+        .await
+    {
+        Ok(resp) => assert!(resp
+            .into_inner()
+            .message()
+            .await
+            .expect("must succeed")
+            .is_none()),
+        Err(e) => assert_eq!(tonic::Code::OutOfRange, e.code()),
+    }
+}
+
+/// Seeking in a [blobservice::GRPCBlobReader] sends new ranged read requests.
+#[rstest]
+#[case::memory(Box::<blobservice::MemoryBlobService>::default())]
+#[case::chunked(make_chunked_blob_service())]
+#[tokio::test]
+async fn grpc_blob_reader_seek(#[case] blob_service: Box<dyn BlobService>) {
+    let mut w = blob_service.open_write().await;
+    tokio::io::copy(&mut io::Cursor::new(&*BLOB_B), &mut w)
+        .await
+        .expect("copy must succeed");
+    assert_eq!(*BLOB_B_DIGEST, w.close().await.expect("close must succeed"));
+
+    let mut r = blobservice::GRPCBlobReader::new(
+        make_grpc_client(blob_service).await,
+        BLOB_B_DIGEST.clone(),
+        None,
+    );
+
+    // read 10 bytes at an offset.
+    assert_eq!(12345, r.seek(io::SeekFrom::Start(12345)).await.unwrap());
+    let mut buf = [0u8; 10];
+    r.read_exact(&mut buf).await.expect("must succeed");
+    assert_eq!(&BLOB_B[12345..12355], &buf);
+
+    // seek backwards, and read again.
+    assert_eq!(12350, r.seek(io::SeekFrom::Current(-5)).await.unwrap());
+    r.read_exact(&mut buf).await.expect("must succeed");
+    assert_eq!(&BLOB_B[12350..12360], &buf);
+
+    // read until the end, which tells us the size.
+    let mut rest = Vec::new();
+    r.read_to_end(&mut rest).await.expect("must succeed");
+    assert_eq!(&BLOB_B[12360..], rest);
+
+    assert_eq!(
+        BLOB_B.len() as u64 - 10,
+        r.seek(io::SeekFrom::End(-10)).await.unwrap()
+    );
+    rest.clear();
+    r.read_to_end(&mut rest).await.expect("must succeed");
+    assert_eq!(&BLOB_B[BLOB_B.len() - 10..], rest);
+
+    // reading past the end returns no data.
+    r.seek(io::SeekFrom::End(10)).await.unwrap();
+    rest.clear();
+    r.read_to_end(&mut rest).await.expect("must succeed");
+    assert!(rest.is_empty());
+
+    // seeking before the start fails.
+    r.seek(io::SeekFrom::Current(-(BLOB_B.len() as i64) - 11))
+        .await
+        .expect_err("must fail");
+}
+
+/// A [blobservice::GRPCBlobReader] can seek relative to the end before the
+/// size of the blob is known, both before and after reading some data.
+#[rstest]
+#[case::start(None)]
+#[case::after_read(Some(12345))]
+#[tokio::test]
+async fn grpc_blob_reader_seek_end_unknown_size(#[case] read_at: Option<u64>) {
+    let blob_service = Box::<blobservice::MemoryBlobService>::default();
+    let mut w = blob_service.open_write().await;
+    tokio::io::copy(&mut io::Cursor::new(&*BLOB_B), &mut w)
+        .await
+        .expect("copy must succeed");
+    assert_eq!(*BLOB_B_DIGEST, w.close().await.expect("close must succeed"));
+
+    let mut r = blobservice::GRPCBlobReader::new(
+        make_grpc_client(blob_service).await,
+        BLOB_B_DIGEST.clone(),
+        None,
+    );
+
+    if let Some(read_at) = read_at {
+        r.seek(io::SeekFrom::Start(read_at)).await.unwrap();
+        let mut buf = [0u8; 10];
+        r.read_exact(&mut buf).await.expect("must succeed");
+    }
+
+    assert_eq!(
+        BLOB_B.len() as u64 - 10,
+        r.seek(io::SeekFrom::End(-10)).await.unwrap()
+    );
+    let mut rest = Vec::new();
+    r.read_to_end(&mut rest).await.expect("must succeed");
+    assert_eq!(&BLOB_B[BLOB_B.len() - 10..], rest);
+}
+
+/// Reading a blob from a [blobservice::GRPCBlobReader] until its end fails if
+/// the data doesn't match the digest.
+#[tokio::test]
+async fn grpc_blob_reader_digest_mismatch() {
+    let blob_service = CorruptingBlobService::default();
+    *blob_service.corrupt.lock() = Some(BLOB_A_DIGEST.clone());
+
+    let mut r = blobservice::GRPCBlobReader::new(
+        make_grpc_client(Box::new(blob_service)).await,
+        BLOB_A_DIGEST.clone(),
+        None,
+    );
+
+    let mut buf = Vec::new();
+    let e = r.read_to_end(&mut buf).await.expect_err("must fail");
+    assert_eq!(io::ErrorKind::InvalidData, e.kind());
+}
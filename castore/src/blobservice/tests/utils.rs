@@ -1,20 +1,34 @@
-use crate::blobservice::{BlobService, MemoryBlobService};
+use crate::blobmetadataservice::{BlobMetadataService, MemoryBlobMetadataService};
+use crate::blobservice::{
+    BlobReader, BlobService, BlobWriter, ChunkedBlobService, FsBlobService, LruCacheBlobService,
+    LruIndex, MemoryBlobService,
+};
+use crate::chunkservice::{ChunkService, MemoryChunkService};
 use crate::proto::blob_service_client::BlobServiceClient;
 use crate::proto::GRPCBlobServiceWrapper;
+use crate::B3Digest;
 use crate::{blobservice::GRPCBlobService, proto::blob_service_server::BlobServiceServer};
 use hyper_util::rt::TokioIo;
-use tonic::transport::{Endpoint, Server, Uri};
+use std::sync::Arc;
+use tonic::transport::{Channel, Endpoint, Server, Uri};
 
 /// Constructs and returns a gRPC BlobService.
 /// The server part is a [MemoryBlobService], exposed via the
 /// [GRPCBlobServiceWrapper], and connected through a DuplexStream
 pub async fn make_grpc_blob_service_client() -> Box<dyn BlobService> {
+    Box::new(GRPCBlobService::from_client(
+        make_grpc_client(Box::<MemoryBlobService>::default()).await,
+    ))
+}
+
+/// Constructs and returns a gRPC client.
+/// The server part is the passed [BlobService], exposed via the
+/// [GRPCBlobServiceWrapper], and connected through a DuplexStream
+pub async fn make_grpc_client(blob_service: Box<dyn BlobService>) -> BlobServiceClient<Channel> {
     let (left, right) = tokio::io::duplex(64);
 
     // spin up a server, which will only connect once, to the left side.
     tokio::spawn(async {
-        let blob_service = Box::<MemoryBlobService>::default() as Box<dyn BlobService>;
-
         // spin up a new DirectoryService
         let mut server = Server::builder();
         let router = server.add_service(BlobServiceServer::new(GRPCBlobServiceWrapper::new(
@@ -29,7 +43,7 @@ pub async fn make_grpc_blob_service_client() -> Box<dyn BlobService> {
     // Create a client, connecting to the right side. The URI is unused.
     let mut maybe_right = Some(right);
 
-    Box::new(GRPCBlobService::from_client(BlobServiceClient::new(
+    BlobServiceClient::new(
         Endpoint::try_from("http://[::]:50051")
             .unwrap()
             .connect_with_connector(tower::service_fn(move |_: Uri| {
@@ -38,5 +52,69 @@ pub async fn make_grpc_blob_service_client() -> Box<dyn BlobService> {
             }))
             .await
             .unwrap(),
-    )))
+    )
+}
+
+/// Constructs and returns a [ChunkedBlobService], using a
+/// [MemoryBlobMetadataService] and [MemoryChunkService].
+pub fn make_chunked_blob_service() -> Box<dyn BlobService> {
+    Box::new(ChunkedBlobService::new(
+        Arc::new(MemoryBlobMetadataService::default()) as Arc<dyn BlobMetadataService>,
+        Arc::new(MemoryChunkService::default()) as Arc<dyn ChunkService>,
+        crate::blobservice::chunked::default_avg_chunk_size(),
+    ))
+}
+
+/// Constructs and returns a [FsBlobService] in a new temporary directory.
+/// The directory is intentionally leaked, so it outlives the service.
+pub async fn make_fs_blob_service(compress: bool) -> Box<dyn BlobService> {
+    Box::new(
+        FsBlobService::new(
+            tempfile::tempdir().unwrap().into_path(),
+            crate::blobservice::chunked::default_avg_chunk_size(),
+            compress,
+        )
+        .await
+        .unwrap(),
+    )
+}
+
+/// Constructs and returns a [LruCacheBlobService], caching a
+/// [MemoryBlobService] in another one.
+pub fn make_lru_cache_blob_service() -> Box<dyn BlobService> {
+    Box::new(LruCacheBlobService::new(
+        Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>,
+        Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>,
+        LruIndex::new_temporary(1024 * 1024 * 1024).unwrap(),
+    ))
+}
+
+/// Wraps a [MemoryBlobService], returning corrupted contents when reading
+/// the blob with the digest in `corrupt`.
+#[derive(Clone, Default)]
+pub struct CorruptingBlobService {
+    inner: MemoryBlobService,
+    pub corrupt: Arc<parking_lot::Mutex<Option<B3Digest>>>,
+}
+
+#[tonic::async_trait]
+impl BlobService for CorruptingBlobService {
+    async fn has(&self, digest: &B3Digest) -> std::io::Result<bool> {
+        self.inner.has(digest).await
+    }
+
+    async fn open_read(&self, digest: &B3Digest) -> std::io::Result<Option<Box<dyn BlobReader>>> {
+        if self.corrupt.lock().as_ref() == Some(digest) {
+            return Ok(Some(Box::new(std::io::Cursor::new(&b"garbage"[..]))));
+        }
+        self.inner.open_read(digest).await
+    }
+
+    async fn open_write(&self) -> Box<dyn BlobWriter> {
+        self.inner.open_write().await
+    }
+
+    async fn delete(&self, digest: &B3Digest) -> std::io::Result<()> {
+        self.inner.delete(digest).await
+    }
 }
@@ -0,0 +1,79 @@
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use parking_lot::RwLock;
+use std::io;
+use std::{collections::HashMap, sync::Arc};
+use tonic::async_trait;
+use tracing::instrument;
+
+use super::ChunkService;
+use crate::composition::{CompositionContext, ServiceBuilder};
+use crate::{B3Digest, Error};
+
+#[derive(Clone, Default)]
+pub struct MemoryChunkService {
+    db: Arc<RwLock<HashMap<B3Digest, Bytes>>>,
+}
+
+#[async_trait]
+impl ChunkService for MemoryChunkService {
+    #[instrument(skip_all, ret, err, fields(chunk.digest=%digest))]
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        Ok(self.db.read().contains_key(digest))
+    }
+
+    #[instrument(skip_all, err, fields(chunk.digest=%digest))]
+    async fn get(&self, digest: &B3Digest) -> io::Result<Option<Bytes>> {
+        Ok(self.db.read().get(digest).cloned())
+    }
+
+    #[instrument(skip_all, err, fields(chunk.size=data.len()))]
+    async fn put(&self, data: Bytes) -> io::Result<B3Digest> {
+        let digest: B3Digest = blake3::hash(&data).as_bytes().into();
+        self.db.write().entry(digest.clone()).or_insert(data);
+
+        Ok(digest)
+    }
+
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        // Take a snapshot of the keys, so we don't hold the lock while the
+        // stream is consumed.
+        let digests: Vec<B3Digest> = self.db.read().keys().cloned().collect();
+
+        futures::stream::iter(digests.into_iter().map(Ok)).boxed()
+    }
+
+    #[instrument(skip_all, err, fields(chunk.digest=%digest))]
+    async fn delete(&self, digest: &B3Digest) -> io::Result<()> {
+        self.db.write().remove(digest);
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryChunkServiceConfig {}
+
+impl TryFrom<url::Url> for MemoryChunkServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // memory doesn't support host or path in the URL.
+        if url.has_host() || !url.path().is_empty() {
+            return Err(Error::StorageError("invalid url".to_string()).into());
+        }
+        Ok(MemoryChunkServiceConfig {})
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for MemoryChunkServiceConfig {
+    type Output = dyn ChunkService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn ChunkService>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        Ok(Arc::new(MemoryChunkService::default()))
+    }
+}
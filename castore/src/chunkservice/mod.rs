@@ -0,0 +1,72 @@
+use std::io;
+
+use auto_impl::auto_impl;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use tonic::async_trait;
+
+use crate::composition::{Registry, ServiceBuilder};
+use crate::B3Digest;
+
+mod memory;
+mod object_store;
+
+#[cfg(test)]
+pub mod tests;
+
+pub use self::memory::{MemoryChunkService, MemoryChunkServiceConfig};
+pub use self::object_store::{ObjectStoreChunkService, ObjectStoreChunkServiceConfig};
+
+pub(crate) use self::object_store::derive_chunk_path;
+
+/// The base trait all ChunkService services need to implement.
+///
+/// Chunks are content-addressed pieces of blobs, small enough to be kept
+/// around in contiguous memory.
+/// A ChunkService doesn't know which blobs consist of which chunks, that's
+/// tracked by a [crate::blobmetadataservice::BlobMetadataService].
+#[async_trait]
+#[auto_impl(&, &mut, Arc, Box)]
+pub trait ChunkService: Send + Sync {
+    /// Check if the service has the chunk, by its content hash.
+    /// The default implementation retrieves the chunk and discards it.
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        Ok(self.get(digest).await?.is_some())
+    }
+
+    /// Retrieve a chunk by its content hash.
+    /// Implementations must ensure the returned data matches the digest.
+    async fn get(&self, digest: &B3Digest) -> io::Result<Option<Bytes>>;
+
+    /// Insert a chunk, and return its digest.
+    async fn put(&self, data: Bytes) -> io::Result<B3Digest>;
+
+    /// Iterate over the digests of all chunks in the store.
+    /// Implementations can decide to disallow listing, which is what the
+    /// default implementation does.
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        Box::pin(futures::stream::once(async {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "listing is not supported",
+            ))
+        }))
+    }
+
+    /// Remove a chunk from the store, by its content hash.
+    /// Deleting a chunk that is not present is not an error.
+    /// Implementations can decide to disallow deletion, which is what the
+    /// default implementation does.
+    async fn delete(&self, _digest: &B3Digest) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "deletion is not supported",
+        ))
+    }
+}
+
+/// Registers the builtin ChunkService implementations with the registry
+pub(crate) fn register_chunk_services(reg: &mut Registry) {
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn ChunkService>>, super::chunkservice::ObjectStoreChunkServiceConfig>("objectstore");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn ChunkService>>, super::chunkservice::MemoryChunkServiceConfig>("memory");
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    io::{self, Cursor},
+    sync::Arc,
+};
+
+use async_stream::try_stream;
+use bytes::Bytes;
+use data_encoding::HEXLOWER;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use object_store::{path::Path, ObjectStore};
+use tonic::async_trait;
+use tracing::{debug, instrument, Level};
+
+use crate::{
+    blobservice::digest_from_path,
+    composition::{CompositionContext, ServiceBuilder},
+    B3Digest,
+};
+
+use super::ChunkService;
+
+/// Uses any object storage supported by the [object_store] crate to provide a
+/// tvix-castore [ChunkService].
+///
+/// Chunks are stored at `${base_path}/chunks/b3/$digest_key`, zstd-compressed,
+/// which is the same layout [crate::blobservice::ObjectStoreBlobService] uses
+/// for its chunks, so both can share the same object store.
+#[derive(Clone)]
+pub struct ObjectStoreChunkService {
+    object_store: Arc<dyn ObjectStore>,
+    base_path: Path,
+}
+
+impl ObjectStoreChunkService {
+    pub fn new(object_store: Arc<dyn ObjectStore>, base_path: Path) -> Self {
+        Self {
+            object_store,
+            base_path,
+        }
+    }
+}
+
+#[instrument(level=Level::TRACE, skip_all,fields(base_path=%base_path,chunk.digest=%digest),ret(Display))]
+pub(crate) fn derive_chunk_path(base_path: &Path, digest: &B3Digest) -> Path {
+    base_path
+        .child("chunks")
+        .child("b3")
+        .child(HEXLOWER.encode(&digest.as_slice()[..2]))
+        .child(HEXLOWER.encode(digest.as_slice()))
+}
+
+#[async_trait]
+impl ChunkService for ObjectStoreChunkService {
+    #[instrument(skip_all, ret(level = Level::TRACE), err, fields(chunk.digest=%digest))]
+    async fn has(&self, digest: &B3Digest) -> io::Result<bool> {
+        match self
+            .object_store
+            .head(&derive_chunk_path(&self.base_path, digest))
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e)?,
+        }
+    }
+
+    #[instrument(skip_all, err, fields(chunk.digest=%digest))]
+    async fn get(&self, digest: &B3Digest) -> io::Result<Option<Bytes>> {
+        match self
+            .object_store
+            .get(&derive_chunk_path(&self.base_path, digest))
+            .await
+        {
+            Ok(res) => {
+                // fetch the entire chunk into memory, decompress, and ensure
+                // the b3 digest matches.
+                // FUTUREWORK: use zstd::bulk to prevent decompression bombs
+                let chunk_raw_bytes = res.bytes().await?;
+                let chunk_contents = zstd::stream::decode_all(Cursor::new(chunk_raw_bytes))?;
+
+                if *digest != blake3::hash(&chunk_contents).as_bytes().into() {
+                    Err(io::Error::other("chunk contents invalid"))?;
+                }
+
+                Ok(Some(chunk_contents.into()))
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Uploads the chunk, if it doesn't exist yet.
+    #[instrument(skip_all, fields(chunk.digest, chunk.size = data.len()), err)]
+    async fn put(&self, data: Bytes) -> io::Result<B3Digest> {
+        let chunk_digest: B3Digest = blake3::hash(&data).as_bytes().into();
+        tracing::Span::current().record("chunk.digest", chunk_digest.to_string());
+
+        let chunk_path = derive_chunk_path(&self.base_path, &chunk_digest);
+        match self.object_store.head(&chunk_path).await {
+            // chunk already exists, nothing to do
+            Ok(_) => {
+                debug!("chunk already exists");
+            }
+
+            // chunk does not yet exist, compress and upload.
+            Err(object_store::Error::NotFound { .. }) => {
+                let chunk_data_compressed =
+                    zstd::encode_all(Cursor::new(data), zstd::DEFAULT_COMPRESSION_LEVEL)?;
+
+                debug!(chunk.compressed_size=%chunk_data_compressed.len(), "uploading chunk");
+
+                self.object_store
+                    .as_ref()
+                    .put(&chunk_path, chunk_data_compressed.into())
+                    .await?;
+            }
+            // other error
+            Err(err) => Err(err)?,
+        }
+
+        Ok(chunk_digest)
+    }
+
+    fn list(&self) -> BoxStream<'static, io::Result<B3Digest>> {
+        let object_store = self.object_store.clone();
+        let prefix = self.base_path.child("chunks").child("b3");
+
+        Box::pin(try_stream! {
+            let mut objects = object_store.list(Some(&prefix));
+
+            while let Some(object_meta) = objects.try_next().await? {
+                yield digest_from_path(&object_meta.location)?;
+            }
+        })
+    }
+
+    #[instrument(skip_all, err, fields(chunk.digest=%digest))]
+    async fn delete(&self, digest: &B3Digest) -> io::Result<()> {
+        match self
+            .object_store
+            .delete(&derive_chunk_path(&self.base_path, digest))
+            .await
+        {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e)?,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectStoreChunkServiceConfig {
+    object_store_url: String,
+    object_store_options: HashMap<String, String>,
+}
+
+impl TryFrom<url::Url> for ObjectStoreChunkServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    /// Constructs a new [ObjectStoreChunkService] from a [url::Url] supported by
+    /// [object_store].
+    /// Any path suffix becomes the base path of the object store.
+    /// additional options, the same as in [object_store::parse_url_opts] can
+    /// be passed.
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        let (object_store_url, object_store_options) = crate::objectstore_url::parse(&url)?;
+        Ok(ObjectStoreChunkServiceConfig {
+            object_store_url,
+            object_store_options,
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for ObjectStoreChunkServiceConfig {
+    type Output = dyn ChunkService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn ChunkService>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let (object_store, path) = object_store::parse_url_opts(
+            &self.object_store_url.parse()?,
+            &self.object_store_options,
+        )?;
+        Ok(Arc::new(ObjectStoreChunkService::new(
+            Arc::new(object_store),
+            path,
+        )))
+    }
+}
@@ -0,0 +1,108 @@
+//! This contains test scenarios that a given [ChunkService] needs to pass.
+//! We use [rstest] and [rstest_reuse] to provide all services we want to test
+//! against, and then apply this template to all test functions.
+
+use futures::TryStreamExt;
+use rstest::*;
+use rstest_reuse::{self, *};
+
+use super::{ChunkService, MemoryChunkService, ObjectStoreChunkService};
+use crate::fixtures::{BLOB_A, BLOB_A_DIGEST, BLOB_B, BLOB_B_DIGEST};
+
+fn objectstore_memory() -> ObjectStoreChunkService {
+    ObjectStoreChunkService::new(
+        std::sync::Arc::new(object_store::memory::InMemory::new()),
+        object_store::path::Path::default(),
+    )
+}
+
+/// This produces a template, which will be applied to all individual test functions.
+/// See https://github.com/la10736/rstest/issues/130#issuecomment-968864832
+#[template]
+#[rstest]
+#[case::memory(MemoryChunkService::default())]
+#[case::objectstore_memory(objectstore_memory())]
+pub fn chunk_services(#[case] chunk_service: impl ChunkService) {}
+
+/// Retrieving a non-existing chunk should return Ok(None).
+#[apply(chunk_services)]
+#[tokio::test]
+async fn not_found(chunk_service: impl ChunkService) {
+    assert!(!chunk_service
+        .has(&BLOB_A_DIGEST)
+        .await
+        .expect("must not fail"));
+    assert!(chunk_service
+        .get(&BLOB_A_DIGEST)
+        .await
+        .expect("must not fail")
+        .is_none());
+}
+
+/// Put a chunk in the store, check has, get it back.
+#[apply(chunk_services)]
+#[tokio::test]
+async fn put_has_get(chunk_service: impl ChunkService) {
+    for (chunk_contents, chunk_digest) in [(&*BLOB_A, &*BLOB_A_DIGEST), (&*BLOB_B, &*BLOB_B_DIGEST)]
+    {
+        assert_eq!(
+            *chunk_digest,
+            chunk_service
+                .put(chunk_contents.clone())
+                .await
+                .expect("put must succeed"),
+            "returned digest must be correct"
+        );
+
+        assert!(chunk_service
+            .has(chunk_digest)
+            .await
+            .expect("must not fail"));
+        assert_eq!(
+            Some(chunk_contents.clone()),
+            chunk_service
+                .get(chunk_digest)
+                .await
+                .expect("must not fail")
+        );
+
+        // Putting the same chunk again is fine.
+        chunk_service
+            .put(chunk_contents.clone())
+            .await
+            .expect("put must succeed");
+    }
+}
+
+/// Put a chunk in the store, ensure it shows up in [ChunkService::list], then
+/// delete it again.
+#[apply(chunk_services)]
+#[tokio::test]
+async fn put_list_delete(chunk_service: impl ChunkService) {
+    chunk_service
+        .put(BLOB_A.clone())
+        .await
+        .expect("put must succeed");
+
+    let digests: Vec<_> = chunk_service
+        .list()
+        .try_collect()
+        .await
+        .expect("list must succeed");
+    assert_eq!(vec![BLOB_A_DIGEST.clone()], digests);
+
+    chunk_service
+        .delete(&BLOB_A_DIGEST)
+        .await
+        .expect("delete must succeed");
+    assert!(!chunk_service
+        .has(&BLOB_A_DIGEST)
+        .await
+        .expect("must not fail"));
+
+    // Deleting a chunk that doesn't exist is not an error.
+    chunk_service
+        .delete(&BLOB_A_DIGEST)
+        .await
+        .expect("delete must succeed");
+}
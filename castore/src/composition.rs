@@ -46,6 +46,10 @@
 //! Now, when a user deserializes a store config with the type tag "myblobservicetype" into a
 //! `Box<dyn ServiceBuilder<Output = Arc<dyn BlobService>>>`, it will be done via `MyBlobServiceConfig`.
 //!
+//! URLs are converted with the `TryFrom<Url>` impl of the type named by their scheme, up to the
+//! first `+`. A type named by the whole scheme, with `+` replaced by `-`, takes precedence, so
+//! `foo+bar://` URLs can be handled by a separate "foo-bar" type.
+//!
 //! ### Example 2.: Composing stores to get one store
 //!
 //! ```
@@ -228,14 +232,13 @@ enum TryFromUrlError {
 impl<T: 'static> TryFrom<url::Url> for DeserializeWithRegistry<T> {
     type Error = Box<dyn std::error::Error + Send + Sync>;
     fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        let full_tag = url.scheme().replace('+', "-");
         let tag = url.scheme().split('+').next().unwrap();
+        let reg = ACTIVE_REG.get().unwrap();
         // same as in the SeedFactory impl: using find() and not get() because of https://github.com/rust-lang/rust/issues/80389
-        let seed = ACTIVE_REG
-            .get()
-            .unwrap()
-            .0
-            .iter()
-            .find(|(k, _)| *k == &(TypeId::of::<T>(), tag))
+        let seed = [full_tag.as_str(), tag]
+            .into_iter()
+            .find_map(|tag| reg.0.iter().find(|(k, _)| *k == &(TypeId::of::<T>(), tag)))
             .ok_or_else(|| Box::new(TryFromUrlError::UnknownTag(tag.into())))?
             .1;
         let entry: &RegistryEntry<T> = <dyn Any>::downcast_ref(&**seed).unwrap();
@@ -275,6 +278,8 @@ pub static REG: LazyLock<&'static Registry> = LazyLock::new(|| {
 /// extra third party types.
 pub fn add_default_services(reg: &mut Registry) {
     crate::blobservice::register_blob_services(reg);
+    crate::blobmetadataservice::register_blob_metadata_services(reg);
+    crate::chunkservice::register_chunk_services(reg);
     crate::directoryservice::register_directory_services(reg);
 }
 
@@ -578,4 +583,69 @@ mod test {
             other => panic!("should have returned an error, returned: {:?}", other.err()),
         }
     }
+
+    /// Test that a chunked BlobService can be composed from a
+    /// BlobMetadataService and ChunkService configured in their own sections.
+    #[tokio::test]
+    async fn chunked() {
+        use crate::blobmetadataservice::BlobMetadataService;
+        use crate::chunkservice::ChunkService;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut composition = Composition::new(&REG);
+        with_registry(&REG, || -> Result<(), serde_json::Error> {
+            composition.extend_with_configs::<dyn BlobService>(serde_json::from_value(
+                serde_json::json!({
+                    "default": {
+                        "type": "chunked",
+                        "metadata": "default",
+                        "chunks": "default",
+                    }
+                }),
+            )?);
+            composition.extend_with_configs::<dyn BlobMetadataService>(serde_json::from_value(
+                serde_json::json!({"default": {"type": "memory"}}),
+            )?);
+            composition.extend_with_configs::<dyn ChunkService>(serde_json::from_value(
+                serde_json::json!({"default": {"type": "memory"}}),
+            )?);
+            Ok(())
+        })
+        .unwrap();
+
+        let blob_service = composition
+            .build::<dyn BlobService>("default")
+            .await
+            .expect("must build");
+
+        let mut writer = blob_service.open_write().await;
+        writer.write_all(&crate::fixtures::BLOB_B).await.unwrap();
+        let digest = writer.close().await.unwrap();
+        assert_eq!(*crate::fixtures::BLOB_B_DIGEST, digest);
+
+        // The written blob must be visible through the chunk service too.
+        let chunk_service = composition
+            .build::<dyn ChunkService>("default")
+            .await
+            .expect("must build");
+        let chunks = blob_service.chunks(&digest).await.unwrap().unwrap();
+        assert!(chunks.len() > 1);
+        for chunk in chunks {
+            assert!(chunk_service
+                .has(&chunk.digest.try_into().unwrap())
+                .await
+                .unwrap());
+        }
+
+        let mut buf = Vec::new();
+        blob_service
+            .open_read(&digest)
+            .await
+            .unwrap()
+            .expect("must exist")
+            .read_to_end(&mut buf)
+            .await
+            .unwrap();
+        assert_eq!(&crate::fixtures::BLOB_B[..], &buf[..]);
+    }
 }
@@ -0,0 +1,321 @@
+//! Copying [Node]s, and all directories and blobs reachable from them, from
+//! one pair of [BlobService] and [DirectoryService] to another.
+//!
+//! Only what's missing in the destination is transferred:
+//!  - Directory closures are only copied if their root directory doesn't
+//!    exist in the destination yet. As DirectoryServices only accept complete
+//!    closures, an existing root implies all its children exist too.
+//!  - Blobs are only copied if they don't exist in the destination yet.
+//!    Their chunks are read from the destination if present there, so only
+//!    the missing chunks are fetched from the source.
+
+use std::collections::HashSet;
+
+use futures::{StreamExt, TryStreamExt};
+use tokio_util::io::InspectReader;
+use tracing::{debug, instrument, Span};
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+
+use crate::blobservice::{BlobService, CombinedBlobService};
+use crate::directoryservice::{DirectoryGraph, DirectoryService, RootToLeavesValidator};
+use crate::{B3Digest, Directory, Error, Node};
+
+/// The number of blobs copied concurrently.
+const BLOB_CONCURRENCY: usize = 8;
+
+/// The digests of everything that was copied.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CopyResult {
+    pub directories: Vec<B3Digest>,
+    pub blobs: Vec<B3Digest>,
+}
+
+/// Copies nodes from the source to the destination services.
+pub struct Copier<SBS, SDS, DBS, DDS> {
+    src_blob_service: SBS,
+    src_directory_service: SDS,
+    dst_blob_service: DBS,
+    dst_directory_service: DDS,
+}
+
+impl<SBS, SDS, DBS, DDS> Copier<SBS, SDS, DBS, DDS>
+where
+    SBS: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+    SDS: DirectoryService,
+    DBS: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+    DDS: DirectoryService,
+{
+    pub fn new(
+        src_blob_service: SBS,
+        src_directory_service: SDS,
+        dst_blob_service: DBS,
+        dst_directory_service: DDS,
+    ) -> Self {
+        Self {
+            src_blob_service,
+            src_directory_service,
+            dst_blob_service,
+            dst_directory_service,
+        }
+    }
+
+    /// Copies the given node, and everything reachable from it, skipping
+    /// everything already present in the destination.
+    ///
+    /// Blobs are copied before the directories referring to them, so an
+    /// interrupted copy doesn't leave directories pointing to missing blobs
+    /// behind.
+    #[instrument(skip_all, err)]
+    pub async fn copy(&self, node: &Node) -> Result<CopyResult, Error> {
+        match node {
+            Node::Directory { digest, .. } => self.copy_directory(digest).await,
+            Node::File { digest, size, .. } => Ok(CopyResult {
+                directories: vec![],
+                blobs: self.copy_blobs(vec![(digest.clone(), *size)]).await?,
+            }),
+            Node::Symlink { .. } => Ok(CopyResult::default()),
+        }
+    }
+
+    #[instrument(skip(self), fields(directory.digest = %root_directory_digest), err)]
+    async fn copy_directory(&self, root_directory_digest: &B3Digest) -> Result<CopyResult, Error> {
+        if self
+            .dst_directory_service
+            .get(root_directory_digest)
+            .await?
+            .is_some()
+        {
+            debug!("directory already exists");
+            return Ok(CopyResult::default());
+        }
+
+        let mut graph = DirectoryGraph::with_order(RootToLeavesValidator::new_with_root_digest(
+            root_directory_digest.clone(),
+        ));
+        let mut directories = self
+            .src_directory_service
+            .get_recursive(root_directory_digest);
+        while let Some(directory) = directories.try_next().await? {
+            graph
+                .add(directory)
+                .map_err(|e| Error::StorageError(e.to_string()))?;
+        }
+
+        let directories: Vec<Directory> = graph
+            .validate()
+            .map_err(|e| Error::StorageError(e.to_string()))?
+            .drain_leaves_to_root()
+            .collect();
+
+        if directories.is_empty() {
+            return Err(Error::StorageError(format!(
+                "directory {} not found",
+                root_directory_digest
+            )));
+        }
+
+        let mut seen_blobs = HashSet::new();
+        let files = directories
+            .iter()
+            .flat_map(|directory| directory.nodes())
+            .filter_map(|(_, node)| match node {
+                Node::File { digest, size, .. } => Some((digest.clone(), *size)),
+                _ => None,
+            })
+            .filter(|(digest, _)| seen_blobs.insert(digest.clone()))
+            .collect();
+
+        let blobs = self.copy_blobs(files).await?;
+
+        let mut putter = self.dst_directory_service.put_multiple_start();
+        let mut directory_digests = Vec::with_capacity(directories.len());
+        for directory in directories {
+            directory_digests.push(directory.digest());
+            putter.put(directory).await?;
+        }
+        putter.close().await?;
+
+        Ok(CopyResult {
+            directories: directory_digests,
+            blobs,
+        })
+    }
+
+    /// Copies all blobs missing in the destination, returning their digests.
+    async fn copy_blobs(&self, blobs: Vec<(B3Digest, u64)>) -> Result<Vec<B3Digest>, Error> {
+        let digests: Vec<B3Digest> = blobs.iter().map(|(digest, _)| digest.clone()).collect();
+        let exists = self.dst_blob_service.as_ref().has_many(&digests).await?;
+
+        futures::stream::iter(
+            blobs
+                .into_iter()
+                .zip(exists)
+                .filter(|(_, exists)| !exists)
+                .map(|((digest, size), _)| async move {
+                    self.copy_blob(&digest, size).await.map(|()| digest)
+                }),
+        )
+        .buffer_unordered(BLOB_CONCURRENCY)
+        .try_collect()
+        .await
+    }
+
+    #[instrument(skip(self), fields(blob.digest = %digest, indicatif.pb_show = tracing::field::Empty), err)]
+    async fn copy_blob(&self, digest: &B3Digest, size: u64) -> Result<(), Error> {
+        let span = Span::current();
+        span.pb_set_style(&tvix_tracing::PB_TRANSFER_STYLE);
+        span.pb_set_message(&format!("Copying blob {}", digest));
+        span.pb_set_length(size);
+        span.pb_start();
+
+        // Read chunks already present in the destination from there, and only
+        // fetch the missing ones from the source.
+        let reader =
+            CombinedBlobService::new(self.dst_blob_service.clone(), self.src_blob_service.clone())
+                .open_read(digest)
+                .await?
+                .ok_or_else(|| Error::StorageError(format!("blob {} not found", digest)))?;
+
+        let mut reader = InspectReader::new(reader, |d| span.pb_inc(d.len() as u64));
+        let mut writer = self.dst_blob_service.as_ref().open_write().await;
+        tokio::io::copy(&mut reader, &mut writer).await?;
+
+        let written_digest = writer.close().await?;
+        if written_digest != *digest {
+            return Err(Error::StorageError(format!(
+                "blob {} was written as {}",
+                digest, written_digest
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::io::AsyncWriteExt;
+
+    use super::{Copier, CopyResult};
+    use crate::blobservice::{BlobService, MemoryBlobService};
+    use crate::directoryservice::{DirectoryService, MemoryDirectoryService};
+    use crate::fixtures::{
+        BLOB_A, BLOB_A_DIGEST, DIRECTORY_COMPLICATED, DIRECTORY_WITH_KEEP, EMPTY_BLOB_CONTENTS,
+        EMPTY_BLOB_DIGEST,
+    };
+    use crate::Node;
+
+    async fn put_blob(blob_service: &Arc<dyn BlobService>, contents: &[u8]) {
+        let mut w = blob_service.open_write().await;
+        w.write_all(contents).await.unwrap();
+        w.close().await.unwrap();
+    }
+
+    fn services() -> (Arc<dyn BlobService>, Arc<dyn DirectoryService>) {
+        (
+            Arc::new(MemoryBlobService::default()),
+            Arc::new(MemoryDirectoryService::default()),
+        )
+    }
+
+    fn complicated_node() -> Node {
+        Node::Directory {
+            digest: DIRECTORY_COMPLICATED.digest(),
+            size: DIRECTORY_COMPLICATED.size(),
+        }
+    }
+
+    /// Copying DIRECTORY_COMPLICATED copies its closure and the empty blob,
+    /// and copying it again doesn't copy anything.
+    #[tokio::test]
+    async fn copy_directory() {
+        let (src_blob_service, src_directory_service) = services();
+        put_blob(&src_blob_service, EMPTY_BLOB_CONTENTS).await;
+        put_blob(&src_blob_service, &BLOB_A).await;
+        let mut putter = src_directory_service.put_multiple_start();
+        putter.put(DIRECTORY_WITH_KEEP.clone()).await.unwrap();
+        putter.put(DIRECTORY_COMPLICATED.clone()).await.unwrap();
+        putter.close().await.unwrap();
+
+        let (dst_blob_service, dst_directory_service) = services();
+        let copier = Copier::new(
+            src_blob_service,
+            src_directory_service,
+            dst_blob_service.clone(),
+            dst_directory_service.clone(),
+        );
+
+        let result = copier
+            .copy(&complicated_node())
+            .await
+            .expect("copy must succeed");
+        assert_eq!(
+            CopyResult {
+                directories: vec![DIRECTORY_WITH_KEEP.digest(), DIRECTORY_COMPLICATED.digest()],
+                blobs: vec![EMPTY_BLOB_DIGEST.clone()],
+            },
+            result
+        );
+
+        assert!(dst_blob_service.has(&EMPTY_BLOB_DIGEST).await.unwrap());
+        assert!(!dst_blob_service.has(&BLOB_A_DIGEST).await.unwrap());
+        assert!(dst_directory_service
+            .get(&DIRECTORY_COMPLICATED.digest())
+            .await
+            .unwrap()
+            .is_some());
+
+        assert_eq!(
+            CopyResult::default(),
+            copier
+                .copy(&complicated_node())
+                .await
+                .expect("copy must succeed")
+        );
+    }
+
+    /// Blobs already present in the destination are not copied again.
+    #[tokio::test]
+    async fn copy_file_existing() {
+        let (src_blob_service, src_directory_service) = services();
+        put_blob(&src_blob_service, &BLOB_A).await;
+
+        let (dst_blob_service, dst_directory_service) = services();
+        put_blob(&dst_blob_service, &BLOB_A).await;
+
+        let result = Copier::new(
+            src_blob_service,
+            src_directory_service,
+            dst_blob_service,
+            dst_directory_service,
+        )
+        .copy(&Node::File {
+            digest: BLOB_A_DIGEST.clone(),
+            size: BLOB_A.len() as u64,
+            executable: false,
+        })
+        .await
+        .expect("copy must succeed");
+
+        assert_eq!(CopyResult::default(), result);
+    }
+
+    /// Copying a directory missing in the source fails.
+    #[tokio::test]
+    async fn copy_directory_missing() {
+        let (src_blob_service, src_directory_service) = services();
+        let (dst_blob_service, dst_directory_service) = services();
+
+        Copier::new(
+            src_blob_service,
+            src_directory_service,
+            dst_blob_service,
+            dst_directory_service,
+        )
+        .copy(&complicated_node())
+        .await
+        .expect_err("copy must fail");
+    }
+}
@@ -19,6 +19,10 @@ use super::DirectoryService;
 /// - `redb:///absolute/path/to/somewhere`
 ///   Uses redb, using a path on the disk for persistency. Can be only opened
 ///   from one process at the same time.
+/// - `sqlite:`
+///   Uses a in-memory sqlite database.
+/// - `sqlite:///absolute/path/to/somewhere`
+///   Uses sqlite, using a path on the disk for persistency.
 /// - `grpc+unix:///absolute/path/to/somewhere`
 ///   Connects to a local tvix-store gRPC service via Unix socket.
 /// - `grpc+http://host:port`, `grpc+https://host:port`
@@ -53,6 +57,8 @@ mod tests {
 
     static TMPDIR_REDB_1: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
     static TMPDIR_REDB_2: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
+    static TMPDIR_FS_1: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
+    static TMPDIR_SQLITE_1: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
 
     #[rstest]
     /// This uses an unsupported scheme.
@@ -75,6 +81,22 @@ mod tests {
     #[case::redb_valid_path(&format!("redb://{}", &TMPDIR_REDB_1.path().join("foo").to_str().unwrap()), true)]
     /// This configures redb with a host, and a valid path path, which should fail.
     #[case::redb_invalid_host_with_valid_path(&format!("redb://foo.example{}", &TMPDIR_REDB_2.path().join("bar").to_str().unwrap()), false)]
+    /// This configures sqlite in temporary mode.
+    #[case::sqlite_valid_temporary("sqlite://", true)]
+    /// This configures sqlite with /, which should fail.
+    #[case::sqlite_invalid_root("sqlite:///", false)]
+    /// This configures sqlite with a host, not path, which should fail.
+    #[case::sqlite_invalid_host("sqlite://foo.example", false)]
+    /// This configures sqlite with a valid path, which should succeed.
+    #[case::sqlite_valid_path(&format!("sqlite://{}", &TMPDIR_SQLITE_1.path().join("foo").to_str().unwrap()), true)]
+    /// This configures fs with a valid path, which should succeed.
+    #[case::fs_valid_path(&format!("fs://{}", &TMPDIR_FS_1.path().join("foo").to_str().unwrap()), true)]
+    /// This configures fs without a path, which should fail.
+    #[case::fs_invalid_no_path("fs://", false)]
+    /// This configures fs with /, which should fail.
+    #[case::fs_invalid_root("fs:///", false)]
+    /// This configures fs with a host, not path, which should fail.
+    #[case::fs_invalid_host("fs://foo.example", false)]
     /// Correct scheme to connect to a unix socket.
     #[case::grpc_valid_unix_socket("grpc+unix:///path/to/somewhere", true)]
     /// Correct scheme for unix socket, but setting a host too, which is invalid.
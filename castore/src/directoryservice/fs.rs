@@ -0,0 +1,150 @@
+use std::{path::PathBuf, sync::Arc};
+
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use prost::Message;
+use tonic::async_trait;
+use tracing::instrument;
+
+use super::utils::traverse_directory;
+use super::{Directory, DirectoryPutter, DirectoryService, SimplePutter};
+use crate::composition::{CompositionContext, ServiceBuilder};
+use crate::sharded_fs::ShardedFs;
+use crate::{proto, B3Digest, Error};
+
+/// Content type of directory files, holding a serialized [proto::Directory].
+const CONTENT_TYPE_DIRECTORY: &str = "application/vnd.tvix-castore.directory+protobuf";
+
+/// Stores directories in a local directory.
+///
+/// Each directory is stored individually as a serialized [proto::Directory]
+/// at `${root}/dirs/b3/$digest_key`, using the same sharding, atomic writes
+/// and content type xattrs as [crate::blobservice::FsBlobService].
+#[derive(Clone)]
+pub struct FsDirectoryService {
+    fs: ShardedFs,
+}
+
+impl FsDirectoryService {
+    /// Opens (and creates, if it doesn't exist) a [FsDirectoryService] at the
+    /// given path.
+    pub async fn new(root: PathBuf) -> Result<Self, Error> {
+        Ok(Self {
+            fs: ShardedFs::open(root).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl DirectoryService for FsDirectoryService {
+    #[instrument(skip(self, digest), fields(directory.digest = %digest))]
+    async fn get(&self, digest: &B3Digest) -> Result<Option<Directory>, Error> {
+        match self.fs.get("dirs", digest).await? {
+            // The directory was not found, return
+            None => Ok(None),
+
+            Some((data, content_type)) if content_type == CONTENT_TYPE_DIRECTORY => {
+                let directory = proto::Directory::decode(Bytes::from(data)).map_err(|e| {
+                    Error::StorageError(format!("unable to parse directory {}: {}", digest, e))
+                })?;
+
+                // Validate the retrieved Directory indeed has the
+                // digest we expect it to have, to detect corruptions.
+                let actual_digest = directory.digest();
+                if actual_digest != *digest {
+                    return Err(Error::StorageError(format!(
+                        "requested directory with digest {}, but got {}",
+                        digest, actual_digest
+                    )));
+                }
+
+                Ok(Some(directory.try_into().map_err(|e| {
+                    Error::StorageError(format!("corrupted directory: {}", e))
+                })?))
+            }
+
+            Some((_, content_type)) => Err(Error::StorageError(format!(
+                "unexpected content type for directory {}: {}",
+                digest, content_type
+            ))),
+        }
+    }
+
+    #[instrument(skip(self, directory), fields(directory.digest = %directory.digest()))]
+    async fn put(&self, directory: Directory) -> Result<B3Digest, Error> {
+        let digest = directory.digest();
+
+        self.fs
+            .put(
+                "dirs",
+                &digest,
+                proto::Directory::from(directory).encode_to_vec(),
+                CONTENT_TYPE_DIRECTORY,
+            )
+            .await?;
+
+        Ok(digest)
+    }
+
+    #[instrument(skip_all, fields(directory.digest = %root_directory_digest))]
+    fn get_recursive(
+        &self,
+        root_directory_digest: &B3Digest,
+    ) -> BoxStream<'static, Result<Directory, Error>> {
+        traverse_directory(self.clone(), root_directory_digest)
+    }
+
+    #[instrument(skip_all)]
+    fn put_multiple_start(&self) -> Box<dyn DirectoryPutter + 'static>
+    where
+        Self: Clone,
+    {
+        Box::new(SimplePutter::new(self.clone()))
+    }
+
+    #[instrument(skip_all)]
+    fn list(&self) -> BoxStream<'static, Result<B3Digest, Error>> {
+        Box::pin(self.fs.list("dirs").map_err(Error::from))
+    }
+
+    #[instrument(skip(self, digest), fields(directory.digest = %digest))]
+    async fn delete(&self, digest: &B3Digest) -> Result<(), Error> {
+        Ok(self.fs.delete("dirs", digest).await?)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FsDirectoryServiceConfig {
+    root: PathBuf,
+}
+
+impl TryFrom<url::Url> for FsDirectoryServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // fs doesn't support host, and requires a path.
+        if url.has_host() {
+            return Err(Error::StorageError("no host allowed".to_string()).into());
+        }
+        if url.path().is_empty() {
+            return Err(Error::StorageError("path required".to_string()).into());
+        }
+
+        Ok(FsDirectoryServiceConfig {
+            root: url.path().into(),
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for FsDirectoryServiceConfig {
+    type Output = dyn DirectoryService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn DirectoryService>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        Ok(Arc::new(FsDirectoryService::new(self.root.clone()).await?))
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{B3Digest, Error};
 use futures::stream::BoxStream;
+use futures::StreamExt;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -71,6 +72,26 @@ impl DirectoryService for MemoryDirectoryService {
     {
         Box::new(SimplePutter::new(self.clone()))
     }
+
+    #[instrument(skip_all)]
+    fn list(&self) -> BoxStream<'static, Result<B3Digest, Error>> {
+        let db = self.db.clone();
+
+        // Take a snapshot of the keys, so we don't hold the lock while the
+        // stream is consumed.
+        futures::stream::once(async move {
+            let digests: Vec<B3Digest> = db.read().await.keys().cloned().collect();
+            futures::stream::iter(digests.into_iter().map(Ok))
+        })
+        .flatten()
+        .boxed()
+    }
+
+    #[instrument(skip(self, digest), fields(directory.digest = %digest))]
+    async fn delete(&self, digest: &B3Digest) -> Result<(), Error> {
+        self.db.write().await.remove(digest);
+        Ok(())
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -7,12 +7,14 @@ use tonic::async_trait;
 mod combinators;
 mod directory_graph;
 mod from_addr;
+mod fs;
 mod grpc;
 mod memory;
 mod object_store;
 mod order_validator;
 mod redb;
 mod simple_putter;
+mod sqlite;
 #[cfg(test)]
 pub mod tests;
 mod traverse;
@@ -21,12 +23,14 @@ mod utils;
 pub use self::combinators::{Cache, CacheConfig};
 pub use self::directory_graph::{DirectoryGraph, ValidatedDirectoryGraph};
 pub use self::from_addr::from_addr;
+pub use self::fs::{FsDirectoryService, FsDirectoryServiceConfig};
 pub use self::grpc::{GRPCDirectoryService, GRPCDirectoryServiceConfig};
 pub use self::memory::{MemoryDirectoryService, MemoryDirectoryServiceConfig};
 pub use self::object_store::{ObjectStoreDirectoryService, ObjectStoreDirectoryServiceConfig};
 pub use self::order_validator::{LeavesToRootValidator, OrderValidator, RootToLeavesValidator};
 pub use self::redb::{RedbDirectoryService, RedbDirectoryServiceConfig};
 pub use self::simple_putter::SimplePutter;
+pub use self::sqlite::{SqliteDirectoryService, SqliteDirectoryServiceConfig};
 pub use self::traverse::descend_to;
 pub use self::utils::traverse_directory;
 
@@ -80,6 +84,29 @@ pub trait DirectoryService: Send + Sync {
     /// Allows persisting a closure of [Directory], which is a graph of
     /// connected Directory messages.
     fn put_multiple_start(&self) -> Box<dyn DirectoryPutter>;
+
+    /// Iterate over the digests of all [Directory] messages stored.
+    ///
+    /// Implementations only allowing retrieval of the root of a closure
+    /// (see [DirectoryService::get]) only return the digests of these roots.
+    /// Implementations can decide to disallow listing, which is what the
+    /// default implementation does.
+    fn list(&self) -> BoxStream<'static, Result<B3Digest, Error>> {
+        Box::pin(futures::stream::once(async {
+            Err(Error::Unsupported("listing".to_string()))
+        }))
+    }
+
+    /// Remove a [Directory] from the store, by its digest.
+    ///
+    /// This does not remove any child directories. Implementations storing
+    /// closures as a whole remove the entire closure stored under that digest.
+    /// Deleting a Directory that is not present is not an error.
+    /// Implementations can decide to disallow deletion, which is what the
+    /// default implementation does.
+    async fn delete(&self, _digest: &B3Digest) -> Result<(), Error> {
+        Err(Error::Unsupported("deletion".to_string()))
+    }
 }
 
 /// Provides a handle to put a closure of connected [Directory] elements.
@@ -111,9 +138,11 @@ pub trait DirectoryPutter: Send {
 pub(crate) fn register_directory_services(reg: &mut Registry) {
     reg.register::<Box<dyn ServiceBuilder<Output = dyn DirectoryService>>, super::directoryservice::ObjectStoreDirectoryServiceConfig>("objectstore");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn DirectoryService>>, super::directoryservice::MemoryDirectoryServiceConfig>("memory");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn DirectoryService>>, super::directoryservice::FsDirectoryServiceConfig>("fs");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn DirectoryService>>, super::directoryservice::CacheConfig>("cache");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn DirectoryService>>, super::directoryservice::GRPCDirectoryServiceConfig>("grpc");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn DirectoryService>>, super::directoryservice::RedbDirectoryServiceConfig>("redb");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn DirectoryService>>, super::directoryservice::SqliteDirectoryServiceConfig>("sqlite");
     #[cfg(feature = "cloud")]
     {
         reg.register::<Box<dyn ServiceBuilder<Output = dyn DirectoryService>>, super::directoryservice::BigtableParameters>("bigtable");
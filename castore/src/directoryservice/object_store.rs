@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use async_stream::try_stream;
 use data_encoding::HEXLOWER;
 use futures::future::Either;
 use futures::stream::BoxStream;
@@ -45,6 +46,14 @@ fn derive_dirs_path(base_path: &Path, digest: &B3Digest) -> Path {
         .child(HEXLOWER.encode(digest.as_slice()))
 }
 
+/// Parses the digest back from a path produced by [derive_dirs_path].
+fn digest_from_path(path: &Path) -> Result<B3Digest, Error> {
+    path.filename()
+        .and_then(|filename| HEXLOWER.decode(filename.as_bytes()).ok())
+        .and_then(|digest| B3Digest::try_from(digest).ok())
+        .ok_or_else(|| Error::StorageError(format!("unexpected key in object store: {}", path)))
+}
+
 #[allow(clippy::identity_op)]
 const MAX_FRAME_LENGTH: usize = 1 * 1024 * 1024 * 1000; // 1 MiB
                                                         //
@@ -178,6 +187,39 @@ impl DirectoryService for ObjectStoreDirectoryService {
             self.base_path.clone(),
         ))
     }
+
+    /// Only returns the digests of the roots of the stored closures, as child
+    /// directories can't be accessed individually.
+    #[instrument(skip_all)]
+    fn list(&self) -> BoxStream<'static, Result<B3Digest, Error>> {
+        let object_store = self.object_store.clone();
+        let prefix = self.base_path.child("dirs").child("b3");
+
+        Box::pin(try_stream! {
+            let mut objects = object_store.list(Some(&prefix));
+
+            while let Some(object_meta) = objects
+                .try_next()
+                .await
+                .map_err(|e| Error::from(std::io::Error::from(e)))?
+            {
+                yield digest_from_path(&object_meta.location)?;
+            }
+        })
+    }
+
+    /// Removes the entire closure stored with the given digest as its root.
+    #[instrument(skip(self, digest), fields(directory.digest = %digest))]
+    async fn delete(&self, digest: &B3Digest) -> Result<(), Error> {
+        match self
+            .object_store
+            .delete(&derive_dirs_path(&self.base_path, digest))
+            .await
+        {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(std::io::Error::from(e).into()),
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -191,25 +233,10 @@ pub struct ObjectStoreDirectoryServiceConfig {
 impl TryFrom<url::Url> for ObjectStoreDirectoryServiceConfig {
     type Error = Box<dyn std::error::Error + Send + Sync>;
     fn try_from(url: url::Url) -> Result<Self, Self::Error> {
-        // We need to convert the URL to string, strip the prefix there, and then
-        // parse it back as url, as Url::set_scheme() rejects some of the transitions we want to do.
-        let trimmed_url = {
-            let s = url.to_string();
-            let mut url = Url::parse(
-                s.strip_prefix("objectstore+")
-                    .ok_or(Error::StorageError("Missing objectstore uri".into()))?,
-            )?;
-            // trim the query pairs, they might contain credentials or local settings we don't want to send as-is.
-            url.set_query(None);
-            url
-        };
+        let (object_store_url, object_store_options) = crate::objectstore_url::parse(&url)?;
         Ok(ObjectStoreDirectoryServiceConfig {
-            object_store_url: trimmed_url.into(),
-            object_store_options: url
-                .query_pairs()
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect(),
+            object_store_url,
+            object_store_options,
         })
     }
 }
@@ -1,7 +1,8 @@
-use futures::stream::BoxStream;
+use futures::{stream::BoxStream, StreamExt};
 use prost::Message;
-use redb::{Database, TableDefinition};
+use redb::{Database, ReadableTable, TableDefinition};
 use std::{path::PathBuf, sync::Arc};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::async_trait;
 use tracing::{instrument, warn};
 
@@ -164,6 +165,54 @@ impl DirectoryService for RedbDirectoryService {
             directory_validator: Some(Default::default()),
         })
     }
+
+    #[instrument(skip_all)]
+    fn list(&self) -> BoxStream<'static, Result<B3Digest, Error>> {
+        let db = self.db.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(50);
+
+        // Spawn a blocking task which writes all digests to tx.
+        // Errors are sent to tx too, so they end up in the stream.
+        tokio::task::spawn_blocking(move || {
+            let list_digests = || -> Result<(), Error> {
+                let txn = db.begin_read()?;
+                let table = txn.open_table(DIRECTORY_TABLE)?;
+
+                for elem in table.iter()? {
+                    let digest: B3Digest = (&elem?.0.value()).into();
+                    tx.blocking_send(Ok(digest))
+                        .map_err(|e| Error::StorageError(e.to_string()))?;
+                }
+
+                Ok(())
+            };
+
+            if let Err(e) = list_digests() {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+
+        ReceiverStream::from(rx).boxed()
+    }
+
+    #[instrument(skip(self, digest), fields(directory.digest = %digest))]
+    async fn delete(&self, digest: &B3Digest) -> Result<(), Error> {
+        tokio::task::spawn_blocking({
+            let db = self.db.clone();
+            let digest_as_array: [u8; digests::B3_LEN] = digest.to_owned().into();
+            move || {
+                let txn = db.begin_write()?;
+                {
+                    let mut table = txn.open_table(DIRECTORY_TABLE)?;
+                    table.remove(digest_as_array)?;
+                }
+                txn.commit()?;
+
+                Ok(())
+            }
+        })
+        .await?
+    }
 }
 
 pub struct RedbDirectoryPutter {
@@ -0,0 +1,349 @@
+use async_stream::try_stream;
+use futures::stream::BoxStream;
+use prost::Message;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tonic::async_trait;
+use tracing::{instrument, warn};
+
+use super::{
+    traverse_directory, Directory, DirectoryGraph, DirectoryPutter, DirectoryService,
+    LeavesToRootValidator,
+};
+use crate::{
+    composition::{CompositionContext, ServiceBuilder},
+    proto, B3Digest, Error,
+};
+
+/// How long to wait for locks held by other connections to the same database
+/// before giving up with SQLITE_BUSY.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The schema of the database. Directories are stored protobuf-encoded,
+/// keyed by their digest.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS directories (
+    digest BLOB PRIMARY KEY NOT NULL,
+    data BLOB NOT NULL
+) STRICT;
+";
+
+/// DirectoryService implementation using sqlite under the hood.
+#[derive(Clone)]
+pub struct SqliteDirectoryService {
+    // rusqlite connections can't be shared between threads, so we serialize
+    // access through a mutex, and move it into spawn_blocking.
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteDirectoryService {
+    /// Constructs a new instance using the specified filesystem path for
+    /// storage.
+    pub async fn new(path: PathBuf) -> Result<Self, Error> {
+        if path == std::path::Path::new("/") {
+            return Err(Error::StorageError(
+                "cowardly refusing to open / with sqlite".to_string(),
+            ));
+        }
+
+        let conn = tokio::task::spawn_blocking(|| -> Result<_, rusqlite::Error> {
+            let conn = Connection::open(path)?;
+            // Wait for other processes holding a lock on the database, rather
+            // than failing with SQLITE_BUSY right away.
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            // WAL allows other processes to read while we write.
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            create_schema(&conn)?;
+            Ok(conn)
+        })
+        .await??;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Constructs a new instance using an in-memory database.
+    pub fn new_temporary() -> Result<Self, Error> {
+        let conn = Connection::open_in_memory()?;
+        create_schema(&conn)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs the given closure with the connection, in a blocking task.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| Error::StorageError("sqlite connection poisoned".to_string()))?;
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+/// Ensures all tables are present.
+fn create_schema(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(SCHEMA)
+}
+
+/// Inserts a directory, unless it already exists.
+fn insert_directory(conn: &Connection, directory: Directory) -> Result<B3Digest, Error> {
+    let digest = directory.digest();
+    conn.prepare_cached("INSERT OR IGNORE INTO directories (digest, data) VALUES (?1, ?2)")?
+        .execute(params![
+            digest.as_slice(),
+            proto::Directory::from(directory).encode_to_vec()
+        ])?;
+
+    Ok(digest)
+}
+
+#[async_trait]
+impl DirectoryService for SqliteDirectoryService {
+    #[instrument(skip(self, digest), fields(directory.digest = %digest))]
+    async fn get(&self, digest: &B3Digest) -> Result<Option<Directory>, Error> {
+        let directory_data = {
+            let digest = digest.clone();
+            self.with_conn(move |conn| {
+                Ok(conn
+                    .prepare_cached("SELECT data FROM directories WHERE digest = ?1")?
+                    .query_row(params![digest.as_slice()], |row| row.get::<_, Vec<u8>>(0))
+                    .optional()?)
+            })
+            .await?
+        };
+
+        // The Directory was not found, return None.
+        let directory_data = match directory_data {
+            None => return Ok(None),
+            Some(d) => d,
+        };
+
+        // We check that the digest of the retrieved Directory matches the expected digest.
+        let actual_digest = blake3::hash(&directory_data);
+        if actual_digest.as_bytes() != digest.as_slice() {
+            warn!(directory.actual_digest=%actual_digest, "requested Directory got the wrong digest");
+            return Err(Error::StorageError(
+                "requested Directory got the wrong digest".to_string(),
+            ));
+        }
+
+        // Attempt to decode the retrieved protobuf-encoded Directory, returning a parsing error if
+        // the decoding failed.
+        let directory = match proto::Directory::decode(directory_data.as_slice()) {
+            Ok(dir) => {
+                // The returned Directory must be valid.
+                dir.try_into().map_err(|e| {
+                    warn!(err=%e, "Directory failed validation");
+                    Error::StorageError("Directory failed validation".to_string())
+                })?
+            }
+            Err(e) => {
+                warn!(err=%e, "failed to parse Directory");
+                return Err(Error::StorageError("failed to parse Directory".to_string()));
+            }
+        };
+
+        Ok(Some(directory))
+    }
+
+    #[instrument(skip(self, directory), fields(directory.digest = %directory.digest()))]
+    async fn put(&self, directory: Directory) -> Result<B3Digest, Error> {
+        self.with_conn(move |conn| insert_directory(conn, directory))
+            .await
+    }
+
+    #[instrument(skip_all, fields(directory.digest = %root_directory_digest))]
+    fn get_recursive(
+        &self,
+        root_directory_digest: &B3Digest,
+    ) -> BoxStream<'static, Result<Directory, Error>> {
+        traverse_directory(self.clone(), root_directory_digest)
+    }
+
+    #[instrument(skip_all)]
+    fn put_multiple_start(&self) -> Box<dyn DirectoryPutter> {
+        Box::new(SqliteDirectoryPutter {
+            directory_service: self.clone(),
+            directory_validator: Some(Default::default()),
+        })
+    }
+
+    #[instrument(skip_all)]
+    fn list(&self) -> BoxStream<'static, Result<B3Digest, Error>> {
+        let directory_service = self.clone();
+
+        Box::pin(try_stream! {
+            // Retrieve all digests at once, so we don't hold the connection
+            // while the stream is consumed.
+            let digests = directory_service
+                .with_conn(|conn| {
+                    let mut stmt = conn.prepare("SELECT digest FROM directories")?;
+                    let digests = stmt
+                        .query_map([], |row| row.get::<_, Vec<u8>>(0))?
+                        .map(|digest| {
+                            B3Digest::try_from(digest?)
+                                .map_err(|e| Error::StorageError(e.to_string()))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(digests)
+                })
+                .await?;
+
+            for digest in digests {
+                yield digest;
+            }
+        })
+    }
+
+    #[instrument(skip(self, digest), fields(directory.digest = %digest))]
+    async fn delete(&self, digest: &B3Digest) -> Result<(), Error> {
+        let digest = digest.clone();
+        self.with_conn(move |conn| {
+            conn.prepare_cached("DELETE FROM directories WHERE digest = ?1")?
+                .execute(params![digest.as_slice()])?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+pub struct SqliteDirectoryPutter {
+    directory_service: SqliteDirectoryService,
+
+    /// The directories (inside the directory validator) that we insert later,
+    /// or None, if they were already inserted.
+    directory_validator: Option<DirectoryGraph<LeavesToRootValidator>>,
+}
+
+#[async_trait]
+impl DirectoryPutter for SqliteDirectoryPutter {
+    #[instrument(level = "trace", skip_all, fields(directory.digest=%directory.digest()), err)]
+    async fn put(&mut self, directory: Directory) -> Result<(), Error> {
+        match self.directory_validator {
+            None => return Err(Error::StorageError("already closed".to_string())),
+            Some(ref mut validator) => {
+                validator
+                    .add(directory)
+                    .map_err(|e| Error::StorageError(e.to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[instrument(level = "trace", skip_all, ret, err)]
+    async fn close(&mut self) -> Result<B3Digest, Error> {
+        match self.directory_validator.take() {
+            None => Err(Error::StorageError("already closed".to_string())),
+            Some(validator) => {
+                // Retrieve the validated directories.
+                let directories = validator
+                    .validate()
+                    .map_err(|e| Error::StorageError(e.to_string()))?
+                    .drain_leaves_to_root()
+                    .collect::<Vec<_>>();
+
+                // Get the root digest, which is at the end (cf. insertion order)
+                let root_digest = directories
+                    .last()
+                    .ok_or_else(|| Error::StorageError("got no directories".to_string()))?
+                    .digest();
+
+                // Insert all directories in a single transaction.
+                self.directory_service
+                    .with_conn(move |conn| {
+                        let txn = conn.transaction()?;
+                        for directory in directories {
+                            insert_directory(&txn, directory)?;
+                        }
+                        txn.commit()?;
+
+                        Ok(())
+                    })
+                    .await?;
+
+                Ok(root_digest)
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SqliteDirectoryServiceConfig {
+    is_temporary: bool,
+    #[serde(default)]
+    /// required when is_temporary = false
+    path: Option<PathBuf>,
+}
+
+impl TryFrom<url::Url> for SqliteDirectoryServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // sqlite doesn't support host, and a path can be provided (otherwise
+        // it'll live in memory only).
+        if url.has_host() {
+            return Err(Error::StorageError("no host allowed".to_string()).into());
+        }
+
+        Ok(if url.path().is_empty() {
+            SqliteDirectoryServiceConfig {
+                is_temporary: true,
+                path: None,
+            }
+        } else {
+            SqliteDirectoryServiceConfig {
+                is_temporary: false,
+                path: Some(url.path().into()),
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for SqliteDirectoryServiceConfig {
+    type Output = dyn DirectoryService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn DirectoryService>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        match self {
+            SqliteDirectoryServiceConfig {
+                is_temporary: true,
+                path: None,
+            } => Ok(Arc::new(
+                tokio::task::spawn_blocking(SqliteDirectoryService::new_temporary).await??,
+            )),
+            SqliteDirectoryServiceConfig {
+                is_temporary: true,
+                path: Some(_),
+            } => Err(Error::StorageError(
+                "Temporary SqliteDirectoryService can not have path".into(),
+            )
+            .into()),
+            SqliteDirectoryServiceConfig {
+                is_temporary: false,
+                path: None,
+            } => Err(Error::StorageError("SqliteDirectoryService is missing path".into()).into()),
+            SqliteDirectoryServiceConfig {
+                is_temporary: false,
+                path: Some(path),
+            } => Ok(Arc::new(SqliteDirectoryService::new(path.into()).await?)),
+        }
+    }
+}
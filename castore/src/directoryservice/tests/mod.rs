@@ -2,7 +2,7 @@
 //! We use [rstest] and [rstest_reuse] to provide all services we want to test
 //! against, and then apply this template to all test functions.
 
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
 use rstest::*;
 use rstest_reuse::{self, *};
 
@@ -12,7 +12,7 @@ use crate::fixtures::{DIRECTORY_A, DIRECTORY_B, DIRECTORY_C, DIRECTORY_D};
 use crate::{Directory, Node};
 
 mod utils;
-use self::utils::make_grpc_directory_service_client;
+use self::utils::{make_fs_directory_service, make_grpc_directory_service_client};
 
 // TODO: add tests doing individual puts of a closure, then doing a get_recursive
 // (and figure out semantics if necessary)
@@ -24,7 +24,9 @@ use self::utils::make_grpc_directory_service_client;
 #[case::grpc(make_grpc_directory_service_client().await)]
 #[case::memory(directoryservice::from_addr("memory://").await.unwrap())]
 #[case::redb(directoryservice::from_addr("redb://").await.unwrap())]
+#[case::sqlite(directoryservice::from_addr("sqlite://").await.unwrap())]
 #[case::objectstore(directoryservice::from_addr("objectstore+memory://").await.unwrap())]
+#[case::fs(make_fs_directory_service().await)]
 #[cfg_attr(all(feature = "cloud", feature = "integration"), case::bigtable(directoryservice::from_addr("bigtable://instance-1?project_id=project-1&table_name=table-1&family_name=cf1").await.unwrap()))]
 pub fn directory_services(#[case] directory_service: impl DirectoryService) {}
 
@@ -235,3 +237,40 @@ async fn upload_reject_wrong_size(directory_service: impl DirectoryService) {
         )
     }
 }
+
+/// Put a closure into the store, ensure its root shows up in
+/// [DirectoryService::list], then delete it again.
+/// Not all implementations support listing and deletion, so this only runs
+/// against the ones that do.
+#[rstest]
+#[case::memory(directoryservice::from_addr("memory://").await.unwrap())]
+#[case::redb(directoryservice::from_addr("redb://").await.unwrap())]
+#[case::objectstore(directoryservice::from_addr("objectstore+memory://").await.unwrap())]
+#[case::fs(make_fs_directory_service().await)]
+#[tokio::test]
+async fn put_list_delete(#[case] directory_service: impl DirectoryService) {
+    let mut handle = directory_service.put_multiple_start();
+    handle.put(DIRECTORY_A.clone()).await.unwrap();
+    handle.put(DIRECTORY_C.clone()).await.unwrap();
+    let root_digest = handle.close().await.unwrap();
+    assert_eq!(DIRECTORY_C.digest(), root_digest);
+
+    let digests: Vec<_> = directory_service
+        .list()
+        .try_collect()
+        .await
+        .expect("list must succeed");
+    assert!(digests.contains(&root_digest));
+
+    directory_service
+        .delete(&root_digest)
+        .await
+        .expect("delete must succeed");
+    assert_eq!(Ok(None), directory_service.get(&root_digest).await);
+
+    // Deleting a directory that doesn't exist is not an error.
+    directory_service
+        .delete(&root_digest)
+        .await
+        .expect("delete must succeed");
+}
@@ -1,4 +1,4 @@
-use crate::directoryservice::{DirectoryService, GRPCDirectoryService};
+use crate::directoryservice::{DirectoryService, FsDirectoryService, GRPCDirectoryService};
 use crate::proto::directory_service_client::DirectoryServiceClient;
 use crate::proto::GRPCDirectoryServiceWrapper;
 use crate::{
@@ -45,3 +45,13 @@ pub async fn make_grpc_directory_service_client() -> Box<dyn DirectoryService> {
         ),
     ))
 }
+
+/// Constructs and returns a [FsDirectoryService] in a new temporary directory.
+/// The directory is intentionally leaked, so it outlives the service.
+pub async fn make_fs_directory_service() -> Box<dyn DirectoryService> {
+    Box::new(
+        FsDirectoryService::new(tempfile::tempdir().unwrap().into_path())
+            .await
+            .unwrap(),
+    )
+}
@@ -16,6 +16,10 @@ pub enum Error {
 
     #[error("internal storage error: {0}")]
     StorageError(String),
+
+    /// The requested operation is not supported by this implementation.
+    #[error("unsupported operation: {0}")]
+    Unsupported(String),
 }
 
 /// Errors that occur during construction of [crate::Node]
@@ -75,6 +79,7 @@ impl From<Error> for Status {
         match value {
             Error::InvalidRequest(msg) => Status::invalid_argument(msg),
             Error::StorageError(msg) => Status::data_loss(format!("storage error: {}", msg)),
+            Error::Unsupported(msg) => Status::unimplemented(msg),
         }
     }
 }
@@ -121,12 +126,18 @@ impl From<redb::CommitError> for Error {
     }
 }
 
+impl From<rusqlite::Error> for Error {
+    fn from(value: rusqlite::Error) -> Self {
+        Error::StorageError(value.to_string())
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
-        if value.kind() == std::io::ErrorKind::InvalidInput {
-            Error::InvalidRequest(value.to_string())
-        } else {
-            Error::StorageError(value.to_string())
+        match value.kind() {
+            std::io::ErrorKind::InvalidInput => Error::InvalidRequest(value.to_string()),
+            std::io::ErrorKind::Unsupported => Error::Unsupported(value.to_string()),
+            _ => Error::StorageError(value.to_string()),
         }
     }
 }
@@ -137,6 +148,7 @@ impl From<Error> for std::io::Error {
         match value {
             Error::InvalidRequest(msg) => Self::new(std::io::ErrorKind::InvalidInput, msg),
             Error::StorageError(msg) => Self::new(std::io::ErrorKind::Other, msg),
+            Error::Unsupported(msg) => Self::new(std::io::ErrorKind::Unsupported, msg),
         }
     }
 }
@@ -0,0 +1,268 @@
+//! Mark-and-sweep garbage collection for [BlobService] and [DirectoryService].
+//!
+//! Starting from a set of root [Node]s, all reachable directories are walked
+//! using [DirectoryService::get_recursive], and all blobs (as well as their
+//! chunks) referred to are marked as reachable.
+//! Everything else returned by [DirectoryService::list] and
+//! [BlobService::list] can then be removed.
+//!
+//! There is no locking: anything uploaded concurrently, but not yet reachable
+//! from one of the roots, might be removed during a sweep. Garbage collection
+//! should therefore not run while other clients are writing to the same
+//! stores.
+
+use std::collections::HashSet;
+
+use futures::TryStreamExt;
+use tracing::{debug, instrument, warn};
+
+use crate::blobservice::BlobService;
+use crate::directoryservice::DirectoryService;
+use crate::{B3Digest, Error, Node};
+
+/// The digests of everything that was (or would have been, in case of a dry
+/// run) removed during a sweep.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SweepResult {
+    pub directories: Vec<B3Digest>,
+    pub blobs: Vec<B3Digest>,
+}
+
+/// Keeps track of the directories and blobs reachable from the roots marked
+/// so far, and removes everything else from the stores on [Self::sweep].
+pub struct GarbageCollector<BS, DS> {
+    blob_service: BS,
+    directory_service: DS,
+
+    reachable_directories: HashSet<B3Digest>,
+    /// Contains both the digests of blobs and their chunks.
+    reachable_blobs: HashSet<B3Digest>,
+}
+
+impl<BS, DS> GarbageCollector<BS, DS>
+where
+    BS: BlobService,
+    DS: DirectoryService,
+{
+    pub fn new(blob_service: BS, directory_service: DS) -> Self {
+        Self {
+            blob_service,
+            directory_service,
+            reachable_directories: HashSet::new(),
+            reachable_blobs: HashSet::new(),
+        }
+    }
+
+    /// Marks the given root node, and everything reachable from it.
+    #[instrument(skip_all, err)]
+    pub async fn mark(&mut self, root_node: &Node) -> Result<(), Error> {
+        match root_node {
+            Node::Directory { digest, .. } => self.mark_directory(digest).await,
+            Node::File { digest, .. } => self.mark_blob(digest).await,
+            Node::Symlink { .. } => Ok(()),
+        }
+    }
+
+    #[instrument(skip(self), fields(directory.digest = %root_directory_digest), err)]
+    async fn mark_directory(&mut self, root_directory_digest: &B3Digest) -> Result<(), Error> {
+        // If we already saw this directory, we also saw its closure.
+        if self.reachable_directories.contains(root_directory_digest) {
+            return Ok(());
+        }
+
+        let mut directories = self.directory_service.get_recursive(root_directory_digest);
+
+        let mut found_root = false;
+        while let Some(directory) = directories.try_next().await? {
+            found_root = true;
+            self.reachable_directories.insert(directory.digest());
+
+            for (_, node) in directory.nodes() {
+                if let Node::File { digest, .. } = node {
+                    self.mark_blob(digest).await?;
+                }
+            }
+        }
+
+        if !found_root {
+            warn!("directory not found");
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip(self), fields(blob.digest = %digest), err)]
+    async fn mark_blob(&mut self, digest: &B3Digest) -> Result<(), Error> {
+        if !self.reachable_blobs.insert(digest.clone()) {
+            return Ok(());
+        }
+
+        match self.blob_service.chunks(digest).await? {
+            None => warn!("blob not found"),
+            Some(chunks) => {
+                for chunk in chunks {
+                    let chunk_digest: B3Digest = chunk.digest.try_into().map_err(|_| {
+                        Error::StorageError("invalid chunk digest length".to_string())
+                    })?;
+                    self.reachable_blobs.insert(chunk_digest);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes all directories and blobs that were not marked as reachable.
+    /// If `dry_run` is set, nothing is removed, but the result still contains
+    /// everything that would have been.
+    #[instrument(skip(self), err)]
+    pub async fn sweep(self, dry_run: bool) -> Result<SweepResult, Error> {
+        // Collect everything to remove first, so we don't modify the stores
+        // while they're still being listed.
+        let directories: Vec<B3Digest> = self
+            .directory_service
+            .list()
+            .try_filter(|digest| std::future::ready(!self.reachable_directories.contains(digest)))
+            .try_collect()
+            .await?;
+
+        let mut blobs: Vec<B3Digest> = self
+            .blob_service
+            .list()
+            .try_filter(|digest| std::future::ready(!self.reachable_blobs.contains(digest)))
+            .try_collect()
+            .await?;
+        // Some stores list the same digest twice, if it's both a blob and a chunk.
+        blobs.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        blobs.dedup();
+
+        if !dry_run {
+            // Remove directories first, so an interrupted sweep doesn't leave
+            // directories pointing to missing blobs behind.
+            for digest in &directories {
+                debug!(directory.digest = %digest, "removing directory");
+                self.directory_service.delete(digest).await?;
+            }
+            for digest in &blobs {
+                debug!(blob.digest = %digest, "removing blob");
+                self.blob_service.delete(digest).await?;
+            }
+        }
+
+        Ok(SweepResult { directories, blobs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GarbageCollector;
+    use crate::blobservice::{BlobService, MemoryBlobService};
+    use crate::directoryservice::{DirectoryService, MemoryDirectoryService};
+    use crate::fixtures::{
+        BLOB_A, BLOB_A_DIGEST, BLOB_B, BLOB_B_DIGEST, DIRECTORY_A, DIRECTORY_B, DIRECTORY_C,
+        DIRECTORY_COMPLICATED, DIRECTORY_WITH_KEEP, EMPTY_BLOB_CONTENTS, EMPTY_BLOB_DIGEST,
+    };
+    use crate::Node;
+    use tokio::io::AsyncWriteExt;
+
+    async fn put_blob(blob_service: &MemoryBlobService, contents: &[u8]) {
+        let mut w = blob_service.open_write().await;
+        w.write_all(contents).await.unwrap();
+        w.close().await.unwrap();
+    }
+
+    async fn populate() -> (MemoryBlobService, MemoryDirectoryService) {
+        let blob_service = MemoryBlobService::default();
+        let directory_service = MemoryDirectoryService::default();
+
+        put_blob(&blob_service, &BLOB_A).await;
+        put_blob(&blob_service, &BLOB_B).await;
+        put_blob(&blob_service, EMPTY_BLOB_CONTENTS).await;
+
+        for directory in [
+            &*DIRECTORY_A,
+            &*DIRECTORY_B,
+            &*DIRECTORY_C,
+            &*DIRECTORY_WITH_KEEP,
+            &*DIRECTORY_COMPLICATED,
+        ] {
+            directory_service.put(directory.clone()).await.unwrap();
+        }
+
+        (blob_service, directory_service)
+    }
+
+    /// Marking DIRECTORY_COMPLICATED keeps its closure (DIRECTORY_WITH_KEEP)
+    /// and the empty blob (.keep), and removes everything else.
+    #[tokio::test]
+    async fn sweep_unreachable() {
+        let (blob_service, directory_service) = populate().await;
+
+        let mut gc = GarbageCollector::new(&blob_service, &directory_service);
+        gc.mark(&Node::Directory {
+            digest: DIRECTORY_COMPLICATED.digest(),
+            size: DIRECTORY_COMPLICATED.size(),
+        })
+        .await
+        .expect("mark must succeed");
+
+        let mut result = gc.sweep(false).await.expect("sweep must succeed");
+        result
+            .directories
+            .sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+
+        let mut expected_directories = vec![
+            DIRECTORY_A.digest(),
+            DIRECTORY_B.digest(),
+            DIRECTORY_C.digest(),
+        ];
+        expected_directories.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        assert_eq!(expected_directories, result.directories);
+
+        let mut expected_blobs = vec![BLOB_A_DIGEST.clone(), BLOB_B_DIGEST.clone()];
+        expected_blobs.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        assert_eq!(expected_blobs, result.blobs);
+
+        // Everything reachable is still there, everything else is gone.
+        assert!(directory_service
+            .get(&DIRECTORY_WITH_KEEP.digest())
+            .await
+            .unwrap()
+            .is_some());
+        assert!(directory_service
+            .get(&DIRECTORY_A.digest())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(blob_service.has(&EMPTY_BLOB_DIGEST).await.unwrap());
+        assert!(!blob_service.has(&BLOB_A_DIGEST).await.unwrap());
+        assert!(!blob_service.has(&BLOB_B_DIGEST).await.unwrap());
+    }
+
+    /// A dry run reports the same, but doesn't remove anything.
+    #[tokio::test]
+    async fn sweep_dry_run() {
+        let (blob_service, directory_service) = populate().await;
+
+        let mut gc = GarbageCollector::new(&blob_service, &directory_service);
+        gc.mark(&Node::File {
+            digest: BLOB_A_DIGEST.clone(),
+            size: BLOB_A.len() as u64,
+            executable: false,
+        })
+        .await
+        .expect("mark must succeed");
+
+        let result = gc.sweep(true).await.expect("sweep must succeed");
+        assert_eq!(5, result.directories.len());
+        assert_eq!(2, result.blobs.len());
+        assert!(!result.blobs.contains(&BLOB_A_DIGEST));
+
+        assert!(blob_service.has(&BLOB_B_DIGEST).await.unwrap());
+        assert!(directory_service
+            .get(&DIRECTORY_A.digest())
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
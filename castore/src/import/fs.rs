@@ -25,6 +25,37 @@ use super::ingest_entries;
 use super::IngestionEntry;
 use super::IngestionError;
 
+/// A hook observing the contents of regular files while they're uploaded
+/// during ingestion, so additional digests can be calculated without reading
+/// them again.
+pub trait FileHook: Sync {
+    /// The state kept while a single file is uploaded.
+    type State: Send;
+
+    /// Called before uploading a file, with its size.
+    fn start(&self, size: u64) -> Self::State;
+
+    /// Called with each chunk of the file contents, in order.
+    fn update(&self, state: &mut Self::State, data: &[u8]);
+
+    /// Called after the file has been uploaded, with the digest of its blob.
+    /// Returning an error fails the ingestion.
+    fn finish(&self, state: Self::State, digest: &B3Digest) -> Result<(), std::io::Error>;
+}
+
+/// The hook used if none is passed, doing nothing.
+impl FileHook for () {
+    type State = ();
+
+    fn start(&self, _size: u64) {}
+
+    fn update(&self, _state: &mut (), _data: &[u8]) {}
+
+    fn finish(&self, _state: (), _digest: &B3Digest) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+}
+
 /// Ingests the contents at a given path into the tvix store, interacting with a [BlobService] and
 /// [DirectoryService]. It returns the root node or an error.
 ///
@@ -32,22 +63,49 @@ use super::IngestionError;
 ///
 /// This function will walk the filesystem using `walkdir` and will consume
 /// `O(#number of entries)` space.
+pub async fn ingest_path<BS, DS, P, P2>(
+    blob_service: BS,
+    directory_service: DS,
+    path: P,
+    reference_scanner: Option<&ReferenceScanner<P2>>,
+) -> Result<Node, IngestionError<Error>>
+where
+    P: AsRef<std::path::Path> + std::fmt::Debug,
+    BS: BlobService + Clone,
+    DS: DirectoryService,
+    P2: AsRef<[u8]> + Send + Sync,
+{
+    ingest_path_with_hook(
+        blob_service,
+        directory_service,
+        path,
+        reference_scanner,
+        &(),
+    )
+    .await
+}
+
+/// Like [ingest_path], but calls the passed [FileHook] with the contents of
+/// each regular file while uploading it.
 #[instrument(
-    skip(blob_service, directory_service, reference_scanner),
+    name = "ingest_path",
+    skip(blob_service, directory_service, reference_scanner, hook),
     fields(path),
     err
 )]
-pub async fn ingest_path<BS, DS, P, P2>(
+pub async fn ingest_path_with_hook<BS, DS, P, P2, H>(
     blob_service: BS,
     directory_service: DS,
     path: P,
     reference_scanner: Option<&ReferenceScanner<P2>>,
+    hook: &H,
 ) -> Result<Node, IngestionError<Error>>
 where
     P: AsRef<std::path::Path> + std::fmt::Debug,
     BS: BlobService + Clone,
     DS: DirectoryService,
     P2: AsRef<[u8]> + Send + Sync,
+    H: FileHook,
 {
     let span = Span::current();
 
@@ -57,8 +115,13 @@ where
         .contents_first(true)
         .into_iter();
 
-    let entries =
-        dir_entries_to_ingestion_stream(blob_service, iter, path.as_ref(), reference_scanner);
+    let entries = dir_entries_to_ingestion_stream_with_hook(
+        blob_service,
+        iter,
+        path.as_ref(),
+        reference_scanner,
+        hook,
+    );
     ingest_entries(
         directory_service,
         entries.inspect({
@@ -89,6 +152,22 @@ where
     BS: BlobService + Clone + 'a,
     I: Iterator<Item = Result<DirEntry, walkdir::Error>> + Send + 'a,
     P: AsRef<[u8]> + Send + Sync,
+{
+    dir_entries_to_ingestion_stream_with_hook(blob_service, iter, root, reference_scanner, &())
+}
+
+fn dir_entries_to_ingestion_stream_with_hook<'a, BS, I, P, H>(
+    blob_service: BS,
+    iter: I,
+    root: &'a std::path::Path,
+    reference_scanner: Option<&'a ReferenceScanner<P>>,
+    hook: &'a H,
+) -> BoxStream<'a, Result<IngestionEntry, Error>>
+where
+    BS: BlobService + Clone + 'a,
+    I: Iterator<Item = Result<DirEntry, walkdir::Error>> + Send + 'a,
+    P: AsRef<[u8]> + Send + Sync,
+    H: FileHook,
 {
     let prefix = root.parent().unwrap_or_else(|| std::path::Path::new(""));
 
@@ -99,11 +178,12 @@ where
                 async move {
                     match x {
                         Ok(dir_entry) => {
-                            dir_entry_to_ingestion_entry(
+                            dir_entry_to_ingestion_entry_with_hook(
                                 blob_service,
                                 &dir_entry,
                                 prefix,
                                 reference_scanner,
+                                hook,
                             )
                             .await
                         }
@@ -132,6 +212,22 @@ pub async fn dir_entry_to_ingestion_entry<BS, P>(
 where
     BS: BlobService,
     P: AsRef<[u8]>,
+{
+    dir_entry_to_ingestion_entry_with_hook(blob_service, entry, prefix, reference_scanner, &())
+        .await
+}
+
+async fn dir_entry_to_ingestion_entry_with_hook<BS, P, H>(
+    blob_service: BS,
+    entry: &DirEntry,
+    prefix: &std::path::Path,
+    reference_scanner: Option<&ReferenceScanner<P>>,
+    hook: &H,
+) -> Result<IngestionEntry, Error>
+where
+    BS: BlobService,
+    P: AsRef<[u8]>,
+    H: FileHook,
 {
     let file_type = entry.file_type();
 
@@ -162,15 +258,20 @@ where
             .metadata()
             .map_err(|e| Error::Stat(entry.path().to_path_buf(), e.into()))?;
 
-        let digest = upload_blob(blob_service, entry.path().to_path_buf(), reference_scanner)
-            .instrument({
-                let span = info_span!("upload_blob", "indicatif.pb_show" = tracing::field::Empty);
-                span.pb_set_message(&format!("Uploading blob for {:?}", fs_path));
-                span.pb_set_style(&tvix_tracing::PB_TRANSFER_STYLE);
+        let digest = upload_blob(
+            blob_service,
+            entry.path().to_path_buf(),
+            reference_scanner,
+            hook,
+        )
+        .instrument({
+            let span = info_span!("upload_blob", "indicatif.pb_show" = tracing::field::Empty);
+            span.pb_set_message(&format!("Uploading blob for {:?}", fs_path));
+            span.pb_set_style(&tvix_tracing::PB_TRANSFER_STYLE);
 
-                span
-            })
-            .await?;
+            span
+        })
+        .await?;
 
         Ok(IngestionEntry::Regular {
             path,
@@ -186,15 +287,17 @@ where
 }
 
 /// Uploads the file at the provided [Path] the the [BlobService].
-#[instrument(skip(blob_service, reference_scanner), fields(path), err)]
-async fn upload_blob<BS, P>(
+#[instrument(skip(blob_service, reference_scanner, hook), fields(path), err)]
+async fn upload_blob<BS, P, H>(
     blob_service: BS,
     path: impl AsRef<std::path::Path>,
     reference_scanner: Option<&ReferenceScanner<P>>,
+    hook: &H,
 ) -> Result<B3Digest, Error>
 where
     BS: BlobService,
     P: AsRef<[u8]>,
+    H: FileHook,
 {
     let span = Span::current();
     span.pb_start();
@@ -209,8 +312,10 @@ where
         .map_err(|e| Error::Stat(path.as_ref().to_path_buf(), e))?;
 
     span.pb_set_length(metadata.len());
+    let mut hook_state = hook.start(metadata.len());
     let reader = InspectReader::new(file, |d| {
         span.pb_inc(d.len() as u64);
+        hook.update(&mut hook_state, d);
     });
 
     let mut writer = blob_service.open_write().await;
@@ -230,6 +335,9 @@ where
         .await
         .map_err(|e| Error::BlobFinalize(path.as_ref().to_path_buf(), e))?;
 
+    hook.finish(hook_state, &digest)
+        .map_err(|e| Error::BlobRead(path.as_ref().to_path_buf(), e))?;
+
     Ok(digest)
 }
 
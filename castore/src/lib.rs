@@ -1,11 +1,17 @@
 mod digests;
 mod errors;
 mod hashing_reader;
+mod objectstore_url;
+mod sharded_fs;
 
+pub mod blobmetadataservice;
 pub mod blobservice;
+pub mod chunkservice;
 pub mod composition;
+pub mod copy;
 pub mod directoryservice;
 pub mod fixtures;
+pub mod gc;
 pub mod refscan;
 
 #[cfg(feature = "fs")]
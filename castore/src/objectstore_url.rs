@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use url::Url;
+
+use crate::Error;
+
+/// Parses an `objectstore+…` URL, as passed to the object_store backed
+/// services, into the URL understood by [object_store::parse_url_opts], and
+/// the options to pass along with it.
+///
+/// The query pairs are stripped from the returned URL and returned as options
+/// instead, as they might contain credentials or local settings we don't want
+/// to send as-is.
+pub(crate) fn parse(url: &Url) -> Result<(String, HashMap<String, String>), Error> {
+    // We need to convert the URL to string, strip the prefix there, and then
+    // parse it back as url, as Url::set_scheme() rejects some of the transitions we want to do.
+    let mut trimmed_url = Url::parse(
+        url.as_str()
+            .strip_prefix("objectstore+")
+            .ok_or(Error::StorageError("Missing objectstore uri".into()))?,
+    )
+    .map_err(|e| Error::StorageError(format!("invalid objectstore uri: {e}")))?;
+    trimmed_url.set_query(None);
+
+    Ok((
+        trimmed_url.into(),
+        url.query_pairs()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    #[test]
+    fn parse() {
+        let (url, options) = super::parse(
+            &Url::parse("objectstore+s3://bucket/some/path?aws_region=eu-west-1").unwrap(),
+        )
+        .expect("must parse");
+
+        assert_eq!("s3://bucket/some/path", url);
+        assert_eq!(
+            Some("eu-west-1"),
+            options.get("aws_region").map(String::as_str)
+        );
+    }
+
+    #[test]
+    fn parse_missing_prefix() {
+        super::parse(&Url::parse("s3://bucket/some/path").unwrap())
+            .expect_err("must fail without objectstore+ prefix");
+    }
+}
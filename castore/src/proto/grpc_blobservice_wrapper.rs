@@ -1,23 +1,37 @@
 use crate::blobservice::BlobService;
+use crate::B3Digest;
 use core::pin::pin;
 use data_encoding::BASE64;
-use futures::{stream::BoxStream, TryFutureExt};
+use futures::{
+    stream::{BoxStream, TryReadyChunksError},
+    TryFutureExt, TryStreamExt,
+};
 use std::{
     collections::VecDeque,
+    io::{self, SeekFrom},
     ops::{Deref, DerefMut},
+    sync::Arc,
 };
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
 use tokio_stream::StreamExt;
 use tokio_util::io::ReaderStream;
 use tonic::{async_trait, Request, Response, Status, Streaming};
 use tracing::{instrument, warn};
 
+/// The maximum number of digests [GRPCBlobServiceWrapper] passes to a single
+/// [BlobService::has_many] call when answering a HasMany request.
+const HAS_MANY_BATCH_SIZE: usize = 1000;
+
 pub struct GRPCBlobServiceWrapper<T> {
-    blob_service: T,
+    // Kept in an [Arc], so the response stream of HasMany can hold on to it.
+    blob_service: Arc<T>,
 }
 
 impl<T> GRPCBlobServiceWrapper<T> {
     pub fn new(blob_service: T) -> Self {
-        Self { blob_service }
+        Self {
+            blob_service: Arc::new(blob_service),
+        }
     }
 }
 
@@ -86,6 +100,7 @@ where
 {
     // https://github.com/tokio-rs/tokio/issues/2723#issuecomment-1534723933
     type ReadStream = BoxStream<'static, Result<super::BlobChunk, Status>>;
+    type HasManyStream = BoxStream<'static, Result<super::HasBlobResponse, Status>>;
 
     #[instrument(skip_all, fields(blob.digest=format!("b3:{}", BASE64.encode(&request.get_ref().digest))))]
     async fn stat(
@@ -111,7 +126,7 @@ where
         }
     }
 
-    #[instrument(skip_all, fields(blob.digest=format!("b3:{}", BASE64.encode(&request.get_ref().digest))))]
+    #[instrument(skip_all, fields(blob.digest=format!("b3:{}", BASE64.encode(&request.get_ref().digest)), blob.offset=request.get_ref().offset, blob.length=request.get_ref().length))]
     async fn read(
         &self,
         request: Request<super::ReadBlobRequest>,
@@ -124,7 +139,25 @@ where
             .map_err(|_e| Status::invalid_argument("invalid digest length"))?;
 
         match self.blob_service.open_read(&req_digest).await {
-            Ok(Some(r)) => {
+            Ok(Some(mut r)) => {
+                if rq.offset != 0 {
+                    r.seek(SeekFrom::Start(rq.offset)).await.map_err(|e| {
+                        // Some BlobReaders refuse to seek past the end.
+                        if e.kind() == io::ErrorKind::InvalidInput {
+                            Status::out_of_range(format!("invalid offset {}", rq.offset))
+                        } else {
+                            warn!(err=%e, "failed to seek");
+                            e.into()
+                        }
+                    })?;
+                }
+
+                let r: Box<dyn AsyncRead + Send + Unpin> = if rq.length != 0 {
+                    Box::new(r.take(rq.length))
+                } else {
+                    Box::new(r)
+                };
+
                 let chunks_stream =
                     ReaderStream::new(r).map(|chunk| Ok(super::BlobChunk { data: chunk? }));
                 Ok(Response::new(Box::pin(chunks_stream)))
@@ -172,4 +205,42 @@ where
             digest: digest.into(),
         }))
     }
+
+    #[instrument(skip_all)]
+    async fn has_many(
+        &self,
+        request: Request<Streaming<super::HasBlobRequest>>,
+    ) -> Result<Response<Self::HasManyStream>, Status> {
+        // Check the digests in batches of whatever the client already sent,
+        // so the BlobService can look them up together, without having to
+        // receive the whole request stream first.
+        let blob_service = self.blob_service.clone();
+        let stream = request
+            .into_inner()
+            .map_err(Status::from)
+            .and_then(|req| async move {
+                B3Digest::try_from(req.digest)
+                    .map_err(|_e| Status::invalid_argument("invalid digest length"))
+            })
+            .try_ready_chunks(HAS_MANY_BATCH_SIZE)
+            .map_err(|TryReadyChunksError(_, e)| e)
+            .and_then(move |digests| {
+                let blob_service = blob_service.clone();
+                async move {
+                    let exists = blob_service.has_many(&digests).await.map_err(|e| {
+                        warn!(err=%e, "failed to call has_many");
+                        Status::from(e)
+                    })?;
+
+                    Ok(futures::stream::iter(
+                        exists
+                            .into_iter()
+                            .map(|exists| Ok(super::HasBlobResponse { exists })),
+                    ))
+                }
+            })
+            .try_flatten();
+
+        Ok(Response::new(Box::pin(stream)))
+    }
 }
@@ -0,0 +1,203 @@
+//! Helpers for storing content-addressed objects in a local directory,
+//! shared by [crate::blobservice::FsBlobService] and
+//! [crate::directoryservice::FsDirectoryService].
+//!
+//! Objects are stored at `${root}/${namespace}/b3/$digest_key`, with the
+//! blake3 digest encoded in lower hex and sharded after the second byte, the
+//! same way [crate::blobservice::ObjectStoreBlobService] does.
+//!
+//! Writes go to a temporary file in `${root}/tmp` first, which is renamed into
+//! place once complete, so readers never observe partially written objects.
+//! The content type of each object is stored in the `user.mime_type` extended
+//! attribute, which is set before the rename.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use async_stream::try_stream;
+use data_encoding::HEXLOWER;
+use futures::stream::BoxStream;
+use tracing::{instrument, Level};
+use xattr::FileExt;
+
+use crate::B3Digest;
+
+/// The extended attribute holding the content type of an object.
+const CONTENT_TYPE_XATTR: &str = "user.mime_type";
+
+#[derive(Clone, Debug)]
+pub(crate) struct ShardedFs {
+    root: PathBuf,
+}
+
+impl ShardedFs {
+    /// Opens the given directory, creating it (and the temporary directory
+    /// inside of it) if it doesn't exist yet.
+    pub(crate) async fn open(root: PathBuf) -> io::Result<Self> {
+        if root == std::path::Path::new("/") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cowardly refusing to use / as root",
+            ));
+        }
+
+        tokio::fs::create_dir_all(root.join("tmp")).await?;
+
+        Ok(Self { root })
+    }
+
+    #[instrument(level=Level::TRACE, skip(self, digest), fields(digest=%digest), ret(Debug))]
+    fn path(&self, namespace: &str, digest: &B3Digest) -> PathBuf {
+        self.root
+            .join(namespace)
+            .join("b3")
+            .join(HEXLOWER.encode(&digest.as_slice()[..2]))
+            .join(HEXLOWER.encode(digest.as_slice()))
+    }
+
+    /// Checks whether an object exists.
+    pub(crate) async fn exists(&self, namespace: &str, digest: &B3Digest) -> io::Result<bool> {
+        tokio::fs::try_exists(self.path(namespace, digest)).await
+    }
+
+    /// Opens an object for reading, returning the file and its content type.
+    pub(crate) async fn open_read(
+        &self,
+        namespace: &str,
+        digest: &B3Digest,
+    ) -> io::Result<Option<(File, String)>> {
+        let path = self.path(namespace, digest);
+
+        tokio::task::spawn_blocking(move || {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            };
+
+            // Query the xattr on the opened file, not the path, so we get the
+            // one belonging to the contents we read.
+            let content_type = file
+                .get_xattr(CONTENT_TYPE_XATTR)?
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} has no content type", path.display()),
+                    )
+                })
+                .and_then(|content_type| {
+                    String::from_utf8(content_type)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                })?;
+
+            Ok(Some((file, content_type)))
+        })
+        .await?
+    }
+
+    /// Reads an entire object into memory, returning its contents and content
+    /// type.
+    pub(crate) async fn get(
+        &self,
+        namespace: &str,
+        digest: &B3Digest,
+    ) -> io::Result<Option<(Vec<u8>, String)>> {
+        let Some((mut file, content_type)) = self.open_read(namespace, digest).await? else {
+            return Ok(None);
+        };
+
+        let data = tokio::task::spawn_blocking(move || {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            Ok::<_, io::Error>(data)
+        })
+        .await??;
+
+        Ok(Some((data, content_type)))
+    }
+
+    /// Atomically writes an object with the given content type.
+    /// Existing objects with the same digest are replaced.
+    pub(crate) async fn put(
+        &self,
+        namespace: &str,
+        digest: &B3Digest,
+        data: Vec<u8>,
+        content_type: &'static str,
+    ) -> io::Result<()> {
+        let path = self.path(namespace, digest);
+        let tmp_dir = self.root.join("tmp");
+
+        tokio::task::spawn_blocking(move || {
+            let shard_dir = path.parent().expect("path must have parent");
+            let new_shard_dir = !shard_dir.exists();
+            std::fs::create_dir_all(shard_dir)?;
+
+            // The temporary file is removed on drop if it hasn't been persisted.
+            let mut tmp_file = tempfile::NamedTempFile::new_in(tmp_dir)?;
+            tmp_file.write_all(&data)?;
+            tmp_file
+                .as_file()
+                .set_xattr(CONTENT_TYPE_XATTR, content_type.as_bytes())?;
+            // Make sure the contents hit the disk before the rename, so a
+            // crash can't leave an empty or truncated object in place.
+            tmp_file.as_file().sync_all()?;
+            tmp_file.persist(&path)?;
+
+            // Make sure the rename itself (and the shard directory, if it was
+            // just created) hits the disk too.
+            std::fs::File::open(shard_dir)?.sync_all()?;
+            if new_shard_dir {
+                std::fs::File::open(shard_dir.parent().expect("shard must have parent"))?
+                    .sync_all()?;
+            }
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Removes an object. Removing an object that doesn't exist is not an
+    /// error.
+    pub(crate) async fn delete(&self, namespace: &str, digest: &B3Digest) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(namespace, digest)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Lists the digests of all objects in a namespace.
+    pub(crate) fn list(&self, namespace: &str) -> BoxStream<'static, io::Result<B3Digest>> {
+        let prefix = self.root.join(namespace).join("b3");
+
+        Box::pin(try_stream! {
+            let mut shards = match tokio::fs::read_dir(&prefix).await {
+                Ok(shards) => Some(shards),
+                // nothing was written to this namespace yet.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => Err(e)?,
+            };
+
+            while let Some(shard) = match shards.as_mut() {
+                Some(shards) => shards.next_entry().await?,
+                None => None,
+            } {
+                let mut entries = tokio::fs::read_dir(shard.path()).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    yield entry
+                        .file_name()
+                        .to_str()
+                        .and_then(|filename| HEXLOWER.decode(filename.as_bytes()).ok())
+                        .and_then(|digest| B3Digest::try_from(digest).ok())
+                        .ok_or_else(|| {
+                            io::Error::other(format!(
+                                "unexpected file: {}",
+                                entry.path().display()
+                            ))
+                        })?;
+                }
+            }
+        })
+    }
+}
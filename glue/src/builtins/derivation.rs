@@ -3,7 +3,7 @@ use crate::builtins::DerivationError;
 use crate::known_paths::KnownPaths;
 use crate::tvix_store_io::TvixStoreIO;
 use bstr::BString;
-use nix_compat::derivation::{Derivation, Output};
+use nix_compat::derivation::{Derivation, Output, OutputKind};
 use nix_compat::nixhash;
 use nix_compat::store_path::{StorePath, StorePathRef};
 use std::collections::{btree_map, BTreeSet};
@@ -17,6 +17,8 @@ use tvix_eval::{
 // Constants used for strangely named fields in derivation inputs.
 const STRUCTURED_ATTRS: &str = "__structuredAttrs";
 const IGNORE_NULLS: &str = "__ignoreNulls";
+const CONTENT_ADDRESSED: &str = "__contentAddressed";
+const IMPURE: &str = "__impure";
 
 /// Populate the inputs of a derivation from the build references
 /// found when scanning the derivation's parameters and extracting their contexts.
@@ -142,13 +144,14 @@ fn handle_fixed_output(
             "out".to_string(),
             Output {
                 path: None,
-                ca_hash: match hash_mode_str.as_deref() {
-                    None | Some("flat") => Some(nixhash::CAHash::Flat(nixhash)),
-                    Some("recursive") => Some(nixhash::CAHash::Nar(nixhash)),
+                kind: OutputKind::Fixed(match hash_mode_str.as_deref() {
+                    None | Some("flat") => nixhash::CAHash::Flat(nixhash),
+                    Some("recursive") => nixhash::CAHash::Nar(nixhash),
+                    Some("git") => nixhash::CAHash::Git(nixhash),
                     Some(other) => {
                         return Err(DerivationError::InvalidOutputHashMode(other.to_string()))?
                     }
-                },
+                }),
             },
         );
 
@@ -165,6 +168,40 @@ fn handle_fixed_output(
     Ok(None)
 }
 
+/// Populate the outputs of content-addressed (`__contentAddressed = true`) or
+/// impure (`__impure = true`) derivations, whose hash is only known after
+/// building them.
+///
+/// outputHashAlgo defaults to sha256, and outputHashMode to recursive.
+fn handle_floating_outputs(
+    drv: &mut Derivation,
+    impure: bool,
+    hash_algo_str: Option<String>, // in nix: outputHashAlgo
+    hash_mode_str: Option<String>, // in nix: outputHashMode
+) -> Result<(), ErrorKind> {
+    let algo = match hash_algo_str.as_deref() {
+        None | Some("") => nixhash::HashAlgo::Sha256,
+        Some(s) => nixhash::HashAlgo::try_from(s).map_err(DerivationError::InvalidOutputHash)?,
+    };
+    let mode = match hash_mode_str.as_deref() {
+        Some("flat") => nixhash::CAHashMode::Flat,
+        None | Some("recursive") => nixhash::CAHashMode::Nar,
+        Some("text") => nixhash::CAHashMode::Text,
+        Some("git") => nixhash::CAHashMode::Git,
+        Some(other) => Err(DerivationError::InvalidOutputHashMode(other.to_string()))?,
+    };
+
+    for output in drv.outputs.values_mut() {
+        output.kind = if impure {
+            OutputKind::Impure { mode, algo }
+        } else {
+            OutputKind::Floating { mode, algo }
+        };
+    }
+
+    Ok(())
+}
+
 #[builtins(state = "Rc<TvixStoreIO>")]
 pub(crate) mod derivation_builtins {
     use std::collections::BTreeMap;
@@ -177,7 +214,7 @@ pub(crate) mod derivation_builtins {
     use bstr::ByteSlice;
     use md5::Digest;
     use nix_compat::nixhash::CAHash;
-    use nix_compat::store_path::{build_ca_path, hash_placeholder};
+    use nix_compat::store_path::{build_ca_path, downstream_placeholder, hash_placeholder};
     use sha2::Sha256;
     use tvix_castore::Node;
     use tvix_eval::generators::Gen;
@@ -262,6 +299,11 @@ pub(crate) mod derivation_builtins {
                 None => None,
             };
 
+        // Set if the derivation is content-addressed or impure, in which case
+        // the hash of its outputs is only known after building.
+        let mut content_addressed = false;
+        let mut impure = false;
+
         // Look at the arguments passed to builtins.derivationStrict.
         // Some set special fields in the Derivation struct, some change
         // behaviour of other functionality.
@@ -275,6 +317,17 @@ pub(crate) mod derivation_builtins {
                 continue;
             }
 
+            // CONTENT_ADDRESSED and IMPURE are not passed to the builder if
+            // they're set to true.
+            if arg_name == CONTENT_ADDRESSED && value.as_bool()? {
+                content_addressed = true;
+                continue;
+            }
+            if arg_name == IMPURE && value.as_bool()? {
+                impure = true;
+                continue;
+            }
+
             match arg_name {
                 // Command line arguments to the builder.
                 // These are only set in drv.arguments.
@@ -427,7 +480,12 @@ pub(crate) mod derivation_builtins {
                 Ok(s) => s,
             };
 
-            if let Some(warning) =
+            if output_hash.is_none() && (content_addressed || impure) {
+                if content_addressed && impure {
+                    return Err(DerivationError::ContentAddressedAndImpure)?;
+                }
+                handle_floating_outputs(&mut drv, impure, output_hash_algo, output_hash_mode)?;
+            } else if let Some(warning) =
                 handle_fixed_output(&mut drv, output_hash, output_hash_algo, output_hash_mode)?
             {
                 emit_warning_kind(&co, warning).await;
@@ -438,13 +496,20 @@ pub(crate) mod derivation_builtins {
         // point initialised as an empty string, as the ATerm serialization of that is later
         // used for the output path calculation (which will also update output
         // paths post-calculation, both in drv.environment and drv.outputs)
-        for output in drv.outputs.keys() {
+        // Outputs only known after building are set to their placeholder
+        // instead.
+        for (output_name, output) in drv.outputs.iter() {
+            let value = if output.is_deferred() {
+                hash_placeholder(output_name)
+            } else {
+                String::new()
+            };
             if drv
                 .environment
-                .insert(output.to_string(), String::new().into())
+                .insert(output_name.to_string(), value.into())
                 .is_some()
             {
-                emit_warning_kind(&co, WarningKind::ShadowedOutput(output.to_string())).await;
+                emit_warning_kind(&co, WarningKind::ShadowedOutput(output_name.to_string())).await;
             }
         }
 
@@ -459,6 +524,14 @@ pub(crate) mod derivation_builtins {
         let mut known_paths = state.as_ref().known_paths.borrow_mut();
         populate_inputs(&mut drv, input_context, &known_paths);
 
+        // Outputs depending on outputs only known after building are deferred.
+        drv.defer_outputs(|drv_path| {
+            known_paths
+                .get_drv_by_drvpath(&drv_path.to_owned())
+                .unwrap_or_else(|| panic!("{} not found", drv_path))
+                .is_deferred()
+        });
+
         // At this point, derivation fields are fully populated from
         // eval data structures.
         drv.validate(false)
@@ -500,7 +573,11 @@ pub(crate) mod derivation_builtins {
                                 derivation: drv_path.to_absolute_path(),
                             }
                             .into(),
-                            output.path.as_ref().unwrap().to_absolute_path(),
+                            match &output.path {
+                                Some(path) => path.to_absolute_path(),
+                                // The path is only known after building.
+                                None => downstream_placeholder(&drv_path, name),
+                            },
                         ),
                     )
                 })
@@ -21,8 +21,10 @@ pub enum DerivationError {
     InvalidDerivation(#[from] nix_compat::derivation::DerivationError),
     #[error("invalid output hash: {0}")]
     InvalidOutputHash(#[from] nixhash::Error),
-    #[error("invalid output hash mode: '{0}', only 'recursive' and 'flat` are supported")]
+    #[error("invalid output hash mode: '{0}', only 'flat', 'recursive', 'git' and, for floating outputs, 'text' are supported")]
     InvalidOutputHashMode(String),
+    #[error("derivation cannot be both content-addressed and impure")]
+    ContentAddressedAndImpure,
 }
 
 impl From<DerivationError> for tvix_eval::ErrorKind {
@@ -208,6 +208,7 @@ mod tests {
     #[case::invalid_outputhash(r#"(builtins.derivation { name = "foo"; builder = "/bin/sh"; system = "x86_64-linux"; outputHashMode = "recursive"; outputHashAlgo = "sha256"; outputHash = "sha256-00"; }).outPath"#)]
     #[case::sha1_and_sha256(r#"(builtins.derivation { name = "foo"; builder = "/bin/sh"; system = "x86_64-linux"; outputHashMode = "recursive"; outputHashAlgo = "sha1"; outputHash = "sha256-Q3QXOoy+iN4VK2CflvRulYvPZXYgF0dO7FoF7CvWFTA="; }).outPath"#)]
     #[case::duplicate_output_names(r#"(builtins.derivation { name = "foo"; builder = "/bin/sh"; outputs = ["foo" "foo"]; system = "x86_64-linux"; }).outPath"#)]
+    #[case::content_addressed_and_impure(r#"(builtins.derivation { name = "foo"; builder = "/bin/sh"; system = "x86_64-linux"; __contentAddressed = true; __impure = true; }).outPath"#)]
     fn test_outpath_invalid(#[case] code: &str) {
         let resp = eval(code);
         assert!(resp.value.is_none(), "Value should be None");
@@ -217,6 +218,61 @@ mod tests {
         );
     }
 
+    /// The outputs of content-addressed and impure derivations, and of
+    /// derivations depending on them, are only known after building.
+    /// Placeholders are returned instead of their output paths, computed
+    /// from the derivation path like Nix' `DownstreamPlaceholder`.
+    /// The expected values were calculated by following Nix' algorithm for
+    /// the ATerm of each derivation, not by running Nix.
+    #[rstest]
+    #[case::content_addressed(
+        r#"(builtins.derivation { name = "foo"; builder = ":"; system = ":"; __contentAddressed = true; }).outPath"#,
+        // drvPath: /nix/store/asqvh5kd8syak2nap6qfby2kzhad93ln-foo.drv
+        "/0va4qp2ahx6mzdj5jv1rmd902hpfaiqqqiacifnckwnv2ab0356k"
+    )]
+    #[case::content_addressed_text(
+        r#"(builtins.derivation { name = "foo"; builder = ":"; system = ":"; __contentAddressed = true; outputHashMode = "text"; }).outPath"#,
+        // drvPath: /nix/store/7wp8yw23fkb2am4fq84xa118754nvaxk-foo.drv
+        "/1l66wja4mhs9rvz30qnr8a5xzfca0bycns6qqxrdrj3kkiipc0q4"
+    )]
+    #[case::impure(
+        r#"(builtins.derivation { name = "foo"; builder = ":"; system = ":"; __impure = true; }).outPath"#,
+        // drvPath: /nix/store/kxf0wsv4s2sq32qf8babggax9dvv970r-foo.drv
+        "/0fdh6nchbj3w1s0dzdxb44b0cnypwzx7fz5lk4v46603phqkx69y"
+    )]
+    #[case::deferred(
+        r#"
+                   let
+                     bar = builtins.derivation {
+                       name = "bar";
+                       builder = ":";
+                       system = ":";
+                       __contentAddressed = true;
+                     };
+                   in
+                   (builtins.derivation {
+                     name = "foo";
+                     builder = ":";
+                     system = ":";
+                     inherit bar;
+                   }).outPath
+        "#,
+        // drvPath: /nix/store/kmb558kg3m47b5df8ibszkhzpl6n4a3c-foo.drv,
+        // with bar set to the placeholder of
+        // /nix/store/hzgl0nhxn6i7rl0f9rmh8na0b76s4j5m-bar.drv.
+        "/199zgj7i4mrhfbm7p1msi2kw25kwma7zyx5sjaxksvrgj1phshir"
+    )]
+    fn test_outpath_placeholder(#[case] code: &str, #[case] expected_placeholder: &str) {
+        let value = eval(code).value.expect("must succeed");
+
+        match value {
+            tvix_eval::Value::String(s) => {
+                assert_eq!(expected_placeholder.as_bytes(), s.as_bytes());
+            }
+            _ => panic!("unexpected value type: {:?}", value),
+        }
+    }
+
     /// Construct two FODs with the same name, and same known output (but
     /// slightly different recipe), ensure they have the same output hash.
     #[test]
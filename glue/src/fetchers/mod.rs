@@ -569,7 +569,9 @@ where
                 .calculate_nar(&node)
                 .await
                 .map_err(|e| FetcherError::Io(e.into()))?,
-            CAHash::Text(_) => unreachable!("Tvix bug: fetch returned CAHash::Text"),
+            CAHash::Text(_) | CAHash::Git(_) => {
+                unreachable!("Tvix bug: fetch returned CAHash::{:?}", ca_hash.mode())
+            }
         };
 
         // Construct the PathInfo and persist it.
@@ -24,7 +24,7 @@ pub(crate) fn fetchurl_derivation_to_fetch(drv: &Derivation) -> Result<(String,
         return Err(Error::NoFOD);
     }
     let out_output = &drv.outputs.get("out").ok_or(Error::NoFOD)?;
-    let ca_hash = out_output.ca_hash.clone().ok_or(Error::NoFOD)?;
+    let ca_hash = out_output.ca_hash().cloned().ok_or(Error::NoFOD)?;
 
     let name: String = drv
         .environment
@@ -58,6 +58,8 @@ pub(crate) fn fetchurl_derivation_to_fetch(drv: &Derivation) -> Result<(String,
         }
         // you can't construct derivations containing this
         CAHash::Text(_) => panic!("Tvix bug: got CaHash::Text in drv"),
+        // builtin:fetchurl doesn't support git hashing.
+        CAHash::Git(_) => Err(Error::GitHashing),
     }
 }
 
@@ -79,4 +81,6 @@ pub(crate) enum Error {
     NameMissing,
     #[error("Name invalid")]
     NameInvalid,
+    #[error("Git hashing is not supported")]
+    GitHashing,
 }
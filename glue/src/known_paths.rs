@@ -84,9 +84,14 @@ impl KnownPaths {
 
         // For all output paths, update our lookup table.
         // We only write into the lookup table once.
-        for output in drv.outputs.values() {
+        // Outputs of deferred derivations don't have a path yet.
+        for output_path in drv
+            .outputs
+            .values()
+            .filter_map(|output| output.path.as_ref())
+        {
             self.outputs_to_drvpath
-                .entry(output.path.as_ref().expect("missing store path").clone())
+                .entry(output_path.clone())
                 .or_insert(drv_path.to_owned());
         }
 
@@ -0,0 +1,194 @@
+//! Authentication for the routes modifying the store.
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::headers::{
+    authorization::{Basic, Bearer},
+    Authorization, HeaderMapExt,
+};
+use data_encoding::BASE64;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::Path,
+};
+use tracing::warn;
+
+use crate::AppState;
+
+/// The credentials accepted for uploads.
+/// Secrets are only kept as hashes, so comparing them doesn't leak them via
+/// timing.
+#[derive(Debug, Default)]
+pub struct WriteAuth {
+    /// SHA-256 digests of the accepted bearer tokens.
+    bearer_tokens: HashSet<[u8; 32]>,
+    /// SHA-1 digests of passwords, keyed by user name.
+    basic_users: HashMap<String, [u8; 20]>,
+}
+
+impl WriteAuth {
+    /// Reads bearer tokens from a file, one per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn add_bearer_tokens_file(&mut self, path: &Path) -> io::Result<()> {
+        self.add_bearer_tokens(&std::fs::read_to_string(path)?);
+        Ok(())
+    }
+
+    pub(crate) fn add_bearer_tokens(&mut self, s: &str) {
+        for line in non_comment_lines(s) {
+            self.bearer_tokens.insert(Sha256::digest(line).into());
+        }
+    }
+
+    /// Reads users from a htpasswd file.
+    /// Only SHA-1 password hashes are supported (`htpasswd -s`). These are
+    /// unsalted, so a leaked file is easy to brute-force unless the passwords
+    /// are long and random. Prefer bearer tokens where possible.
+    pub fn add_htpasswd_file(&mut self, path: &Path) -> io::Result<()> {
+        self.add_htpasswd(&std::fs::read_to_string(path)?)
+    }
+
+    fn add_htpasswd(&mut self, s: &str) -> io::Result<()> {
+        for line in non_comment_lines(s) {
+            let (user, password_hash) = line
+                .split_once(':')
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing separator"))?;
+
+            let password_digest = password_hash
+                .strip_prefix("{SHA}")
+                .and_then(|b64| BASE64.decode(b64.as_bytes()).ok())
+                .and_then(|digest| <[u8; 20]>::try_from(digest).ok())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unsupported password hash for user {user}"),
+                    )
+                })?;
+
+            self.basic_users.insert(user.to_string(), password_digest);
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the Authorization header carries accepted credentials.
+    fn is_authorized(&self, parts: &Parts) -> bool {
+        if let Some(Authorization(bearer)) = parts.headers.typed_get::<Authorization<Bearer>>() {
+            let digest: [u8; 32] = Sha256::digest(bearer.token()).into();
+            return self.bearer_tokens.contains(&digest);
+        }
+
+        if let Some(Authorization(basic)) = parts.headers.typed_get::<Authorization<Basic>>() {
+            let digest: [u8; 20] = Sha1::digest(basic.password()).into();
+            return self.basic_users.get(basic.username()) == Some(&digest);
+        }
+
+        false
+    }
+}
+
+fn non_comment_lines(s: &str) -> impl Iterator<Item = &str> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Extractor ensuring the request is allowed to modify the store.
+/// Requests are rejected with 401 if authentication is configured, and the
+/// request doesn't carry accepted credentials.
+#[derive(Debug)]
+pub struct WriteAuthorized;
+
+#[async_trait]
+impl FromRequestParts<AppState> for WriteAuthorized {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        match &state.write_auth {
+            Some(write_auth) if !write_auth.is_authorized(parts) => {
+                warn!("unauthorized upload");
+                Err((
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, "Basic realm=\"nar-bridge\"")],
+                )
+                    .into_response())
+            }
+            _ => Ok(WriteAuthorized),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, request::Parts, Request};
+    use rstest::rstest;
+
+    use super::WriteAuth;
+
+    /// `htpasswd -nbs alice secret`
+    const HTPASSWD: &str = "# users allowed to upload\n\nalice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n";
+
+    fn parts(authorization: Option<&str>) -> Parts {
+        let mut request = Request::builder();
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        request.body(()).unwrap().into_parts().0
+    }
+
+    fn write_auth() -> WriteAuth {
+        let mut write_auth = WriteAuth::default();
+        write_auth.add_bearer_tokens("# comment\n\n  token1  \ntoken2\n");
+        write_auth.add_htpasswd(HTPASSWD).expect("must parse");
+        write_auth
+    }
+
+    #[test]
+    fn parse_bearer_tokens() {
+        let write_auth = write_auth();
+        assert_eq!(2, write_auth.bearer_tokens.len());
+    }
+
+    #[test]
+    fn parse_htpasswd() {
+        let write_auth = write_auth();
+        assert_eq!(1, write_auth.basic_users.len());
+        assert!(write_auth.basic_users.contains_key("alice"));
+    }
+
+    #[rstest]
+    #[case::missing_separator("alice{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=")]
+    #[case::bcrypt("alice:$2y$05$c4WoMPo3SXsafkva.HHa6uXQZWr7oboPiC2bT/r7q1BB8I2s0BRqC")]
+    #[case::apr1("alice:$apr1$uDbYMIW7$8mZEWHSRPyx1MNjXe0LR81")]
+    #[case::crypt("alice:rqXexS6ZhobKA")]
+    #[case::invalid_base64("alice:{SHA}not base64")]
+    #[case::wrong_length("alice:{SHA}c2VjcmV0")]
+    fn parse_htpasswd_invalid(#[case] line: &str) {
+        WriteAuth::default()
+            .add_htpasswd(line)
+            .expect_err("must fail");
+    }
+
+    #[rstest]
+    #[case::bearer(Some("Bearer token1"), true)]
+    #[case::bearer_second(Some("Bearer token2"), true)]
+    #[case::bearer_wrong(Some("Bearer token3"), false)]
+    #[case::bearer_comment(Some("Bearer # comment"), false)]
+    #[case::basic(Some("Basic YWxpY2U6c2VjcmV0"), true)] // alice:secret
+    #[case::basic_wrong_password(Some("Basic YWxpY2U6d3Jvbmc="), false)] // alice:wrong
+    #[case::basic_wrong_user(Some("Basic Ym9iOnNlY3JldA=="), false)] // bob:secret
+    #[case::basic_token_as_password(Some("Basic YWxpY2U6dG9rZW4x"), false)] // alice:token1
+    #[case::none(None, false)]
+    fn is_authorized(#[case] authorization: Option<&str>, #[case] expected: bool) {
+        assert_eq!(expected, write_auth().is_authorized(&parts(authorization)));
+    }
+}
@@ -1,6 +1,8 @@
 use clap::Parser;
 use mimalloc::MiMalloc;
-use nar_bridge::AppState;
+use nar_bridge::{AppState, NarCompression, WriteAuth};
+use nix_compat::narinfo::VerifyingKey;
+use std::path::PathBuf;
 use tower::ServiceBuilder;
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
 use tracing::info;
@@ -16,11 +18,52 @@ struct Cli {
     #[clap(flatten)]
     service_addrs: ServiceUrlsGrpc,
 
+    /// Where build logs are stored.
+    /// They are kept in memory only by default.
+    /// Ignored if the store composition has a "default" buildlogservice.
+    #[arg(long, env, default_value = "memory://")]
+    build_log_service_addr: String,
+
+    /// Where realisations of content-addressed derivation outputs are stored.
+    /// They are kept in memory only by default.
+    /// Ignored if the store composition has a "default" realisationservice.
+    #[arg(long, env, default_value = "memory://")]
+    realisation_service_addr: String,
+
     /// The priority to announce at the `nix-cache-info` endpoint.
     /// A lower number means it's *more preferred.
     #[arg(long, env, default_value_t = 39)]
     priority: u64,
 
+    /// The compression to apply to served NARs.
+    /// Independently of this, uploaded NARs may be compressed with xz, zstd,
+    /// bzip2 or gzip.
+    #[arg(long, env, value_enum, default_value_t = NarCompression::None)]
+    nar_compression: NarCompression,
+
+    /// Disable uploading NARs and NARInfo files.
+    #[arg(long, env)]
+    read_only: bool,
+
+    /// Path to a file containing bearer tokens, one per line.
+    /// If this or --htpasswd-file is set, uploads need to be authenticated.
+    #[arg(long, env)]
+    bearer_tokens_file: Option<PathBuf>,
+
+    /// Path to a htpasswd file with users allowed to upload, using basic auth.
+    /// Only SHA-1 password hashes (`htpasswd -s`) are supported, bcrypt and
+    /// MD5 ones are rejected. SHA-1 hashes are unsalted, so use long random
+    /// passwords, or prefer --bearer-tokens-file.
+    #[arg(long, env)]
+    htpasswd_file: Option<PathBuf>,
+
+    /// Space-separated list of public keys, in the `trusted-public-keys`
+    /// format used by Nix.
+    /// If set, uploaded NARInfo files and realisations need to have a correct
+    /// signature by at least one of these.
+    #[arg(long, env, value_delimiter = ' ')]
+    trusted_public_keys: Option<Vec<String>>,
+
     /// The address to listen on.
     #[clap(flatten)]
     listen_args: tokio_listener::ListenerAddressLFlag,
@@ -48,12 +91,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     };
 
     // initialize stores
+    let mut configs = tvix_store::utils::addrs_to_configs(cli.service_addrs).await?;
+    let (build_log_service, realisation_service) =
+        tvix_store::utils::construct_build_log_and_realisation_services(
+            &mut configs,
+            &cli.build_log_service_addr,
+            &cli.realisation_service_addr,
+        )
+        .await?;
     let (blob_service, directory_service, path_info_service, _nar_calculation_service) =
-        tvix_store::utils::construct_services(cli.service_addrs).await?;
+        tvix_store::utils::construct_services_from_configs(configs).await?;
+
+    let mut state = AppState::new(
+        blob_service,
+        directory_service,
+        path_info_service,
+        build_log_service,
+        realisation_service,
+        cli.nar_compression,
+    );
+
+    if cli.bearer_tokens_file.is_some() || cli.htpasswd_file.is_some() {
+        let mut write_auth = WriteAuth::default();
+        if let Some(path) = &cli.bearer_tokens_file {
+            write_auth.add_bearer_tokens_file(path)?;
+        }
+        if let Some(path) = &cli.htpasswd_file {
+            write_auth.add_htpasswd_file(path)?;
+        }
+        state.set_write_auth(write_auth);
+    }
 
-    let state = AppState::new(blob_service, directory_service, path_info_service);
+    if let Some(public_keys) = &cli.trusted_public_keys {
+        state.set_public_keys(
+            public_keys
+                .iter()
+                .map(|pubkey_str| VerifyingKey::parse(pubkey_str))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+    }
 
-    let app = nar_bridge::gen_router(cli.priority)
+    let app = nar_bridge::gen_router(cli.priority, cli.read_only)
         .layer(
             ServiceBuilder::new()
                 .layer(
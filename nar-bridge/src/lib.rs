@@ -1,19 +1,31 @@
 use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::response::{IntoResponse, Response};
 use axum::routing::{head, put};
 use axum::{routing::get, Router};
 use lru::LruCache;
-use nix_compat::nix_http;
+use nix_compat::{narinfo::VerifyingKey, nix_http};
 use parking_lot::RwLock;
+use serde::Deserialize;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
 use tvix_castore::blobservice::BlobService;
 use tvix_castore::directoryservice::DirectoryService;
 use tvix_castore::Node;
+use tvix_store::buildlogservice::BuildLogService;
 use tvix_store::pathinfoservice::PathInfoService;
+use tvix_store::realisationservice::RealisationService;
 
+mod auth;
+mod listing;
+mod log;
 mod nar;
 mod narinfo;
+mod realisation;
+
+#[cfg(test)]
+mod tests;
+
+pub use auth::WriteAuth;
 
 /// The capacity of the lookup table from NarHash to [Node].
 /// Should be bigger than the number of concurrent NAR upload.
@@ -21,15 +33,64 @@ mod narinfo;
 /// SAFETY: 1000 != 0
 const ROOT_NODES_CACHE_CAPACITY: usize = 1000;
 
+/// The capacity of the lookup table from NarHash to the size and hash of the
+/// compressed NAR.
+/// SAFETY: 1000 != 0
+const COMPRESSED_NARS_CACHE_CAPACITY: usize = 1000;
+
+/// The compression applied to NARs served by nar-bridge.
+/// NARs are rendered on the fly, so compressing them costs CPU on every
+/// request, in exchange for less bandwidth.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NarCompression {
+    #[default]
+    None,
+    Zstd,
+    Xz,
+}
+
+impl NarCompression {
+    /// Returns the name used in the `Compression` field of a NARInfo.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NarCompression::None => "none",
+            NarCompression::Zstd => "zstd",
+            NarCompression::Xz => "xz",
+        }
+    }
+}
+
+/// The FileSize and FileHash of a (compressed) NAR.
+type FileSizeAndHash = (u64, [u8; 32]);
+
 #[derive(Clone)]
 pub struct AppState {
     blob_service: Arc<dyn BlobService>,
     directory_service: Arc<dyn DirectoryService>,
     path_info_service: Arc<dyn PathInfoService>,
+    build_log_service: Arc<dyn BuildLogService>,
+    realisation_service: Arc<dyn RealisationService>,
 
     /// Lookup table from NarHash to [Node], necessary to populate the root_node
     /// field of the PathInfo when processing the narinfo upload.
     root_nodes: Arc<RwLock<LruCache<[u8; 32], Node>>>,
+
+    /// The compression to advertise in NARInfo files, and apply to the NARs
+    /// they point to.
+    nar_compression: NarCompression,
+
+    /// Lookup table from NarHash to the FileSize and FileHash of the NAR
+    /// compressed with [Self::nar_compression], as determining them requires
+    /// compressing the whole NAR.
+    compressed_nars: Arc<RwLock<LruCache<[u8; 32], FileSizeAndHash>>>,
+
+    /// If set, uploads need to carry credentials accepted by [WriteAuth].
+    write_auth: Option<Arc<WriteAuth>>,
+
+    /// If set, uploaded NARInfo files and realisations need to have a correct
+    /// signature by at least one of these.
+    public_keys: Option<Arc<Vec<VerifyingKey>>>,
 }
 
 impl AppState {
@@ -37,39 +98,87 @@ impl AppState {
         blob_service: Arc<dyn BlobService>,
         directory_service: Arc<dyn DirectoryService>,
         path_info_service: Arc<dyn PathInfoService>,
+        build_log_service: Arc<dyn BuildLogService>,
+        realisation_service: Arc<dyn RealisationService>,
+        nar_compression: NarCompression,
     ) -> Self {
         Self {
             blob_service,
             directory_service,
             path_info_service,
+            build_log_service,
+            realisation_service,
+            nar_compression,
+            write_auth: None,
+            public_keys: None,
             root_nodes: Arc::new(RwLock::new(LruCache::new({
                 // SAFETY: 1000 != 0
                 unsafe { NonZeroUsize::new_unchecked(ROOT_NODES_CACHE_CAPACITY) }
             }))),
+            compressed_nars: Arc::new(RwLock::new(LruCache::new({
+                // SAFETY: 1000 != 0
+                unsafe { NonZeroUsize::new_unchecked(COMPRESSED_NARS_CACHE_CAPACITY) }
+            }))),
         }
     }
+
+    /// Configures [Self] to require authentication for uploads.
+    pub fn set_write_auth(&mut self, write_auth: WriteAuth) {
+        self.write_auth = Some(Arc::new(write_auth));
+    }
+
+    /// Configures [Self] to validate the signatures of uploaded NARInfo files
+    /// and realisations with the public keys passed.
+    pub fn set_public_keys(&mut self, public_keys: Vec<VerifyingKey>) {
+        self.public_keys = Some(Arc::new(public_keys));
+    }
 }
 
-pub fn gen_router(priority: u64) -> Router<AppState> {
-    Router::new()
+/// Returns the router serving the Nix HTTP Binary Cache protocol.
+/// If `read_only` is set, the routes accepting uploads are omitted.
+pub fn gen_router(priority: u64, read_only: bool) -> Router<AppState> {
+    let router = Router::new()
         .route("/", get(root))
         // FUTUREWORK: respond for NARs that we still have in root_nodes (at least HEAD)
         // This avoids some unnecessary NAR uploading from multiple concurrent clients, and is cheap.
         .route("/nar/:nar_str", get(four_o_four))
         .route("/nar/:nar_str", head(four_o_four))
-        .route("/nar/:nar_str", put(nar::put))
         .route("/nar/tvix-castore/:root_node_enc", get(nar::get_head))
         .route("/nar/tvix-castore/:root_node_enc", head(nar::get_head))
-        .route("/:narinfo_str", get(narinfo::get))
+        .route("/:narinfo_str", get(narinfo_or_listing))
         .route("/:narinfo_str", head(narinfo::head))
+        .route("/log/:drv_str", get(log::get))
+        .route("/realisations/:doi_str", get(realisation::get))
+        .route("/nix-cache-info", get(move || nix_cache_info(priority)));
+
+    if read_only {
+        return router;
+    }
+
+    router
+        .route("/nar/:nar_str", put(nar::put))
         .route("/:narinfo_str", put(narinfo::put))
-        .route("/nix-cache-info", get(move || nix_cache_info(priority)))
+        .route("/log/:drv_str", put(log::put))
+        .route("/realisations/:doi_str", put(realisation::put))
 }
 
 async fn root() -> &'static str {
     "Hello from nar-bridge"
 }
 
+/// Dispatches GET requests for `<hash>.narinfo` and `<hash>.ls`, which can't be
+/// told apart by the router, as they only differ in their suffix.
+async fn narinfo_or_listing(
+    path: axum::extract::Path<String>,
+    state: axum::extract::State<AppState>,
+) -> Response {
+    if path.ends_with(".ls") {
+        listing::get(path, state).await.into_response()
+    } else {
+        narinfo::get(path, state).await.into_response()
+    }
+}
+
 async fn four_o_four() -> Result<(), StatusCode> {
     Err(StatusCode::NOT_FOUND)
 }
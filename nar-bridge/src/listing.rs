@@ -0,0 +1,99 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use futures::TryStreamExt;
+use nix_compat::{
+    nar::listing::{builder, ListingError},
+    nix_http,
+};
+use std::collections::HashMap;
+use tracing::{instrument, warn, Span};
+use tvix_castore::{B3Digest, Directory, Node};
+
+use crate::AppState;
+
+/// Returns the `.ls` listing of a store path, describing the structure of its
+/// NAR, including the offsets of all regular files.
+/// The listing is produced from the [Directory] graph of the root node, so
+/// neither the NAR nor any blobs need to be read.
+#[instrument(skip(path_info_service, directory_service))]
+pub async fn get(
+    axum::extract::Path(ls_str): axum::extract::Path<String>,
+    axum::extract::State(AppState {
+        path_info_service,
+        directory_service,
+        ..
+    }): axum::extract::State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let digest = nix_http::parse_ls_str(&ls_str).ok_or(StatusCode::NOT_FOUND)?;
+    Span::current().record("path_info.digest", &ls_str[0..32]);
+
+    // fetch the PathInfo
+    let path_info = path_info_service
+        .get(digest)
+        .await
+        .map_err(|e| {
+            warn!(err=%e, "failed to get PathInfo");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // fetch all directories below the root node, if it is a directory.
+    let directories: HashMap<B3Digest, Directory> = match &path_info.node {
+        Node::Directory { digest, .. } => directory_service
+            .get_recursive(digest)
+            .map_ok(|directory| (directory.digest(), directory))
+            .try_collect()
+            .await
+            .map_err(|e| {
+                warn!(err=%e, "failed to get directories");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?,
+        _ => HashMap::new(),
+    };
+
+    let mut listing_builder = builder::ListingBuilder::new();
+    describe_node(listing_builder.root(), &path_info.node, &directories)
+        .and_then(|()| listing_builder.build())
+        .map_err(|e| {
+            warn!(err=%e, "failed to produce listing");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+        .and_then(|listing| {
+            serde_json::to_string(&listing).map_err(|e| {
+                warn!(err=%e, "failed to serialize listing");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })
+        })
+        .map(|listing_json| ([("content-type", nix_http::MIME_TYPE_LS)], listing_json))
+}
+
+/// Describes a castore [Node] (and all its children) in the listing builder,
+/// looking up directories in the passed map.
+fn describe_node(
+    nar_node: builder::Node<'_>,
+    node: &Node,
+    directories: &HashMap<B3Digest, Directory>,
+) -> Result<(), ListingError> {
+    match node {
+        Node::Symlink { target } => nar_node.symlink(target.as_ref()),
+        Node::File {
+            size, executable, ..
+        } => {
+            nar_node.file(*executable, *size);
+            Ok(())
+        }
+        Node::Directory { digest, .. } => {
+            let directory = directories.get(digest).ok_or_else(|| {
+                warn!(directory.digest = %digest, "directory not found");
+                ListingError::MissingNode
+            })?;
+
+            let mut nar_dir = nar_node.directory();
+            // Directory::nodes() yields nodes sorted by their names, which is
+            // the order they appear in the NAR.
+            for (name, node) in directory.nodes() {
+                describe_node(nar_dir.entry(name.as_ref())?, node, directories)?;
+            }
+            nar_dir.close()
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use bytes::Bytes;
+use nix_compat::store_path::StorePathRef;
+use tracing::{instrument, warn, Span};
+
+use crate::{auth::WriteAuthorized, AppState};
+
+/// The size limit for build log uploads nar-bridge receives
+const BUILD_LOG_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Parses the basename of a derivation store path, and returns its digest.
+fn parse_drv_str(drv_str: &str) -> Option<[u8; 20]> {
+    let drv_path = StorePathRef::from_bytes(drv_str.as_bytes()).ok()?;
+    if !drv_path.name().ends_with(".drv") {
+        return None;
+    }
+
+    Some(*drv_path.digest())
+}
+
+#[instrument(skip(build_log_service))]
+pub async fn get(
+    axum::extract::Path(drv_str): axum::extract::Path<String>,
+    axum::extract::State(AppState {
+        build_log_service, ..
+    }): axum::extract::State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let drv_digest = parse_drv_str(&drv_str).ok_or(StatusCode::NOT_FOUND)?;
+
+    let log = build_log_service
+        .get(drv_digest)
+        .await
+        .map_err(|e| {
+            warn!(err=%e, "failed to get build log");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(([("content-type", "text/plain; charset=utf-8")], log))
+}
+
+#[instrument(skip(build_log_service, request), fields(log.size))]
+pub async fn put(
+    axum::extract::Path(drv_str): axum::extract::Path<String>,
+    axum::extract::State(AppState {
+        build_log_service, ..
+    }): axum::extract::State<AppState>,
+    _: WriteAuthorized,
+    request: axum::extract::Request,
+) -> Result<&'static str, StatusCode> {
+    let drv_digest = parse_drv_str(&drv_str).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let log: Bytes = axum::body::to_bytes(request.into_body(), BUILD_LOG_LIMIT)
+        .await
+        .map_err(|e| {
+            warn!(err=%e, "unable to fetch body");
+            StatusCode::BAD_REQUEST
+        })?;
+    Span::current().record("log.size", log.len());
+
+    build_log_service.put(drv_digest, log).await.map_err(|e| {
+        warn!(err=%e, "failed to persist the build log");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok("")
+}
@@ -9,17 +9,24 @@ use data_encoding::BASE64URL_NOPAD;
 use futures::TryStreamExt;
 use nix_compat::{nix_http, nixbase32};
 use serde::Deserialize;
+use sha2::Sha256;
 use std::io;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio_util::io::ReaderStream;
 use tracing::{instrument, warn, Span};
-use tvix_store::nar::ingest_nar_and_hash;
+use tvix_castore::blobservice::BlobService;
+use tvix_castore::directoryservice::DirectoryService;
+use tvix_castore::{HashingReader, Node};
+use tvix_store::nar::{ingest_nar_and_hash, RenderError};
 
-use crate::AppState;
+use crate::{auth::WriteAuthorized, AppState, NarCompression};
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct GetNARParams {
     #[serde(rename = "narsize")]
     nar_size: u64,
+    #[serde(default)]
+    compression: NarCompression,
 }
 
 #[instrument(skip(blob_service, directory_service))]
@@ -27,7 +34,10 @@ pub async fn get_head(
     method: axum::http::Method,
     ranges: Option<TypedHeader<Range>>,
     axum::extract::Path(root_node_enc): axum::extract::Path<String>,
-    axum::extract::Query(GetNARParams { nar_size }): Query<GetNARParams>,
+    axum::extract::Query(GetNARParams {
+        nar_size,
+        compression,
+    }): Query<GetNARParams>,
     axum::extract::State(AppState {
         blob_service,
         directory_service,
@@ -70,12 +80,16 @@ pub async fn get_head(
         if method == axum::http::Method::HEAD {
             // If this is a HEAD request, construct a response returning back the
             // user-provided content-length, but don't actually talk to castore.
-            Response::builder()
-                .header("content-length", nar_size)
-                .body(Body::empty())
-                .unwrap()
-        } else if let Some(TypedHeader(ranges)) = ranges {
+            // The length of compressed NARs is not known upfront.
+            let mut builder = Response::builder();
+            if compression == NarCompression::None {
+                builder = builder.header("content-length", nar_size);
+            }
+            builder.body(Body::empty()).unwrap()
+        } else if let (Some(TypedHeader(ranges)), NarCompression::None) = (ranges, compression) {
             // If this is a range request, construct a seekable NAR reader.
+            // Compressed NARs are not seekable, so ranges are ignored for
+            // them, and the whole NAR is returned.
             let r =
                 tvix_store::nar::seekable::Reader::new(root_node, blob_service, directory_service)
                     .await
@@ -101,21 +115,85 @@ pub async fn get_head(
 
             // spawn a task rendering the NAR to the client.
             tokio::spawn(async move {
-                if let Err(e) =
-                    tvix_store::nar::write_nar(w, &root_node, blob_service, directory_service).await
+                if let Err(e) = write_nar_compressed(
+                    w,
+                    compression,
+                    &root_node,
+                    blob_service,
+                    directory_service,
+                )
+                .await
                 {
                     warn!(err=%e, "failed to write out NAR");
                 }
             });
 
-            Response::builder()
-                .header("content-length", nar_size)
+            let mut builder = Response::builder();
+            if compression == NarCompression::None {
+                builder = builder.header("content-length", nar_size);
+            }
+            builder
                 .body(Body::from_stream(ReaderStream::new(r)))
                 .unwrap()
         },
     ))
 }
 
+/// Renders the NAR of the given root node to w, compressed with the given
+/// compression.
+/// The output is deterministic, so [compressed_nar_size_and_hash] can
+/// describe it without keeping it around.
+async fn write_nar_compressed<W, BS, DS>(
+    w: W,
+    compression: NarCompression,
+    root_node: &Node,
+    blob_service: BS,
+    directory_service: DS,
+) -> Result<(), RenderError>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+    BS: BlobService + Send,
+    DS: DirectoryService + Send,
+{
+    let mut w: Box<dyn AsyncWrite + Unpin + Send> = match compression {
+        NarCompression::None => Box::new(w),
+        NarCompression::Zstd => Box::new(async_compression::tokio::write::ZstdEncoder::new(w)),
+        NarCompression::Xz => Box::new(async_compression::tokio::write::XzEncoder::new(w)),
+    };
+
+    tvix_store::nar::write_nar(&mut w, root_node, blob_service, directory_service).await?;
+
+    // Encoders only write out their trailer on shutdown.
+    w.shutdown().await.map_err(RenderError::NARWriterError)
+}
+
+/// Renders the NAR of the given root node like [get_head] serves it with the
+/// given compression, and returns the size and sha256 digest of the result,
+/// to populate the FileSize and FileHash fields of the NARInfo.
+pub(crate) async fn compressed_nar_size_and_hash<BS, DS>(
+    compression: NarCompression,
+    root_node: &Node,
+    blob_service: BS,
+    directory_service: DS,
+) -> Result<(u64, [u8; 32]), RenderError>
+where
+    BS: BlobService + Send,
+    DS: DirectoryService + Send,
+{
+    let (w, r) = tokio::io::duplex(1024 * 64);
+    let mut r = HashingReader::<_, Sha256>::from(r);
+    let mut sink = tokio::io::sink();
+
+    let (res, file_size) = tokio::join!(
+        write_nar_compressed(w, compression, root_node, blob_service, directory_service),
+        tokio::io::copy(&mut r, &mut sink)
+    );
+    res?;
+    let file_size = file_size.map_err(RenderError::NARWriterError)?;
+
+    Ok((file_size, r.digest().into()))
+}
+
 #[instrument(skip(blob_service, directory_service, request))]
 pub async fn put(
     axum::extract::Path(nar_str): axum::extract::Path<String>,
@@ -125,23 +203,44 @@ pub async fn put(
         root_nodes,
         ..
     }): axum::extract::State<AppState>,
+    _: WriteAuthorized,
     request: axum::extract::Request,
 ) -> Result<&'static str, StatusCode> {
-    let (nar_hash_expected, compression_suffix) =
-        nix_http::parse_nar_str(&nar_str).ok_or(StatusCode::UNAUTHORIZED)?;
-
-    // No paths with compression suffix are supported.
-    if !compression_suffix.is_empty() {
-        warn!(%compression_suffix, "invalid compression suffix requested");
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    // For compressed NARs, the hash in the path is the one of the compressed
+    // file (FileHash), for uncompressed ones it's the same as the NarHash.
+    let (file_hash_expected, compression_suffix) =
+        nix_http::parse_nar_str(&nar_str).ok_or(StatusCode::BAD_REQUEST)?;
 
     let s = request.into_body().into_data_stream();
 
-    let mut r = tokio_util::io::StreamReader::new(s.map_err(|e| {
-        warn!(err=%e, "failed to read request body");
-        io::Error::new(io::ErrorKind::BrokenPipe, e.to_string())
-    }));
+    // Hash the file as received, before decompression.
+    let mut file_r = BufReader::new(HashingReader::<_, Sha256>::from(
+        tokio_util::io::StreamReader::new(s.map_err(|e| {
+            warn!(err=%e, "failed to read request body");
+            io::Error::new(io::ErrorKind::BrokenPipe, e.to_string())
+        })),
+    ));
+
+    // handle decompression, depending on the compression suffix.
+    let mut r: Box<dyn AsyncRead + Send + Unpin + '_> = match compression_suffix {
+        "" => Box::new(&mut file_r),
+        ".bz2" => Box::new(async_compression::tokio::bufread::BzDecoder::new(
+            &mut file_r,
+        )),
+        ".gz" => Box::new(async_compression::tokio::bufread::GzipDecoder::new(
+            &mut file_r,
+        )),
+        ".xz" => Box::new(async_compression::tokio::bufread::XzDecoder::new(
+            &mut file_r,
+        )),
+        ".zst" => Box::new(async_compression::tokio::bufread::ZstdDecoder::new(
+            &mut file_r,
+        )),
+        _ => {
+            warn!(%compression_suffix, "unsupported compression suffix requested");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
 
     // ingest the NAR
     let (root_node, nar_hash_actual, nar_size) =
@@ -153,15 +252,26 @@ pub async fn put(
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
 
+    // Read up to the end of the (compressed) file, which might contain a
+    // trailer after the NAR, so all of it is hashed.
+    drop(r);
+    tokio::io::copy(&mut file_r, &mut tokio::io::sink())
+        .await
+        .map_err(|e| {
+            warn!(err=%e, "failed to read up to the end of the file");
+            StatusCode::BAD_REQUEST
+        })?;
+    let file_hash_actual: [u8; 32] = file_r.into_inner().digest().into();
+
     let s = Span::current();
-    s.record("nar_hash.expected", nixbase32::encode(&nar_hash_expected));
+    s.record("file_hash.expected", nixbase32::encode(&file_hash_expected));
     s.record("nar_size", nar_size);
 
-    if nar_hash_expected != nar_hash_actual {
+    if file_hash_expected != file_hash_actual {
         warn!(
-            nar_hash.expected = nixbase32::encode(&nar_hash_expected),
-            nar_hash.actual = nixbase32::encode(&nar_hash_actual),
-            "nar hash mismatch"
+            file_hash.expected = nixbase32::encode(&file_hash_expected),
+            file_hash.actual = nixbase32::encode(&file_hash_actual),
+            "file hash mismatch"
         );
         return Err(StatusCode::BAD_REQUEST);
     }
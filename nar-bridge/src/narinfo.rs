@@ -10,7 +10,7 @@ use tracing::{instrument, warn, Span};
 use tvix_castore::proto::{self as castorepb};
 use tvix_store::pathinfoservice::PathInfo;
 
-use crate::AppState;
+use crate::{auth::WriteAuthorized, AppState, NarCompression};
 
 /// The size limit for NARInfo uploads nar-bridge receives
 const NARINFO_LIMIT: usize = 2 * 1024 * 1024;
@@ -41,11 +41,16 @@ pub async fn head(
     }
 }
 
-#[instrument(skip(path_info_service))]
+#[instrument(skip(blob_service, directory_service, path_info_service, compressed_nars))]
 pub async fn get(
     axum::extract::Path(narinfo_str): axum::extract::Path<String>,
     axum::extract::State(AppState {
-        path_info_service, ..
+        blob_service,
+        directory_service,
+        path_info_service,
+        nar_compression,
+        compressed_nars,
+        ..
     }): axum::extract::State<AppState>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let digest = nix_http::parse_narinfo_str(&narinfo_str).ok_or(StatusCode::NOT_FOUND)?;
@@ -61,16 +66,53 @@ pub async fn get(
         })?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    let url = format!(
+    let mut url = format!(
         "nar/tvix-castore/{}?narsize={}",
         data_encoding::BASE64URL_NOPAD.encode(
             &castorepb::Node::from_name_and_node("".into(), path_info.node.clone()).encode_to_vec()
         ),
         path_info.nar_size,
     );
+    if nar_compression != NarCompression::None {
+        url.push_str("&compression=");
+        url.push_str(nar_compression.as_str());
+    }
+
+    // The size and hash of compressed NARs are only known after compressing
+    // them, and NARs are compressed on the fly when they're requested.
+    // Compress the NAR once here, discarding the output, and remember the
+    // result.
+    let (file_size, file_hash) = if nar_compression == NarCompression::None {
+        (path_info.nar_size, path_info.nar_sha256)
+    } else {
+        let cached = compressed_nars.write().get(&path_info.nar_sha256).copied();
+        match cached {
+            Some(file_size_and_hash) => file_size_and_hash,
+            None => {
+                let file_size_and_hash = crate::nar::compressed_nar_size_and_hash(
+                    nar_compression,
+                    &path_info.node,
+                    blob_service,
+                    directory_service,
+                )
+                .await
+                .map_err(|e| {
+                    warn!(err=%e, "failed to compress NAR");
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+                compressed_nars
+                    .write()
+                    .put(path_info.nar_sha256, file_size_and_hash);
+                file_size_and_hash
+            }
+        }
+    };
 
     let mut narinfo = path_info.to_narinfo();
     narinfo.url = &url;
+    narinfo.compression = Some(nar_compression.as_str());
+    narinfo.file_hash = Some(file_hash);
+    narinfo.file_size = Some(file_size);
 
     Ok((
         [("content-type", nix_http::MIME_TYPE_NARINFO)],
@@ -78,17 +120,20 @@ pub async fn get(
     ))
 }
 
-#[instrument(skip(path_info_service, root_nodes, request))]
+#[instrument(skip(path_info_service, root_nodes, public_keys, request))]
 pub async fn put(
     axum::extract::Path(narinfo_str): axum::extract::Path<String>,
     axum::extract::State(AppState {
         path_info_service,
         root_nodes,
+        public_keys,
         ..
     }): axum::extract::State<AppState>,
+    _: WriteAuthorized,
     request: axum::extract::Request,
 ) -> Result<&'static str, StatusCode> {
-    let _narinfo_digest = nix_http::parse_narinfo_str(&narinfo_str).ok_or(StatusCode::UNAUTHORIZED);
+    let _narinfo_digest =
+        nix_http::parse_narinfo_str(&narinfo_str).ok_or(StatusCode::BAD_REQUEST)?;
     Span::current().record("path_info.digest", &narinfo_str[0..32]);
 
     let narinfo_bytes: Bytes = axum::body::to_bytes(request.into_body(), NARINFO_LIMIT)
@@ -109,6 +154,21 @@ pub async fn put(
         StatusCode::BAD_REQUEST
     })?;
 
+    // if public_keys is set, ensure there's at least one valid signature.
+    if let Some(public_keys) = &public_keys {
+        let fingerprint = narinfo.fingerprint();
+
+        if !public_keys.iter().any(|pubkey| {
+            narinfo
+                .signatures
+                .iter()
+                .any(|sig| pubkey.verify(&fingerprint, sig))
+        }) {
+            warn!("no valid signature found");
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
     // Extract the NARHash from the PathInfo.
     Span::current().record("path_info.nar_info", nixbase32::encode(&narinfo.nar_hash));
 
@@ -0,0 +1,87 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use bytes::Bytes;
+use nix_compat::realisation::{DrvOutput, Realisation};
+use tracing::{instrument, warn};
+
+use crate::{auth::WriteAuthorized, AppState};
+
+/// The size limit for realisation uploads nar-bridge receives
+const REALISATION_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Parses a `sha256:<hex digest>!<output name>.doi` string.
+fn parse_doi_str(doi_str: &str) -> Option<DrvOutput> {
+    doi_str.strip_suffix(".doi")?.parse().ok()
+}
+
+#[instrument(skip(realisation_service))]
+pub async fn get(
+    axum::extract::Path(doi_str): axum::extract::Path<String>,
+    axum::extract::State(AppState {
+        realisation_service,
+        ..
+    }): axum::extract::State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let id = parse_doi_str(&doi_str).ok_or(StatusCode::NOT_FOUND)?;
+
+    let realisation = realisation_service
+        .get(&id)
+        .await
+        .map_err(|e| {
+            warn!(err=%e, "failed to get Realisation");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let realisation_json = serde_json::to_string(&realisation).map_err(|e| {
+        warn!(err=%e, "failed to serialize Realisation");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(([("content-type", "application/json")], realisation_json))
+}
+
+#[instrument(skip(realisation_service, public_keys, request))]
+pub async fn put(
+    axum::extract::Path(doi_str): axum::extract::Path<String>,
+    axum::extract::State(AppState {
+        realisation_service,
+        public_keys,
+        ..
+    }): axum::extract::State<AppState>,
+    _: WriteAuthorized,
+    request: axum::extract::Request,
+) -> Result<&'static str, StatusCode> {
+    let id = parse_doi_str(&doi_str).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let realisation_bytes: Bytes = axum::body::to_bytes(request.into_body(), REALISATION_LIMIT)
+        .await
+        .map_err(|e| {
+            warn!(err=%e, "unable to fetch body");
+            StatusCode::BAD_REQUEST
+        })?;
+
+    let realisation: Realisation = serde_json::from_slice(&realisation_bytes).map_err(|e| {
+        warn!(err=%e, "unable to parse Realisation");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    if realisation.id != id {
+        warn!(realisation.id=%realisation.id, "Realisation id doesn't match the path");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // if public_keys is set, ensure there's at least one valid signature.
+    if let Some(public_keys) = &public_keys {
+        if !public_keys.iter().any(|pubkey| realisation.verify(pubkey)) {
+            warn!("no valid signature found");
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    realisation_service.put(realisation).await.map_err(|e| {
+        warn!(err=%e, "failed to persist the Realisation");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok("")
+}
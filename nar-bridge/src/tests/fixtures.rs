@@ -0,0 +1,134 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use bytes::Bytes;
+use nix_compat::narinfo::{self, NarInfo, SigningKey};
+use nix_compat::{nixbase32, store_path::StorePathRef};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, LazyLock};
+use tower::Service;
+use tvix_castore::blobservice::MemoryBlobService;
+use tvix_castore::directoryservice::MemoryDirectoryService;
+use tvix_store::buildlogservice::MemoryBuildLogService;
+use tvix_store::pathinfoservice::MemoryPathInfoService;
+use tvix_store::realisationservice::MemoryRealisationService;
+
+use crate::{AppState, NarCompression};
+
+/// A keypair only used for testing.
+pub const DUMMY_KEYPAIR: &str = "do.not.use:sGPzxuK5WvWPraytx+6sjtaff866sYlfvErE6x0hFEhy5eqe7OVZ8ZMqZ/ME/HaRdKGNGvJkyGKXYTaeA6lR3A==";
+/// Another keypair only used for testing, not trusted by the server.
+pub const OTHER_KEYPAIR: &str = "other.do.not.use:TIyJAC4ZppKT+AYaAssGklq1h7iKtmsdSBxI9tt60FjLHpFhB9k6aR/0HLVPxoeoxbgW4OnIt0a0diYC3joYgg==";
+
+pub const STORE_PATH_STR: &str = "00bgd045z0d4icpbc2yyz4gx48ak44la-hello";
+
+/// The NAR representation of a regular file with the contents "Hello World!"
+pub static NAR_CONTENTS: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    let contents = b"Hello World!";
+    let mut buf = Vec::new();
+    nix_compat::nar::writer::open(&mut buf)
+        .expect("open nar")
+        .file(false, contents.len() as u64, &mut &contents[..])
+        .expect("write file");
+    buf
+});
+
+pub static NAR_SHA256: LazyLock<[u8; 32]> =
+    LazyLock::new(|| Sha256::digest(NAR_CONTENTS.as_slice()).into());
+
+/// Returns an [AppState] using in-memory services.
+pub fn gen_state(nar_compression: NarCompression) -> AppState {
+    AppState::new(
+        Arc::new(MemoryBlobService::default()),
+        Arc::new(MemoryDirectoryService::default()),
+        Arc::new(MemoryPathInfoService::default()),
+        Arc::new(MemoryBuildLogService::default()),
+        Arc::new(MemoryRealisationService::default()),
+        nar_compression,
+    )
+}
+
+/// Builds a request with the given method, uri and body, optionally
+/// carrying the given Authorization header.
+pub fn request(
+    method: &str,
+    uri: &str,
+    authorization: Option<&str>,
+    body: impl Into<Body>,
+) -> Request<Body> {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(authorization) = authorization {
+        request = request.header("authorization", authorization);
+    }
+    request.body(body.into()).unwrap()
+}
+
+/// Sends the request to the router, and returns status code and body of the
+/// response.
+pub async fn send(router: &mut Router, request: Request<Body>) -> (StatusCode, Bytes) {
+    let response = router.call(request).await.expect("infallible");
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("read body");
+    (status, body)
+}
+
+/// Returns the uri uploading the NAR to, for a file with the given contents
+/// and compression suffix.
+pub fn nar_uri(file_contents: &[u8], compression_suffix: &str) -> String {
+    format!(
+        "/nar/{}.nar{}",
+        nixbase32::encode(&Sha256::digest(file_contents)),
+        compression_suffix
+    )
+}
+
+/// Returns the uri of the NARInfo for [STORE_PATH_STR].
+pub fn narinfo_uri() -> String {
+    format!("/{}.narinfo", &STORE_PATH_STR[..32])
+}
+
+/// Returns a NARInfo describing [NAR_CONTENTS] at [STORE_PATH_STR], signed
+/// with the passed keypair, if any.
+pub fn narinfo(keypair: Option<&str>) -> String {
+    let signing_key: Option<SigningKey<_>> =
+        keypair.map(|keypair| narinfo::parse_keypair(keypair).expect("valid keypair").0);
+
+    let mut narinfo = NarInfo {
+        flags: narinfo::Flags::empty(),
+        store_path: StorePathRef::from_bytes(STORE_PATH_STR.as_bytes()).unwrap(),
+        nar_hash: *NAR_SHA256,
+        nar_size: NAR_CONTENTS.len() as u64,
+        references: vec![],
+        signatures: vec![],
+        ca: None,
+        system: None,
+        deriver: None,
+        url: "nar/irrelevant.nar",
+        compression: Some("none"),
+        file_hash: None,
+        file_size: None,
+    };
+    if let Some(signing_key) = &signing_key {
+        narinfo.add_signature(signing_key);
+    }
+
+    narinfo.to_string()
+}
+
+pub const DRV_OUTPUT: &str =
+    "sha256:ba1ad2a5e98fb2a4b1d3a1db7b4a6d1c64fb1bcb1bb8ce7ac0cb5c1d5a3b9e6f!out";
+
+/// Returns the uri of the realisation with the given id.
+pub fn realisation_uri(drv_output: &str) -> String {
+    format!("/realisations/{drv_output}.doi")
+}
+
+/// Returns an unsigned realisation with the given id, pointing to
+/// [STORE_PATH_STR], as JSON.
+pub fn realisation_json(drv_output: &str) -> String {
+    format!(
+        r#"{{"dependentRealisations":{{}},"id":"{drv_output}","outPath":"{STORE_PATH_STR}","signatures":[]}}"#
+    )
+}
@@ -0,0 +1,40 @@
+use axum::http::StatusCode;
+use rstest::rstest;
+
+use super::fixtures::*;
+use crate::{gen_router, NarCompression};
+
+const LOG_URI: &str = "/log/00bgd045z0d4icpbc2yyz4gx48ak44la-hello.drv";
+const LOG: &str = "building '/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-hello.drv'...\n";
+
+#[tokio::test]
+async fn put_get() {
+    let mut router = gen_router(39, false).with_state(gen_state(NarCompression::None));
+
+    let (status, _) = send(&mut router, request("GET", LOG_URI, None, "")).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+
+    let (status, _) = send(&mut router, request("PUT", LOG_URI, None, LOG)).await;
+    assert_eq!(StatusCode::OK, status);
+
+    let (status, body) = send(&mut router, request("GET", LOG_URI, None, "")).await;
+    assert_eq!(StatusCode::OK, status);
+    assert_eq!(LOG.as_bytes(), body);
+}
+
+/// Build logs are only served and accepted for derivation store paths.
+#[rstest]
+#[case::no_drv("/log/00bgd045z0d4icpbc2yyz4gx48ak44la-hello")]
+#[case::invalid_digest("/log/00bgd045z0d4icpbc2yyz4gx48ak44lu-hello.drv")]
+#[case::no_name("/log/00bgd045z0d4icpbc2yyz4gx48ak44la")]
+#[case::full_path("/log/%2Fnix%2Fstore%2F00bgd045z0d4icpbc2yyz4gx48ak44la-hello.drv")]
+#[tokio::test]
+async fn invalid_drv(#[case] uri: &str) {
+    let mut router = gen_router(39, false).with_state(gen_state(NarCompression::None));
+
+    let (status, _) = send(&mut router, request("PUT", uri, None, LOG)).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+
+    let (status, _) = send(&mut router, request("GET", uri, None, "")).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+}
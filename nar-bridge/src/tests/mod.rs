@@ -0,0 +1,5 @@
+pub mod fixtures;
+mod log;
+mod nar;
+mod realisation;
+mod upload_auth;
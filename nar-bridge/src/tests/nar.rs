@@ -0,0 +1,179 @@
+use async_compression::tokio::{bufread, write};
+use axum::http::StatusCode;
+use nix_compat::narinfo::NarInfo;
+use rstest::rstest;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::fixtures::*;
+use crate::{gen_router, NarCompression};
+
+/// Compresses data with the compression Nix indicates with the given suffix
+/// in NAR urls.
+async fn compress(compression_suffix: &str, data: &[u8]) -> Vec<u8> {
+    async fn encode<W: AsyncWrite + Unpin>(mut w: W, data: &[u8]) -> W {
+        w.write_all(data).await.unwrap();
+        w.shutdown().await.unwrap();
+        w
+    }
+
+    match compression_suffix {
+        "" => data.to_vec(),
+        ".bz2" => encode(write::BzEncoder::new(Vec::new()), data)
+            .await
+            .into_inner(),
+        ".gz" => encode(write::GzipEncoder::new(Vec::new()), data)
+            .await
+            .into_inner(),
+        ".xz" => encode(write::XzEncoder::new(Vec::new()), data)
+            .await
+            .into_inner(),
+        ".zst" => encode(write::ZstdEncoder::new(Vec::new()), data)
+            .await
+            .into_inner(),
+        _ => panic!("unknown compression suffix {compression_suffix}"),
+    }
+}
+
+async fn decompress(compression: NarCompression, data: &[u8]) -> Vec<u8> {
+    let mut r: Box<dyn AsyncRead + Unpin + '_> = match compression {
+        NarCompression::None => Box::new(data),
+        NarCompression::Zstd => Box::new(bufread::ZstdDecoder::new(data)),
+        NarCompression::Xz => Box::new(bufread::XzDecoder::new(data)),
+    };
+    let mut buf = Vec::new();
+    r.read_to_end(&mut buf).await.unwrap();
+    buf
+}
+
+/// Uploads NARs compressed in any of the ways Nix does, which are then
+/// accepted as the NAR referred to by the narinfo.
+#[rstest]
+#[case::none("")]
+#[case::bzip2(".bz2")]
+#[case::gzip(".gz")]
+#[case::xz(".xz")]
+#[case::zstd(".zst")]
+#[tokio::test]
+async fn put_compressed(#[case] compression_suffix: &str) {
+    let mut router = gen_router(39, false).with_state(gen_state(NarCompression::None));
+
+    let file_contents = compress(compression_suffix, &NAR_CONTENTS).await;
+    let (status, _) = send(
+        &mut router,
+        request(
+            "PUT",
+            &nar_uri(&file_contents, compression_suffix),
+            None,
+            file_contents,
+        ),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &narinfo_uri(), None, narinfo(None)),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+}
+
+/// The hash in the url of compressed NARs is the one of the compressed file
+/// (FileHash), uploads not matching it are rejected.
+#[tokio::test]
+async fn put_compressed_wrong_file_hash() {
+    let mut router = gen_router(39, false).with_state(gen_state(NarCompression::None));
+
+    let file_contents = compress(".xz", &NAR_CONTENTS).await;
+    // the NarHash, not the FileHash
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &nar_uri(&NAR_CONTENTS, ".xz"), None, file_contents),
+    )
+    .await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+
+    // The NAR is unknown, so the narinfo is rejected.
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &narinfo_uri(), None, narinfo(None)),
+    )
+    .await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+}
+
+/// NARs are served with the configured compression, as announced in the
+/// narinfo.
+#[rstest]
+#[case::none(NarCompression::None)]
+#[case::zstd(NarCompression::Zstd)]
+#[case::xz(NarCompression::Xz)]
+#[tokio::test]
+async fn get_compressed(#[case] nar_compression: NarCompression) {
+    let mut router = gen_router(39, false).with_state(gen_state(nar_compression));
+
+    let (status, _) = send(
+        &mut router,
+        request(
+            "PUT",
+            &nar_uri(&NAR_CONTENTS, ""),
+            None,
+            NAR_CONTENTS.clone(),
+        ),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &narinfo_uri(), None, narinfo(None)),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+
+    let (status, body) = send(&mut router, request("GET", &narinfo_uri(), None, "")).await;
+    assert_eq!(StatusCode::OK, status);
+    let narinfo_str = std::str::from_utf8(&body).unwrap();
+    let narinfo = NarInfo::parse(narinfo_str).expect("must parse");
+
+    if nar_compression == NarCompression::None {
+        // parsed from `Compression: none`
+        assert_eq!(None, narinfo.compression);
+    } else {
+        assert_eq!(Some(nar_compression.as_str()), narinfo.compression);
+    }
+
+    let (status, body) = send(
+        &mut router,
+        request("GET", &format!("/{}", narinfo.url), None, ""),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+    assert_eq!(*NAR_CONTENTS, decompress(nar_compression, &body).await);
+
+    // FileSize and FileHash describe the file as served.
+    assert_eq!(Some(body.len() as u64), narinfo.file_size);
+    assert_eq!(
+        Some(<[u8; 32]>::from(Sha256::digest(&body))),
+        narinfo.file_hash
+    );
+
+    // Requesting the narinfo again returns the same (cached) values.
+    let (status, body) = send(&mut router, request("GET", &narinfo_uri(), None, "")).await;
+    assert_eq!(StatusCode::OK, status);
+    assert_eq!(narinfo_str, std::str::from_utf8(&body).unwrap());
+}
+
+/// Uploads to paths that aren't NARs (or narinfos), or with unsupported
+/// compression, are rejected as bad requests.
+#[rstest]
+#[case::not_a_nar("/nar/foo")]
+#[case::not_a_narinfo("/foo")]
+#[case::unknown_compression("/nar/0mw6qwsrz35cck0wnjgmfnjzwnjbspsyihnfkng38kxghdc9k9zd.nar.lz4")]
+#[tokio::test]
+async fn put_bad_path(#[case] uri: &str) {
+    let mut router = gen_router(39, false).with_state(gen_state(NarCompression::None));
+
+    let (status, _) = send(&mut router, request("PUT", uri, None, NAR_CONTENTS.clone())).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+}
@@ -0,0 +1,73 @@
+use axum::http::StatusCode;
+use nix_compat::realisation::Realisation;
+use rstest::rstest;
+
+use super::fixtures::*;
+use crate::{gen_router, NarCompression};
+
+#[tokio::test]
+async fn put_get() {
+    let mut router = gen_router(39, false).with_state(gen_state(NarCompression::None));
+    let uri = realisation_uri(DRV_OUTPUT);
+
+    let (status, _) = send(&mut router, request("GET", &uri, None, "")).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &uri, None, realisation_json(DRV_OUTPUT)),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+
+    let (status, body) = send(&mut router, request("GET", &uri, None, "")).await;
+    assert_eq!(StatusCode::OK, status);
+    assert_eq!(
+        serde_json::from_str::<Realisation>(&realisation_json(DRV_OUTPUT)).unwrap(),
+        serde_json::from_slice::<Realisation>(&body).expect("must parse")
+    );
+}
+
+/// Realisations are only served and accepted for valid DrvOutput ids.
+#[rstest]
+#[case::no_doi(
+    "/realisations/sha256:ba1ad2a5e98fb2a4b1d3a1db7b4a6d1c64fb1bcb1bb8ce7ac0cb5c1d5a3b9e6f!out"
+)]
+#[case::no_output_name(
+    "/realisations/sha256:ba1ad2a5e98fb2a4b1d3a1db7b4a6d1c64fb1bcb1bb8ce7ac0cb5c1d5a3b9e6f.doi"
+)]
+#[case::sha1("/realisations/sha1:ba1ad2a5e98fb2a4b1d3a1db7b4a6d1c64fb1bcb!out.doi")]
+#[case::short_digest("/realisations/sha256:ba1ad2a5!out.doi")]
+#[tokio::test]
+async fn invalid_id(#[case] uri: &str) {
+    let mut router = gen_router(39, false).with_state(gen_state(NarCompression::None));
+
+    let (status, _) = send(
+        &mut router,
+        request("PUT", uri, None, realisation_json(DRV_OUTPUT)),
+    )
+    .await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+
+    let (status, _) = send(&mut router, request("GET", uri, None, "")).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+}
+
+/// Uploads with an id not matching the one in the path, or which aren't a
+/// realisation at all, are rejected.
+#[rstest]
+#[case::other_id(realisation_json(
+    "sha256:ba1ad2a5e98fb2a4b1d3a1db7b4a6d1c64fb1bcb1bb8ce7ac0cb5c1d5a3b9e6f!dev"
+))]
+#[case::invalid_json("{".to_string())]
+#[tokio::test]
+async fn put_invalid(#[case] body: String) {
+    let mut router = gen_router(39, false).with_state(gen_state(NarCompression::None));
+    let uri = realisation_uri(DRV_OUTPUT);
+
+    let (status, _) = send(&mut router, request("PUT", &uri, None, body)).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+
+    let (status, _) = send(&mut router, request("GET", &uri, None, "")).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+}
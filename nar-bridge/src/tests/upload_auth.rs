@@ -0,0 +1,183 @@
+use axum::http::StatusCode;
+use nix_compat::narinfo::parse_keypair;
+use nix_compat::realisation::Realisation;
+
+use super::fixtures::*;
+use crate::{gen_router, NarCompression, WriteAuth};
+
+const TOKEN: &str = "token1";
+
+/// Uploads without credentials are rejected if authentication is configured.
+#[tokio::test]
+async fn put_unauthorized() {
+    let mut state = gen_state(NarCompression::None);
+    let mut write_auth = WriteAuth::default();
+    write_auth.add_bearer_tokens(TOKEN);
+    state.set_write_auth(write_auth);
+    let mut router = gen_router(39, false).with_state(state);
+
+    let nar_uri = nar_uri(&NAR_CONTENTS, "");
+    for authorization in [None, Some("Bearer wrong")] {
+        let (status, _) = send(
+            &mut router,
+            request("PUT", &nar_uri, authorization, NAR_CONTENTS.clone()),
+        )
+        .await;
+        assert_eq!(StatusCode::UNAUTHORIZED, status);
+
+        let (status, _) = send(
+            &mut router,
+            request("PUT", &narinfo_uri(), authorization, narinfo(None)),
+        )
+        .await;
+        assert_eq!(StatusCode::UNAUTHORIZED, status);
+    }
+
+    let authorization = Some("Bearer token1");
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &nar_uri, authorization, NAR_CONTENTS.clone()),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &narinfo_uri(), authorization, narinfo(None)),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+}
+
+/// With read_only set, there are no routes accepting uploads.
+#[tokio::test]
+async fn put_read_only() {
+    let mut router = gen_router(39, true).with_state(gen_state(NarCompression::None));
+
+    for (uri, body) in [
+        (nar_uri(&NAR_CONTENTS, ""), NAR_CONTENTS.clone()),
+        (narinfo_uri(), narinfo(None).into_bytes()),
+    ] {
+        let (status, _) = send(&mut router, request("PUT", &uri, None, body)).await;
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, status);
+    }
+
+    // The narinfo was not uploaded.
+    let (status, _) = send(&mut router, request("GET", &narinfo_uri(), None, "")).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+}
+
+/// With public keys set, only NARInfo files signed by one of them are
+/// accepted.
+#[tokio::test]
+async fn put_narinfo_signatures() {
+    let mut state = gen_state(NarCompression::None);
+    state.set_public_keys(vec![parse_keypair(DUMMY_KEYPAIR).unwrap().1]);
+    let mut router = gen_router(39, false).with_state(state);
+
+    let (status, _) = send(
+        &mut router,
+        request(
+            "PUT",
+            &nar_uri(&NAR_CONTENTS, ""),
+            None,
+            NAR_CONTENTS.clone(),
+        ),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+
+    for keypair in [None, Some(OTHER_KEYPAIR)] {
+        let (status, _) = send(
+            &mut router,
+            request("PUT", &narinfo_uri(), None, narinfo(keypair)),
+        )
+        .await;
+        assert_eq!(StatusCode::FORBIDDEN, status);
+    }
+
+    // A signature with the trusted key's name, but not made by it.
+    let forged = narinfo(Some(OTHER_KEYPAIR)).replace("other.do.not.use:", "do.not.use:");
+    let (status, _) = send(&mut router, request("PUT", &narinfo_uri(), None, forged)).await;
+    assert_eq!(StatusCode::FORBIDDEN, status);
+
+    let (status, _) = send(&mut router, request("GET", &narinfo_uri(), None, "")).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &narinfo_uri(), None, narinfo(Some(DUMMY_KEYPAIR))),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+
+    let (status, _) = send(&mut router, request("GET", &narinfo_uri(), None, "")).await;
+    assert_eq!(StatusCode::OK, status);
+}
+
+/// With public keys set, only realisations signed by one of them are
+/// accepted.
+#[tokio::test]
+async fn put_realisation_signatures() {
+    let mut state = gen_state(NarCompression::None);
+    state.set_public_keys(vec![parse_keypair(DUMMY_KEYPAIR).unwrap().1]);
+    let mut router = gen_router(39, false).with_state(state);
+    let uri = realisation_uri(DRV_OUTPUT);
+
+    let realisation = |keypair: Option<&str>| {
+        let mut realisation: Realisation =
+            serde_json::from_str(&realisation_json(DRV_OUTPUT)).unwrap();
+        if let Some(keypair) = keypair {
+            realisation.add_signature(&parse_keypair(keypair).unwrap().0);
+        }
+        serde_json::to_string(&realisation).unwrap()
+    };
+
+    for keypair in [None, Some(OTHER_KEYPAIR)] {
+        let (status, _) = send(
+            &mut router,
+            request("PUT", &uri, None, realisation(keypair)),
+        )
+        .await;
+        assert_eq!(StatusCode::FORBIDDEN, status);
+    }
+
+    let (status, _) = send(&mut router, request("GET", &uri, None, "")).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+
+    let (status, _) = send(
+        &mut router,
+        request("PUT", &uri, None, realisation(Some(DUMMY_KEYPAIR))),
+    )
+    .await;
+    assert_eq!(StatusCode::OK, status);
+
+    let (status, _) = send(&mut router, request("GET", &uri, None, "")).await;
+    assert_eq!(StatusCode::OK, status);
+}
+
+/// Build logs and realisations need the same credentials as NARs, and can't
+/// be uploaded with read_only set.
+#[tokio::test]
+async fn put_log_realisation() {
+    let uris = [
+        "/log/00bgd045z0d4icpbc2yyz4gx48ak44la-hello.drv",
+        "/realisations/sha256:ba1ad2a5e98fb2a4b1d3a1db7b4a6d1c64fb1bcb1bb8ce7ac0cb5c1d5a3b9e6f!out.doi",
+    ];
+
+    let mut state = gen_state(NarCompression::None);
+    let mut write_auth = WriteAuth::default();
+    write_auth.add_bearer_tokens(TOKEN);
+    state.set_write_auth(write_auth);
+    let mut router = gen_router(39, false).with_state(state);
+    for uri in uris {
+        let (status, _) = send(&mut router, request("PUT", uri, None, "")).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, status);
+    }
+
+    let mut router = gen_router(39, true).with_state(gen_state(NarCompression::None));
+    for uri in uris {
+        let (status, _) = send(&mut router, request("PUT", uri, None, "")).await;
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, status);
+    }
+}
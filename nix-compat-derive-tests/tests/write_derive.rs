@@ -0,0 +1,275 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use nix_compat::nix_daemon::de::{NixDeserialize, NixRead, NixReader};
+use nix_compat::nix_daemon::ser::{NixSerialize, NixWrite, NixWriter};
+use nix_compat::ProtocolVersion;
+use nix_compat_derive::{NixDeserialize, NixSerialize};
+
+#[derive(Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+pub struct UnitTest;
+
+#[derive(Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+pub struct EmptyTupleTest();
+
+#[derive(Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+pub struct StructTest {
+    first: u64,
+    second: String,
+}
+
+#[derive(Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+pub struct TupleTest(u64, String);
+
+#[derive(Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+pub struct StructVersionTest {
+    test: u64,
+    #[nix(version = "20..")]
+    hello: String,
+}
+
+#[derive(Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+pub struct TupleVersionTest(u64, #[nix(version = "25..")] String);
+
+/// Writes the value with the given protocol version, compares the result
+/// with the expected bytes, and reads it back.
+async fn roundtrip<T>(version: (u8, u8), value: T, expected: &[u8])
+where
+    T: NixSerialize + NixDeserialize + Send + Sync + PartialEq + fmt::Debug,
+{
+    let version: ProtocolVersion = version.into();
+    let mut writer = NixWriter::builder().set_version(version).build(Vec::new());
+    writer.write_value(&value).await.unwrap();
+    let written = writer.into_inner();
+    assert_eq!(expected, &written[..]);
+
+    let mut reader = NixReader::builder()
+        .set_version(version)
+        .build(&written[..]);
+    assert_eq!(value, reader.read_value::<T>().await.unwrap());
+}
+
+/// Concatenates the wire representations of the given values.
+async fn wire<T>(values: &[&T]) -> Vec<u8>
+where
+    T: NixSerialize + Send + Sync + ?Sized,
+{
+    let mut writer = NixWriter::new(Vec::new());
+    for value in values {
+        writer.write_value(*value).await.unwrap();
+    }
+    writer.into_inner()
+}
+
+#[tokio::test]
+async fn write_unit() {
+    roundtrip((1, 37), UnitTest, &[]).await;
+}
+
+#[tokio::test]
+async fn write_empty_tuple() {
+    roundtrip((1, 37), EmptyTupleTest(), &[]).await;
+}
+
+#[tokio::test]
+async fn write_struct() {
+    let mut expected = wire(&[&89u64]).await;
+    expected.extend(wire(&["klomp"]).await);
+    roundtrip(
+        (1, 37),
+        StructTest {
+            first: 89,
+            second: String::from("klomp"),
+        },
+        &expected,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn write_tuple() {
+    let mut expected = wire(&[&89u64]).await;
+    expected.extend(wire(&["klomp"]).await);
+    roundtrip((1, 37), TupleTest(89, String::from("klomp")), &expected).await;
+}
+
+#[tokio::test]
+async fn write_struct_version() {
+    let mut expected = wire(&[&89u64]).await;
+    expected.extend(wire(&["klomp"]).await);
+    roundtrip(
+        (1, 20),
+        StructVersionTest {
+            test: 89,
+            hello: String::from("klomp"),
+        },
+        &expected,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn write_struct_without_version() {
+    roundtrip(
+        (1, 19),
+        StructVersionTest {
+            test: 89,
+            hello: String::new(),
+        },
+        &wire(&[&89u64]).await,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn write_tuple_version() {
+    let mut expected = wire(&[&89u64]).await;
+    expected.extend(wire(&["klomp"]).await);
+    roundtrip((1, 26), TupleVersionTest(89, "klomp".into()), &expected).await;
+}
+
+#[tokio::test]
+async fn write_tuple_without_version() {
+    roundtrip(
+        (1, 19),
+        TupleVersionTest(89, String::new()),
+        &wire(&[&89u64]).await,
+    )
+    .await;
+}
+
+#[derive(Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+#[nix(from_str, display)]
+struct TestFromStr;
+
+impl FromStr for TestFromStr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "test" {
+            Ok(TestFromStr)
+        } else {
+            Err(s.into())
+        }
+    }
+}
+
+impl fmt::Display for TestFromStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("test")
+    }
+}
+
+#[tokio::test]
+async fn write_display() {
+    roundtrip((1, 37), TestFromStr, &wire(&["test"]).await).await;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+#[nix(try_from = "u64", into = "u64")]
+struct TestU64(u64);
+
+impl TryFrom<u64> for TestU64 {
+    type Error = u64;
+
+    fn try_from(value: u64) -> Result<TestU64, Self::Error> {
+        if value != 666 {
+            Ok(TestU64(value))
+        } else {
+            Err(value)
+        }
+    }
+}
+
+impl From<TestU64> for u64 {
+    fn from(value: TestU64) -> u64 {
+        value.0
+    }
+}
+
+#[tokio::test]
+async fn write_into_u64() {
+    roundtrip((1, 37), TestU64(42), &wire(&[&42u64]).await).await;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+#[nix(from = "u64", try_into = "u64")]
+struct TestTryIntoU64(u64);
+
+impl From<u64> for TestTryIntoU64 {
+    fn from(value: u64) -> TestTryIntoU64 {
+        TestTryIntoU64(value)
+    }
+}
+
+impl TryFrom<TestTryIntoU64> for u64 {
+    type Error = String;
+
+    fn try_from(value: TestTryIntoU64) -> Result<u64, Self::Error> {
+        if value.0 != 666 {
+            Ok(value.0)
+        } else {
+            Err("666 can't be written".into())
+        }
+    }
+}
+
+#[tokio::test]
+async fn write_try_into_u64() {
+    roundtrip((1, 37), TestTryIntoU64(42), &wire(&[&42u64]).await).await;
+}
+
+#[tokio::test]
+async fn write_try_into_u64_unsupported_data() {
+    let mut writer = NixWriter::new(Vec::new());
+    let err = writer.write_value(&TestTryIntoU64(666)).await.unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    assert_eq!("666 can't be written", err.to_string());
+}
+
+#[derive(Debug, PartialEq, Eq, NixDeserialize, NixSerialize)]
+enum TestEnum {
+    #[nix(version = "..=19")]
+    Pre20(TestU64),
+    #[nix(version = "20..")]
+    Post20(StructVersionTest),
+}
+
+#[tokio::test]
+async fn write_enum_19() {
+    roundtrip(
+        (1, 19),
+        TestEnum::Pre20(TestU64(42)),
+        &wire(&[&42u64]).await,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn write_enum_20() {
+    let mut expected = wire(&[&42u64]).await;
+    expected.extend(wire(&["klomp"]).await);
+    roundtrip(
+        (1, 20),
+        TestEnum::Post20(StructVersionTest {
+            test: 42,
+            hello: "klomp".into(),
+        }),
+        &expected,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn write_enum_invalid_version() {
+    let mut writer = NixWriter::builder()
+        .set_version((1, 20).into())
+        .build(Vec::new());
+    let err = writer
+        .write_value(&TestEnum::Pre20(TestU64(42)))
+        .await
+        .unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    assert_eq!("Pre20 is not valid for version 1.20", err.to_string());
+}
@@ -35,7 +35,9 @@ pub fn expand_nix_deserialize_remote(
     let remote = remote.unwrap();
 
     let crate_path = remote.crate_path();
-    let body = nix_deserialize_body_from(crate_path, &remote.attrs).expect("From tokenstream");
+    let body = nix_deserialize_body_from(crate_path, &remote.attrs).ok_or_else(|| {
+        syn::Error::new_spanned(input, "Missing from_str, from or try_from attribute")
+    })?;
     let generics = Generics::default();
     Ok(nix_deserialize_impl(crate_path, remote.ty, &generics, body))
 }
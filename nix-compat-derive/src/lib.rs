@@ -6,7 +6,10 @@
 //!         1. [`#[nix(from_str)]`](#nixfrom_str)
 //!         2. [`#[nix(from = "FromType")]`](#nixfrom--fromtype)
 //!         3. [`#[nix(try_from = "FromType")]`](#nixtry_from--fromtype)
-//!         4. [`#[nix(crate = "...")]`](#nixcrate--)
+//!         4. [`#[nix(display)]`](#nixdisplay)
+//!         5. [`#[nix(into = "IntoType")]`](#nixinto--intotype)
+//!         6. [`#[nix(try_into = "IntoType")]`](#nixtry_into--intotype)
+//!         7. [`#[nix(crate = "...")]`](#nixcrate--)
 //!     2. [Variant attributes](#variant-attributes)
 //!         1. [`#[nix(version = "range")]`](#nixversion--range)
 //!     3. [Field attributes](#field-attributes)
@@ -17,7 +20,10 @@
 //! ## Overview
 //!
 //! This crate contains derive macros and function-like macros for implementing
-//! `NixDeserialize` with less boilerplate.
+//! `NixDeserialize` and `NixSerialize` with less boilerplate.
+//!
+//! All attributes apply to both derives, except for the container attributes
+//! selecting a conversion, which only apply to one direction.
 //!
 //! ### Examples
 //! ```rust
@@ -44,6 +50,16 @@
 //! struct Ignored;
 //! ```
 //!
+//! ```rust
+//! # use nix_compat_derive::{NixDeserialize, NixSerialize};
+//! #
+//! #[derive(NixDeserialize, NixSerialize)]
+//! struct Both {
+//!     number: u64,
+//!     message: String,
+//! };
+//! ```
+//!
 //! ## Attributes
 //!
 //! To customize the derived trait implementations you can add
@@ -156,6 +172,83 @@
 //! }
 //! ```
 //!
+//! ##### `#[nix(display)]`
+//!
+//! When `display` is specified the fields are all ignored and instead the
+//! container is formatted using `Display` and the resulting string is
+//! serialized. This is the counterpart of [`#[nix(from_str)]`](#nixfrom_str)
+//! for `NixSerialize`.
+//!
+//! This means that the container must implement `Display`.
+//!
+//! ###### Example
+//!
+//! ```rust
+//! # use nix_compat_derive::NixSerialize;
+//! #
+//! #[derive(NixSerialize)]
+//! #[nix(display)]
+//! struct MyString(String);
+//! impl std::fmt::Display for MyString {
+//!     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+//!         f.write_str(&self.0)
+//!     }
+//! }
+//! ```
+//!
+//! ##### `#[nix(into = "IntoType")]`
+//!
+//! When `into` is specified the fields are all ignored and instead the
+//! container is cloned and converted to `IntoType` using `Into::into`, and
+//! that value is serialized.
+//!
+//! This means that the container must implement `Clone` and `Into<IntoType>`
+//! and `IntoType` must implement `NixSerialize`.
+//!
+//! ###### Example
+//!
+//! ```rust
+//! # use nix_compat_derive::NixSerialize;
+//! #
+//! #[derive(Clone, NixSerialize)]
+//! #[nix(into="usize")]
+//! struct MyValue(usize);
+//! impl From<MyValue> for usize {
+//!     fn from(val: MyValue) -> Self {
+//!         val.0
+//!     }
+//! }
+//! ```
+//!
+//! ##### `#[nix(try_into = "IntoType")]`
+//!
+//! With `try_into` the container is cloned and converted to `IntoType`
+//! using `TryFrom::try_from`, and that value is serialized.
+//!
+//! This means that the container must implement `Clone`, `IntoType` must
+//! implement `TryFrom<Container>` and `NixSerialize`.
+//! The error returned from `try_from` also needs to implement `Display`.
+//!
+//! ###### Example
+//!
+//! ```rust
+//! # use nix_compat_derive::NixSerialize;
+//! #
+//! #[derive(Clone, NixSerialize)]
+//! #[nix(try_into="u64")]
+//! struct WrongAnswer(usize);
+//! impl TryFrom<WrongAnswer> for u64 {
+//!     type Error = String;
+//!     fn try_from(val: WrongAnswer) -> Result<Self, Self::Error> {
+//!         if val.0 != 42 {
+//!             Ok(val.0 as u64)
+//!         } else {
+//!             Err("Got the answer to life the universe and everything".to_string())
+//!         }
+//!     }
+//! }
+//! ```
+//!
 //! ##### `#[nix(crate = "...")]`
 //!
 //! Specify the path to the `nix-compat` crate instance to use when referring
@@ -171,6 +264,9 @@
 //! the version ranges of all variants combined must cover all versions
 //! without any overlap or the first variant that matches is selected.
 //!
+//! When serializing, writing a variant outside of its version range is an
+//! error.
+//!
 //! ###### Example
 //!
 //! ```rust
@@ -191,7 +287,8 @@
 //! Specifies the protocol version range where this field is included.
 //! The range is for minor version. For example `version = "..20"`
 //! includes the field in protocol versions `1.0` to `1.19` and skips
-//! it in version `1.20` and above.
+//! it in version `1.20` and above. Skipped fields are not written when
+//! serializing.
 //!
 //! ###### Example
 //!
@@ -260,6 +357,7 @@ use syn::{parse_quote, DeriveInput};
 
 mod de;
 mod internal;
+mod ser;
 
 #[proc_macro_derive(NixDeserialize, attributes(nix))]
 pub fn derive_nix_deserialize(item: TokenStream) -> TokenStream {
@@ -301,3 +399,42 @@ pub fn nix_deserialize_remote(item: TokenStream) -> TokenStream {
         .unwrap_or_else(syn::Error::into_compile_error)
         .into()
 }
+
+#[proc_macro_derive(NixSerialize, attributes(nix))]
+pub fn derive_nix_serialize(item: TokenStream) -> TokenStream {
+    let mut input = syn::parse_macro_input!(item as DeriveInput);
+    let nnixrs: syn::Path = parse_quote!(::nix_compat);
+    ser::expand_nix_serialize(nnixrs, &mut input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Macro to implement `NixSerialize` on a type.
+/// Like [nix_deserialize_remote!] this can be used where you can't derive,
+/// but would have used `#[nix(display)]`, `#[nix(into = "IntoType")]` or
+/// `#[nix(try_into = "IntoType")]` if you could.
+///
+/// #### Example
+///
+/// ```rust
+/// # use nix_compat_derive::nix_serialize_remote;
+/// #
+/// #[derive(Clone)]
+/// struct MyU64(u64);
+///
+/// impl From<MyU64> for u64 {
+///     fn from(value: MyU64) -> Self {
+///         value.0
+///     }
+/// }
+///
+/// nix_serialize_remote!(#[nix(into="u64")] MyU64);
+/// ```
+#[proc_macro]
+pub fn nix_serialize_remote(item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as RemoteInput);
+    let crate_path = parse_quote!(::nix_compat);
+    ser::expand_nix_serialize_remote(crate_path, &input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -27,7 +27,43 @@ fn build_serde_json_value(drv: Derivation) -> serde_json::Value {
     })
 }
 
+/// Prints the derivation in the format of `nix derivation show`, keyed by its
+/// derivation path.
+fn print_nix_json(drv: Derivation) {
+    let name = match drv.environment.get("name") {
+        Some(name) => name.to_string(),
+        None => {
+            eprintln!("derivation has no name");
+            return;
+        }
+    };
+
+    let drv_path = match drv.calculate_derivation_path(&name) {
+        Ok(drv_path) => drv_path,
+        Err(e) => {
+            eprintln!("unable to calculate derivation path: {}", e);
+            return;
+        }
+    };
+
+    match drv.to_json_bytes(&name) {
+        Ok(json_bytes) => {
+            let value: serde_json::Value =
+                serde_json::from_slice(&json_bytes).expect("unable to parse JSON");
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({ drv_path.to_absolute_path(): value }))
+                    .expect("unable to serialize")
+            );
+        }
+        Err(e) => eprintln!("unable to serialize derivation: {}", e),
+    }
+}
+
 fn main() {
+    // `--json` prints the format of `nix derivation show` instead.
+    let nix_json = std::env::args().skip(1).any(|arg| arg == "--json");
+
     // read A-Term from stdin
     let mut buf = Vec::new();
     std::io::stdin()
@@ -35,6 +71,7 @@ fn main() {
         .expect("failed to read from stdin");
 
     match Derivation::from_aterm_bytes(&buf) {
+        Ok(drv) if nix_json => print_nix_json(drv),
         Ok(drv) => {
             println!(
                 "{}",
@@ -1,4 +1,5 @@
 //! Contains [DerivationError], exported as [crate::derivation::DerivationError]
+use crate::nixhash::HashAlgo;
 use crate::store_path;
 use thiserror::Error;
 
@@ -16,6 +17,10 @@ pub enum DerivationError {
     MoreThanOneOutputButFixed(),
     #[error("invalid output name for fixed-output derivation: {0}")]
     InvalidOutputNameForFixed(String),
+    #[error("can't mix derivation output kinds")]
+    MixedOutputKinds(),
+    #[error("all content-addressed outputs must use the same hash algo and mode")]
+    InconsistentOutputHashAlgos(),
     #[error("unable to validate output {0}: {1}")]
     InvalidOutput(String, OutputError),
     #[error("unable to validate output {0}: {1}")]
@@ -29,6 +34,8 @@ pub enum DerivationError {
     EmptyInputDerivationOutputNames(String),
     #[error("input derivation {0} output name {1} is invalid")]
     InvalidInputDerivationOutputName(String, String),
+    #[error("dynamic outputs for {0}, which is not an input derivation")]
+    DynamicOutputsWithoutInputDerivation(String),
 
     // input sources
     #[error("unable to parse input sources path {0}: {1}")]
@@ -57,4 +64,24 @@ pub enum OutputError {
     MissingOutputPath,
     #[error("Invalid CAHash: {:?}", .0)]
     InvalidCAHash(CAHash),
+    #[error("Invalid hash algo for text output: {0}")]
+    InvalidTextHashAlgo(HashAlgo),
+    #[error("Invalid hash algo for git output: {0}")]
+    InvalidGitHashAlgo(HashAlgo),
+    #[error("Unexpected output path {0}, it's only known after building")]
+    UnexpectedOutputPath(String),
+}
+
+/// Errors that can occur when converting a [crate::derivation::Derivation]
+/// from or to its JSON representation.
+#[derive(Debug, Error)]
+pub enum JsonError {
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("environment variable {0} is not valid UTF-8")]
+    NonUtf8Environment(String),
+    #[error("invalid structured attrs: {0}")]
+    InvalidStructuredAttrs(serde_json::Error),
+    #[error("invalid output {0}: {1}")]
+    InvalidOutput(String, String),
 }
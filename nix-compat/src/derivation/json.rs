@@ -0,0 +1,317 @@
+//! The JSON format of derivations, as printed by `nix derivation show` and
+//! accepted by `nix derivation add`.
+//!
+//! Contrary to the serde implementation of [Derivation], which mirrors the
+//! struct fields, this includes the name of the derivation, describes
+//! content-addressed outputs by `method`, `hashAlgo` and `hash`, and moves
+//! the `__json` environment variable of derivations using structured
+//! attributes to `structuredAttrs`.
+//!
+//! The older format printed by `nix show-derivation` is accepted as well.
+//!
+//! The `dynamicOutputs` of input derivations map to
+//! [Derivation::input_derivation_dynamic_outputs].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bstr::BString;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{
+    parse_ca_kind_prefix, CAHash, Derivation, DynamicOutputs, JsonError, Output, OutputKind,
+};
+use crate::nixhash::{self, CAHashMode, HashAlgo, NixHash};
+use crate::store_path::StorePath;
+
+/// The environment variable holding the structured attributes.
+const STRUCTURED_ATTRS_ENV: &str = "__json";
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DerivationJson {
+    args: Vec<String>,
+    builder: String,
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    input_drvs: BTreeMap<StorePath<String>, InputDrvJson>,
+    #[serde(default)]
+    input_srcs: BTreeSet<StorePath<String>>,
+    name: String,
+    outputs: BTreeMap<String, OutputJson>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    structured_attrs: Option<Map<String, Value>>,
+    system: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum InputDrvJson {
+    #[serde(rename_all = "camelCase")]
+    Outputs {
+        #[serde(default)]
+        dynamic_outputs: DynamicOutputs,
+        outputs: BTreeSet<String>,
+    },
+    /// `nix show-derivation` only printed the output names.
+    Legacy(BTreeSet<String>),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OutputJson {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash_algo: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    impure: bool,
+    /// Not present in the format printed by `nix show-derivation`, which
+    /// prefixed the hash algo with `r:` for NAR hashing instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<StorePath<String>>,
+}
+
+fn method_str(mode: CAHashMode) -> &'static str {
+    match mode {
+        CAHashMode::Flat => "flat",
+        CAHashMode::Nar => "nar",
+        CAHashMode::Text => "text",
+        CAHashMode::Git => "git",
+    }
+}
+
+impl OutputJson {
+    fn from_output(output: &Output) -> Self {
+        let (mode, algo, hash) = match &output.kind {
+            OutputKind::InputAddressed | OutputKind::Deferred => (None, None, None),
+            OutputKind::Fixed(ca_hash) => (
+                Some(ca_hash.mode()),
+                Some(ca_hash.hash().algo()),
+                Some(ca_hash.hash().to_plain_hex_string()),
+            ),
+            OutputKind::Floating { mode, algo } | OutputKind::Impure { mode, algo } => {
+                (Some(*mode), Some(*algo), None)
+            }
+        };
+
+        Self {
+            hash,
+            hash_algo: algo.map(|algo| algo.to_string()),
+            impure: matches!(output.kind, OutputKind::Impure { .. }),
+            method: mode.map(|mode| method_str(mode).to_string()),
+            path: output.path.clone(),
+        }
+    }
+
+    fn into_output(self, output_name: &str) -> Result<Output, JsonError> {
+        let invalid = |msg: String| JsonError::InvalidOutput(output_name.to_string(), msg);
+
+        let hash_algo = match self.hash_algo {
+            Some(hash_algo) => hash_algo,
+            None if self.hash.is_some() => {
+                return Err(invalid("hash requires hashAlgo to be set".to_string()))
+            }
+            None if self.path.is_none() => {
+                return Ok(Output {
+                    path: None,
+                    kind: OutputKind::Deferred,
+                })
+            }
+            None => {
+                return Ok(Output {
+                    path: self.path,
+                    kind: OutputKind::InputAddressed,
+                })
+            }
+        };
+
+        // Determine the method, from the dedicated field or the prefix of
+        // the hash algo.
+        let (mode, algo) = match self.method.as_deref() {
+            Some(method) => {
+                let mode = match method {
+                    "flat" => CAHashMode::Flat,
+                    "nar" => CAHashMode::Nar,
+                    "text" => CAHashMode::Text,
+                    "git" => CAHashMode::Git,
+                    method => return Err(invalid(format!("unsupported method {method}"))),
+                };
+                let algo = HashAlgo::try_from(hash_algo.as_str())
+                    .map_err(|e| invalid(format!("invalid hash algo: {e}")))?;
+                (mode, algo)
+            }
+            None => parse_ca_kind_prefix(&hash_algo)
+                .map_err(|e| invalid(format!("invalid hash algo: {e}")))?,
+        };
+
+        let kind = match self.hash {
+            None if self.impure => OutputKind::Impure { mode, algo },
+            None => OutputKind::Floating { mode, algo },
+            Some(hash) => {
+                let hash = nixhash::from_str(&hash, Some(&algo.to_string()))
+                    .map_err(|e| invalid(format!("invalid hash: {e}")))?;
+
+                OutputKind::Fixed(match (mode, hash) {
+                    (CAHashMode::Flat, hash) => CAHash::Flat(hash),
+                    (CAHashMode::Nar, hash) => CAHash::Nar(hash),
+                    (CAHashMode::Git, hash @ (NixHash::Sha1(_) | NixHash::Sha256(_))) => {
+                        CAHash::Git(hash)
+                    }
+                    (CAHashMode::Text, NixHash::Sha256(digest)) => CAHash::Text(digest),
+                    (mode @ (CAHashMode::Text | CAHashMode::Git), _) => {
+                        return Err(invalid(format!(
+                            "unsupported hash algo {algo} for {}",
+                            method_str(mode)
+                        )))
+                    }
+                })
+            }
+        };
+
+        Ok(Output {
+            path: self.path,
+            kind,
+        })
+    }
+}
+
+/// Sorts the keys of all objects in the passed JSON object, recursively, the
+/// way Nix writes them.
+/// [Map] is only sorted by itself as long as the `preserve_order` feature of
+/// serde_json is disabled, which any crate in the dependency graph can
+/// enable.
+fn sort_keys(map: Map<String, Value>) -> Map<String, Value> {
+    fn sort_value(value: Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(sort_keys(map)),
+            Value::Array(values) => Value::Array(values.into_iter().map(sort_value).collect()),
+            value => value,
+        }
+    }
+
+    map.into_iter()
+        .map(|(k, v)| (k, sort_value(v)))
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .collect()
+}
+
+impl Derivation {
+    /// Returns the JSON representation of the derivation, as printed by
+    /// `nix derivation show`, for a derivation with the passed name.
+    ///
+    /// This fails if environment variables aren't valid UTF-8, or the
+    /// `__json` environment variable doesn't contain a JSON object.
+    pub fn to_json_bytes(&self, name: &str) -> Result<Vec<u8>, JsonError> {
+        let mut env = BTreeMap::new();
+        let mut structured_attrs = None;
+        for (k, v) in &self.environment {
+            let v = String::from_utf8(v.to_vec())
+                .map_err(|_| JsonError::NonUtf8Environment(k.to_string()))?;
+
+            if k == STRUCTURED_ATTRS_ENV {
+                structured_attrs = Some(sort_keys(
+                    serde_json::from_str(&v).map_err(JsonError::InvalidStructuredAttrs)?,
+                ));
+            } else {
+                env.insert(k.to_string(), v);
+            }
+        }
+
+        let json = DerivationJson {
+            args: self.arguments.clone(),
+            builder: self.builder.clone(),
+            env,
+            input_drvs: self
+                .input_derivations
+                .iter()
+                .map(|(drv_path, outputs)| {
+                    (
+                        drv_path.clone(),
+                        InputDrvJson::Outputs {
+                            dynamic_outputs: self
+                                .input_derivation_dynamic_outputs
+                                .get(drv_path)
+                                .cloned()
+                                .unwrap_or_default(),
+                            outputs: outputs.clone(),
+                        },
+                    )
+                })
+                .collect(),
+            input_srcs: self.input_sources.clone(),
+            name: name.to_string(),
+            outputs: self
+                .outputs
+                .iter()
+                .map(|(output_name, output)| (output_name.clone(), OutputJson::from_output(output)))
+                .collect(),
+            structured_attrs,
+            system: self.system.clone(),
+        };
+
+        Ok(serde_json::to_vec(&json)?)
+    }
+
+    /// Parses a derivation from its JSON representation, as accepted by
+    /// `nix derivation add`, and returns its name along with it.
+    ///
+    /// Structured attributes are re-encoded into the `__json` environment
+    /// variable the way Nix does, as compact JSON with sorted keys.
+    pub fn from_json_bytes(b: &[u8]) -> Result<(String, Derivation), JsonError> {
+        let json: DerivationJson = serde_json::from_slice(b)?;
+
+        let mut environment: BTreeMap<String, BString> =
+            json.env.into_iter().map(|(k, v)| (k, v.into())).collect();
+        if let Some(structured_attrs) = json.structured_attrs {
+            environment.insert(
+                STRUCTURED_ATTRS_ENV.to_string(),
+                serde_json::to_string(&sort_keys(structured_attrs))?.into(),
+            );
+        }
+
+        let mut input_derivations = BTreeMap::new();
+        let mut input_derivation_dynamic_outputs = BTreeMap::new();
+        for (drv_path, input_drv) in json.input_drvs {
+            let outputs = match input_drv {
+                InputDrvJson::Outputs {
+                    dynamic_outputs,
+                    outputs,
+                } => {
+                    if !dynamic_outputs.is_empty() {
+                        input_derivation_dynamic_outputs.insert(drv_path.clone(), dynamic_outputs);
+                    }
+                    outputs
+                }
+                InputDrvJson::Legacy(outputs) => outputs,
+            };
+            input_derivations.insert(drv_path, outputs);
+        }
+
+        let outputs = json
+            .outputs
+            .into_iter()
+            .map(|(output_name, output)| {
+                let output = output.into_output(&output_name)?;
+                Ok((output_name, output))
+            })
+            .collect::<Result<_, JsonError>>()?;
+
+        Ok((
+            json.name,
+            Derivation {
+                arguments: json.args,
+                builder: json.builder,
+                environment,
+                input_derivations,
+                input_derivation_dynamic_outputs,
+                input_sources: json.input_srcs,
+                outputs,
+                system: json.system,
+            },
+        ))
+    }
+}
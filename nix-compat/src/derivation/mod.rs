@@ -1,3 +1,4 @@
+use crate::nixhash::{self, CAHashMode, HashAlgo};
 use crate::store_path::{
     self, build_ca_path, build_output_path, build_text_path, StorePath, StorePathRef,
 };
@@ -8,6 +9,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::io;
 
 mod errors;
+mod json;
 mod output;
 mod parse_error;
 mod parser;
@@ -19,8 +21,8 @@ mod tests;
 
 // Public API of the crate.
 pub use crate::nixhash::{CAHash, NixHash};
-pub use errors::{DerivationError, OutputError};
-pub use output::Output;
+pub use errors::{DerivationError, JsonError, OutputError};
+pub use output::{Output, OutputKind};
 
 use self::write::AtermWriteable;
 
@@ -38,6 +40,17 @@ pub struct Derivation {
     #[serde(rename = "inputDrvs")]
     pub input_derivations: BTreeMap<StorePath<String>, BTreeSet<String>>,
 
+    /// Map from drv path to the outputs used from the derivations produced
+    /// by its outputs, for dynamic derivations.
+    /// Each drv path also needs to be in [Derivation::input_derivations],
+    /// where its output names may then be empty.
+    #[serde(
+        rename = "inputDrvsDynamicOutputs",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub input_derivation_dynamic_outputs: BTreeMap<StorePath<String>, DynamicOutputs>,
+
     /// Plain store paths of additional inputs.
     #[serde(rename = "inputSrcs")]
     pub input_sources: BTreeSet<StorePath<String>>,
@@ -48,24 +61,65 @@ pub struct Derivation {
     pub system: String,
 }
 
+/// Maps output names of a derivation to the outputs used from the
+/// derivations they produce, which may again be dynamic.
+pub type DynamicOutputs = BTreeMap<String, DynamicOutput>;
+
+/// The outputs used from a derivation produced by an output of another
+/// derivation, in dynamic derivations.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicOutput {
+    /// The outputs used from the derivations produced by its outputs.
+    #[serde(default)]
+    pub dynamic_outputs: DynamicOutputs,
+
+    /// Output names used from the produced derivation.
+    pub outputs: BTreeSet<String>,
+}
+
+/// The output names and dynamic outputs used from an input derivation, as
+/// written in ATerm.
+type InputDerivationOutputs<'a> = (&'a BTreeSet<String>, Option<&'a DynamicOutputs>);
+
 impl Derivation {
     /// write the Derivation to the given [std::io::Write], in ATerm format.
     ///
     /// The only errors returns are these when writing to the passed writer.
     pub fn serialize(&self, writer: &mut impl std::io::Write) -> Result<(), io::Error> {
-        self.serialize_with_replacements(writer, &self.input_derivations)
+        self.serialize_with_replacements(
+            writer,
+            self.input_derivations
+                .iter()
+                .map(|(drv_path, output_names)| {
+                    (
+                        drv_path,
+                        (
+                            output_names,
+                            self.input_derivation_dynamic_outputs.get(drv_path),
+                        ),
+                    )
+                }),
+        )
     }
 
     /// Like `serialize` but allow replacing the input_derivations for hash calculations.
-    fn serialize_with_replacements(
+    /// They need to be passed sorted.
+    fn serialize_with_replacements<'a, K: AtermWriteable>(
         &self,
         writer: &mut impl std::io::Write,
-        input_derivations: &BTreeMap<impl AtermWriteable, BTreeSet<String>>,
+        input_derivations: impl IntoIterator<Item = (K, InputDerivationOutputs<'a>)>,
     ) -> Result<(), io::Error> {
         use write::*;
 
-        writer.write_all(write::DERIVATION_PREFIX.as_bytes())?;
-        write_char(writer, write::PAREN_OPEN)?;
+        // Derivations with dynamic outputs use a different prefix, so older
+        // versions of Nix don't misinterpret them.
+        if self.input_derivation_dynamic_outputs.is_empty() {
+            writer.write_all(write::DERIVATION_PREFIX.as_bytes())?;
+            write_char(writer, write::PAREN_OPEN)?;
+        } else {
+            writer.write_all(write::DYNAMIC_DERIVATION_PREFIX.as_bytes())?;
+        }
 
         write_outputs(writer, &self.outputs)?;
         write_char(writer, COMMA)?;
@@ -94,23 +148,31 @@ impl Derivation {
 
     /// return the ATerm serialization.
     pub fn to_aterm_bytes(&self) -> Vec<u8> {
-        self.to_aterm_bytes_with_replacements(&self.input_derivations)
+        let mut buffer: Vec<u8> = Vec::new();
+
+        // invoke serialize and write to the buffer.
+        // Note we only propagate errors writing to the writer in serialize,
+        // which won't panic for the string we write to.
+        self.serialize(&mut buffer).unwrap();
+
+        buffer
     }
 
     /// Like `to_aterm_bytes`, but accept a different BTreeMap for input_derivations.
     /// This is used to render the ATerm representation of a Derivation "modulo
     /// fixed-output derivations".
-    fn to_aterm_bytes_with_replacements(
+    fn to_aterm_bytes_with_replacements<K: AtermWriteable>(
         &self,
-        input_derivations: &BTreeMap<impl AtermWriteable, BTreeSet<String>>,
+        input_derivations: &BTreeMap<K, InputDerivationOutputs<'_>>,
     ) -> Vec<u8> {
         let mut buffer: Vec<u8> = Vec::new();
 
-        // invoke serialize and write to the buffer.
-        // Note we only propagate errors writing to the writer in serialize,
-        // which won't panic for the string we write to.
-        self.serialize_with_replacements(&mut buffer, input_derivations)
-            .unwrap();
+        // See to_aterm_bytes for why this doesn't panic.
+        self.serialize_with_replacements(
+            &mut buffer,
+            input_derivations.iter().map(|(k, v)| (k, *v)),
+        )
+        .unwrap();
 
         buffer
     }
@@ -158,12 +220,12 @@ impl Derivation {
         }
 
         let out_output = self.outputs.get("out")?;
-        let ca_hash = &out_output.ca_hash.as_ref()?;
+        let ca_hash = out_output.ca_hash()?;
 
         Some(
             Sha256::new_with_prefix(format!(
                 "fixed:out:{}{}:{}",
-                ca_kind_prefix(ca_hash),
+                ca_kind_prefix(ca_hash.mode()),
                 ca_hash.hash().to_nix_hex_string(),
                 out_output
                     .path
@@ -188,7 +250,9 @@ impl Derivation {
     ///     recursive call to this function" and that
     ///  - for fixed-output derivations the special
     ///    `fixed:out:${algo}:${digest}:${fodPath}` string is hashed instead of
-    ///    the A-Term.
+    ///    the A-Term, and
+    ///  - for impure derivations, the digest of the string `impure` is
+    ///    returned, as their outputs may differ on every build.
     ///
     /// It's up to the caller of this function to provide a (infallible) lookup
     /// function to query [hash_derivation_modulo] of direct input derivations,
@@ -205,6 +269,14 @@ impl Derivation {
         // call to this function.
         // We call [fn_lookup_hash_derivation_modulo] rather than recursing
         // ourselves, so callers can precompute this.
+        if self
+            .outputs
+            .values()
+            .any(|output| matches!(output.kind, OutputKind::Impure { .. }))
+        {
+            return Sha256::new_with_prefix("impure").finalize().into();
+        }
+
         self.fod_digest().unwrap_or({
             // For each input_derivation, look up the hash derivation modulo,
             // and replace the derivation path in the aterm with it's HEXLOWER digest.
@@ -214,7 +286,13 @@ impl Derivation {
                     .map(|(drv_path, output_names)| {
                         let hash = fn_lookup_hash_derivation_modulo(&drv_path.as_ref());
 
-                        (hash, output_names.to_owned())
+                        (
+                            hash,
+                            (
+                                output_names,
+                                self.input_derivation_dynamic_outputs.get(drv_path),
+                            ),
+                        )
                     }),
             ));
 
@@ -240,7 +318,8 @@ impl Derivation {
     ///
     /// On completion, `self.environment[$outputName]` and
     /// `self.outputs[$outputName].path` are set to the calculated output path for all
-    /// outputs.
+    /// outputs whose path is known upfront. Floating, impure and deferred
+    /// outputs are left untouched, their paths are only known after building.
     pub fn calculate_output_paths(
         &mut self,
         name: &str,
@@ -253,11 +332,15 @@ impl Derivation {
             // footgun prevention mechanism.
             assert!(output.path.is_none());
 
+            if output.is_deferred() {
+                continue;
+            }
+
             let path_name = output_path_name(name, output_name);
 
             // For fixed output derivation we use [build_ca_path], otherwise we
             // use [build_output_path] with [hash_derivation_modulo].
-            let store_path = if let Some(ref hwm) = output.ca_hash {
+            let store_path = if let Some(hwm) = output.ca_hash() {
                 build_ca_path(&path_name, hwm, Vec::<&str>::new(), false).map_err(|e| {
                     DerivationError::InvalidOutputDerivationPath(output_name.to_string(), e)
                 })?
@@ -279,6 +362,41 @@ impl Derivation {
 
         Ok(())
     }
+
+    /// Returns whether the paths of some outputs of the derivation are only
+    /// known after building it (or its inputs).
+    ///
+    /// Derivations depending on such outputs need to have their outputs
+    /// deferred, see [Derivation::defer_outputs].
+    pub fn is_deferred(&self) -> bool {
+        self.outputs.values().any(Output::is_deferred)
+    }
+
+    /// Turns all input-addressed outputs into [OutputKind::Deferred] ones, if
+    /// any input derivation is deferred, as determined by
+    /// [Derivation::is_deferred].
+    ///
+    /// It's up to the caller of this function to provide a lookup function,
+    /// returning that for direct input derivations by their [StorePathRef].
+    /// This needs to be called before [Derivation::calculate_output_paths].
+    pub fn defer_outputs<F>(&mut self, fn_lookup_is_deferred: F)
+    where
+        F: Fn(&StorePathRef) -> bool,
+    {
+        if !self
+            .input_derivations
+            .keys()
+            .any(|drv_path| fn_lookup_is_deferred(&drv_path.as_ref()))
+        {
+            return;
+        }
+
+        for output in self.outputs.values_mut() {
+            if output.kind == OutputKind::InputAddressed {
+                output.kind = OutputKind::Deferred;
+            }
+        }
+    }
 }
 
 /// Calculate the name part of the store path of a derivation [Output].
@@ -294,14 +412,31 @@ fn output_path_name(derivation_name: &str, output_name: &str) -> String {
     output_path_name
 }
 
-/// For a [CAHash], return the "prefix" used for NAR purposes.
-/// For [CAHash::Flat], this is an empty string, for [CAHash::Nar], it's "r:".
-/// Panics for other [CAHash] kinds, as they're not valid in a derivation
-/// context.
-fn ca_kind_prefix(ca_hash: &CAHash) -> &'static str {
-    match ca_hash {
-        CAHash::Flat(_) => "",
-        CAHash::Nar(_) => "r:",
-        _ => panic!("invalid ca hash in derivation context: {:?}", ca_hash),
+/// For a [CAHashMode], return the prefix of the hash algo used in
+/// derivation outputs.
+/// For [CAHashMode::Flat], this is an empty string, for [CAHashMode::Nar],
+/// it's "r:", for [CAHashMode::Text] it's "text:", and for [CAHashMode::Git]
+/// it's "git:".
+fn ca_kind_prefix(mode: CAHashMode) -> &'static str {
+    match mode {
+        CAHashMode::Flat => "",
+        CAHashMode::Nar => "r:",
+        CAHashMode::Text => "text:",
+        CAHashMode::Git => "git:",
     }
 }
+
+/// Parses a hash algo with the prefix returned by [ca_kind_prefix].
+fn parse_ca_kind_prefix(s: &str) -> Result<(CAHashMode, HashAlgo), nixhash::Error> {
+    let (mode, algo) = if let Some(algo) = s.strip_prefix("r:") {
+        (CAHashMode::Nar, algo)
+    } else if let Some(algo) = s.strip_prefix("text:") {
+        (CAHashMode::Text, algo)
+    } else if let Some(algo) = s.strip_prefix("git:") {
+        (CAHashMode::Git, algo)
+    } else {
+        (CAHashMode::Flat, s)
+    };
+
+    Ok((mode, HashAlgo::try_from(algo)?))
+}
@@ -1,18 +1,87 @@
-use crate::nixhash::CAHash;
+use crate::derivation::{ca_kind_prefix, parse_ca_kind_prefix};
+use crate::nixhash::{CAHash, CAHashMode, HashAlgo, NixHash};
 use crate::{derivation::OutputError, store_path::StorePath};
 use serde::de::Unexpected;
+use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize};
-use serde_json::Map;
+use serde_json::{Map, Value};
 use std::borrow::Cow;
 
 /// References the derivation output.
-#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Output {
     /// Store path of build result.
+    /// Only known upfront for [OutputKind::InputAddressed] and
+    /// [OutputKind::Fixed] outputs, once calculated.
     pub path: Option<StorePath<String>>,
 
-    #[serde(flatten)]
-    pub ca_hash: Option<CAHash>, // we can only represent a subset here.
+    pub kind: OutputKind,
+}
+
+/// Describes how the store path of a derivation output is determined.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum OutputKind {
+    /// The path is calculated from the [hash_derivation_modulo] of the
+    /// derivation.
+    ///
+    /// [hash_derivation_modulo]: crate::derivation::Derivation::hash_derivation_modulo
+    #[default]
+    InputAddressed,
+
+    /// Fixed-output: the content hash is known upfront, and the path is
+    /// calculated from it.
+    Fixed(CAHash),
+
+    /// Content-addressed (`__contentAddressed = true`), the content hash (and
+    /// thus the path) is only known after building.
+    Floating { mode: CAHashMode, algo: HashAlgo },
+
+    /// Input-addressed, but depending on outputs whose paths are only known
+    /// after building, so the path can only be calculated once these are
+    /// built.
+    Deferred,
+
+    /// Impure (`__impure = true`), content-addressed after building, but
+    /// never substituted or reused.
+    Impure { mode: CAHashMode, algo: HashAlgo },
+}
+
+impl Serialize for Output {
+    /// Serializes into the format of `nix show-derivation`.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(path) = &self.path {
+            map.serialize_entry("path", path)?;
+        }
+        match &self.kind {
+            OutputKind::InputAddressed | OutputKind::Deferred => {}
+            OutputKind::Fixed(ca_hash) => {
+                map.serialize_entry(
+                    "hash",
+                    &data_encoding::HEXLOWER.encode(ca_hash.hash().digest_as_bytes()),
+                )?;
+                map.serialize_entry(
+                    "hashAlgo",
+                    &format!(
+                        "{}{}",
+                        ca_kind_prefix(ca_hash.mode()),
+                        ca_hash.hash().algo()
+                    ),
+                )?;
+            }
+            OutputKind::Floating { mode, algo } => {
+                map.serialize_entry("hashAlgo", &format!("{}{}", ca_kind_prefix(*mode), algo))?;
+            }
+            OutputKind::Impure { mode, algo } => {
+                map.serialize_entry("hashAlgo", &format!("{}{}", ca_kind_prefix(*mode), algo))?;
+                map.serialize_entry("impure", &true)?;
+            }
+        }
+        map.end()
+    }
 }
 
 impl<'de> Deserialize<'de> for Output {
@@ -21,29 +90,61 @@ impl<'de> Deserialize<'de> for Output {
         D: serde::Deserializer<'de>,
     {
         let fields = Map::deserialize(deserializer)?;
-        let path: &str = fields
+        let path = fields
             .get("path")
-            .ok_or(serde::de::Error::missing_field(
-                "`path` is missing but required for outputs",
-            ))?
-            .as_str()
-            .ok_or(serde::de::Error::invalid_type(
-                serde::de::Unexpected::Other("certainly not a string"),
-                &"a string",
-            ))?;
-
-        let path = StorePath::from_absolute_path(path.as_bytes())
-            .map_err(|_| serde::de::Error::invalid_value(Unexpected::Str(path), &"StorePath"))?;
-        Ok(Self {
-            path: Some(path),
-            ca_hash: CAHash::from_map::<D>(&fields)?,
-        })
+            .map(|path| {
+                let path = path.as_str().ok_or(serde::de::Error::invalid_type(
+                    serde::de::Unexpected::Other("certainly not a string"),
+                    &"a string",
+                ))?;
+                StorePath::from_absolute_path(path.as_bytes()).map_err(|_| {
+                    serde::de::Error::invalid_value(Unexpected::Str(path), &"StorePath")
+                })
+            })
+            .transpose()?;
+
+        // Content-addressed outputs without a hash are floating (or impure).
+        let kind = match (fields.get("hash"), fields.get("hashAlgo")) {
+            (None, Some(Value::String(hash_algo))) => {
+                let (mode, algo) = parse_ca_kind_prefix(hash_algo).map_err(|e| {
+                    serde::de::Error::invalid_value(Unexpected::Other(&e.to_string()), &"hash algo")
+                })?;
+                if fields.get("impure") == Some(&Value::Bool(true)) {
+                    OutputKind::Impure { mode, algo }
+                } else {
+                    OutputKind::Floating { mode, algo }
+                }
+            }
+            _ => match CAHash::from_map::<D>(&fields)? {
+                Some(ca_hash) => OutputKind::Fixed(ca_hash),
+                None if path.is_none() => OutputKind::Deferred,
+                None => OutputKind::InputAddressed,
+            },
+        };
+
+        Ok(Self { path, kind })
     }
 }
 
 impl Output {
     pub fn is_fixed(&self) -> bool {
-        self.ca_hash.is_some()
+        matches!(self.kind, OutputKind::Fixed(_))
+    }
+
+    /// Returns the content hash of fixed outputs.
+    pub fn ca_hash(&self) -> Option<&CAHash> {
+        match &self.kind {
+            OutputKind::Fixed(ca_hash) => Some(ca_hash),
+            _ => None,
+        }
+    }
+
+    /// Whether the path of this output is only known after building.
+    pub fn is_deferred(&self) -> bool {
+        matches!(
+            self.kind,
+            OutputKind::Floating { .. } | OutputKind::Deferred | OutputKind::Impure { .. }
+        )
     }
 
     /// The output path as a string -- use `""` to indicate an unset output path.
@@ -55,16 +156,33 @@ impl Output {
     }
 
     pub fn validate(&self, validate_output_paths: bool) -> Result<(), OutputError> {
-        if let Some(fixed_output_hash) = &self.ca_hash {
-            match fixed_output_hash {
+        match &self.kind {
+            OutputKind::InputAddressed => {}
+            OutputKind::Fixed(fixed_output_hash) => match fixed_output_hash {
                 CAHash::Flat(_) | CAHash::Nar(_) => {
                     // all hashes allowed for Flat, and Nar.
                 }
+                CAHash::Git(NixHash::Sha1(_) | NixHash::Sha256(_)) => {}
+                CAHash::Git(hash) => return Err(OutputError::InvalidGitHashAlgo(hash.algo())),
                 _ => return Err(OutputError::InvalidCAHash(fixed_output_hash.clone())),
+            },
+            OutputKind::Floating { mode, algo } | OutputKind::Impure { mode, algo } => {
+                if *mode == CAHashMode::Text && *algo != HashAlgo::Sha256 {
+                    return Err(OutputError::InvalidTextHashAlgo(*algo));
+                }
+                if *mode == CAHashMode::Git && !matches!(algo, HashAlgo::Sha1 | HashAlgo::Sha256) {
+                    return Err(OutputError::InvalidGitHashAlgo(*algo));
+                }
             }
+            OutputKind::Deferred => {}
         }
 
-        if validate_output_paths && self.path.is_none() {
+        if self.is_deferred() {
+            // The path can't be known yet.
+            if let Some(path) = &self.path {
+                return Err(OutputError::UnexpectedOutputPath(path.to_absolute_path()));
+            }
+        } else if validate_output_paths && self.path.is_none() {
             return Err(OutputError::MissingOutputPath);
         }
         Ok(())
@@ -143,8 +261,8 @@ fn deserialize_with_error_missing_hash_algo_fixed_output() {
     assert!(output.is_err());
 }
 
-/// This ensures that parsing an input with the missing hash but present hash algo will result in a
-/// parsing failure.
+/// This ensures that parsing an input with the missing hash and an invalid hash algo will result
+/// in a parsing failure, rather than a floating output.
 #[test]
 fn deserialize_with_error_missing_hash_fixed_output() {
     let json_bytes = r#"
@@ -157,6 +275,34 @@ fn deserialize_with_error_missing_hash_fixed_output() {
     assert!(output.is_err());
 }
 
+/// Content-addressed outputs without a hash are floating, or impure.
+#[cfg(test)]
+#[rstest::rstest]
+#[case::floating(
+    r#"{ "hashAlgo": "r:sha256" }"#,
+    OutputKind::Floating { mode: CAHashMode::Nar, algo: HashAlgo::Sha256 }
+)]
+#[case::floating_text(
+    r#"{ "hashAlgo": "text:sha256" }"#,
+    OutputKind::Floating { mode: CAHashMode::Text, algo: HashAlgo::Sha256 }
+)]
+#[case::impure(
+    r#"{ "hashAlgo": "sha1", "impure": true }"#,
+    OutputKind::Impure { mode: CAHashMode::Flat, algo: HashAlgo::Sha1 }
+)]
+#[case::deferred(r#"{}"#, OutputKind::Deferred)]
+fn deserialize_without_path(#[case] json_bytes: &str, #[case] expected: OutputKind) {
+    let output: Output = serde_json::from_str(json_bytes).expect("must parse");
+
+    assert_eq!(None, output.path);
+    assert_eq!(expected, output.kind);
+    assert!(output.is_deferred());
+
+    let s = serde_json::to_string(&output).expect("Serialize");
+    let output2: Output = serde_json::from_str(&s).expect("must parse again");
+    assert_eq!(output, output2);
+}
+
 #[test]
 fn serialize_deserialize() {
     let json_bytes = r#"
@@ -3,16 +3,21 @@
 //!
 //! [ATerm]: http://program-transformation.org/Tools/ATermFormat.html
 
+use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::char as nomchar;
-use nom::combinator::{all_consuming, map_res};
+use nom::combinator::{all_consuming, map, map_res, value};
 use nom::multi::{separated_list0, separated_list1};
-use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
+use nom::sequence::{delimited, separated_pair, terminated, tuple};
 use std::collections::{btree_map, BTreeMap, BTreeSet};
 use thiserror;
 
 use crate::derivation::parse_error::{into_nomerror, ErrorKind, NomError, NomResult};
-use crate::derivation::{write, CAHash, Derivation, Output};
+use crate::derivation::{
+    parse_ca_kind_prefix, write, CAHash, Derivation, DynamicOutput, DynamicOutputs, Output,
+    OutputKind,
+};
+use crate::nixhash::{CAHashMode, NixHash};
 use crate::store_path::{self, StorePath};
 use crate::{aterm, nixhash};
 
@@ -42,27 +47,39 @@ pub(crate) fn parse(i: &[u8]) -> Result<Derivation, Error<&[u8]>> {
     }
 }
 
-/// Consume a string containing the algo, and optionally a `r:`
-/// prefix, and a digest (bytes), return a [CAHash::Nar] or [CAHash::Flat].
+/// Consume a string containing the algo, and optionally a `r:`, `text:` or
+/// `git:` prefix, and a digest (bytes), return a [CAHash].
 fn from_algo_and_mode_and_digest<B: AsRef<[u8]>>(
     algo_and_mode: &str,
     digest: B,
 ) -> crate::nixhash::NixHashResult<CAHash> {
-    Ok(match algo_and_mode.strip_prefix("r:") {
-        Some(algo) => nixhash::CAHash::Nar(nixhash::from_algo_and_digest(
-            algo.try_into()?,
-            digest.as_ref(),
-        )?),
-        None => nixhash::CAHash::Flat(nixhash::from_algo_and_digest(
-            algo_and_mode.try_into()?,
-            digest.as_ref(),
-        )?),
+    let (mode, algo) = parse_ca_kind_prefix(algo_and_mode)?;
+    let hash = nixhash::from_algo_and_digest(algo, digest.as_ref())?;
+
+    Ok(match (mode, hash) {
+        (CAHashMode::Flat, hash) => CAHash::Flat(hash),
+        (CAHashMode::Nar, hash) => CAHash::Nar(hash),
+        (CAHashMode::Git, hash @ (NixHash::Sha1(_) | NixHash::Sha256(_))) => CAHash::Git(hash),
+        (CAHashMode::Text, NixHash::Sha256(digest)) => CAHash::Text(digest),
+        (CAHashMode::Text | CAHashMode::Git, hash) => {
+            return Err(nixhash::Error::InvalidAlgo(hash.algo().to_string()))
+        }
     })
 }
 
+/// The digest field of impure outputs.
+const IMPURE_DIGEST: &[u8] = b"impure";
+
 /// Parse one output in ATerm. This is 4 string fields inside parans:
 /// output name, output path, algo (and mode), digest.
 /// Returns the output name and [Output] struct.
+///
+/// The kind of the output is determined by which of the fields are set:
+/// - input-addressed outputs only have a path,
+/// - fixed outputs have all fields set,
+/// - floating outputs only have the algo set,
+/// - impure outputs have the algo set, and `impure` as digest,
+/// - deferred outputs have none of the fields set.
 fn parse_output(i: &[u8]) -> NomResult<&[u8], (String, Output)> {
     delimited(
         nomchar('('),
@@ -78,32 +95,41 @@ fn parse_output(i: &[u8]) -> NomResult<&[u8], (String, Output)> {
             },
             |(output_name, output_path, algo_and_mode, encoded_digest)| {
                 // convert these 4 fields into an [Output].
-                let ca_hash_res = {
-                    if algo_and_mode.is_empty() && encoded_digest.is_empty() {
-                        None
+                let kind_res = if algo_and_mode.is_empty() && encoded_digest.is_empty() {
+                    if output_path.is_empty() {
+                        Ok(OutputKind::Deferred)
                     } else {
-                        match data_encoding::HEXLOWER.decode(&encoded_digest) {
-                            Ok(digest) => {
-                                Some(from_algo_and_mode_and_digest(&algo_and_mode, digest))
-                            }
-                            Err(e) => Some(Err(nixhash::Error::InvalidBase64Encoding(e))),
+                        Ok(OutputKind::InputAddressed)
+                    }
+                } else if encoded_digest.is_empty() || encoded_digest == IMPURE_DIGEST {
+                    parse_ca_kind_prefix(&algo_and_mode).map(|(mode, algo)| {
+                        if encoded_digest.is_empty() {
+                            OutputKind::Floating { mode, algo }
+                        } else {
+                            OutputKind::Impure { mode, algo }
                         }
+                    })
+                } else {
+                    match data_encoding::HEXLOWER.decode(&encoded_digest) {
+                        Ok(digest) => from_algo_and_mode_and_digest(&algo_and_mode, digest)
+                            .map(OutputKind::Fixed),
+                        Err(e) => Err(nixhash::Error::InvalidBase64Encoding(e)),
                     }
-                }
-                .transpose();
+                };
 
-                match ca_hash_res {
-                    Ok(hash_with_mode) => Ok((
+                match kind_res {
+                    Ok(kind) => Ok((
                         output_name,
                         Output {
-                            // TODO: Check if allowing empty paths here actually makes sense
-                            //       or we should make this code stricter.
+                            // Fixed outputs may have an empty path, if it's
+                            // not calculated yet. Validation takes care of
+                            // rejecting paths for outputs that can't have one.
                             path: if output_path.is_empty() {
                                 None
                             } else {
                                 Some(string_to_store_path(i, &output_path)?)
                             },
-                            ca_hash: hash_with_mode,
+                            kind,
                         },
                     )),
                     Err(e) => Err(nom::Err::Failure(NomError {
@@ -148,35 +174,151 @@ fn parse_outputs(i: &[u8]) -> NomResult<&[u8], BTreeMap<String, Output>> {
     }
 }
 
-fn parse_input_derivations(
-    i: &[u8],
-) -> NomResult<&[u8], BTreeMap<StorePath<String>, BTreeSet<String>>> {
-    let (i, input_derivations_list) = parse_kv(aterm::parse_string_list)(i)?;
+/// The output names used from an input derivation, as written in ATerm.
+/// For dynamic derivations, this also contains the outputs used from the
+/// derivations produced by its outputs, recursively.
+#[derive(Clone, Debug, Default)]
+struct InputDerivationNode {
+    output_names: Vec<String>,
+    dynamic_outputs: Vec<(String, InputDerivationNode)>,
+}
 
-    // This is a HashMap of drv paths to a list of output names.
-    let mut input_derivations: BTreeMap<StorePath<String>, BTreeSet<_>> = BTreeMap::new();
+/// Parse the output names used from an input derivation, as a list of strings.
+fn parse_input_derivation_output_names(i: &[u8]) -> nom::IResult<&[u8], InputDerivationNode> {
+    map(aterm::parse_string_list, |output_names| {
+        InputDerivationNode {
+            output_names,
+            ..Default::default()
+        }
+    })(i)
+}
 
-    for (input_derivation, output_names) in input_derivations_list {
-        let mut new_output_names = BTreeSet::new();
-        for output_name in output_names.into_iter() {
-            if new_output_names.contains(&output_name) {
+/// Parse the output names used from an input derivation in a dynamic
+/// derivation. This is either a list of strings, or a tuple of that list
+/// and a list of (output name, [InputDerivationNode]) tuples.
+fn parse_input_derivation_node(i: &[u8]) -> nom::IResult<&[u8], InputDerivationNode> {
+    alt((
+        parse_input_derivation_output_names,
+        map(
+            delimited(
+                nomchar('('),
+                separated_pair(
+                    aterm::parse_string_list,
+                    nomchar(','),
+                    delimited(
+                        nomchar('['),
+                        separated_list0(
+                            nomchar(','),
+                            delimited(
+                                nomchar('('),
+                                separated_pair(
+                                    aterm::parse_string_field,
+                                    nomchar(','),
+                                    parse_input_derivation_node,
+                                ),
+                                nomchar(')'),
+                            ),
+                        ),
+                        nomchar(']'),
+                    ),
+                ),
+                nomchar(')'),
+            ),
+            |(output_names, dynamic_outputs)| InputDerivationNode {
+                output_names,
+                dynamic_outputs,
+            },
+        ),
+    ))(i)
+}
+
+/// Collect the output names of an input derivation into a set, failing on
+/// duplicates.
+fn output_names_to_set<'i>(
+    i: &'i [u8],
+    input_derivation: &str,
+    output_names: Vec<String>,
+) -> Result<BTreeSet<String>, nom::Err<NomError<&'i [u8]>>> {
+    let mut new_output_names = BTreeSet::new();
+    for output_name in output_names.into_iter() {
+        if new_output_names.contains(&output_name) {
+            return Err(nom::Err::Failure(NomError {
+                input: i,
+                code: ErrorKind::DuplicateInputDerivationOutputName(
+                    input_derivation.to_string(),
+                    output_name.to_string(),
+                ),
+            }));
+        }
+        new_output_names.insert(output_name);
+    }
+
+    Ok(new_output_names)
+}
+
+/// Collect the dynamic outputs of an input derivation into [DynamicOutputs],
+/// failing on duplicate output names.
+fn to_dynamic_outputs<'i>(
+    i: &'i [u8],
+    input_derivation: &str,
+    dynamic_outputs: Vec<(String, InputDerivationNode)>,
+) -> Result<DynamicOutputs, nom::Err<NomError<&'i [u8]>>> {
+    let mut new_dynamic_outputs = DynamicOutputs::new();
+    for (output_name, node) in dynamic_outputs {
+        match new_dynamic_outputs.entry(output_name) {
+            btree_map::Entry::Vacant(e) => {
+                e.insert(DynamicOutput {
+                    outputs: output_names_to_set(i, input_derivation, node.output_names)?,
+                    dynamic_outputs: to_dynamic_outputs(i, input_derivation, node.dynamic_outputs)?,
+                });
+            }
+            btree_map::Entry::Occupied(e) => {
                 return Err(nom::Err::Failure(NomError {
                     input: i,
-                    code: ErrorKind::DuplicateInputDerivationOutputName(
-                        input_derivation.to_string(),
-                        output_name.to_string(),
-                    ),
+                    code: ErrorKind::DuplicateMapKey(e.key().clone()),
                 }));
             }
-            new_output_names.insert(output_name);
         }
+    }
+
+    Ok(new_dynamic_outputs)
+}
+
+/// Input derivations with their output names, and the dynamic outputs of
+/// those that have any.
+type InputDerivations = (
+    BTreeMap<StorePath<String>, BTreeSet<String>>,
+    BTreeMap<StorePath<String>, DynamicOutputs>,
+);
+
+/// Parse the input derivations. Dynamic outputs are only accepted if
+/// `dynamic` is set, which is the case for derivations with the
+/// [write::DYNAMIC_DERIVATION_PREFIX].
+fn parse_input_derivations(i: &[u8], dynamic: bool) -> NomResult<&[u8], InputDerivations> {
+    let (i, input_derivations_list) = if dynamic {
+        parse_kv(parse_input_derivation_node)(i)?
+    } else {
+        parse_kv(parse_input_derivation_output_names)(i)?
+    };
 
-        let input_derivation = string_to_store_path(i, input_derivation.as_str())?;
+    // This is a HashMap of drv paths to a list of output names.
+    let mut input_derivations: BTreeMap<StorePath<String>, BTreeSet<_>> = BTreeMap::new();
+    let mut input_derivation_dynamic_outputs = BTreeMap::new();
+
+    for (input_derivation, node) in input_derivations_list {
+        let output_names = output_names_to_set(i, &input_derivation, node.output_names)?;
+        let dynamic_outputs = to_dynamic_outputs(i, &input_derivation, node.dynamic_outputs)?;
 
-        input_derivations.insert(input_derivation, new_output_names);
+        let input_derivation: StorePath<String> =
+            string_to_store_path(i, input_derivation.as_str())?;
+
+        if !dynamic_outputs.is_empty() {
+            input_derivation_dynamic_outputs.insert(input_derivation.clone(), dynamic_outputs);
+        }
+        input_derivations.insert(input_derivation, output_names);
     }
 
-    Ok((i, input_derivations))
+    Ok((i, (input_derivations, input_derivation_dynamic_outputs)))
 }
 
 fn parse_input_sources(i: &[u8]) -> NomResult<&[u8], BTreeSet<StorePath<String>>> {
@@ -225,53 +367,58 @@ where
 
 pub fn parse_derivation(i: &[u8]) -> NomResult<&[u8], Derivation> {
     use nom::Parser;
-    preceded(
-        tag(write::DERIVATION_PREFIX),
-        delimited(
-            // inside parens
-            nomchar('('),
-            // tuple requires all errors to be of the same type, so we need to be a
-            // bit verbose here wrapping generic IResult into [NomATermResult].
-            tuple((
-                // parse outputs
-                terminated(parse_outputs, nomchar(',')),
-                // // parse input derivations
-                terminated(parse_input_derivations, nomchar(',')),
-                // // parse input sources
-                terminated(parse_input_sources, nomchar(',')),
-                // // parse system
-                |i| terminated(aterm::parse_string_field, nomchar(','))(i).map_err(into_nomerror),
-                // // parse builder
-                |i| terminated(aterm::parse_string_field, nomchar(','))(i).map_err(into_nomerror),
-                // // parse arguments
-                |i| terminated(aterm::parse_string_list, nomchar(','))(i).map_err(into_nomerror),
-                // parse environment
-                parse_kv(aterm::parse_bytes_field),
-            )),
-            nomchar(')'),
-        )
-        .map(
-            |(
-                outputs,
+
+    // Derivations with dynamic outputs have a different prefix, which
+    // already includes the opening paren.
+    let (i, dynamic) = alt((
+        value(false, tuple((tag(write::DERIVATION_PREFIX), nomchar('(')))),
+        value(true, tag(write::DYNAMIC_DERIVATION_PREFIX)),
+    ))(i)?;
+
+    terminated(
+        // tuple requires all errors to be of the same type, so we need to be a
+        // bit verbose here wrapping generic IResult into [NomATermResult].
+        tuple((
+            // parse outputs
+            terminated(parse_outputs, nomchar(',')),
+            // // parse input derivations
+            terminated(move |i| parse_input_derivations(i, dynamic), nomchar(',')),
+            // // parse input sources
+            terminated(parse_input_sources, nomchar(',')),
+            // // parse system
+            |i| terminated(aterm::parse_string_field, nomchar(','))(i).map_err(into_nomerror),
+            // // parse builder
+            |i| terminated(aterm::parse_string_field, nomchar(','))(i).map_err(into_nomerror),
+            // // parse arguments
+            |i| terminated(aterm::parse_string_list, nomchar(','))(i).map_err(into_nomerror),
+            // parse environment
+            parse_kv(aterm::parse_bytes_field),
+        )),
+        nomchar(')'),
+    )
+    .map(
+        |(
+            outputs,
+            (input_derivations, input_derivation_dynamic_outputs),
+            input_sources,
+            system,
+            builder,
+            arguments,
+            environment,
+        )| {
+            Derivation {
+                arguments,
+                builder,
+                environment,
                 input_derivations,
+                input_derivation_dynamic_outputs,
                 input_sources,
+                outputs,
                 system,
-                builder,
-                arguments,
-                environment,
-            )| {
-                Derivation {
-                    arguments,
-                    builder,
-                    environment,
-                    input_derivations,
-                    input_sources,
-                    outputs,
-                    system,
-                }
-            },
-        ),
-    )(i)
+            }
+        },
+    )
+    .parse(i)
 }
 
 /// Parse a list of key/value pairs into a BTreeMap.
@@ -337,8 +484,10 @@ mod tests {
 
     use crate::{
         derivation::{
-            parse_error::ErrorKind, parser::from_algo_and_mode_and_digest, CAHash, NixHash, Output,
+            parse_error::ErrorKind, parser::from_algo_and_mode_and_digest, CAHash, DynamicOutput,
+            NixHash, Output, OutputKind,
         },
+        nixhash::{CAHashMode, HashAlgo},
         store_path::StorePath,
     };
     use bstr::{BString, ByteSlice};
@@ -358,7 +507,7 @@ mod tests {
                     StorePath::from_bytes(b"2vixb94v0hy2xc6p7mbnxxcyc095yyia-has-multi-out-lib")
                         .unwrap(),
                 ),
-                ca_hash: None,
+                kind: OutputKind::InputAddressed,
             },
         );
         b.insert(
@@ -370,7 +519,7 @@ mod tests {
                     )
                     .unwrap(),
                 ),
-                ca_hash: None,
+                kind: OutputKind::InputAddressed,
             },
         );
         b
@@ -460,12 +609,69 @@ mod tests {
         #[case] input: &'static [u8],
         #[case] expected: &BTreeMap<StorePath<String>, BTreeSet<String>>,
     ) {
-        let (rest, parsed) = super::parse_input_derivations(input).expect("must parse");
+        let (rest, (parsed, parsed_dynamic)) =
+            super::parse_input_derivations(input, false).expect("must parse");
 
         assert_eq!(expected, &parsed, "parsed mismatch");
+        assert!(parsed_dynamic.is_empty(), "no dynamic outputs expected");
         assert!(rest.is_empty(), "rest must be empty");
     }
 
+    /// Ensure parsing input derivations with dynamic outputs works, and is
+    /// only accepted for dynamic derivations.
+    #[test]
+    fn parse_input_derivations_dynamic() {
+        let input = format!(
+            "[(\"{0}\",[\"out\"]),(\"{1}\",([],[(\"out\",([\"lib\"],[(\"dev\",[\"out\"])]))]))]",
+            "/nix/store/8bjm87p310sb7r2r0sg4xrynlvg86j8k-hello-2.12.1.tar.gz.drv",
+            "/nix/store/p3jc8aw45dza6h52v81j7lk69khckmcj-bash-5.2-p15.drv"
+        );
+
+        let (rest, (parsed, parsed_dynamic)) =
+            super::parse_input_derivations(input.as_bytes(), true).expect("must parse");
+        assert!(rest.is_empty(), "rest must be empty");
+
+        let bash_drv =
+            StorePath::from_bytes(b"p3jc8aw45dza6h52v81j7lk69khckmcj-bash-5.2-p15.drv").unwrap();
+        assert_eq!(
+            BTreeMap::from([
+                (
+                    StorePath::from_bytes(
+                        b"8bjm87p310sb7r2r0sg4xrynlvg86j8k-hello-2.12.1.tar.gz.drv"
+                    )
+                    .unwrap(),
+                    BTreeSet::from(["out".to_string()])
+                ),
+                (bash_drv.clone(), BTreeSet::new()),
+            ]),
+            parsed,
+            "parsed mismatch"
+        );
+        assert_eq!(
+            BTreeMap::from([(
+                bash_drv,
+                BTreeMap::from([(
+                    "out".to_string(),
+                    DynamicOutput {
+                        outputs: BTreeSet::from(["lib".to_string()]),
+                        dynamic_outputs: BTreeMap::from([(
+                            "dev".to_string(),
+                            DynamicOutput {
+                                outputs: BTreeSet::from(["out".to_string()]),
+                                dynamic_outputs: BTreeMap::new(),
+                            }
+                        )]),
+                    }
+                )])
+            )]),
+            parsed_dynamic,
+            "parsed dynamic outputs mismatch"
+        );
+
+        super::parse_input_derivations(input.as_bytes(), false)
+            .expect_err("must fail without dynamic prefix");
+    }
+
     /// Ensures the input derivation parser complains about duplicate output names
     #[test]
     fn parse_input_derivations_fail_dup_output_names() {
@@ -474,7 +680,7 @@ mod tests {
             "/nix/store/8bjm87p310sb7r2r0sg4xrynlvg86j8k-hello-2.12.1.tar.gz.drv",
             "/nix/store/p3jc8aw45dza6h52v81j7lk69khckmcj-bash-5.2-p15.drv"
         );
-        let e = super::parse_input_derivations(input_str.as_bytes()).expect_err("must fail");
+        let e = super::parse_input_derivations(input_str.as_bytes(), false).expect_err("must fail");
 
         match e {
             nom::Err::Failure(e) => {
@@ -537,7 +743,7 @@ mod tests {
         ("out".to_string(), Output {
             path: Some(
                 StorePathRef::from_absolute_path("/nix/store/5vyvcwah9l9kf07d52rcgdk70g2f4y13-foo".as_bytes()).unwrap().to_owned()),
-            ca_hash: None
+            kind: OutputKind::InputAddressed,
         })
     )]
     #[case::fod(
@@ -546,10 +752,38 @@ mod tests {
             path: Some(
                 StorePathRef::from_absolute_path(
                 "/nix/store/4q0pg5zpfmznxscq3avycvf9xdvx50n3-bar".as_bytes()).unwrap().to_owned()),
-            ca_hash: Some(from_algo_and_mode_and_digest("r:sha256",
+            kind: OutputKind::Fixed(from_algo_and_mode_and_digest("r:sha256",
                    data_encoding::HEXLOWER.decode(b"08813cbee9903c62be4c5027726a418a300da4500b2d369d3af9286f4815ceba").unwrap()            ).unwrap()),
         })
      )]
+    #[case::floating(
+        br#"("out","","r:sha256","")"#,
+        ("out".to_string(), Output {
+            path: None,
+            kind: OutputKind::Floating { mode: CAHashMode::Nar, algo: HashAlgo::Sha256 },
+        })
+    )]
+    #[case::floating_text(
+        br#"("out","","text:sha256","")"#,
+        ("out".to_string(), Output {
+            path: None,
+            kind: OutputKind::Floating { mode: CAHashMode::Text, algo: HashAlgo::Sha256 },
+        })
+    )]
+    #[case::impure(
+        br#"("out","","sha256","impure")"#,
+        ("out".to_string(), Output {
+            path: None,
+            kind: OutputKind::Impure { mode: CAHashMode::Flat, algo: HashAlgo::Sha256 },
+        })
+    )]
+    #[case::deferred(
+        br#"("out","","","")"#,
+        ("out".to_string(), Output {
+            path: None,
+            kind: OutputKind::Deferred,
+        })
+    )]
     fn parse_output(#[case] input: &[u8], #[case] expected: (String, Output)) {
         let (rest, parsed) = super::parse_output(input).expect("must parse");
         assert!(rest.is_empty());
@@ -570,6 +804,7 @@ mod tests {
     #[rstest]
     #[case::sha256_flat("sha256", &DIGEST_SHA256, CAHash::Flat(NIXHASH_SHA256.clone()))]
     #[case::sha256_recursive("r:sha256", &DIGEST_SHA256, CAHash::Nar(NIXHASH_SHA256.clone()))]
+    #[case::sha256_text("text:sha256", &DIGEST_SHA256, CAHash::Text(DIGEST_SHA256))]
     fn test_from_algo_and_mode_and_digest(
         #[case] algo_and_mode: &str,
         #[case] digest: &[u8],
@@ -585,5 +820,8 @@ mod tests {
     fn from_algo_and_mode_and_digest_failure() {
         assert!(from_algo_and_mode_and_digest("r:sha256", []).is_err());
         assert!(from_algo_and_mode_and_digest("ha256", DIGEST_SHA256).is_err());
+        assert!(from_algo_and_mode_and_digest("text:sha1", [0; 20]).is_err());
+        assert!(from_algo_and_mode_and_digest("git:md5", [0; 16]).is_err());
+        assert!(from_algo_and_mode_and_digest("git:sha512", [0; 64]).is_err());
     }
 }
@@ -1,13 +1,14 @@
 use super::parse_error::ErrorKind;
-use crate::derivation::output::Output;
+use crate::derivation::output::{Output, OutputKind};
 use crate::derivation::parse_error::NomError;
 use crate::derivation::parser::Error;
-use crate::derivation::Derivation;
+use crate::derivation::{Derivation, DerivationError, DynamicOutput, JsonError};
+use crate::nixhash::{CAHashMode, HashAlgo};
 use crate::store_path::StorePath;
 use bstr::{BStr, BString};
 use hex_literal::hex;
 use rstest::rstest;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -139,6 +140,239 @@ fn from_aterm_bytes_trailer() {
     Derivation::from_aterm_bytes(&buf).expect_err("must fail");
 }
 
+/// Returns the derivation name from the path of a fixture.
+fn drv_name(path_to_drv_file: &Path) -> String {
+    let file_name = path_to_drv_file.file_name().unwrap().to_str().unwrap();
+    let (_digest, name) = file_name.split_once('-').unwrap();
+    name.strip_suffix(".drv").unwrap().to_string()
+}
+
+/// Converts derivations in ATerm representation to the JSON format used by
+/// `nix derivation show`, parses that again, and ensures we end up with the
+/// same ATerm representation.
+#[rstest]
+fn json_roundtrip(
+    #[files("src/derivation/tests/derivation_tests/ok/*.drv")]
+    #[exclude("(cp1252)|(latin1)")] // non-UTF-8 env can't be represented in JSON
+    path_to_drv_file: PathBuf,
+) {
+    let aterm_bytes = fs::read(&path_to_drv_file).expect("unable to read .drv");
+    let derivation = Derivation::from_aterm_bytes(&aterm_bytes).expect("must succeed");
+    let name = drv_name(&path_to_drv_file);
+
+    let json_bytes = derivation.to_json_bytes(&name).expect("must serialize");
+    let (parsed_name, parsed_drv) =
+        Derivation::from_json_bytes(&json_bytes).expect("must deserialize");
+
+    assert_eq!(name, parsed_name);
+    assert_eq!(derivation, parsed_drv);
+    assert_eq!(
+        &aterm_bytes,
+        &BString::new(parsed_drv.to_aterm_bytes()),
+        "expected serialized ATerm to match initial input"
+    );
+}
+
+/// Parses the fixtures, which use the format of `nix show-derivation`
+/// (without a name), with the JSON parser.
+#[rstest]
+fn from_json_bytes_legacy(
+    #[files("src/derivation/tests/derivation_tests/ok/*.drv")]
+    #[exclude("(cp1252)|(latin1)")] // skip JSON files known to fail parsing
+    path_to_drv_file: PathBuf,
+) {
+    let json_bytes =
+        fs::read(path_to_drv_file.with_extension("drv.json")).expect("unable to read JSON");
+    let mut json: serde_json::Value =
+        serde_json::from_slice(&json_bytes).expect("JSON was not well-formatted");
+    json["name"] = drv_name(&path_to_drv_file).into();
+
+    let (_, derivation) =
+        Derivation::from_json_bytes(&serde_json::to_vec(&json).unwrap()).expect("must deserialize");
+
+    let expected = fs::read(&path_to_drv_file).expect("unable to read .drv");
+    assert_eq!(expected, BStr::new(&derivation.to_aterm_bytes()));
+}
+
+#[test]
+fn from_json_bytes_structured_attrs() {
+    let json = br#"{
+        "args": [],
+        "builder": ":",
+        "env": {
+            "out": "/nix/store/6a39dl014j57bqka7qx25k0vb20vkqm6-structured-attrs"
+        },
+        "inputDrvs": {},
+        "inputSrcs": [],
+        "name": "structured-attrs",
+        "outputs": {
+            "out": {
+                "path": "/nix/store/6a39dl014j57bqka7qx25k0vb20vkqm6-structured-attrs"
+            }
+        },
+        "structuredAttrs": {
+            "system": ":",
+            "name": "structured-attrs",
+            "builder": ":"
+        },
+        "system": ":"
+    }"#;
+
+    let (name, derivation) = Derivation::from_json_bytes(json).expect("must deserialize");
+    assert_eq!("structured-attrs", name);
+
+    let expected = fs::read(format!(
+        "{}/ok/{}",
+        RESOURCES_PATHS, "9lj1lkjm2ag622mh4h9rpy6j607an8g2-structured-attrs.drv"
+    ))
+    .expect("unable to read .drv");
+    assert_eq!(expected, BStr::new(&derivation.to_aterm_bytes()));
+}
+
+/// Structured attrs are written with sorted keys, including nested objects.
+#[test]
+fn from_json_bytes_structured_attrs_sorted() {
+    let json = br#"{
+        "args": [],
+        "builder": ":",
+        "env": {},
+        "name": "structured-attrs",
+        "outputs": { "out": {} },
+        "structuredAttrs": {
+            "z": [{ "b": 1, "a": 2 }],
+            "y": { "d": true, "c": { "f": null, "e": "" } }
+        },
+        "system": ":"
+    }"#;
+
+    let (_, derivation) = Derivation::from_json_bytes(json).expect("must deserialize");
+    assert_eq!(
+        BStr::new(r#"{"y":{"c":{"e":"","f":null},"d":true},"z":[{"a":2,"b":1}]}"#),
+        derivation.environment["__json"]
+    );
+
+    let json = derivation.to_json_bytes("structured-attrs").unwrap();
+    assert!(BStr::new(&json).ends_with(
+        br#""structuredAttrs":{"y":{"c":{"e":"","f":null},"d":true},"z":[{"a":2,"b":1}]},"system":":"}"#
+    ));
+}
+
+/// Git hashes only exist for sha1 and sha256, other algos are rejected.
+#[rstest]
+#[case::md5("md5", "d41d8cd98f00b204e9800998ecf8427e")]
+#[case::sha512(
+    "sha512",
+    "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e"
+)]
+fn from_json_bytes_git_hash_algo(#[case] hash_algo: &str, #[case] hash: &str) {
+    let json = serde_json::json!({
+        "args": [],
+        "builder": ":",
+        "env": {},
+        "name": "foo",
+        "outputs": { "out": { "hash": hash, "hashAlgo": hash_algo, "method": "git" } },
+        "system": ":"
+    });
+
+    let err =
+        Derivation::from_json_bytes(&serde_json::to_vec(&json).unwrap()).expect_err("must fail");
+    assert!(matches!(err, JsonError::InvalidOutput(output_name, _) if output_name == "out"));
+}
+
+/// Dynamic outputs of input derivations are parsed from JSON, written to
+/// ATerm with the dynamic derivation prefix, and round-trip.
+#[test]
+fn from_json_bytes_dynamic_outputs() {
+    let json = br#"{
+        "args": [],
+        "builder": ":",
+        "env": {},
+        "inputDrvs": {
+            "/nix/store/0hm2f1psjpcwg8fijsmr4wwxrx59s092-bar.drv": {
+                "dynamicOutputs": {
+                    "out": { "dynamicOutputs": {}, "outputs": ["out"] }
+                },
+                "outputs": []
+            }
+        },
+        "inputSrcs": [],
+        "name": "foo",
+        "outputs": { "out": { "hashAlgo": "sha256", "method": "nar" } },
+        "system": ":"
+    }"#;
+
+    let (name, derivation) = Derivation::from_json_bytes(json).expect("must deserialize");
+    assert_eq!("foo", name);
+    derivation.validate(true).expect("must validate");
+
+    let bar_drv = StorePath::from_bytes(b"0hm2f1psjpcwg8fijsmr4wwxrx59s092-bar.drv").unwrap();
+    assert_eq!(
+        BTreeMap::from([(bar_drv.clone(), BTreeSet::new())]),
+        derivation.input_derivations
+    );
+    assert_eq!(
+        BTreeMap::from([(
+            bar_drv,
+            BTreeMap::from([(
+                "out".to_string(),
+                DynamicOutput {
+                    dynamic_outputs: BTreeMap::new(),
+                    outputs: BTreeSet::from(["out".to_string()]),
+                }
+            )])
+        )]),
+        derivation.input_derivation_dynamic_outputs
+    );
+
+    let aterm_bytes = derivation.to_aterm_bytes();
+    assert_eq!(
+        BStr::new(concat!(
+            r#"DrvWithVersion("xp-dyn-drv",[("out","","r:sha256","")],"#,
+            r#"[("/nix/store/0hm2f1psjpcwg8fijsmr4wwxrx59s092-bar.drv",([],[("out",["out"])]))],"#,
+            r#"[],":",":",[],[])"#
+        )),
+        BStr::new(&aterm_bytes)
+    );
+    assert_eq!(
+        derivation,
+        Derivation::from_aterm_bytes(&aterm_bytes).expect("must parse")
+    );
+
+    let json = derivation.to_json_bytes(&name).expect("must serialize");
+    assert_eq!(
+        (name, derivation),
+        Derivation::from_json_bytes(&json).expect("must deserialize")
+    );
+}
+
+/// Dynamic outputs need their drv to be an input derivation.
+#[test]
+fn validate_dynamic_outputs_without_input_derivation() {
+    let derivation = Derivation {
+        builder: ":".to_string(),
+        system: ":".to_string(),
+        input_derivation_dynamic_outputs: BTreeMap::from([(
+            StorePath::from_bytes(b"0hm2f1psjpcwg8fijsmr4wwxrx59s092-bar.drv").unwrap(),
+            BTreeMap::from([(
+                "out".to_string(),
+                DynamicOutput {
+                    dynamic_outputs: BTreeMap::new(),
+                    outputs: BTreeSet::from(["out".to_string()]),
+                },
+            )]),
+        )]),
+        outputs: BTreeMap::from([("out".to_string(), Output::default())]),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        DerivationError::DynamicOutputsWithoutInputDerivation(
+            "0hm2f1psjpcwg8fijsmr4wwxrx59s092-bar.drv".to_string()
+        ),
+        derivation.validate(false).expect_err("must fail")
+    );
+}
+
 #[rstest]
 #[case::fixed_sha256("bar", "0hm2f1psjpcwg8fijsmr4wwxrx59s092-bar.drv")]
 #[case::simple_sha256("foo", "4wvvbi4jwn0prsdxb7vs673qa5h9gr7x-foo.drv")]
@@ -325,7 +559,7 @@ fn output_path_construction() {
         "out".to_string(),
         Output {
             path: None, // will be calculated
-            ca_hash: Some(crate::nixhash::CAHash::Nar(
+            kind: OutputKind::Fixed(crate::nixhash::CAHash::Nar(
                 crate::nixhash::from_algo_and_digest(
                     crate::nixhash::HashAlgo::Sha256,
                     &data_encoding::HEXLOWER
@@ -396,7 +630,7 @@ fn output_path_construction() {
         "out".to_string(),
         Output {
             path: None, // will be calculated
-            ca_hash: None,
+            kind: OutputKind::InputAddressed,
         },
     );
 
@@ -434,3 +668,100 @@ fn output_path_construction() {
             .expect("must succeed")
     );
 }
+
+/// A content-addressed derivation, as produced by `__contentAddressed = true`.
+/// Its output environment variable holds the placeholder of the output.
+const CA_FLOATING_ATERM: &[u8] = br#"Derive([("dev","","r:sha256",""),("out","","r:sha256","")],[],[],":",":",[],[("builder",":"),("dev","/02qcpld1y6xhs5gz9bchpxaw0xdhmsp5dv88lh25r2ss44kh8dxz"),("name","ca"),("out","/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9"),("outputs","dev out"),("system",":")])"#;
+
+/// Parses a derivation with floating outputs, and ensures they're left alone
+/// by the output path calculation.
+#[test]
+fn ca_floating() {
+    let mut derivation = Derivation::from_aterm_bytes(CA_FLOATING_ATERM).expect("must succeed");
+
+    for output in derivation.outputs.values() {
+        assert_eq!(
+            OutputKind::Floating {
+                mode: CAHashMode::Nar,
+                algo: HashAlgo::Sha256
+            },
+            output.kind
+        );
+    }
+    assert!(derivation.is_deferred());
+    assert_eq!(
+        CA_FLOATING_ATERM,
+        BStr::new(&derivation.to_aterm_bytes()),
+        "expected serialized ATerm to match initial input"
+    );
+
+    let expected = derivation.clone();
+    let hash_derivation_modulo = derivation.hash_derivation_modulo(|_| panic!("no inputs"));
+    derivation
+        .calculate_output_paths("ca", &hash_derivation_modulo)
+        .unwrap();
+    assert_eq!(expected, derivation);
+
+    let json_bytes = derivation.to_json_bytes("ca").expect("must serialize");
+    let (_, parsed_drv) = Derivation::from_json_bytes(&json_bytes).expect("must deserialize");
+    assert_eq!(derivation, parsed_drv);
+}
+
+/// Derivations depending on floating outputs get their input-addressed
+/// outputs deferred.
+#[test]
+fn defer_outputs() {
+    let ca_drv = Derivation::from_aterm_bytes(CA_FLOATING_ATERM).expect("must succeed");
+    let ca_drv_path = ca_drv.calculate_derivation_path("ca").unwrap();
+
+    let mut derivation = Derivation {
+        builder: ":".to_string(),
+        system: ":".to_string(),
+        environment: BTreeMap::from([("out".to_string(), "".into())]),
+        input_derivations: BTreeMap::from([(ca_drv_path.clone(), BTreeSet::from(["out".into()]))]),
+        outputs: BTreeMap::from([("out".to_string(), Output::default())]),
+        ..Default::default()
+    };
+
+    derivation.defer_outputs(|drv_path| {
+        assert_eq!(ca_drv_path.as_ref(), *drv_path);
+        ca_drv.is_deferred()
+    });
+    assert_eq!(OutputKind::Deferred, derivation.outputs["out"].kind);
+    derivation.validate(true).expect("must validate");
+
+    let hash_derivation_modulo =
+        derivation.hash_derivation_modulo(|_| ca_drv.hash_derivation_modulo(|_| unreachable!()));
+    derivation
+        .calculate_output_paths("foo", &hash_derivation_modulo)
+        .unwrap();
+    assert_eq!(None, derivation.outputs["out"].path);
+
+    let aterm_bytes = derivation.to_aterm_bytes();
+    assert!(aterm_bytes.starts_with(br#"Derive([("out","","","")]"#));
+    assert_eq!(
+        derivation,
+        Derivation::from_aterm_bytes(&aterm_bytes).expect("must succeed")
+    );
+}
+
+/// Impure derivations have a constant hash modulo, as their outputs can
+/// differ on every build.
+#[test]
+fn hash_derivation_modulo_impure() {
+    let aterm = br#"Derive([("out","","r:sha256","impure")],[],[],":",":",[],[("builder",":"),("name","impure"),("out","/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9"),("system",":")])"#;
+    let derivation = Derivation::from_aterm_bytes(aterm).expect("must succeed");
+
+    assert_eq!(
+        OutputKind::Impure {
+            mode: CAHashMode::Nar,
+            algo: HashAlgo::Sha256
+        },
+        derivation.outputs["out"].kind
+    );
+    assert_eq!(aterm, BStr::new(&derivation.to_aterm_bytes()));
+    assert_eq!(
+        hex!("ddffc26775f61118da8597d1f2f62c544ec92461b31b5b1075ed8689a7ee8292"),
+        derivation.hash_derivation_modulo(|_| panic!("must not be called"))
+    );
+}
@@ -1,6 +1,62 @@
-use crate::derivation::{Derivation, DerivationError};
+use std::mem;
+
+use crate::derivation::{Derivation, DerivationError, DynamicOutputs, OutputKind};
 use crate::store_path;
 
+/// Checks whether the output name of an input derivation is valid.
+///
+/// Empty output names are invalid.
+///
+/// `drv` is an invalid output name too, as this would cause
+/// a `builtins.derivation` call to return an attrset with a
+/// `drvPath` key (which already exists) and has a different
+/// meaning.
+///
+/// Other output names that don't match the name restrictions from
+/// [store_path::StorePath] will fail the [store_path::validate_name] check.
+fn validate_input_derivation_output_name(
+    input_derivation_path: &str,
+    output_name: &str,
+) -> Result<(), DerivationError> {
+    if output_name.is_empty()
+        || output_name == "drv"
+        || store_path::validate_name(output_name.as_bytes()).is_err()
+    {
+        return Err(DerivationError::InvalidInputDerivationOutputName(
+            input_derivation_path.to_string(),
+            output_name.to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validates the dynamic outputs of an input derivation, recursively.
+/// Each of them needs to use at least one output, either directly or from
+/// the derivations it produces.
+fn validate_dynamic_outputs(
+    input_derivation_path: &str,
+    dynamic_outputs: &DynamicOutputs,
+) -> Result<(), DerivationError> {
+    for (output_name, dynamic_output) in dynamic_outputs {
+        validate_input_derivation_output_name(input_derivation_path, output_name)?;
+
+        if dynamic_output.outputs.is_empty() && dynamic_output.dynamic_outputs.is_empty() {
+            return Err(DerivationError::EmptyInputDerivationOutputNames(
+                input_derivation_path.to_string(),
+            ));
+        }
+
+        for output_name in &dynamic_output.outputs {
+            validate_input_derivation_output_name(input_derivation_path, output_name)?;
+        }
+
+        validate_dynamic_outputs(input_derivation_path, &dynamic_output.dynamic_outputs)?;
+    }
+
+    Ok(())
+}
+
 impl Derivation {
     /// validate ensures a Derivation struct is properly populated,
     /// and returns a [DerivationError] if not.
@@ -16,6 +72,26 @@ impl Derivation {
             return Err(DerivationError::NoOutputs());
         }
 
+        // All outputs need to be of the same kind, and content-addressed
+        // outputs need to use the same hash algo and mode.
+        let mut outputs = self.outputs.values();
+        if let Some(first) = outputs.next() {
+            for output in outputs {
+                match (&first.kind, &output.kind) {
+                    (OutputKind::Floating { .. }, OutputKind::Floating { .. })
+                    | (OutputKind::Impure { .. }, OutputKind::Impure { .. }) => {
+                        if first.kind != output.kind {
+                            return Err(DerivationError::InconsistentOutputHashAlgos());
+                        }
+                    }
+                    (a, b) if mem::discriminant(a) != mem::discriminant(b) => {
+                        return Err(DerivationError::MixedOutputKinds());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
         // Validate all outputs
         for (output_name, output) in &self.outputs {
             // empty output names are invalid.
@@ -59,32 +135,41 @@ impl Derivation {
                 ));
             }
 
-            if output_names.is_empty() {
+            // Output names may only be empty if outputs of the derivations
+            // produced by it are used.
+            if output_names.is_empty()
+                && !self
+                    .input_derivation_dynamic_outputs
+                    .contains_key(input_derivation_path)
+            {
                 return Err(DerivationError::EmptyInputDerivationOutputNames(
                     input_derivation_path.to_string(),
                 ));
             }
 
             for output_name in output_names.iter() {
-                // empty output names are invalid.
-                //
-                // `drv` is an invalid output name too, as this would cause
-                // a `builtins.derivation` call to return an attrset with a
-                // `drvPath` key (which already exists) and has a different
-                // meaning.
-                //
-                // Other output names that don't match the name restrictions from
-                // [StorePath] will fail the [StorePathRef::validate_name] check.
-                if output_name.is_empty()
-                    || output_name == "drv"
-                    || store_path::validate_name(output_name.as_bytes()).is_err()
-                {
-                    return Err(DerivationError::InvalidInputDerivationOutputName(
-                        input_derivation_path.to_string(),
-                        output_name.to_string(),
-                    ));
-                }
+                validate_input_derivation_output_name(
+                    &input_derivation_path.to_string(),
+                    output_name,
+                )?;
+            }
+        }
+
+        // Validate all dynamic outputs of input derivations
+        for (input_derivation_path, dynamic_outputs) in &self.input_derivation_dynamic_outputs {
+            if !self.input_derivations.contains_key(input_derivation_path) {
+                return Err(DerivationError::DynamicOutputsWithoutInputDerivation(
+                    input_derivation_path.to_string(),
+                ));
+            }
+
+            if dynamic_outputs.is_empty() {
+                return Err(DerivationError::EmptyInputDerivationOutputNames(
+                    input_derivation_path.to_string(),
+                ));
             }
+
+            validate_dynamic_outputs(&input_derivation_path.to_string(), dynamic_outputs)?;
         }
 
         // validate platform
@@ -113,7 +198,8 @@ impl Derivation {
 mod test {
     use std::collections::BTreeMap;
 
-    use crate::derivation::{CAHash, Derivation, Output};
+    use crate::derivation::{CAHash, Derivation, DerivationError, Output, OutputKind};
+    use crate::nixhash::{CAHashMode, HashAlgo};
 
     /// Regression test: produce a Derivation that's almost valid, except its
     /// fixed-output output has the wrong hash specified.
@@ -124,7 +210,7 @@ mod test {
             "out".to_string(),
             Output {
                 path: None,
-                ca_hash: Some(CAHash::Text([0; 32])), // This is disallowed
+                kind: OutputKind::Fixed(CAHash::Text([0; 32])), // This is disallowed
             },
         );
 
@@ -138,4 +224,48 @@ mod test {
 
         drv.validate(false).expect_err("must fail");
     }
+
+    fn floating(mode: CAHashMode, algo: HashAlgo) -> Output {
+        Output {
+            path: None,
+            kind: OutputKind::Floating { mode, algo },
+        }
+    }
+
+    /// Floating outputs don't have a path upfront, and must agree on their
+    /// hash algo and mode.
+    #[test]
+    fn validate_floating() {
+        let mut drv = Derivation {
+            builder: "/bin/sh".to_string(),
+            outputs: BTreeMap::from([
+                (
+                    "out".to_string(),
+                    floating(CAHashMode::Nar, HashAlgo::Sha256),
+                ),
+                (
+                    "dev".to_string(),
+                    floating(CAHashMode::Nar, HashAlgo::Sha256),
+                ),
+            ]),
+            system: "x86_64-linux".to_string(),
+            ..Default::default()
+        };
+        drv.validate(true).expect("must validate");
+
+        drv.outputs.insert(
+            "dev".to_string(),
+            floating(CAHashMode::Flat, HashAlgo::Sha256),
+        );
+        assert_eq!(
+            Err(DerivationError::InconsistentOutputHashAlgos()),
+            drv.validate(true)
+        );
+
+        drv.outputs.insert("dev".to_string(), Default::default());
+        assert_eq!(
+            Err(DerivationError::MixedOutputKinds()),
+            drv.validate(false)
+        );
+    }
 }
@@ -4,7 +4,7 @@
 //! [ATerm]: http://program-transformation.org/Tools/ATermFormat.html
 
 use crate::aterm::escape_bytes;
-use crate::derivation::{ca_kind_prefix, output::Output};
+use crate::derivation::{ca_kind_prefix, DynamicOutputs, Output, OutputKind};
 use crate::nixbase32;
 use crate::store_path::{StorePath, STORE_DIR_WITH_SLASH};
 use bstr::BString;
@@ -18,6 +18,9 @@ use std::{
 };
 
 pub const DERIVATION_PREFIX: &str = "Derive";
+/// Prefix of derivations with dynamic outputs, including the opening paren
+/// and the version.
+pub const DYNAMIC_DERIVATION_PREFIX: &str = "DrvWithVersion(\"xp-dyn-drv\",";
 pub const PAREN_OPEN: char = '(';
 pub const PAREN_CLOSE: char = ')';
 pub const BRACKET_OPEN: char = '[';
@@ -34,6 +37,12 @@ pub(crate) trait AtermWriteable {
     fn aterm_write(&self, writer: &mut impl Write) -> std::io::Result<()>;
 }
 
+impl<T: AtermWriteable> AtermWriteable for &T {
+    fn aterm_write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        (*self).aterm_write(writer)
+    }
+}
+
 impl<S> AtermWriteable for StorePath<S>
 where
     S: std::cmp::Eq + std::ops::Deref<Target = str>,
@@ -120,12 +129,23 @@ pub(crate) fn write_outputs(
         let path_str = output.path_str();
         let mut elements: Vec<&str> = vec![output_name, &path_str];
 
-        let (mode_and_algo, digest) = match &output.ca_hash {
-            Some(ca_hash) => (
-                format!("{}{}", ca_kind_prefix(ca_hash), ca_hash.hash().algo()),
+        let (mode_and_algo, digest) = match &output.kind {
+            OutputKind::InputAddressed | OutputKind::Deferred => ("".to_string(), "".to_string()),
+            OutputKind::Fixed(ca_hash) => (
+                format!(
+                    "{}{}",
+                    ca_kind_prefix(ca_hash.mode()),
+                    ca_hash.hash().algo()
+                ),
                 data_encoding::HEXLOWER.encode(ca_hash.hash().digest_as_bytes()),
             ),
-            None => ("".to_string(), "".to_string()),
+            OutputKind::Floating { mode, algo } => {
+                (format!("{}{}", ca_kind_prefix(*mode), algo), "".to_string())
+            }
+            OutputKind::Impure { mode, algo } => (
+                format!("{}{}", ca_kind_prefix(*mode), algo),
+                "impure".to_string(),
+            ),
         };
 
         elements.push(&mode_and_algo);
@@ -140,13 +160,20 @@ pub(crate) fn write_outputs(
     Ok(())
 }
 
-pub(crate) fn write_input_derivations(
+pub(crate) fn write_input_derivations<'a>(
     writer: &mut impl Write,
-    input_derivations: &BTreeMap<impl AtermWriteable, BTreeSet<String>>,
+    input_derivations: impl IntoIterator<
+        Item = (
+            impl AtermWriteable,
+            (&'a BTreeSet<String>, Option<&'a DynamicOutputs>),
+        ),
+    >,
 ) -> Result<(), io::Error> {
     write_char(writer, BRACKET_OPEN)?;
 
-    for (ii, (input_derivation_aterm, output_names)) in input_derivations.iter().enumerate() {
+    for (ii, (input_derivation_aterm, (output_names, dynamic_outputs))) in
+        input_derivations.into_iter().enumerate()
+    {
         if ii > 0 {
             write_char(writer, COMMA)?;
         }
@@ -155,15 +182,7 @@ pub(crate) fn write_input_derivations(
         input_derivation_aterm.aterm_write(writer)?;
         write_char(writer, COMMA)?;
 
-        write_char(writer, BRACKET_OPEN)?;
-        write_array_elements(
-            writer,
-            &output_names
-                .iter()
-                .map(String::as_bytes)
-                .collect::<Vec<_>>(),
-        )?;
-        write_char(writer, BRACKET_CLOSE)?;
+        write_input_derivation_outputs(writer, output_names, dynamic_outputs)?;
 
         write_char(writer, PAREN_CLOSE)?;
     }
@@ -173,6 +192,55 @@ pub(crate) fn write_input_derivations(
     Ok(())
 }
 
+/// Writes the output names used from an input derivation.
+/// If there are dynamic outputs, they're written together with them as
+/// `([output_names],[(output_name,...),...])`, recursively.
+fn write_input_derivation_outputs(
+    writer: &mut impl Write,
+    output_names: &BTreeSet<String>,
+    dynamic_outputs: Option<&DynamicOutputs>,
+) -> Result<(), io::Error> {
+    let dynamic_outputs = dynamic_outputs.filter(|d| !d.is_empty());
+
+    if dynamic_outputs.is_some() {
+        write_char(writer, PAREN_OPEN)?;
+    }
+
+    write_char(writer, BRACKET_OPEN)?;
+    write_array_elements(
+        writer,
+        &output_names
+            .iter()
+            .map(String::as_bytes)
+            .collect::<Vec<_>>(),
+    )?;
+    write_char(writer, BRACKET_CLOSE)?;
+
+    if let Some(dynamic_outputs) = dynamic_outputs {
+        write_char(writer, COMMA)?;
+        write_char(writer, BRACKET_OPEN)?;
+        for (ii, (output_name, dynamic_output)) in dynamic_outputs.iter().enumerate() {
+            if ii > 0 {
+                write_char(writer, COMMA)?;
+            }
+
+            write_char(writer, PAREN_OPEN)?;
+            write_field(writer, output_name, true)?;
+            write_char(writer, COMMA)?;
+            write_input_derivation_outputs(
+                writer,
+                &dynamic_output.outputs,
+                Some(&dynamic_output.dynamic_outputs),
+            )?;
+            write_char(writer, PAREN_CLOSE)?;
+        }
+        write_char(writer, BRACKET_CLOSE)?;
+        write_char(writer, PAREN_CLOSE)?;
+    }
+
+    Ok(())
+}
+
 pub(crate) fn write_input_sources(
     writer: &mut impl Write,
     input_sources: &BTreeSet<StorePath<String>>,
@@ -0,0 +1,40 @@
+//! Reader and writer for the format produced by `nix-store --export`, and
+//! consumed by `nix-store --import`.
+//!
+//! An export stream contains a sequence of store paths, each preceded by
+//! the number `1`, and terminated by `0`. For each path, its NAR is
+//! followed by a magic number, the store path, its references, its deriver
+//! (or an empty string) and a marker for a (no longer used) signature.
+//! All numbers are u64, LE-encoded, strings are sent as "bytes wire
+//! packets".
+//!
+//! NARs are not length-prefixed, so the NAR of each path needs to be parsed
+//! to find its end.
+
+use crate::store_path::{StorePath, ENCODED_DIGEST_SIZE, STORE_DIR_WITH_SLASH};
+
+mod reader;
+mod writer;
+pub use reader::{NarReader, Reader};
+pub use writer::Writer;
+
+#[cfg(all(test, feature = "async"))]
+mod test;
+
+/// Written after each NAR, before the metadata of the path.
+const EXPORT_MAGIC: u64 = 0x4558494e;
+
+/// The maximum length of store paths we're willing to accept.
+const MAX_STORE_PATH_LEN: usize = STORE_DIR_WITH_SLASH.len() + ENCODED_DIGEST_SIZE + 1 + 211;
+
+/// The maximum length of the legacy signature we're willing to skip over.
+const MAX_SIGNATURE_LEN: usize = 1024;
+
+/// The metadata following the NAR of each path in an export stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathMetadata {
+    pub path: StorePath<String>,
+    pub references: Vec<StorePath<String>>,
+    /// The store path of the .drv file producing this path.
+    pub deriver: Option<StorePath<String>>,
+}
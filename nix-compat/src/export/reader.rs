@@ -0,0 +1,153 @@
+use std::{
+    pin::Pin,
+    task::{self, ready, Poll},
+};
+
+use sha2::{Digest, Sha256};
+use tokio::io::{self, AsyncBufRead, AsyncRead, AsyncReadExt, ErrorKind::InvalidData, ReadBuf};
+
+use super::{PathMetadata, EXPORT_MAGIC, MAX_SIGNATURE_LEN, MAX_STORE_PATH_LEN};
+use crate::{store_path::StorePath, wire};
+
+/// The size of the buffer of [NarReader].
+const BUF_SIZE: usize = 64 * 1024;
+
+/// Reads an export stream, produced by `nix-store --export`.
+pub struct Reader<R> {
+    inner: NarReader<R>,
+}
+
+impl<R: AsyncRead + Unpin> Reader<R> {
+    pub fn new(r: R) -> Self {
+        Self {
+            inner: NarReader {
+                inner: r,
+                buf: vec![0; BUF_SIZE].into_boxed_slice(),
+                pos: 0,
+                filled: 0,
+                hasher: Sha256::new(),
+                size: 0,
+            },
+        }
+    }
+
+    /// Starts reading the next path in the stream, returning a reader for
+    /// its NAR, or `None` at the end of the stream.
+    ///
+    /// The NAR must be read up to its end, but not any further, before
+    /// calling [Reader::finish_path]. This is what reading it with
+    /// [crate::nar::reader::r#async] does.
+    pub async fn next_path(&mut self) -> io::Result<Option<&mut NarReader<R>>> {
+        match self.inner.read_u64_le().await? {
+            0 => Ok(None),
+            1 => {
+                self.inner.hasher = Sha256::new();
+                self.inner.size = 0;
+                Ok(Some(&mut self.inner))
+            }
+            _ => Err(io::Error::new(InvalidData, "not an export stream")),
+        }
+    }
+
+    /// Reads the metadata following the NAR of the current path.
+    /// Returns it alongside the sha256 digest and size of the NAR.
+    pub async fn finish_path(&mut self) -> io::Result<(PathMetadata, [u8; 32], u64)> {
+        let nar_sha256 = std::mem::take(&mut self.inner.hasher).finalize().into();
+        let nar_size = self.inner.size;
+
+        if self.inner.read_u64_le().await? != EXPORT_MAGIC {
+            return Err(io::Error::new(InvalidData, "invalid magic after NAR"));
+        }
+
+        let path = read_store_path(&mut self.inner).await?;
+
+        let count = self.inner.read_u64_le().await?;
+        let mut references = Vec::new();
+        for _ in 0..count {
+            references.push(read_store_path(&mut self.inner).await?);
+        }
+
+        let deriver = wire::read_bytes(&mut self.inner, 0..=MAX_STORE_PATH_LEN).await?;
+        let deriver = if deriver.is_empty() {
+            None
+        } else {
+            Some(parse_store_path(&deriver)?)
+        };
+
+        // Nix doesn't write signatures anymore, and ignores them.
+        if self.inner.read_u64_le().await? == 1 {
+            wire::read_bytes(&mut self.inner, 0..=MAX_SIGNATURE_LEN).await?;
+        }
+
+        Ok((
+            PathMetadata {
+                path,
+                references,
+                deriver,
+            },
+            nar_sha256,
+            nar_size,
+        ))
+    }
+}
+
+async fn read_store_path<R: AsyncRead + Unpin>(r: &mut R) -> io::Result<StorePath<String>> {
+    let buf = wire::read_bytes(r, 1..=MAX_STORE_PATH_LEN).await?;
+    parse_store_path(&buf)
+}
+
+fn parse_store_path(buf: &[u8]) -> io::Result<StorePath<String>> {
+    StorePath::from_absolute_path(buf).map_err(|e| io::Error::new(InvalidData, e))
+}
+
+/// Reads the NAR of a path in an export stream, keeping track of the sha256
+/// digest and size of the data consumed.
+pub struct NarReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    /// The position of the first unconsumed byte in `buf`.
+    pos: usize,
+    /// The number of bytes in `buf` read from `inner`.
+    filled: usize,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for NarReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+
+        if this.pos == this.filled {
+            let mut buf = ReadBuf::new(&mut this.buf);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut buf))?;
+            this.filled = buf.filled().len();
+            this.pos = 0;
+        }
+
+        Poll::Ready(Ok(&this.buf[this.pos..this.filled]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        let amt = amt.min(this.filled - this.pos);
+
+        this.hasher.update(&this.buf[this.pos..this.pos + amt]);
+        this.size += amt as u64;
+        this.pos += amt;
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for NarReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let data = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = data.len().min(buf.remaining());
+        buf.put_slice(&data[..n]);
+        self.consume(n);
+
+        Poll::Ready(Ok(()))
+    }
+}
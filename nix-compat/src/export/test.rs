@@ -0,0 +1,184 @@
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use super::{PathMetadata, Reader, Writer, EXPORT_MAGIC};
+use crate::{nar::reader::r#async as nar_reader, store_path::StorePath, wire};
+
+const NAR_SYMLINK: &[u8] = include_bytes!("../nar/tests/symlink.nar");
+const NAR_HELLOWORLD: &[u8] = include_bytes!("../nar/tests/helloworld.nar");
+const NAR_COMPLICATED: &[u8] = include_bytes!("../nar/tests/complicated.nar");
+
+fn store_path(s: &str) -> StorePath<String> {
+    StorePath::from_absolute_path(s.as_bytes()).expect("must parse")
+}
+
+/// Reads a NAR node up to its end.
+async fn read_node(node: nar_reader::Node<'_, '_>) {
+    match node {
+        nar_reader::Node::Symlink { .. } => {}
+        nar_reader::Node::File { mut reader, .. } => {
+            tokio::io::copy(&mut reader, &mut tokio::io::sink())
+                .await
+                .expect("must read");
+        }
+        nar_reader::Node::Directory(mut dir_reader) => {
+            while let Some(entry) = dir_reader.next().await.expect("must read") {
+                Box::pin(read_node(entry.node)).await;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn roundtrip() {
+    let paths = [
+        (
+            PathMetadata {
+                path: store_path("/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-complicated"),
+                references: vec![],
+                deriver: None,
+            },
+            NAR_COMPLICATED,
+        ),
+        (
+            PathMetadata {
+                path: store_path("/nix/store/dxwkwjzdaq7ka55pkk252gh32bgpmql4-hello-2.12.1"),
+                references: vec![
+                    store_path("/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-complicated"),
+                    store_path("/nix/store/dxwkwjzdaq7ka55pkk252gh32bgpmql4-hello-2.12.1"),
+                ],
+                deriver: Some(store_path(
+                    "/nix/store/9qhwzn1ihqplm4ax5hxqjz4bqbcjldg0-hello-2.12.1.drv",
+                )),
+            },
+            NAR_HELLOWORLD,
+        ),
+        (
+            PathMetadata {
+                path: store_path("/nix/store/9qhwzn1ihqplm4ax5hxqjz4bqbcjldg0-symlink"),
+                references: vec![],
+                deriver: None,
+            },
+            NAR_SYMLINK,
+        ),
+    ];
+
+    let mut writer = Writer::new(Vec::new());
+    for (metadata, nar) in &paths {
+        writer
+            .start_path()
+            .await
+            .expect("must write")
+            .write_all(nar)
+            .await
+            .expect("must write");
+        writer.finish_path(metadata).await.expect("must write");
+    }
+    let buf = writer.finish().await.expect("must write");
+
+    let mut reader = Reader::new(&buf[..]);
+    for (metadata, nar) in &paths {
+        let r = reader
+            .next_path()
+            .await
+            .expect("must read")
+            .expect("must have a path");
+        read_node(nar_reader::open(r).await.expect("must open")).await;
+
+        let (read_metadata, nar_sha256, nar_size) =
+            reader.finish_path().await.expect("must read metadata");
+        assert_eq!(metadata, &read_metadata);
+        assert_eq!(Sha256::digest(nar)[..], nar_sha256);
+        assert_eq!(nar.len() as u64, nar_size);
+    }
+
+    assert!(reader.next_path().await.expect("must read").is_none());
+}
+
+/// Writes a single path, and checks the exact bytes written.
+#[tokio::test]
+async fn write() {
+    let metadata = PathMetadata {
+        path: store_path("/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-symlink"),
+        references: vec![store_path(
+            "/nix/store/dxwkwjzdaq7ka55pkk252gh32bgpmql4-hello-2.12.1",
+        )],
+        deriver: None,
+    };
+
+    let mut writer = Writer::new(Vec::new());
+    writer
+        .start_path()
+        .await
+        .expect("must write")
+        .write_all(NAR_SYMLINK)
+        .await
+        .expect("must write");
+    writer.finish_path(&metadata).await.expect("must write");
+    let buf = writer.finish().await.expect("must write");
+
+    let mut expected = Vec::new();
+    expected.extend_from_slice(&1u64.to_le_bytes());
+    expected.extend_from_slice(NAR_SYMLINK);
+    expected.extend_from_slice(&EXPORT_MAGIC.to_le_bytes());
+    wire::write_bytes(
+        &mut expected,
+        "/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-symlink",
+    )
+    .await
+    .unwrap();
+    expected.extend_from_slice(&1u64.to_le_bytes());
+    wire::write_bytes(
+        &mut expected,
+        "/nix/store/dxwkwjzdaq7ka55pkk252gh32bgpmql4-hello-2.12.1",
+    )
+    .await
+    .unwrap();
+    wire::write_bytes(&mut expected, "").await.unwrap();
+    expected.extend_from_slice(&0u64.to_le_bytes());
+    expected.extend_from_slice(&0u64.to_le_bytes());
+
+    assert_eq!(expected, buf);
+}
+
+/// Signatures written by older versions of Nix are skipped.
+#[tokio::test]
+async fn read_legacy_signature() {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&1u64.to_le_bytes());
+    buf.extend_from_slice(NAR_SYMLINK);
+    buf.extend_from_slice(&EXPORT_MAGIC.to_le_bytes());
+    wire::write_bytes(
+        &mut buf,
+        "/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-symlink",
+    )
+    .await
+    .unwrap();
+    buf.extend_from_slice(&0u64.to_le_bytes());
+    wire::write_bytes(&mut buf, "").await.unwrap();
+    buf.extend_from_slice(&1u64.to_le_bytes());
+    wire::write_bytes(&mut buf, "signature").await.unwrap();
+    buf.extend_from_slice(&0u64.to_le_bytes());
+
+    let mut reader = Reader::new(&buf[..]);
+    let r = reader
+        .next_path()
+        .await
+        .expect("must read")
+        .expect("must have a path");
+    read_node(nar_reader::open(r).await.expect("must open")).await;
+    let (metadata, _, _) = reader.finish_path().await.expect("must read metadata");
+    assert_eq!(
+        store_path("/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-symlink"),
+        metadata.path
+    );
+
+    assert!(reader.next_path().await.expect("must read").is_none());
+}
+
+#[tokio::test]
+async fn read_invalid_marker() {
+    let buf = 2u64.to_le_bytes();
+    let mut reader = Reader::new(&buf[..]);
+    assert!(reader.next_path().await.is_err());
+}
@@ -0,0 +1,52 @@
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
+
+use super::{PathMetadata, EXPORT_MAGIC};
+use crate::{store_path::StorePath, wire};
+
+/// Writes an export stream, as produced by `nix-store --export`.
+pub struct Writer<W> {
+    inner: W,
+}
+
+impl<W: AsyncWrite + Unpin> Writer<W> {
+    pub fn new(w: W) -> Self {
+        Self { inner: w }
+    }
+
+    /// Starts writing a path, returning the writer its NAR needs to be
+    /// written to, before calling [Writer::finish_path].
+    pub async fn start_path(&mut self) -> io::Result<&mut W> {
+        self.inner.write_u64_le(1).await?;
+        Ok(&mut self.inner)
+    }
+
+    /// Writes the metadata following the NAR of the current path.
+    pub async fn finish_path(&mut self, metadata: &PathMetadata) -> io::Result<()> {
+        self.inner.write_u64_le(EXPORT_MAGIC).await?;
+        wire::write_bytes(&mut self.inner, metadata.path.to_absolute_path()).await?;
+
+        self.inner
+            .write_u64_le(metadata.references.len() as u64)
+            .await?;
+        for reference in &metadata.references {
+            wire::write_bytes(&mut self.inner, reference.to_absolute_path()).await?;
+        }
+
+        let deriver = metadata
+            .deriver
+            .as_ref()
+            .map(StorePath::to_absolute_path)
+            .unwrap_or_default();
+        wire::write_bytes(&mut self.inner, deriver).await?;
+
+        // no signature
+        self.inner.write_u64_le(0).await
+    }
+
+    /// Writes the end of the stream, and returns the underlying writer.
+    pub async fn finish(mut self) -> io::Result<W> {
+        self.inner.write_u64_le(0).await?;
+        self.inner.flush().await?;
+        Ok(self.inner)
+    }
+}
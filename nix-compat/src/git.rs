@@ -0,0 +1,150 @@
+//! Git object encoding, as used by the `git` file ingestion method.
+//!
+//! With it, the content address of a file is the digest of its git blob
+//! object, and the one of a directory the digest of its git tree object.
+//! Symlinks are blobs containing the symlink target.
+//!
+//! This is generic over the hash function, as git supports both sha1 and
+//! sha256 object ids.
+
+use sha2::Digest;
+
+/// The mode of an entry in a git tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryMode {
+    Regular,
+    Executable,
+    Symlink,
+    Directory,
+}
+
+impl EntryMode {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            EntryMode::Regular => b"100644",
+            EntryMode::Executable => b"100755",
+            EntryMode::Symlink => b"120000",
+            EntryMode::Directory => b"40000",
+        }
+    }
+}
+
+/// An entry in a git tree, referring to another object by its digest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: EntryMode,
+    pub name: Vec<u8>,
+    pub digest: Vec<u8>,
+}
+
+impl TreeEntry {
+    /// Git sorts entries by name, with directories sorted as if their name
+    /// had a trailing slash.
+    fn cmp_git(&self, other: &Self) -> std::cmp::Ordering {
+        let suffix = |e: &Self| match e.mode {
+            EntryMode::Directory => &b"/"[..],
+            _ => &b""[..],
+        };
+
+        self.name
+            .iter()
+            .chain(suffix(self))
+            .cmp(other.name.iter().chain(suffix(other)))
+    }
+}
+
+/// Returns the header of a git blob object with contents of the given size.
+/// The object consists of the header, followed by the contents.
+pub fn blob_header(size: u64) -> Vec<u8> {
+    format!("blob {}\0", size).into_bytes()
+}
+
+/// Hashes a git blob object with the passed contents.
+pub fn hash_blob<D: Digest>(contents: &[u8]) -> Vec<u8> {
+    let mut h = D::new();
+    h.update(blob_header(contents.len() as u64));
+    h.update(contents);
+    h.finalize().to_vec()
+}
+
+/// Returns the git tree object for the passed entries, including its header.
+/// Entries are sorted the way git does.
+pub fn tree_object(mut entries: Vec<TreeEntry>) -> Vec<u8> {
+    entries.sort_by(TreeEntry::cmp_git);
+
+    let mut body = Vec::new();
+    for entry in entries {
+        body.extend_from_slice(entry.mode.as_bytes());
+        body.push(b' ');
+        body.extend_from_slice(&entry.name);
+        body.push(0);
+        body.extend_from_slice(&entry.digest);
+    }
+
+    let mut object = format!("tree {}\0", body.len()).into_bytes();
+    object.extend_from_slice(&body);
+    object
+}
+
+/// Hashes the git tree object for the passed entries.
+pub fn hash_tree<D: Digest>(entries: Vec<TreeEntry>) -> Vec<u8> {
+    D::digest(tree_object(entries)).to_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+    use sha2::Sha256;
+
+    use super::{EntryMode, TreeEntry};
+
+    // Object ids below are the ones of a git repository using
+    // `--object-format=sha256`.
+
+    #[test]
+    fn empty_blob() {
+        assert_eq!(
+            hex!("473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813"),
+            super::hash_blob::<Sha256>(b"")[..]
+        );
+    }
+
+    #[test]
+    fn empty_tree() {
+        assert_eq!(
+            hex!("6ef19b41225c5369f1c104d45d8d85efa9b057b53b14b4b9b939dd74decc5321"),
+            super::hash_tree::<Sha256>(vec![])[..]
+        );
+    }
+
+    /// Directories are sorted as if they had a trailing slash, so the `a`
+    /// directory comes after `a-` and `a.b`.
+    #[test]
+    fn tree_sorting() {
+        let entry = |mode, name: &[u8]| TreeEntry {
+            mode,
+            name: name.to_vec(),
+            digest: vec![0; 32],
+        };
+
+        let object = super::tree_object(vec![
+            entry(EntryMode::Directory, b"a"),
+            entry(EntryMode::Regular, b"a.b"),
+            entry(EntryMode::Symlink, b"a-"),
+        ]);
+
+        let mut expected = b"tree 125\0".to_vec();
+        for (mode, name) in [
+            (&b"120000"[..], &b"a-"[..]),
+            (b"100644", b"a.b"),
+            (b"40000", b"a"),
+        ] {
+            expected.extend_from_slice(mode);
+            expected.push(b' ');
+            expected.extend_from_slice(name);
+            expected.push(0);
+            expected.extend_from_slice(&[0; 32]);
+        }
+        assert_eq!(expected, object);
+    }
+}
@@ -2,6 +2,7 @@ extern crate self as nix_compat;
 
 pub(crate) mod aterm;
 pub mod derivation;
+pub mod git;
 pub mod nar;
 pub mod narinfo;
 pub mod nix_http;
@@ -9,11 +10,15 @@ pub mod nixbase32;
 pub mod nixcpp;
 pub mod nixhash;
 pub mod path_info;
+pub mod realisation;
 pub mod store_path;
 
 #[cfg(feature = "wire")]
 pub mod wire;
 
+#[cfg(feature = "wire")]
+pub mod export;
+
 #[cfg(feature = "wire")]
 pub mod nix_daemon;
 #[cfg(feature = "wire")]
@@ -0,0 +1,199 @@
+//! Builds a [Listing] by describing the structure of a NAR, the same way it
+//! would be written with [crate::nar::writer], but without its contents.
+//!
+//! The NAR offsets of regular files are calculated from the sizes of all
+//! preceding elements, so the NAR itself doesn't need to be rendered.
+//!
+//! ```rust
+//! use nix_compat::nar::listing::builder::ListingBuilder;
+//!
+//! let mut builder = ListingBuilder::new();
+//! let mut dir = builder.root().directory();
+//! dir.entry(b"bin")?.directory().close()?;
+//! dir.entry(b"hello.txt")?.file(false, 12);
+//! dir.close()?;
+//!
+//! let listing = builder.build()?;
+//! println!("{}", serde_json::to_string(&listing).unwrap());
+//! # Ok::<(), nix_compat::nar::listing::ListingError>(())
+//! ```
+
+use std::collections::HashMap;
+
+use super::{Listing, ListingEntry, ListingError, ListingVersion};
+use crate::nar::wire;
+
+/// Returns the number of bytes a length-prefixed, padded string of the given
+/// length occupies in a NAR.
+fn str_len(len: usize) -> u64 {
+    8 + padded_len(len as u64)
+}
+
+/// Returns the given length, rounded up to a multiple of 8.
+fn padded_len(len: u64) -> u64 {
+    (len + 7) & !7
+}
+
+/// Builds a [Listing], keeping track of the current offset in the NAR.
+#[derive(Debug)]
+pub struct ListingBuilder {
+    offset: u64,
+    root: Option<ListingEntry>,
+}
+
+impl Default for ListingBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ListingBuilder {
+    pub fn new() -> Self {
+        Self {
+            offset: wire::TOK_NAR.len() as u64,
+            root: None,
+        }
+    }
+
+    /// Returns the root node, which needs to be described before calling
+    /// [ListingBuilder::build].
+    pub fn root(&mut self) -> Node<'_> {
+        Node {
+            offset: &mut self.offset,
+            slot: &mut self.root,
+        }
+    }
+
+    /// Returns the [Listing], or an error if the root node wasn't described.
+    pub fn build(self) -> Result<Listing, ListingError> {
+        Ok(Listing::V1 {
+            root: self.root.ok_or(ListingError::MissingNode)?,
+            version: ListingVersion,
+        })
+    }
+}
+
+/// Single node in a NAR, see [crate::nar::writer::sync::Node].
+#[derive(Debug)]
+pub struct Node<'a> {
+    offset: &'a mut u64,
+    slot: &'a mut Option<ListingEntry>,
+}
+
+impl<'a> Node<'a> {
+    /// Make this node a symlink.
+    pub fn symlink(self, target: &[u8]) -> Result<(), ListingError> {
+        // Nix can't represent non-UTF8 symlink targets in listings either.
+        let target = std::str::from_utf8(target).map_err(|_| ListingError::InvalidEncoding)?;
+
+        *self.offset += (wire::TOK_SYM.len() + wire::TOK_PAR.len()) as u64 + str_len(target.len());
+        *self.slot = Some(ListingEntry::Symlink {
+            target: target.to_owned(),
+        });
+
+        Ok(())
+    }
+
+    /// Make this node a single file of the given size.
+    pub fn file(self, executable: bool, size: u64) {
+        *self.offset += if executable {
+            wire::TOK_EXE.len()
+        } else {
+            wire::TOK_REG.len()
+        } as u64
+            + 8;
+
+        // The contents start right after the size.
+        let nar_offset = *self.offset;
+
+        *self.offset += padded_len(size) + wire::TOK_PAR.len() as u64;
+        *self.slot = Some(ListingEntry::Regular {
+            size,
+            executable,
+            nar_offset,
+        });
+    }
+
+    /// Make this node a directory, the content of which is set using the
+    /// resulting [Directory] value.
+    ///
+    /// It is the caller's responsibility to invoke [Directory::close],
+    /// otherwise the node is left undescribed.
+    pub fn directory(self) -> Directory<'a> {
+        *self.offset += wire::TOK_DIR.len() as u64;
+
+        Directory {
+            offset: self.offset,
+            slot: self.slot,
+            entries: HashMap::new(),
+            current: None,
+        }
+    }
+}
+
+/// Content of a NAR node that represents a directory.
+#[derive(Debug)]
+pub struct Directory<'a> {
+    offset: &'a mut u64,
+    slot: &'a mut Option<ListingEntry>,
+    entries: HashMap<String, ListingEntry>,
+    /// The name of the last entry, and its node, once described.
+    current: Option<(String, Option<ListingEntry>)>,
+}
+
+impl<'a> Directory<'a> {
+    /// Moves the last entry into entries, once its node has been described.
+    fn finish_current(&mut self) -> Result<(), ListingError> {
+        if let Some((name, entry)) = self.current.take() {
+            self.entries
+                .insert(name, entry.ok_or(ListingError::MissingNode)?);
+            *self.offset += wire::TOK_PAR.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Add an entry to the directory.
+    ///
+    /// The entry is simply another [Node], which can then be described like
+    /// the root of a NAR (including, of course, by nesting directories).
+    ///
+    /// It is the caller's responsibility to ensure that directory entries are
+    /// added in order of ascending name, otherwise the offsets won't match the
+    /// NAR.
+    pub fn entry(&mut self, name: &[u8]) -> Result<Node<'_>, ListingError> {
+        let name = std::str::from_utf8(name).map_err(|_| ListingError::InvalidEncoding)?;
+
+        if let Some((prev_name, _)) = &self.current {
+            debug_assert!(
+                prev_name.as_str() < name,
+                "misordered names: {prev_name:?} >= {name:?}"
+            );
+        }
+
+        self.finish_current()?;
+
+        *self.offset += (wire::TOK_ENT.len() + wire::TOK_NOD.len()) as u64 + str_len(name.len());
+
+        let (_, slot) = self.current.insert((name.to_owned(), None));
+
+        Ok(Node {
+            offset: &mut *self.offset,
+            slot,
+        })
+    }
+
+    /// Close a directory.
+    ///
+    /// **Important:** This *must* be called when all entries have been added
+    /// to a directory, otherwise the node is left undescribed.
+    pub fn close(mut self) -> Result<(), ListingError> {
+        self.finish_current()?;
+        *self.offset += wire::TOK_PAR.len() as u64;
+        *self.slot = Some(ListingEntry::Directory {
+            entries: self.entries,
+        });
+
+        Ok(())
+    }
+}
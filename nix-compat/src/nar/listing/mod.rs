@@ -1,19 +1,22 @@
-//! Parser for the Nix archive listing format, aka .ls.
+//! Parser and serializer for the Nix archive listing format, aka .ls.
 //!
 //! LS files are produced by the C++ Nix implementation via `write-nar-listing=1` query parameter
 //! passed to a store implementation when transferring store paths.
 //!
 //! Listing files contains metadata about a file and its offset in the corresponding NAR.
+//! [builder] allows producing them from the structure of a NAR.
 //!
 //! NOTE: LS entries does not offer any integrity field to validate the retrieved file at the provided
 //! offset. Validating the contents is the caller's responsibility.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     path::{Component, Path},
 };
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+pub mod builder;
 
 #[cfg(test)]
 mod test;
@@ -30,14 +33,17 @@ pub enum ListingError {
     UnsupportedPathComponent,
     #[error("invalid encoding for entry component")]
     InvalidEncoding,
+    /// A node was not described while building a listing.
+    #[error("missing node")]
+    MissingNode,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ListingEntry {
     Regular {
         size: u64,
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
         executable: bool,
         #[serde(rename = "narOffset")]
         nar_offset: u64,
@@ -46,6 +52,7 @@ pub enum ListingEntry {
         // It's tempting to think that the key should be a `Vec<u8>`
         // but Nix does not support that and will fail to emit a listing version 1 for any non-UTF8
         // encodeable string.
+        #[serde(serialize_with = "serialize_sorted")]
         entries: HashMap<String, ListingEntry>,
     },
     Symlink {
@@ -53,6 +60,20 @@ pub enum ListingEntry {
     },
 }
 
+/// Serializes directory entries sorted by their names, like Nix does.
+fn serialize_sorted<S>(
+    entries: &HashMap<String, ListingEntry>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    entries
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
 impl ListingEntry {
     /// Given a relative path without `..` component, this will locate, relative to this entry, a
     /// deeper entry.
@@ -96,7 +117,7 @@ impl ListingEntry {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct ListingVersion<const V: u8>;
 
 #[derive(Debug, thiserror::Error)]
@@ -117,7 +138,16 @@ impl<'de, const V: u8> Deserialize<'de> for ListingVersion<V> {
     }
 }
 
-#[derive(Debug, Deserialize)]
+impl<const V: u8> Serialize for ListingVersion<V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        V.serialize(serializer)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(untagged)]
 #[non_exhaustive]
 pub enum Listing {
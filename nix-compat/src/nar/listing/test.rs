@@ -57,3 +57,97 @@ fn nixos_release() {
         nar::listing::ListingEntry::Directory { .. }
     ));
 }
+
+/// Builds a listing alongside writing a NAR with the same structure, and
+/// ensures the NAR offsets point to the contents of the regular files.
+#[test]
+fn builder_offsets() {
+    let mut nar = vec![];
+    let mut builder = nar::listing::builder::ListingBuilder::new();
+
+    {
+        let mut nar_dir = nar::writer::open(&mut nar).unwrap().directory().unwrap();
+        let mut ls_dir = builder.root().directory();
+
+        nar_dir
+            .entry(b"a")
+            .unwrap()
+            .file(true, 5, &mut &b"Hello"[..])
+            .unwrap();
+        ls_dir.entry(b"a").unwrap().file(true, 5);
+
+        nar_dir
+            .entry(b"b")
+            .unwrap()
+            .symlink(b"/nix/store/somewhereelse")
+            .unwrap();
+        ls_dir
+            .entry(b"b")
+            .unwrap()
+            .symlink(b"/nix/store/somewhereelse")
+            .unwrap();
+
+        {
+            let mut nar_subdir = nar_dir.entry(b"c").unwrap().directory().unwrap();
+            let mut ls_subdir = ls_dir.entry(b"c").unwrap().directory();
+
+            nar_subdir
+                .entry(b"d")
+                .unwrap()
+                .file(false, 12, &mut &b"Hello World!"[..])
+                .unwrap();
+            ls_subdir.entry(b"d").unwrap().file(false, 12);
+
+            nar_subdir.close().unwrap();
+            ls_subdir.close().unwrap();
+        }
+
+        nar_dir
+            .entry(b"e")
+            .unwrap()
+            .file(false, 0, &mut &b""[..])
+            .unwrap();
+        ls_dir.entry(b"e").unwrap().file(false, 0);
+
+        nar_dir.close().unwrap();
+        ls_dir.close().unwrap();
+    }
+
+    let listing = builder.build().expect("must succeed");
+    let nar::listing::Listing::V1 { root, .. } = &listing;
+
+    for (path, contents) in [("a", &b"Hello"[..]), ("c/d", b"Hello World!"), ("e", b"")] {
+        let Some(nar::listing::ListingEntry::Regular {
+            size, nar_offset, ..
+        }) = root.locate(path).unwrap()
+        else {
+            panic!("{path} must be a regular file");
+        };
+
+        assert_eq!(contents.len() as u64, *size);
+        assert_eq!(
+            contents,
+            &nar[*nar_offset as usize..(*nar_offset + *size) as usize]
+        );
+    }
+
+    // Ensure the listing can be parsed back.
+    let listing_json = serde_json::to_string(&listing).unwrap();
+    assert_eq!(
+        listing,
+        serde_json::from_str::<nar::listing::Listing>(&listing_json).unwrap()
+    );
+}
+
+/// Building a listing without describing a node fails.
+#[test]
+fn builder_missing_node() {
+    let mut builder = nar::listing::builder::ListingBuilder::new();
+    {
+        let mut dir = builder.root().directory();
+        let _ = dir.entry(b"a").unwrap();
+        dir.close().expect_err("must fail");
+    }
+
+    builder.build().expect_err("must fail");
+}
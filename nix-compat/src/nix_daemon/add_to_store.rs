@@ -0,0 +1,266 @@
+use crate::nixhash::{CAHashMode as HashMode, HashAlgo};
+use crate::store_path::StorePath;
+
+use super::de::{Error, NixDeserialize, NixRead};
+use super::ser::{self, NixSerialize, NixWrite};
+
+/// The arguments of an `AddToStore` operation, which adds a path by its
+/// contents, and returns its store path.
+///
+/// Since protocol version 1.25, the contents follow as a framed stream, and
+/// the method is sent in the same form as content addresses, without the
+/// digest (`fixed:r:sha256`).
+/// Before, references and repair weren't sent, the method was sent as
+/// separate fields, and the contents followed as an unframed NAR, even when
+/// ingesting a flat file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddToStoreRequest {
+    pub name: String,
+    pub mode: HashMode,
+    pub hash_algo: HashAlgo,
+    pub references: Vec<StorePath<String>>,
+    pub repair: bool,
+}
+
+impl AddToStoreRequest {
+    /// The method in the form sent since protocol version 1.25.
+    fn method_str(&self) -> String {
+        match self.mode {
+            HashMode::Flat => format!("fixed:{}", self.hash_algo),
+            HashMode::Nar => format!("fixed:r:{}", self.hash_algo),
+            HashMode::Text => format!("text:{}", self.hash_algo),
+            HashMode::Git => format!("fixed:git:{}", self.hash_algo),
+        }
+    }
+}
+
+fn parse_method(s: &str) -> Option<(HashMode, HashAlgo)> {
+    let (mode, algo) = if let Some(algo) = s.strip_prefix("text:") {
+        (HashMode::Text, algo)
+    } else if let Some(algo) = s.strip_prefix("fixed:r:") {
+        (HashMode::Nar, algo)
+    } else if let Some(algo) = s.strip_prefix("fixed:git:") {
+        (HashMode::Git, algo)
+    } else if let Some(algo) = s.strip_prefix("fixed:") {
+        (HashMode::Flat, algo)
+    } else {
+        return None;
+    };
+
+    let algo = HashAlgo::try_from(algo).ok()?;
+    if mode == HashMode::Text && algo != HashAlgo::Sha256 {
+        return None;
+    }
+    Some((mode, algo))
+}
+
+impl NixDeserialize for AddToStoreRequest {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        let name: String = match reader.try_read_value().await? {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+
+        if reader.version().minor() >= 25 {
+            let method: String = reader.read_value().await?;
+            let (mode, hash_algo) = parse_method(&method)
+                .ok_or_else(|| R::Error::invalid_data(format!("invalid method {method}")))?;
+            let references = reader.read_value().await?;
+            let repair = reader.read_value().await?;
+
+            Ok(Some(Self {
+                name,
+                mode,
+                hash_algo,
+                references,
+                repair,
+            }))
+        } else {
+            let fixed: bool = reader.read_value().await?;
+            let recursive = match reader.read_number().await? {
+                0 => false,
+                1 => true,
+                n => {
+                    return Err(R::Error::invalid_data(format!(
+                        "unsupported ingestion method {n}"
+                    )))
+                }
+            };
+            let hash_algo: String = reader.read_value().await?;
+
+            // Paths added without a fixed hash are always sha256 NAR-hashed.
+            let (mode, hash_algo) = if !fixed {
+                (HashMode::Nar, HashAlgo::Sha256)
+            } else {
+                let hash_algo =
+                    HashAlgo::try_from(hash_algo.as_str()).map_err(R::Error::invalid_data)?;
+                if recursive {
+                    (HashMode::Nar, hash_algo)
+                } else {
+                    (HashMode::Flat, hash_algo)
+                }
+            };
+
+            Ok(Some(Self {
+                name,
+                mode,
+                hash_algo,
+                references: vec![],
+                repair: false,
+            }))
+        }
+    }
+}
+
+impl NixSerialize for AddToStoreRequest {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_value(&self.name).await?;
+
+        if writer.version().minor() >= 25 {
+            writer.write_value(&self.method_str()).await?;
+            writer.write_value(&self.references).await?;
+            writer.write_value(&self.repair).await
+        } else {
+            // Text paths were added with AddTextToStore, git hashing didn't
+            // exist.
+            if matches!(self.mode, HashMode::Text | HashMode::Git) || !self.references.is_empty() {
+                return Err(<W::Error as ser::Error>::unsupported_data(
+                    "AddToStore with text or git method or references requires protocol version 1.25",
+                ));
+            }
+
+            let recursive = self.mode == HashMode::Nar;
+            // Source paths (sha256, NAR-hashed) are sent as not fixed.
+            let fixed = !(recursive && self.hash_algo == HashAlgo::Sha256);
+            writer.write_value(&fixed).await?;
+            writer.write_value(&recursive).await?;
+            writer.write_display(self.hash_algo).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use super::AddToStoreRequest;
+    use crate::nix_daemon::de::{NixRead, NixReader};
+    use crate::nix_daemon::ser::{NixWrite, NixWriter};
+    use crate::nix_daemon::ProtocolVersion;
+    use crate::nixhash::{CAHashMode as HashMode, HashAlgo};
+
+    fn request(mode: HashMode, hash_algo: HashAlgo) -> AddToStoreRequest {
+        AddToStoreRequest {
+            name: "foo".to_string(),
+            mode,
+            hash_algo,
+            references: vec![],
+            repair: false,
+        }
+    }
+
+    /// The expected encoding at both sides of the version boundary.
+    /// These are derived from the serializers in Nix' daemon.cc and
+    /// remote-store.cc, not captured from a running Nix.
+    #[rstest]
+    #[case::v1_21_source(
+        21,
+        request(HashMode::Nar, HashAlgo::Sha256),
+        &hex!(
+            "0300 0000 0000 0000 666f 6f00 0000 0000
+             0000 0000 0000 0000
+             0100 0000 0000 0000
+             0600 0000 0000 0000 7368 6132 3536 0000"
+        )
+    )]
+    #[case::v1_21_flat(
+        21,
+        request(HashMode::Flat, HashAlgo::Sha1),
+        &hex!(
+            "0300 0000 0000 0000 666f 6f00 0000 0000
+             0100 0000 0000 0000
+             0000 0000 0000 0000
+             0400 0000 0000 0000 7368 6131 0000 0000"
+        )
+    )]
+    #[case::v1_24_source(
+        24,
+        request(HashMode::Nar, HashAlgo::Sha256),
+        &hex!(
+            "0300 0000 0000 0000 666f 6f00 0000 0000
+             0000 0000 0000 0000
+             0100 0000 0000 0000
+             0600 0000 0000 0000 7368 6132 3536 0000"
+        )
+    )]
+    #[case::v1_25_source(
+        25,
+        request(HashMode::Nar, HashAlgo::Sha256),
+        &hex!(
+            "0300 0000 0000 0000 666f 6f00 0000 0000
+             0e00 0000 0000 0000 6669 7865 643a 723a 7368 6132 3536 0000
+             0000 0000 0000 0000
+             0000 0000 0000 0000"
+        )
+    )]
+    #[case::v1_37_text(
+        37,
+        request(HashMode::Text, HashAlgo::Sha256),
+        &hex!(
+            "0300 0000 0000 0000 666f 6f00 0000 0000
+             0b00 0000 0000 0000 7465 7874 3a73 6861 3235 3600 0000 0000
+             0000 0000 0000 0000
+             0000 0000 0000 0000"
+        )
+    )]
+    #[case::v1_37_git(
+        37,
+        request(HashMode::Git, HashAlgo::Sha1),
+        &hex!(
+            "0300 0000 0000 0000 666f 6f00 0000 0000
+             0e00 0000 0000 0000 6669 7865 643a 6769 743a 7368 6131 0000
+             0000 0000 0000 0000
+             0000 0000 0000 0000"
+        )
+    )]
+    #[tokio::test]
+    async fn roundtrip(
+        #[case] minor: u8,
+        #[case] request: AddToStoreRequest,
+        #[case] expected: &[u8],
+    ) {
+        let version = ProtocolVersion::from_parts(1, minor);
+
+        let mut writer = NixWriter::builder().set_version(version).build(Vec::new());
+        writer.write_value(&request).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::builder()
+            .set_version(version)
+            .build(&written[..]);
+        assert_eq!(
+            request,
+            reader.read_value::<AddToStoreRequest>().await.unwrap()
+        );
+    }
+
+    /// Text paths can't be added with AddToStore before 1.25.
+    #[tokio::test]
+    async fn text_v1_21() {
+        let mut writer = NixWriter::builder()
+            .set_version(ProtocolVersion::from_parts(1, 21))
+            .build(Vec::new());
+        writer
+            .write_value(&request(HashMode::Text, HashAlgo::Sha256))
+            .await
+            .expect_err("must fail");
+    }
+}
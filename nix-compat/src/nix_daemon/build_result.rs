@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+
+use enum_primitive_derive::Primitive;
+use num_traits::FromPrimitive;
+
+use super::de::{Error, NixDeserialize, NixRead};
+use super::ser::{NixSerialize, NixWrite};
+use crate::realisation::{DrvOutput, Realisation};
+
+/// The outcome of building or substituting a derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Primitive)]
+pub enum BuildStatus {
+    Built = 0,
+    Substituted = 1,
+    AlreadyValid = 2,
+    PermanentFailure = 3,
+    InputRejected = 4,
+    OutputRejected = 5,
+    /// Possibly transient.
+    TransientFailure = 6,
+    /// No longer used.
+    CachedFailure = 7,
+    TimedOut = 8,
+    MiscFailure = 9,
+    DependencyFailed = 10,
+    LogLimitExceeded = 11,
+    NotDeterministic = 12,
+    ResolvesToAlreadyValid = 13,
+    NoSubstituters = 14,
+}
+
+impl BuildStatus {
+    pub fn success(&self) -> bool {
+        matches!(
+            self,
+            BuildStatus::Built
+                | BuildStatus::Substituted
+                | BuildStatus::AlreadyValid
+                | BuildStatus::ResolvesToAlreadyValid
+        )
+    }
+}
+
+impl NixDeserialize for BuildStatus {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if let Some(value) = reader.try_read_number().await? {
+            BuildStatus::from_u64(value)
+                .map(Some)
+                .ok_or_else(|| R::Error::invalid_data(format!("invalid build status {value}")))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl NixSerialize for BuildStatus {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_number(*self as u64).await
+    }
+}
+
+/// The result of a `BuildDerivation` operation.
+///
+/// Which fields are sent depends on the protocol version, fields not sent
+/// are left at their defaults when reading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildResult {
+    pub status: BuildStatus,
+    pub error_msg: String,
+    /// How many times the derivation was built, only sent since protocol
+    /// version 1.29, like the following three fields.
+    pub times_built: u64,
+    /// Whether repeated builds of the derivation differed.
+    pub is_non_deterministic: bool,
+    /// Seconds since the epoch.
+    pub start_time: u64,
+    pub stop_time: u64,
+    /// CPU time spent in user space by the build, in microseconds.
+    /// Only sent since protocol version 1.37, like `cpu_system`.
+    pub cpu_user: Option<u64>,
+    pub cpu_system: Option<u64>,
+    /// The realisations of the outputs that were built.
+    /// Only sent since protocol version 1.28.
+    pub built_outputs: BTreeMap<DrvOutput, Realisation>,
+}
+
+impl BuildResult {
+    pub fn new(status: BuildStatus, error_msg: impl Into<String>) -> Self {
+        Self {
+            status,
+            error_msg: error_msg.into(),
+            times_built: 0,
+            is_non_deterministic: false,
+            start_time: 0,
+            stop_time: 0,
+            cpu_user: None,
+            cpu_system: None,
+            built_outputs: BTreeMap::new(),
+        }
+    }
+}
+
+/// Optional durations are sent as a flag, followed by the value if present.
+async fn read_duration<R>(reader: &mut R) -> Result<Option<u64>, R::Error>
+where
+    R: ?Sized + NixRead + Send,
+{
+    if reader.read_value::<bool>().await? {
+        Ok(Some(reader.read_number().await?))
+    } else {
+        Ok(None)
+    }
+}
+
+async fn write_duration<W>(writer: &mut W, duration: Option<u64>) -> Result<(), W::Error>
+where
+    W: ?Sized + NixWrite + Send,
+{
+    match duration {
+        Some(duration) => {
+            writer.write_value(&true).await?;
+            writer.write_number(duration).await
+        }
+        None => writer.write_value(&false).await,
+    }
+}
+
+impl NixDeserialize for BuildResult {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        let status = match reader.try_read_value().await? {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+        let mut result = BuildResult::new(status, reader.read_value::<String>().await?);
+
+        if reader.version().minor() >= 29 {
+            result.times_built = reader.read_value().await?;
+            result.is_non_deterministic = reader.read_value().await?;
+            result.start_time = reader.read_value().await?;
+            result.stop_time = reader.read_value().await?;
+        }
+        if reader.version().minor() >= 37 {
+            result.cpu_user = read_duration(reader).await?;
+            result.cpu_system = read_duration(reader).await?;
+        }
+        if reader.version().minor() >= 28 {
+            result.built_outputs = reader.read_value().await?;
+        }
+
+        Ok(Some(result))
+    }
+}
+
+impl NixSerialize for BuildResult {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_value(&self.status).await?;
+        writer.write_value(&self.error_msg).await?;
+
+        if writer.version().minor() >= 29 {
+            writer.write_value(&self.times_built).await?;
+            writer.write_value(&self.is_non_deterministic).await?;
+            writer.write_value(&self.start_time).await?;
+            writer.write_value(&self.stop_time).await?;
+        }
+        if writer.version().minor() >= 37 {
+            write_duration(writer, self.cpu_user).await?;
+            write_duration(writer, self.cpu_system).await?;
+        }
+        if writer.version().minor() >= 28 {
+            writer.write_value(&self.built_outputs).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use super::{BuildResult, BuildStatus};
+    use crate::nix_daemon::de::{NixRead, NixReader};
+    use crate::nix_daemon::ser::{NixWrite, NixWriter};
+    use crate::nix_daemon::ProtocolVersion;
+    use crate::realisation::Realisation;
+    use crate::store_path::StorePath;
+
+    fn build_result() -> BuildResult {
+        let realisation = Realisation {
+            dependent_realisations: BTreeMap::new(),
+            id: "sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c!out"
+                .parse()
+                .unwrap(),
+            out_path: StorePath::from_bytes(b"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools").unwrap(),
+            signatures: vec![],
+        };
+
+        BuildResult {
+            status: BuildStatus::Built,
+            error_msg: "".to_string(),
+            times_built: 1,
+            is_non_deterministic: false,
+            start_time: 2,
+            stop_time: 3,
+            cpu_user: Some(4),
+            cpu_system: None,
+            built_outputs: BTreeMap::from([(realisation.id.clone(), realisation)]),
+        }
+    }
+
+    const STATUS_AND_MSG: [u8; 16] = hex!("0000 0000 0000 0000 0000 0000 0000 0000");
+    const TIMES: [u8; 32] = hex!(
+        "0100 0000 0000 0000 0000 0000 0000 0000
+         0200 0000 0000 0000 0300 0000 0000 0000"
+    );
+    const CPU_TIMES: [u8; 24] = hex!(
+        "0100 0000 0000 0000 0400 0000 0000 0000
+         0000 0000 0000 0000"
+    );
+    const NO_BUILT_OUTPUTS: [u8; 8] = hex!("0000 0000 0000 0000");
+
+    /// The expected encoding for each version, without the built outputs,
+    /// at both sides of each version boundary.
+    /// Older versions drop the fields they don't know about.
+    /// These are derived from the serializers in Nix' worker-protocol.cc,
+    /// not captured from a running Nix.
+    #[rstest]
+    #[case::v1_21(21, &[&STATUS_AND_MSG[..]])]
+    #[case::v1_27(27, &[&STATUS_AND_MSG[..]])]
+    #[case::v1_28(28, &[&STATUS_AND_MSG[..], &NO_BUILT_OUTPUTS])]
+    #[case::v1_29(29, &[&STATUS_AND_MSG[..], &TIMES, &NO_BUILT_OUTPUTS])]
+    #[case::v1_36(36, &[&STATUS_AND_MSG[..], &TIMES, &NO_BUILT_OUTPUTS])]
+    #[case::v1_37(37, &[&STATUS_AND_MSG[..], &TIMES, &CPU_TIMES, &NO_BUILT_OUTPUTS])]
+    #[tokio::test]
+    async fn write_versioned(#[case] minor: u8, #[case] expected: &[&[u8]]) {
+        let version = ProtocolVersion::from_parts(1, minor);
+        let result = BuildResult {
+            built_outputs: BTreeMap::new(),
+            ..build_result()
+        };
+
+        let mut writer = NixWriter::builder().set_version(version).build(Vec::new());
+        writer.write_value(&result).await.unwrap();
+        assert_eq!(expected.concat(), writer.into_inner());
+    }
+
+    #[rstest]
+    #[case::v1_21(21)]
+    #[case::v1_28(28)]
+    #[case::v1_29(29)]
+    #[case::v1_37(37)]
+    #[tokio::test]
+    async fn roundtrip(#[case] minor: u8) {
+        let version = ProtocolVersion::from_parts(1, minor);
+        let result = build_result();
+
+        let mut writer = NixWriter::builder().set_version(version).build(Vec::new());
+        writer.write_value(&result).await.unwrap();
+        let written = writer.into_inner();
+
+        let mut reader = NixReader::builder()
+            .set_version(version)
+            .build(&written[..]);
+        let read_result = reader.read_value::<BuildResult>().await.unwrap();
+
+        let mut expected = BuildResult::new(result.status, result.error_msg.clone());
+        if minor >= 28 {
+            expected.built_outputs = result.built_outputs.clone();
+        }
+        if minor >= 29 {
+            expected.times_built = result.times_built;
+            expected.start_time = result.start_time;
+            expected.stop_time = result.stop_time;
+        }
+        if minor >= 37 {
+            expected.cpu_user = result.cpu_user;
+        }
+        assert_eq!(expected, read_result);
+    }
+}
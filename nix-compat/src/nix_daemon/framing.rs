@@ -0,0 +1,176 @@
+//! Framed data, as sent by clients in `AddToStoreNar` and
+//! `AddMultipleToStore` requests.
+//!
+//! The data is split into frames, each preceded by its length as a u64.
+//! Contrary to bytes packets, frames are not padded.
+//! A frame of length 0 marks the end of the data.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use pin_project_lite::pin_project;
+use tokio::io::{AsyncRead, ReadBuf};
+
+#[derive(Debug)]
+enum State {
+    /// Reading the length of the next frame.
+    Length { buf: [u8; 8], filled: usize },
+    /// Reading the contents of a frame, with this many bytes left.
+    Data { remaining: u64 },
+    /// The terminating empty frame has been read.
+    Eof,
+}
+
+pin_project! {
+    /// Exposes the contents of framed data as an [AsyncRead].
+    /// It returns EOF once the terminating frame is read, and doesn't read
+    /// any further from the inner reader.
+    pub struct NixFramedReader<R> {
+        #[pin]
+        inner: R,
+        state: State,
+    }
+}
+
+impl<R> NixFramedReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            state: State::Length {
+                buf: [0; 8],
+                filled: 0,
+            },
+        }
+    }
+
+    /// Whether the terminating frame has been read.
+    pub fn is_eof(&self) -> bool {
+        matches!(self.state, State::Eof)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for NixFramedReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        loop {
+            match this.state {
+                State::Length {
+                    buf: len_buf,
+                    filled,
+                } => {
+                    let mut len_read_buf = ReadBuf::new(&mut len_buf[*filled..]);
+                    ready!(this.inner.as_mut().poll_read(cx, &mut len_read_buf))?;
+                    let n = len_read_buf.filled().len();
+                    if n == 0 {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    *filled += n;
+
+                    if *filled == len_buf.len() {
+                        *this.state = match u64::from_le_bytes(*len_buf) {
+                            0 => State::Eof,
+                            remaining => State::Data { remaining },
+                        };
+                    }
+                }
+                State::Data { remaining } => {
+                    if buf.remaining() == 0 {
+                        return Poll::Ready(Ok(()));
+                    }
+
+                    let max = usize::try_from(*remaining)
+                        .unwrap_or(usize::MAX)
+                        .min(buf.remaining());
+                    let mut data_buf = buf.take(max);
+                    ready!(this.inner.as_mut().poll_read(cx, &mut data_buf))?;
+                    let n = data_buf.filled().len();
+                    if n == 0 {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+
+                    // SAFETY: the inner reader initialized and filled n bytes
+                    // of the unfilled part of buf.
+                    unsafe {
+                        buf.assume_init(n);
+                    }
+                    buf.advance(n);
+
+                    *remaining -= n as u64;
+                    if *remaining == 0 {
+                        *this.state = State::Length {
+                            buf: [0; 8],
+                            filled: 0,
+                        };
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                State::Eof => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hex_literal::hex;
+    use tokio::io::AsyncReadExt;
+    use tokio_test::io::Builder;
+
+    use super::NixFramedReader;
+
+    #[tokio::test]
+    async fn read_frames() {
+        let mock = Builder::new()
+            .read(&hex!("0300 0000 0000 0000 6865 6c"))
+            .wait(Duration::ZERO)
+            .read(&hex!("0200 0000 0000 0000 6c6f 0000 0000 0000 0000"))
+            .read(b"rest")
+            .build();
+        let mut reader = NixFramedReader::new(mock);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(b"hello", &buf[..]);
+        assert!(reader.is_eof());
+
+        // data after the terminating frame is left untouched.
+        let mut rest = Vec::new();
+        reader.into_inner().read_to_end(&mut rest).await.unwrap();
+        assert_eq!(b"rest", &rest[..]);
+    }
+
+    #[tokio::test]
+    async fn read_empty() {
+        let mock = Builder::new().read(&hex!("0000 0000 0000 0000")).build();
+        let mut reader = NixFramedReader::new(mock);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_missing_terminator() {
+        let mock = Builder::new()
+            .read(&hex!("0200 0000 0000 0000 6869"))
+            .build();
+        let mut reader = NixFramedReader::new(mock);
+
+        let mut buf = Vec::new();
+        assert_eq!(
+            std::io::ErrorKind::UnexpectedEof,
+            reader.read_to_end(&mut buf).await.unwrap_err().kind()
+        );
+    }
+}
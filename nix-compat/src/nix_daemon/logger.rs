@@ -0,0 +1,470 @@
+//! Log messages the daemon interleaves with the results of operations.
+//!
+//! After reading the request of an operation, the daemon sends any number of
+//! [LogMessage]s, concluded by [LogMessage::Last] (followed by the result) or
+//! [LogMessage::Error] (without result).
+//! See `docs/src/nix-daemon/logging.md` for details.
+
+use bytes::Bytes;
+use enum_primitive_derive::Primitive;
+use num_traits::FromPrimitive;
+
+use super::de::{self, NixDeserialize, NixRead};
+use super::ser::{NixSerialize, NixWrite};
+use super::worker_protocol::{
+    Verbosity, STDERR_ERROR, STDERR_LAST, STDERR_NEXT, STDERR_READ, STDERR_RESULT,
+    STDERR_START_ACTIVITY, STDERR_STOP_ACTIVITY, STDERR_WRITE,
+};
+
+/// The type of an [Activity].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Primitive)]
+pub enum ActivityType {
+    Unknown = 0,
+    CopyPath = 100,
+    FileTransfer = 101,
+    Realise = 102,
+    CopyPaths = 103,
+    Builds = 104,
+    Build = 105,
+    OptimiseStore = 106,
+    VerifyPaths = 107,
+    Substitute = 108,
+    QueryPathInfo = 109,
+    PostBuildHook = 110,
+    BuildWaiting = 111,
+    FetchTree = 112,
+}
+
+/// The type of an [ActivityResult].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Primitive)]
+pub enum ResultType {
+    FileLinked = 100,
+    BuildLogLine = 101,
+    UntrustedPath = 102,
+    CorruptedPath = 103,
+    SetPhase = 104,
+    Progress = 105,
+    SetExpected = 106,
+    PostBuildLogLine = 107,
+    FetchStatus = 108,
+}
+
+macro_rules! primitive_enum {
+    ($($ty:ident),*) => {$(
+        impl NixDeserialize for $ty {
+            async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+            where
+                R: ?Sized + NixRead + Send,
+            {
+                if let Some(value) = reader.try_read_number().await? {
+                    $ty::from_u64(value).map(Some).ok_or_else(|| {
+                        <R::Error as de::Error>::invalid_data(format!(
+                            "invalid {} {}",
+                            stringify!($ty),
+                            value
+                        ))
+                    })
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+
+        impl NixSerialize for $ty {
+            async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+            where
+                W: ?Sized + NixWrite + Send,
+            {
+                writer.write_number(*self as u64).await
+            }
+        }
+    )*};
+}
+
+primitive_enum!(Verbosity, ActivityType, ResultType);
+
+/// Fields of activities and their results, their meaning depends on the
+/// [ActivityType] or [ResultType].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoggerField {
+    Int(u64),
+    String(String),
+}
+
+impl NixDeserialize for LoggerField {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if let Some(field_type) = reader.try_read_number().await? {
+            match field_type {
+                0 => Ok(Some(LoggerField::Int(reader.read_number().await?))),
+                1 => Ok(Some(LoggerField::String(reader.read_value().await?))),
+                _ => Err(<R::Error as de::Error>::invalid_data(format!(
+                    "invalid field type {field_type}"
+                ))),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl NixSerialize for LoggerField {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        match self {
+            LoggerField::Int(value) => {
+                writer.write_number(0).await?;
+                writer.write_number(*value).await
+            }
+            LoggerField::String(value) => {
+                writer.write_number(1).await?;
+                writer.write_value(value).await
+            }
+        }
+    }
+}
+
+/// Begins an activity, which is like a span in other tracing frameworks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    pub id: u64,
+    pub level: Verbosity,
+    pub activity_type: ActivityType,
+    pub text: String,
+    pub fields: Vec<LoggerField>,
+    /// The id of the parent activity, 0 if there's none.
+    pub parent: u64,
+}
+
+/// A result of an activity, like progress or a build log line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivityResult {
+    pub id: u64,
+    pub result_type: ResultType,
+    pub fields: Vec<LoggerField>,
+}
+
+/// An error reported by the daemon, concluding the operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NixError {
+    pub level: Verbosity,
+    pub msg: String,
+    /// Hints of the traces, outermost last.
+    /// Only sent since protocol version 1.26.
+    pub traces: Vec<String>,
+}
+
+impl NixError {
+    pub fn new(msg: impl Into<String>) -> Self {
+        Self {
+            level: Verbosity::LvlError,
+            msg: msg.into(),
+            traces: vec![],
+        }
+    }
+}
+
+impl std::fmt::Display for NixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+impl NixDeserialize for NixError {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if reader.version().minor() >= 26 {
+            // "Error", unused.
+            if reader.try_read_bytes().await?.is_none() {
+                return Ok(None);
+            }
+            let level = reader.read_value().await?;
+            let _name: Bytes = reader.read_value().await?;
+            let msg = reader.read_value().await?;
+            let _have_pos = reader.read_number().await?;
+            let num_traces = reader.read_number().await?;
+            let mut traces = Vec::new();
+            for _ in 0..num_traces {
+                let _have_pos = reader.read_number().await?;
+                traces.push(reader.read_value().await?);
+            }
+            Ok(Some(NixError { level, msg, traces }))
+        } else if let Some(msg) = reader.try_read_value().await? {
+            let _exit_status = reader.read_number().await?;
+            Ok(Some(NixError {
+                level: Verbosity::LvlError,
+                msg,
+                traces: vec![],
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl NixSerialize for NixError {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        if writer.version().minor() >= 26 {
+            writer.write_value("Error").await?;
+            writer.write_value(&self.level).await?;
+            writer.write_value("Error").await?;
+            writer.write_value(&self.msg).await?;
+            writer.write_number(0).await?; // no position
+            writer.write_number(self.traces.len() as u64).await?;
+            for trace in &self.traces {
+                writer.write_number(0).await?; // no position
+                writer.write_value(trace).await?;
+            }
+            Ok(())
+        } else {
+            writer.write_value(&self.msg).await?;
+            writer.write_number(1).await // exit status
+        }
+    }
+}
+
+/// A message sent on the stderr channel of the daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogMessage {
+    /// Concludes the log messages, the result of the operation follows.
+    Last,
+    /// Concludes the log messages, the operation failed.
+    Error(NixError),
+    /// A plain log line.
+    Next(String),
+    /// Requests the client to send that many bytes.
+    Read(u64),
+    /// Data the client should write out.
+    Write(Bytes),
+    /// Since protocol version 1.20, like the following two.
+    StartActivity(Activity),
+    StopActivity(u64),
+    Result(ActivityResult),
+}
+
+impl NixDeserialize for LogMessage {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        let msg = if let Some(msg) = reader.try_read_number().await? {
+            msg
+        } else {
+            return Ok(None);
+        };
+
+        // The message types are statics, so can't be matched on.
+        Ok(Some(if msg == STDERR_LAST {
+            LogMessage::Last
+        } else if msg == STDERR_ERROR {
+            LogMessage::Error(reader.read_value().await?)
+        } else if msg == STDERR_NEXT {
+            LogMessage::Next(reader.read_value().await?)
+        } else if msg == STDERR_READ {
+            LogMessage::Read(reader.read_number().await?)
+        } else if msg == STDERR_WRITE {
+            LogMessage::Write(reader.read_bytes().await?)
+        } else if msg == STDERR_START_ACTIVITY {
+            LogMessage::StartActivity(Activity {
+                id: reader.read_number().await?,
+                level: reader.read_value().await?,
+                activity_type: reader.read_value().await?,
+                text: reader.read_value().await?,
+                fields: reader.read_value().await?,
+                parent: reader.read_number().await?,
+            })
+        } else if msg == STDERR_STOP_ACTIVITY {
+            LogMessage::StopActivity(reader.read_number().await?)
+        } else if msg == STDERR_RESULT {
+            LogMessage::Result(ActivityResult {
+                id: reader.read_number().await?,
+                result_type: reader.read_value().await?,
+                fields: reader.read_value().await?,
+            })
+        } else {
+            return Err(<R::Error as de::Error>::invalid_data(format!(
+                "invalid log message {msg:#x}"
+            )));
+        }))
+    }
+}
+
+/// Activities are only supported since protocol version 1.20. For older
+/// clients, the text of started activities is sent as a plain log line, and
+/// other activity messages are skipped.
+impl NixSerialize for LogMessage {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        let has_activities = writer.version().minor() >= 20;
+
+        match self {
+            LogMessage::Last => writer.write_number(STDERR_LAST).await,
+            LogMessage::Error(err) => {
+                writer.write_number(STDERR_ERROR).await?;
+                writer.write_value(err).await
+            }
+            LogMessage::Next(msg) => {
+                writer.write_number(STDERR_NEXT).await?;
+                writer.write_value(msg).await
+            }
+            LogMessage::Read(len) => {
+                writer.write_number(STDERR_READ).await?;
+                writer.write_number(*len).await
+            }
+            LogMessage::Write(buf) => {
+                writer.write_number(STDERR_WRITE).await?;
+                writer.write_value(buf).await
+            }
+            LogMessage::StartActivity(activity) if has_activities => {
+                writer.write_number(STDERR_START_ACTIVITY).await?;
+                writer.write_number(activity.id).await?;
+                writer.write_value(&activity.level).await?;
+                writer.write_value(&activity.activity_type).await?;
+                writer.write_value(&activity.text).await?;
+                writer.write_value(&activity.fields).await?;
+                writer.write_number(activity.parent).await
+            }
+            LogMessage::StartActivity(activity) => {
+                if activity.text.is_empty() {
+                    return Ok(());
+                }
+                writer.write_number(STDERR_NEXT).await?;
+                writer
+                    .write_display(format!("{}...\n", activity.text))
+                    .await
+            }
+            LogMessage::StopActivity(id) if has_activities => {
+                writer.write_number(STDERR_STOP_ACTIVITY).await?;
+                writer.write_number(*id).await
+            }
+            LogMessage::Result(result) if has_activities => {
+                writer.write_number(STDERR_RESULT).await?;
+                writer.write_number(result.id).await?;
+                writer.write_value(&result.result_type).await?;
+                writer.write_value(&result.fields).await
+            }
+            LogMessage::StopActivity(_) | LogMessage::Result(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::nix_daemon::de::NixReader;
+    use crate::nix_daemon::ser::NixWriter;
+    use crate::nix_daemon::ProtocolVersion;
+
+    #[rstest]
+    #[case::last(LogMessage::Last, &hex!("7374 6c61 0000 0000"))]
+    #[case::next(
+        LogMessage::Next("hi\n".to_string()),
+        &hex!("676d 6c6f 0000 0000 0300 0000 0000 0000 6869 0a00 0000 0000")
+    )]
+    #[case::stop_activity(
+        LogMessage::StopActivity(42),
+        &hex!("504f 5453 0000 0000 2a00 0000 0000 0000")
+    )]
+    #[case::start_activity(
+        LogMessage::StartActivity(Activity {
+            id: 1,
+            level: Verbosity::LvlInfo,
+            activity_type: ActivityType::CopyPath,
+            text: "copy".to_string(),
+            fields: vec![LoggerField::Int(7), LoggerField::String("a".to_string())],
+            parent: 0,
+        }),
+        &hex!(
+            "5452 5453 0000 0000
+             0100 0000 0000 0000
+             0300 0000 0000 0000
+             6400 0000 0000 0000
+             0400 0000 0000 0000 636f 7079 0000 0000
+             0200 0000 0000 0000
+             0000 0000 0000 0000 0700 0000 0000 0000
+             0100 0000 0000 0000 0100 0000 0000 0000 6100 0000 0000 0000
+             0000 0000 0000 0000"
+        )
+    )]
+    #[case::result(
+        LogMessage::Result(ActivityResult {
+            id: 1,
+            result_type: ResultType::Progress,
+            fields: vec![LoggerField::Int(1), LoggerField::Int(2)],
+        }),
+        &hex!(
+            "544c 5352 0000 0000
+             0100 0000 0000 0000
+             6900 0000 0000 0000
+             0200 0000 0000 0000
+             0000 0000 0000 0000 0100 0000 0000 0000
+             0000 0000 0000 0000 0200 0000 0000 0000"
+        )
+    )]
+    #[case::error(
+        LogMessage::Error(NixError::new("oops")),
+        &hex!(
+            "7074 7863 0000 0000
+             0500 0000 0000 0000 4572 726f 7200 0000
+             0000 0000 0000 0000
+             0500 0000 0000 0000 4572 726f 7200 0000
+             0400 0000 0000 0000 6f6f 7073 0000 0000
+             0000 0000 0000 0000
+             0000 0000 0000 0000"
+        )
+    )]
+    #[tokio::test]
+    async fn roundtrip(#[case] msg: LogMessage, #[case] expected: &[u8]) {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(&msg).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(msg, reader.read_value::<LogMessage>().await.unwrap());
+    }
+
+    #[rstest]
+    #[case::error(
+        LogMessage::Error(NixError::new("oops")),
+        &hex!(
+            "7074 7863 0000 0000
+             0400 0000 0000 0000 6f6f 7073 0000 0000
+             0100 0000 0000 0000"
+        )
+    )]
+    #[case::start_activity(
+        LogMessage::StartActivity(Activity {
+            id: 1,
+            level: Verbosity::LvlInfo,
+            activity_type: ActivityType::CopyPath,
+            text: "copy".to_string(),
+            fields: vec![],
+            parent: 0,
+        }),
+        &hex!("676d 6c6f 0000 0000 0800 0000 0000 0000 636f 7079 2e2e 2e0a")
+    )]
+    #[case::stop_activity(LogMessage::StopActivity(1), &[])]
+    #[tokio::test]
+    async fn write_v1_19(#[case] msg: LogMessage, #[case] expected: &[u8]) {
+        let mut writer = NixWriter::builder()
+            .set_version(ProtocolVersion::from_parts(1, 19))
+            .build(Vec::new());
+        writer.write_value(&msg).await.unwrap();
+        assert_eq!(expected, &writer.into_inner()[..]);
+    }
+}
@@ -4,3 +4,17 @@ mod protocol_version;
 pub use protocol_version::ProtocolVersion;
 
 pub mod de;
+pub mod framing;
+pub mod logger;
+pub mod ser;
+
+mod add_to_store;
+pub use add_to_store::AddToStoreRequest;
+
+mod build_result;
+pub use build_result::{BuildResult, BuildStatus};
+
+mod path_info;
+pub use path_info::{UnkeyedValidPathInfo, ValidPathInfo};
+
+mod types;
@@ -0,0 +1,233 @@
+use crate::narinfo::Signature;
+use crate::nixhash::{CAHash, NixHash};
+use crate::store_path::StorePath;
+
+use super::de::{NixDeserialize, NixRead};
+use super::ser::{NixSerialize, NixWrite};
+
+/// The metadata of a store path, as sent in `QueryPathInfo` replies, and in
+/// `AddToStoreNar` requests.
+/// This is called `UnkeyedValidPathInfo` in Nix, as it doesn't contain the
+/// store path itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnkeyedValidPathInfo {
+    /// The store path of the derivation that produced this path, including
+    /// its `.drv` suffix.
+    pub deriver: Option<StorePath<String>>,
+    /// The hash of the NAR representation of the contents.
+    /// It's sent hex-encoded, without the algo.
+    pub nar_hash: NixHash,
+    pub references: Vec<StorePath<String>>,
+    /// Seconds since the epoch.
+    pub registration_time: u64,
+    pub nar_size: u64,
+    /// Whether the path was built locally, rather than substituted.
+    /// Only sent since protocol version 1.16, like the following fields.
+    pub ultimate: bool,
+    pub signatures: Vec<Signature<String>>,
+    pub ca: Option<CAHash>,
+}
+
+impl NixDeserialize for UnkeyedValidPathInfo {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        let deriver = match reader.try_read_value().await? {
+            Some(deriver) => deriver,
+            None => return Ok(None),
+        };
+        let nar_hash = reader.read_value().await?;
+        let references = reader.read_value().await?;
+        let registration_time = reader.read_value().await?;
+        let nar_size = reader.read_value().await?;
+
+        let (ultimate, signatures, ca) = if reader.version().minor() >= 16 {
+            (
+                reader.read_value().await?,
+                reader.read_value().await?,
+                reader.read_value().await?,
+            )
+        } else {
+            (false, vec![], None)
+        };
+
+        Ok(Some(Self {
+            deriver,
+            nar_hash,
+            references,
+            registration_time,
+            nar_size,
+            ultimate,
+            signatures,
+            ca,
+        }))
+    }
+}
+
+impl NixSerialize for UnkeyedValidPathInfo {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_value(&self.deriver).await?;
+        writer.write_value(&self.nar_hash).await?;
+        writer.write_value(&self.references).await?;
+        writer.write_value(&self.registration_time).await?;
+        writer.write_value(&self.nar_size).await?;
+
+        if writer.version().minor() >= 16 {
+            writer.write_value(&self.ultimate).await?;
+            writer.write_value(&self.signatures).await?;
+            writer.write_value(&self.ca).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// [UnkeyedValidPathInfo], preceded by the store path it describes, as sent
+/// in `AddMultipleToStore` requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidPathInfo {
+    pub path: StorePath<String>,
+    pub info: UnkeyedValidPathInfo,
+}
+
+impl NixDeserialize for ValidPathInfo {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        let path = match reader.try_read_value().await? {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let info = reader.read_value().await?;
+
+        Ok(Some(Self { path, info }))
+    }
+}
+
+impl NixSerialize for ValidPathInfo {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_value(&self.path).await?;
+        writer.write_value(&self.info).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use super::{UnkeyedValidPathInfo, ValidPathInfo};
+    use crate::narinfo::Signature;
+    use crate::nix_daemon::de::{NixRead, NixReader};
+    use crate::nix_daemon::ser::{NixWrite, NixWriter};
+    use crate::nix_daemon::ProtocolVersion;
+    use crate::nixhash::{CAHash, NixHash};
+    use crate::store_path::StorePath;
+
+    fn path_info() -> ValidPathInfo {
+        ValidPathInfo {
+            path: StorePath::from_bytes(b"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432").unwrap(),
+            info: UnkeyedValidPathInfo {
+                deriver: Some(StorePath::from_bytes(b"1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf-net-tools-1.60_p20170221182432.drv").unwrap()),
+                nar_hash: NixHash::Sha256(hex!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")),
+                references: vec![StorePath::from_bytes(b"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432").unwrap()],
+                registration_time: 1700000000,
+                nar_size: 1234,
+                ultimate: true,
+                signatures: vec![Signature::parse("cache.nixos.org-1:92fl0i5q7EyegCj5Yf4L0bENkWuVAtgveiRcTEEUH0P6HvCE1xFcPbz/0Pf6Np+K1LPzHK+s5RHOmVoxRsvsDg==").unwrap()],
+                ca: CAHash::from_nix_hex_str("fixed:r:sha256:1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf7c4mwa4xj7r5sk0m9lkx"),
+            },
+        }
+    }
+
+    /// The fields sent in all versions, for [path_info] without the deriver,
+    /// references and signatures.
+    const BASE_FIELDS: [u8; 104] = hex!(
+        "0000 0000 0000 0000
+         4000 0000 0000 0000
+         6533 6230 6334 3432 3938 6663 3163 3134 3961 6662 6634 6338 3939 3666 6239 3234
+         3237 6165 3431 6534 3634 3962 3933 3463 6134 3935 3939 3162 3738 3532 6238 3535
+         0000 0000 0000 0000
+         00f1 5365 0000 0000
+         d204 0000 0000 0000"
+    );
+    /// ultimate, signatures and ca, sent since protocol version 1.16.
+    const V16_FIELDS: [u8; 24] = hex!(
+        "0100 0000 0000 0000
+         0000 0000 0000 0000
+         0000 0000 0000 0000"
+    );
+
+    /// The expected encoding at both sides of the version boundary.
+    /// These are derived from the serializers in Nix' worker-protocol.cc,
+    /// not captured from a running Nix.
+    #[rstest]
+    #[case::v1_15(15, &[&BASE_FIELDS[..]])]
+    #[case::v1_16(16, &[&BASE_FIELDS[..], &V16_FIELDS])]
+    #[case::v1_37(37, &[&BASE_FIELDS[..], &V16_FIELDS])]
+    #[tokio::test]
+    async fn write_versioned(#[case] minor: u8, #[case] expected: &[&[u8]]) {
+        let version = ProtocolVersion::from_parts(1, minor);
+        let info = UnkeyedValidPathInfo {
+            deriver: None,
+            references: vec![],
+            signatures: vec![],
+            ca: None,
+            ..path_info().info
+        };
+
+        let mut writer = NixWriter::builder().set_version(version).build(Vec::new());
+        writer.write_value(&info).await.unwrap();
+        assert_eq!(expected.concat(), writer.into_inner());
+    }
+
+    #[rstest]
+    #[case::current(37)]
+    #[case::v16(16)]
+    #[tokio::test]
+    async fn roundtrip(#[case] minor: u8) {
+        let version = ProtocolVersion::from_parts(1, minor);
+        let path_info = path_info();
+
+        let mut writer = NixWriter::builder().set_version(version).build(Vec::new());
+        writer.write_value(&path_info).await.unwrap();
+        let written = writer.into_inner();
+
+        let mut reader = NixReader::builder()
+            .set_version(version)
+            .build(&written[..]);
+        assert_eq!(
+            path_info,
+            reader.read_value::<ValidPathInfo>().await.unwrap()
+        );
+    }
+
+    /// Before protocol version 1.16, ultimate, signatures and ca aren't sent.
+    #[tokio::test]
+    async fn roundtrip_v15() {
+        let version = ProtocolVersion::from_parts(1, 15);
+        let mut path_info = path_info();
+
+        let mut writer = NixWriter::builder().set_version(version).build(Vec::new());
+        writer.write_value(&path_info).await.unwrap();
+        let written = writer.into_inner();
+
+        let mut reader = NixReader::builder()
+            .set_version(version)
+            .build(&written[..]);
+        let read_path_info = reader.read_value::<ValidPathInfo>().await.unwrap();
+
+        path_info.info.ultimate = false;
+        path_info.info.signatures = vec![];
+        path_info.info.ca = None;
+        assert_eq!(path_info, read_path_info);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{collections::BTreeMap, future::Future};
+
+use super::{NixSerialize, NixWrite};
+
+#[allow(clippy::manual_async_fn)]
+impl<T> NixSerialize for [T]
+where
+    T: NixSerialize + Send + Sync,
+{
+    fn serialize<W>(&self, writer: &mut W) -> impl Future<Output = Result<(), W::Error>> + Send
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        async move {
+            writer.write_value(&self.len()).await?;
+            for value in self {
+                writer.write_value(value).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl<T> NixSerialize for Vec<T>
+where
+    T: NixSerialize + Send + Sync,
+{
+    fn serialize<W>(&self, writer: &mut W) -> impl Future<Output = Result<(), W::Error>> + Send
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        self.as_slice().serialize(writer)
+    }
+}
+
+#[allow(clippy::manual_async_fn)]
+impl<K, V> NixSerialize for BTreeMap<K, V>
+where
+    K: NixSerialize + Ord + Send + Sync,
+    V: NixSerialize + Send + Sync,
+{
+    fn serialize<W>(&self, writer: &mut W) -> impl Future<Output = Result<(), W::Error>> + Send
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        async move {
+            writer.write_value(&self.len()).await?;
+            for (key, value) in self {
+                writer.write_value(key).await?;
+                writer.write_value(value).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::fmt;
+
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use crate::nix_daemon::de::{NixDeserialize, NixRead, NixReader};
+    use crate::nix_daemon::ser::{NixSerialize, NixWrite, NixWriter};
+
+    #[rstest]
+    #[case::empty(vec![], &hex!("0000 0000 0000 0000"))]
+    #[case::one(vec![0x29], &hex!("0100 0000 0000 0000 2900 0000 0000 0000"))]
+    #[case::two(vec![0x7469, 10], &hex!("0200 0000 0000 0000 6974 0000 0000 0000 0A00 0000 0000 0000"))]
+    #[tokio::test]
+    async fn test_write_small_vec(#[case] value: Vec<usize>, #[case] expected: &[u8]) {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(&value).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(value, reader.read_value::<Vec<usize>>().await.unwrap());
+    }
+
+    fn empty_map() -> BTreeMap<usize, u64> {
+        BTreeMap::new()
+    }
+    macro_rules! map {
+        ($($key:expr => $value:expr),*) => {{
+            let mut ret = BTreeMap::new();
+            $(ret.insert($key, $value);)*
+            ret
+        }};
+    }
+
+    #[rstest]
+    #[case::empty(empty_map(), &hex!("0000 0000 0000 0000"))]
+    #[case::one(map![0x7469usize => 10u64], &hex!("0100 0000 0000 0000 6974 0000 0000 0000 0A00 0000 0000 0000"))]
+    #[tokio::test]
+    async fn test_write_small_btree_map<E>(#[case] value: E, #[case] expected: &[u8])
+    where
+        E: NixSerialize + NixDeserialize + Send + Sync + PartialEq + fmt::Debug,
+    {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(&value).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(value, reader.read_value::<E>().await.unwrap());
+    }
+}
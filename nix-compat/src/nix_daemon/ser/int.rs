@@ -0,0 +1,108 @@
+use super::{Error, NixSerialize, NixWrite};
+
+impl NixSerialize for u64 {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_number(*self).await
+    }
+}
+
+impl NixSerialize for usize {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        let value: u64 = (*self).try_into().map_err(W::Error::unsupported_data)?;
+        writer.write_number(value).await
+    }
+}
+
+impl NixSerialize for bool {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_number(*self as u64).await
+    }
+}
+
+impl NixSerialize for i64 {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_number(*self as u64).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use crate::nix_daemon::de::{NixRead, NixReader};
+    use crate::nix_daemon::ser::{NixWrite, NixWriter};
+
+    #[rstest]
+    #[case::simple_false(false, &hex!("0000 0000 0000 0000"))]
+    #[case::simple_true(true, &hex!("0100 0000 0000 0000"))]
+    #[tokio::test]
+    async fn test_write_bool(#[case] value: bool, #[case] expected: &[u8]) {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(&value).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(value, reader.read_value::<bool>().await.unwrap());
+    }
+
+    #[rstest]
+    #[case::zero(0, &hex!("0000 0000 0000 0000"))]
+    #[case::one(1, &hex!("0100 0000 0000 0000"))]
+    #[case::other(0x563412, &hex!("1234 5600 0000 0000"))]
+    #[case::max_value(u64::MAX, &hex!("FFFF FFFF FFFF FFFF"))]
+    #[tokio::test]
+    async fn test_write_u64(#[case] value: u64, #[case] expected: &[u8]) {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(&value).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(value, reader.read_value::<u64>().await.unwrap());
+    }
+
+    #[rstest]
+    #[case::zero(0, &hex!("0000 0000 0000 0000"))]
+    #[case::one(1, &hex!("0100 0000 0000 0000"))]
+    #[case::other(0x563412, &hex!("1234 5600 0000 0000"))]
+    #[case::max_value(usize::MAX, &usize::MAX.to_le_bytes())]
+    #[tokio::test]
+    async fn test_write_usize(#[case] value: usize, #[case] expected: &[u8]) {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(&value).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(value, reader.read_value::<usize>().await.unwrap());
+    }
+
+    #[rstest]
+    #[case::zero(0, &hex!("0000 0000 0000 0000"))]
+    #[case::minus_one(-1, &hex!("FFFF FFFF FFFF FFFF"))]
+    #[case::max_value(i64::MAX, &hex!("FFFF FFFF FFFF FF7F"))]
+    #[tokio::test]
+    async fn test_write_i64(#[case] value: i64, #[case] expected: &[u8]) {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(&value).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(value, reader.read_value::<i64>().await.unwrap());
+    }
+}
@@ -0,0 +1,138 @@
+use std::io;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::nix_daemon::ProtocolVersion;
+use crate::wire::EMPTY_BYTES;
+
+use super::NixWrite;
+
+#[derive(Default)]
+pub struct NixWriterBuilder {
+    version: ProtocolVersion,
+}
+
+impl NixWriterBuilder {
+    pub fn set_version(mut self, version: ProtocolVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn build<W>(self, writer: W) -> NixWriter<W> {
+        NixWriter {
+            inner: writer,
+            version: self.version,
+        }
+    }
+}
+
+/// Writes values to an [AsyncWrite] in the Nix daemon wire format.
+///
+/// Note: values are written piece by piece, so make sure the inner writer
+/// is buffered if performance matters to you.
+pub struct NixWriter<W> {
+    inner: W,
+    version: ProtocolVersion,
+}
+
+impl NixWriter<Vec<u8>> {
+    pub fn builder() -> NixWriterBuilder {
+        NixWriterBuilder::default()
+    }
+}
+
+impl<W> NixWriter<W>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    pub fn new(writer: W) -> NixWriter<W> {
+        NixWriter::builder().build(writer)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the inner writer, to write data not in the wire format
+    /// (like a NAR) directly.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush().await
+    }
+
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+impl<W> NixWrite for NixWriter<W>
+where
+    W: AsyncWrite + Send + Unpin,
+{
+    type Error = io::Error;
+
+    fn version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    async fn write_number(&mut self, value: u64) -> Result<(), Self::Error> {
+        self.inner.write_all(&value.to_le_bytes()).await
+    }
+
+    async fn write_slice(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.write_number(buf.len() as u64).await?;
+        self.inner.write_all(buf).await?;
+
+        // pad the contents to a multiple of 8 bytes
+        let padding = buf.len().wrapping_neg() & 7;
+        if padding != 0 {
+            self.inner.write_all(&EMPTY_BYTES[..padding]).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use crate::nix_daemon::de::{NixRead, NixReader};
+    use crate::nix_daemon::ser::{NixWrite, NixWriter};
+
+    #[rstest]
+    #[case::zero(0, &hex!("0000 0000 0000 0000"))]
+    #[case::one(1, &hex!("0100 0000 0000 0000"))]
+    #[case::other(0x563412, &hex!("1234 5600 0000 0000"))]
+    #[case::max_value(u64::MAX, &hex!("FFFF FFFF FFFF FFFF"))]
+    #[tokio::test]
+    async fn test_write_number(#[case] value: u64, #[case] expected: &[u8]) {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_number(value).await.unwrap();
+        assert_eq!(expected, &writer.into_inner()[..]);
+    }
+
+    #[rstest]
+    #[case::empty(b"", &hex!("0000 0000 0000 0000"))]
+    #[case::one(b")", &hex!("0100 0000 0000 0000 2900 0000 0000 0000"))]
+    #[case::seven(b"where's", &hex!("0700 0000 0000 0000 7768 6572 6527 7300"))]
+    #[case::aligned(b"read_tea", &hex!("0800 0000 0000 0000 7265 6164 5F74 6561"))]
+    #[case::more_bytes(b"read_tess", &hex!("0900 0000 0000 0000 7265 6164 5F74 6573 7300 0000 0000 0000"))]
+    #[tokio::test]
+    async fn test_write_slice(#[case] value: &[u8], #[case] expected: &[u8]) {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_slice(value).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(value, &reader.read_bytes().await.unwrap()[..]);
+    }
+}
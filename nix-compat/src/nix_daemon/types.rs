@@ -0,0 +1,307 @@
+//! [NixSerialize] and [NixDeserialize] implementations for types defined in
+//! other modules of nix-compat, as they appear in the daemon protocol.
+
+use data_encoding::HEXLOWER;
+
+use super::de::{Error, NixDeserialize, NixRead};
+use super::ser::{NixSerialize, NixWrite};
+use crate::narinfo::Signature;
+use crate::nixhash::{self, CAHash, HashAlgo, NixHash};
+use crate::store_path::StorePath;
+
+/// Store paths are sent as absolute paths.
+impl NixSerialize for StorePath<String> {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_display(self.to_absolute_path()).await
+    }
+}
+
+impl NixDeserialize for StorePath<String> {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if let Some(buf) = reader.try_read_bytes().await? {
+            StorePath::from_absolute_path(&buf)
+                .map_err(R::Error::invalid_data)
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Optional store paths (like the deriver of a path) are sent as an empty
+/// string if missing.
+impl NixSerialize for Option<StorePath<String>> {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        match self {
+            Some(store_path) => writer.write_value(store_path).await,
+            None => writer.write_slice(b"").await,
+        }
+    }
+}
+
+impl NixDeserialize for Option<StorePath<String>> {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if let Some(buf) = reader.try_read_bytes().await? {
+            if buf.is_empty() {
+                Ok(Some(None))
+            } else {
+                StorePath::from_absolute_path(&buf)
+                    .map_err(R::Error::invalid_data)
+                    .map(|store_path| Some(Some(store_path)))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Hashes (like the NAR hash of a path) are sent as the hex-encoded digest,
+/// without the algo. The algo is inferred from the digest length on the
+/// receiving end.
+impl NixSerialize for NixHash {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_display(self.to_plain_hex_string()).await
+    }
+}
+
+impl NixDeserialize for NixHash {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if let Some(buf) = reader.try_read_bytes().await? {
+            let digest = HEXLOWER.decode(&buf).map_err(R::Error::invalid_data)?;
+            let algo = [
+                HashAlgo::Md5,
+                HashAlgo::Sha1,
+                HashAlgo::Sha256,
+                HashAlgo::Sha512,
+            ]
+            .into_iter()
+            .find(|algo| algo.digest_length() == digest.len())
+            .ok_or_else(|| R::Error::invalid_data("invalid digest length"))?;
+
+            nixhash::from_algo_and_digest(algo, &digest)
+                .map_err(R::Error::invalid_data)
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Content addresses are sent in the same textual form used in NARInfo,
+/// like `fixed:r:sha256:$nixbase32digest`.
+impl NixSerialize for CAHash {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_display(self.to_nix_nixbase32_string()).await
+    }
+}
+
+impl NixDeserialize for CAHash {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if let Some(buf) = reader.try_read_bytes().await? {
+            let s = std::str::from_utf8(&buf).map_err(R::Error::invalid_data)?;
+            CAHash::from_nix_hex_str(s)
+                .ok_or_else(|| R::Error::invalid_data(format!("invalid content address {s}")))
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Missing content addresses are sent as an empty string.
+impl NixSerialize for Option<CAHash> {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        match self {
+            Some(ca_hash) => writer.write_value(ca_hash).await,
+            None => writer.write_slice(b"").await,
+        }
+    }
+}
+
+impl NixDeserialize for Option<CAHash> {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if let Some(buf) = reader.try_read_bytes().await? {
+            if buf.is_empty() {
+                return Ok(Some(None));
+            }
+            let s = std::str::from_utf8(&buf).map_err(R::Error::invalid_data)?;
+            CAHash::from_nix_hex_str(s)
+                .ok_or_else(|| R::Error::invalid_data(format!("invalid content address {s}")))
+                .map(|ca_hash| Some(Some(ca_hash)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Signatures are sent in the same form as in NARInfo, `name:base64`.
+impl NixSerialize for Signature<String> {
+    async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: ?Sized + NixWrite + Send,
+    {
+        writer.write_display(self).await
+    }
+}
+
+impl NixDeserialize for Signature<String> {
+    async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+    where
+        R: ?Sized + NixRead + Send,
+    {
+        if let Some(buf) = reader.try_read_bytes().await? {
+            let s = std::str::from_utf8(&buf).map_err(R::Error::invalid_data)?;
+            Signature::parse(s)
+                .map_err(R::Error::invalid_data)
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt;
+    use std::io;
+
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use crate::narinfo::Signature;
+    use crate::nix_daemon::de::{NixDeserialize, NixRead, NixReader};
+    use crate::nix_daemon::ser::{NixSerialize, NixWrite, NixWriter};
+    use crate::nixhash::{CAHash, NixHash};
+    use crate::store_path::StorePath;
+
+    async fn roundtrip<T>(value: T, expected: &[u8])
+    where
+        T: NixSerialize + NixDeserialize + Send + Sync + PartialEq + fmt::Debug,
+    {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(&value).await.unwrap();
+        let written = writer.into_inner();
+        assert_eq!(expected, &written[..]);
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(value, reader.read_value::<T>().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn store_path_roundtrip() {
+        let store_path = StorePath::<String>::from_bytes(
+            b"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432",
+        )
+        .unwrap();
+
+        let mut expected = 74u64.to_le_bytes().to_vec();
+        expected.extend_from_slice(
+            b"/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432\0\0\0\0\0\0",
+        );
+        roundtrip(store_path, &expected).await;
+    }
+
+    #[rstest]
+    #[case::none(None, &hex!("0000 0000 0000 0000"))]
+    #[case::some(
+        Some(StorePath::from_bytes(b"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432").unwrap()),
+        b"\x4a\0\0\0\0\0\0\0/nix/store/00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432\0\0\0\0\0\0"
+    )]
+    #[tokio::test]
+    async fn optional_store_path_roundtrip(
+        #[case] store_path: Option<StorePath<String>>,
+        #[case] expected: &[u8],
+    ) {
+        roundtrip(store_path, expected).await;
+    }
+
+    #[rstest]
+    #[case::md5(NixHash::Md5(hex!("d41d8cd98f00b204e9800998ecf8427e")))]
+    #[case::sha1(NixHash::Sha1(hex!("da39a3ee5e6b4b0d3255bfef95601890afd80709")))]
+    #[case::sha256(NixHash::Sha256(hex!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")))]
+    #[tokio::test]
+    async fn nix_hash_roundtrip(#[case] hash: NixHash) {
+        let hex_digest = hash.to_plain_hex_string();
+        let mut expected = (hex_digest.len() as u64).to_le_bytes().to_vec();
+        expected.extend_from_slice(hex_digest.as_bytes());
+        roundtrip(hash, &expected).await;
+    }
+
+    #[tokio::test]
+    async fn nix_hash_invalid_length() {
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value("abcd").await.unwrap();
+        let written = writer.into_inner();
+
+        let mut reader = NixReader::new(&written[..]);
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            reader.read_value::<NixHash>().await.unwrap_err().kind()
+        );
+    }
+
+    #[rstest]
+    #[case::nar("fixed:r:sha256:1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf7c4mwa4xj7r5sk0m9lkx")]
+    #[case::flat("fixed:sha1:1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf")]
+    #[case::text("text:sha256:1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf7c4mwa4xj7r5sk0m9lkx")]
+    #[tokio::test]
+    async fn ca_hash_roundtrip(#[case] ca_str: &str) {
+        let ca_hash = CAHash::from_nix_hex_str(ca_str).expect("must parse");
+
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(ca_str).await.unwrap();
+        roundtrip(ca_hash, &writer.into_inner()).await;
+    }
+
+    #[tokio::test]
+    async fn optional_ca_hash_roundtrip() {
+        roundtrip::<Option<CAHash>>(None, &hex!("0000 0000 0000 0000")).await;
+
+        let ca_str = "fixed:r:sha256:1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf7c4mwa4xj7r5sk0m9lkx";
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(ca_str).await.unwrap();
+        roundtrip(CAHash::from_nix_hex_str(ca_str), &writer.into_inner()).await;
+    }
+
+    #[tokio::test]
+    async fn signature_roundtrip() {
+        let sig_str = "cache.nixos.org-1:92fl0i5q7EyegCj5Yf4L0bENkWuVAtgveiRcTEEUH0P6HvCE1xFcPbz/0Pf6Np+K1LPzHK+s5RHOmVoxRsvsDg==";
+        let mut writer = NixWriter::new(Vec::new());
+        writer.write_value(sig_str).await.unwrap();
+        roundtrip(
+            Signature::<String>::parse(sig_str).unwrap(),
+            &writer.into_inner(),
+        )
+        .await;
+    }
+}
@@ -13,7 +13,14 @@ use super::ProtocolVersion;
 
 static WORKER_MAGIC_1: u64 = 0x6e697863; // "nixc"
 static WORKER_MAGIC_2: u64 = 0x6478696f; // "dxio"
+pub static STDERR_NEXT: u64 = 0x6f6c6d67; // "oglm"
 pub static STDERR_LAST: u64 = 0x616c7473; // "alts"
+pub static STDERR_ERROR: u64 = 0x63787470; // "cxtp"
+pub static STDERR_READ: u64 = 0x64617461; // "data"
+pub static STDERR_WRITE: u64 = 0x64617416; // "dat\x16"
+pub static STDERR_START_ACTIVITY: u64 = 0x53545254; // "STRT"
+pub static STDERR_STOP_ACTIVITY: u64 = 0x53544f50; // "STOP"
+pub static STDERR_RESULT: u64 = 0x52534c54; // "RSLT"
 
 /// | Nix version     | Protocol |
 /// |-----------------|----------|
@@ -51,9 +58,10 @@ pub static MAX_SETTING_SIZE: usize = 1024;
 /// [write_op] operations to serialize/deserialize the
 /// operation on the wire.
 ///
-/// Note: for now, we're using the Nix 2.20 operation description. The
-/// operations marked as obsolete are obsolete for Nix 2.20, not
-/// necessarily for Nix 2.3. We'll revisit this later on.
+/// Operations introduced after protocol version 1.10 must only be sent if
+/// the negotiated version is at least [Operation::min_version]. The
+/// operations marked as obsolete are still understood by Nix 2.20, but its
+/// clients don't send them anymore.
 #[derive(Debug, PartialEq, Primitive)]
 pub enum Operation {
     IsValidPath = 1,
@@ -102,10 +110,29 @@ pub enum Operation {
     AddPermRoot = 47,
 }
 
+impl Operation {
+    /// The oldest protocol version supporting the operation.
+    ///
+    /// The arguments and results of operations may further depend on the
+    /// negotiated version.
+    pub fn min_version(&self) -> ProtocolVersion {
+        let minor = match self {
+            Operation::AddToStoreNar => 18,
+            Operation::QueryDerivationOutputMap => 22,
+            Operation::RegisterDrvOutput | Operation::QueryRealisation => 27,
+            Operation::AddMultipleToStore | Operation::AddBuildLog => 32,
+            Operation::BuildPathsWithResults => 34,
+            Operation::AddPermRoot => 36,
+            _ => 10,
+        };
+        ProtocolVersion::from_parts(1, minor)
+    }
+}
+
 /// Log verbosity. In the Nix wire protocol, the client requests a
 /// verbosity level to the daemon, which in turns does not produce any
 /// log below this verbosity.
-#[derive(Debug, PartialEq, Primitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Primitive)]
 pub enum Verbosity {
     LvlError = 0,
     LvlWarn = 1,
@@ -264,6 +291,63 @@ where
     }
 }
 
+/// Performs the initial handshake with a daemon, as a client.
+///
+/// This is the counterpart of [server_handshake_client]: the client sends
+/// its magic u64 and protocol version, and discards the daemon's Nix
+/// version and trust level.
+///
+/// # Return
+///
+/// The negotiated protocol version, which is the lower one of the client
+/// and daemon versions.
+pub async fn client_handshake_server<'a, RW: 'a>(
+    mut conn: &'a mut RW,
+) -> std::io::Result<ProtocolVersion>
+where
+    &'a mut RW: AsyncReadExt + AsyncWriteExt + Unpin,
+{
+    conn.write_u64_le(WORKER_MAGIC_1).await?;
+    conn.flush().await?;
+    let worker_magic_2 = conn.read_u64_le().await?;
+    if worker_magic_2 != WORKER_MAGIC_2 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Incorrect worker magic number received: {}", worker_magic_2),
+        ));
+    }
+    let daemon_version: ProtocolVersion = conn
+        .read_u64_le()
+        .await?
+        .try_into()
+        .map_err(|e| Error::new(ErrorKind::Unsupported, e))?;
+    if daemon_version < ProtocolVersion::from_parts(1, 10) {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("The nix daemon version {} is too old", daemon_version),
+        ));
+    }
+    let version = std::cmp::min(daemon_version, PROTOCOL_VERSION);
+
+    conn.write_u64_le(PROTOCOL_VERSION.into()).await?;
+    if version.minor() >= 14 {
+        // Obsolete CPU affinity, unset.
+        conn.write_u64_le(0).await?;
+    }
+    if version.minor() >= 11 {
+        // Obsolete reserveSpace
+        conn.write_u64_le(0).await?;
+    }
+    conn.flush().await?;
+    if version.minor() >= 33 {
+        let _nix_version = wire::read_string(&mut conn, 0..=MAX_SETTING_SIZE).await?;
+    }
+    if version.minor() >= 35 {
+        let _trust = conn.read_u64_le().await?;
+    }
+    Ok(version)
+}
+
 /// Read a worker [Operation] from the wire.
 pub async fn read_op<R: AsyncReadExt + Unpin>(r: &mut R) -> std::io::Result<Operation> {
     let op_number = r.read_u64_le().await?;
@@ -279,7 +363,7 @@ pub async fn write_op<W: AsyncWriteExt + Unpin>(w: &mut W, op: &Operation) -> st
         ErrorKind::Other,
         format!("Can't convert the OP {:?} to u64", op),
     ))?;
-    w.write_u64(op).await
+    w.write_u64_le(op).await
 }
 
 #[derive(Debug, PartialEq)]
@@ -308,6 +392,7 @@ where
 mod tests {
     use super::*;
     use hex_literal::hex;
+    use rstest::rstest;
     use tokio_test::io::Builder;
 
     #[tokio::test]
@@ -337,6 +422,190 @@ mod tests {
         assert_eq!(client_version, PROTOCOL_VERSION)
     }
 
+    #[tokio::test]
+    async fn test_init_handshake_nix_2_3() {
+        // A Nix 2.3 client doesn't expect the daemon version nor the trust
+        // level.
+        let mut test_conn = tokio_test::io::Builder::new()
+            .read(&WORKER_MAGIC_1.to_le_bytes())
+            .write(&WORKER_MAGIC_2.to_le_bytes())
+            .write(&[37, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .read(&[21, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            // cpu affinity, set
+            .read(&[1, 0, 0, 0, 0, 0, 0, 0])
+            .read(&[3, 0, 0, 0, 0, 0, 0, 0])
+            // reservespace
+            .read(&[0; 8])
+            .build();
+        let client_version = server_handshake_client(&mut test_conn, "2.18.2", Trust::Trusted)
+            .await
+            .unwrap();
+
+        assert_eq!(client_version, ProtocolVersion::from_parts(1, 21))
+    }
+
+    #[tokio::test]
+    async fn test_init_handshake_v1_10() {
+        // Neither cpu affinity nor reservespace are sent.
+        let mut test_conn = tokio_test::io::Builder::new()
+            .read(&WORKER_MAGIC_1.to_le_bytes())
+            .write(&WORKER_MAGIC_2.to_le_bytes())
+            .write(&[37, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .read(&[10, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .build();
+        let client_version = server_handshake_client(&mut test_conn, "2.18.2", Trust::Trusted)
+            .await
+            .unwrap();
+
+        assert_eq!(client_version, ProtocolVersion::from_parts(1, 10))
+    }
+
+    #[tokio::test]
+    async fn test_init_handshake_too_old() {
+        let mut test_conn = tokio_test::io::Builder::new()
+            .read(&WORKER_MAGIC_1.to_le_bytes())
+            .write(&WORKER_MAGIC_2.to_le_bytes())
+            .write(&[37, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .read(&[9, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .build();
+        let err = server_handshake_client(&mut test_conn, "2.18.2", Trust::Trusted)
+            .await
+            .expect_err("must fail");
+
+        assert_eq!(err.kind(), ErrorKind::Unsupported)
+    }
+
+    /// Sent by clients since 1.14: no CPU affinity, followed by reserveSpace,
+    /// which is sent alone since 1.11.
+    const AFFINITY_RESERVE_SPACE: [u8; 16] = [0; 16];
+    /// Sent by the daemon since 1.33: its Nix version, "2.18.2".
+    const NIX_VERSION: [u8; 16] = hex!("0600 0000 0000 0000 322e 3138 2e32 0000");
+    /// Sent by the daemon since 1.35: the client is trusted.
+    const TRUSTED: [u8; 8] = hex!("0100 0000 0000 0000");
+
+    /// The bytes exchanged after the client version, at both sides of each
+    /// version boundary of the handshake.
+    /// These are derived from Nix' daemon.cc, not captured from a running
+    /// Nix.
+    #[rstest]
+    #[case::v1_10(10, &[], &[])]
+    #[case::v1_11(11, &[0; 8], &[])]
+    #[case::v1_13(13, &[0; 8], &[])]
+    #[case::v1_14(14, &AFFINITY_RESERVE_SPACE, &[])]
+    #[case::v1_32(32, &AFFINITY_RESERVE_SPACE, &[])]
+    #[case::v1_33(33, &AFFINITY_RESERVE_SPACE, &NIX_VERSION)]
+    #[case::v1_34(34, &AFFINITY_RESERVE_SPACE, &NIX_VERSION)]
+    #[case::v1_35(35, &AFFINITY_RESERVE_SPACE, &[&NIX_VERSION[..], &TRUSTED].concat())]
+    #[case::v1_37(37, &AFFINITY_RESERVE_SPACE, &[&NIX_VERSION[..], &TRUSTED].concat())]
+    #[tokio::test]
+    async fn test_init_handshake_versioned(
+        #[case] minor: u8,
+        #[case] from_client: &[u8],
+        #[case] from_daemon: &[u8],
+    ) {
+        let mut builder = Builder::new();
+        builder
+            .read(&WORKER_MAGIC_1.to_le_bytes())
+            .write(&WORKER_MAGIC_2.to_le_bytes())
+            .write(&[37, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .read(&[minor, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        if !from_client.is_empty() {
+            builder.read(from_client);
+        }
+        if !from_daemon.is_empty() {
+            builder.write(from_daemon);
+        }
+        let mut test_conn = builder.build();
+
+        let client_version = server_handshake_client(&mut test_conn, "2.18.2", Trust::Trusted)
+            .await
+            .unwrap();
+
+        assert_eq!(client_version, ProtocolVersion::from_parts(1, minor))
+    }
+
+    #[tokio::test]
+    async fn test_client_handshake() {
+        let mut test_conn = tokio_test::io::Builder::new()
+            .write(&WORKER_MAGIC_1.to_le_bytes())
+            .read(&WORKER_MAGIC_2.to_le_bytes())
+            .read(&[37, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .write(&[37, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            // cpu affinity
+            .write(&[0; 8])
+            // reservespace
+            .write(&[0; 8])
+            // version (size)
+            .read(&[0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            // version (data == 2.18.2 + padding)
+            .read(&[50, 46, 49, 56, 46, 50, 0, 0])
+            // Trusted (1 == client trusted
+            .read(&[1, 0, 0, 0, 0, 0, 0, 0])
+            .build();
+        let version = client_handshake_server(&mut test_conn).await.unwrap();
+
+        assert_eq!(version, PROTOCOL_VERSION)
+    }
+
+    #[tokio::test]
+    async fn test_client_handshake_old_daemon() {
+        // A Nix 2.3 daemon doesn't send its version nor the trust level.
+        let mut test_conn = tokio_test::io::Builder::new()
+            .write(&WORKER_MAGIC_1.to_le_bytes())
+            .read(&WORKER_MAGIC_2.to_le_bytes())
+            .read(&[21, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .write(&[37, 1, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+            // cpu affinity
+            .write(&[0; 8])
+            // reservespace
+            .write(&[0; 8])
+            .build();
+        let version = client_handshake_server(&mut test_conn).await.unwrap();
+
+        assert_eq!(version, ProtocolVersion::from_parts(1, 21))
+    }
+
+    #[tokio::test]
+    async fn test_op_roundtrip() {
+        let mut test_conn = tokio_test::io::Builder::new()
+            .write(&[26, 0, 0, 0, 0, 0, 0, 0])
+            .read(&[26, 0, 0, 0, 0, 0, 0, 0])
+            .build();
+        write_op(&mut test_conn, &Operation::QueryPathInfo)
+            .await
+            .unwrap();
+        assert_eq!(
+            Operation::QueryPathInfo,
+            read_op(&mut test_conn).await.unwrap()
+        );
+    }
+
+    /// Operations are sent little-endian, like all other numbers.
+    /// [write_op] used to send them big-endian, which [read_op] and Nix
+    /// reject.
+    #[tokio::test]
+    async fn test_write_op_le() {
+        let mut buf = Vec::new();
+        write_op(&mut buf, &Operation::AddMultipleToStore)
+            .await
+            .unwrap();
+        assert_eq!(&[44, 0, 0, 0, 0, 0, 0, 0], &buf[..]);
+        assert_eq!(
+            Operation::AddMultipleToStore,
+            read_op(&mut &buf[..]).await.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_op_min_version() {
+        assert_eq!(
+            Operation::IsValidPath.min_version(),
+            ProtocolVersion::from_parts(1, 10)
+        );
+        assert!(Operation::AddToStoreNar.min_version() <= ProtocolVersion::from_parts(1, 21));
+        assert!(Operation::AddMultipleToStore.min_version() > ProtocolVersion::from_parts(1, 21));
+    }
+
     #[tokio::test]
     async fn test_read_client_settings_without_overrides() {
         // Client settings bits captured from a Nix 2.3.17 run w/ sockdump (protocol version 21).
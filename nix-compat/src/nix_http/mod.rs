@@ -8,6 +8,8 @@ pub const MIME_TYPE_NAR: &str = "application/x-nix-nar";
 pub const MIME_TYPE_NARINFO: &str = "text/x-nix-narinfo";
 /// The mime type used for the `nix-cache-info` file
 pub const MIME_TYPE_CACHE_INFO: &str = "text/x-nix-cache-info";
+/// The mime type used for NAR listing (`.ls`) files
+pub const MIME_TYPE_LS: &str = "application/json";
 
 /// Parses a `14cx20k6z4hq508kqi2lm79qfld5f9mf7kiafpqsjs3zlmycza0k.nar`
 /// string and returns the nixbase32-decoded digest, as well as the compression
@@ -41,34 +43,44 @@ pub fn parse_nar_str(s: &str) -> Option<([u8; 32], &str)> {
 /// Parses a `3mzh8lvgbynm9daj7c82k2sfsfhrsfsy.narinfo` string and returns the
 /// nixbase32-decoded digest.
 pub fn parse_narinfo_str(s: &str) -> Option<[u8; 20]> {
+    parse_digest_with_suffix(s, ".narinfo")
+}
+
+/// Parses a `3mzh8lvgbynm9daj7c82k2sfsfhrsfsy.ls` string and returns the
+/// nixbase32-decoded digest.
+pub fn parse_ls_str(s: &str) -> Option<[u8; 20]> {
+    parse_digest_with_suffix(s, ".ls")
+}
+
+/// Parses a string consisting of a nixbase32-encoded store path digest,
+/// followed by the given suffix, and returns the decoded digest.
+fn parse_digest_with_suffix(s: &str, expected_suffix: &str) -> Option<[u8; 20]> {
     if !s.is_char_boundary(32) {
         trace!("invalid string, no char boundary at 32");
         return None;
     }
 
-    match s.split_at(32) {
-        (hash_str, ".narinfo") => {
-            // we know this is 32 bytes, so it's ok to unwrap here.
-            let hash_str_fixed: [u8; 32] = hash_str.as_bytes().try_into().unwrap();
-
-            match nixbase32::decode_fixed(hash_str_fixed) {
-                Err(e) => {
-                    trace!(err=%e, "invalid nixbase32 encoding");
-                    None
-                }
-                Ok(digest) => Some(digest),
-            }
-        }
-        _ => {
-            trace!("invalid string, no .narinfo suffix");
+    let (hash_str, suffix) = s.split_at(32);
+    if suffix != expected_suffix {
+        trace!(expected_suffix, "invalid string, wrong suffix");
+        return None;
+    }
+
+    // we know this is 32 bytes, so it's ok to unwrap here.
+    let hash_str_fixed: [u8; 32] = hash_str.as_bytes().try_into().unwrap();
+
+    match nixbase32::decode_fixed(hash_str_fixed) {
+        Err(e) => {
+            trace!(err=%e, "invalid nixbase32 encoding");
             None
         }
+        Ok(digest) => Some(digest),
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{parse_nar_str, parse_narinfo_str};
+    use super::{parse_ls_str, parse_nar_str, parse_narinfo_str};
     use hex_literal::hex;
 
     #[test]
@@ -112,4 +124,19 @@ mod test {
         assert!(parse_narinfo_str("000000").is_none());
         assert!(parse_narinfo_str("00bgd045z0d4icpbc2yyz4gx48ak44l🦊.narinfo").is_none());
     }
+
+    #[test]
+    fn parse_ls_str_success() {
+        assert_eq!(
+            hex!("8a12321522fd91efbd60ebb2481af88580f61600"),
+            parse_ls_str("00bgd045z0d4icpbc2yyz4gx48ak44la.ls").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_ls_str_failure() {
+        assert!(parse_ls_str("00bgd045z0d4icpbc2yyz4gx48ak44la").is_none());
+        assert!(parse_ls_str("00bgd045z0d4icpbc2yyz4gx48ak44la.narinfo").is_none());
+        assert!(parse_ls_str("00bgd045z0d4icpbc2yyz4gx48ak44l🦊.ls").is_none());
+    }
 }
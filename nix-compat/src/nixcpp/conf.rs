@@ -1,115 +1,407 @@
-use std::{fmt::Display, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 /// Represents configuration as stored in /etc/nix/nix.conf.
 /// This list is not exhaustive, feel free to add more.
+/// Settings without a field here are kept in [NixConfig::unknown_settings].
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct NixConfig<'a> {
     pub allowed_users: Option<Vec<&'a str>>,
     pub auto_optimise_store: Option<bool>,
     pub cores: Option<u64>,
-    pub max_jobs: Option<u64>,
+    pub max_jobs: Option<MaxJobs>,
     pub require_sigs: Option<bool>,
     pub sandbox: Option<SandboxSetting>,
     pub sandbox_fallback: Option<bool>,
+    pub sandbox_paths: Option<Vec<&'a str>>,
     pub substituters: Option<Vec<&'a str>>,
     pub system_features: Option<Vec<&'a str>>,
     pub trusted_public_keys: Option<Vec<crate::narinfo::VerifyingKey>>,
     pub trusted_substituters: Option<Vec<&'a str>>,
     pub trusted_users: Option<Vec<&'a str>>,
     pub extra_platforms: Option<Vec<&'a str>>,
-    pub extra_sandbox_paths: Option<Vec<&'a str>>,
     pub experimental_features: Option<Vec<&'a str>>,
     pub builders_use_substitutes: Option<bool>,
+    /// All other settings, by their name.
+    /// Unknown `extra-` settings are appended to, like known ones, with the
+    /// values separated by a space.
+    pub unknown_settings: BTreeMap<&'a str, String>,
 }
 
+/// Used to parse config without any included files available.
+const NO_INCLUDES: &BTreeMap<PathBuf, String> = &BTreeMap::new();
+
 impl<'a> NixConfig<'a> {
     /// Parses configuration from a file like `/etc/nix/nix.conf`, returning
     /// a [NixConfig] with all values contained in there.
-    /// It does not support parsing multiple config files, merging semantics,
-    /// and also does not understand `include` and `!include` statements.
+    /// As there's no file to resolve them against, `include` statements are
+    /// rejected and `!include` statements are ignored. Use [NixConfigFiles]
+    /// to load configuration from disk.
     pub fn parse(input: &'a str) -> Result<Self, Error> {
+        let mut settings = Vec::new();
+        collect_settings(Path::new(""), input, NO_INCLUDES, &mut settings)?;
+
+        let mut out = Self::default();
+        out.apply(settings)?;
+        Ok(out)
+    }
+
+    /// Applies the settings of a config file, in the order Nix does:
+    /// experimental features first, as other settings may depend on them.
+    fn apply(&mut self, settings: Vec<(&'a str, &'a str)>) -> Result<(), Error> {
+        let is_experimental =
+            |name: &str| name == "experimental-features" || name == "extra-experimental-features";
+
+        let (experimental, other): (Vec<_>, Vec<_>) = settings
+            .into_iter()
+            .partition(|(name, _)| is_experimental(name));
+
+        for (name, val) in experimental.into_iter().chain(other) {
+            self.set(name, val)?;
+        }
+
+        Ok(())
+    }
+
+    /// The paths set by `sandbox-paths` and `extra-sandbox-paths`.
+    #[deprecated(
+        note = "use the sandbox_paths field, which includes the paths from extra-sandbox-paths"
+    )]
+    pub fn extra_sandbox_paths(&self) -> Option<&Vec<&'a str>> {
+        self.sandbox_paths.as_ref()
+    }
+
+    /// Sets a single setting.
+    /// `extra-$name` appends to the list setting `$name`, settings not known
+    /// are stored in [NixConfig::unknown_settings].
+    fn set(&mut self, name: &'a str, val: &'a str) -> Result<(), Error> {
+        let invalid = || Error::InvalidValue(name.to_string(), val.to_string());
+
+        if self.set_known(name, val, false).ok_or_else(invalid)? {
+            return Ok(());
+        }
+
+        if let Some(base_name) = name.strip_prefix("extra-") {
+            if self.set_known(base_name, val, true).ok_or_else(invalid)? {
+                return Ok(());
+            }
+        }
+
+        match self.unknown_settings.get_mut(name) {
+            Some(existing) if name.starts_with("extra-") => {
+                existing.push(' ');
+                existing.push_str(val);
+            }
+            _ => {
+                self.unknown_settings.insert(name, val.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets or appends to a setting with a field in [NixConfig].
+    /// Returns `Some(false)` if there's no such setting, or it can't be
+    /// appended to, and `None` if the value is invalid.
+    fn set_known(&mut self, name: &str, val: &'a str, append: bool) -> Option<bool> {
+        fn set_list<T>(field: &mut Option<Vec<T>>, vals: Vec<T>, append: bool) {
+            match field {
+                Some(existing) if append => existing.extend(vals),
+                _ => *field = Some(vals),
+            }
+        }
+
+        let words = || val.split_whitespace().collect::<Vec<_>>();
+
+        match name {
+            "allowed-users" => set_list(&mut self.allowed_users, words(), append),
+            "auto-optimise-store" if !append => {
+                self.auto_optimise_store = Some(val.parse::<bool>().ok()?);
+            }
+            "cores" if !append => {
+                self.cores = Some(val.parse().ok()?);
+            }
+            "max-jobs" if !append => {
+                self.max_jobs = Some(val.parse().ok()?);
+            }
+            "require-sigs" if !append => {
+                self.require_sigs = Some(val.parse().ok()?);
+            }
+            "sandbox" if !append => self.sandbox = Some(val.parse().ok()?),
+            "sandbox-fallback" if !append => self.sandbox_fallback = Some(val.parse().ok()?),
+            "sandbox-paths" => set_list(&mut self.sandbox_paths, words(), append),
+            "substituters" => set_list(&mut self.substituters, words(), append),
+            "system-features" => set_list(&mut self.system_features, words(), append),
+            "trusted-public-keys" => set_list(
+                &mut self.trusted_public_keys,
+                val.split_whitespace()
+                    .map(crate::narinfo::VerifyingKey::parse)
+                    .collect::<Result<Vec<crate::narinfo::VerifyingKey>, _>>()
+                    .ok()?,
+                append,
+            ),
+            "trusted-substituters" => set_list(&mut self.trusted_substituters, words(), append),
+            "trusted-users" => set_list(&mut self.trusted_users, words(), append),
+            "extra-platforms" => set_list(&mut self.extra_platforms, words(), append),
+            "experimental-features" => set_list(&mut self.experimental_features, words(), append),
+            "builders-use-substitutes" if !append => {
+                self.builders_use_substitutes = Some(val.parse().ok()?)
+            }
+            _ => return Some(false),
+        }
+        Some(true)
+    }
+}
+
+/// The contents of the config files making up the Nix configuration, along
+/// with all files they include.
+///
+/// Files are read upfront, so the [NixConfig] returned by
+/// [NixConfigFiles::config] can borrow from them.
+#[derive(Clone, Debug, Default)]
+pub struct NixConfigFiles {
+    /// The top-level config files, in the order they're applied.
+    files: Vec<(PathBuf, String)>,
+    /// The contents of all included files, by their path.
+    included: BTreeMap<PathBuf, String>,
+}
+
+impl NixConfigFiles {
+    /// Loads the config files Nix would load, from the locations returned
+    /// by [conf_file_paths], followed by the contents of `$NIX_CONFIG`.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::load_env(|key| std::env::var_os(key))
+    }
+
+    /// [NixConfigFiles::from_env], using `var` to look up environment
+    /// variables.
+    fn load_env<F: Fn(&str) -> Option<OsString>>(var: F) -> Result<Self, Error> {
+        let mut out = Self::load(conf_file_paths(&var))?;
+
+        // Like in Nix, `$NIX_CONFIG` is applied last, and includes in it are
+        // relative to the working directory.
+        if let Some(contents) = var("NIX_CONFIG") {
+            let path = PathBuf::from("NIX_CONFIG");
+            let contents = contents.into_string().map_err(|_| {
+                Error::Io(
+                    path.clone(),
+                    io::Error::new(io::ErrorKind::InvalidData, "not valid UTF-8"),
+                )
+            })?;
+            out.add(path, contents)?;
+        }
+
+        Ok(out)
+    }
+
+    /// Loads the passed config files, and all files included by them.
+    /// Files are applied in the passed order, so later files take
+    /// precedence. Files that don't exist are skipped.
+    pub fn load<I: IntoIterator<Item = PathBuf>>(paths: I) -> Result<Self, Error> {
         let mut out = Self::default();
 
-        for line in input.lines() {
-            // strip comments at the end of the line
-            let line = if let Some((line, _comment)) = line.split_once('#') {
-                line
-            } else {
-                line
+        for path in paths {
+            let contents = match std::fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::Io(path, e)),
             };
 
-            // skip comments and empty lines
-            if line.trim().is_empty() {
+            out.add(path, contents)?;
+        }
+
+        Ok(out)
+    }
+
+    /// Adds a top-level config file, and loads the files it includes.
+    fn add(&mut self, path: PathBuf, contents: String) -> Result<(), Error> {
+        self.load_includes(&path, &contents, &mut vec![path.clone()])?;
+        self.files.push((path, contents));
+        Ok(())
+    }
+
+    /// Reads all files included by a config file, recursively.
+    /// `stack` contains the files currently being processed, to detect
+    /// include cycles.
+    fn load_includes(
+        &mut self,
+        path: &Path,
+        contents: &str,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), Error> {
+        for line in contents.lines() {
+            let (include, ignore_missing) = match parse_line(line)? {
+                Some(Line::Include {
+                    path: include,
+                    ignore_missing,
+                }) => (include_path(path, include), ignore_missing),
+                _ => continue,
+            };
+
+            if stack.contains(&include) {
+                return Err(Error::IncludeCycle(include));
+            }
+            if self.included.contains_key(&include) {
                 continue;
             }
 
-            let (tag, val) = line
+            let included = match std::fs::read_to_string(&include) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    if ignore_missing {
+                        continue;
+                    }
+                    return Err(Error::IncludeNotFound(include, path.to_owned()));
+                }
+                Err(e) => return Err(Error::Io(include, e)),
+            };
+
+            stack.push(include.clone());
+            self.load_includes(&include, &included, stack)?;
+            stack.pop();
+
+            self.included.insert(include, included);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the configuration resulting from applying all config files.
+    pub fn config(&self) -> Result<NixConfig<'_>, Error> {
+        let mut out = NixConfig::default();
+
+        for (path, contents) in &self.files {
+            let mut settings = Vec::new();
+            collect_settings(path, contents, &self.included, &mut settings)?;
+            out.apply(settings)?;
+        }
+
+        Ok(out)
+    }
+}
+
+/// Returns the paths of the config files Nix loads, in the order they're
+/// applied, using `var` to look up environment variables.
+///
+/// This is `$NIX_CONF_DIR/nix.conf` (defaulting to `/etc/nix/nix.conf`),
+/// followed by the user config files. These are the ones listed in
+/// `$NIX_USER_CONF_FILES`, or `nix/nix.conf` in the XDG config directories
+/// otherwise. The first user config file takes precedence, so it comes
+/// last.
+pub fn conf_file_paths<F: Fn(&str) -> Option<OsString>>(var: F) -> Vec<PathBuf> {
+    let conf_dir = var("NIX_CONF_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/etc/nix"));
+
+    let split = |paths: OsString| -> Vec<PathBuf> {
+        std::env::split_paths(&paths)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect()
+    };
+
+    let user_files = match var("NIX_USER_CONF_FILES") {
+        Some(files) => split(files),
+        None => {
+            let config_home = var("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")));
+            let config_dirs = split(var("XDG_CONFIG_DIRS").unwrap_or_else(|| "/etc/xdg".into()));
+
+            config_home
+                .into_iter()
+                .chain(config_dirs)
+                .map(|dir| dir.join("nix/nix.conf"))
+                .collect()
+        }
+    };
+
+    std::iter::once(conf_dir.join("nix.conf"))
+        .chain(user_files.into_iter().rev())
+        .collect()
+}
+
+/// A non-empty line of a config file.
+enum Line<'a> {
+    Include { path: &'a str, ignore_missing: bool },
+    Setting { name: &'a str, val: &'a str },
+}
+
+fn parse_line(line: &str) -> Result<Option<Line<'_>>, Error> {
+    // strip comments at the end of the line
+    let line = if let Some((line, _comment)) = line.split_once('#') {
+        line
+    } else {
+        line
+    };
+
+    // skip comments and empty lines
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let mut tokens = line.split_whitespace();
+    let ignore_missing = match tokens.next() {
+        Some("include") => false,
+        Some("!include") => true,
+        _ => {
+            let (name, val) = line
                 .split_once('=')
                 .ok_or_else(|| Error::InvalidLine(line.to_string()))?;
 
             // trim whitespace
-            let tag = tag.trim();
-            let val = val.trim();
-
-            #[inline]
-            fn parse_val<'a>(this: &mut NixConfig<'a>, tag: &str, val: &'a str) -> Option<()> {
-                match tag {
-                    "allowed-users" => {
-                        this.allowed_users = Some(val.split_whitespace().collect());
-                    }
-                    "auto-optimise-store" => {
-                        this.auto_optimise_store = Some(val.parse::<bool>().ok()?);
-                    }
-                    "cores" => {
-                        this.cores = Some(val.parse().ok()?);
-                    }
-                    "max-jobs" => {
-                        this.max_jobs = Some(val.parse().ok()?);
-                    }
-                    "require-sigs" => {
-                        this.require_sigs = Some(val.parse().ok()?);
-                    }
-                    "sandbox" => this.sandbox = Some(val.parse().ok()?),
-                    "sandbox-fallback" => this.sandbox_fallback = Some(val.parse().ok()?),
-                    "substituters" => this.substituters = Some(val.split_whitespace().collect()),
-                    "system-features" => {
-                        this.system_features = Some(val.split_whitespace().collect())
-                    }
-                    "trusted-public-keys" => {
-                        this.trusted_public_keys = Some(
-                            val.split_whitespace()
-                                .map(crate::narinfo::VerifyingKey::parse)
-                                .collect::<Result<Vec<crate::narinfo::VerifyingKey>, _>>()
-                                .ok()?,
-                        )
-                    }
-                    "trusted-substituters" => {
-                        this.trusted_substituters = Some(val.split_whitespace().collect())
-                    }
-                    "trusted-users" => this.trusted_users = Some(val.split_whitespace().collect()),
-                    "extra-platforms" => {
-                        this.extra_platforms = Some(val.split_whitespace().collect())
-                    }
-                    "extra-sandbox-paths" => {
-                        this.extra_sandbox_paths = Some(val.split_whitespace().collect())
-                    }
-                    "experimental-features" => {
-                        this.experimental_features = Some(val.split_whitespace().collect())
-                    }
-                    "builders-use-substitutes" => {
-                        this.builders_use_substitutes = Some(val.parse().ok()?)
-                    }
-                    _ => return None,
+            return Ok(Some(Line::Setting {
+                name: name.trim(),
+                val: val.trim(),
+            }));
+        }
+    };
+
+    match (tokens.next(), tokens.next()) {
+        (Some(path), None) => Ok(Some(Line::Include {
+            path,
+            ignore_missing,
+        })),
+        _ => Err(Error::InvalidLine(line.to_string())),
+    }
+}
+
+/// Included paths are relative to the directory of the including file.
+fn include_path(from: &Path, path: &str) -> PathBuf {
+    from.parent().unwrap_or(Path::new("")).join(path)
+}
+
+/// Collects the settings of a config file, with those of included files
+/// inlined, looking up included files in `included`.
+fn collect_settings<'a>(
+    path: &Path,
+    contents: &'a str,
+    included: &'a BTreeMap<PathBuf, String>,
+    out: &mut Vec<(&'a str, &'a str)>,
+) -> Result<(), Error> {
+    for line in contents.lines() {
+        match parse_line(line)? {
+            None => {}
+            Some(Line::Setting { name, val }) => out.push((name, val)),
+            Some(Line::Include {
+                path: include,
+                ignore_missing,
+            }) => {
+                let include = include_path(path, include);
+                match included.get(&include) {
+                    Some(contents) => collect_settings(&include, contents, included, out)?,
+                    None if ignore_missing => {}
+                    None => return Err(Error::IncludeNotFound(include, path.to_owned())),
                 }
-                Some(())
             }
-
-            parse_val(&mut out, tag, val)
-                .ok_or_else(|| Error::InvalidValue(tag.to_string(), val.to_string()))?
         }
-
-        Ok(out)
     }
+
+    Ok(())
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -120,6 +412,40 @@ pub enum Error {
     UnrecognizedKey(String),
     #[error("Invalid value '{1}' for key '{0}'")]
     InvalidValue(String, String),
+    #[error("File {0:?} included from {1:?} not found")]
+    IncludeNotFound(PathBuf, PathBuf),
+    #[error("File {0:?} includes itself")]
+    IncludeCycle(PathBuf),
+    #[error("Unable to read {0:?}: {1}")]
+    Io(PathBuf, io::Error),
+}
+
+/// Valid values for the Nix 'max-jobs' setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaxJobs {
+    /// As many jobs as there are CPUs.
+    Auto,
+    Jobs(u64),
+}
+
+impl Display for MaxJobs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaxJobs::Auto => write!(f, "auto"),
+            MaxJobs::Jobs(jobs) => write!(f, "{}", jobs),
+        }
+    }
+}
+
+impl FromStr for MaxJobs {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => Self::Auto,
+            s => Self::Jobs(s.parse().map_err(|_| "invalid value")?),
+        })
+    }
 }
 
 /// Valid values for the Nix 'sandbox' setting
@@ -155,9 +481,24 @@ impl FromStr for SandboxSetting {
 
 #[cfg(test)]
 mod tests {
-    use crate::{narinfo::VerifyingKey, nixcpp::conf::SandboxSetting};
+    use std::{
+        collections::BTreeMap,
+        ffi::OsString,
+        path::{Path, PathBuf},
+    };
+
+    use crate::{
+        narinfo::VerifyingKey,
+        nixcpp::conf::{MaxJobs, SandboxSetting},
+    };
 
-    use super::NixConfig;
+    use super::{conf_file_paths, Error, NixConfig, NixConfigFiles};
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/nix_conf")
+            .join(name)
+    }
 
     #[test]
     pub fn test_parse() {
@@ -168,7 +509,7 @@ mod tests {
                 allowed_users: Some(vec!["*"]),
                 auto_optimise_store: Some(false),
                 cores: Some(0),
-                max_jobs: Some(8),
+                max_jobs: Some(MaxJobs::Jobs(8)),
                 require_sigs: Some(true),
                 sandbox: Some(SandboxSetting::True),
                 sandbox_fallback: Some(false),
@@ -183,11 +524,12 @@ mod tests {
                 trusted_substituters: Some(vec![]),
                 trusted_users: Some(vec!["flokli"]),
                 extra_platforms: Some(vec!["aarch64-linux", "i686-linux"]),
-                extra_sandbox_paths: Some(vec![
+                sandbox_paths: Some(vec![
                     "/run/binfmt", "/nix/store/swwyxyqpazzvbwx8bv40z7ih144q841f-qemu-aarch64-binfmt-P-x86_64-unknown-linux-musl"
                 ]),
                 experimental_features: Some(vec!["nix-command"]),
-                builders_use_substitutes: Some(true)
+                builders_use_substitutes: Some(true),
+                unknown_settings: BTreeMap::new(),
             },
             config
         );
@@ -199,4 +541,198 @@ mod tests {
 
         assert_eq!(config, other_config);
     }
+
+    /// `extra-` settings append to list settings, and are kept as unknown
+    /// settings otherwise, like other unknown settings.
+    #[test]
+    fn parse_extra_and_unknown() {
+        let config = NixConfig::parse(
+            "substituters = a\nextra-substituters = b c\nextra-cores = 4\nkeep-outputs = true\n",
+        )
+        .expect("must parse");
+
+        assert_eq!(Some(vec!["a", "b", "c"]), config.substituters);
+        assert_eq!(None, config.cores);
+        assert_eq!(
+            BTreeMap::from([
+                ("extra-cores", "4".to_string()),
+                ("keep-outputs", "true".to_string())
+            ]),
+            config.unknown_settings
+        );
+    }
+
+    /// The deprecated `extra_sandbox_paths` accessor returns the combined
+    /// `sandbox-paths` and `extra-sandbox-paths`.
+    #[test]
+    #[allow(deprecated)]
+    fn extra_sandbox_paths() {
+        let config =
+            NixConfig::parse("sandbox-paths = /a\nextra-sandbox-paths = /b\n").expect("must parse");
+        assert_eq!(Some(&vec!["/a", "/b"]), config.extra_sandbox_paths());
+    }
+
+    /// `max-jobs` accepts `auto` besides a number.
+    #[test]
+    fn parse_max_jobs() {
+        for (val, expected) in [("auto", MaxJobs::Auto), ("0", MaxJobs::Jobs(0))] {
+            let input = format!("max-jobs = {val}\n");
+            let config = NixConfig::parse(&input).expect("must parse");
+            assert_eq!(Some(expected), config.max_jobs);
+            assert_eq!(val, expected.to_string());
+        }
+
+        assert!(matches!(
+            NixConfig::parse("max-jobs = some\n"),
+            Err(Error::InvalidValue(..))
+        ));
+    }
+
+    /// Without a file to resolve includes against, `include` fails, while
+    /// `!include` is ignored.
+    #[test]
+    fn parse_include() {
+        assert!(matches!(
+            NixConfig::parse("include foo.conf\n"),
+            Err(Error::IncludeNotFound(..))
+        ));
+        assert_eq!(
+            NixConfig::default(),
+            NixConfig::parse("!include foo.conf\n").expect("must parse")
+        );
+    }
+
+    #[test]
+    fn load() {
+        let files = NixConfigFiles::load([
+            testdata("nix.conf"),
+            testdata("does-not-exist.conf"),
+            testdata("user.conf"),
+        ])
+        .expect("must load");
+        let config = files.config().expect("must parse");
+
+        assert_eq!(
+            NixConfig {
+                substituters: Some(vec![
+                    "https://cache.nixos.org/",
+                    "https://nix-community.cachix.org"
+                ]),
+                trusted_public_keys: Some(vec![
+                    VerifyingKey::parse(
+                        "cache.nixos.org-1:6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY="
+                    )
+                    .expect("failed to parse pubkey"),
+                    VerifyingKey::parse(
+                        "nix-community.cachix.org-1:mB9FSh9qf2dCimDSUo8Zy7bkq5CX+/rkCWyvRCYg3Fs="
+                    )
+                    .expect("failed to parse pubkey")
+                ]),
+                experimental_features: Some(vec!["nix-command", "flakes"]),
+                system_features: Some(vec!["kvm", "big-parallel"]),
+                unknown_settings: BTreeMap::from([
+                    ("extra-cores", "4".to_string()),
+                    ("keep-outputs", "true".to_string())
+                ]),
+                ..Default::default()
+            },
+            config
+        );
+    }
+
+    /// Unknown `extra-` settings are appended to across files, while other
+    /// unknown settings are overridden.
+    #[test]
+    fn load_unknown_across_files() {
+        let files = NixConfigFiles::load([
+            testdata("nix.conf"),
+            testdata("user.conf"),
+            testdata("unknown.conf"),
+        ])
+        .expect("must load");
+        let config = files.config().expect("must parse");
+
+        assert_eq!(
+            BTreeMap::from([
+                ("extra-cores", "4 8 16".to_string()),
+                ("keep-outputs", "false".to_string())
+            ]),
+            config.unknown_settings
+        );
+    }
+
+    /// `$NIX_CONFIG` is applied after all config files.
+    #[test]
+    fn load_env() {
+        let env = BTreeMap::from([
+            ("NIX_CONF_DIR", testdata("")),
+            ("NIX_USER_CONF_FILES", testdata("user.conf")),
+            (
+                "NIX_CONFIG",
+                "substituters = https://example.org\nextra-cores = 8\n".into(),
+            ),
+        ]);
+        let files =
+            NixConfigFiles::load_env(|key| env.get(key).map(OsString::from)).expect("must load");
+        let config = files.config().expect("must parse");
+
+        assert_eq!(Some(vec!["https://example.org"]), config.substituters);
+        assert_eq!(
+            Some(vec!["nix-command", "flakes"]),
+            config.experimental_features
+        );
+        assert_eq!(
+            Some(&"4 8".to_string()),
+            config.unknown_settings.get("extra-cores")
+        );
+    }
+
+    #[test]
+    fn load_include_not_found() {
+        assert!(matches!(
+            NixConfigFiles::load([testdata("broken.conf")]),
+            Err(Error::IncludeNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn load_include_cycle() {
+        assert!(matches!(
+            NixConfigFiles::load([testdata("cycle.conf")]),
+            Err(Error::IncludeCycle(..))
+        ));
+    }
+
+    #[test]
+    fn paths_user_conf_files() {
+        let env = BTreeMap::from([
+            ("NIX_CONF_DIR", "/nix/etc"),
+            ("NIX_USER_CONF_FILES", "/a/nix.conf::/b/nix.conf"),
+            ("XDG_CONFIG_HOME", "/home/user/.config"),
+        ]);
+
+        assert_eq!(
+            vec![
+                PathBuf::from("/nix/etc/nix.conf"),
+                PathBuf::from("/b/nix.conf"),
+                PathBuf::from("/a/nix.conf"),
+            ],
+            conf_file_paths(|key| env.get(key).map(OsString::from))
+        );
+    }
+
+    #[test]
+    fn paths_xdg() {
+        let env = BTreeMap::from([("HOME", "/home/user"), ("XDG_CONFIG_DIRS", "/etc/a:/etc/b")]);
+
+        assert_eq!(
+            vec![
+                PathBuf::from("/etc/nix/nix.conf"),
+                PathBuf::from("/etc/b/nix/nix.conf"),
+                PathBuf::from("/etc/a/nix/nix.conf"),
+                PathBuf::from("/home/user/.config/nix/nix.conf"),
+            ],
+            conf_file_paths(|key| env.get(key).map(OsString::from))
+        );
+    }
 }
@@ -12,14 +12,18 @@ use super::decode_digest;
 /// A Nix CAHash describes a content-addressed hash of a path.
 ///
 /// The way Nix prints it as a string is a bit confusing, but there's essentially
-/// three modes, `Flat`, `Nar` and `Text`.
+/// four modes, `Flat`, `Nar`, `Text` and `Git`.
 /// `Flat` and `Nar` support all 4 algos that [NixHash] supports
 /// (sha1, md5, sha256, sha512), `Text` only supports sha256.
+/// `Git` holds the digest of a git blob or tree object, which only exist for
+/// sha1 and sha256. The parsers reject other algos, and code handling a
+/// [CAHash] may rely on this.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum CAHash {
     Flat(NixHash),  // "fixed flat"
     Nar(NixHash),   // "fixed recursive"
     Text([u8; 32]), // "text", only supports sha256
+    Git(NixHash),   // "fixed git"
 }
 
 /// Representation for the supported hash modes.
@@ -28,6 +32,7 @@ pub enum HashMode {
     Flat,
     Nar,
     Text,
+    Git,
 }
 
 impl CAHash {
@@ -36,6 +41,7 @@ impl CAHash {
             CAHash::Flat(ref digest) => Cow::Borrowed(digest),
             CAHash::Nar(ref digest) => Cow::Borrowed(digest),
             CAHash::Text(digest) => Cow::Owned(NixHash::Sha256(digest)),
+            CAHash::Git(ref digest) => Cow::Borrowed(digest),
         }
     }
 
@@ -44,6 +50,7 @@ impl CAHash {
             CAHash::Flat(_) => HashMode::Flat,
             CAHash::Nar(_) => HashMode::Nar,
             CAHash::Text(_) => HashMode::Text,
+            CAHash::Git(_) => HashMode::Git,
         }
     }
 
@@ -64,13 +71,20 @@ impl CAHash {
                 NixHash::Sha512(_) => "fixed:r:sha512",
             },
             HashMode::Text => "text:sha256",
+            HashMode::Git => match self.hash().as_ref() {
+                NixHash::Md5(_) => "fixed:git:md5",
+                NixHash::Sha1(_) => "fixed:git:sha1",
+                NixHash::Sha256(_) => "fixed:git:sha256",
+                NixHash::Sha512(_) => "fixed:git:sha512",
+            },
         }
     }
 
     /// Constructs a [CAHash] from the textual representation,
-    /// which is one of the three:
+    /// which is one of the four:
     /// - `text:sha256:$nixbase32sha256digest`
     /// - `fixed:r:$algo:$nixbase32digest`
+    /// - `fixed:git:$algo:$nixbase32digest`
     /// - `fixed:$algo:$nixbase32digest`
     ///
     /// These formats are used in NARInfo, for example.
@@ -86,6 +100,10 @@ impl CAHash {
             "fixed" => {
                 if let Some(s) = s.strip_prefix("r:") {
                     NixHash::from_nix_hex_str(s).map(CAHash::Nar)
+                } else if let Some(s) = s.strip_prefix("git:") {
+                    NixHash::from_nix_hex_str(s)
+                        .filter(is_git_hash)
+                        .map(CAHash::Git)
                 } else {
                     NixHash::from_nix_hex_str(s).map(CAHash::Flat)
                 }
@@ -123,7 +141,8 @@ impl CAHash {
     ///
     /// The serde data model has a `hash` field (containing a digest in nixbase32),
     /// and a `hashAlgo` field, containing the stringified hash algo.
-    /// In case the hash is recursive, hashAlgo also has a `r:` prefix.
+    /// In case the hash is recursive, hashAlgo also has a `r:` prefix, and a
+    /// `git:` prefix for git hashes.
     ///
     /// This is to match how `nix show-derivation` command shows them in JSON
     /// representation.
@@ -144,10 +163,12 @@ impl CAHash {
         let hash_algo = hash_algo_v.as_str().ok_or_else(|| {
             serde::de::Error::invalid_type(Unexpected::Other(&hash_algo_v.to_string()), &"a string")
         })?;
-        let (mode_is_nar, hash_algo) = if let Some(s) = hash_algo.strip_prefix("r:") {
-            (true, s)
+        let (mode, hash_algo) = if let Some(s) = hash_algo.strip_prefix("r:") {
+            (HashMode::Nar, s)
+        } else if let Some(s) = hash_algo.strip_prefix("git:") {
+            (HashMode::Git, s)
         } else {
-            (false, hash_algo)
+            (HashMode::Flat, hash_algo)
         };
         let hash_algo = HashAlgo::try_from(hash_algo).map_err(|e| {
             serde::de::Error::invalid_value(
@@ -166,14 +187,23 @@ impl CAHash {
         })?;
         let hash = decode_digest(hash.as_bytes(), hash_algo)
             .map_err(|e| serde::de::Error::custom(e.to_string()))?;
-        if mode_is_nar {
-            Ok(Some(Self::Nar(hash)))
-        } else {
-            Ok(Some(Self::Flat(hash)))
+        match mode {
+            HashMode::Nar => Ok(Some(Self::Nar(hash))),
+            HashMode::Git if !is_git_hash(&hash) => Err(serde::de::Error::invalid_value(
+                Unexpected::Str(hash_algo_v.as_str().unwrap_or_default()),
+                &"git:sha1 or git:sha256",
+            )),
+            HashMode::Git => Ok(Some(Self::Git(hash))),
+            _ => Ok(Some(Self::Flat(hash))),
         }
     }
 }
 
+/// Whether git objects exist with the algo of the passed hash.
+fn is_git_hash(hash: &NixHash) -> bool {
+    matches!(hash, NixHash::Sha1(_) | NixHash::Sha256(_))
+}
+
 impl Serialize for CAHash {
     /// map a CAHash into the serde data model.
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -197,6 +227,10 @@ impl Serialize for CAHash {
                 map.serialize_entry("hash", &nixbase32::encode(h.as_ref()))?;
                 map.serialize_entry("hashAlgo", "text")?;
             }
+            CAHash::Git(h) => {
+                map.serialize_entry("hash", &nixbase32::encode(h.digest_as_bytes()))?;
+                map.serialize_entry("hashAlgo", &format!("git:{}", &h.algo()))?;
+            }
         };
         map.end()
     }
@@ -347,6 +381,39 @@ mod tests {
         assert_eq!(hash, hash2);
     }
 
+    #[test]
+    fn serialize_deserialize_git() {
+        let json_bytes = r#"{"hash":"1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf","hashAlgo":"git:sha1"}"#;
+        let hash: CAHash = serde_json::from_str(json_bytes).expect("must parse");
+        assert!(matches!(hash, CAHash::Git(nixhash::NixHash::Sha1(_))));
+
+        let serialized = serde_json::to_string(&hash).expect("Serialize");
+        assert_eq!(json_bytes, serialized);
+    }
+
+    #[test]
+    fn nix_hex_str_git() {
+        let s = "fixed:git:sha1:1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf";
+        let hash = CAHash::from_nix_hex_str(s).expect("must parse");
+
+        assert!(matches!(hash, CAHash::Git(nixhash::NixHash::Sha1(_))));
+        assert_eq!(s, hash.to_nix_nixbase32_string());
+    }
+
+    /// There are no md5 or sha512 git objects.
+    #[test]
+    fn git_invalid_algo() {
+        for s in [
+            "fixed:git:md5:1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf",
+            "fixed:git:sha512:1cjvx7q4hrxg3jjqyr1npjkhm0f7n3yf",
+        ] {
+            assert_eq!(None, CAHash::from_nix_hex_str(s));
+        }
+
+        let json_bytes = r#"{"hash":"d41d8cd98f00b204e9800998ecf8427e","hashAlgo":"git:md5"}"#;
+        serde_json::from_str::<CAHash>(json_bytes).expect_err("must fail");
+    }
+
     #[test]
     fn serialize_deserialize_flat() {
         let json_bytes = r#"
@@ -0,0 +1,435 @@
+//! Realisations map the outputs of content-addressed derivations to the store
+//! paths they were built to.
+//!
+//! Binary caches serve them as JSON, at `/realisations/<id>.doi`.
+
+use crate::{
+    narinfo::{Signature, SigningKey, VerifyingKey},
+    store_path::{self, StorePath},
+};
+use data_encoding::HEXLOWER;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+/// Identifies an output of a derivation, by the hash of the derivation
+/// (modulo fixed-output derivations) and the name of the output.
+///
+/// Its string representation is `sha256:<hex digest>!<output name>`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DrvOutput {
+    pub drv_hash: [u8; 32],
+    pub output_name: String,
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    #[error("missing separator")]
+    MissingSeparator,
+    #[error("unsupported hash algo in {0}")]
+    UnsupportedHashAlgo(String),
+    #[error("invalid digest: {0}")]
+    InvalidDigest(String),
+    #[error("invalid output name: {0}")]
+    InvalidOutputName(String),
+}
+
+impl FromStr for DrvOutput {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hash_str, output_name) = s.split_once('!').ok_or(Error::MissingSeparator)?;
+
+        let digest_str = hash_str
+            .strip_prefix("sha256:")
+            .ok_or_else(|| Error::UnsupportedHashAlgo(hash_str.to_string()))?;
+
+        let drv_hash = HEXLOWER
+            .decode(digest_str.as_bytes())
+            .ok()
+            .and_then(|digest| <[u8; 32]>::try_from(digest).ok())
+            .ok_or_else(|| Error::InvalidDigest(digest_str.to_string()))?;
+
+        // Output names end up in store path names, so need to be valid there.
+        if store_path::validate_name(output_name.as_bytes()).is_err() {
+            return Err(Error::InvalidOutputName(output_name.to_string()));
+        }
+
+        Ok(DrvOutput {
+            drv_hash,
+            output_name: output_name.to_string(),
+        })
+    }
+}
+
+impl Display for DrvOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sha256:{}!{}",
+            HEXLOWER.encode(&self.drv_hash),
+            self.output_name
+        )
+    }
+}
+
+impl Serialize for DrvOutput {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DrvOutput {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let str: &'de str = Deserialize::deserialize(deserializer)?;
+        str.parse().map_err(|_| {
+            serde::de::Error::invalid_value(serde::de::Unexpected::Str(str), &"DrvOutput")
+        })
+    }
+}
+
+/// A realisation of a [DrvOutput], as served in `.doi` files.
+///
+/// Contrary to the serialization of [StorePath], store paths are represented
+/// by their basename here.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Realisation {
+    // Fields are sorted by their serialized name, which is the order Nix
+    // emits them in.
+    /// The realisations of the outputs of other content-addressed derivations
+    /// this output refers to.
+    #[serde(default, with = "store_path_basename_map")]
+    pub dependent_realisations: BTreeMap<DrvOutput, StorePath<String>>,
+
+    pub id: DrvOutput,
+
+    #[serde(with = "store_path_basename")]
+    pub out_path: StorePath<String>,
+
+    #[serde(default)]
+    pub signatures: Vec<Signature<String>>,
+}
+
+impl Realisation {
+    /// Computes the fingerprint of this [Realisation], which is signed in
+    /// [Self::signatures].
+    ///
+    /// It's the JSON representation without the `signatures` field, with
+    /// keys sorted, like `Realisation::fingerprint` in Nix, which dumps its
+    /// (sorted) nlohmann::json object without whitespace.
+    pub fn fingerprint(&self) -> String {
+        let mut json = serde_json::to_value(self).expect("Realisation always serializes");
+        json.as_object_mut()
+            .expect("Realisation serializes to an object")
+            .remove("signatures");
+        json.to_string()
+    }
+
+    /// Adds a signature, using the passed signer to sign.
+    /// This is generic over algo implementations / providers,
+    /// so users can bring their own signers.
+    pub fn add_signature<S>(&mut self, signer: &SigningKey<S>)
+    where
+        S: ed25519::signature::Signer<ed25519::Signature>,
+    {
+        let fp = self.fingerprint();
+        let sig = signer.sign(fp.as_bytes());
+
+        self.signatures
+            .push(Signature::new(sig.name().to_string(), *sig.bytes()));
+    }
+
+    /// Returns whether any of the signatures was made by the signing key
+    /// referred to by the passed [VerifyingKey].
+    pub fn verify(&self, verifying_key: &VerifyingKey) -> bool {
+        let fp = self.fingerprint();
+
+        self.signatures
+            .iter()
+            .any(|sig| verifying_key.verify(&fp, &sig.as_ref()))
+    }
+}
+
+mod store_path_basename {
+    use crate::store_path::StorePath;
+    use serde::{Deserialize, Serializer};
+
+    pub fn serialize<S>(store_path: &StorePath<String>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(store_path)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<StorePath<String>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let str: &'de str = Deserialize::deserialize(deserializer)?;
+        StorePath::from_bytes(str.as_bytes()).map_err(|_| {
+            serde::de::Error::invalid_value(serde::de::Unexpected::Str(str), &"StorePath")
+        })
+    }
+}
+
+mod store_path_basename_map {
+    use super::DrvOutput;
+    use crate::store_path::StorePath;
+    use serde::{Deserialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S>(
+        map: &BTreeMap<DrvOutput, StorePath<String>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(map.iter().map(|(k, v)| (k, v.to_string())))
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<BTreeMap<DrvOutput, StorePath<String>>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let map: BTreeMap<DrvOutput, &'de str> = Deserialize::deserialize(deserializer)?;
+        map.into_iter()
+            .map(|(k, v)| {
+                StorePath::from_bytes(v.as_bytes())
+                    .map(|store_path| (k, store_path))
+                    .map_err(|_| {
+                        serde::de::Error::invalid_value(serde::de::Unexpected::Str(v), &"StorePath")
+                    })
+            })
+            .collect()
+    }
+}
+
+/// [NixSerialize] and [NixDeserialize] implementations, for realisations as they
+/// appear in the daemon protocol.
+#[cfg(feature = "wire")]
+mod wire {
+    use super::{DrvOutput, Realisation};
+    use crate::nix_daemon::de::{Error, NixDeserialize, NixRead};
+    use crate::nix_daemon::ser::{self, NixSerialize, NixWrite};
+
+    /// [DrvOutput]s are sent as `sha256:<hex digest>!<output name>`.
+    impl NixSerialize for DrvOutput {
+        async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+        where
+            W: ?Sized + NixWrite + Send,
+        {
+            writer.write_display(self).await
+        }
+    }
+
+    impl NixDeserialize for DrvOutput {
+        async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+        where
+            R: ?Sized + NixRead + Send,
+        {
+            if let Some(buf) = reader.try_read_bytes().await? {
+                let s = std::str::from_utf8(&buf).map_err(R::Error::invalid_data)?;
+                s.parse().map_err(R::Error::invalid_data).map(Some)
+            } else {
+                Ok(None)
+            }
+        }
+    }
+
+    /// [Realisation]s are sent in their JSON form.
+    impl NixSerialize for Realisation {
+        async fn serialize<W>(&self, writer: &mut W) -> Result<(), W::Error>
+        where
+            W: ?Sized + NixWrite + Send,
+        {
+            let json =
+                serde_json::to_string(self).map_err(<W::Error as ser::Error>::unsupported_data)?;
+            writer.write_value(&json).await
+        }
+    }
+
+    impl NixDeserialize for Realisation {
+        async fn try_deserialize<R>(reader: &mut R) -> Result<Option<Self>, R::Error>
+        where
+            R: ?Sized + NixRead + Send,
+        {
+            if let Some(buf) = reader.try_read_bytes().await? {
+                serde_json::from_slice(&buf)
+                    .map_err(R::Error::invalid_data)
+                    .map(Some)
+            } else {
+                Ok(None)
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::super::{DrvOutput, Realisation};
+        use crate::nix_daemon::de::{NixDeserialize, NixRead, NixReader};
+        use crate::nix_daemon::ser::{NixSerialize, NixWrite, NixWriter};
+        use std::fmt;
+
+        async fn roundtrip<T>(value: T, expected: &[u8])
+        where
+            T: NixSerialize + NixDeserialize + Send + Sync + PartialEq + fmt::Debug,
+        {
+            let mut writer = NixWriter::new(Vec::new());
+            writer.write_value(&value).await.unwrap();
+            let written = writer.into_inner();
+            assert_eq!(expected, &written[..]);
+
+            let mut reader = NixReader::new(&written[..]);
+            assert_eq!(value, reader.read_value::<T>().await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn drv_output_roundtrip() {
+            let s = "sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c!out";
+            let mut writer = NixWriter::new(Vec::new());
+            writer.write_value(s).await.unwrap();
+            roundtrip(s.parse::<DrvOutput>().unwrap(), &writer.into_inner()).await;
+        }
+
+        #[tokio::test]
+        async fn realisation_roundtrip() {
+            let json = r#"{"dependentRealisations":{"sha256:6e46b9cf4fecaeab4b3c0578f4ab99e89d2f93535878c4ac69b5d5c4eb3a3db9!dev":"9qhwzn1ihqplm4ax5hxqjz4bqbcjldg0-hello-2.12.1-dev"},"id":"sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c!out","outPath":"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432","signatures":["cache.example.com-1:67clgON48lUnTAbV+ZyXFOXFlsUhAcW9+pz3wWiZcE+ldsjSSBX+p09972jresbtES9d+gVMrj39u8BS9dRoCw=="]}"#;
+            let mut writer = NixWriter::new(Vec::new());
+            writer.write_value(json).await.unwrap();
+            roundtrip(
+                serde_json::from_str::<Realisation>(json).unwrap(),
+                &writer.into_inner(),
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DrvOutput, Error, Realisation};
+    use crate::narinfo::{parse_keypair, VerifyingKey};
+    use hex_literal::hex;
+
+    const REALISATION_JSON: &str = r#"{"dependentRealisations":{},"id":"sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c!out","outPath":"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432","signatures":[]}"#;
+
+    /// A realisation with a dependent realisation, signed by the key pair
+    /// below.
+    /// This is not captured from Nix: the hashes are made up, and the
+    /// signature was produced with [Realisation::add_signature]. It checks
+    /// the signing and verification code agree with each other, and that the
+    /// fingerprint has the shape Nix produces.
+    const SIGNED_REALISATION_JSON: &str = r#"{"dependentRealisations":{"sha256:6e46b9cf4fecaeab4b3c0578f4ab99e89d2f93535878c4ac69b5d5c4eb3a3db9!dev":"9qhwzn1ihqplm4ax5hxqjz4bqbcjldg0-hello-2.12.1-dev"},"id":"sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c!out","outPath":"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432","signatures":["cache.example.com-1:67clgON48lUnTAbV+ZyXFOXFlsUhAcW9+pz3wWiZcE+ldsjSSBX+p09972jresbtES9d+gVMrj39u8BS9dRoCw=="]}"#;
+
+    const KEYPAIR: &str = "cache.example.com-1:cCta2MEsRNuYCgWYyeRXLyfoFpKhQJKn8gLMeXWAb7vIpRKKo/3JoxJ24OYa3DxT2JVV38KjK/1ywHWuMe2JEw==";
+    const VERIFYING_KEY: &str = "cache.example.com-1:yKUSiqP9yaMSduDmGtw8U9iVVd/Coyv9csB1rjHtiRM=";
+
+    #[test]
+    fn drv_output_roundtrip() {
+        let s = "sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c!out";
+        let drv_output: DrvOutput = s.parse().expect("must parse");
+
+        assert_eq!(
+            hex!("ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c"),
+            drv_output.drv_hash
+        );
+        assert_eq!("out", drv_output.output_name);
+        assert_eq!(s, drv_output.to_string());
+    }
+
+    #[test]
+    fn drv_output_invalid() {
+        assert_eq!(
+            Err(Error::MissingSeparator),
+            "sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c"
+                .parse::<DrvOutput>()
+        );
+        assert!(matches!(
+            "md5:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4!out".parse::<DrvOutput>(),
+            Err(Error::UnsupportedHashAlgo(_))
+        ));
+        assert!(matches!(
+            "sha256:ba0e!out".parse::<DrvOutput>(),
+            Err(Error::InvalidDigest(_))
+        ));
+        assert!(matches!(
+            "sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c!o/ut"
+                .parse::<DrvOutput>(),
+            Err(Error::InvalidOutputName(_))
+        ));
+    }
+
+    #[test]
+    fn realisation_roundtrip() {
+        let realisation: Realisation =
+            serde_json::from_str(REALISATION_JSON).expect("must deserialize");
+
+        assert_eq!("out", realisation.id.output_name);
+        assert_eq!(
+            "00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432",
+            realisation.out_path.to_string()
+        );
+
+        assert_eq!(
+            REALISATION_JSON,
+            serde_json::to_string(&realisation).expect("must serialize")
+        );
+    }
+
+    #[test]
+    fn fingerprint() {
+        let realisation: Realisation =
+            serde_json::from_str(SIGNED_REALISATION_JSON).expect("must deserialize");
+
+        assert_eq!(
+            r#"{"dependentRealisations":{"sha256:6e46b9cf4fecaeab4b3c0578f4ab99e89d2f93535878c4ac69b5d5c4eb3a3db9!dev":"9qhwzn1ihqplm4ax5hxqjz4bqbcjldg0-hello-2.12.1-dev"},"id":"sha256:ba0e8dcd2f2ed5b5a09b4fbbc1e5b7e4d2d5e0a7bb1d6a0cb7c8a8bdf2d35a3c!out","outPath":"00bgd045z0d4icpbc2yyz4gx48ak44la-net-tools-1.60_p20170221182432"}"#,
+            realisation.fingerprint()
+        );
+        assert_eq!(
+            SIGNED_REALISATION_JSON,
+            serde_json::to_string(&realisation).expect("must serialize")
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let mut realisation: Realisation =
+            serde_json::from_str(SIGNED_REALISATION_JSON).expect("must deserialize");
+        let verifying_key = VerifyingKey::parse(VERIFYING_KEY).expect("must parse");
+
+        assert!(realisation.verify(&verifying_key));
+
+        // Changing the output path invalidates the signature.
+        realisation.out_path = "9qhwzn1ihqplm4ax5hxqjz4bqbcjldg0-hello-2.12.1-dev"
+            .parse()
+            .expect("must parse");
+        assert!(!realisation.verify(&verifying_key));
+    }
+
+    /// Signs a realisation, and ensures the signature matches the one in the
+    /// sample, as ed25519 signatures are deterministic.
+    #[test]
+    fn sign() {
+        let signed: Realisation =
+            serde_json::from_str(SIGNED_REALISATION_JSON).expect("must deserialize");
+        let (signing_key, verifying_key) = parse_keypair(KEYPAIR).expect("must parse");
+
+        let mut realisation = signed.clone();
+        realisation.signatures.clear();
+        assert!(!realisation.verify(&verifying_key));
+
+        realisation.add_signature(&signing_key);
+
+        assert!(realisation.verify(&verifying_key));
+        assert_eq!(signed, realisation);
+    }
+}
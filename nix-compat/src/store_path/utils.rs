@@ -1,5 +1,5 @@
 use crate::nixbase32;
-use crate::nixhash::{CAHash, NixHash};
+use crate::nixhash::{CAHash, HashAlgo, NixHash};
 use crate::store_path::{Error, StorePath, STORE_DIR};
 use data_encoding::HEXLOWER;
 use sha2::{Digest, Sha256};
@@ -17,6 +17,9 @@ pub enum BuildStorePathError {
     /// lifted at some point but there isn't a pressing need to anticipate that.
     #[error("References were not supported as much as requested")]
     InvalidReference(),
+    /// Store paths of git-hashed contents need a sha1 digest, like in Nix.
+    #[error("Unsupported hash algo for git hashing: {0}")]
+    InvalidGitHashAlgo(HashAlgo),
 }
 
 /// compress_hash takes an arbitrarily long sequence of bytes (usually
@@ -77,7 +80,8 @@ where
         return Err(BuildStorePathError::InvalidReference());
     }
 
-    /// Helper function, used for the non-sha256 [CAHash::Nar] and all [CAHash::Flat].
+    /// Helper function, used for the non-sha256 [CAHash::Nar] and all
+    /// [CAHash::Flat] and [CAHash::Git].
     fn fixed_out_digest(prefix: &str, hash: &NixHash) -> [u8; 32] {
         Sha256::new_with_prefix(format!("{}:{}:", prefix, hash.to_nix_hex_string()))
             .finalize()
@@ -113,6 +117,21 @@ where
                 fixed_out_digest("fixed:out", hash),
             )
         }
+        // CaHash::Git uses a `git:` prefix instead.
+        CAHash::Git(ref hash) => {
+            // Nix only calculates store paths from sha1 git hashes.
+            if !matches!(hash, NixHash::Sha1(_)) {
+                return Err(BuildStorePathError::InvalidGitHashAlgo(hash.algo()));
+            }
+            if references.into_iter().next().is_some() {
+                return Err(BuildStorePathError::InvalidReference());
+            }
+
+            (
+                "output:out".to_string(),
+                fixed_out_digest("fixed:out:git", hash),
+            )
+        }
     };
 
     build_store_path_from_fingerprint_parts(&ty, &inner_digest, name)
@@ -208,9 +227,40 @@ pub fn hash_placeholder(name: &str) -> String {
     format!("/{}", nixbase32::encode(&digest))
 }
 
+/// Placeholders for outputs of derivations whose output paths are only known
+/// after building them, like content-addressed derivations.
+///
+/// These are what `builtins.derivation` returns for such outputs, and are
+/// string-replaced with the actual output paths once built.
+/// Nix calls these "downstream placeholders".
+pub fn downstream_placeholder<S>(drv_path: &StorePath<S>, output_name: &str) -> String
+where
+    S: std::cmp::Eq + std::ops::Deref<Target = str>,
+{
+    let drv_name: &str = drv_path.name();
+    let mut output_path_name = drv_name
+        .strip_suffix(".drv")
+        .unwrap_or(drv_name)
+        .to_string();
+    if output_name != "out" {
+        output_path_name.push('-');
+        output_path_name.push_str(output_name);
+    }
+
+    let digest = Sha256::new_with_prefix(format!(
+        "nix-upstream-output:{}:{}",
+        nixbase32::encode(drv_path.digest()),
+        output_path_name
+    ))
+    .finalize();
+
+    format!("/{}", nixbase32::encode(&digest))
+}
+
 #[cfg(test)]
 mod test {
     use hex_literal::hex;
+    use rstest::rstest;
 
     use super::*;
     use crate::{
@@ -218,6 +268,21 @@ mod test {
         store_path::StorePathRef,
     };
 
+    #[test]
+    fn downstream_placeholder() {
+        let drv_path =
+            StorePathRef::from_bytes(b"g1w7hy3qg1w7hy3qg1w7hy3qg1w7hy3q-foo.drv").unwrap();
+
+        assert_eq!(
+            "/0c6rn30q4frawknapgwq386zq358m8r6msvywcvc89n6m5p2dgbz",
+            super::downstream_placeholder(&drv_path, "out")
+        );
+        assert_eq!(
+            "/08sm7m5zgp4qn0ccs1nqz5vkrfwni4dp7vny27rwsx66l493lidf",
+            super::downstream_placeholder(&drv_path, "dev")
+        );
+    }
+
     #[test]
     fn build_text_path_with_zero_references() {
         // This hash should match `builtins.toFile`, e.g.:
@@ -272,6 +337,37 @@ mod test {
         );
     }
 
+    #[test]
+    fn build_git_path() {
+        // The git blob hash of an empty file.
+        let outer: StorePathRef = build_ca_path(
+            "empty",
+            &CAHash::Git(NixHash::Sha1(hex!(
+                "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+            ))),
+            Vec::<String>::new(),
+            false,
+        )
+        .expect("build_ca_path() should succeed");
+
+        assert_eq!(
+            outer.to_absolute_path().as_str(),
+            "/nix/store/b18sd80w6jfcn627mms2pqwq1766yild-empty"
+        );
+    }
+
+    #[rstest]
+    #[case::md5(NixHash::Md5(hex!("d41d8cd98f00b204e9800998ecf8427e")))]
+    #[case::sha256(NixHash::Sha256(hex!(
+        "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813"
+    )))]
+    fn build_git_path_invalid_algo(#[case] hash: NixHash) {
+        assert_eq!(
+            Err(BuildStorePathError::InvalidGitHashAlgo(hash.algo())),
+            build_ca_path::<_, String, _>("empty", &CAHash::Git(hash), Vec::<String>::new(), false,)
+        );
+    }
+
     #[test]
     fn build_store_path_with_non_zero_references() {
         // This hash should match:
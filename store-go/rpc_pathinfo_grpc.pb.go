@@ -23,10 +23,13 @@ import (
 const _ = grpc.SupportPackageIsVersion7
 
 const (
-	PathInfoService_Get_FullMethodName          = "/tvix.store.v1.PathInfoService/Get"
-	PathInfoService_Put_FullMethodName          = "/tvix.store.v1.PathInfoService/Put"
-	PathInfoService_CalculateNAR_FullMethodName = "/tvix.store.v1.PathInfoService/CalculateNAR"
-	PathInfoService_List_FullMethodName         = "/tvix.store.v1.PathInfoService/List"
+	PathInfoService_Get_FullMethodName           = "/tvix.store.v1.PathInfoService/Get"
+	PathInfoService_Put_FullMethodName           = "/tvix.store.v1.PathInfoService/Put"
+	PathInfoService_CalculateNAR_FullMethodName  = "/tvix.store.v1.PathInfoService/CalculateNAR"
+	PathInfoService_List_FullMethodName          = "/tvix.store.v1.PathInfoService/List"
+	PathInfoService_GetMany_FullMethodName       = "/tvix.store.v1.PathInfoService/GetMany"
+	PathInfoService_ListReferrers_FullMethodName = "/tvix.store.v1.PathInfoService/ListReferrers"
+	PathInfoService_ListByDeriver_FullMethodName = "/tvix.store.v1.PathInfoService/ListByDeriver"
 )
 
 // PathInfoServiceClient is the client API for PathInfoService service.
@@ -65,6 +68,21 @@ type PathInfoServiceClient interface {
 	// Return a stream of PathInfo messages matching the criteria specified in
 	// ListPathInfoRequest.
 	List(ctx context.Context, in *ListPathInfoRequest, opts ...grpc.CallOption) (PathInfoService_ListClient, error)
+	// Return a GetManyPathInfoResponse message for each GetPathInfoRequest
+	// sent, in the same order.
+	// This saves a roundtrip per PathInfo compared to individual `Get`
+	// requests.
+	GetMany(ctx context.Context, opts ...grpc.CallOption) (PathInfoService_GetManyClient, error)
+	// Return a stream of PathInfo messages referring to the store path
+	// specified in the ListReferrersRequest, which is the reverse of
+	// PathInfo.references.
+	// Implementations not supporting this query return UNIMPLEMENTED.
+	ListReferrers(ctx context.Context, in *ListReferrersRequest, opts ...grpc.CallOption) (PathInfoService_ListReferrersClient, error)
+	// Return a stream of PathInfo messages produced by the derivation
+	// specified in the ListByDeriverRequest, which is the reverse of
+	// NARInfo.deriver.
+	// Implementations not supporting this query return UNIMPLEMENTED.
+	ListByDeriver(ctx context.Context, in *ListByDeriverRequest, opts ...grpc.CallOption) (PathInfoService_ListByDeriverClient, error)
 }
 
 type pathInfoServiceClient struct {
@@ -134,6 +152,101 @@ func (x *pathInfoServiceListClient) Recv() (*PathInfo, error) {
 	return m, nil
 }
 
+func (c *pathInfoServiceClient) GetMany(ctx context.Context, opts ...grpc.CallOption) (PathInfoService_GetManyClient, error) {
+	stream, err := c.cc.NewStream(ctx, &PathInfoService_ServiceDesc.Streams[1], PathInfoService_GetMany_FullMethodName, opts...)
+	if err != nil {
+		return nil, err
+	}
+	x := &pathInfoServiceGetManyClient{stream}
+	return x, nil
+}
+
+type PathInfoService_GetManyClient interface {
+	Send(*GetPathInfoRequest) error
+	Recv() (*GetManyPathInfoResponse, error)
+	grpc.ClientStream
+}
+
+type pathInfoServiceGetManyClient struct {
+	grpc.ClientStream
+}
+
+func (x *pathInfoServiceGetManyClient) Send(m *GetPathInfoRequest) error {
+	return x.ClientStream.SendMsg(m)
+}
+
+func (x *pathInfoServiceGetManyClient) Recv() (*GetManyPathInfoResponse, error) {
+	m := new(GetManyPathInfoResponse)
+	if err := x.ClientStream.RecvMsg(m); err != nil {
+		return nil, err
+	}
+	return m, nil
+}
+
+func (c *pathInfoServiceClient) ListReferrers(ctx context.Context, in *ListReferrersRequest, opts ...grpc.CallOption) (PathInfoService_ListReferrersClient, error) {
+	stream, err := c.cc.NewStream(ctx, &PathInfoService_ServiceDesc.Streams[2], PathInfoService_ListReferrers_FullMethodName, opts...)
+	if err != nil {
+		return nil, err
+	}
+	x := &pathInfoServiceListReferrersClient{stream}
+	if err := x.ClientStream.SendMsg(in); err != nil {
+		return nil, err
+	}
+	if err := x.ClientStream.CloseSend(); err != nil {
+		return nil, err
+	}
+	return x, nil
+}
+
+type PathInfoService_ListReferrersClient interface {
+	Recv() (*PathInfo, error)
+	grpc.ClientStream
+}
+
+type pathInfoServiceListReferrersClient struct {
+	grpc.ClientStream
+}
+
+func (x *pathInfoServiceListReferrersClient) Recv() (*PathInfo, error) {
+	m := new(PathInfo)
+	if err := x.ClientStream.RecvMsg(m); err != nil {
+		return nil, err
+	}
+	return m, nil
+}
+
+func (c *pathInfoServiceClient) ListByDeriver(ctx context.Context, in *ListByDeriverRequest, opts ...grpc.CallOption) (PathInfoService_ListByDeriverClient, error) {
+	stream, err := c.cc.NewStream(ctx, &PathInfoService_ServiceDesc.Streams[3], PathInfoService_ListByDeriver_FullMethodName, opts...)
+	if err != nil {
+		return nil, err
+	}
+	x := &pathInfoServiceListByDeriverClient{stream}
+	if err := x.ClientStream.SendMsg(in); err != nil {
+		return nil, err
+	}
+	if err := x.ClientStream.CloseSend(); err != nil {
+		return nil, err
+	}
+	return x, nil
+}
+
+type PathInfoService_ListByDeriverClient interface {
+	Recv() (*PathInfo, error)
+	grpc.ClientStream
+}
+
+type pathInfoServiceListByDeriverClient struct {
+	grpc.ClientStream
+}
+
+func (x *pathInfoServiceListByDeriverClient) Recv() (*PathInfo, error) {
+	m := new(PathInfo)
+	if err := x.ClientStream.RecvMsg(m); err != nil {
+		return nil, err
+	}
+	return m, nil
+}
+
 // PathInfoServiceServer is the server API for PathInfoService service.
 // All implementations must embed UnimplementedPathInfoServiceServer
 // for forward compatibility
@@ -170,6 +283,21 @@ type PathInfoServiceServer interface {
 	// Return a stream of PathInfo messages matching the criteria specified in
 	// ListPathInfoRequest.
 	List(*ListPathInfoRequest, PathInfoService_ListServer) error
+	// Return a GetManyPathInfoResponse message for each GetPathInfoRequest
+	// sent, in the same order.
+	// This saves a roundtrip per PathInfo compared to individual `Get`
+	// requests.
+	GetMany(PathInfoService_GetManyServer) error
+	// Return a stream of PathInfo messages referring to the store path
+	// specified in the ListReferrersRequest, which is the reverse of
+	// PathInfo.references.
+	// Implementations not supporting this query return UNIMPLEMENTED.
+	ListReferrers(*ListReferrersRequest, PathInfoService_ListReferrersServer) error
+	// Return a stream of PathInfo messages produced by the derivation
+	// specified in the ListByDeriverRequest, which is the reverse of
+	// NARInfo.deriver.
+	// Implementations not supporting this query return UNIMPLEMENTED.
+	ListByDeriver(*ListByDeriverRequest, PathInfoService_ListByDeriverServer) error
 	mustEmbedUnimplementedPathInfoServiceServer()
 }
 
@@ -189,6 +317,15 @@ func (UnimplementedPathInfoServiceServer) CalculateNAR(context.Context, *castore
 func (UnimplementedPathInfoServiceServer) List(*ListPathInfoRequest, PathInfoService_ListServer) error {
 	return status.Errorf(codes.Unimplemented, "method List not implemented")
 }
+func (UnimplementedPathInfoServiceServer) GetMany(PathInfoService_GetManyServer) error {
+	return status.Errorf(codes.Unimplemented, "method GetMany not implemented")
+}
+func (UnimplementedPathInfoServiceServer) ListReferrers(*ListReferrersRequest, PathInfoService_ListReferrersServer) error {
+	return status.Errorf(codes.Unimplemented, "method ListReferrers not implemented")
+}
+func (UnimplementedPathInfoServiceServer) ListByDeriver(*ListByDeriverRequest, PathInfoService_ListByDeriverServer) error {
+	return status.Errorf(codes.Unimplemented, "method ListByDeriver not implemented")
+}
 func (UnimplementedPathInfoServiceServer) mustEmbedUnimplementedPathInfoServiceServer() {}
 
 // UnsafePathInfoServiceServer may be embedded to opt out of forward compatibility for this service.
@@ -277,6 +414,74 @@ func (x *pathInfoServiceListServer) Send(m *PathInfo) error {
 	return x.ServerStream.SendMsg(m)
 }
 
+func _PathInfoService_GetMany_Handler(srv interface{}, stream grpc.ServerStream) error {
+	return srv.(PathInfoServiceServer).GetMany(&pathInfoServiceGetManyServer{stream})
+}
+
+type PathInfoService_GetManyServer interface {
+	Send(*GetManyPathInfoResponse) error
+	Recv() (*GetPathInfoRequest, error)
+	grpc.ServerStream
+}
+
+type pathInfoServiceGetManyServer struct {
+	grpc.ServerStream
+}
+
+func (x *pathInfoServiceGetManyServer) Send(m *GetManyPathInfoResponse) error {
+	return x.ServerStream.SendMsg(m)
+}
+
+func (x *pathInfoServiceGetManyServer) Recv() (*GetPathInfoRequest, error) {
+	m := new(GetPathInfoRequest)
+	if err := x.ServerStream.RecvMsg(m); err != nil {
+		return nil, err
+	}
+	return m, nil
+}
+
+func _PathInfoService_ListReferrers_Handler(srv interface{}, stream grpc.ServerStream) error {
+	m := new(ListReferrersRequest)
+	if err := stream.RecvMsg(m); err != nil {
+		return err
+	}
+	return srv.(PathInfoServiceServer).ListReferrers(m, &pathInfoServiceListReferrersServer{stream})
+}
+
+type PathInfoService_ListReferrersServer interface {
+	Send(*PathInfo) error
+	grpc.ServerStream
+}
+
+type pathInfoServiceListReferrersServer struct {
+	grpc.ServerStream
+}
+
+func (x *pathInfoServiceListReferrersServer) Send(m *PathInfo) error {
+	return x.ServerStream.SendMsg(m)
+}
+
+func _PathInfoService_ListByDeriver_Handler(srv interface{}, stream grpc.ServerStream) error {
+	m := new(ListByDeriverRequest)
+	if err := stream.RecvMsg(m); err != nil {
+		return err
+	}
+	return srv.(PathInfoServiceServer).ListByDeriver(m, &pathInfoServiceListByDeriverServer{stream})
+}
+
+type PathInfoService_ListByDeriverServer interface {
+	Send(*PathInfo) error
+	grpc.ServerStream
+}
+
+type pathInfoServiceListByDeriverServer struct {
+	grpc.ServerStream
+}
+
+func (x *pathInfoServiceListByDeriverServer) Send(m *PathInfo) error {
+	return x.ServerStream.SendMsg(m)
+}
+
 // PathInfoService_ServiceDesc is the grpc.ServiceDesc for PathInfoService service.
 // It's only intended for direct use with grpc.RegisterService,
 // and not to be introspected or modified (even as a copy)
@@ -303,6 +508,22 @@ var PathInfoService_ServiceDesc = grpc.ServiceDesc{
 			Handler:       _PathInfoService_List_Handler,
 			ServerStreams: true,
 		},
+		{
+			StreamName:    "GetMany",
+			Handler:       _PathInfoService_GetMany_Handler,
+			ServerStreams: true,
+			ClientStreams: true,
+		},
+		{
+			StreamName:    "ListReferrers",
+			Handler:       _PathInfoService_ListReferrers_Handler,
+			ServerStreams: true,
+		},
+		{
+			StreamName:    "ListByDeriver",
+			Handler:       _PathInfoService_ListByDeriver_Handler,
+			ServerStreams: true,
+		},
 	},
 	Metadata: "tvix/store/protos/rpc_pathinfo.proto",
 }
@@ -3,6 +3,7 @@ use clap::Subcommand;
 
 use futures::StreamExt;
 use futures::TryStreamExt;
+use nix_compat::narinfo::VerifyingKey;
 use nix_compat::nix_daemon::de::Error;
 use nix_compat::nixhash::CAHash;
 use nix_compat::nixhash::NixHash;
@@ -14,12 +15,14 @@ use std::sync::Arc;
 use tonic::transport::Server;
 use tower::ServiceBuilder;
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
-use tracing::{debug, info, info_span, instrument, warn, Instrument, Level, Span};
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument, Level, Span};
 use tracing_indicatif::span_ext::IndicatifSpanExt;
 use tvix_castore::import::fs::ingest_path;
+use tvix_store::git::ingest_path as git_ingest_path;
 use tvix_store::import::path_to_name;
 use tvix_store::nar::NarCalculationService;
-use tvix_store::utils::{ServiceUrls, ServiceUrlsGrpc};
+use tvix_store::nix_daemon::{NixDaemon, NixDaemonLayer};
+use tvix_store::utils::{ServiceUrls, ServiceUrlsFrom, ServiceUrlsGrpc, ServiceUrlsTo};
 use tvix_tracing::TracingHandle;
 
 use tvix_castore::proto::blob_service_server::BlobServiceServer;
@@ -77,12 +80,33 @@ enum Commands {
 
         #[clap(flatten)]
         service_addrs: ServiceUrls,
+
+        /// If set, additionally serves the Nix daemon protocol on a Unix
+        /// socket at this path, so Nix clients can use the store.
+        #[arg(long, env)]
+        nix_daemon_socket: Option<PathBuf>,
+
+        /// The uids of the users trusted by the Nix daemon.
+        /// Paths added by other users need to be signed by one of
+        /// --nix-daemon-trusted-public-keys, or be content-addressed.
+        #[arg(long, env, value_delimiter = ' ', default_value = "0")]
+        nix_daemon_trusted_users: Vec<u32>,
+
+        /// The keys accepted in signatures of paths added via the Nix
+        /// daemon.
+        #[arg(long, env, value_delimiter = ' ')]
+        nix_daemon_trusted_public_keys: Vec<String>,
     },
     /// Imports a list of paths into the store, print the store path for each of them.
     Import {
         #[clap(value_name = "PATH")]
         paths: Vec<PathBuf>,
 
+        /// Content-address the paths by their git (sha1) tree or blob hash,
+        /// rather than the sha256 digest of their NAR serialization.
+        #[arg(long)]
+        git: bool,
+
         #[clap(flatten)]
         service_addrs: ServiceUrlsGrpc,
     },
@@ -104,6 +128,53 @@ enum Commands {
         #[arg(value_name = "NIX_ATTRS_JSON_FILE", env = "NIX_ATTRS_JSON_FILE")]
         reference_graph_path: PathBuf,
     },
+    /// Copies the closure of the given store paths from one tvix-store to
+    /// another, and prints all store paths copied.
+    /// Only PathInfos, directories and blobs missing in the destination are
+    /// transferred.
+    CopyClosure {
+        #[clap(flatten)]
+        from_service_addrs: ServiceUrlsFrom,
+
+        #[clap(flatten)]
+        to_service_addrs: ServiceUrlsTo,
+
+        /// Store paths whose closure should be copied.
+        #[arg(value_name = "STORE_PATH", required = true)]
+        paths: Vec<String>,
+    },
+    /// Imports the store paths contained in a stream produced by
+    /// `nix-store --export`, read from stdin, and prints them.
+    ImportExport {
+        #[clap(flatten)]
+        service_addrs: ServiceUrlsGrpc,
+    },
+    /// Writes the closure of the given store paths to stdout, in the format
+    /// produced by `nix-store --export`.
+    ExportClosure {
+        #[clap(flatten)]
+        service_addrs: ServiceUrlsGrpc,
+
+        /// Store paths whose closure should be exported.
+        #[arg(value_name = "STORE_PATH", required = true)]
+        paths: Vec<String>,
+    },
+    /// Removes all PathInfos, directories and blobs not reachable from the
+    /// given roots, and prints everything removed.
+    Gc {
+        #[clap(flatten)]
+        service_addrs: ServiceUrls,
+
+        /// Store paths whose closure should be kept.
+        /// If none are given, all PathInfos in the store are roots, and only
+        /// directories and blobs not referred to by any of them are removed.
+        #[arg(long = "root", value_name = "STORE_PATH")]
+        roots: Vec<String>,
+
+        /// Only print what would be removed, without removing anything.
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
     /// Mounts a tvix-store at the given mountpoint
     #[cfg(feature = "fuse")]
     Mount {
@@ -171,6 +242,9 @@ async fn run_cli(
         Commands::Daemon {
             listen_args,
             service_addrs,
+            nix_daemon_socket,
+            nix_daemon_trusted_users,
+            nix_daemon_trusted_public_keys,
         } => {
             // initialize stores
             let (blob_service, directory_service, path_info_service, nar_calculation_service) =
@@ -194,13 +268,13 @@ async fn run_cli(
             let mut router = server
                 .add_service(health_service)
                 .add_service(BlobServiceServer::new(GRPCBlobServiceWrapper::new(
-                    blob_service,
+                    blob_service.clone(),
                 )))
                 .add_service(DirectoryServiceServer::new(
-                    GRPCDirectoryServiceWrapper::new(directory_service),
+                    GRPCDirectoryServiceWrapper::new(directory_service.clone()),
                 ))
                 .add_service(PathInfoServiceServer::new(GRPCPathInfoServiceWrapper::new(
-                    path_info_service,
+                    path_info_service.clone(),
                     nar_calculation_service,
                 )));
 
@@ -233,12 +307,33 @@ async fn run_cli(
             )
             .await?;
 
+            if let Some(nix_daemon_socket) = nix_daemon_socket {
+                let nix_daemon_listener = tokio::net::UnixListener::bind(&nix_daemon_socket)?;
+                let mut nix_daemon =
+                    NixDaemon::new(blob_service, directory_service, path_info_service);
+                nix_daemon.set_trusted_users(nix_daemon_trusted_users);
+                nix_daemon.set_public_keys(
+                    nix_daemon_trusted_public_keys
+                        .iter()
+                        .map(|pubkey_str| VerifyingKey::parse(pubkey_str))
+                        .collect::<Result<Vec<_>, _>>()?,
+                );
+
+                info!(socket=?nix_daemon_socket, "starting nix-daemon");
+                tokio::spawn(async move {
+                    if let Err(e) = nix_daemon.serve(nix_daemon_listener).await {
+                        error!(err=%e, "nix-daemon failed");
+                    }
+                });
+            }
+
             info!(listen_address=%listen_address, "starting daemon");
 
             router.serve_with_incoming(listener).await?;
         }
         Commands::Import {
             paths,
+            git,
             service_addrs,
         } => {
             // FUTUREWORK: allow flat for single files?
@@ -284,14 +379,27 @@ async fn run_cli(
                         span.pb_start();
 
                         // Ingest the contents at the given path into castore.
-                        let root_node = ingest_path::<_, _, _, &[u8]>(
-                            blob_service,
-                            directory_service,
-                            &path,
-                            None,
-                        )
-                        .await
-                        .map_err(std::io::Error::custom)?;
+                        // With git, the git object id is calculated while
+                        // ingesting, so file contents are only read once.
+                        let (root_node, git_digest) = if git {
+                            let (root_node, digest) = git_ingest_path::<sha1::Sha1, _, _>(
+                                blob_service,
+                                directory_service,
+                                &path,
+                            )
+                            .await?;
+                            (root_node, Some(digest))
+                        } else {
+                            let root_node = ingest_path::<_, _, _, &[u8]>(
+                                blob_service,
+                                directory_service,
+                                &path,
+                                None,
+                            )
+                            .await
+                            .map_err(std::io::Error::custom)?;
+                            (root_node, None)
+                        };
 
                         span.pb_set_message(&format!("NAR Calculation for {:?}", path));
 
@@ -299,9 +407,15 @@ async fn run_cli(
                         let (nar_size, nar_sha256) =
                             nar_calculation_service.calculate_nar(&root_node).await?;
 
+                        let ca = match git_digest {
+                            Some(digest) => CAHash::Git(NixHash::Sha1(
+                                digest.try_into().expect("sha1 digest has 20 bytes"),
+                            )),
+                            None => CAHash::Nar(NixHash::Sha256(nar_sha256)),
+                        };
+
                         // Calculate the output path. This might still fail, as some names are illegal.
                         // FUTUREWORK: express the `name` at the type level to be valid and check for this earlier.
-                        let ca = CAHash::Nar(NixHash::Sha256(nar_sha256));
                         let output_path: StorePath<String> =
                             nix_compat::store_path::build_ca_path::<&str, _, _>(
                                 &name,
@@ -445,6 +559,139 @@ async fn run_cli(
                 path_info_service.put(path_info).await?;
             }
         }
+        Commands::CopyClosure {
+            from_service_addrs,
+            to_service_addrs,
+            paths,
+        } => {
+            let (src_blob_service, src_directory_service, src_path_info_service, _) =
+                tvix_store::utils::construct_services(from_service_addrs).await?;
+            let (dst_blob_service, dst_directory_service, dst_path_info_service, _) =
+                tvix_store::utils::construct_services(to_service_addrs).await?;
+
+            let paths = paths
+                .iter()
+                .map(|path| {
+                    StorePath::<String>::from_absolute_path(path.as_bytes())
+                        .map_err(|e| format!("invalid store path {path}: {e}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let result = tvix_store::copy::copy_closure(
+                src_blob_service,
+                src_directory_service,
+                src_path_info_service,
+                dst_blob_service,
+                dst_directory_service,
+                dst_path_info_service,
+                &paths,
+            )
+            .await?;
+
+            use std::io::Write;
+            let mut stdout = tracing_handle.get_stdout_writer();
+            for store_path in &result.path_infos {
+                writeln!(&mut stdout, "{}", store_path.to_absolute_path())?;
+            }
+
+            info!(
+                path_infos = result.path_infos.len(),
+                directories = result.castore.directories.len(),
+                blobs = result.castore.blobs.len(),
+                "copy finished"
+            );
+        }
+        Commands::ImportExport { service_addrs } => {
+            let (blob_service, directory_service, path_info_service, _nar_calculation_service) =
+                tvix_store::utils::construct_services(service_addrs).await?;
+
+            let store_paths = tvix_store::export::import_paths(
+                blob_service,
+                directory_service,
+                path_info_service,
+                tokio::io::stdin(),
+            )
+            .await?;
+
+            use std::io::Write;
+            let mut stdout = tracing_handle.get_stdout_writer();
+            for store_path in &store_paths {
+                writeln!(&mut stdout, "{}", store_path.to_absolute_path())?;
+            }
+
+            info!(path_infos = store_paths.len(), "import finished");
+        }
+        Commands::ExportClosure {
+            service_addrs,
+            paths,
+        } => {
+            let (blob_service, directory_service, path_info_service, _nar_calculation_service) =
+                tvix_store::utils::construct_services(service_addrs).await?;
+
+            let paths = paths
+                .iter()
+                .map(|path| {
+                    StorePath::<String>::from_absolute_path(path.as_bytes())
+                        .map_err(|e| format!("invalid store path {path}: {e}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let store_paths = tvix_store::export::export_closure(
+                blob_service,
+                directory_service,
+                path_info_service,
+                &paths,
+                tokio::io::BufWriter::new(tokio::io::stdout()),
+            )
+            .await?;
+
+            info!(path_infos = store_paths.len(), "export finished");
+        }
+        Commands::Gc {
+            service_addrs,
+            roots,
+            dry_run,
+        } => {
+            let (blob_service, directory_service, path_info_service, _nar_calculation_service) =
+                tvix_store::utils::construct_services(service_addrs).await?;
+
+            let roots = roots
+                .iter()
+                .map(|root| {
+                    StorePath::<String>::from_absolute_path(root.as_bytes())
+                        .map_err(|e| format!("invalid root {root}: {e}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let result = tvix_store::gc::collect_garbage(
+                blob_service,
+                directory_service,
+                path_info_service,
+                (!roots.is_empty()).then_some(roots.as_slice()),
+                dry_run,
+            )
+            .await?;
+
+            use std::io::Write;
+            let mut stdout = tracing_handle.get_stdout_writer();
+            for store_path in &result.path_infos {
+                writeln!(&mut stdout, "{}", store_path.to_absolute_path())?;
+            }
+            for digest in &result.castore.directories {
+                writeln!(&mut stdout, "directory {digest}")?;
+            }
+            for digest in &result.castore.blobs {
+                writeln!(&mut stdout, "blob {digest}")?;
+            }
+
+            info!(
+                path_infos = result.path_infos.len(),
+                directories = result.castore.directories.len(),
+                blobs = result.castore.blobs.len(),
+                dry_run,
+                "garbage collection finished"
+            );
+        }
         #[cfg(feature = "fuse")]
         Commands::Mount {
             dest,
@@ -522,7 +769,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     let tracing_handle = {
         let mut builder = tvix_tracing::TracingBuilder::default();
-        builder = builder.level(cli.log_level).enable_progressbar();
+        builder = builder
+            .level(cli.log_level)
+            .enable_progressbar()
+            // forwards logs to clients of the nix-daemon socket, if enabled.
+            .with_layer(NixDaemonLayer::default());
         #[cfg(feature = "otlp")]
         {
             if cli.otlp {
@@ -0,0 +1,41 @@
+use super::BuildLogService;
+
+use crate::composition::{
+    with_registry, CompositionContext, DeserializeWithRegistry, ServiceBuilder, REG,
+};
+use std::sync::Arc;
+use tvix_castore::Error;
+use url::Url;
+
+/// Constructs a new instance of a [BuildLogService] from an URI.
+///
+/// The following URIs are supported:
+/// - `memory:`
+///   Uses a in-memory implementation.
+/// - `redb:`
+///   Uses a in-memory redb implementation.
+/// - `redb:///absolute/path/to/somewhere`
+///   Uses redb, using a path on the disk for persistency. Can be only opened
+///   from one process at the same time.
+pub async fn from_addr(
+    uri: &str,
+    context: Option<&CompositionContext<'_>>,
+) -> Result<Arc<dyn BuildLogService>, Box<dyn std::error::Error + Send + Sync>> {
+    let url =
+        Url::parse(uri).map_err(|e| Error::StorageError(format!("unable to parse url: {}", e)))?;
+
+    let build_log_service_config = with_registry(&REG, || {
+        <DeserializeWithRegistry<Box<dyn ServiceBuilder<Output = dyn BuildLogService>>>>::try_from(
+            url,
+        )
+    })?
+    .0;
+    let build_log_service = build_log_service_config
+        .build(
+            "anonymous",
+            context.unwrap_or(&CompositionContext::blank(&REG)),
+        )
+        .await?;
+
+    Ok(build_log_service)
+}
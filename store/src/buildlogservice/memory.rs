@@ -0,0 +1,56 @@
+use super::BuildLogService;
+use bytes::Bytes;
+use nix_compat::nixbase32;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+use tonic::async_trait;
+use tracing::instrument;
+use tvix_castore::composition::{CompositionContext, ServiceBuilder};
+use tvix_castore::Error;
+
+#[derive(Default)]
+pub struct MemoryBuildLogService {
+    db: Arc<RwLock<HashMap<[u8; 20], Bytes>>>,
+}
+
+#[async_trait]
+impl BuildLogService for MemoryBuildLogService {
+    #[instrument(level = "trace", skip_all, fields(drv.digest = nixbase32::encode(&drv_digest)))]
+    async fn get(&self, drv_digest: [u8; 20]) -> Result<Option<Bytes>, Error> {
+        Ok(self.db.read().await.get(&drv_digest).cloned())
+    }
+
+    #[instrument(level = "trace", skip_all, fields(drv.digest = nixbase32::encode(&drv_digest), log.size = log.len()))]
+    async fn put(&self, drv_digest: [u8; 20], log: Bytes) -> Result<(), Error> {
+        self.db.write().await.insert(drv_digest, log);
+
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryBuildLogServiceConfig {}
+
+impl TryFrom<url::Url> for MemoryBuildLogServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // memory doesn't support host or path in the URL.
+        if url.has_host() || !url.path().is_empty() {
+            return Err(Error::StorageError("invalid url".to_string()).into());
+        }
+        Ok(MemoryBuildLogServiceConfig {})
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for MemoryBuildLogServiceConfig {
+    type Output = dyn BuildLogService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn BuildLogService>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        Ok(Arc::new(MemoryBuildLogService::default()))
+    }
+}
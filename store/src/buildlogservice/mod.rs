@@ -0,0 +1,36 @@
+mod from_addr;
+mod memory;
+mod redb;
+
+#[cfg(test)]
+mod tests;
+
+use auto_impl::auto_impl;
+use bytes::Bytes;
+use tonic::async_trait;
+use tvix_castore::composition::{Registry, ServiceBuilder};
+use tvix_castore::Error;
+
+pub use self::from_addr::from_addr;
+pub use self::memory::{MemoryBuildLogService, MemoryBuildLogServiceConfig};
+pub use self::redb::{RedbBuildLogService, RedbBuildLogServiceConfig};
+
+/// The base trait all BuildLog services need to implement.
+/// Build logs are stored by the output digest of the derivation store path
+/// they were produced by.
+#[async_trait]
+#[auto_impl(&, &mut, Arc, Box)]
+pub trait BuildLogService: Send + Sync {
+    /// Retrieve the build log of the derivation with the given output digest.
+    async fn get(&self, drv_digest: [u8; 20]) -> Result<Option<Bytes>, Error>;
+
+    /// Store the build log of the derivation with the given output digest,
+    /// replacing a previously stored one.
+    async fn put(&self, drv_digest: [u8; 20], log: Bytes) -> Result<(), Error>;
+}
+
+/// Registers the builtin BuildLogService implementations with the registry
+pub(crate) fn register_build_log_services(reg: &mut Registry) {
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn BuildLogService>>, MemoryBuildLogServiceConfig>("memory");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn BuildLogService>>, RedbBuildLogServiceConfig>("redb");
+}
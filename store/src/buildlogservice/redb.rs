@@ -0,0 +1,157 @@
+use super::BuildLogService;
+use bytes::Bytes;
+use nix_compat::nixbase32;
+use redb::{Database, TableDefinition};
+use std::{path::PathBuf, sync::Arc};
+use tonic::async_trait;
+use tracing::instrument;
+use tvix_castore::{
+    composition::{CompositionContext, ServiceBuilder},
+    Error,
+};
+
+const BUILDLOG_TABLE: TableDefinition<[u8; 20], Vec<u8>> = TableDefinition::new("buildlog");
+
+/// BuildLogService implementation using redb under the hood.
+/// redb stores all of its data in a single file with a K/V pointing from the
+/// output hash of a derivation store path to the log of its build.
+pub struct RedbBuildLogService {
+    // We wrap db in an Arc to be able to move it into spawn_blocking,
+    // as discussed in https://github.com/cberner/redb/issues/789
+    db: Arc<Database>,
+}
+
+impl RedbBuildLogService {
+    /// Constructs a new instance using the specified file system path for
+    /// storage.
+    pub async fn new(path: PathBuf) -> Result<Self, Error> {
+        if path == PathBuf::from("/") {
+            return Err(Error::StorageError(
+                "cowardly refusing to open / with redb".to_string(),
+            ));
+        }
+
+        let db = tokio::task::spawn_blocking(|| -> Result<_, Error> {
+            let db = redb::Database::create(path)?;
+            create_schema(&db)?;
+            Ok(db)
+        })
+        .await??;
+
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Constructs a new instance using the in-memory backend.
+    pub fn new_temporary() -> Result<Self, Error> {
+        let db =
+            redb::Database::builder().create_with_backend(redb::backends::InMemoryBackend::new())?;
+
+        create_schema(&db)?;
+
+        Ok(Self { db: Arc::new(db) })
+    }
+}
+
+/// Ensures all tables are present.
+/// Opens a write transaction and calls open_table on BUILDLOG_TABLE, which will
+/// create it if not present.
+fn create_schema(db: &redb::Database) -> Result<(), redb::Error> {
+    let txn = db.begin_write()?;
+    txn.open_table(BUILDLOG_TABLE)?;
+    txn.commit()?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl BuildLogService for RedbBuildLogService {
+    #[instrument(level = "trace", skip_all, fields(drv.digest = nixbase32::encode(&drv_digest)))]
+    async fn get(&self, drv_digest: [u8; 20]) -> Result<Option<Bytes>, Error> {
+        let db = self.db.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let txn = db.begin_read()?;
+            let table = txn.open_table(BUILDLOG_TABLE)?;
+            Ok(table.get(drv_digest)?.map(|log| log.value().into()))
+        })
+        .await?
+    }
+
+    #[instrument(level = "trace", skip_all, fields(drv.digest = nixbase32::encode(&drv_digest), log.size = log.len()))]
+    async fn put(&self, drv_digest: [u8; 20], log: Bytes) -> Result<(), Error> {
+        let db = self.db.clone();
+
+        tokio::task::spawn_blocking(move || -> Result<(), Error> {
+            let txn = db.begin_write()?;
+            {
+                let mut table = txn.open_table(BUILDLOG_TABLE)?;
+                table.insert(drv_digest, log.to_vec())?;
+            }
+            Ok(txn.commit()?)
+        })
+        .await?
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedbBuildLogServiceConfig {
+    is_temporary: bool,
+    #[serde(default)]
+    /// required when is_temporary = false
+    path: Option<PathBuf>,
+}
+
+impl TryFrom<url::Url> for RedbBuildLogServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // redb doesn't support host, and a path can be provided (otherwise it'll live in memory only)
+        if url.has_host() {
+            return Err(Error::StorageError("no host allowed".to_string()).into());
+        }
+
+        Ok(if url.path().is_empty() {
+            RedbBuildLogServiceConfig {
+                is_temporary: true,
+                path: None,
+            }
+        } else {
+            RedbBuildLogServiceConfig {
+                is_temporary: false,
+                path: Some(url.path().into()),
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for RedbBuildLogServiceConfig {
+    type Output = dyn BuildLogService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn BuildLogService>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        match self {
+            RedbBuildLogServiceConfig {
+                is_temporary: true,
+                path: None,
+            } => Ok(Arc::new(RedbBuildLogService::new_temporary()?)),
+            RedbBuildLogServiceConfig {
+                is_temporary: true,
+                path: Some(_),
+            } => Err(
+                Error::StorageError("Temporary RedbBuildLogService can not have path".into())
+                    .into(),
+            ),
+            RedbBuildLogServiceConfig {
+                is_temporary: false,
+                path: None,
+            } => Err(Error::StorageError("RedbBuildLogService is missing path".into()).into()),
+            RedbBuildLogServiceConfig {
+                is_temporary: false,
+                path: Some(path),
+            } => Ok(Arc::new(RedbBuildLogService::new(path.to_owned()).await?)),
+        }
+    }
+}
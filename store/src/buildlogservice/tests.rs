@@ -0,0 +1,51 @@
+//! This contains test scenarios that a given [BuildLogService] needs to pass.
+
+use bytes::Bytes;
+use rstest::*;
+use rstest_reuse::{self, *};
+
+use super::{BuildLogService, MemoryBuildLogService, RedbBuildLogService};
+use crate::tests::fixtures::DUMMY_PATH_DIGEST;
+
+#[template]
+#[rstest]
+#[case::memory(MemoryBuildLogService::default())]
+#[case::redb(RedbBuildLogService::new_temporary().unwrap())]
+pub fn build_log_services(#[case] svc: impl BuildLogService) {}
+
+/// Trying to get a non-existent build log should return Ok(None).
+#[apply(build_log_services)]
+#[tokio::test]
+async fn not_found(svc: impl BuildLogService) {
+    assert!(svc
+        .get(DUMMY_PATH_DIGEST)
+        .await
+        .expect("must succeed")
+        .is_none());
+}
+
+/// Put a build log into the store, get it back, then replace it.
+#[apply(build_log_services)]
+#[tokio::test]
+async fn put_get(svc: impl BuildLogService) {
+    svc.put(DUMMY_PATH_DIGEST, Bytes::from_static(b"building foo\n"))
+        .await
+        .expect("must succeed");
+
+    assert_eq!(
+        Some(Bytes::from_static(b"building foo\n")),
+        svc.get(DUMMY_PATH_DIGEST).await.expect("must succeed")
+    );
+
+    svc.put(
+        DUMMY_PATH_DIGEST,
+        Bytes::from_static(b"building foo again\n"),
+    )
+    .await
+    .expect("must succeed");
+
+    assert_eq!(
+        Some(Bytes::from_static(b"building foo again\n")),
+        svc.get(DUMMY_PATH_DIGEST).await.expect("must succeed")
+    );
+}
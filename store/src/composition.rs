@@ -4,7 +4,8 @@ pub use tvix_castore::composition::*;
 
 /// The provided registry of tvix_store, which has all the builtin
 /// tvix_castore (BlobStore/DirectoryStore) and tvix_store
-/// (PathInfoService) implementations.
+/// (PathInfoService, NarCalculationService, BuildLogService,
+/// RealisationService) implementations.
 pub static REG: LazyLock<&'static Registry> = LazyLock::new(|| {
     let mut reg = Default::default();
     add_default_services(&mut reg);
@@ -18,4 +19,7 @@ pub static REG: LazyLock<&'static Registry> = LazyLock::new(|| {
 pub fn add_default_services(reg: &mut Registry) {
     tvix_castore::composition::add_default_services(reg);
     crate::pathinfoservice::register_pathinfo_services(reg);
+    crate::nar::register_nar_calculation_services(reg);
+    crate::buildlogservice::register_build_log_services(reg);
+    crate::realisationservice::register_realisation_services(reg);
 }
@@ -0,0 +1,366 @@
+//! Copying the closure of store paths from one store to another, each
+//! composed of [BlobService], [DirectoryService] and [PathInfoService].
+//!
+//! The closure is determined by following [PathInfo::references] in the
+//! source. Only PathInfos missing in the destination are copied, alongside
+//! the directories and blobs they refer to (see [tvix_castore::copy] for how
+//! these are deduplicated).
+
+use std::collections::{HashMap, HashSet};
+
+use futures::{StreamExt, TryStreamExt};
+use nix_compat::store_path::StorePath;
+use tracing::{debug, info_span, instrument, Instrument, Span};
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+use tvix_castore::blobservice::BlobService;
+use tvix_castore::copy::{Copier, CopyResult as CastoreCopyResult};
+use tvix_castore::directoryservice::DirectoryService;
+use tvix_castore::Error;
+
+use crate::pathinfoservice::{PathInfo, PathInfoService};
+
+/// The number of store paths whose contents are copied concurrently.
+const PATH_CONCURRENCY: usize = 10;
+
+/// Everything that was copied.
+#[derive(Debug, Default)]
+pub struct CopyResult {
+    pub path_infos: Vec<StorePath<String>>,
+    pub castore: CastoreCopyResult,
+}
+
+/// Copies the closure of the given store paths from the source to the
+/// destination services.
+///
+/// All directories and blobs are copied before any PathInfo is inserted, and
+/// PathInfos are inserted after all of their references, so an interrupted
+/// copy never leaves PathInfos with missing contents or references behind in
+/// the destination.
+#[instrument(skip_all, fields(roots = roots.len(), indicatif.pb_show = tracing::field::Empty), err)]
+#[allow(clippy::too_many_arguments)]
+pub async fn copy_closure<SBS, SDS, SPS, DBS, DDS, DPS>(
+    src_blob_service: SBS,
+    src_directory_service: SDS,
+    src_path_info_service: SPS,
+    dst_blob_service: DBS,
+    dst_directory_service: DDS,
+    dst_path_info_service: DPS,
+    roots: &[StorePath<String>],
+) -> Result<CopyResult, Error>
+where
+    SBS: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+    SDS: DirectoryService,
+    SPS: PathInfoService,
+    DBS: AsRef<dyn BlobService> + Clone + Send + Sync + 'static,
+    DDS: DirectoryService,
+    DPS: PathInfoService,
+{
+    let closure = closure(&src_path_info_service, roots).await?;
+
+    let digests: Vec<[u8; 20]> = closure
+        .iter()
+        .map(|path_info| *path_info.store_path.digest())
+        .collect();
+    let missing: Vec<PathInfo> = closure
+        .into_iter()
+        .zip(dst_path_info_service.get_many(&digests).await?)
+        .filter_map(|(path_info, existing)| existing.is_none().then_some(path_info))
+        .collect();
+    debug!(
+        closure = digests.len(),
+        missing = missing.len(),
+        "determined missing store paths"
+    );
+
+    let span = Span::current();
+    span.pb_set_style(&tvix_tracing::PB_PROGRESS_STYLE);
+    span.pb_set_message("Copying store paths");
+    span.pb_set_length(missing.len() as u64);
+    span.pb_start();
+
+    let copier = Copier::new(
+        src_blob_service,
+        src_directory_service,
+        dst_blob_service,
+        dst_directory_service,
+    );
+
+    let castore_results: Vec<CastoreCopyResult> = futures::stream::iter(&missing)
+        .map(|path_info| {
+            let span = span.clone();
+            let copier = &copier;
+            async move {
+                let result = copier.copy(&path_info.node).await?;
+                span.pb_inc(1);
+                Ok::<_, Error>(result)
+            }
+            .instrument(info_span!("copy path", store_path = %path_info.store_path))
+        })
+        .buffer_unordered(PATH_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    let mut result = CopyResult::default();
+    for castore_result in castore_results {
+        result
+            .castore
+            .directories
+            .extend(castore_result.directories);
+        result.castore.blobs.extend(castore_result.blobs);
+    }
+
+    for path_info in sort_references_first(missing) {
+        debug!(store_path=%path_info.store_path, "inserting PathInfo");
+        result.path_infos.push(path_info.store_path.clone());
+        dst_path_info_service.put(path_info).await?;
+    }
+
+    Ok(result)
+}
+
+/// Returns the PathInfos of all store paths in the closure of the given roots.
+/// Unlike when collecting garbage, the closure needs to be complete, so
+/// missing references are an error too.
+pub(crate) async fn closure<PS: PathInfoService>(
+    path_info_service: &PS,
+    roots: &[StorePath<String>],
+) -> Result<Vec<PathInfo>, Error> {
+    let mut seen: HashSet<[u8; 20]> = HashSet::new();
+    let mut path_infos = Vec::new();
+
+    // Look up the closure one level at a time, batching all lookups of a level.
+    let mut queue: Vec<StorePath<String>> = roots
+        .iter()
+        .filter(|root| seen.insert(*root.digest()))
+        .cloned()
+        .collect();
+
+    while !queue.is_empty() {
+        let digests: Vec<[u8; 20]> = queue
+            .iter()
+            .map(|store_path| *store_path.digest())
+            .collect();
+        let mut next_queue = Vec::new();
+
+        for (store_path, path_info) in queue
+            .into_iter()
+            .zip(path_info_service.get_many(&digests).await?)
+        {
+            let path_info = path_info.ok_or_else(|| {
+                Error::InvalidRequest(format!("store path {} not found", store_path))
+            })?;
+
+            next_queue.extend(
+                path_info
+                    .references
+                    .iter()
+                    .filter(|reference| seen.insert(*reference.digest()))
+                    .cloned(),
+            );
+            path_infos.push(path_info);
+        }
+
+        queue = next_queue;
+    }
+
+    Ok(path_infos)
+}
+
+/// Orders the given PathInfos so each of them comes after all of its
+/// references contained in the list.
+pub(crate) fn sort_references_first(path_infos: Vec<PathInfo>) -> Vec<PathInfo> {
+    let mut remaining: HashMap<[u8; 20], PathInfo> = path_infos
+        .into_iter()
+        .map(|path_info| (*path_info.store_path.digest(), path_info))
+        .collect();
+    let mut roots: Vec<[u8; 20]> = remaining.keys().copied().collect();
+    // Sort, so the order doesn't depend on the HashMap.
+    roots.sort();
+
+    let mut sorted = Vec::with_capacity(remaining.len());
+    for root in roots {
+        // Depth-first, emitting a PathInfo once all of its references have
+        // been emitted. The bool tracks whether its references were pushed.
+        let mut stack = vec![(root, false)];
+        while let Some((digest, references_pushed)) = stack.pop() {
+            if references_pushed {
+                if let Some(path_info) = remaining.remove(&digest) {
+                    sorted.push(path_info);
+                }
+                continue;
+            }
+
+            let Some(path_info) = remaining.get(&digest) else {
+                // Already emitted, or not in the list.
+                continue;
+            };
+
+            stack.push((digest, true));
+            stack.extend(
+                path_info
+                    .references
+                    .iter()
+                    .map(|reference| *reference.digest())
+                    // Self-references are allowed.
+                    .filter(|reference| *reference != digest)
+                    .map(|reference| (reference, false)),
+            );
+        }
+    }
+
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rstest::*;
+    use tvix_castore::blobservice::{BlobService, MemoryBlobService};
+    use tvix_castore::directoryservice::{DirectoryService, MemoryDirectoryService};
+    use tvix_castore::fixtures::{DIRECTORY_COMPLICATED, DIRECTORY_WITH_KEEP, EMPTY_BLOB_DIGEST};
+
+    use super::{copy_closure, sort_references_first};
+    use crate::pathinfoservice::{MemoryPathInfoService, PathInfoService};
+    use crate::tests::fixtures::{
+        blob_service_with_contents, directory_service_with_contents, path_info, store_path,
+        CASTORE_NODE_COMPLICATED, CASTORE_NODE_SYMLINK,
+    };
+
+    /// Populates a PathInfoService with three store paths:
+    ///  - `symlink`, referring to itself and `complicated`
+    ///  - `complicated`
+    ///  - `unrelated`
+    async fn path_info_service() -> MemoryPathInfoService {
+        let path_info_service = MemoryPathInfoService::default();
+        for path_info in [
+            path_info(
+                "symlink",
+                1,
+                &CASTORE_NODE_SYMLINK,
+                vec![store_path("symlink", 1), store_path("complicated", 2)],
+            ),
+            path_info("complicated", 2, &CASTORE_NODE_COMPLICATED, vec![]),
+            path_info("unrelated", 3, &CASTORE_NODE_SYMLINK, vec![]),
+        ] {
+            path_info_service.put(path_info).await.unwrap();
+        }
+        path_info_service
+    }
+
+    /// Copying the closure of `symlink` copies it and `complicated`, with its
+    /// directories and blobs, but not `unrelated`.
+    /// Copying again doesn't copy anything.
+    #[rstest]
+    #[tokio::test]
+    async fn copy_closure_missing(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let src_blob_service = blob_service_with_contents.await;
+        let src_directory_service = directory_service_with_contents.await;
+        let src_path_info_service = path_info_service().await;
+
+        let dst_blob_service: Arc<dyn BlobService> = Arc::new(MemoryBlobService::default());
+        let dst_directory_service: Arc<dyn DirectoryService> =
+            Arc::new(MemoryDirectoryService::default());
+        let dst_path_info_service = MemoryPathInfoService::default();
+
+        let result = copy_closure(
+            src_blob_service.clone(),
+            &src_directory_service,
+            &src_path_info_service,
+            dst_blob_service.clone(),
+            &dst_directory_service,
+            &dst_path_info_service,
+            &[store_path("symlink", 1)],
+        )
+        .await
+        .expect("copy must succeed");
+
+        // complicated needs to be inserted first, as symlink refers to it.
+        assert_eq!(
+            vec![store_path("complicated", 2), store_path("symlink", 1)],
+            result.path_infos
+        );
+        assert_eq!(
+            vec![DIRECTORY_WITH_KEEP.digest(), DIRECTORY_COMPLICATED.digest()],
+            result.castore.directories
+        );
+        assert_eq!(vec![EMPTY_BLOB_DIGEST.clone()], result.castore.blobs);
+
+        assert!(dst_path_info_service
+            .get([3; 20])
+            .await
+            .expect("get must succeed")
+            .is_none());
+        assert!(dst_blob_service.has(&EMPTY_BLOB_DIGEST).await.unwrap());
+
+        let result = copy_closure(
+            src_blob_service,
+            &src_directory_service,
+            &src_path_info_service,
+            dst_blob_service,
+            &dst_directory_service,
+            &dst_path_info_service,
+            &[store_path("symlink", 1)],
+        )
+        .await
+        .expect("copy must succeed");
+        assert!(result.path_infos.is_empty());
+        assert!(result.castore.directories.is_empty());
+        assert!(result.castore.blobs.is_empty());
+    }
+
+    /// References missing in the source are an error.
+    #[rstest]
+    #[tokio::test]
+    async fn copy_closure_incomplete(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let src_path_info_service = MemoryPathInfoService::default();
+        src_path_info_service
+            .put(path_info(
+                "symlink",
+                1,
+                &CASTORE_NODE_SYMLINK,
+                vec![store_path("complicated", 2)],
+            ))
+            .await
+            .unwrap();
+
+        copy_closure(
+            blob_service_with_contents.await,
+            directory_service_with_contents.await,
+            src_path_info_service,
+            Arc::new(MemoryBlobService::default()) as Arc<dyn BlobService>,
+            MemoryDirectoryService::default(),
+            MemoryPathInfoService::default(),
+            &[store_path("symlink", 1)],
+        )
+        .await
+        .expect_err("copy must fail");
+    }
+
+    #[test]
+    fn sort_references_first_chain() {
+        let a = path_info("a", 1, &CASTORE_NODE_SYMLINK, vec![store_path("b", 2)]);
+        let b = path_info(
+            "b",
+            2,
+            &CASTORE_NODE_SYMLINK,
+            vec![store_path("b", 2), store_path("c", 3)],
+        );
+        let c = path_info("c", 3, &CASTORE_NODE_SYMLINK, vec![]);
+
+        let sorted: Vec<_> = sort_references_first(vec![a, b, c])
+            .into_iter()
+            .map(|path_info| path_info.store_path)
+            .collect();
+        assert_eq!(
+            vec![store_path("c", 3), store_path("b", 2), store_path("a", 1)],
+            sorted
+        );
+    }
+}
@@ -0,0 +1,427 @@
+//! Importing and exporting store paths in the format of `nix-store --export`,
+//! see [nix_compat::export].
+
+use std::collections::HashSet;
+
+use nix_compat::export::{PathMetadata, Reader, Writer};
+use nix_compat::store_path::StorePath;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{debug, instrument};
+use tvix_castore::blobservice::BlobService;
+use tvix_castore::directoryservice::DirectoryService;
+use tvix_castore::Error;
+
+use crate::copy::{closure, sort_references_first};
+use crate::nar::{ingest_nar, write_nar};
+use crate::path_info::{deriver_from_drv_path, deriver_to_drv_path};
+use crate::pathinfoservice::{PathInfo, PathInfoService};
+
+/// Ingests all store paths contained in the export stream read from `r`,
+/// and returns them in the order they were read.
+///
+/// The stream is expected to contain paths after their references, which is
+/// how `nix-store --export` writes them, so PathInfos are inserted as soon
+/// as their contents are ingested.
+/// A path referring to another path that is neither earlier in the stream
+/// nor already in `path_info_service` is rejected.
+/// Paths already in `path_info_service` are skipped, like Nix does, but
+/// still returned.
+#[instrument(skip_all, err)]
+pub async fn import_paths<R, BS, DS, PS>(
+    blob_service: BS,
+    directory_service: DS,
+    path_info_service: PS,
+    r: R,
+) -> Result<Vec<StorePath<String>>, Error>
+where
+    R: AsyncRead + Unpin + Send,
+    BS: BlobService + Clone + 'static,
+    DS: DirectoryService + Clone,
+    PS: PathInfoService,
+{
+    let mut reader = Reader::new(r);
+    let mut store_paths = Vec::new();
+    // The paths read so far, to look up references.
+    let mut seen = HashSet::new();
+
+    while let Some(nar_reader) = reader
+        .next_path()
+        .await
+        .map_err(|e| Error::InvalidRequest(format!("failed to read export stream: {e}")))?
+    {
+        let node = ingest_nar(blob_service.clone(), directory_service.clone(), nar_reader)
+            .await
+            .map_err(|e| Error::StorageError(format!("failed to ingest NAR: {e}")))?;
+
+        let (metadata, nar_sha256, nar_size) = reader
+            .finish_path()
+            .await
+            .map_err(|e| Error::InvalidRequest(format!("failed to read export stream: {e}")))?;
+
+        let deriver = metadata
+            .deriver
+            .map(|deriver| {
+                deriver_from_drv_path(&deriver).ok_or_else(|| {
+                    Error::InvalidRequest(format!(
+                        "invalid deriver '{}'",
+                        deriver.to_absolute_path()
+                    ))
+                })
+            })
+            .transpose()?;
+
+        // Look up the path itself, and all references not read earlier, at once.
+        let unseen_references: Vec<_> = metadata
+            .references
+            .iter()
+            .filter(|reference| *reference != &metadata.path && !seen.contains(*reference))
+            .collect();
+        let digests: Vec<[u8; 20]> = std::iter::once(&metadata.path)
+            .chain(unseen_references.iter().copied())
+            .map(|store_path| *store_path.digest())
+            .collect();
+        let path_infos = path_info_service.get_many(&digests).await?;
+
+        if let Some((reference, _)) = unseen_references
+            .iter()
+            .zip(&path_infos[1..])
+            .find(|(_, path_info)| path_info.is_none())
+        {
+            return Err(Error::InvalidRequest(format!(
+                "'{}' refers to '{}', which is not valid",
+                metadata.path.to_absolute_path(),
+                reference.to_absolute_path()
+            )));
+        }
+
+        store_paths.push(metadata.path.clone());
+        seen.insert(metadata.path.clone());
+
+        if path_infos[0].is_some() {
+            debug!(store_path=%metadata.path, "already valid, skipping");
+            continue;
+        }
+
+        debug!(store_path=%metadata.path, "inserting PathInfo");
+        path_info_service
+            .put(PathInfo {
+                store_path: metadata.path,
+                node,
+                references: metadata.references,
+                nar_size,
+                nar_sha256,
+                signatures: vec![],
+                deriver,
+                ca: None,
+            })
+            .await?;
+    }
+
+    Ok(store_paths)
+}
+
+/// Writes the closure of the given store paths as an export stream to `w`,
+/// with each path after all of its references, and returns the store paths
+/// in the order they were written.
+#[instrument(skip_all, fields(roots = roots.len()), err)]
+pub async fn export_closure<W, BS, DS, PS>(
+    blob_service: BS,
+    directory_service: DS,
+    path_info_service: PS,
+    roots: &[StorePath<String>],
+    w: W,
+) -> Result<Vec<StorePath<String>>, Error>
+where
+    W: AsyncWrite + Unpin + Send,
+    BS: BlobService + Clone,
+    DS: DirectoryService + Clone,
+    PS: PathInfoService,
+{
+    let path_infos = sort_references_first(closure(&path_info_service, roots).await?);
+
+    let write_err =
+        |e: std::io::Error| Error::StorageError(format!("failed to write export stream: {e}"));
+
+    let mut writer = Writer::new(w);
+    let mut store_paths = Vec::with_capacity(path_infos.len());
+
+    for path_info in path_infos {
+        write_nar(
+            writer.start_path().await.map_err(write_err)?,
+            &path_info.node,
+            blob_service.clone(),
+            directory_service.clone(),
+        )
+        .await
+        .map_err(|e| Error::StorageError(format!("failed to render NAR: {e}")))?;
+
+        let deriver = path_info
+            .deriver
+            .as_ref()
+            .map(deriver_to_drv_path)
+            .transpose()
+            .map_err(|e| Error::StorageError(format!("invalid deriver: {e}")))?;
+
+        writer
+            .finish_path(&PathMetadata {
+                path: path_info.store_path.clone(),
+                references: path_info.references,
+                deriver,
+            })
+            .await
+            .map_err(write_err)?;
+
+        store_paths.push(path_info.store_path);
+    }
+
+    writer.finish().await.map_err(write_err)?;
+
+    Ok(store_paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use nix_compat::export::{PathMetadata, Writer};
+    use nix_compat::store_path::StorePath;
+    use rstest::*;
+    use sha2::{Digest, Sha256};
+    use tvix_castore::blobservice::{BlobService, MemoryBlobService};
+    use tvix_castore::directoryservice::{DirectoryService, MemoryDirectoryService};
+
+    use super::{export_closure, import_paths};
+    use crate::nar::write_nar;
+    use crate::pathinfoservice::{MemoryPathInfoService, PathInfo, PathInfoService};
+    use crate::tests::fixtures::{
+        blob_service_with_contents, directory_service_with_contents, path_info, store_path,
+        CASTORE_NODE_COMPLICATED, CASTORE_NODE_SYMLINK, NAR_CONTENTS_COMPLICATED,
+        NAR_CONTENTS_SYMLINK,
+    };
+
+    /// Returns [path_info], with the NAR size and hash of `nar_contents`.
+    fn path_info_with_nar(
+        name: &str,
+        digest_byte: u8,
+        node: &tvix_castore::Node,
+        nar_contents: &[u8],
+        references: Vec<StorePath<String>>,
+    ) -> PathInfo {
+        PathInfo {
+            nar_size: nar_contents.len() as u64,
+            nar_sha256: Sha256::digest(nar_contents).into(),
+            ..path_info(name, digest_byte, node, references)
+        }
+    }
+
+    /// Exporting the closure of `symlink` writes `complicated` and `symlink`,
+    /// but not `unrelated`. Importing the stream into empty services
+    /// restores the same PathInfos.
+    #[rstest]
+    #[tokio::test]
+    async fn export_import_roundtrip(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let path_infos = [
+            PathInfo {
+                deriver: Some(store_path("symlink", 4)),
+                ..path_info_with_nar(
+                    "symlink",
+                    1,
+                    &CASTORE_NODE_SYMLINK,
+                    &NAR_CONTENTS_SYMLINK,
+                    vec![store_path("symlink", 1), store_path("complicated", 2)],
+                )
+            },
+            path_info_with_nar(
+                "complicated",
+                2,
+                &CASTORE_NODE_COMPLICATED,
+                &NAR_CONTENTS_COMPLICATED,
+                vec![],
+            ),
+            path_info_with_nar(
+                "unrelated",
+                3,
+                &CASTORE_NODE_SYMLINK,
+                &NAR_CONTENTS_SYMLINK,
+                vec![],
+            ),
+        ];
+
+        let src_path_info_service = MemoryPathInfoService::default();
+        for path_info in path_infos.iter().cloned() {
+            src_path_info_service.put(path_info).await.unwrap();
+        }
+
+        let mut buf = Vec::new();
+        let exported = export_closure(
+            blob_service_with_contents.await,
+            directory_service_with_contents.await,
+            &src_path_info_service,
+            &[store_path("symlink", 1)],
+            &mut buf,
+        )
+        .await
+        .expect("export must succeed");
+
+        // complicated needs to come first, as symlink refers to it.
+        assert_eq!(
+            vec![store_path("complicated", 2), store_path("symlink", 1)],
+            exported
+        );
+
+        let dst_blob_service: Arc<dyn BlobService> = Arc::new(MemoryBlobService::default());
+        let dst_directory_service: Arc<dyn DirectoryService> =
+            Arc::new(MemoryDirectoryService::default());
+        let dst_path_info_service = MemoryPathInfoService::default();
+
+        let imported = import_paths(
+            dst_blob_service,
+            dst_directory_service,
+            &dst_path_info_service,
+            &buf[..],
+        )
+        .await
+        .expect("import must succeed");
+        assert_eq!(exported, imported);
+
+        for (digest_byte, expected) in [(1, &path_infos[0]), (2, &path_infos[1])] {
+            let path_info = dst_path_info_service
+                .get([digest_byte; 20])
+                .await
+                .expect("get must succeed")
+                .expect("must exist");
+            assert_eq!(expected, &path_info);
+        }
+        assert!(dst_path_info_service
+            .get([3; 20])
+            .await
+            .expect("get must succeed")
+            .is_none());
+    }
+
+    /// Paths already present are skipped rather than overwritten, but still
+    /// returned, and can be referred to.
+    #[rstest]
+    #[tokio::test]
+    async fn import_skips_valid_paths(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let blob_service = blob_service_with_contents.await;
+        let directory_service = directory_service_with_contents.await;
+
+        let src_path_info_service = MemoryPathInfoService::default();
+        for path_info in [
+            path_info_with_nar(
+                "symlink",
+                1,
+                &CASTORE_NODE_SYMLINK,
+                &NAR_CONTENTS_SYMLINK,
+                vec![store_path("complicated", 2)],
+            ),
+            path_info_with_nar(
+                "complicated",
+                2,
+                &CASTORE_NODE_COMPLICATED,
+                &NAR_CONTENTS_COMPLICATED,
+                vec![],
+            ),
+        ] {
+            src_path_info_service.put(path_info).await.unwrap();
+        }
+
+        let mut buf = Vec::new();
+        export_closure(
+            blob_service.clone(),
+            directory_service.clone(),
+            &src_path_info_service,
+            &[store_path("symlink", 1)],
+            &mut buf,
+        )
+        .await
+        .expect("export must succeed");
+
+        // complicated is already valid, with a deriver the stream doesn't have.
+        let existing = PathInfo {
+            deriver: Some(store_path("complicated", 4)),
+            ..path_info_with_nar(
+                "complicated",
+                2,
+                &CASTORE_NODE_COMPLICATED,
+                &NAR_CONTENTS_COMPLICATED,
+                vec![],
+            )
+        };
+        let dst_path_info_service = MemoryPathInfoService::default();
+        dst_path_info_service.put(existing.clone()).await.unwrap();
+
+        let imported = import_paths(
+            blob_service,
+            directory_service,
+            &dst_path_info_service,
+            &buf[..],
+        )
+        .await
+        .expect("import must succeed");
+        assert_eq!(
+            vec![store_path("complicated", 2), store_path("symlink", 1)],
+            imported
+        );
+
+        assert_eq!(
+            Some(existing),
+            dst_path_info_service.get([2; 20]).await.unwrap()
+        );
+        assert!(dst_path_info_service.get([1; 20]).await.unwrap().is_some());
+    }
+
+    /// Importing a stream with a path before one of its references fails.
+    #[rstest]
+    #[tokio::test]
+    async fn import_missing_reference(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let blob_service = blob_service_with_contents.await;
+        let directory_service = directory_service_with_contents.await;
+
+        // Write only symlink, which refers to complicated.
+        let mut buf = Vec::new();
+        let mut writer = Writer::new(&mut buf);
+        write_nar(
+            writer.start_path().await.unwrap(),
+            &CASTORE_NODE_SYMLINK,
+            blob_service.clone(),
+            directory_service.clone(),
+        )
+        .await
+        .unwrap();
+        writer
+            .finish_path(&PathMetadata {
+                path: store_path("symlink", 1),
+                references: vec![store_path("complicated", 2)],
+                deriver: None,
+            })
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+
+        let dst_path_info_service = MemoryPathInfoService::default();
+        import_paths(
+            blob_service,
+            directory_service,
+            &dst_path_info_service,
+            &buf[..],
+        )
+        .await
+        .expect_err("import must fail");
+        assert!(dst_path_info_service
+            .get([1; 20])
+            .await
+            .expect("get must succeed")
+            .is_none());
+    }
+}
@@ -0,0 +1,290 @@
+//! Garbage collection for a store composed of [BlobService],
+//! [DirectoryService] and [PathInfoService].
+//!
+//! By default, all PathInfos in the [PathInfoService] are roots.
+//! If an explicit list of roots is passed, only their closure (following
+//! [PathInfo::references]) is kept, and all other PathInfos are removed too.
+//!
+//! See [tvix_castore::gc] for the caveats regarding concurrent writers.
+
+use std::collections::HashSet;
+
+use futures::TryStreamExt;
+use nix_compat::store_path::StorePath;
+use tracing::{debug, instrument, warn};
+use tvix_castore::blobservice::BlobService;
+use tvix_castore::directoryservice::DirectoryService;
+use tvix_castore::gc::{GarbageCollector, SweepResult};
+use tvix_castore::Error;
+
+use crate::pathinfoservice::{PathInfo, PathInfoService};
+
+/// Everything that was (or would have been, in case of a dry run) removed.
+#[derive(Debug, Default)]
+pub struct GcResult {
+    pub path_infos: Vec<StorePath<String>>,
+    pub castore: SweepResult,
+}
+
+/// Runs a garbage collection on the given services.
+///
+/// If `roots` is None, all PathInfos are considered roots, and only
+/// directories and blobs not referred to by any of them are removed.
+/// Otherwise, the closure of the given store paths is kept, and all other
+/// PathInfos, directories and blobs are removed.
+///
+/// If `dry_run` is set, nothing is removed, but the result still contains
+/// everything that would have been.
+#[instrument(skip_all, fields(dry_run = dry_run), err)]
+pub async fn collect_garbage<BS, DS, PS>(
+    blob_service: BS,
+    directory_service: DS,
+    path_info_service: PS,
+    roots: Option<&[StorePath<String>]>,
+    dry_run: bool,
+) -> Result<GcResult, Error>
+where
+    BS: BlobService,
+    DS: DirectoryService,
+    PS: PathInfoService,
+{
+    let all_path_infos: Vec<PathInfo> = path_info_service.list().try_collect().await?;
+
+    let (live, dead): (Vec<PathInfo>, Vec<PathInfo>) = match roots {
+        None => (all_path_infos, vec![]),
+        Some(roots) => {
+            let live_digests = closure(&path_info_service, roots).await?;
+            all_path_infos
+                .into_iter()
+                .partition(|path_info| live_digests.contains(path_info.store_path.digest()))
+        }
+    };
+
+    let mut gc = GarbageCollector::new(blob_service, directory_service);
+    for path_info in &live {
+        gc.mark(&path_info.node).await?;
+    }
+
+    // Remove PathInfos first, so an interrupted run doesn't leave PathInfos
+    // pointing to missing directories or blobs behind.
+    if !dry_run {
+        for path_info in &dead {
+            debug!(store_path=%path_info.store_path, "removing PathInfo");
+            path_info_service
+                .delete(*path_info.store_path.digest())
+                .await?;
+        }
+    }
+
+    Ok(GcResult {
+        path_infos: dead.into_iter().map(|p| p.store_path).collect(),
+        castore: gc.sweep(dry_run).await?,
+    })
+}
+
+/// Returns the digests of all store paths in the closure of the given roots.
+/// Roots missing from the [PathInfoService] are an error, missing references
+/// are logged and skipped.
+async fn closure<PS: PathInfoService>(
+    path_info_service: &PS,
+    roots: &[StorePath<String>],
+) -> Result<HashSet<[u8; 20]>, Error> {
+    let mut seen = HashSet::new();
+    // Store paths still to visit, alongside the store path referring to them
+    // (or None for roots).
+    let mut queue: Vec<(StorePath<String>, Option<StorePath<String>>)> =
+        roots.iter().map(|root| (root.clone(), None)).collect();
+
+    while let Some((store_path, referrer)) = queue.pop() {
+        if !seen.insert(*store_path.digest()) {
+            continue;
+        }
+
+        match path_info_service.get(*store_path.digest()).await? {
+            Some(path_info) => queue.extend(
+                path_info
+                    .references
+                    .into_iter()
+                    .map(|reference| (reference, Some(store_path.clone()))),
+            ),
+            None => match referrer {
+                None => {
+                    return Err(Error::InvalidRequest(format!(
+                        "root {} not found",
+                        store_path
+                    )))
+                }
+                Some(referrer) => {
+                    warn!(store_path=%store_path, referrer=%referrer, "reference not found");
+                }
+            },
+        }
+    }
+
+    Ok(seen)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rstest::*;
+    use tvix_castore::blobservice::BlobService;
+    use tvix_castore::directoryservice::DirectoryService;
+    use tvix_castore::fixtures::{
+        DIRECTORY_COMPLICATED, DIRECTORY_WITH_KEEP, EMPTY_BLOB_DIGEST, HELLOWORLD_BLOB_DIGEST,
+    };
+    use tvix_castore::Error;
+
+    use super::collect_garbage;
+    use crate::pathinfoservice::{MemoryPathInfoService, PathInfoService};
+    use crate::tests::fixtures::{
+        blob_service_with_contents, directory_service_with_contents, path_info, store_path,
+        CASTORE_NODE_COMPLICATED, CASTORE_NODE_HELLOWORLD, CASTORE_NODE_SYMLINK,
+    };
+
+    /// Populates a PathInfoService with three store paths:
+    ///  - `helloworld`, referring to `complicated`
+    ///  - `complicated`
+    ///  - `symlink`
+    async fn path_info_service() -> MemoryPathInfoService {
+        let path_info_service = MemoryPathInfoService::default();
+        for path_info in [
+            path_info(
+                "helloworld",
+                1,
+                &CASTORE_NODE_HELLOWORLD,
+                vec![store_path("complicated", 2)],
+            ),
+            path_info("complicated", 2, &CASTORE_NODE_COMPLICATED, vec![]),
+            path_info("symlink", 3, &CASTORE_NODE_SYMLINK, vec![]),
+        ] {
+            path_info_service.put(path_info).await.unwrap();
+        }
+        path_info_service
+    }
+
+    /// Without explicit roots, everything is kept.
+    #[rstest]
+    #[tokio::test]
+    async fn all_path_infos_are_roots(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let result = collect_garbage(
+            blob_service_with_contents.await,
+            directory_service_with_contents.await,
+            path_info_service().await,
+            None,
+            false,
+        )
+        .await
+        .expect("gc must succeed");
+
+        assert!(result.path_infos.is_empty());
+        assert!(result.castore.directories.is_empty());
+        assert!(result.castore.blobs.is_empty());
+    }
+
+    /// Only the closure of the given roots is kept.
+    #[rstest]
+    #[tokio::test]
+    async fn closure_of_roots(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let (blob_service, directory_service) = (
+            blob_service_with_contents.await,
+            directory_service_with_contents.await,
+        );
+        let path_info_service = path_info_service().await;
+
+        // Keeping helloworld also keeps complicated, so only symlink is removed.
+        let result = collect_garbage(
+            &blob_service,
+            &directory_service,
+            &path_info_service,
+            Some(&[store_path("helloworld", 1)]),
+            false,
+        )
+        .await
+        .expect("gc must succeed");
+
+        assert_eq!(vec![store_path("symlink", 3)], result.path_infos);
+        assert!(result.castore.directories.is_empty());
+        assert!(result.castore.blobs.is_empty());
+
+        // Keeping symlink removes everything else, including all directories
+        // and blobs.
+        let path_info_service = self::path_info_service().await;
+        let result = collect_garbage(
+            &blob_service,
+            &directory_service,
+            &path_info_service,
+            Some(&[store_path("symlink", 3)]),
+            false,
+        )
+        .await
+        .expect("gc must succeed");
+
+        assert_eq!(2, result.path_infos.len());
+        assert_eq!(2, result.castore.directories.len());
+        assert_eq!(2, result.castore.blobs.len());
+
+        assert!(path_info_service.get([1; 20]).await.unwrap().is_none());
+        assert!(path_info_service.get([3; 20]).await.unwrap().is_some());
+        for digest in [DIRECTORY_COMPLICATED.digest(), DIRECTORY_WITH_KEEP.digest()] {
+            assert!(directory_service.get(&digest).await.unwrap().is_none());
+        }
+        for digest in [&*EMPTY_BLOB_DIGEST, &*HELLOWORLD_BLOB_DIGEST] {
+            assert!(!blob_service.has(digest).await.unwrap());
+        }
+    }
+
+    /// A dry run doesn't remove anything.
+    #[rstest]
+    #[tokio::test]
+    async fn dry_run(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let (blob_service, directory_service) = (
+            blob_service_with_contents.await,
+            directory_service_with_contents.await,
+        );
+        let path_info_service = path_info_service().await;
+
+        let result = collect_garbage(
+            &blob_service,
+            &directory_service,
+            &path_info_service,
+            Some(&[store_path("symlink", 3)]),
+            true,
+        )
+        .await
+        .expect("gc must succeed");
+
+        assert_eq!(2, result.path_infos.len());
+        assert!(path_info_service.get([1; 20]).await.unwrap().is_some());
+        assert!(blob_service.has(&HELLOWORLD_BLOB_DIGEST).await.unwrap());
+    }
+
+    /// Roots that don't exist are rejected.
+    #[rstest]
+    #[tokio::test]
+    async fn missing_root(
+        #[future] blob_service_with_contents: Arc<dyn BlobService>,
+        #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    ) {
+        let result = collect_garbage(
+            blob_service_with_contents.await,
+            directory_service_with_contents.await,
+            path_info_service().await,
+            Some(&[store_path("missing", 4)]),
+            false,
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::InvalidRequest(_))));
+    }
+}
@@ -0,0 +1,312 @@
+//! Calculates git object ids of castore nodes, used by the `git` file
+//! ingestion method.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use nix_compat::git::{self, EntryMode, TreeEntry};
+use sha1::Digest;
+use tokio::io::AsyncReadExt;
+use tracing::instrument;
+use tvix_castore::import::fs::{ingest_path_with_hook, FileHook};
+use tvix_castore::{blobservice::BlobService, directoryservice::DirectoryService, B3Digest, Node};
+
+use crate::nar::RenderError;
+
+/// Returns the git object id of the passed root node, using the passed hash
+/// function (sha1 or sha256).
+///
+/// For directories, this is the hash of the git tree object, for files and
+/// symlinks the one of the blob object.
+/// Directories are looked up in the passed directory service, and file
+/// contents are read from the passed blob service.
+#[instrument(skip_all)]
+pub async fn calculate_git_hash<D, BS, DS>(
+    root_node: &Node,
+    blob_service: BS,
+    directory_service: DS,
+) -> Result<Vec<u8>, RenderError>
+where
+    D: Digest + Send,
+    BS: BlobService + Send,
+    DS: DirectoryService + Send,
+{
+    let (digest, _, _) = hash_node::<D, _, _>(
+        root_node,
+        b"",
+        &HashMap::new(),
+        blob_service,
+        directory_service,
+    )
+    .await?;
+    Ok(digest)
+}
+
+/// A [FileHook] calculating the git blob ids of files while they're
+/// uploaded, by the digest of their blob.
+struct BlobIds<D> {
+    blob_ids: Mutex<HashMap<B3Digest, Vec<u8>>>,
+    _hash: PhantomData<fn() -> D>,
+}
+
+impl<D> Default for BlobIds<D> {
+    fn default() -> Self {
+        Self {
+            blob_ids: Default::default(),
+            _hash: PhantomData,
+        }
+    }
+}
+
+impl<D: Digest + Send> FileHook for BlobIds<D> {
+    /// The hash function, along with the expected and read file sizes.
+    type State = (D, u64, u64);
+
+    fn start(&self, size: u64) -> Self::State {
+        // The size is part of the header, so it needs to be known upfront.
+        let mut h = D::new();
+        h.update(git::blob_header(size));
+        (h, size, 0)
+    }
+
+    fn update(&self, (h, _, read): &mut Self::State, data: &[u8]) {
+        h.update(data);
+        *read += data.len() as u64;
+    }
+
+    fn finish(&self, (h, size, read): Self::State, digest: &B3Digest) -> std::io::Result<()> {
+        if read != size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "file changed while reading it",
+            ));
+        }
+
+        self.blob_ids
+            .lock()
+            .expect("poisoned")
+            .insert(digest.clone(), h.finalize().to_vec());
+        Ok(())
+    }
+}
+
+/// Ingests the contents at the given path into the castore, like
+/// [tvix_castore::import::fs::ingest_path], and returns the root node along
+/// with its git object id.
+///
+/// The git object ids of files are calculated while uploading them, so their
+/// contents are only read once.
+#[instrument(skip(blob_service, directory_service), err)]
+pub async fn ingest_path<D, BS, DS>(
+    blob_service: BS,
+    directory_service: DS,
+    path: &std::path::Path,
+) -> Result<(Node, Vec<u8>), std::io::Error>
+where
+    D: Digest + Send,
+    BS: BlobService + Clone,
+    DS: DirectoryService + Clone,
+{
+    let hook = BlobIds::<D>::default();
+    let root_node = ingest_path_with_hook::<_, _, _, &[u8], _>(
+        blob_service.clone(),
+        directory_service.clone(),
+        path,
+        None,
+        &hook,
+    )
+    .await
+    .map_err(std::io::Error::other)?;
+
+    let blob_ids = hook.blob_ids.into_inner().expect("poisoned");
+    let (digest, _, _) =
+        hash_node::<D, _, _>(&root_node, b"", &blob_ids, blob_service, directory_service)
+            .await
+            .map_err(std::io::Error::other)?;
+
+    Ok((root_node, digest))
+}
+
+/// Process an intermediate node in the structure, returning its object id,
+/// and the blob and directory service back to the caller.
+/// Files whose object id is in `blob_ids` aren't read.
+async fn hash_node<D, BS, DS>(
+    node: &Node,
+    name: &[u8],
+    blob_ids: &HashMap<B3Digest, Vec<u8>>,
+    blob_service: BS,
+    directory_service: DS,
+) -> Result<(Vec<u8>, BS, DS), RenderError>
+where
+    D: Digest + Send,
+    BS: BlobService + Send,
+    DS: DirectoryService + Send,
+{
+    let digest = match node {
+        Node::Symlink { target } => git::hash_blob::<D>(target.as_ref()),
+        Node::File { digest, .. } if blob_ids.contains_key(digest) => blob_ids[digest].clone(),
+        Node::File { digest, size, .. } => {
+            let mut blob_reader = blob_service
+                .open_read(digest)
+                .await
+                .map_err(RenderError::StoreError)?
+                .ok_or_else(|| {
+                    RenderError::BlobNotFound(digest.clone(), bytes::Bytes::copy_from_slice(name))
+                })?;
+
+            let mut h = D::new();
+            h.update(git::blob_header(*size));
+
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let n = blob_reader
+                    .read(&mut buf)
+                    .await
+                    .map_err(RenderError::StoreError)?;
+                if n == 0 {
+                    break;
+                }
+                h.update(&buf[..n]);
+            }
+
+            h.finalize().to_vec()
+        }
+        Node::Directory { digest, .. } => {
+            let directory = directory_service
+                .get(digest)
+                .await
+                .map_err(|e| RenderError::StoreError(e.into()))?
+                .ok_or_else(|| {
+                    RenderError::DirectoryNotFound(
+                        digest.clone(),
+                        bytes::Bytes::copy_from_slice(name),
+                    )
+                })?;
+
+            // We put blob_service, directory_service back here whenever we come up from
+            // the recursion.
+            let mut blob_service = blob_service;
+            let mut directory_service = directory_service;
+
+            let mut entries = Vec::new();
+            for (name, node) in directory.nodes() {
+                let digest;
+                (digest, blob_service, directory_service) = Box::pin(hash_node::<D, _, _>(
+                    node,
+                    name.as_ref(),
+                    blob_ids,
+                    blob_service,
+                    directory_service,
+                ))
+                .await?;
+
+                entries.push(TreeEntry {
+                    mode: match node {
+                        Node::Directory { .. } => EntryMode::Directory,
+                        Node::File {
+                            executable: true, ..
+                        } => EntryMode::Executable,
+                        Node::File { .. } => EntryMode::Regular,
+                        Node::Symlink { .. } => EntryMode::Symlink,
+                    },
+                    name: name.as_ref().to_vec(),
+                    digest,
+                });
+            }
+
+            return Ok((
+                git::hash_tree::<D>(entries),
+                blob_service,
+                directory_service,
+            ));
+        }
+    };
+
+    Ok((digest, blob_service, directory_service))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::Arc;
+
+    use data_encoding::HEXLOWER;
+    use rstest::rstest;
+    use sha1::Sha1;
+    use tvix_castore::blobservice::BlobService;
+    use tvix_castore::directoryservice::DirectoryService;
+
+    use super::{calculate_git_hash, ingest_path};
+    use crate::nar::ingest_nar;
+    use crate::tests::fixtures::{
+        blob_service, directory_service, NAR_CONTENTS_COMPLICATED, NAR_CONTENTS_HELLOWORLD,
+        NAR_CONTENTS_SYMLINK,
+    };
+
+    /// Ingests the NARs, and compares their git hash with the one git
+    /// calculates for the same contents (`git hash-object`, `git write-tree`).
+    #[rstest]
+    #[case::symlink(&NAR_CONTENTS_SYMLINK, "acdff1fcd71d5034e07ad738d885b1b703d079e6")]
+    #[case::helloworld(&NAR_CONTENTS_HELLOWORLD, "c57eff55ebc0c54973903af5f72bac72762cf4f4")]
+    #[case::complicated(&NAR_CONTENTS_COMPLICATED, "6a6ec11559ff59fd2fc117bdb44d405edd428681")]
+    #[tokio::test]
+    async fn git_hash(
+        blob_service: Arc<dyn BlobService>,
+        directory_service: Arc<dyn DirectoryService>,
+        #[case] nar_contents: &[u8],
+        #[case] expected: &str,
+    ) {
+        let root_node = ingest_nar(
+            blob_service.clone(),
+            directory_service.clone(),
+            &mut Cursor::new(nar_contents),
+        )
+        .await
+        .expect("must parse");
+
+        let digest = calculate_git_hash::<Sha1, _, _>(&root_node, blob_service, directory_service)
+            .await
+            .expect("must succeed");
+        assert_eq!(expected, HEXLOWER.encode(&digest));
+    }
+
+    /// Ingests a directory from the filesystem, and compares its git hash with
+    /// the one `git write-tree` calculates for it, and the one calculated
+    /// from the castore nodes afterwards.
+    #[rstest]
+    #[tokio::test]
+    async fn git_hash_ingest_path(
+        blob_service: Arc<dyn BlobService>,
+        directory_service: Arc<dyn DirectoryService>,
+    ) {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let root = tmpdir.path().join("test");
+        std::fs::create_dir_all(root.join("b")).unwrap();
+        std::fs::write(root.join("a"), "Hello World!").unwrap();
+        std::fs::write(root.join("b/c"), "").unwrap();
+        std::fs::set_permissions(root.join("b/c"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("../a", root.join("b/d")).unwrap();
+
+        for (path, expected) in [
+            (root.join("a"), "c57eff55ebc0c54973903af5f72bac72762cf4f4"),
+            (root, "30151a92e8cb97ed8f163d094b5aa91416a19bac"),
+        ] {
+            let (root_node, digest) =
+                ingest_path::<Sha1, _, _>(blob_service.clone(), directory_service.clone(), &path)
+                    .await
+                    .expect("must succeed");
+            assert_eq!(expected, HEXLOWER.encode(&digest));
+
+            let digest = calculate_git_hash::<Sha1, _, _>(
+                &root_node,
+                blob_service.clone(),
+                directory_service.clone(),
+            )
+            .await
+            .expect("must succeed");
+            assert_eq!(expected, HEXLOWER.encode(&digest));
+        }
+    }
+}
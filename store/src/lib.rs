@@ -1,9 +1,16 @@
+pub mod buildlogservice;
 pub mod composition;
+pub mod copy;
+pub mod export;
+pub mod gc;
+pub mod git;
 pub mod import;
 pub mod nar;
+pub mod nix_daemon;
 pub mod path_info;
 pub mod pathinfoservice;
 pub mod proto;
+pub mod realisationservice;
 pub mod utils;
 
 #[cfg(test)]
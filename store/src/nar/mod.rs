@@ -2,13 +2,17 @@ use tonic::async_trait;
 use tvix_castore::B3Digest;
 
 mod import;
+mod prefetching;
 mod renderer;
 pub mod seekable;
 pub use import::ingest_nar;
 pub use import::ingest_nar_and_hash;
+pub use prefetching::write_nar_prefetching;
+pub use prefetching::{PrefetchingRenderer, PrefetchingRendererConfig};
 pub use renderer::calculate_size_and_sha256;
 pub use renderer::write_nar;
-pub use renderer::SimpleRenderer;
+pub use renderer::{SimpleRenderer, SimpleRendererConfig};
+use tvix_castore::composition::{Registry, ServiceBuilder};
 use tvix_castore::Node;
 
 #[async_trait]
@@ -32,6 +36,12 @@ where
     }
 }
 
+/// Registers the builtin NarCalculationService implementations with the registry
+pub(crate) fn register_nar_calculation_services(reg: &mut Registry) {
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn NarCalculationService>>, SimpleRendererConfig>("simple");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn NarCalculationService>>, PrefetchingRendererConfig>("prefetching");
+}
+
 /// Errors that can encounter while rendering NARs.
 #[derive(Debug, thiserror::Error)]
 pub enum RenderError {
@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use count_write::CountWrite;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use nix_compat::nar::writer::r#async as nar_writer;
+use sha2::{Digest, Sha256};
+use tokio::io::{self, AsyncBufRead, AsyncReadExt, AsyncSeekExt, AsyncWrite};
+use tokio_util::io::StreamReader;
+use tonic::async_trait;
+use tracing::instrument;
+use tvix_castore::blobservice::BlobService;
+use tvix_castore::composition::{CompositionContext, ServiceBuilder};
+use tvix_castore::directoryservice::{DirectoryGraph, DirectoryService, RootToLeavesValidator};
+use tvix_castore::{B3Digest, Directory, Node};
+use url::Url;
+
+use super::{NarCalculationService, RenderError};
+use crate::utils::AsyncIoBridge;
+
+/// A NAR renderer which knows the order of all blobs in the NAR ahead of time,
+/// and fetches up to `prefetch_window` upcoming blobs (or chunks of blobs, if
+/// the [BlobService] exposes more granular chunking info) concurrently,
+/// while still writing the NAR contents in order.
+/// Blobs and chunks larger than [MAX_FETCH_SIZE] are fetched in multiple
+/// ranges, so at most `prefetch_window` times that many bytes are buffered.
+///
+/// This avoids paying a round trip for every blob when rendering (or hashing)
+/// NARs against remote stores.
+pub struct PrefetchingRenderer<BS, DS> {
+    blob_service: BS,
+    directory_service: DS,
+    prefetch_window: usize,
+}
+
+impl<BS, DS> PrefetchingRenderer<BS, DS> {
+    pub fn new(blob_service: BS, directory_service: DS, prefetch_window: usize) -> Self {
+        Self {
+            blob_service,
+            directory_service,
+            prefetch_window,
+        }
+    }
+}
+
+#[async_trait]
+impl<BS, DS> NarCalculationService for PrefetchingRenderer<BS, DS>
+where
+    BS: BlobService + Clone + 'static,
+    DS: DirectoryService + Clone,
+{
+    async fn calculate_nar(
+        &self,
+        root_node: &Node,
+    ) -> Result<(u64, [u8; 32]), tvix_castore::Error> {
+        let mut h = Sha256::new();
+        let mut cw = CountWrite::from(&mut h);
+
+        write_nar_prefetching(
+            // The hasher doesn't speak async. It doesn't
+            // actually do any I/O, so it's fine to wrap.
+            AsyncIoBridge(&mut cw),
+            root_node,
+            self.blob_service.clone(),
+            self.directory_service.clone(),
+            self.prefetch_window,
+        )
+        .await
+        .map_err(|e| tvix_castore::Error::StorageError(format!("failed rendering nar: {}", e)))?;
+
+        Ok((cw.count(), h.finalize().into()))
+    }
+}
+
+/// Like [super::write_nar], but fetches the directory closure upfront, and
+/// prefetches up to `prefetch_window` blobs or chunks concurrently.
+#[instrument(skip_all, fields(prefetch_window = prefetch_window), err)]
+pub async fn write_nar_prefetching<W, BS, DS>(
+    mut w: W,
+    root_node: &Node,
+    blob_service: BS,
+    directory_service: DS,
+    prefetch_window: usize,
+) -> Result<(), RenderError>
+where
+    W: AsyncWrite + Unpin + Send,
+    BS: BlobService + 'static,
+    DS: DirectoryService,
+{
+    let directories = fetch_directories(root_node, &directory_service).await?;
+
+    // Collect all blobs, in the order they appear in the NAR.
+    let mut blobs = Vec::new();
+    collect_blobs(root_node, &directories, &mut blobs);
+
+    // All file contents, concatenated in NAR order.
+    // Each file node only consumes its own size from it.
+    let mut contents = StreamReader::new(prefetch_blobs(blob_service, blobs, prefetch_window));
+
+    let nar_root_node = nar_writer::open(&mut w)
+        .await
+        .map_err(RenderError::NARWriterError)?;
+
+    walk_node(nar_root_node, root_node, b"", &directories, &mut contents).await
+}
+
+/// Fetches all directories below the root node (if it's a directory at all).
+async fn fetch_directories<DS: DirectoryService>(
+    root_node: &Node,
+    directory_service: &DS,
+) -> Result<HashMap<B3Digest, Directory>, RenderError> {
+    let Node::Directory { digest, .. } = root_node else {
+        return Ok(HashMap::new());
+    };
+
+    let mut closure =
+        DirectoryGraph::with_order(RootToLeavesValidator::new_with_root_digest(digest.clone()));
+    let mut stream = directory_service.get_recursive(digest);
+    while let Some(dir) = stream
+        .try_next()
+        .await
+        .map_err(|e| RenderError::StoreError(e.into()))?
+    {
+        closure.add(dir).map_err(|e| {
+            RenderError::StoreError(tvix_castore::Error::StorageError(e.to_string()).into())
+        })?;
+    }
+    let closure = closure.validate().map_err(|e| {
+        RenderError::StoreError(tvix_castore::Error::StorageError(e.to_string()).into())
+    })?;
+
+    Ok(closure
+        .drain_root_to_leaves()
+        .map(|directory| (directory.digest(), directory))
+        .collect())
+}
+
+/// Recursively walks the node, and appends the digest and size of each file to
+/// `blobs`, in NAR order.
+fn collect_blobs(
+    node: &Node,
+    directories: &HashMap<B3Digest, Directory>,
+    blobs: &mut Vec<(B3Digest, u64)>,
+) {
+    match node {
+        Node::Symlink { .. } => {}
+        Node::File { digest, size, .. } => blobs.push((digest.clone(), *size)),
+        Node::Directory { digest, .. } => {
+            // DirectoryGraph ensures the closure is complete.
+            let directory = directories.get(digest).expect("missing directory");
+            for (_name, node) in directory.nodes() {
+                collect_blobs(node, directories, blobs);
+            }
+        }
+    }
+}
+
+/// The maximum number of bytes fetched into memory by a single request.
+/// Larger blobs (or chunks) are split into multiple ranges.
+const MAX_FETCH_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A range of a blob (or chunk) to fetch.
+struct FetchRange {
+    digest: B3Digest,
+    offset: u64,
+    len: u64,
+    /// Whether this is the last range of the blob (or chunk), after which
+    /// there must not be any more data.
+    last: bool,
+}
+
+/// Splits a blob (or chunk) of the given size into ranges of at most
+/// [MAX_FETCH_SIZE] bytes.
+fn fetch_ranges(digest: B3Digest, size: u64) -> impl Iterator<Item = FetchRange> {
+    (0..size.div_ceil(MAX_FETCH_SIZE)).map(move |i| {
+        let offset = i * MAX_FETCH_SIZE;
+        let len = MAX_FETCH_SIZE.min(size - offset);
+        FetchRange {
+            digest: digest.clone(),
+            offset,
+            len,
+            last: offset + len == size,
+        }
+    })
+}
+
+/// Returns a stream of the contents of all passed blobs, concatenated, in order.
+///
+/// The list of chunks of each blob is requested, and the blobs (or their
+/// chunks, if there's more granular chunking info) are fetched in ranges of
+/// at most [MAX_FETCH_SIZE] bytes, with up to `prefetch_window` requests of
+/// each kind in flight.
+fn prefetch_blobs<BS>(
+    blob_service: BS,
+    blobs: Vec<(B3Digest, u64)>,
+    prefetch_window: usize,
+) -> impl Stream<Item = io::Result<Bytes>> + Send + Unpin
+where
+    BS: BlobService + 'static,
+{
+    let blob_service = Arc::new(blob_service);
+    let prefetch_window = prefetch_window.max(1);
+
+    let chunks = stream::iter(blobs)
+        .map({
+            let blob_service = blob_service.clone();
+            move |(digest, size)| {
+                let blob_service = blob_service.clone();
+                async move { blob_chunks(blob_service.as_ref(), digest, size).await }
+            }
+        })
+        .buffered(prefetch_window)
+        .map_ok(|chunks| {
+            stream::iter(
+                chunks
+                    .into_iter()
+                    .flat_map(|(digest, size)| fetch_ranges(digest, size))
+                    .map(io::Result::Ok),
+            )
+        })
+        .try_flatten();
+
+    Box::pin(
+        chunks
+            .map(move |range| {
+                let blob_service = blob_service.clone();
+                async move { fetch_range(blob_service.as_ref(), range?).await }
+            })
+            .buffered(prefetch_window),
+    )
+}
+
+/// Returns the list of chunks (digest and size) to fetch for a blob.
+/// If the [BlobService] has no more granular chunking info, this is the blob
+/// itself.
+async fn blob_chunks<BS: BlobService>(
+    blob_service: &BS,
+    digest: B3Digest,
+    size: u64,
+) -> io::Result<Vec<(B3Digest, u64)>> {
+    // Nothing to fetch for empty files.
+    if size == 0 {
+        return Ok(vec![]);
+    }
+
+    let chunks = blob_service.chunks(&digest).await?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("blob with digest {} not found", &digest),
+        )
+    })?;
+
+    if chunks.is_empty() {
+        return Ok(vec![(digest, size)]);
+    }
+
+    let chunks = chunks
+        .into_iter()
+        .map(|chunk| {
+            let chunk_digest = B3Digest::try_from(chunk.digest)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Ok((chunk_digest, chunk.size))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    check_size(chunks.iter().map(|(_, size)| size).sum(), size)?;
+
+    Ok(chunks)
+}
+
+/// Fetches a range of a chunk (or blob) into memory, and ensures it has the
+/// expected size.
+async fn fetch_range<BS: BlobService>(blob_service: &BS, range: FetchRange) -> io::Result<Bytes> {
+    let mut reader = blob_service
+        .open_read(&range.digest)
+        .await?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("blob with digest {} not found", &range.digest),
+            )
+        })?;
+
+    if range.offset != 0 {
+        reader.seek(io::SeekFrom::Start(range.offset)).await?;
+    }
+
+    // For the last range, try to read one more byte, to detect blobs being
+    // larger than expected.
+    let limit = if range.last { range.len + 1 } else { range.len };
+
+    // range.len is bounded by MAX_FETCH_SIZE.
+    let mut buf = Vec::with_capacity(range.len as usize);
+    reader.take(limit).read_to_end(&mut buf).await?;
+
+    check_size(buf.len() as u64, range.len)?;
+
+    Ok(buf.into())
+}
+
+/// Ensures the actual size of some data matches what the [Node] claimed.
+/// This produces the same errors the NAR writer would when being passed a
+/// reader returning too little or too much data.
+fn check_size(actual: u64, expected: u64) -> io::Result<()> {
+    if actual < expected {
+        Err(io::ErrorKind::UnexpectedEof.into())
+    } else if actual > expected {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "reader contained more data than specified size",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Process an intermediate node in the structure.
+/// File contents are consumed from `contents`, which must yield them in NAR
+/// order.
+async fn walk_node<R>(
+    nar_node: nar_writer::Node<'_, '_>,
+    castore_node: &Node,
+    name: &[u8],
+    directories: &HashMap<B3Digest, Directory>,
+    contents: &mut R,
+) -> Result<(), RenderError>
+where
+    R: AsyncBufRead + Unpin + Send,
+{
+    match castore_node {
+        Node::Symlink { target, .. } => {
+            nar_node
+                .symlink(target.as_ref())
+                .await
+                .map_err(RenderError::NARWriterError)?;
+        }
+        Node::File {
+            size, executable, ..
+        } => {
+            nar_node
+                .file(*executable, *size, &mut (&mut *contents).take(*size))
+                .await
+                .map_err(RenderError::NARWriterError)?;
+        }
+        Node::Directory { digest, .. } => {
+            let directory = directories.get(digest).ok_or_else(|| {
+                RenderError::DirectoryNotFound(digest.clone(), bytes::Bytes::copy_from_slice(name))
+            })?;
+
+            // start a directory node
+            let mut nar_node_directory = nar_node
+                .directory()
+                .await
+                .map_err(RenderError::NARWriterError)?;
+
+            // for each node in the directory, create a new entry with its name,
+            // and then recurse on that entry.
+            for (name, node) in directory.nodes() {
+                let child_node = nar_node_directory
+                    .entry(name.as_ref())
+                    .await
+                    .map_err(RenderError::NARWriterError)?;
+
+                Box::pin(walk_node(
+                    child_node,
+                    node,
+                    name.as_ref(),
+                    directories,
+                    contents,
+                ))
+                .await?;
+            }
+
+            // close the directory
+            nar_node_directory
+                .close()
+                .await
+                .map_err(RenderError::NARWriterError)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn default_prefetch_window() -> usize {
+    16
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PrefetchingRendererConfig {
+    #[serde(default = "default_blob_service")]
+    blob_service: String,
+    #[serde(default = "default_directory_service")]
+    directory_service: String,
+    /// Number of blobs (or chunks, or ranges of them) to fetch concurrently.
+    #[serde(default = "default_prefetch_window")]
+    prefetch_window: usize,
+}
+
+fn default_blob_service() -> String {
+    "default".to_string()
+}
+
+fn default_directory_service() -> String {
+    "default".to_string()
+}
+
+impl TryFrom<Url> for PrefetchingRendererConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    /// Parses `prefetching://?prefetch_window=32`, optionally also containing
+    /// `blob_service` and `directory_service` query pairs.
+    fn try_from(url: Url) -> Result<Self, Self::Error> {
+        // prefetching doesn't support host or path in the URL.
+        if url.has_host() || !url.path().is_empty() {
+            return Err(tvix_castore::Error::StorageError("invalid url".to_string()).into());
+        }
+
+        let mut config = PrefetchingRendererConfig {
+            blob_service: default_blob_service(),
+            directory_service: default_directory_service(),
+            prefetch_window: default_prefetch_window(),
+        };
+        for (k, v) in url.query_pairs() {
+            match k.as_ref() {
+                "blob_service" => config.blob_service = v.to_string(),
+                "directory_service" => config.directory_service = v.to_string(),
+                "prefetch_window" => config.prefetch_window = v.parse()?,
+                _ => {
+                    return Err(tvix_castore::Error::StorageError(format!(
+                        "unknown query parameter: {}",
+                        k
+                    ))
+                    .into())
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for PrefetchingRendererConfig {
+    type Output = dyn NarCalculationService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        context: &CompositionContext,
+    ) -> Result<Arc<dyn NarCalculationService>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        let (blob_service, directory_service) = futures::join!(
+            context.resolve::<dyn BlobService>(self.blob_service.clone()),
+            context.resolve::<dyn DirectoryService>(self.directory_service.clone())
+        );
+        Ok(Arc::new(PrefetchingRenderer::new(
+            blob_service?,
+            directory_service?,
+            self.prefetch_window,
+        )))
+    }
+}
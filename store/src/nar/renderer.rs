@@ -4,10 +4,13 @@ use super::{NarCalculationService, RenderError};
 use count_write::CountWrite;
 use nix_compat::nar::writer::r#async as nar_writer;
 use sha2::{Digest, Sha256};
+use std::sync::Arc;
 use tokio::io::{self, AsyncWrite, BufReader};
 use tonic::async_trait;
 use tracing::instrument;
+use tvix_castore::composition::{CompositionContext, ServiceBuilder};
 use tvix_castore::{blobservice::BlobService, directoryservice::DirectoryService, Node};
+use url::Url;
 
 pub struct SimpleRenderer<BS, DS> {
     blob_service: BS,
@@ -43,6 +46,43 @@ where
     }
 }
 
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SimpleRendererConfig {
+    blob_service: String,
+    directory_service: String,
+}
+
+impl TryFrom<Url> for SimpleRendererConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(_url: Url) -> Result<Self, Self::Error> {
+        Err(tvix_castore::Error::StorageError(
+            "Instantiating a SimpleRenderer from a url is not supported".into(),
+        )
+        .into())
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for SimpleRendererConfig {
+    type Output = dyn NarCalculationService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        context: &CompositionContext,
+    ) -> Result<Arc<dyn NarCalculationService>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        let (blob_service, directory_service) = futures::join!(
+            context.resolve::<dyn BlobService>(self.blob_service.clone()),
+            context.resolve::<dyn DirectoryService>(self.directory_service.clone())
+        );
+        Ok(Arc::new(SimpleRenderer::new(
+            blob_service?,
+            directory_service?,
+        )))
+    }
+}
+
 /// Invoke [write_nar], and return the size and sha256 digest of the produced
 /// NAR output.
 #[instrument(skip_all)]
@@ -0,0 +1,308 @@
+//! Forwards tracing spans and events to Nix daemon clients.
+//!
+//! While an operation is handled, spans become activities and events become
+//! log lines on the stderr channel of the client connection, so `nix` can
+//! display them as progress.
+//!
+//! Spans with an `activity_type` field, naming one of Nix's [ActivityType]s
+//! like `activity_type = "CopyPath"`, become activities of that type. Their
+//! `message` field is used as the text of the activity, and their other
+//! fields are sent in order, as the fields Nix expects for that type.
+//! Other spans become activities of the Unknown type, described by their
+//! name and fields.
+//!
+//! The `done` and `expected` fields of a span are not sent as fields, but
+//! as the Progress of its activity, whenever they are recorded.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use nix_compat::nix_daemon::logger::{
+    Activity, ActivityResult, ActivityType, LogMessage, LoggerField, ResultType,
+};
+use nix_compat::worker_protocol::Verbosity;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+tokio::task_local! {
+    /// The client of the operation currently handled by the task.
+    static CLIENT: ClientLogger;
+}
+
+/// Receives the log messages for a single client connection.
+#[derive(Clone)]
+pub(crate) struct ClientLogger {
+    tx: UnboundedSender<LogMessage>,
+    verbosity: Arc<AtomicU64>,
+}
+
+impl ClientLogger {
+    pub(crate) fn new(tx: UnboundedSender<LogMessage>) -> Self {
+        Self {
+            tx,
+            verbosity: Arc::new(AtomicU64::new(Verbosity::LvlInfo as u64)),
+        }
+    }
+
+    /// Runs the passed future with logs being forwarded to this client.
+    pub(crate) async fn scope<F: std::future::Future>(&self, f: F) -> F::Output {
+        CLIENT.scope(self.clone(), f).await
+    }
+
+    /// Sends a message to the client of the current task.
+    pub(crate) fn send(msg: LogMessage) {
+        let _ = CLIENT.try_with(|client| client.tx.send(msg));
+    }
+
+    /// Updates the verbosity requested by the client of the current task.
+    pub(crate) fn set_verbosity(verbosity: Verbosity) {
+        let _ =
+            CLIENT.try_with(|client| client.verbosity.store(verbosity as u64, Ordering::Relaxed));
+    }
+
+    fn enabled(&self, level: &Level) -> bool {
+        to_verbosity(level) as u64 <= self.verbosity.load(Ordering::Relaxed)
+    }
+}
+
+/// The activity id of a span, and where to report its progress and it being
+/// stopped.
+struct ActivityId {
+    id: u64,
+    tx: UnboundedSender<LogMessage>,
+    progress: Progress,
+}
+
+/// The `done` and `expected` fields of a span.
+#[derive(Default, Clone, Copy, PartialEq)]
+struct Progress {
+    done: u64,
+    expected: u64,
+}
+
+impl Visit for Progress {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "done" => self.done = value,
+            "expected" => self.expected = value,
+            _ => {}
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if let Ok(value) = value.try_into() {
+            self.record_u64(field, value)
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// A [Layer] sending spans and events to the client whose operation is
+/// handled by the current task, if any.
+#[derive(Default)]
+pub struct NixDaemonLayer {
+    next_id: AtomicU64,
+}
+
+impl<S> Layer<S> for NixDaemonLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let client = match CLIENT.try_with(Clone::clone) {
+            Ok(client) => client,
+            Err(_) => return,
+        };
+        let metadata = attrs.metadata();
+        if !client.enabled(metadata.level()) {
+            return;
+        }
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+
+        // Use the closest parent which is an activity.
+        let parent = span
+            .scope()
+            .skip(1)
+            .find_map(|parent| parent.extensions().get::<ActivityId>().map(|a| a.id))
+            .unwrap_or(0);
+
+        let mut fields = ActivityFields::default();
+        attrs.record(&mut fields);
+        let (activity_type, text, fields) = match fields.activity_type {
+            Some(activity_type) => (
+                activity_type,
+                fields.text.unwrap_or_else(|| metadata.name().to_string()),
+                fields.fields,
+            ),
+            None => {
+                let mut text = String::from(metadata.name());
+                attrs.record(&mut FieldFormatter(&mut text));
+                (ActivityType::Unknown, text, vec![])
+            }
+        };
+
+        let activity_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let _ = client.tx.send(LogMessage::StartActivity(Activity {
+            id: activity_id,
+            level: to_verbosity(metadata.level()),
+            activity_type,
+            text,
+            fields,
+            parent,
+        }));
+
+        let mut progress = Progress::default();
+        attrs.record(&mut progress);
+        if progress != Progress::default() {
+            send_progress(&client.tx, activity_id, progress);
+        }
+
+        span.extensions_mut().insert(ActivityId {
+            id: activity_id,
+            tx: client.tx,
+            progress,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(activity) = span.extensions_mut().get_mut::<ActivityId>() {
+                let mut progress = activity.progress;
+                values.record(&mut progress);
+                if progress != activity.progress {
+                    activity.progress = progress;
+                    send_progress(&activity.tx, activity.id, progress);
+                }
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(activity) = span.extensions().get::<ActivityId>() {
+                let _ = activity.tx.send(LogMessage::StopActivity(activity.id));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let _ = CLIENT.try_with(|client| {
+            if !client.enabled(event.metadata().level()) {
+                return;
+            }
+
+            let mut msg = String::new();
+            event.record(&mut FieldFormatter(&mut msg));
+            msg.push('\n');
+            let _ = client.tx.send(LogMessage::Next(msg));
+        });
+    }
+}
+
+/// Sends the Progress result of an activity.
+fn send_progress(tx: &UnboundedSender<LogMessage>, id: u64, progress: Progress) {
+    let _ = tx.send(LogMessage::Result(ActivityResult {
+        id,
+        result_type: ResultType::Progress,
+        // done, expected, running and failed
+        fields: vec![
+            LoggerField::Int(progress.done),
+            LoggerField::Int(progress.expected),
+            LoggerField::Int(0),
+            LoggerField::Int(0),
+        ],
+    }));
+}
+
+/// Collects the type, text and fields of an activity from the fields of a
+/// span.
+#[derive(Default)]
+struct ActivityFields {
+    activity_type: Option<ActivityType>,
+    text: Option<String>,
+    fields: Vec<LoggerField>,
+}
+
+impl Visit for ActivityFields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "done" | "expected" => {}
+            _ => self.fields.push(LoggerField::Int(value)),
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        match value.try_into() {
+            Ok(value) => self.record_u64(field, value),
+            Err(_) => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "done" | "expected" => {}
+            "activity_type" => self.activity_type = parse_activity_type(value),
+            "message" => self.text = Some(value.to_string()),
+            _ => self.fields.push(LoggerField::String(value.to_string())),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"))
+    }
+}
+
+/// Parses the name of an [ActivityType], as used in the `activity_type`
+/// field of spans.
+fn parse_activity_type(name: &str) -> Option<ActivityType> {
+    Some(match name {
+        "CopyPath" => ActivityType::CopyPath,
+        "FileTransfer" => ActivityType::FileTransfer,
+        "Realise" => ActivityType::Realise,
+        "CopyPaths" => ActivityType::CopyPaths,
+        "Builds" => ActivityType::Builds,
+        "Build" => ActivityType::Build,
+        "OptimiseStore" => ActivityType::OptimiseStore,
+        "VerifyPaths" => ActivityType::VerifyPaths,
+        "Substitute" => ActivityType::Substitute,
+        "QueryPathInfo" => ActivityType::QueryPathInfo,
+        "PostBuildHook" => ActivityType::PostBuildHook,
+        "BuildWaiting" => ActivityType::BuildWaiting,
+        "FetchTree" => ActivityType::FetchTree,
+        _ => return None,
+    })
+}
+
+/// Formats the message of an event or span, followed by its other fields.
+struct FieldFormatter<'a>(&'a mut String);
+
+impl Visit for FieldFormatter<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let sep = if self.0.is_empty() { "" } else { " " };
+        let _ = if field.name() == "message" {
+            write!(self.0, "{sep}{value:?}")
+        } else {
+            write!(self.0, "{sep}{}={value:?}", field.name())
+        };
+    }
+}
+
+fn to_verbosity(level: &Level) -> Verbosity {
+    match *level {
+        Level::ERROR => Verbosity::LvlError,
+        Level::WARN => Verbosity::LvlWarn,
+        Level::INFO => Verbosity::LvlInfo,
+        Level::DEBUG => Verbosity::LvlDebug,
+        Level::TRACE => Verbosity::LvlVomit,
+    }
+}
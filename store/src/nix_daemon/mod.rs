@@ -0,0 +1,796 @@
+//! Serves the Nix daemon worker protocol, backed by tvix-store.
+//!
+//! This allows pointing unmodified Nix clients (via `--store unix:///path`,
+//! or `NIX_DAEMON_SOCKET_PATH`) at a tvix-store. Only the operations needed
+//! to query and copy store paths are supported, building is not.
+//!
+//! Like in Nix, clients are trusted based on their uid. Paths added by
+//! untrusted clients need to be signed by one of the configured public keys,
+//! or be content-addressed.
+//!
+//! While an operation is handled, spans and events are forwarded to the
+//! client, if [NixDaemonLayer] is registered with the tracing subscriber.
+
+use std::io;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{future, TryStreamExt};
+use nix_compat::nix_daemon::de::{NixRead, NixReader};
+use nix_compat::nix_daemon::framing::NixFramedReader;
+use nix_compat::nix_daemon::logger::{LogMessage, NixError};
+use nix_compat::nix_daemon::ser::{NixWrite, NixWriter};
+use nix_compat::nix_daemon::{UnkeyedValidPathInfo, ValidPathInfo};
+use nix_compat::nixhash::NixHash;
+use nix_compat::store_path::{build_ca_path, StorePath};
+use nix_compat::worker_protocol::{self, Operation, Trust};
+use nix_compat::ProtocolVersion;
+use nix_compat::{narinfo, nixbase32};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, BufWriter};
+use tokio::net::UnixListener;
+use tokio::sync::mpsc;
+use tokio_util::io::{InspectReader, StreamReader};
+use tracing::{debug, info_span, instrument, warn, Instrument, Span};
+use tvix_castore::blobservice::BlobService;
+use tvix_castore::directoryservice::DirectoryService;
+use tvix_castore::Node;
+
+use crate::nar::{ingest_nar_and_hash, write_nar};
+use crate::path_info::{deriver_from_drv_path, deriver_to_drv_path};
+use crate::pathinfoservice::{PathInfo, PathInfoService};
+
+mod logger;
+use logger::ClientLogger;
+pub use logger::NixDaemonLayer;
+
+/// The Nix version reported to clients during the handshake.
+const NIX_VERSION: &str = "2.3.17";
+
+/// How much data to request at once from clients sending data on request.
+const TUNNEL_CHUNK_SIZE: u64 = 32 * 1024;
+
+/// How often the progress of NAR uploads is reported to the client, in bytes.
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// Errors occuring while handling a single operation.
+#[derive(Debug, thiserror::Error)]
+enum OpError {
+    /// The operation failed. This is reported to the client, which can
+    /// continue to send other operations on the same connection.
+    #[error("{0}")]
+    Store(String),
+    /// The operation is not supported, and its arguments can't be skipped
+    /// over. This is reported to the client, and the connection closed.
+    #[error("{0}")]
+    Unsupported(String),
+    /// Reading from or writing to the client failed, the connection can't be
+    /// used anymore.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The result of a successful operation, sent to the client after its log.
+enum Reply {
+    Empty,
+    Bool(bool),
+    StorePath(Option<StorePath<String>>),
+    StorePaths(Vec<StorePath<String>>),
+    /// The [UnkeyedValidPathInfo] of a path, or None if it's not valid.
+    PathInfo(Option<UnkeyedValidPathInfo>),
+    /// The NAR serialization of the node.
+    Nar(Node),
+}
+
+/// Serves the Nix daemon worker protocol from the passed services.
+#[derive(Clone)]
+pub struct NixDaemon {
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+    path_info_service: Arc<dyn PathInfoService>,
+    /// The uids of trusted users, see [Self::set_trusted_users].
+    trusted_users: Arc<Vec<u32>>,
+    /// The keys accepted in signatures of added paths.
+    public_keys: Arc<Vec<narinfo::VerifyingKey>>,
+}
+
+impl NixDaemon {
+    pub fn new(
+        blob_service: Arc<dyn BlobService>,
+        directory_service: Arc<dyn DirectoryService>,
+        path_info_service: Arc<dyn PathInfoService>,
+    ) -> Self {
+        Self {
+            blob_service,
+            directory_service,
+            path_info_service,
+            trusted_users: Arc::new(vec![0]),
+            public_keys: Arc::new(vec![]),
+        }
+    }
+
+    /// Sets the uids of the users whose connections are trusted by
+    /// [Self::serve]. Only root is trusted by default.
+    pub fn set_trusted_users(&mut self, uids: Vec<u32>) {
+        self.trusted_users = Arc::new(uids);
+    }
+
+    /// Sets the keys accepted in signatures of paths added by untrusted
+    /// clients, or by trusted clients not disabling signature checks.
+    /// There are none by default, so only content-addressed paths can be
+    /// added by untrusted clients.
+    pub fn set_public_keys(&mut self, public_keys: Vec<narinfo::VerifyingKey>) {
+        self.public_keys = Arc::new(public_keys);
+    }
+
+    /// Accepts connections on the passed listener, and serves each of them in
+    /// a separate task.
+    ///
+    /// Clients are trusted if their uid is one of [Self::set_trusted_users].
+    pub async fn serve(self, listener: UnixListener) -> io::Result<()> {
+        loop {
+            let (conn, _addr) = listener.accept().await?;
+            let trust = match conn.peer_cred() {
+                Ok(cred) if self.trusted_users.contains(&cred.uid()) => Trust::Trusted,
+                Ok(_) => Trust::NotTrusted,
+                Err(e) => {
+                    warn!(err=%e, "failed to get peer credentials");
+                    Trust::NotTrusted
+                }
+            };
+            let daemon = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = daemon.handle_connection(conn, trust).await {
+                    warn!(err=%e, "failed to handle connection");
+                }
+            });
+        }
+    }
+
+    /// Serves a single client connection, until the client closes it.
+    /// Untrusted clients can't disable signature checks.
+    #[instrument(skip(self, conn))]
+    pub async fn handle_connection<C>(&self, mut conn: C, trust: Trust) -> io::Result<()>
+    where
+        C: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let trusted = trust == Trust::Trusted;
+        let client_version =
+            worker_protocol::server_handshake_client(&mut conn, NIX_VERSION, trust).await?;
+
+        // Talk the highest version supported by both sides.
+        let version = std::cmp::min(client_version, ProtocolVersion::default());
+        debug!(%client_version, %version, trusted, "client connected");
+
+        let (r, w) = tokio::io::split(conn);
+        let mut reader = NixReader::builder().set_version(version).build(r);
+        let mut writer = NixWriter::builder()
+            .set_version(version)
+            .build(BufWriter::new(w));
+
+        // The handshake is concluded by the (empty) log of the daemon startup.
+        writer.write_value(&LogMessage::Last).await?;
+        writer.flush().await?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let logger = ClientLogger::new(tx);
+
+        loop {
+            let op = match worker_protocol::read_op(&mut reader).await {
+                Ok(op) => op,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    debug!("client closed connection");
+                    return Ok(());
+                }
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    // An unknown operation, whose arguments can't be skipped.
+                    writer
+                        .write_value(&LogMessage::Error(NixError::new(e.to_string())))
+                        .await?;
+                    writer.flush().await?;
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            };
+
+            // Forward log messages while the operation is handled.
+            let result = {
+                let op_future = logger.scope(self.handle_op(&op, &mut reader, trusted));
+                tokio::pin!(op_future);
+                loop {
+                    tokio::select! {
+                        result = &mut op_future => break result,
+                        Some(msg) = rx.recv() => {
+                            writer.write_value(&msg).await?;
+                            writer.flush().await?;
+                        }
+                    }
+                }
+            };
+            while let Ok(msg) = rx.try_recv() {
+                writer.write_value(&msg).await?;
+            }
+
+            match result {
+                Ok(reply) => {
+                    writer.write_value(&LogMessage::Last).await?;
+                    self.write_reply(reply, &mut writer).await?;
+                }
+                Err(OpError::Store(msg)) => {
+                    warn!(?op, err = %msg, "operation failed");
+                    writer
+                        .write_value(&LogMessage::Error(NixError::new(msg)))
+                        .await?;
+                }
+                Err(OpError::Unsupported(msg)) => {
+                    warn!(?op, "unsupported operation");
+                    writer
+                        .write_value(&LogMessage::Error(NixError::new(msg.clone())))
+                        .await?;
+                    writer.flush().await?;
+                    return Err(io::Error::new(io::ErrorKind::Unsupported, msg));
+                }
+                Err(OpError::Io(e)) => return Err(e),
+            }
+
+            writer.flush().await?;
+        }
+    }
+
+    /// Reads the arguments of the operation and processes it.
+    /// The reply is written by [Self::write_reply], after the log.
+    #[instrument(skip(self, reader))]
+    async fn handle_op<R>(
+        &self,
+        op: &Operation,
+        reader: &mut NixReader<R>,
+        trusted: bool,
+    ) -> Result<Reply, OpError>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        if op.min_version() > reader.version() {
+            return Err(OpError::Unsupported(format!(
+                "{op:?} is not supported for protocol version {}",
+                reader.version()
+            )));
+        }
+
+        Ok(match op {
+            Operation::IsValidPath => {
+                let path: StorePath<String> = reader.read_value().await?;
+                Reply::Bool(self.get_path_info(&path).await?.is_some())
+            }
+            Operation::QueryValidPaths => {
+                let paths: Vec<StorePath<String>> = reader.read_value().await?;
+                if reader.version().minor() >= 27 {
+                    // We never substitute.
+                    let _substitute: bool = reader.read_value().await?;
+                }
+
+                let digests: Vec<[u8; 20]> = paths.iter().map(|path| *path.digest()).collect();
+                let path_infos = self
+                    .path_info_service
+                    .get_many(&digests)
+                    .await
+                    .map_err(|e| OpError::Store(e.to_string()))?;
+
+                let valid_paths: Vec<StorePath<String>> = paths
+                    .into_iter()
+                    .zip(path_infos)
+                    .filter(|(path, path_info)| {
+                        matches!(path_info, Some(path_info) if &path_info.store_path == path)
+                    })
+                    .map(|(path, _)| path)
+                    .collect();
+
+                Reply::StorePaths(valid_paths)
+            }
+            Operation::QueryAllValidPaths => {
+                let paths: Vec<StorePath<String>> = self
+                    .path_info_service
+                    .list()
+                    .map_ok(|path_info| path_info.store_path)
+                    .try_collect()
+                    .await
+                    .map_err(|e| OpError::Store(e.to_string()))?;
+
+                Reply::StorePaths(paths)
+            }
+            Operation::QueryPathInfo => {
+                let path: StorePath<String> = reader.read_value().await?;
+                let path_info = self.get_path_info(&path).await?;
+
+                // Older clients don't expect a flag, but an error for
+                // invalid paths.
+                if path_info.is_none() && reader.version().minor() < 17 {
+                    return Err(OpError::Store(format!(
+                        "path '{}' is not valid",
+                        path.to_absolute_path()
+                    )));
+                }
+
+                Reply::PathInfo(path_info.map(to_unkeyed_valid_path_info).transpose()?)
+            }
+            Operation::QueryPathFromHashPart => {
+                let hash_part: String = reader.read_value().await?;
+                let path_info = match nixbase32::decode_fixed(&hash_part) {
+                    Ok(digest) => self
+                        .path_info_service
+                        .get(digest)
+                        .await
+                        .map_err(|e| OpError::Store(e.to_string()))?,
+                    Err(_) => None,
+                };
+
+                Reply::StorePath(path_info.map(|path_info| path_info.store_path))
+            }
+            Operation::QueryReferrers => {
+                let path: StorePath<String> = reader.read_value().await?;
+                let referrers = self
+                    .path_info_service
+                    .list_referrers(*path.digest())
+                    .try_filter(|path_info| future::ready(path_info.references.contains(&path)))
+                    .map_ok(|path_info| path_info.store_path)
+                    .try_collect()
+                    .await
+                    .map_err(|e| OpError::Store(e.to_string()))?;
+
+                Reply::StorePaths(referrers)
+            }
+            Operation::QueryValidDerivers => {
+                let path: StorePath<String> = reader.read_value().await?;
+
+                // Only the deriver recorded in the PathInfo is known. Like
+                // Nix, it's only returned if the .drv file is valid.
+                let mut derivers = vec![];
+                if let Some(deriver) = self
+                    .get_path_info(&path)
+                    .await?
+                    .and_then(|path_info| path_info.deriver)
+                {
+                    let drv_path = deriver_to_drv_path(&deriver)
+                        .map_err(|e| OpError::Store(format!("invalid deriver: {e}")))?;
+                    if self.get_path_info(&drv_path).await?.is_some() {
+                        derivers.push(drv_path);
+                    }
+                }
+
+                Reply::StorePaths(derivers)
+            }
+            Operation::NarFromPath => {
+                let path: StorePath<String> = reader.read_value().await?;
+                let path_info = self.get_path_info(&path).await?.ok_or_else(|| {
+                    OpError::Store(format!("path '{}' is not valid", path.to_absolute_path()))
+                })?;
+
+                Reply::Nar(path_info.node)
+            }
+            Operation::SetOptions => {
+                let version = reader.version();
+                let settings = worker_protocol::read_client_settings(reader, version).await?;
+                debug!(?settings, "received client settings");
+                ClientLogger::set_verbosity(settings.verbosity);
+
+                Reply::Empty
+            }
+            Operation::AddToStoreNar => {
+                let path: StorePath<String> = reader.read_value().await?;
+                let info: UnkeyedValidPathInfo = reader.read_value().await?;
+                let _repair: bool = reader.read_value().await?;
+                let dont_check_sigs: bool = reader.read_value().await?;
+                let check_sigs = !(trusted && dont_check_sigs);
+
+                let result = if reader.version().minor() >= 23 {
+                    let mut framed = NixFramedReader::new(&mut *reader);
+                    let result = self.ingest(path, info, &mut framed).await;
+
+                    // Consume the remainder of the NAR in any case, so the
+                    // next operation can be read.
+                    tokio::io::copy(&mut framed, &mut tokio::io::sink()).await?;
+                    result
+                } else if reader.version().minor() >= 21 {
+                    // The client only sends data when asked for it, so
+                    // there's nothing to skip over if ingesting fails.
+                    let nar_size = info.nar_size;
+                    self.ingest(path, info, &mut tunnel_reader(reader, nar_size))
+                        .await
+                } else {
+                    // The NAR is sent unframed, but we know its size.
+                    let mut nar = (&mut *reader).take(info.nar_size);
+                    let result = self.ingest(path, info, &mut nar).await;
+                    tokio::io::copy(&mut nar, &mut tokio::io::sink()).await?;
+                    result
+                };
+
+                self.put_path_info(result?, check_sigs).await?;
+
+                Reply::Empty
+            }
+            Operation::AddMultipleToStore => {
+                let _repair: bool = reader.read_value().await?;
+                let dont_check_sigs: bool = reader.read_value().await?;
+                let check_sigs = !(trusted && dont_check_sigs);
+
+                let version = reader.version();
+                let mut framed = NixFramedReader::new(&mut *reader);
+                let result = self.ingest_multiple(version, &mut framed, check_sigs).await;
+
+                // Consume the remainder of the stream in any case, so the
+                // next operation can be read.
+                tokio::io::copy(&mut framed, &mut tokio::io::sink()).await?;
+
+                result?;
+
+                Reply::Empty
+            }
+            _ => {
+                let msg = format!("{op:?} is not supported by tvix");
+                let Some(args) = unsupported_op_args(op, reader.version()) else {
+                    return Err(OpError::Unsupported(msg));
+                };
+
+                // Skip over the arguments, so the client can continue to
+                // send other operations after the error.
+                skip_args(reader, args).await?;
+                return Err(OpError::Store(msg));
+            }
+        })
+    }
+
+    /// Sends the reply of a successful operation.
+    async fn write_reply<W>(&self, reply: Reply, writer: &mut NixWriter<W>) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        match reply {
+            Reply::Empty => {}
+            Reply::Bool(value) => writer.write_value(&value).await?,
+            Reply::StorePath(path) => writer.write_value(&path).await?,
+            Reply::StorePaths(paths) => writer.write_value(&paths).await?,
+            Reply::PathInfo(Some(info)) => {
+                if writer.version().minor() >= 17 {
+                    writer.write_value(&true).await?;
+                }
+                writer.write_value(&info).await?;
+            }
+            Reply::PathInfo(None) => writer.write_value(&false).await?,
+            Reply::Nar(node) => {
+                // The NAR is sent as-is, without any framing.
+                // If rendering fails halfway, the client can't recover, so
+                // the connection is closed.
+                write_nar(
+                    writer.get_mut(),
+                    &node,
+                    self.blob_service.clone(),
+                    self.directory_service.clone(),
+                )
+                .await
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up the [PathInfo] of the passed store path.
+    /// PathInfos for other names with the same digest are ignored.
+    async fn get_path_info(&self, path: &StorePath<String>) -> Result<Option<PathInfo>, OpError> {
+        Ok(self
+            .path_info_service
+            .get(*path.digest())
+            .await
+            .map_err(|e| OpError::Store(e.to_string()))?
+            .filter(|path_info| &path_info.store_path == path))
+    }
+
+    /// Stores the [PathInfo] of an added path. If `check_sigs` is set, it
+    /// must be content-addressed, or signed by one of [Self::public_keys].
+    async fn put_path_info(&self, path_info: PathInfo, check_sigs: bool) -> Result<(), OpError> {
+        if check_sigs && !is_content_addressed(&path_info) {
+            let narinfo = path_info.to_narinfo();
+            let fingerprint = narinfo.fingerprint();
+
+            if !self.public_keys.iter().any(|pubkey| {
+                narinfo
+                    .signatures
+                    .iter()
+                    .any(|sig| pubkey.verify(&fingerprint, sig))
+            }) {
+                return Err(OpError::Store(format!(
+                    "cannot add path '{}' because it lacks a signature by a trusted key",
+                    path_info.store_path.to_absolute_path()
+                )));
+            }
+        }
+
+        self.path_info_service
+            .put(path_info)
+            .await
+            .map_err(|e| OpError::Store(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Ingests the NAR read from r, and returns the [PathInfo] of the passed
+    /// path, after verifying NAR size and hash.
+    async fn ingest<R>(
+        &self,
+        path: StorePath<String>,
+        info: UnkeyedValidPathInfo,
+        r: &mut R,
+    ) -> Result<PathInfo, OpError>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let expected_nar_sha256 = match info.nar_hash {
+            NixHash::Sha256(digest) => digest,
+            nar_hash => {
+                return Err(OpError::Store(format!(
+                    "unsupported NAR hash algo {}",
+                    nar_hash.algo()
+                )))
+            }
+        };
+
+        // Reported to the client like the CopyPath activities of Nix, with
+        // the store path, source and destination as fields.
+        let span = info_span!(
+            "copy_path",
+            activity_type = "CopyPath",
+            message = %format!("copying path '{}' to tvix-store", path.to_absolute_path()),
+            path = %path.to_absolute_path(),
+            from = "client",
+            to = "tvix-store",
+            done = 0u64,
+            expected = info.nar_size,
+        );
+        let mut done = 0u64;
+        let mut r = InspectReader::new(r, |buf: &[u8]| {
+            let previous = done;
+            done += buf.len() as u64;
+            if previous / PROGRESS_INTERVAL != done / PROGRESS_INTERVAL {
+                span.record("done", done);
+            }
+        });
+
+        let (node, nar_sha256, nar_size) = ingest_nar_and_hash(
+            self.blob_service.clone(),
+            self.directory_service.clone(),
+            &mut r,
+        )
+        .instrument(span.clone())
+        .await
+        .map_err(|e| OpError::Store(format!("failed to ingest NAR: {e}")))?;
+        span.record("done", nar_size);
+
+        if nar_size != info.nar_size {
+            return Err(OpError::Store(format!(
+                "NAR size mismatch for '{}': expected {}, got {}",
+                path.to_absolute_path(),
+                info.nar_size,
+                nar_size
+            )));
+        }
+        if nar_sha256 != expected_nar_sha256 {
+            return Err(OpError::Store(format!(
+                "NAR hash mismatch for '{}': expected {}, got {}",
+                path.to_absolute_path(),
+                NixHash::Sha256(expected_nar_sha256).to_nix_hex_string(),
+                NixHash::Sha256(nar_sha256).to_nix_hex_string(),
+            )));
+        }
+
+        let deriver = info
+            .deriver
+            .map(|deriver| {
+                deriver_from_drv_path(&deriver).ok_or_else(|| {
+                    OpError::Store(format!("invalid deriver '{}'", deriver.to_absolute_path()))
+                })
+            })
+            .transpose()?;
+
+        Ok(PathInfo {
+            store_path: path,
+            node,
+            references: info.references,
+            nar_size,
+            nar_sha256,
+            signatures: info.signatures,
+            deriver,
+            ca: info.ca,
+        })
+    }
+
+    /// Ingests the paths sent in an `AddMultipleToStore` operation.
+    /// Each of them is sent as its [ValidPathInfo], followed by its NAR.
+    async fn ingest_multiple<R>(
+        &self,
+        version: ProtocolVersion,
+        r: R,
+        check_sigs: bool,
+    ) -> Result<(), OpError>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let mut reader = NixReader::builder().set_version(version).build(r);
+
+        let count: u64 = reader.read_value().await?;
+        let span = info_span!(
+            "copy_paths",
+            activity_type = "CopyPaths",
+            message = %format!("copying {count} paths to tvix-store"),
+            done = 0u64,
+            expected = count,
+        );
+
+        async {
+            for done in 1..=count {
+                let ValidPathInfo { path, info } = reader.read_value().await?;
+                let nar_size = info.nar_size;
+
+                let path_info = self
+                    .ingest(path, info, &mut (&mut reader).take(nar_size))
+                    .await?;
+                self.put_path_info(path_info, check_sigs).await?;
+                Span::current().record("done", done);
+            }
+
+            Ok(())
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+/// Reads `len` bytes the client sends in response to [LogMessage::Read]
+/// requests, which are sent as more data is needed.
+/// This is how clients before protocol version 1.23 send NARs, starting with
+/// 1.21.
+fn tunnel_reader<R>(reader: &mut NixReader<R>, len: u64) -> impl AsyncRead + Unpin + Send + '_
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let chunks: BoxStream<'_, io::Result<Bytes>> = Box::pin(async_stream::try_stream! {
+        let mut remaining = len;
+        while remaining > 0 {
+            ClientLogger::send(LogMessage::Read(remaining.min(TUNNEL_CHUNK_SIZE)));
+            let chunk: Bytes = reader.read_value().await?;
+            if chunk.is_empty() || chunk.len() as u64 > remaining {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "client sent unexpected amount of data",
+                ))?;
+            }
+            remaining -= chunk.len() as u64;
+            yield chunk;
+        }
+    });
+
+    StreamReader::new(chunks)
+}
+
+/// Checks whether the store path of the [PathInfo] is the one computed from
+/// its content address and references, so it doesn't need to be signed.
+fn is_content_addressed(path_info: &PathInfo) -> bool {
+    let Some(ca) = &path_info.ca else {
+        return false;
+    };
+
+    let self_reference = path_info.references.contains(&path_info.store_path);
+    let mut references: Vec<String> = path_info
+        .references
+        .iter()
+        .filter(|reference| *reference != &path_info.store_path)
+        .map(StorePath::to_absolute_path)
+        .collect();
+    references.sort();
+
+    build_ca_path::<_, String, _>(path_info.store_path.name(), ca, references, self_reference)
+        .is_ok_and(|store_path| store_path == path_info.store_path)
+}
+
+/// How an argument of an unsupported operation is encoded, to skip over it.
+#[derive(Clone, Copy)]
+enum Arg {
+    /// A number, or a bool.
+    Number,
+    /// A string, like a store path.
+    String,
+    /// A list of strings, like a set of store paths.
+    Strings,
+    /// A list of pairs of strings, like a map from store paths to content
+    /// addresses.
+    StringPairs,
+}
+
+/// Returns the arguments of an operation tvix doesn't support, or None if
+/// they can't be skipped over, as they contain data or derivations.
+fn unsupported_op_args(op: &Operation, version: ProtocolVersion) -> Option<&'static [Arg]> {
+    use Arg::*;
+
+    Some(match op {
+        Operation::SyncWithGC
+        | Operation::FindRoots
+        | Operation::QueryFailedPaths
+        | Operation::OptimiseStore => &[],
+        Operation::HasSubstitutes
+        | Operation::QueryPathHash
+        | Operation::QueryReferences
+        | Operation::EnsurePath
+        | Operation::AddTempRoot
+        | Operation::AddIndirectRoot
+        | Operation::QueryDeriver
+        | Operation::QuerySubstitutablePathInfo
+        | Operation::QueryDerivationOutputs
+        | Operation::QueryDerivationOutputNames
+        | Operation::QueryDerivationOutputMap
+        | Operation::QueryRealisation => &[String],
+        Operation::ExportPath => &[String, Number],
+        Operation::AddPermRoot => &[String, String],
+        Operation::AddSignatures => &[String, Strings],
+        Operation::AddTextToStore => &[String, String, Strings],
+        Operation::RegisterDrvOutput if version.minor() >= 31 => &[String],
+        Operation::RegisterDrvOutput => &[String, String],
+        Operation::ClearFailedPaths
+        | Operation::QuerySubstitutablePaths
+        | Operation::QueryMissing => &[Strings],
+        Operation::BuildPaths if version.minor() < 15 => &[Strings],
+        Operation::BuildPaths | Operation::BuildPathsWithResults => &[Strings, Number],
+        Operation::QuerySubstitutablePathInfos if version.minor() < 22 => &[Strings],
+        Operation::QuerySubstitutablePathInfos => &[StringPairs],
+        Operation::VerifyStore => &[Number, Number],
+        Operation::CollectGarbage => &[Number, Strings, Number, Number, Number, Number, Number],
+        _ => return None,
+    })
+}
+
+/// Reads and discards the passed arguments.
+async fn skip_args<R>(reader: &mut NixReader<R>, args: &[Arg]) -> io::Result<()>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    for arg in args {
+        match arg {
+            Arg::Number => {
+                reader.read_number().await?;
+            }
+            Arg::String => {
+                reader.read_value::<Bytes>().await?;
+            }
+            Arg::Strings => {
+                reader.read_value::<Vec<Bytes>>().await?;
+            }
+            Arg::StringPairs => {
+                for _ in 0..reader.read_number().await? {
+                    reader.read_value::<Bytes>().await?;
+                    reader.read_value::<Bytes>().await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts a [PathInfo] into the metadata sent to clients.
+fn to_unkeyed_valid_path_info(path_info: PathInfo) -> Result<UnkeyedValidPathInfo, OpError> {
+    let deriver = path_info
+        .deriver
+        .as_ref()
+        .map(deriver_to_drv_path)
+        .transpose()
+        .map_err(|e| OpError::Store(format!("invalid deriver: {e}")))?;
+
+    Ok(UnkeyedValidPathInfo {
+        deriver,
+        nar_hash: NixHash::Sha256(path_info.nar_sha256),
+        references: path_info.references,
+        // We don't keep track of when paths were registered.
+        registration_time: 0,
+        nar_size: path_info.nar_size,
+        ultimate: false,
+        signatures: path_info.signatures,
+        ca: path_info.ca,
+    })
+}
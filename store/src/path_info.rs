@@ -85,3 +85,22 @@ impl PathInfo {
         }
     }
 }
+
+/// Converts the store path of a .drv file into the form kept in
+/// [PathInfo::deriver], by stripping the `.drv` suffix from its name.
+///
+/// Returns `None` if the name doesn't end with `.drv`.
+pub fn deriver_from_drv_path(drv_path: &StorePath<String>) -> Option<StorePath<String>> {
+    drv_path
+        .name()
+        .strip_suffix(".drv")
+        .and_then(|name| StorePath::from_name_and_digest_fixed(name, *drv_path.digest()).ok())
+}
+
+/// Converts a [PathInfo::deriver] back into the store path of the .drv file,
+/// by appending `.drv` to its name.
+pub fn deriver_to_drv_path(
+    deriver: &StorePath<String>,
+) -> Result<StorePath<String>, nix_compat::store_path::Error> {
+    StorePath::from_name_and_digest_fixed(&format!("{}.drv", deriver.name()), *deriver.digest())
+}
@@ -2,14 +2,13 @@ use super::{PathInfo, PathInfoService};
 use crate::proto;
 use async_stream::try_stream;
 use bigtable_rs::{bigtable, google::bigtable::v2 as bigtable_v2};
-use bytes::Bytes;
 use data_encoding::HEXLOWER;
 use futures::stream::BoxStream;
 use nix_compat::nixbase32;
 use prost::Message;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DurationSeconds};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use tonic::async_trait;
 use tracing::{instrument, trace};
 use tvix_castore::composition::{CompositionContext, ServiceBuilder};
@@ -19,6 +18,20 @@ use tvix_castore::Error;
 /// https://cloud.google.com/bigtable/docs/schema-design#cells
 const CELL_SIZE_LIMIT: u64 = 10 * 1024 * 1024;
 
+/// Filters must not be larger than 20 KiB.
+/// https://cloud.google.com/bigtable/quotas#limits-operations
+/// With 40 hex characters per key plus a separator, 400 keys stay below that.
+const GET_MANY_BATCH_SIZE: usize = 400;
+
+/// Prefix of the index rows listing the referrers of a store path.
+const REFERRERS_ROW_PREFIX: &str = "referrers/";
+
+/// Prefix of the index rows listing the outputs of a derivation.
+const OUTPUTS_ROW_PREFIX: &str = "outputs/";
+
+/// All PathInfo row keys (hexlower) sort before this, all index rows after.
+const PATHINFO_ROWS_END: &str = "g";
+
 /// Provides a [PathInfoService] implementation using
 /// [Bigtable](https://cloud.google.com/bigtable/docs/)
 /// as an underlying K/V store.
@@ -31,8 +44,18 @@ const CELL_SIZE_LIMIT: u64 = 10 * 1024 * 1024;
 /// Its value is the PathInfo message, serialized in canonical protobuf.
 /// We currently only populate this column.
 ///
-/// Listing is ranging over all rows, and calculate_nar is returning a
+/// Listing is ranging over all PathInfo rows, and calculate_nar is returning a
 /// "unimplemented" error.
+///
+/// For listing referrers and outputs of a derivation, there's an index row
+/// for each referenced store path and each derivation, keyed by
+/// `referrers/` or `outputs/` followed by the hexlower digest. It has one
+/// (empty) cell per referrer/output, with their hexlower digest as column
+/// qualifier. These rows sort after all PathInfo rows.
+/// Index entries are written before the PathInfo itself, and as an existing
+/// PathInfo is never replaced, they can be stale. Lookups only return
+/// PathInfos actually referring to the store path (or produced by the
+/// derivation).
 #[derive(Clone)]
 pub struct BigtablePathInfoService {
     client: bigtable::BigTable,
@@ -146,6 +169,152 @@ fn derive_pathinfo_key(digest: &[u8; 20]) -> String {
     HEXLOWER.encode(digest)
 }
 
+/// Derives the key of the index row with the given prefix for an output path.
+fn derive_index_row_key(prefix: &str, digest: &[u8; 20]) -> String {
+    format!("{}{}", prefix, derive_pathinfo_key(digest))
+}
+
+impl BigtablePathInfoService {
+    /// Adds the output path with the given digest to the index row with the
+    /// given key, unless it's already present.
+    async fn insert_index_entry(&self, row_key: String, digest: &[u8; 20]) -> Result<(), Error> {
+        let mut client = self.client.clone();
+        let qualifier = derive_pathinfo_key(digest);
+
+        client
+            .check_and_mutate_row(bigtable_v2::CheckAndMutateRowRequest {
+                table_name: client.get_full_table_name(&self.params.table_name),
+                app_profile_id: self.params.app_profile_id.to_string(),
+                row_key: row_key.into(),
+                predicate_filter: Some(bigtable_v2::RowFilter {
+                    filter: Some(bigtable_v2::row_filter::Filter::ColumnQualifierRegexFilter(
+                        qualifier.clone().into(),
+                    )),
+                }),
+                // If the entry was already found, do nothing.
+                true_mutations: vec![],
+                // Else, insert it.
+                false_mutations: vec![bigtable_v2::Mutation {
+                    mutation: Some(bigtable_v2::mutation::Mutation::SetCell(
+                        bigtable_v2::mutation::SetCell {
+                            family_name: self.params.family_name.to_string(),
+                            column_qualifier: qualifier.into(),
+                            timestamp_micros: -1, // use server time to fill timestamp
+                            value: vec![],
+                        },
+                    )),
+                }],
+            })
+            .await
+            .map_err(|e| Error::StorageError(format!("unable to mutate rows: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Yields the PathInfos listed in the index row with the given key, which
+    /// `is_match` returns true for.
+    fn list_index<F>(
+        &self,
+        row_key: String,
+        is_match: F,
+    ) -> BoxStream<'static, Result<PathInfo, Error>>
+    where
+        F: Fn(&PathInfo) -> bool + Send + 'static,
+    {
+        let svc = self.clone();
+
+        let stream = try_stream! {
+            let mut client = svc.client.clone();
+
+            let request = bigtable_v2::ReadRowsRequest {
+                app_profile_id: svc.params.app_profile_id.to_string(),
+                table_name: client.get_full_table_name(&svc.params.table_name),
+                rows_limit: 1,
+                rows: Some(bigtable_v2::RowSet {
+                    row_keys: vec![row_key.into()],
+                    row_ranges: vec![],
+                }),
+                filter: Some(bigtable_v2::RowFilter {
+                    filter: Some(bigtable_v2::row_filter::Filter::FamilyNameRegexFilter(
+                        svc.params.family_name.to_string(),
+                    )),
+                }),
+                ..Default::default()
+            };
+
+            let response = client
+                .read_rows(request)
+                .await
+                .map_err(|e| Error::StorageError(format!("unable to read rows: {}", e)))?;
+
+            let mut digests = response
+                .into_iter()
+                .flat_map(|(_row_key, cells)| cells)
+                .map(|cell| {
+                    HEXLOWER
+                        .decode(&cell.qualifier)
+                        .ok()
+                        .and_then(|digest| <[u8; 20]>::try_from(digest).ok())
+                        .ok_or_else(|| Error::StorageError("invalid index entry".into()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            // There might be more than one version of a cell.
+            digests.sort();
+            digests.dedup();
+
+            for digests in digests.chunks(GET_MANY_BATCH_SIZE) {
+                for path_info in svc.get_many(digests).await?.into_iter().flatten() {
+                    if is_match(&path_info) {
+                        yield path_info;
+                    }
+                }
+            }
+        };
+
+        Box::pin(stream)
+    }
+}
+
+/// Parses the cells of a PathInfo row into a [PathInfo], ensuring it has the
+/// expected digest.
+fn parse_path_info_cells(
+    digest: &[u8; 20],
+    cells: &[bigtable::RowCell],
+) -> Result<PathInfo, Error> {
+    let path_info_key = derive_pathinfo_key(digest);
+
+    // Ensure there's exactly one cell.
+    // More than one shouldn't happen, we filter out other cells in our query.
+    let cell = match cells {
+        [] => return Err(Error::StorageError("found no cells".into())),
+        [cell] => cell,
+        _ => {
+            return Err(Error::StorageError(
+                "more than one cell returned from bigtable".into(),
+            ))
+        }
+    };
+
+    // We also require the qualifier to be correct in the filter above,
+    // so this shouldn't happen.
+    if path_info_key.as_bytes() != cell.qualifier {
+        return Err(Error::StorageError("unexpected cell qualifier".into()));
+    }
+
+    // Try to parse the value into a PathInfo message
+    let path_info_proto = proto::PathInfo::decode(cell.value.as_slice())
+        .map_err(|e| Error::StorageError(format!("unable to decode pathinfo proto: {}", e)))?;
+
+    let path_info = PathInfo::try_from(path_info_proto)
+        .map_err(|e| Error::StorageError(format!("Invalid path info: {e}")))?;
+
+    if path_info.store_path.digest() != digest {
+        return Err(Error::StorageError("PathInfo has unexpected digest".into()));
+    }
+
+    Ok(path_info)
+}
+
 #[async_trait]
 impl PathInfoService for BigtablePathInfoService {
     #[instrument(level = "trace", skip_all, fields(path_info.digest = nixbase32::encode(&digest)))]
@@ -204,7 +373,7 @@ impl PathInfoService for BigtablePathInfoService {
             return Ok(None);
         }
 
-        let (row_key, mut cells) = response.pop().unwrap();
+        let (row_key, cells) = response.pop().unwrap();
         if row_key != path_info_key.as_bytes() {
             // This shouldn't happen, we requested this row key.
             return Err(Error::StorageError(
@@ -212,36 +381,78 @@ impl PathInfoService for BigtablePathInfoService {
             ));
         }
 
-        let cell = cells
-            .pop()
-            .ok_or_else(|| Error::StorageError("found no cells".into()))?;
+        Ok(Some(parse_path_info_cells(&digest, &cells)?))
+    }
 
-        // Ensure there's only one cell (so no more left after the pop())
-        // This shouldn't happen, We filter out other cells in our query.
-        if !cells.is_empty() {
-            return Err(Error::StorageError(
-                "more than one cell returned from bigtable".into(),
-            ));
+    #[instrument(level = "trace", skip_all, fields(path_info.count = digests.len()))]
+    async fn get_many(&self, digests: &[[u8; 20]]) -> Result<Vec<Option<PathInfo>>, Error> {
+        if digests.is_empty() {
+            return Ok(vec![]);
         }
 
-        // We also require the qualifier to be correct in the filter above,
-        // so this shouldn't happen.
-        if path_info_key.as_bytes() != cell.qualifier {
-            return Err(Error::StorageError("unexpected cell qualifier".into()));
-        }
+        let mut path_infos = Vec::with_capacity(digests.len());
+
+        // The column qualifier filter is a single regex alternating over all
+        // requested keys, and Bigtable caps the size of filters, so read the
+        // rows in batches.
+        for digests in digests.chunks(GET_MANY_BATCH_SIZE) {
+            let mut client = self.client.clone();
+            let path_info_keys: Vec<String> = digests.iter().map(derive_pathinfo_key).collect();
+
+            let request = bigtable_v2::ReadRowsRequest {
+                app_profile_id: self.params.app_profile_id.to_string(),
+                table_name: client.get_full_table_name(&self.params.table_name),
+                rows: Some(bigtable_v2::RowSet {
+                    row_keys: path_info_keys
+                        .iter()
+                        .map(|path_info_key| path_info_key.clone().into())
+                        .collect(),
+                    row_ranges: vec![],
+                }),
+                // Filter selected family name, and column qualifiers matching any
+                // of the digests.
+                filter: Some(bigtable_v2::RowFilter {
+                    filter: Some(bigtable_v2::row_filter::Filter::Chain(
+                        bigtable_v2::row_filter::Chain {
+                            filters: vec![
+                                bigtable_v2::RowFilter {
+                                    filter: Some(
+                                        bigtable_v2::row_filter::Filter::FamilyNameRegexFilter(
+                                            self.params.family_name.to_string(),
+                                        ),
+                                    ),
+                                },
+                                bigtable_v2::RowFilter {
+                                    filter: Some(
+                                        bigtable_v2::row_filter::Filter::ColumnQualifierRegexFilter(
+                                            path_info_keys.join("|").into(),
+                                        ),
+                                    ),
+                                },
+                            ],
+                        },
+                    )),
+                }),
+                ..Default::default()
+            };
 
-        // Try to parse the value into a PathInfo message
-        let path_info_proto = proto::PathInfo::decode(Bytes::from(cell.value))
-            .map_err(|e| Error::StorageError(format!("unable to decode pathinfo proto: {}", e)))?;
+            let response = client
+                .read_rows(request)
+                .await
+                .map_err(|e| Error::StorageError(format!("unable to read rows: {}", e)))?;
 
-        let path_info = PathInfo::try_from(path_info_proto)
-            .map_err(|e| Error::StorageError(format!("Invalid path info: {e}")))?;
+            let rows: HashMap<Vec<u8>, Vec<_>> = response.into_iter().collect();
 
-        if path_info.store_path.digest() != &digest {
-            return Err(Error::StorageError("PathInfo has unexpected digest".into()));
+            for (digest, path_info_key) in digests.iter().zip(path_info_keys) {
+                path_infos.push(
+                    rows.get(path_info_key.as_bytes())
+                        .map(|cells| parse_path_info_cells(digest, cells))
+                        .transpose()?,
+                );
+            }
         }
 
-        Ok(Some(path_info))
+        Ok(path_infos)
     }
 
     #[instrument(level = "trace", skip_all, fields(path_info.root_node = ?path_info.node))]
@@ -256,6 +467,24 @@ impl PathInfoService for BigtablePathInfoService {
             ));
         }
 
+        // Add the index entries first, so the PathInfo can't be visible
+        // without them.
+        let digest = path_info.store_path.digest();
+        futures::future::try_join_all(
+            path_info
+                .references
+                .iter()
+                .map(|reference| derive_index_row_key(REFERRERS_ROW_PREFIX, reference.digest()))
+                .chain(
+                    path_info
+                        .deriver
+                        .iter()
+                        .map(|deriver| derive_index_row_key(OUTPUTS_ROW_PREFIX, deriver.digest())),
+                )
+                .map(|row_key| self.insert_index_entry(row_key, digest)),
+        )
+        .await?;
+
         let resp = client
             .check_and_mutate_row(bigtable_v2::CheckAndMutateRowRequest {
                 table_name: client.get_full_table_name(&self.params.table_name),
@@ -299,6 +528,16 @@ impl PathInfoService for BigtablePathInfoService {
         let request = bigtable_v2::ReadRowsRequest {
             app_profile_id: self.params.app_profile_id.to_string(),
             table_name: client.get_full_table_name(&self.params.table_name),
+            // Skip the index rows.
+            rows: Some(bigtable_v2::RowSet {
+                row_keys: vec![],
+                row_ranges: vec![bigtable_v2::RowRange {
+                    start_key: None,
+                    end_key: Some(bigtable_v2::row_range::EndKey::EndKeyOpen(
+                        PATHINFO_ROWS_END.into(),
+                    )),
+                }],
+            }),
             filter: Some(bigtable_v2::RowFilter {
                 filter: Some(bigtable_v2::row_filter::Filter::FamilyNameRegexFilter(
                     self.params.family_name.to_string(),
@@ -334,7 +573,7 @@ impl PathInfoService for BigtablePathInfoService {
                 }
 
                 // Try to parse the value into a PathInfo message.
-                let path_info_proto = proto::PathInfo::decode(Bytes::from(cell.value))
+                let path_info_proto = proto::PathInfo::decode(cell.value.as_slice())
                     .map_err(|e| Error::StorageError(format!("unable to decode pathinfo proto: {}", e)))?;
 
                 let path_info = PathInfo::try_from(path_info_proto).map_err(|e| Error::StorageError(format!("Invalid path info: {e}")))?;
@@ -352,6 +591,35 @@ impl PathInfoService for BigtablePathInfoService {
 
         Box::pin(stream)
     }
+
+    #[instrument(level = "trace", skip_all, fields(path_info.digest = nixbase32::encode(&digest)))]
+    fn list_referrers(&self, digest: [u8; 20]) -> BoxStream<'static, Result<PathInfo, Error>> {
+        self.list_index(
+            derive_index_row_key(REFERRERS_ROW_PREFIX, &digest),
+            move |path_info| {
+                path_info
+                    .references
+                    .iter()
+                    .any(|reference| reference.digest() == &digest)
+            },
+        )
+    }
+
+    #[instrument(level = "trace", skip_all, fields(deriver.digest = nixbase32::encode(&deriver_digest)))]
+    fn list_by_deriver(
+        &self,
+        deriver_digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        self.list_index(
+            derive_index_row_key(OUTPUTS_ROW_PREFIX, &deriver_digest),
+            move |path_info| {
+                path_info
+                    .deriver
+                    .as_ref()
+                    .is_some_and(|deriver| deriver.digest() == &deriver_digest)
+            },
+        )
+    }
 }
 
 /// Represents configuration of [BigtablePathInfoService].
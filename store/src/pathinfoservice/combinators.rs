@@ -14,6 +14,8 @@ use super::{PathInfo, PathInfoService};
 /// near.
 /// There is no negative cache.
 /// Inserts and listings are not implemented for now.
+/// Queries for referrers and outputs of a derivation are answered by far, as
+/// near only holds a subset of all PathInfos.
 pub struct Cache<PS1, PS2> {
     near: PS1,
     far: PS2,
@@ -52,6 +54,42 @@ where
         }
     }
 
+    #[instrument(level = "trace", skip_all, fields(path_info.count = digests.len()))]
+    async fn get_many(&self, digests: &[[u8; 20]]) -> Result<Vec<Option<PathInfo>>, Error> {
+        let mut path_infos = self.near.get_many(digests).await?;
+
+        // Ask far for everything not found in near.
+        let (missing_idxs, missing_digests): (Vec<usize>, Vec<[u8; 20]>) = path_infos
+            .iter()
+            .zip(digests)
+            .enumerate()
+            .filter(|(_, (path_info, _))| path_info.is_none())
+            .map(|(i, (_, digest))| (i, *digest))
+            .unzip();
+
+        if missing_digests.is_empty() {
+            debug!("serving all from cache");
+            return Ok(path_infos);
+        }
+
+        debug!(
+            missing = missing_digests.len(),
+            "not all found in near, asking remote…"
+        );
+        for (i, path_info) in missing_idxs
+            .into_iter()
+            .zip(self.far.get_many(&missing_digests).await?)
+        {
+            if let Some(path_info) = path_info {
+                debug!("found in remote, adding to cache");
+                self.near.put(path_info.clone()).await?;
+                path_infos[i] = Some(path_info);
+            }
+        }
+
+        Ok(path_infos)
+    }
+
     async fn put(&self, _path_info: PathInfo) -> Result<PathInfo, Error> {
         Err(Error::StorageError("unimplemented".to_string()))
     }
@@ -61,6 +99,17 @@ where
             "unimplemented".to_string(),
         ))))
     }
+
+    fn list_referrers(&self, digest: [u8; 20]) -> BoxStream<'static, Result<PathInfo, Error>> {
+        self.far.list_referrers(digest)
+    }
+
+    fn list_by_deriver(
+        &self,
+        deriver_digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        self.far.list_by_deriver(deriver_digest)
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -102,9 +151,11 @@ impl ServiceBuilder for CacheConfig {
 mod test {
     use std::num::NonZeroUsize;
 
+    use futures::TryStreamExt;
+
     use crate::{
-        pathinfoservice::{LruPathInfoService, MemoryPathInfoService, PathInfoService},
-        tests::fixtures::PATH_INFO,
+        pathinfoservice::{LruPathInfoService, MemoryPathInfoService, PathInfo, PathInfoService},
+        tests::fixtures::{DUMMY_DERIVER, DUMMY_PATH_DIGEST, PATH_INFO, PATH_INFO_WITH_DERIVER},
     };
 
     /// Helper function setting up an instance of a "far" and "near"
@@ -147,4 +198,29 @@ mod test {
             svc.near.get(*PATH_INFO.store_path.digest()).await.unwrap()
         );
     }
+
+    /// Referrers and outputs of a derivation are looked up in the far
+    /// backend, even if nothing was cached in the near one.
+    #[tokio::test]
+    async fn test_list_referrers_by_deriver() {
+        let svc = create_pathinfoservice().await;
+
+        let path_info = PATH_INFO_WITH_DERIVER.clone();
+        svc.far.put(path_info.clone()).await.unwrap();
+
+        // PATH_INFO refers to itself.
+        let referrers: Vec<PathInfo> = svc
+            .list_referrers(DUMMY_PATH_DIGEST)
+            .try_collect()
+            .await
+            .expect("must succeed");
+        assert_eq!(vec![path_info.clone()], referrers);
+
+        let outputs: Vec<PathInfo> = svc
+            .list_by_deriver(*DUMMY_DERIVER.digest())
+            .try_collect()
+            .await
+            .expect("must succeed");
+        assert_eq!(vec![path_info], outputs);
+    }
 }
@@ -17,11 +17,20 @@ use url::Url;
 /// - `redb:///absolute/path/to/somewhere`
 ///   Uses redb, using a path on the disk for persistency. Can be only opened
 ///   from one process at the same time.
+/// - `sqlite:`
+///   Uses a in-memory sqlite database.
+/// - `sqlite:///absolute/path/to/somewhere`
+///   Uses sqlite, using a path on the disk for persistency.
 /// - `nix+https://cache.nixos.org?trusted-public-keys=cache.nixos.org-1:6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=`
 ///   Exposes the Nix binary cache as a PathInfoService, ingesting NARs into the
 ///   {Blob,Directory}Service. You almost certainly want to use this with some cache.
 ///   The `trusted-public-keys` URL parameter can be provided, which will then
 ///   enable signature verification.
+/// - `nix+daemon:///nix/var/nix/daemon-socket/socket`
+///   Connects to a Nix daemon via its Unix socket, ingesting NARs into the
+///   {Blob,Directory}Service. Like the Nix binary cache, you almost certainly
+///   want to use this with some cache. In composition configs, this is the
+///   `nix-daemon` type, while `nix` is always a binary cache.
 /// - `grpc+unix:///absolute/path/to/somewhere`
 ///   Connects to a local tvix-store gRPC service via Unix socket.
 /// - `grpc+http://host:port`, `grpc+https://host:port`
@@ -65,6 +74,7 @@ mod tests {
 
     static TMPDIR_REDB_1: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
     static TMPDIR_REDB_2: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
+    static TMPDIR_SQLITE_1: LazyLock<TempDir> = LazyLock::new(|| TempDir::new().unwrap());
 
     // the gRPC tests below don't fail, because we connect lazily.
 
@@ -87,6 +97,14 @@ mod tests {
     #[case::redb_valid_path(&format!("redb://{}", &TMPDIR_REDB_2.path().join("foo").to_str().unwrap()), true)]
     /// redb using the in-memory backend, which should succeed.
     #[case::redb_valid_in_memory("redb://", true)]
+    /// sqlite with a host, which should fail.
+    #[case::sqlite_invalid_host("sqlite://foo.example", false)]
+    /// sqlite with / as path, which should fail.
+    #[case::sqlite_invalid_root("sqlite:///", false)]
+    /// This configures sqlite with a valid path, which should succeed.
+    #[case::sqlite_valid_path(&format!("sqlite://{}", &TMPDIR_SQLITE_1.path().join("foo").to_str().unwrap()), true)]
+    /// sqlite using an in-memory database, which should succeed.
+    #[case::sqlite_valid_in_memory("sqlite://", true)]
     /// Correct Scheme for the cache.nixos.org binary cache.
     #[case::correct_nix_https("nix+https://cache.nixos.org", true)]
     /// Correct Scheme for the cache.nixos.org binary cache (HTTP URL).
@@ -99,6 +117,15 @@ mod tests {
     #[case::correct_nix_https_with_trusted_public_key("nix+https://cache.nixos.org?trusted-public-keys=cache.nixos.org-1:6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=", true)]
     /// Correct Scheme for the cache.nixos.org binary cache, and two correct trusted public keys set
     #[case::correct_nix_https_with_two_trusted_public_keys("nix+https://cache.nixos.org?trusted-public-keys=cache.nixos.org-1:6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=%20foo:jp4fCEx9tBEId/L0ZsVJ26k0wC0fu7vJqLjjIGFkup8=", true)]
+    /// Correct scheme for a Nix daemon socket.
+    #[case::correct_nix_daemon("nix+daemon:///nix/var/nix/daemon-socket/socket", true)]
+    /// Nix daemon socket, but setting a host too, which is invalid.
+    #[case::nix_daemon_invalid_host(
+        "nix+daemon://host.example/nix/var/nix/daemon-socket/socket",
+        false
+    )]
+    /// Nix daemon without a socket path, which is invalid.
+    #[case::nix_daemon_missing_path("nix+daemon://", false)]
     /// Correct scheme to connect to a unix socket.
     #[case::grpc_valid_unix_socket("grpc+unix:///path/to/somewhere", true)]
     /// Correct scheme for unix socket, but setting a host too, which is invalid.
@@ -1,10 +1,10 @@
-use super::{PathInfo, PathInfoService};
+use super::{get_concurrently, PathInfo, PathInfoService};
 use crate::{
     nar::NarCalculationService,
     proto::{self, ListPathInfoRequest},
 };
 use async_stream::try_stream;
-use futures::stream::BoxStream;
+use futures::{stream::BoxStream, TryStreamExt};
 use nix_compat::nixbase32;
 use std::sync::Arc;
 use tonic::{async_trait, Code};
@@ -62,6 +62,54 @@ where
         }
     }
 
+    #[instrument(level = "trace", skip_all, fields(path_info.count = digests.len()))]
+    async fn get_many(&self, digests: &[[u8; 20]]) -> Result<Vec<Option<PathInfo>>, Error> {
+        let requests: Vec<_> = digests
+            .iter()
+            .map(|digest| proto::GetPathInfoRequest {
+                by_what: Some(proto::get_path_info_request::ByWhat::ByOutputHash(
+                    digest.to_vec().into(),
+                )),
+            })
+            .collect();
+
+        let resp = match self
+            .grpc_client
+            .clone()
+            .get_many(tokio_stream::iter(requests))
+            .await
+        {
+            Ok(resp) => resp,
+            // Older servers don't support batching.
+            Err(e) if e.code() == Code::Unimplemented => {
+                return get_concurrently(self, digests).await
+            }
+            Err(e) => return Err(Error::StorageError(e.to_string())),
+        };
+
+        let path_infos: Vec<Option<PathInfo>> = resp
+            .into_inner()
+            .map_err(|e| Error::StorageError(e.to_string()))
+            .and_then(|resp| async move {
+                resp.path_info
+                    .map(PathInfo::try_from)
+                    .transpose()
+                    .map_err(|e| Error::StorageError(format!("Invalid path info: {e}")))
+            })
+            .try_collect()
+            .await?;
+
+        if path_infos.len() != digests.len() {
+            return Err(Error::StorageError(format!(
+                "got {} responses for {} digests",
+                path_infos.len(),
+                digests.len()
+            )));
+        }
+
+        Ok(path_infos)
+    }
+
     #[instrument(level = "trace", skip_all, fields(path_info.root_node = ?path_info.node))]
     async fn put(&self, path_info: PathInfo) -> Result<PathInfo, Error> {
         let path_info = self
@@ -96,6 +144,59 @@ where
         Box::pin(stream)
     }
 
+    #[instrument(level = "trace", skip_all, fields(path_info.digest = nixbase32::encode(&digest)))]
+    fn list_referrers(&self, digest: [u8; 20]) -> BoxStream<'static, Result<PathInfo, Error>> {
+        let mut grpc_client = self.grpc_client.clone();
+
+        let stream = try_stream! {
+            let resp = grpc_client
+                .list_referrers(proto::ListReferrersRequest {
+                    by_output_hash: digest.to_vec().into(),
+                })
+                .await;
+
+            let mut stream = resp.map_err(status_to_error)?.into_inner();
+
+            loop {
+                match stream.message().await {
+                    Ok(Some(path_info)) => yield PathInfo::try_from(path_info).map_err(|e| Error::StorageError(format!("Invalid path info: {e}")))?,
+                    Ok(None) => return,
+                    Err(e) => Err(status_to_error(e))?,
+                }
+            }
+        };
+
+        Box::pin(stream)
+    }
+
+    #[instrument(level = "trace", skip_all, fields(deriver.digest = nixbase32::encode(&deriver_digest)))]
+    fn list_by_deriver(
+        &self,
+        deriver_digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        let mut grpc_client = self.grpc_client.clone();
+
+        let stream = try_stream! {
+            let resp = grpc_client
+                .list_by_deriver(proto::ListByDeriverRequest {
+                    by_deriver_hash: deriver_digest.to_vec().into(),
+                })
+                .await;
+
+            let mut stream = resp.map_err(status_to_error)?.into_inner();
+
+            loop {
+                match stream.message().await {
+                    Ok(Some(path_info)) => yield PathInfo::try_from(path_info).map_err(|e| Error::StorageError(format!("Invalid path info: {e}")))?,
+                    Ok(None) => return,
+                    Err(e) => Err(status_to_error(e))?,
+                }
+            }
+        };
+
+        Box::pin(stream)
+    }
+
     #[instrument(level = "trace", skip_all)]
     fn nar_calculation_service(&self) -> Option<Box<dyn NarCalculationService>> {
         Some(Box::new(GRPCPathInfoService {
@@ -139,6 +240,15 @@ where
     }
 }
 
+/// Converts an error returned by the remote, keeping track of whether the
+/// remote doesn't support the requested query.
+fn status_to_error(status: tonic::Status) -> Error {
+    match status.code() {
+        Code::Unimplemented => Error::Unsupported(status.message().to_string()),
+        _ => Error::StorageError(status.to_string()),
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct GRPCPathInfoServiceConfig {
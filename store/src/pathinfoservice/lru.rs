@@ -53,6 +53,13 @@ impl PathInfoService for LruPathInfoService {
             }
         })
     }
+
+    #[instrument(level = "trace", skip_all, fields(path_info.digest = nixbase32::encode(&digest)))]
+    async fn delete(&self, digest: [u8; 20]) -> Result<(), Error> {
+        self.lru.write().await.pop(&digest);
+
+        Ok(())
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -47,6 +47,52 @@ impl PathInfoService for MemoryPathInfoService {
             }
         })
     }
+
+    fn list_referrers(&self, digest: [u8; 20]) -> BoxStream<'static, Result<PathInfo, Error>> {
+        let db = self.db.clone();
+
+        Box::pin(try_stream! {
+            let db = db.read().await;
+            let it = db.values().filter(|path_info| {
+                path_info
+                    .references
+                    .iter()
+                    .any(|reference| *reference.digest() == digest)
+            });
+
+            for v in it {
+                yield v.clone()
+            }
+        })
+    }
+
+    fn list_by_deriver(
+        &self,
+        deriver_digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        let db = self.db.clone();
+
+        Box::pin(try_stream! {
+            let db = db.read().await;
+            let it = db.values().filter(|path_info| {
+                path_info
+                    .deriver
+                    .as_ref()
+                    .is_some_and(|deriver| *deriver.digest() == deriver_digest)
+            });
+
+            for v in it {
+                yield v.clone()
+            }
+        })
+    }
+
+    #[instrument(level = "trace", skip_all, fields(path_info.digest = nixbase32::encode(&digest)))]
+    async fn delete(&self, digest: [u8; 20]) -> Result<(), Error> {
+        self.db.write().await.remove(&digest);
+
+        Ok(())
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -3,9 +3,11 @@ mod from_addr;
 mod grpc;
 mod lru;
 mod memory;
+mod nix_daemon;
 mod nix_http;
 mod redb;
 mod signing_wrapper;
+mod sqlite;
 
 #[cfg(any(feature = "fuse", feature = "virtiofs"))]
 mod fs;
@@ -15,6 +17,7 @@ mod tests;
 
 use auto_impl::auto_impl;
 use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
 use tonic::async_trait;
 use tvix_castore::composition::{Registry, ServiceBuilder};
 use tvix_castore::Error;
@@ -29,12 +32,14 @@ pub use self::from_addr::from_addr;
 pub use self::grpc::{GRPCPathInfoService, GRPCPathInfoServiceConfig};
 pub use self::lru::{LruPathInfoService, LruPathInfoServiceConfig};
 pub use self::memory::{MemoryPathInfoService, MemoryPathInfoServiceConfig};
+pub use self::nix_daemon::{NixDaemonPathInfoService, NixDaemonPathInfoServiceConfig};
 pub use self::nix_http::{NixHTTPPathInfoService, NixHTTPPathInfoServiceConfig};
 pub use self::redb::{RedbPathInfoService, RedbPathInfoServiceConfig};
 pub use self::signing_wrapper::{KeyFileSigningPathInfoServiceConfig, SigningPathInfoService};
+pub use self::sqlite::{SqlitePathInfoService, SqlitePathInfoServiceConfig};
 
 #[cfg(test)]
-pub(crate) use self::signing_wrapper::test_signing_service;
+pub(crate) use self::signing_wrapper::{test_signing_service, DUMMY_VERIFYING_KEY};
 
 #[cfg(feature = "cloud")]
 mod bigtable;
@@ -51,6 +56,15 @@ pub trait PathInfoService: Send + Sync {
     /// Retrieve a PathInfo message by the output digest.
     async fn get(&self, digest: [u8; 20]) -> Result<Option<PathInfo>, Error>;
 
+    /// Retrieve multiple PathInfo messages by their output digests.
+    /// The returned Vec has the same length and order as the passed digests.
+    ///
+    /// The default implementation issues concurrent calls to [Self::get],
+    /// implementations should override it if they can do better.
+    async fn get_many(&self, digests: &[[u8; 20]]) -> Result<Vec<Option<PathInfo>>, Error> {
+        get_concurrently(self, digests).await
+    }
+
     /// Store a PathInfo message. Implementations MUST call validate and reject
     /// invalid messages.
     async fn put(&self, path_info: PathInfo) -> Result<PathInfo, Error>;
@@ -64,6 +78,36 @@ pub trait PathInfoService: Send + Sync {
     /// [async_trait] generates, but for streams instead of futures.
     fn list(&self) -> BoxStream<'static, Result<PathInfo, Error>>;
 
+    /// Iterate over all PathInfo objects referring to the store path with the
+    /// given output digest, the reverse of [PathInfo::references].
+    /// A store path referring to itself is part of its own referrers.
+    /// Implementations can decide to not support this query.
+    fn list_referrers(&self, _digest: [u8; 20]) -> BoxStream<'static, Result<PathInfo, Error>> {
+        Box::pin(futures::stream::once(async {
+            Err(Error::Unsupported("listing referrers".to_string()))
+        }))
+    }
+
+    /// Iterate over all PathInfo objects produced by the derivation whose
+    /// store path has the given output digest, the reverse of
+    /// [PathInfo::deriver].
+    /// Implementations can decide to not support this query.
+    fn list_by_deriver(
+        &self,
+        _deriver_digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        Box::pin(futures::stream::once(async {
+            Err(Error::Unsupported("listing by deriver".to_string()))
+        }))
+    }
+
+    /// Remove the PathInfo message with the given output digest.
+    /// Removing a PathInfo that doesn't exist is not an error.
+    /// Implementations can decide to disallow deletion.
+    async fn delete(&self, _digest: [u8; 20]) -> Result<(), Error> {
+        Err(Error::Unsupported("deletion".to_string()))
+    }
+
     /// Returns a (more) suitable NarCalculationService.
     /// This can be used to offload NAR calculation to the remote side.
     fn nar_calculation_service(&self) -> Option<Box<dyn NarCalculationService>> {
@@ -71,6 +115,23 @@ pub trait PathInfoService: Send + Sync {
     }
 }
 
+/// The number of concurrent [PathInfoService::get] calls issued by
+/// [get_concurrently].
+const GET_CONCURRENCY: usize = 16;
+
+/// Retrieves multiple PathInfo messages with concurrent calls to
+/// [PathInfoService::get], preserving order.
+pub(crate) async fn get_concurrently<PS: PathInfoService + ?Sized>(
+    path_info_service: &PS,
+    digests: &[[u8; 20]],
+) -> Result<Vec<Option<PathInfo>>, Error> {
+    futures::stream::iter(digests.iter().copied())
+        .map(|digest| async move { path_info_service.get(digest).await })
+        .buffered(GET_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
 /// Registers the builtin PathInfoService implementations with the registry
 pub(crate) fn register_pathinfo_services(reg: &mut Registry) {
     reg.register::<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>, CachePathInfoServiceConfig>("cache");
@@ -78,7 +139,9 @@ pub(crate) fn register_pathinfo_services(reg: &mut Registry) {
     reg.register::<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>, LruPathInfoServiceConfig>("lru");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>, MemoryPathInfoServiceConfig>("memory");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>, NixHTTPPathInfoServiceConfig>("nix");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>, NixDaemonPathInfoServiceConfig>("nix-daemon");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>, RedbPathInfoServiceConfig>("redb");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>, SqlitePathInfoServiceConfig>("sqlite");
     reg.register::<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>, KeyFileSigningPathInfoServiceConfig>("keyfile-signing");
     #[cfg(feature = "cloud")]
     {
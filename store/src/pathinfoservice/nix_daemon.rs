@@ -0,0 +1,405 @@
+use super::{PathInfo, PathInfoService};
+use crate::nar::ingest_nar_and_hash;
+use crate::path_info::deriver_from_drv_path;
+use async_stream::try_stream;
+use futures::stream::BoxStream;
+use nix_compat::{
+    nix_daemon::{
+        de::{NixRead, NixReader},
+        logger::LogMessage,
+        ser::{NixWrite, NixWriter},
+        UnkeyedValidPathInfo,
+    },
+    nixbase32,
+    nixhash::NixHash,
+    store_path::StorePath,
+    worker_protocol::{self, Operation},
+};
+use parking_lot::Mutex;
+use std::{path::PathBuf, sync::Arc};
+use tokio::{
+    io::{self, AsyncReadExt, BufWriter},
+    net::{
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+        UnixStream,
+    },
+};
+use tonic::async_trait;
+use tracing::{debug, instrument, warn};
+use tvix_castore::composition::{CompositionContext, ServiceBuilder};
+use tvix_castore::{blobservice::BlobService, directoryservice::DirectoryService, Error};
+use url::Url;
+
+/// NixDaemonPathInfoService talks to a Nix daemon via its Unix socket, as a
+/// client of the worker protocol.
+/// Every [PathInfoService::get] looks up the store path with
+/// `QueryPathFromHashPart` and `QueryPathInfo`, then fetches the NAR with
+/// `NarFromPath`, ingesting it into the [BlobService] and [DirectoryService].
+///
+/// The full NAR is transferred and ingested on every call, even if the
+/// contents are already present in the [BlobService] and [DirectoryService],
+/// and [PathInfoService::list] does so for every path in the Nix store.
+/// Like [super::NixHTTPPathInfoService], this is quite costly, so clients are
+/// expected to layer it with store composition, caching the PathInfos.
+/// [PathInfoService::put] is not implemented and returns an error if called.
+#[derive(Clone)]
+pub struct NixDaemonPathInfoService<BS, DS> {
+    socket_path: PathBuf,
+
+    blob_service: BS,
+    directory_service: DS,
+
+    /// Idle connections to the daemon, reused by subsequent requests.
+    connections: Arc<Mutex<Vec<Connection>>>,
+}
+
+impl<BS, DS> NixDaemonPathInfoService<BS, DS> {
+    pub fn new(socket_path: PathBuf, blob_service: BS, directory_service: DS) -> Self {
+        Self {
+            socket_path,
+            blob_service,
+            directory_service,
+            connections: Default::default(),
+        }
+    }
+}
+
+/// Errors occuring while talking to the daemon.
+#[derive(Debug, thiserror::Error)]
+enum ClientError {
+    /// The daemon reported an error. The connection can still be used.
+    #[error("daemon error: {0}")]
+    Daemon(String),
+    /// Anything else, after which the connection can't be used anymore.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<ClientError> for Error {
+    fn from(value: ClientError) -> Self {
+        Error::StorageError(value.to_string())
+    }
+}
+
+/// A connection to the daemon, after the handshake.
+struct Connection {
+    reader: NixReader<OwnedReadHalf>,
+    writer: NixWriter<BufWriter<OwnedWriteHalf>>,
+}
+
+impl Connection {
+    async fn connect(socket_path: &PathBuf) -> Result<Self, ClientError> {
+        let mut stream = UnixStream::connect(socket_path).await?;
+        let version = worker_protocol::client_handshake_server(&mut stream).await?;
+        debug!(%version, "connected to daemon");
+
+        let (r, w) = stream.into_split();
+        let mut conn = Connection {
+            reader: NixReader::builder().set_version(version).build(r),
+            writer: NixWriter::builder()
+                .set_version(version)
+                .build(BufWriter::new(w)),
+        };
+
+        // The daemon sends the log of its startup after the handshake.
+        conn.read_stderr().await?;
+
+        Ok(conn)
+    }
+
+    /// Sends an operation, its arguments need to be written afterwards.
+    async fn write_op(&mut self, op: Operation) -> Result<(), ClientError> {
+        worker_protocol::write_op(self.writer.get_mut(), &op).await?;
+        Ok(())
+    }
+
+    /// Reads log messages sent by the daemon, until it concludes them.
+    /// Errors reported by the daemon are returned.
+    async fn read_stderr(&mut self) -> Result<(), ClientError> {
+        self.writer.flush().await?;
+
+        loop {
+            match self.reader.read_value().await? {
+                LogMessage::Last => return Ok(()),
+                LogMessage::Error(err) => return Err(ClientError::Daemon(err.msg)),
+                LogMessage::Next(line) => debug!(line = line.trim_end(), "daemon log"),
+                LogMessage::StartActivity(activity) => {
+                    debug!(
+                        id = activity.id,
+                        text = activity.text,
+                        "daemon activity started"
+                    )
+                }
+                LogMessage::StopActivity(id) => debug!(id, "daemon activity stopped"),
+                LogMessage::Result(_) => {}
+                // We never send data along with operations, nor expect
+                // data unrelated to the operation result.
+                msg @ (LogMessage::Read(_) | LogMessage::Write(_)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unsupported stderr message {msg:?}"),
+                    )
+                    .into());
+                }
+            }
+        }
+    }
+
+    async fn query_path_from_hash_part(
+        &mut self,
+        digest: &[u8; 20],
+    ) -> Result<Option<StorePath<String>>, ClientError> {
+        self.write_op(Operation::QueryPathFromHashPart).await?;
+        self.writer.write_value(&nixbase32::encode(digest)).await?;
+        self.read_stderr().await?;
+
+        let path: String = self.reader.read_value().await?;
+        if path.is_empty() {
+            return Ok(None);
+        }
+
+        StorePath::from_absolute_path(path.as_bytes())
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+    }
+
+    async fn query_path_info(
+        &mut self,
+        path: &StorePath<String>,
+    ) -> Result<Option<UnkeyedValidPathInfo>, ClientError> {
+        self.write_op(Operation::QueryPathInfo).await?;
+        self.writer.write_value(path).await?;
+        self.read_stderr().await?;
+
+        // Older daemons don't send a flag, but an error for invalid paths.
+        if self.reader.version().minor() >= 17 && !self.reader.read_value::<bool>().await? {
+            return Ok(None);
+        }
+
+        Ok(Some(self.reader.read_value().await?))
+    }
+
+    async fn query_all_valid_paths(&mut self) -> Result<Vec<StorePath<String>>, ClientError> {
+        self.write_op(Operation::QueryAllValidPaths).await?;
+        self.read_stderr().await?;
+
+        Ok(self.reader.read_value().await?)
+    }
+}
+
+impl<BS, DS> NixDaemonPathInfoService<BS, DS>
+where
+    BS: BlobService + Send + Sync + Clone + 'static,
+    DS: DirectoryService + Send + Sync + Clone + 'static,
+{
+    /// Returns an idle connection, or a new one if there's none.
+    async fn take_connection(&self) -> Result<Connection, ClientError> {
+        let idle_conn = self.connections.lock().pop();
+        match idle_conn {
+            Some(conn) => Ok(conn),
+            None => Connection::connect(&self.socket_path).await,
+        }
+    }
+
+    /// Returns the connection to the pool after an operation, unless it
+    /// can't be used anymore.
+    fn return_connection<T>(&self, conn: Connection, result: &Result<T, ClientError>) {
+        match result {
+            Ok(_) | Err(ClientError::Daemon(_)) => self.connections.lock().push(conn),
+            Err(ClientError::Io(e)) => warn!(err=%e, "dropping daemon connection"),
+        }
+    }
+
+    async fn get_with(
+        &self,
+        conn: &mut Connection,
+        digest: &[u8; 20],
+    ) -> Result<Option<PathInfo>, ClientError> {
+        let path = match conn.query_path_from_hash_part(digest).await? {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let info = match conn.query_path_info(&path).await? {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+
+        self.fetch(conn, path, info).await.map(Some)
+    }
+
+    /// Fetches the NAR of the passed path from the daemon, and ingests it.
+    async fn fetch(
+        &self,
+        conn: &mut Connection,
+        path: StorePath<String>,
+        info: UnkeyedValidPathInfo,
+    ) -> Result<PathInfo, ClientError> {
+        let expected_nar_sha256 = match info.nar_hash {
+            NixHash::Sha256(digest) => digest,
+            nar_hash => {
+                return Err(ClientError::Daemon(format!(
+                    "unsupported NAR hash algo {}",
+                    nar_hash.algo()
+                )))
+            }
+        };
+
+        conn.write_op(Operation::NarFromPath).await?;
+        conn.writer.write_value(&path).await?;
+        conn.read_stderr().await?;
+
+        // The NAR follows unframed, so read exactly as many bytes as announced.
+        // If ingestion fails, the connection is out of sync, and dropped.
+        let (root_node, nar_sha256, nar_size) = ingest_nar_and_hash(
+            self.blob_service.clone(),
+            self.directory_service.clone(),
+            &mut (&mut conn.reader).take(info.nar_size),
+        )
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+        // ensure the ingested narhash and narsize do actually match.
+        if info.nar_size != nar_size {
+            warn!(
+                daemon.nar_size = info.nar_size,
+                nar_size, "NarSize mismatch"
+            );
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "NarSize mismatch".to_string(),
+            ))?;
+        }
+        if expected_nar_sha256 != nar_sha256 {
+            warn!(
+                daemon.nar_hash = %NixHash::Sha256(expected_nar_sha256),
+                nar_hash = %NixHash::Sha256(nar_sha256),
+                "NarHash mismatch"
+            );
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "NarHash mismatch".to_string(),
+            ))?;
+        }
+
+        let deriver = info
+            .deriver
+            .map(|deriver| {
+                deriver_from_drv_path(&deriver).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid deriver: {}", deriver),
+                    )
+                })
+            })
+            .transpose()?;
+
+        Ok(PathInfo {
+            store_path: path,
+            node: root_node,
+            references: info.references,
+            nar_size,
+            nar_sha256,
+            signatures: info.signatures,
+            deriver,
+            ca: info.ca,
+        })
+    }
+}
+
+#[async_trait]
+impl<BS, DS> PathInfoService for NixDaemonPathInfoService<BS, DS>
+where
+    BS: BlobService + Send + Sync + Clone + 'static,
+    DS: DirectoryService + Send + Sync + Clone + 'static,
+{
+    #[instrument(skip_all, err, fields(path.digest=nixbase32::encode(&digest)))]
+    async fn get(&self, digest: [u8; 20]) -> Result<Option<PathInfo>, Error> {
+        let mut conn = self.take_connection().await?;
+        let result = self.get_with(&mut conn, &digest).await;
+        self.return_connection(conn, &result);
+
+        Ok(result?)
+    }
+
+    #[instrument(skip_all, fields(path_info=?_path_info))]
+    async fn put(&self, _path_info: PathInfo) -> Result<PathInfo, Error> {
+        Err(Error::InvalidRequest(
+            "put not supported for this backend".to_string(),
+        ))
+    }
+
+    fn list(&self) -> BoxStream<'static, Result<PathInfo, Error>> {
+        let svc = self.clone();
+
+        Box::pin(try_stream! {
+            let mut conn = svc.take_connection().await?;
+            let result = conn.query_all_valid_paths().await;
+            svc.return_connection(conn, &result);
+            let paths = result?;
+
+            for path in paths {
+                // Paths might have been deleted in the meantime.
+                if let Some(path_info) = svc.get(*path.digest()).await? {
+                    yield path_info;
+                }
+            }
+        })
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NixDaemonPathInfoServiceConfig {
+    socket_path: PathBuf,
+    blob_service: String,
+    directory_service: String,
+}
+
+impl TryFrom<Url> for NixDaemonPathInfoServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: Url) -> Result<Self, Self::Error> {
+        // nix+daemon:///path/to/socket, so no host and a non-empty path.
+        if url.has_host() || url.path().is_empty() || url.path() == "/" {
+            return Err(Error::StorageError("invalid socket path".to_string()).into());
+        }
+
+        let blob_service = url
+            .query_pairs()
+            .into_iter()
+            .find(|(k, _)| k == "blob_service")
+            .map(|(_, v)| v.to_string())
+            .unwrap_or("default".to_string());
+        let directory_service = url
+            .query_pairs()
+            .into_iter()
+            .find(|(k, _)| k == "directory_service")
+            .map(|(_, v)| v.to_string())
+            .unwrap_or("default".to_string());
+
+        Ok(NixDaemonPathInfoServiceConfig {
+            socket_path: url.path().into(),
+            blob_service,
+            directory_service,
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for NixDaemonPathInfoServiceConfig {
+    type Output = dyn PathInfoService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        context: &CompositionContext,
+    ) -> Result<Arc<Self::Output>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let (blob_service, directory_service) = futures::join!(
+            context.resolve::<dyn BlobService>(self.blob_service.clone()),
+            context.resolve::<dyn DirectoryService>(self.directory_service.clone())
+        );
+        Ok(Arc::new(NixDaemonPathInfoService::new(
+            self.socket_path.clone(),
+            blob_service?,
+            directory_service?,
+        )))
+    }
+}
@@ -3,7 +3,10 @@ use crate::proto;
 use data_encoding::BASE64;
 use futures::{stream::BoxStream, StreamExt};
 use prost::Message;
-use redb::{Database, ReadableTable, TableDefinition};
+use redb::{
+    Database, MultimapTable, MultimapTableDefinition, MultimapTableHandle, ReadableMultimapTable,
+    ReadableTable, TableDefinition,
+};
 use std::{path::PathBuf, sync::Arc};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::async_trait;
@@ -15,9 +18,21 @@ use tvix_castore::{
 
 const PATHINFO_TABLE: TableDefinition<[u8; 20], Vec<u8>> = TableDefinition::new("pathinfo");
 
+/// Reverse index from the output hash of a store path to the output hashes of
+/// the store paths referring to it.
+const REFERRERS_TABLE: MultimapTableDefinition<[u8; 20], [u8; 20]> =
+    MultimapTableDefinition::new("referrers");
+
+/// Reverse index from the output hash of a derivation to the output hashes of
+/// the store paths it produced.
+const DERIVERS_TABLE: MultimapTableDefinition<[u8; 20], [u8; 20]> =
+    MultimapTableDefinition::new("derivers");
+
 /// PathInfoService implementation using redb under the hood.
 /// redb stores all of its data in a single file with a K/V pointing from a path's output hash to
 /// its corresponding protobuf-encoded PathInfo.
+/// Two more multimap tables index references and derivers in reverse, and are
+/// kept in sync with it in the same write transaction.
 pub struct RedbPathInfoService {
     // We wrap db in an Arc to be able to move it into spawn_blocking,
     // as discussed in https://github.com/cberner/redb/issues/789
@@ -34,7 +49,7 @@ impl RedbPathInfoService {
             ));
         }
 
-        let db = tokio::task::spawn_blocking(|| -> Result<_, redb::Error> {
+        let db = tokio::task::spawn_blocking(|| -> Result<_, Error> {
             let db = redb::Database::create(path)?;
             create_schema(&db)?;
             Ok(db)
@@ -56,16 +71,85 @@ impl RedbPathInfoService {
 }
 
 /// Ensures all tables are present.
-/// Opens a write transaction and calls open_table on PATHINFO_TABLE, which will
-/// create it if not present.
-fn create_schema(db: &redb::Database) -> Result<(), redb::Error> {
+/// Opens a write transaction and calls open_table on PATHINFO_TABLE (and
+/// open_multimap_table on the index tables), which will create them if not
+/// present.
+/// If the index tables didn't exist yet, they're populated from the PathInfos
+/// already present.
+fn create_schema(db: &redb::Database) -> Result<(), Error> {
     let txn = db.begin_write()?;
-    txn.open_table(PATHINFO_TABLE)?;
+    let has_indices = txn
+        .list_multimap_tables()?
+        .any(|table| table.name() == REFERRERS_TABLE.name());
+    {
+        let table = txn.open_table(PATHINFO_TABLE)?;
+        let mut referrers = txn.open_multimap_table(REFERRERS_TABLE)?;
+        let mut derivers = txn.open_multimap_table(DERIVERS_TABLE)?;
+
+        if !has_indices {
+            for elem in table.iter()? {
+                let (digest, pathinfo_bytes) = elem?;
+                insert_indices(
+                    &mut referrers,
+                    &mut derivers,
+                    &digest.value(),
+                    &decode_path_info(&pathinfo_bytes.value())?,
+                )?;
+            }
+        }
+    }
     txn.commit()?;
 
     Ok(())
 }
 
+/// Adds the references and deriver of the PathInfo stored at the given digest
+/// to the index tables.
+fn insert_indices(
+    referrers: &mut MultimapTable<[u8; 20], [u8; 20]>,
+    derivers: &mut MultimapTable<[u8; 20], [u8; 20]>,
+    digest: &[u8; 20],
+    path_info: &PathInfo,
+) -> Result<(), Error> {
+    for reference in &path_info.references {
+        referrers.insert(reference.digest(), digest)?;
+    }
+    if let Some(deriver) = &path_info.deriver {
+        derivers.insert(deriver.digest(), digest)?;
+    }
+
+    Ok(())
+}
+
+/// Removes the references and deriver of the PathInfo stored at the given
+/// digest from the index tables.
+fn remove_indices(
+    referrers: &mut MultimapTable<[u8; 20], [u8; 20]>,
+    derivers: &mut MultimapTable<[u8; 20], [u8; 20]>,
+    digest: &[u8; 20],
+    path_info: &PathInfo,
+) -> Result<(), Error> {
+    for reference in &path_info.references {
+        referrers.remove(reference.digest(), digest)?;
+    }
+    if let Some(deriver) = &path_info.deriver {
+        derivers.remove(deriver.digest(), digest)?;
+    }
+
+    Ok(())
+}
+
+/// Decodes a PathInfo stored in [PATHINFO_TABLE].
+fn decode_path_info(pathinfo_bytes: &[u8]) -> Result<PathInfo, Error> {
+    proto::PathInfo::decode(pathinfo_bytes)
+        .map_err(|e| {
+            warn!(err=%e, "failed to decode stored PathInfo");
+            Error::StorageError("failed to decode stored PathInfo".to_string())
+        })?
+        .try_into()
+        .map_err(|e| Error::StorageError(format!("Invalid path info: {e}")))
+}
+
 #[async_trait]
 impl PathInfoService for RedbPathInfoService {
     #[instrument(level = "trace", skip_all, fields(path_info.digest = BASE64.encode(&digest)))]
@@ -77,15 +161,7 @@ impl PathInfoService for RedbPathInfoService {
                 let txn = db.begin_read()?;
                 let table = txn.open_table(PATHINFO_TABLE)?;
                 match table.get(digest)? {
-                    Some(pathinfo_bytes) => Ok(Some(
-                        proto::PathInfo::decode(pathinfo_bytes.value().as_slice())
-                            .map_err(|e| {
-                                warn!(err=%e, "failed to decode stored PathInfo");
-                                Error::StorageError("failed to decode stored PathInfo".to_string())
-                            })?
-                            .try_into()
-                            .map_err(|e| Error::StorageError(format!("Invalid path info: {e}")))?,
-                    )),
+                    Some(pathinfo_bytes) => Ok(Some(decode_path_info(&pathinfo_bytes.value())?)),
                     None => Ok(None),
                 }
             }
@@ -93,6 +169,26 @@ impl PathInfoService for RedbPathInfoService {
         .await?
     }
 
+    #[instrument(level = "trace", skip_all, fields(path_info.count = digests.len()))]
+    async fn get_many(&self, digests: &[[u8; 20]]) -> Result<Vec<Option<PathInfo>>, Error> {
+        let db = self.db.clone();
+        let digests = digests.to_vec();
+
+        // Look up all digests in a single read transaction.
+        tokio::task::spawn_blocking(move || {
+            let txn = db.begin_read()?;
+            let table = txn.open_table(PATHINFO_TABLE)?;
+            digests
+                .into_iter()
+                .map(|digest| match table.get(digest)? {
+                    Some(pathinfo_bytes) => Ok(Some(decode_path_info(&pathinfo_bytes.value())?)),
+                    None => Ok(None),
+                })
+                .collect()
+        })
+        .await?
+    }
+
     #[instrument(level = "trace", skip_all, fields(path_info.root_node = ?path_info.node))]
     async fn put(&self, path_info: PathInfo) -> Result<PathInfo, Error> {
         let db = self.db.clone();
@@ -100,18 +196,30 @@ impl PathInfoService for RedbPathInfoService {
         tokio::task::spawn_blocking({
             let path_info = path_info.clone();
             move || -> Result<(), Error> {
+                let digest = *path_info.store_path.digest();
                 let txn = db.begin_write()?;
                 {
                     let mut table = txn.open_table(PATHINFO_TABLE)?;
-                    table
+                    let mut referrers = txn.open_multimap_table(REFERRERS_TABLE)?;
+                    let mut derivers = txn.open_multimap_table(DERIVERS_TABLE)?;
+
+                    let previous = table
                         .insert(
-                            *path_info.store_path.digest(),
-                            proto::PathInfo::from(path_info).encode_to_vec(),
+                            digest,
+                            proto::PathInfo::from(path_info.clone()).encode_to_vec(),
                         )
                         .map_err(|e| {
                             warn!(err=%e, "failed to insert PathInfo");
                             Error::StorageError("failed to insert PathInfo".to_string())
-                        })?;
+                        })?
+                        .map(|previous| decode_path_info(&previous.value()))
+                        .transpose()?;
+
+                    // Replace the index entries of the PathInfo we overwrote.
+                    if let Some(previous) = previous {
+                        remove_indices(&mut referrers, &mut derivers, &digest, &previous)?;
+                    }
+                    insert_indices(&mut referrers, &mut derivers, &digest, &path_info)?;
                 }
                 Ok(txn.commit()?)
             }
@@ -155,6 +263,81 @@ impl PathInfoService for RedbPathInfoService {
 
         ReceiverStream::from(rx).boxed()
     }
+
+    fn list_referrers(&self, digest: [u8; 20]) -> BoxStream<'static, Result<PathInfo, Error>> {
+        list_by_index(self.db.clone(), REFERRERS_TABLE, digest)
+    }
+
+    fn list_by_deriver(
+        &self,
+        deriver_digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        list_by_index(self.db.clone(), DERIVERS_TABLE, deriver_digest)
+    }
+
+    #[instrument(level = "trace", skip_all, fields(path_info.digest = BASE64.encode(&digest)))]
+    async fn delete(&self, digest: [u8; 20]) -> Result<(), Error> {
+        let db = self.db.clone();
+
+        tokio::task::spawn_blocking(move || -> Result<(), Error> {
+            let txn = db.begin_write()?;
+            {
+                let mut table = txn.open_table(PATHINFO_TABLE)?;
+                let previous = table.remove(digest)?.map(|previous| previous.value());
+                if let Some(previous) = previous {
+                    let previous = decode_path_info(&previous)?;
+                    remove_indices(
+                        &mut txn.open_multimap_table(REFERRERS_TABLE)?,
+                        &mut txn.open_multimap_table(DERIVERS_TABLE)?,
+                        &digest,
+                        &previous,
+                    )?;
+                }
+            }
+            Ok(txn.commit()?)
+        })
+        .await?
+    }
+}
+
+/// Yields all PathInfos whose output hashes are stored at the given key in the
+/// given index table.
+fn list_by_index(
+    db: Arc<Database>,
+    index: MultimapTableDefinition<'static, [u8; 20], [u8; 20]>,
+    key: [u8; 20],
+) -> BoxStream<'static, Result<PathInfo, Error>> {
+    let (tx, rx) = tokio::sync::mpsc::channel(50);
+
+    // Spawn a blocking task which writes all matching PathInfos to tx.
+    tokio::task::spawn_blocking(move || {
+        let res = (|| -> Result<(), Error> {
+            let read_txn = db.begin_read()?;
+            let index = read_txn.open_multimap_table(index)?;
+            let table = read_txn.open_table(PATHINFO_TABLE)?;
+
+            for digest in index.get(key)? {
+                let Some(pathinfo_bytes) = table.get(digest?.value())? else {
+                    continue;
+                };
+                if tx
+                    .blocking_send(Ok(decode_path_info(&pathinfo_bytes.value())?))
+                    .is_err()
+                {
+                    // The receiver went away.
+                    return Ok(());
+                }
+            }
+
+            Ok(())
+        })();
+
+        if let Err(e) = res {
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+
+    ReceiverStream::from(rx).boxed()
 }
 
 #[derive(serde::Deserialize)]
@@ -219,3 +402,80 @@ impl ServiceBuilder for RedbPathInfoServiceConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use prost::Message;
+    use redb::{ReadableMultimapTable, ReadableTableMetadata};
+
+    use super::{RedbPathInfoService, DERIVERS_TABLE, PATHINFO_TABLE, REFERRERS_TABLE};
+    use crate::pathinfoservice::{PathInfo, PathInfoService};
+    use crate::proto;
+    use crate::tests::fixtures::{DUMMY_DERIVER, DUMMY_PATH_DIGEST, PATH_INFO_WITH_DERIVER};
+
+    /// Opening a database written before the index tables existed populates
+    /// them from the PathInfos already stored.
+    #[tokio::test]
+    async fn reindex_existing() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = tmpdir.path().join("pathinfo.redb");
+
+        // Write a database only containing the PathInfo table.
+        {
+            let db = redb::Database::create(&path).unwrap();
+            let txn = db.begin_write().unwrap();
+            txn.open_table(PATHINFO_TABLE)
+                .unwrap()
+                .insert(
+                    DUMMY_PATH_DIGEST,
+                    proto::PathInfo::from(PATH_INFO_WITH_DERIVER.clone()).encode_to_vec(),
+                )
+                .unwrap();
+            txn.commit().unwrap();
+        }
+
+        let svc = RedbPathInfoService::new(path).await.unwrap();
+
+        let referrers: Vec<PathInfo> = svc
+            .list_referrers(DUMMY_PATH_DIGEST)
+            .try_collect()
+            .await
+            .expect("must succeed");
+        assert_eq!(vec![PATH_INFO_WITH_DERIVER.clone()], referrers);
+
+        let outputs: Vec<PathInfo> = svc
+            .list_by_deriver(*DUMMY_DERIVER.digest())
+            .try_collect()
+            .await
+            .expect("must succeed");
+        assert_eq!(vec![PATH_INFO_WITH_DERIVER.clone()], outputs);
+    }
+
+    /// Deleting a PathInfo removes its entries from the index tables.
+    #[tokio::test]
+    async fn delete_removes_indices() {
+        let svc = RedbPathInfoService::new_temporary().unwrap();
+
+        svc.put(PATH_INFO_WITH_DERIVER.clone())
+            .await
+            .expect("must succeed");
+        svc.delete(DUMMY_PATH_DIGEST).await.expect("must succeed");
+
+        let txn = svc.db.begin_read().unwrap();
+        assert_eq!(
+            0,
+            txn.open_multimap_table(REFERRERS_TABLE)
+                .unwrap()
+                .len()
+                .unwrap()
+        );
+        assert_eq!(
+            0,
+            txn.open_multimap_table(DERIVERS_TABLE)
+                .unwrap()
+                .len()
+                .unwrap()
+        );
+    }
+}
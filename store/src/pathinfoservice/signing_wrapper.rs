@@ -50,6 +50,11 @@ where
         self.inner.get(digest).await
     }
 
+    #[instrument(level = "trace", skip_all, fields(path_info.count = digests.len()))]
+    async fn get_many(&self, digests: &[[u8; 20]]) -> Result<Vec<Option<PathInfo>>, Error> {
+        self.inner.get_many(digests).await
+    }
+
     async fn put(&self, mut path_info: PathInfo) -> Result<PathInfo, Error> {
         path_info.signatures.push({
             let mut nar_info = path_info.to_narinfo();
@@ -73,6 +78,21 @@ where
     fn list(&self) -> BoxStream<'static, Result<PathInfo, Error>> {
         self.inner.list()
     }
+
+    fn list_referrers(&self, digest: [u8; 20]) -> BoxStream<'static, Result<PathInfo, Error>> {
+        self.inner.list_referrers(digest)
+    }
+
+    fn list_by_deriver(
+        &self,
+        deriver_digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        self.inner.list_by_deriver(deriver_digest)
+    }
+
+    async fn delete(&self, digest: [u8; 20]) -> Result<(), Error> {
+        self.inner.delete(digest).await
+    }
 }
 
 /// [ServiceBuilder] implementation that builds a [SigningPathInfoService] that signs narinfos using
@@ -0,0 +1,564 @@
+use super::{PathInfo, PathInfoService};
+use async_stream::try_stream;
+use data_encoding::BASE64;
+use futures::stream::BoxStream;
+use nix_compat::{narinfo::Signature, nixhash::CAHash, store_path::StorePath};
+use prost::Message;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tonic::async_trait;
+use tracing::{instrument, warn};
+use tvix_castore::{
+    composition::{CompositionContext, ServiceBuilder},
+    proto as castorepb, Error,
+};
+
+/// How long to wait for locks held by other connections to the same database
+/// before giving up with SQLITE_BUSY.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The schema of the database.
+///
+/// Unlike the Nix database, this keeps the castore root node of each store
+/// path. References and derivers live in separate tables, indexed by the
+/// digest they point to, so they can be queried in reverse.
+/// Store paths are kept in their absolute form (and derivers with their `.drv`
+/// suffix), to make ad-hoc queries more readable.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS path_infos (
+    digest BLOB PRIMARY KEY NOT NULL,
+    store_path TEXT NOT NULL,
+    -- protobuf-encoded castore Node, with an empty name
+    node BLOB NOT NULL,
+    nar_size INTEGER NOT NULL,
+    nar_sha256 BLOB NOT NULL,
+    -- space-separated, like in the Nix database
+    signatures TEXT NOT NULL,
+    ca TEXT
+) STRICT;
+
+CREATE TABLE IF NOT EXISTS refs (
+    referrer BLOB NOT NULL REFERENCES path_infos(digest) ON DELETE CASCADE,
+    idx INTEGER NOT NULL,
+    reference_digest BLOB NOT NULL,
+    reference TEXT NOT NULL,
+    PRIMARY KEY (referrer, idx)
+) STRICT;
+CREATE INDEX IF NOT EXISTS refs_reference_digest ON refs(reference_digest);
+
+CREATE TABLE IF NOT EXISTS derivers (
+    output BLOB PRIMARY KEY NOT NULL REFERENCES path_infos(digest) ON DELETE CASCADE,
+    deriver_digest BLOB NOT NULL,
+    deriver TEXT NOT NULL
+) STRICT;
+CREATE INDEX IF NOT EXISTS derivers_deriver_digest ON derivers(deriver_digest);
+";
+
+/// PathInfoService implementation using sqlite under the hood.
+/// See [SCHEMA] for how PathInfos are stored.
+#[derive(Clone)]
+pub struct SqlitePathInfoService {
+    // rusqlite connections can't be shared between threads, so we serialize
+    // access through a mutex, and move it into spawn_blocking.
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqlitePathInfoService {
+    /// Constructs a new instance using the specified file system path for
+    /// storage.
+    pub async fn new(path: PathBuf) -> Result<Self, Error> {
+        if path == std::path::Path::new("/") {
+            return Err(Error::StorageError(
+                "cowardly refusing to open / with sqlite".to_string(),
+            ));
+        }
+
+        let conn = tokio::task::spawn_blocking(|| -> Result<_, rusqlite::Error> {
+            let conn = Connection::open(path)?;
+            // Wait for other processes holding a lock on the database, rather
+            // than failing with SQLITE_BUSY right away.
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            // WAL allows other processes to read while we write.
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            create_schema(&conn)?;
+            Ok(conn)
+        })
+        .await??;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Constructs a new instance using an in-memory database.
+    pub fn new_temporary() -> Result<Self, Error> {
+        let conn = Connection::open_in_memory()?;
+        create_schema(&conn)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs the given closure with the connection, in a blocking task.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| Error::StorageError("sqlite connection poisoned".to_string()))?;
+            f(&mut conn)
+        })
+        .await?
+    }
+
+    /// Yields the PathInfos whose digests are returned by the given query on
+    /// one of the reverse indices, parametrized with the given digest.
+    fn list_by_index(
+        &self,
+        query: &'static str,
+        digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        let path_info_service = self.clone();
+
+        Box::pin(try_stream! {
+            // Like in list(), retrieve all digests at once, and look up
+            // PathInfos one by one.
+            let digests = path_info_service
+                .with_conn(move |conn| {
+                    let mut stmt = conn.prepare_cached(query)?;
+                    let digests = stmt
+                        .query_map(params![&digest[..]], |row| row.get::<_, [u8; 20]>(0))?
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(digests)
+                })
+                .await?;
+
+            for digest in digests {
+                if let Some(path_info) = path_info_service.get(digest).await? {
+                    yield path_info;
+                }
+            }
+        })
+    }
+}
+
+/// Ensures all tables are present, and foreign keys are enforced.
+fn create_schema(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.pragma_update(None, "foreign_keys", true)?;
+    conn.execute_batch(SCHEMA)
+}
+
+fn invalid(field: &str, e: impl std::fmt::Display) -> Error {
+    warn!(err=%e, field, "invalid stored PathInfo");
+    Error::StorageError(format!("invalid stored PathInfo {field}: {e}"))
+}
+
+/// Reads a PathInfo, including its references and deriver.
+fn read_path_info(conn: &Connection, digest: &[u8; 20]) -> Result<Option<PathInfo>, Error> {
+    let Some((store_path, node, nar_size, nar_sha256, signatures, ca)) = conn
+        .prepare_cached(
+            "SELECT store_path, node, nar_size, nar_sha256, signatures, ca
+             FROM path_infos WHERE digest = ?1",
+        )?
+        .query_row(params![&digest[..]], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Vec<u8>>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, Vec<u8>>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, Option<String>>(5)?,
+            ))
+        })
+        .optional()?
+    else {
+        return Ok(None);
+    };
+
+    let references = conn
+        .prepare_cached("SELECT reference FROM refs WHERE referrer = ?1 ORDER BY idx")?
+        .query_map(params![&digest[..]], |row| row.get::<_, String>(0))?
+        .map(|reference| {
+            StorePath::from_absolute_path(reference?.as_bytes())
+                .map_err(|e| invalid("reference", e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let deriver = conn
+        .prepare_cached("SELECT deriver FROM derivers WHERE output = ?1")?
+        .query_row(params![&digest[..]], |row| row.get::<_, String>(0))
+        .optional()?
+        .map(|deriver| {
+            deriver
+                .strip_suffix(".drv")
+                .ok_or_else(|| invalid("deriver", "missing .drv suffix"))
+                .and_then(|deriver| {
+                    StorePath::from_absolute_path(deriver.as_bytes())
+                        .map_err(|e| invalid("deriver", e))
+                })
+        })
+        .transpose()?;
+
+    Ok(Some(PathInfo {
+        store_path: StorePath::from_absolute_path(store_path.as_bytes())
+            .map_err(|e| invalid("store_path", e))?,
+        node: castorepb::Node::decode(node.as_slice())
+            .map_err(|e| invalid("node", e))?
+            .try_into_anonymous_node()
+            .map_err(|e| invalid("node", e))?,
+        references,
+        nar_size,
+        nar_sha256: nar_sha256
+            .try_into()
+            .map_err(|_| invalid("nar_sha256", "invalid length"))?,
+        signatures: signatures
+            .split_whitespace()
+            .map(|signature| Signature::parse(signature).map_err(|e| invalid("signatures", e)))
+            .collect::<Result<Vec<_>, _>>()?,
+        deriver,
+        ca: ca
+            .map(|ca| CAHash::from_nix_hex_str(&ca).ok_or_else(|| invalid("ca", ca)))
+            .transpose()?,
+    }))
+}
+
+/// Writes a PathInfo, replacing an existing one with the same digest.
+fn write_path_info(conn: &mut Connection, path_info: &PathInfo) -> Result<(), Error> {
+    let digest = &path_info.store_path.digest()[..];
+    let txn = conn.transaction()?;
+
+    // This also removes the references and deriver, through the foreign keys.
+    txn.prepare_cached("DELETE FROM path_infos WHERE digest = ?1")?
+        .execute(params![digest])?;
+
+    txn.prepare_cached(
+        "INSERT INTO path_infos (digest, store_path, node, nar_size, nar_sha256, signatures, ca)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?
+    .execute(params![
+        digest,
+        path_info.store_path.to_absolute_path(),
+        castorepb::Node::from_name_and_node("".into(), path_info.node.clone()).encode_to_vec(),
+        path_info.nar_size,
+        &path_info.nar_sha256[..],
+        path_info
+            .signatures
+            .iter()
+            .map(|signature| signature.to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+        path_info.ca.as_ref().map(CAHash::to_nix_nixbase32_string),
+    ])?;
+
+    {
+        let mut stmt = txn.prepare_cached(
+            "INSERT INTO refs (referrer, idx, reference_digest, reference)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (idx, reference) in path_info.references.iter().enumerate() {
+            stmt.execute(params![
+                digest,
+                idx,
+                &reference.digest()[..],
+                reference.to_absolute_path()
+            ])?;
+        }
+    }
+
+    if let Some(deriver) = &path_info.deriver {
+        txn.prepare_cached(
+            "INSERT INTO derivers (output, deriver_digest, deriver) VALUES (?1, ?2, ?3)",
+        )?
+        .execute(params![
+            digest,
+            &deriver.digest()[..],
+            format!("{}.drv", deriver.to_absolute_path())
+        ])?;
+    }
+
+    Ok(txn.commit()?)
+}
+
+#[async_trait]
+impl PathInfoService for SqlitePathInfoService {
+    #[instrument(level = "trace", skip_all, fields(path_info.digest = BASE64.encode(&digest)))]
+    async fn get(&self, digest: [u8; 20]) -> Result<Option<PathInfo>, Error> {
+        self.with_conn(move |conn| read_path_info(conn, &digest))
+            .await
+    }
+
+    #[instrument(level = "trace", skip_all, fields(path_info.count = digests.len()))]
+    async fn get_many(&self, digests: &[[u8; 20]]) -> Result<Vec<Option<PathInfo>>, Error> {
+        let digests = digests.to_vec();
+
+        // Look up all digests in a single read transaction.
+        self.with_conn(move |conn| {
+            let txn = conn.transaction()?;
+            digests
+                .iter()
+                .map(|digest| read_path_info(&txn, digest))
+                .collect()
+        })
+        .await
+    }
+
+    #[instrument(level = "trace", skip_all, fields(path_info.root_node = ?path_info.node))]
+    async fn put(&self, path_info: PathInfo) -> Result<PathInfo, Error> {
+        self.with_conn({
+            let path_info = path_info.clone();
+            move |conn| {
+                write_path_info(conn, &path_info).map_err(|e| {
+                    warn!(err=%e, "failed to insert PathInfo");
+                    Error::StorageError("failed to insert PathInfo".to_string())
+                })
+            }
+        })
+        .await?;
+
+        Ok(path_info)
+    }
+
+    fn list(&self) -> BoxStream<'static, Result<PathInfo, Error>> {
+        let path_info_service = self.clone();
+
+        Box::pin(try_stream! {
+            // Retrieve all digests at once, and look up PathInfos one by one,
+            // so we don't hold the connection while the stream is consumed.
+            let digests = path_info_service
+                .with_conn(|conn| {
+                    let mut stmt = conn.prepare("SELECT digest FROM path_infos")?;
+                    let digests = stmt
+                        .query_map([], |row| row.get::<_, [u8; 20]>(0))?
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(digests)
+                })
+                .await?;
+
+            for digest in digests {
+                // Skip PathInfos removed in the meantime.
+                if let Some(path_info) = path_info_service.get(digest).await? {
+                    yield path_info;
+                }
+            }
+        })
+    }
+
+    fn list_referrers(&self, digest: [u8; 20]) -> BoxStream<'static, Result<PathInfo, Error>> {
+        self.list_by_index(
+            "SELECT DISTINCT referrer FROM refs WHERE reference_digest = ?1",
+            digest,
+        )
+    }
+
+    fn list_by_deriver(
+        &self,
+        deriver_digest: [u8; 20],
+    ) -> BoxStream<'static, Result<PathInfo, Error>> {
+        self.list_by_index(
+            "SELECT output FROM derivers WHERE deriver_digest = ?1",
+            deriver_digest,
+        )
+    }
+
+    #[instrument(level = "trace", skip_all, fields(path_info.digest = BASE64.encode(&digest)))]
+    async fn delete(&self, digest: [u8; 20]) -> Result<(), Error> {
+        self.with_conn(move |conn| {
+            conn.prepare_cached("DELETE FROM path_infos WHERE digest = ?1")?
+                .execute(params![&digest[..]])?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SqlitePathInfoServiceConfig {
+    is_temporary: bool,
+    #[serde(default)]
+    /// required when is_temporary = false
+    path: Option<PathBuf>,
+}
+
+impl TryFrom<url::Url> for SqlitePathInfoServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // sqlite doesn't support host, and a path can be provided (otherwise it'll live in memory only)
+        if url.has_host() {
+            return Err(Error::StorageError("no host allowed".to_string()).into());
+        }
+
+        Ok(if url.path().is_empty() {
+            SqlitePathInfoServiceConfig {
+                is_temporary: true,
+                path: None,
+            }
+        } else {
+            SqlitePathInfoServiceConfig {
+                is_temporary: false,
+                path: Some(url.path().into()),
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for SqlitePathInfoServiceConfig {
+    type Output = dyn PathInfoService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn PathInfoService>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        match self {
+            SqlitePathInfoServiceConfig {
+                is_temporary: true,
+                path: None,
+            } => Ok(Arc::new(
+                tokio::task::spawn_blocking(SqlitePathInfoService::new_temporary).await??,
+            )),
+            SqlitePathInfoServiceConfig {
+                is_temporary: true,
+                path: Some(_),
+            } => Err(Error::StorageError(
+                "Temporary SqlitePathInfoService can not have path".into(),
+            )
+            .into()),
+            SqlitePathInfoServiceConfig {
+                is_temporary: false,
+                path: None,
+            } => Err(Error::StorageError("SqlitePathInfoService is missing path".into()).into()),
+            SqlitePathInfoServiceConfig {
+                is_temporary: false,
+                path: Some(path),
+            } => Ok(Arc::new(SqlitePathInfoService::new(path.to_owned()).await?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nix_compat::narinfo::Signature;
+    use nix_compat::store_path::StorePath;
+    use rusqlite::params;
+
+    use super::SqlitePathInfoService;
+    use crate::pathinfoservice::{PathInfo, PathInfoService};
+    use crate::tests::fixtures::{
+        DUMMY_DERIVER, DUMMY_PATH, DUMMY_PATH_DIGEST, PATH_INFO, PATH_INFO_WITH_DERIVER,
+    };
+
+    /// Deriver and signatures are stored and retrieved, and references and
+    /// derivers are queryable by their digest.
+    /// Replacing or removing a PathInfo also replaces or removes these.
+    #[tokio::test]
+    async fn put_get_replace_delete() {
+        let svc = SqlitePathInfoService::new_temporary().unwrap();
+
+        let deriver = &*DUMMY_DERIVER;
+        let path_info = PathInfo {
+            signatures: vec![Signature::new("cache.example.org-1".to_string(), [1; 64])],
+            ..PATH_INFO_WITH_DERIVER.clone()
+        };
+
+        svc.put(path_info.clone()).await.expect("put must succeed");
+        assert_eq!(
+            Some(path_info.clone()),
+            svc.get(DUMMY_PATH_DIGEST).await.expect("get must succeed")
+        );
+
+        let count_rows = |table: &'static str, column: &'static str, digest: Vec<u8>| {
+            let conn = svc.conn.lock().unwrap();
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM {table} WHERE {column} = ?1"),
+                params![digest],
+                |row| row.get::<_, u64>(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            1,
+            count_rows("refs", "reference_digest", DUMMY_PATH.digest().to_vec())
+        );
+        assert_eq!(
+            1,
+            count_rows("derivers", "deriver_digest", deriver.digest().to_vec())
+        );
+
+        // Replace it with a PathInfo without references and deriver.
+        let path_info = PathInfo {
+            references: vec![],
+            deriver: None,
+            ..path_info
+        };
+        svc.put(path_info.clone()).await.expect("put must succeed");
+        assert_eq!(
+            Some(path_info),
+            svc.get(DUMMY_PATH_DIGEST).await.expect("get must succeed")
+        );
+        assert_eq!(
+            0,
+            count_rows("refs", "reference_digest", DUMMY_PATH.digest().to_vec())
+        );
+        assert_eq!(
+            0,
+            count_rows("derivers", "deriver_digest", deriver.digest().to_vec())
+        );
+
+        svc.put(PATH_INFO.clone()).await.expect("put must succeed");
+        svc.delete(DUMMY_PATH_DIGEST)
+            .await
+            .expect("delete must succeed");
+        assert!(svc
+            .get(DUMMY_PATH_DIGEST)
+            .await
+            .expect("get must succeed")
+            .is_none());
+        assert_eq!(
+            0,
+            count_rows("refs", "reference_digest", DUMMY_PATH.digest().to_vec())
+        );
+    }
+
+    /// Two instances writing to the same database file wait for each other's
+    /// locks, rather than failing with SQLITE_BUSY.
+    #[tokio::test]
+    async fn concurrent_writers() {
+        let tmpdir = tempfile::TempDir::new().unwrap();
+        let path = tmpdir.path().join("pathinfo.sqlite");
+
+        let svc_a = SqlitePathInfoService::new(path.clone()).await.unwrap();
+        let svc_b = SqlitePathInfoService::new(path).await.unwrap();
+
+        let put_many = |svc: SqlitePathInfoService, offset: u8| async move {
+            for i in 0..50u8 {
+                let store_path =
+                    StorePath::from_name_and_digest_fixed("foo", [offset.wrapping_add(i); 20])
+                        .unwrap();
+                svc.put(PathInfo {
+                    store_path,
+                    ..PATH_INFO.clone()
+                })
+                .await
+                .expect("put must succeed");
+            }
+        };
+
+        tokio::join!(put_many(svc_a.clone(), 0), put_many(svc_b, 100));
+
+        assert!(svc_a
+            .get([120; 20])
+            .await
+            .expect("get must succeed")
+            .is_some());
+    }
+}
@@ -6,15 +6,20 @@ use futures::TryStreamExt;
 use rstest::*;
 use rstest_reuse::{self, *};
 
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use tvix_castore::Error;
+
 use super::{PathInfo, PathInfoService};
 use crate::pathinfoservice::redb::RedbPathInfoService;
-use crate::pathinfoservice::MemoryPathInfoService;
-use crate::tests::fixtures::{DUMMY_PATH_DIGEST, PATH_INFO};
+use crate::pathinfoservice::{LruPathInfoService, MemoryPathInfoService, SqlitePathInfoService};
+use crate::tests::fixtures::{DUMMY_DERIVER, DUMMY_PATH_DIGEST, PATH_INFO, PATH_INFO_WITH_DERIVER};
 
 use crate::pathinfoservice::test_signing_service;
 
 mod utils;
 pub use self::utils::make_grpc_path_info_service_client;
+use self::utils::make_grpc_path_info_service_client_with;
 
 #[cfg(all(feature = "cloud", feature = "integration"))]
 use self::utils::make_bigtable_path_info_service;
@@ -27,6 +32,7 @@ use self::utils::make_bigtable_path_info_service;
     svc
 })]
 #[case::redb(RedbPathInfoService::new_temporary().unwrap())]
+#[case::sqlite(SqlitePathInfoService::new_temporary().unwrap())]
 #[case::signing(test_signing_service())]
 #[cfg_attr(all(feature = "cloud",feature="integration"), case::bigtable(make_bigtable_path_info_service().await))]
 pub fn path_info_services(#[case] svc: impl PathInfoService) {}
@@ -76,9 +82,221 @@ async fn put_get(svc: impl PathInfoService) {
     );
 }
 
+/// Put a PathInfo into the store, get it back with [PathInfoService::get_many],
+/// alongside a non-existent one.
+#[apply(path_info_services)]
+#[tokio::test]
+async fn put_get_many(svc: impl PathInfoService) {
+    svc.put(PATH_INFO.clone()).await.expect("must succeed");
+
+    let resp = svc
+        .get_many(&[DUMMY_PATH_DIGEST, [1; 20], DUMMY_PATH_DIGEST])
+        .await
+        .expect("must succeed");
+
+    assert_eq!(
+        vec![Some(PATH_INFO.clone()), None, Some(PATH_INFO.clone())],
+        resp.into_iter()
+            .map(|path_info| path_info.map(strip_signatures))
+            .collect::<Vec<_>>()
+    );
+
+    assert!(svc.get_many(&[]).await.expect("must succeed").is_empty());
+}
+
+/// Request more PathInfos with [PathInfoService::get_many] than fit in a
+/// single batch, and ensure they're still returned in order.
+#[apply(path_info_services)]
+#[tokio::test]
+async fn put_get_many_large(svc: impl PathInfoService) {
+    svc.put(PATH_INFO.clone()).await.expect("must succeed");
+
+    let digests: Vec<[u8; 20]> = (0..2500)
+        .map(|i| {
+            if i % 3 == 0 {
+                DUMMY_PATH_DIGEST
+            } else {
+                [1; 20]
+            }
+        })
+        .collect();
+
+    let resp = svc.get_many(&digests).await.expect("must succeed");
+
+    assert_eq!(digests.len(), resp.len());
+    for (i, path_info) in resp.into_iter().enumerate() {
+        assert_eq!(
+            (i % 3 == 0).then(|| PATH_INFO.clone()),
+            path_info.map(strip_signatures),
+            "unexpected response at position {}",
+            i
+        );
+    }
+}
+
 fn strip_signatures(path_info: PathInfo) -> PathInfo {
     PathInfo {
         signatures: vec![],
         ..path_info
     }
 }
+
+/// Put a PathInfo into the store, delete it, and ensure it's gone.
+/// Not all implementations support deletion, so this only runs against the
+/// ones that do.
+#[rstest]
+#[case::memory(MemoryPathInfoService::default())]
+#[case::redb(RedbPathInfoService::new_temporary().unwrap())]
+#[case::signing(test_signing_service())]
+#[tokio::test]
+async fn put_delete(#[case] svc: impl PathInfoService) {
+    svc.put(PATH_INFO.clone()).await.expect("must succeed");
+
+    svc.delete(DUMMY_PATH_DIGEST).await.expect("must succeed");
+    assert!(svc
+        .get(DUMMY_PATH_DIGEST)
+        .await
+        .expect("must succeed")
+        .is_none());
+
+    // Deleting a PathInfo that doesn't exist is not an error.
+    svc.delete(DUMMY_PATH_DIGEST).await.expect("must succeed");
+}
+
+/// Put a PathInfo with a deriver into the store, and ensure it can be found
+/// by its references and deriver.
+/// Replacing it with one without references and deriver needs to remove it
+/// from these results.
+/// Not all implementations support these queries, so this only runs against
+/// the ones that do.
+#[rstest]
+#[case::memory(MemoryPathInfoService::default())]
+#[case::grpc({
+    let (_, _, svc) = make_grpc_path_info_service_client().await;
+    svc
+})]
+#[case::redb(RedbPathInfoService::new_temporary().unwrap())]
+#[case::sqlite(SqlitePathInfoService::new_temporary().unwrap())]
+#[case::signing(test_signing_service())]
+#[tokio::test]
+async fn put_list_referrers_by_deriver(#[case] svc: impl PathInfoService) {
+    let deriver = &*DUMMY_DERIVER;
+    let path_info = PATH_INFO_WITH_DERIVER.clone();
+    svc.put(path_info.clone()).await.expect("must succeed");
+
+    // PATH_INFO refers to itself.
+    let referrers: Vec<PathInfo> = svc
+        .list_referrers(DUMMY_PATH_DIGEST)
+        .try_collect()
+        .await
+        .expect("must succeed");
+    assert_eq!(
+        vec![path_info.clone()],
+        referrers
+            .into_iter()
+            .map(strip_signatures)
+            .collect::<Vec<_>>()
+    );
+
+    let outputs: Vec<PathInfo> = svc
+        .list_by_deriver(*deriver.digest())
+        .try_collect()
+        .await
+        .expect("must succeed");
+    assert_eq!(
+        vec![path_info.clone()],
+        outputs
+            .into_iter()
+            .map(strip_signatures)
+            .collect::<Vec<_>>()
+    );
+
+    // Nothing refers to, or was produced by another path.
+    assert!(svc
+        .list_referrers([1; 20])
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("must succeed")
+        .is_empty());
+    assert!(svc
+        .list_by_deriver(DUMMY_PATH_DIGEST)
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("must succeed")
+        .is_empty());
+
+    // Replace it with a PathInfo without references and deriver.
+    svc.put(PathInfo {
+        references: vec![],
+        deriver: None,
+        ..path_info
+    })
+    .await
+    .expect("must succeed");
+
+    assert!(svc
+        .list_referrers(DUMMY_PATH_DIGEST)
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("must succeed")
+        .is_empty());
+    assert!(svc
+        .list_by_deriver(*deriver.digest())
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("must succeed")
+        .is_empty());
+}
+
+/// Put a PathInfo with a deriver into Bigtable, and ensure it can be found by
+/// its references and deriver.
+/// Bigtable doesn't replace existing PathInfos, so this only checks lookups.
+#[cfg(all(feature = "cloud", feature = "integration"))]
+#[tokio::test]
+async fn bigtable_put_list_referrers_by_deriver() {
+    let svc = make_bigtable_path_info_service().await;
+    svc.put(PATH_INFO_WITH_DERIVER.clone())
+        .await
+        .expect("must succeed");
+
+    let referrers: Vec<PathInfo> = svc
+        .list_referrers(DUMMY_PATH_DIGEST)
+        .try_collect()
+        .await
+        .expect("must succeed");
+    assert_eq!(vec![PATH_INFO_WITH_DERIVER.clone()], referrers);
+
+    let outputs: Vec<PathInfo> = svc
+        .list_by_deriver(*DUMMY_DERIVER.digest())
+        .try_collect()
+        .await
+        .expect("must succeed");
+    assert_eq!(vec![PATH_INFO_WITH_DERIVER.clone()], outputs);
+
+    // The index rows must not show up when listing.
+    let all: Vec<PathInfo> = svc.list().try_collect().await.expect("must succeed");
+    assert_eq!(vec![PATH_INFO_WITH_DERIVER.clone()], all);
+}
+
+/// Referrer and deriver queries an implementation doesn't support are
+/// reported as unsupported, also when going through gRPC.
+#[tokio::test]
+async fn list_referrers_by_deriver_unsupported() {
+    let (_, _, svc) = make_grpc_path_info_service_client_with(Arc::new(
+        LruPathInfoService::with_capacity(NonZeroUsize::new(1).unwrap()),
+    ))
+    .await;
+
+    assert!(matches!(
+        svc.list_referrers(DUMMY_PATH_DIGEST)
+            .try_collect::<Vec<_>>()
+            .await,
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        svc.list_by_deriver(*DUMMY_DERIVER.digest())
+            .try_collect::<Vec<_>>()
+            .await,
+        Err(Error::Unsupported(_))
+    ));
+}
@@ -21,6 +21,18 @@ pub async fn make_grpc_path_info_service_client() -> (
     impl BlobService,
     impl DirectoryService,
     GRPCPathInfoService<tonic::transport::Channel>,
+) {
+    make_grpc_path_info_service_client_with(Arc::from(MemoryPathInfoService::default())).await
+}
+
+/// Like [make_grpc_path_info_service_client], but exposing the given
+/// PathInfoService over gRPC.
+pub async fn make_grpc_path_info_service_client_with(
+    path_info_service: Arc<dyn PathInfoService>,
+) -> (
+    impl BlobService,
+    impl DirectoryService,
+    GRPCPathInfoService<tonic::transport::Channel>,
 ) {
     let (left, right) = tokio::io::duplex(64);
 
@@ -32,8 +44,6 @@ pub async fn make_grpc_path_info_service_client() -> (
         let blob_service = blob_service.clone();
         let directory_service = directory_service.clone();
         async move {
-            let path_info_service: Arc<dyn PathInfoService> =
-                Arc::from(MemoryPathInfoService::default());
             let nar_calculation_service =
                 Box::new(SimpleRenderer::new(blob_service, directory_service))
                     as Box<dyn NarCalculationService>;
@@ -1,14 +1,22 @@
 use crate::nar::{NarCalculationService, RenderError};
 use crate::pathinfoservice::{PathInfo, PathInfoService};
 use crate::proto;
-use futures::{stream::BoxStream, TryStreamExt};
-use std::ops::Deref;
-use tonic::{async_trait, Request, Response, Result, Status};
+use futures::{
+    stream::{BoxStream, TryReadyChunksError},
+    TryStreamExt,
+};
+use std::{ops::Deref, sync::Arc};
+use tonic::{async_trait, Request, Response, Result, Status, Streaming};
 use tracing::{instrument, warn};
 use tvix_castore::proto as castorepb;
 
+/// The maximum number of digests [GRPCPathInfoServiceWrapper] passes to a
+/// single [PathInfoService::get_many] call when answering a GetMany request.
+const GET_MANY_BATCH_SIZE: usize = 1000;
+
 pub struct GRPCPathInfoServiceWrapper<PS, NS> {
-    path_info_service: PS,
+    // Kept in an [Arc], so the response stream of GetMany can hold on to it.
+    path_info_service: Arc<PS>,
     // FUTUREWORK: allow exposing without allowing listing
     nar_calculation_service: NS,
 }
@@ -16,7 +24,7 @@ pub struct GRPCPathInfoServiceWrapper<PS, NS> {
 impl<PS, NS> GRPCPathInfoServiceWrapper<PS, NS> {
     pub fn new(path_info_service: PS, nar_calculation_service: NS) -> Self {
         Self {
-            path_info_service,
+            path_info_service: Arc::new(path_info_service),
             nar_calculation_service,
         }
     }
@@ -29,6 +37,9 @@ where
     NS: NarCalculationService + Send + Sync + 'static,
 {
     type ListStream = BoxStream<'static, tonic::Result<proto::PathInfo, Status>>;
+    type GetManyStream = BoxStream<'static, tonic::Result<proto::GetManyPathInfoResponse, Status>>;
+    type ListReferrersStream = BoxStream<'static, tonic::Result<proto::PathInfo, Status>>;
+    type ListByDeriverStream = BoxStream<'static, tonic::Result<proto::PathInfo, Status>>;
 
     #[instrument(skip_all)]
     async fn get(
@@ -111,6 +122,91 @@ where
 
         Ok(Response::new(Box::pin(stream)))
     }
+
+    #[instrument(skip_all, err)]
+    async fn get_many(
+        &self,
+        request: Request<Streaming<proto::GetPathInfoRequest>>,
+    ) -> Result<Response<Self::GetManyStream>, Status> {
+        // Look up the digests in batches of whatever the client already sent,
+        // so the PathInfoService can fetch them together, without having to
+        // receive the whole request stream first.
+        let path_info_service = self.path_info_service.clone();
+        let stream = request
+            .into_inner()
+            .map_err(Status::from)
+            .and_then(|req| async move {
+                match req.by_what {
+                    None => Err(Status::unimplemented("by_what needs to be specified")),
+                    Some(proto::get_path_info_request::ByWhat::ByOutputHash(output_digest)) => {
+                        <[u8; 20]>::try_from(output_digest.to_vec())
+                            .map_err(|_e| Status::invalid_argument("invalid output digest length"))
+                    }
+                }
+            })
+            .try_ready_chunks(GET_MANY_BATCH_SIZE)
+            .map_err(|TryReadyChunksError(_, e)| e)
+            .and_then(move |digests| {
+                let path_info_service = path_info_service.clone();
+                async move {
+                    let path_infos = path_info_service.get_many(&digests).await.map_err(|e| {
+                        warn!(err = %e, "failed to get PathInfos");
+                        Status::from(e)
+                    })?;
+
+                    Ok(futures::stream::iter(path_infos.into_iter().map(
+                        |path_info| {
+                            Ok(proto::GetManyPathInfoResponse {
+                                path_info: path_info.map(proto::PathInfo::from),
+                            })
+                        },
+                    )))
+                }
+            })
+            .try_flatten();
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[instrument(skip_all, err)]
+    async fn list_referrers(
+        &self,
+        request: Request<proto::ListReferrersRequest>,
+    ) -> Result<Response<Self::ListReferrersStream>, Status> {
+        let digest: [u8; 20] = request
+            .into_inner()
+            .by_output_hash
+            .to_vec()
+            .try_into()
+            .map_err(|_e| Status::invalid_argument("invalid output digest length"))?;
+
+        Ok(Response::new(Box::pin(
+            self.path_info_service
+                .list_referrers(digest)
+                .map_ok(proto::PathInfo::from)
+                .map_err(Status::from),
+        )))
+    }
+
+    #[instrument(skip_all, err)]
+    async fn list_by_deriver(
+        &self,
+        request: Request<proto::ListByDeriverRequest>,
+    ) -> Result<Response<Self::ListByDeriverStream>, Status> {
+        let deriver_digest: [u8; 20] = request
+            .into_inner()
+            .by_deriver_hash
+            .to_vec()
+            .try_into()
+            .map_err(|_e| Status::invalid_argument("invalid deriver digest length"))?;
+
+        Ok(Response::new(Box::pin(
+            self.path_info_service
+                .list_by_deriver(deriver_digest)
+                .map_ok(proto::PathInfo::from)
+                .map_err(Status::from),
+        )))
+    }
 }
 
 impl From<RenderError> for tonic::Status {
@@ -148,6 +148,16 @@ impl TryFrom<&nar_info::Ca> for nix_compat::nixhash::CAHash {
                     ConvertCAError::InvalidReferenceDigestLen(value.digest.len(), "FlatSha512")
                 })?),
             )),
+            typ if typ == nar_info::ca::Hash::GitSha1 as i32 => {
+                Self::Git(NixHash::Sha1(value.digest[..].try_into().map_err(
+                    |_| ConvertCAError::InvalidReferenceDigestLen(value.digest.len(), "GitSha1"),
+                )?))
+            }
+            typ if typ == nar_info::ca::Hash::GitSha256 as i32 => {
+                Self::Git(NixHash::Sha256(value.digest[..].try_into().map_err(
+                    |_| ConvertCAError::InvalidReferenceDigestLen(value.digest.len(), "GitSha256"),
+                )?))
+            }
             typ => return Err(ConvertCAError::UnknownHashType(typ)),
         })
     }
@@ -165,6 +175,11 @@ impl From<&nix_compat::nixhash::CAHash> for nar_info::ca::Hash {
             CAHash::Nar(NixHash::Sha256(_)) => nar_info::ca::Hash::NarSha256,
             CAHash::Nar(NixHash::Sha512(_)) => nar_info::ca::Hash::NarSha512,
             CAHash::Text(_) => nar_info::ca::Hash::TextSha256,
+            CAHash::Git(NixHash::Sha1(_)) => nar_info::ca::Hash::GitSha1,
+            CAHash::Git(NixHash::Sha256(_)) => nar_info::ca::Hash::GitSha256,
+            CAHash::Git(NixHash::Md5(_) | NixHash::Sha512(_)) => {
+                unreachable!("CAHash::Git is only parsed with sha1 and sha256 digests")
+            }
         }
     }
 }
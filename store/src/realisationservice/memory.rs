@@ -0,0 +1,59 @@
+use super::RealisationService;
+use nix_compat::realisation::{DrvOutput, Realisation};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+use tonic::async_trait;
+use tracing::instrument;
+use tvix_castore::composition::{CompositionContext, ServiceBuilder};
+use tvix_castore::Error;
+
+#[derive(Default)]
+pub struct MemoryRealisationService {
+    db: Arc<RwLock<HashMap<DrvOutput, Realisation>>>,
+}
+
+#[async_trait]
+impl RealisationService for MemoryRealisationService {
+    #[instrument(level = "trace", skip_all, fields(realisation.id = %id))]
+    async fn get(&self, id: &DrvOutput) -> Result<Option<Realisation>, Error> {
+        Ok(self.db.read().await.get(id).cloned())
+    }
+
+    #[instrument(level = "trace", skip_all, fields(realisation.id = %realisation.id))]
+    async fn put(&self, realisation: Realisation) -> Result<Realisation, Error> {
+        self.db
+            .write()
+            .await
+            .insert(realisation.id.clone(), realisation.clone());
+
+        Ok(realisation)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryRealisationServiceConfig {}
+
+impl TryFrom<url::Url> for MemoryRealisationServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // memory doesn't support host or path in the URL.
+        if url.has_host() || !url.path().is_empty() {
+            return Err(Error::StorageError("invalid url".to_string()).into());
+        }
+        Ok(MemoryRealisationServiceConfig {})
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for MemoryRealisationServiceConfig {
+    type Output = dyn RealisationService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn RealisationService>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        Ok(Arc::new(MemoryRealisationService::default()))
+    }
+}
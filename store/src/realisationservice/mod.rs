@@ -0,0 +1,36 @@
+mod from_addr;
+mod memory;
+mod redb;
+
+#[cfg(test)]
+mod tests;
+
+use auto_impl::auto_impl;
+use nix_compat::realisation::{DrvOutput, Realisation};
+use tonic::async_trait;
+use tvix_castore::composition::{Registry, ServiceBuilder};
+use tvix_castore::Error;
+
+pub use self::from_addr::from_addr;
+pub use self::memory::{MemoryRealisationService, MemoryRealisationServiceConfig};
+pub use self::redb::{RedbRealisationService, RedbRealisationServiceConfig};
+
+/// The base trait all Realisation services need to implement.
+/// They map outputs of content-addressed derivations to the store paths they
+/// were built to.
+#[async_trait]
+#[auto_impl(&, &mut, Arc, Box)]
+pub trait RealisationService: Send + Sync {
+    /// Retrieve the realisation of the given derivation output.
+    async fn get(&self, id: &DrvOutput) -> Result<Option<Realisation>, Error>;
+
+    /// Store a realisation, replacing a previously stored one with the same
+    /// id.
+    async fn put(&self, realisation: Realisation) -> Result<Realisation, Error>;
+}
+
+/// Registers the builtin RealisationService implementations with the registry
+pub(crate) fn register_realisation_services(reg: &mut Registry) {
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn RealisationService>>, MemoryRealisationServiceConfig>("memory");
+    reg.register::<Box<dyn ServiceBuilder<Output = dyn RealisationService>>, RedbRealisationServiceConfig>("redb");
+}
@@ -0,0 +1,178 @@
+use super::RealisationService;
+use nix_compat::realisation::{DrvOutput, Realisation};
+use redb::{Database, TableDefinition};
+use std::{path::PathBuf, sync::Arc};
+use tonic::async_trait;
+use tracing::{instrument, warn};
+use tvix_castore::{
+    composition::{CompositionContext, ServiceBuilder},
+    Error,
+};
+
+const REALISATION_TABLE: TableDefinition<&str, Vec<u8>> = TableDefinition::new("realisation");
+
+/// RealisationService implementation using redb under the hood.
+/// redb stores all of its data in a single file with a K/V pointing from the
+/// string representation of a [DrvOutput] to its JSON-encoded [Realisation].
+pub struct RedbRealisationService {
+    // We wrap db in an Arc to be able to move it into spawn_blocking,
+    // as discussed in https://github.com/cberner/redb/issues/789
+    db: Arc<Database>,
+}
+
+impl RedbRealisationService {
+    /// Constructs a new instance using the specified file system path for
+    /// storage.
+    pub async fn new(path: PathBuf) -> Result<Self, Error> {
+        if path == PathBuf::from("/") {
+            return Err(Error::StorageError(
+                "cowardly refusing to open / with redb".to_string(),
+            ));
+        }
+
+        let db = tokio::task::spawn_blocking(|| -> Result<_, Error> {
+            let db = redb::Database::create(path)?;
+            create_schema(&db)?;
+            Ok(db)
+        })
+        .await??;
+
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Constructs a new instance using the in-memory backend.
+    pub fn new_temporary() -> Result<Self, Error> {
+        let db =
+            redb::Database::builder().create_with_backend(redb::backends::InMemoryBackend::new())?;
+
+        create_schema(&db)?;
+
+        Ok(Self { db: Arc::new(db) })
+    }
+}
+
+/// Ensures all tables are present.
+/// Opens a write transaction and calls open_table on REALISATION_TABLE, which will
+/// create it if not present.
+fn create_schema(db: &redb::Database) -> Result<(), redb::Error> {
+    let txn = db.begin_write()?;
+    txn.open_table(REALISATION_TABLE)?;
+    txn.commit()?;
+
+    Ok(())
+}
+
+/// Decodes a Realisation stored in [REALISATION_TABLE].
+fn decode_realisation(realisation_bytes: &[u8]) -> Result<Realisation, Error> {
+    serde_json::from_slice(realisation_bytes).map_err(|e| {
+        warn!(err=%e, "failed to decode stored Realisation");
+        Error::StorageError("failed to decode stored Realisation".to_string())
+    })
+}
+
+#[async_trait]
+impl RealisationService for RedbRealisationService {
+    #[instrument(level = "trace", skip_all, fields(realisation.id = %id))]
+    async fn get(&self, id: &DrvOutput) -> Result<Option<Realisation>, Error> {
+        let db = self.db.clone();
+        let id = id.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let txn = db.begin_read()?;
+            let table = txn.open_table(REALISATION_TABLE)?;
+            match table.get(id.as_str())? {
+                Some(realisation_bytes) => {
+                    Ok(Some(decode_realisation(&realisation_bytes.value())?))
+                }
+                None => Ok(None),
+            }
+        })
+        .await?
+    }
+
+    #[instrument(level = "trace", skip_all, fields(realisation.id = %realisation.id))]
+    async fn put(&self, realisation: Realisation) -> Result<Realisation, Error> {
+        let db = self.db.clone();
+        let id = realisation.id.to_string();
+        let realisation_bytes = serde_json::to_vec(&realisation)
+            .map_err(|e| Error::StorageError(format!("failed to encode Realisation: {e}")))?;
+
+        tokio::task::spawn_blocking(move || -> Result<(), Error> {
+            let txn = db.begin_write()?;
+            {
+                let mut table = txn.open_table(REALISATION_TABLE)?;
+                table.insert(id.as_str(), realisation_bytes)?;
+            }
+            Ok(txn.commit()?)
+        })
+        .await??;
+
+        Ok(realisation)
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedbRealisationServiceConfig {
+    is_temporary: bool,
+    #[serde(default)]
+    /// required when is_temporary = false
+    path: Option<PathBuf>,
+}
+
+impl TryFrom<url::Url> for RedbRealisationServiceConfig {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(url: url::Url) -> Result<Self, Self::Error> {
+        // redb doesn't support host, and a path can be provided (otherwise it'll live in memory only)
+        if url.has_host() {
+            return Err(Error::StorageError("no host allowed".to_string()).into());
+        }
+
+        Ok(if url.path().is_empty() {
+            RedbRealisationServiceConfig {
+                is_temporary: true,
+                path: None,
+            }
+        } else {
+            RedbRealisationServiceConfig {
+                is_temporary: false,
+                path: Some(url.path().into()),
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceBuilder for RedbRealisationServiceConfig {
+    type Output = dyn RealisationService;
+    async fn build<'a>(
+        &'a self,
+        _instance_name: &str,
+        _context: &CompositionContext,
+    ) -> Result<Arc<dyn RealisationService>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        match self {
+            RedbRealisationServiceConfig {
+                is_temporary: true,
+                path: None,
+            } => Ok(Arc::new(RedbRealisationService::new_temporary()?)),
+            RedbRealisationServiceConfig {
+                is_temporary: true,
+                path: Some(_),
+            } => Err(Error::StorageError(
+                "Temporary RedbRealisationService can not have path".into(),
+            )
+            .into()),
+            RedbRealisationServiceConfig {
+                is_temporary: false,
+                path: None,
+            } => Err(Error::StorageError("RedbRealisationService is missing path".into()).into()),
+            RedbRealisationServiceConfig {
+                is_temporary: false,
+                path: Some(path),
+            } => Ok(Arc::new(
+                RedbRealisationService::new(path.to_owned()).await?,
+            )),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! This contains test scenarios that a given [RealisationService] needs to pass.
+
+use nix_compat::realisation::{DrvOutput, Realisation};
+use nix_compat::store_path::StorePath;
+use rstest::*;
+use rstest_reuse::{self, *};
+use std::collections::BTreeMap;
+
+use super::{MemoryRealisationService, RealisationService, RedbRealisationService};
+
+#[template]
+#[rstest]
+#[case::memory(MemoryRealisationService::default())]
+#[case::redb(RedbRealisationService::new_temporary().unwrap())]
+pub fn realisation_services(#[case] svc: impl RealisationService) {}
+
+fn drv_output() -> DrvOutput {
+    DrvOutput {
+        drv_hash: [0x42; 32],
+        output_name: "out".to_string(),
+    }
+}
+
+/// Trying to get a non-existent Realisation should return Ok(None).
+#[apply(realisation_services)]
+#[tokio::test]
+async fn not_found(svc: impl RealisationService) {
+    assert!(svc
+        .get(&drv_output())
+        .await
+        .expect("must succeed")
+        .is_none());
+}
+
+/// Put a Realisation into the store, get it back.
+#[apply(realisation_services)]
+#[tokio::test]
+async fn put_get(svc: impl RealisationService) {
+    let realisation = Realisation {
+        dependent_realisations: BTreeMap::new(),
+        id: drv_output(),
+        out_path: StorePath::from_bytes(b"00bgd045z0d4icpbc2yyz4gx48ak44la-foo").unwrap(),
+        signatures: vec![],
+    };
+
+    let resp = svc.put(realisation.clone()).await.expect("must succeed");
+    assert_eq!(realisation, resp);
+
+    assert_eq!(
+        Some(realisation),
+        svc.get(&drv_output()).await.expect("must succeed")
+    );
+
+    // Other outputs of the same derivation are not found.
+    assert!(svc
+        .get(&DrvOutput {
+            output_name: "dev".to_string(),
+            ..drv_output()
+        })
+        .await
+        .expect("must succeed")
+        .is_none());
+}
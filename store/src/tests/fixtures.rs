@@ -155,6 +155,43 @@ pub static PATH_INFO: LazyLock<PathInfo> = LazyLock::new(|| PathInfo {
     ca: Some(CAHash::Nar(NixHash::Sha256([0; 32]))),
 });
 
+/// The derivation [PATH_INFO_WITH_DERIVER] was produced by.
+pub static DUMMY_DERIVER: LazyLock<StorePath<String>> =
+    LazyLock::new(|| StorePath::from_bytes(b"00bgd045z0d4icpbc2yyz4gx48ak44la-foo.drv").unwrap());
+
+/// [PATH_INFO], with [DUMMY_DERIVER] as deriver.
+pub static PATH_INFO_WITH_DERIVER: LazyLock<PathInfo> = LazyLock::new(|| PathInfo {
+    deriver: Some(DUMMY_DERIVER.clone()),
+    ..PATH_INFO.clone()
+});
+
+/// Returns a store path with the given name, and a digest consisting of
+/// `digest_byte` repeated.
+pub(crate) fn store_path(name: &str, digest_byte: u8) -> StorePath<String> {
+    StorePath::from_name_and_digest_fixed(name, [digest_byte; 20]).unwrap()
+}
+
+/// Returns a PathInfo for [store_path] `name` and `digest_byte`, pointing to
+/// `node` and referring to `references`.
+/// NAR size and hash are zeroed, there's no deriver and no CA.
+pub(crate) fn path_info(
+    name: &str,
+    digest_byte: u8,
+    node: &Node,
+    references: Vec<StorePath<String>>,
+) -> PathInfo {
+    PathInfo {
+        store_path: store_path(name, digest_byte),
+        node: node.clone(),
+        references,
+        nar_size: 0,
+        nar_sha256: [0; 32],
+        signatures: vec![],
+        deriver: None,
+        ca: None,
+    }
+}
+
 #[fixture]
 pub(crate) fn blob_service() -> Arc<dyn BlobService> {
     Arc::from(MemoryBlobService::default())
@@ -1,3 +1,5 @@
 pub mod fixtures;
 mod nar_renderer;
+mod nar_renderer_prefetching;
 mod nar_renderer_seekable;
+mod nix_daemon;
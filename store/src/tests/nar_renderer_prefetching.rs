@@ -0,0 +1,148 @@
+use crate::nar::write_nar_prefetching;
+use crate::tests::fixtures::*;
+use rstest::*;
+use rstest_reuse::*;
+use std::io;
+use std::sync::Arc;
+use tokio::io::sink;
+use tvix_castore::blobservice::BlobService;
+use tvix_castore::directoryservice::DirectoryService;
+use tvix_castore::Node;
+
+/// Make sure the prefetching renderer fails if a referred blob doesn't exist.
+#[rstest]
+#[tokio::test]
+async fn single_file_missing_blob(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let e = write_nar_prefetching(
+        sink(),
+        &CASTORE_NODE_HELLOWORLD,
+        // the blobservice is empty intentionally, to provoke the error.
+        blob_service,
+        directory_service,
+        4,
+    )
+    .await
+    .expect_err("must fail");
+
+    match e {
+        crate::nar::RenderError::NARWriterError(e) => {
+            assert_eq!(io::ErrorKind::NotFound, e.kind());
+        }
+        _ => panic!("unexpected error: {:?}", e),
+    }
+}
+
+#[apply(castore_fixtures_template)]
+#[tokio::test]
+async fn prefetching(
+    #[future] blob_service_with_contents: Arc<dyn BlobService>,
+    #[future] directory_service_with_contents: Arc<dyn DirectoryService>,
+    #[case] test_input: &Node,
+    #[case] test_output: Result<Result<&Vec<u8>, io::ErrorKind>, crate::nar::RenderError>,
+    #[values(1, 4)] prefetch_window: usize,
+) {
+    let blob_service = blob_service_with_contents.await;
+    let directory_service = directory_service_with_contents.await;
+
+    let mut buf: Vec<u8> = vec![];
+    let read_result = write_nar_prefetching(
+        &mut buf,
+        test_input,
+        blob_service,
+        directory_service,
+        prefetch_window,
+    )
+    .await;
+
+    match (read_result, test_output) {
+        (Ok(_), Err(_)) => panic!("creating reader should have failed but succeeded"),
+        (Ok(_), Ok(Err(_))) => panic!("creating reader should have failed but succeeded"),
+        (Err(err), Ok(Ok(_))) => {
+            panic!("creating reader should have succeeded but failed: {}", err)
+        }
+        (Err(reader_err), Err(expected_err)) => {
+            assert_eq!(format!("{}", reader_err), format!("{}", expected_err));
+        }
+        (Err(reader_err), Ok(Err(expected_err))) => {
+            let crate::nar::RenderError::NARWriterError(e) = reader_err else {
+                panic!("expected nar writer error")
+            };
+            assert_eq!(e.kind(), expected_err);
+        }
+        (Ok(_n), Ok(Ok(expected_read_result))) => {
+            assert_eq!(buf, expected_read_result.to_vec());
+        }
+    }
+}
+
+/// Blobs larger than what's fetched in one request are fetched in multiple
+/// ranges, and rendered the same as by [crate::nar::write_nar].
+#[rstest]
+#[tokio::test]
+async fn large_blob(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let contents: Vec<u8> = (0..9 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let mut writer = blob_service.open_write().await;
+    tokio::io::copy(&mut io::Cursor::new(&contents), &mut writer)
+        .await
+        .unwrap();
+    let node = Node::File {
+        digest: writer.close().await.unwrap(),
+        size: contents.len() as u64,
+        executable: false,
+    };
+
+    let mut expected = vec![];
+    crate::nar::write_nar(
+        &mut expected,
+        &node,
+        blob_service.clone(),
+        directory_service.clone(),
+    )
+    .await
+    .expect("must succeed");
+
+    let mut buf = vec![];
+    write_nar_prefetching(&mut buf, &node, blob_service, directory_service, 4)
+        .await
+        .expect("must succeed");
+
+    assert_eq!(expected, buf);
+}
+
+/// A node claiming a huge size doesn't make the renderer allocate that much
+/// memory, but fails once the blob ends.
+#[rstest]
+#[tokio::test]
+async fn huge_size(
+    #[future] blob_service_with_contents: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let node = Node::File {
+        digest: tvix_castore::fixtures::HELLOWORLD_BLOB_DIGEST.clone(),
+        size: u64::MAX / 2,
+        executable: false,
+    };
+
+    let e = write_nar_prefetching(
+        sink(),
+        &node,
+        blob_service_with_contents.await,
+        directory_service,
+        4,
+    )
+    .await
+    .expect_err("must fail");
+
+    match e {
+        crate::nar::RenderError::NARWriterError(e) => {
+            assert_eq!(io::ErrorKind::UnexpectedEof, e.kind());
+        }
+        _ => panic!("unexpected error: {:?}", e),
+    }
+}
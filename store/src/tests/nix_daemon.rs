@@ -0,0 +1,790 @@
+use crate::composition::{with_registry, DeserializeWithRegistry, ServiceBuilder, REG};
+use crate::nar::ingest_nar_and_hash;
+use crate::nix_daemon::{NixDaemon, NixDaemonLayer};
+use crate::path_info::deriver_from_drv_path;
+use crate::pathinfoservice::{
+    test_signing_service, MemoryPathInfoService, NixDaemonPathInfoService, PathInfo,
+    PathInfoService, DUMMY_VERIFYING_KEY,
+};
+use crate::tests::fixtures::*;
+use futures::TryStreamExt;
+use nix_compat::narinfo::VerifyingKey;
+use nix_compat::nix_daemon::de::{NixRead, NixReader};
+use nix_compat::nix_daemon::logger::{ActivityType, LogMessage, LoggerField, ResultType};
+use nix_compat::nix_daemon::ser::{NixWrite, NixWriter};
+use nix_compat::nix_daemon::UnkeyedValidPathInfo;
+use nix_compat::nixhash::{CAHash, NixHash};
+use nix_compat::store_path::{build_ca_path, StorePath};
+use nix_compat::worker_protocol::{Operation, Trust, STDERR_LAST};
+use nix_compat::ProtocolVersion;
+use rstest::*;
+use sha2::Digest;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+use tracing_subscriber::layer::SubscriberExt;
+use tvix_castore::blobservice::{BlobService, MemoryBlobService};
+use tvix_castore::directoryservice::{DirectoryService, MemoryDirectoryService};
+use tvix_castore::fixtures::HELLOWORLD_BLOB_DIGEST;
+
+type Client = (
+    NixReader<ReadHalf<DuplexStream>>,
+    NixWriter<WriteHalf<DuplexStream>>,
+);
+
+/// Spawns a daemon serving the passed services, and returns a client
+/// connection to it, after performing the handshake.
+async fn connect(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+    path_info_service: Arc<dyn PathInfoService>,
+) -> Client {
+    connect_version(
+        blob_service,
+        directory_service,
+        path_info_service,
+        ProtocolVersion::from_parts(1, 37),
+    )
+    .await
+}
+
+/// Like [connect], but for a client talking the passed protocol version.
+async fn connect_version(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+    path_info_service: Arc<dyn PathInfoService>,
+    version: ProtocolVersion,
+) -> Client {
+    connect_daemon(
+        NixDaemon::new(blob_service, directory_service, path_info_service),
+        version,
+        Trust::Trusted,
+    )
+    .await
+}
+
+/// Like [connect_version], but to the passed daemon, which treats the client
+/// according to `trust`.
+async fn connect_daemon(daemon: NixDaemon, version: ProtocolVersion, trust: Trust) -> Client {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let trust_level = match trust {
+        Trust::Trusted => 1,
+        Trust::NotTrusted => 2,
+    };
+    tokio::spawn(async move { daemon.handle_connection(server, trust).await });
+
+    let (r, w) = tokio::io::split(client);
+    let mut reader = NixReader::builder().set_version(version).build(r);
+    let mut writer = NixWriter::builder().set_version(version).build(w);
+
+    writer.write_number(0x6e697863).await.unwrap(); // "nixc"
+    writer.flush().await.unwrap();
+    assert_eq!(0x6478696f, reader.read_number().await.unwrap()); // "dxio"
+                                                                 // The daemon announces the newest version it supports, and both sides
+                                                                 // continue with the older of the two.
+    assert_eq!(
+        u64::from(ProtocolVersion::from_parts(1, 37)),
+        reader.read_number().await.unwrap()
+    );
+
+    writer.write_number(version.into()).await.unwrap();
+    writer.write_number(0).await.unwrap(); // no CPU affinity
+    writer.write_number(0).await.unwrap(); // reserveSpace
+    writer.flush().await.unwrap();
+
+    if version.minor() >= 33 {
+        let _nix_version: String = reader.read_value().await.unwrap();
+    }
+    if version.minor() >= 35 {
+        assert_eq!(trust_level, reader.read_number().await.unwrap());
+    }
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+
+    (reader, writer)
+}
+
+fn helloworld_info() -> UnkeyedValidPathInfo {
+    UnkeyedValidPathInfo {
+        deriver: None,
+        nar_hash: NixHash::Sha256(sha2::Sha256::digest(&*NAR_CONTENTS_HELLOWORLD).into()),
+        references: vec![],
+        registration_time: 0,
+        nar_size: NAR_CONTENTS_HELLOWORLD.len() as u64,
+        ultimate: false,
+        signatures: vec![],
+        ca: None,
+    }
+}
+
+/// Uploads a path with AddToStoreNar, and queries it back.
+#[rstest]
+#[tokio::test]
+async fn add_and_query(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let (mut reader, mut writer) =
+        connect(blob_service, directory_service, path_info_service.clone()).await;
+
+    // The path isn't valid yet.
+    writer
+        .write_number(Operation::IsValidPath as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+    assert!(!reader.read_value::<bool>().await.unwrap());
+
+    writer
+        .write_number(Operation::AddToStoreNar as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.write_value(&helloworld_info()).await.unwrap();
+    writer.write_value(&false).await.unwrap(); // repair
+    writer.write_value(&true).await.unwrap(); // dontCheckSigs
+    writer
+        .write_number(NAR_CONTENTS_HELLOWORLD.len() as u64)
+        .await
+        .unwrap();
+    writer
+        .get_mut()
+        .write_all(&NAR_CONTENTS_HELLOWORLD)
+        .await
+        .unwrap();
+    writer.write_number(0).await.unwrap(); // end of frames
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+
+    let path_info = path_info_service
+        .get(*DUMMY_PATH.digest())
+        .await
+        .unwrap()
+        .expect("must exist");
+    assert_eq!(*CASTORE_NODE_HELLOWORLD, path_info.node);
+
+    writer
+        .write_number(Operation::IsValidPath as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+    assert!(reader.read_value::<bool>().await.unwrap());
+
+    writer
+        .write_number(Operation::QueryPathInfo as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+    assert!(reader.read_value::<bool>().await.unwrap());
+    assert_eq!(
+        helloworld_info(),
+        reader.read_value::<UnkeyedValidPathInfo>().await.unwrap()
+    );
+
+    writer
+        .write_number(Operation::NarFromPath as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+    let mut nar = vec![0; NAR_CONTENTS_HELLOWORLD.len()];
+    reader.read_exact(&mut nar).await.unwrap();
+    assert_eq!(*NAR_CONTENTS_HELLOWORLD, nar);
+}
+
+/// Uploading a NAR not matching its NAR hash must fail, but leave the
+/// connection usable.
+#[rstest]
+#[tokio::test]
+async fn add_wrong_nar_hash(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let (mut reader, mut writer) =
+        connect(blob_service, directory_service, path_info_service.clone()).await;
+
+    writer
+        .write_number(Operation::AddToStoreNar as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer
+        .write_value(&UnkeyedValidPathInfo {
+            nar_hash: NixHash::Sha256([0; 32]),
+            ..helloworld_info()
+        })
+        .await
+        .unwrap();
+    writer.write_value(&false).await.unwrap(); // repair
+    writer.write_value(&true).await.unwrap(); // dontCheckSigs
+    writer
+        .write_number(NAR_CONTENTS_HELLOWORLD.len() as u64)
+        .await
+        .unwrap();
+    writer
+        .get_mut()
+        .write_all(&NAR_CONTENTS_HELLOWORLD)
+        .await
+        .unwrap();
+    writer.write_number(0).await.unwrap(); // end of frames
+    writer.flush().await.unwrap();
+
+    match reader.read_value::<LogMessage>().await.unwrap() {
+        LogMessage::Error(err) => {
+            assert!(
+                err.msg.contains("NAR hash mismatch"),
+                "unexpected error: {}",
+                err.msg
+            );
+        }
+        msg => panic!("unexpected message: {msg:?}"),
+    }
+
+    assert!(path_info_service
+        .get(*DUMMY_PATH.digest())
+        .await
+        .unwrap()
+        .is_none());
+
+    // The connection can still be used.
+    writer
+        .write_number(Operation::IsValidPath as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+    assert!(!reader.read_value::<bool>().await.unwrap());
+}
+
+/// Paths added by untrusted clients need to be signed by a trusted key, or be
+/// content-addressed, even if the client disables signature checks.
+#[rstest]
+#[case::trusted(Trust::Trusted, false, false, true)]
+#[case::untrusted(Trust::NotTrusted, false, false, false)]
+#[case::untrusted_signed(Trust::NotTrusted, true, false, true)]
+#[case::untrusted_ca(Trust::NotTrusted, false, true, true)]
+#[tokio::test]
+async fn add_untrusted(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+    #[case] trust: Trust,
+    #[case] signed: bool,
+    #[case] content_addressed: bool,
+    #[case] exp_succeed: bool,
+) {
+    let mut info = helloworld_info();
+    let NixHash::Sha256(nar_sha256) = info.nar_hash else {
+        unreachable!()
+    };
+    let path = if content_addressed {
+        info.ca = Some(CAHash::Nar(NixHash::Sha256(nar_sha256)));
+        build_ca_path(DUMMY_PATH.name(), info.ca.as_ref().unwrap(), [""; 0], false).unwrap()
+    } else {
+        DUMMY_PATH.clone()
+    };
+    if signed {
+        let node = CASTORE_NODE_HELLOWORLD.clone();
+        info.signatures = test_signing_service()
+            .put(PathInfo {
+                store_path: path.clone(),
+                node,
+                references: vec![],
+                nar_size: info.nar_size,
+                nar_sha256,
+                signatures: vec![],
+                deriver: None,
+                ca: None,
+            })
+            .await
+            .unwrap()
+            .signatures;
+    }
+
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let mut daemon = NixDaemon::new(blob_service, directory_service, path_info_service.clone());
+    daemon.set_public_keys(vec![VerifyingKey::parse(DUMMY_VERIFYING_KEY).unwrap()]);
+    let (mut reader, mut writer) =
+        connect_daemon(daemon, ProtocolVersion::from_parts(1, 37), trust).await;
+
+    writer
+        .write_number(Operation::AddToStoreNar as u64)
+        .await
+        .unwrap();
+    writer.write_value(&path).await.unwrap();
+    writer.write_value(&info).await.unwrap();
+    writer.write_value(&false).await.unwrap(); // repair
+    writer.write_value(&true).await.unwrap(); // dontCheckSigs
+    writer
+        .write_number(NAR_CONTENTS_HELLOWORLD.len() as u64)
+        .await
+        .unwrap();
+    writer
+        .get_mut()
+        .write_all(&NAR_CONTENTS_HELLOWORLD)
+        .await
+        .unwrap();
+    writer.write_number(0).await.unwrap(); // end of frames
+    writer.flush().await.unwrap();
+
+    match reader.read_value::<LogMessage>().await.unwrap() {
+        LogMessage::Last => assert!(exp_succeed, "adding must fail"),
+        LogMessage::Error(err) => {
+            assert!(!exp_succeed, "unexpected error: {}", err.msg);
+            assert!(
+                err.msg.contains("lacks a signature by a trusted key"),
+                "unexpected error: {}",
+                err.msg
+            );
+        }
+        msg => panic!("unexpected message: {msg:?}"),
+    }
+    assert_eq!(
+        exp_succeed,
+        path_info_service
+            .get(*path.digest())
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
+
+/// QueryReferrers and QueryValidDerivers are answered from the
+/// PathInfoService.
+#[rstest]
+#[tokio::test]
+async fn query_referrers_and_derivers(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let drv = path_info("foo.drv", 1, &CASTORE_NODE_HELLOWORLD, vec![]);
+    let dep = path_info("dep", 2, &CASTORE_NODE_HELLOWORLD, vec![]);
+    let out = PathInfo {
+        deriver: deriver_from_drv_path(&drv.store_path),
+        ..path_info(
+            "foo",
+            3,
+            &CASTORE_NODE_HELLOWORLD,
+            vec![dep.store_path.clone()],
+        )
+    };
+    // The .drv file producing this one isn't valid.
+    let out_without_drv = PathInfo {
+        deriver: Some(store_path("bar", 4)),
+        ..path_info("bar", 5, &CASTORE_NODE_HELLOWORLD, vec![])
+    };
+
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    for path_info in [&drv, &dep, &out, &out_without_drv] {
+        path_info_service.put(path_info.clone()).await.unwrap();
+    }
+    let (mut reader, mut writer) =
+        connect(blob_service, directory_service, path_info_service).await;
+
+    for (op, path, expected) in [
+        (Operation::QueryReferrers, &dep, &out),
+        (Operation::QueryValidDerivers, &out, &drv),
+    ] {
+        writer.write_number(op as u64).await.unwrap();
+        writer.write_value(&path.store_path).await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+        assert_eq!(
+            vec![expected.store_path.clone()],
+            reader.read_value::<Vec<StorePath<String>>>().await.unwrap()
+        );
+    }
+
+    writer
+        .write_number(Operation::QueryValidDerivers as u64)
+        .await
+        .unwrap();
+    writer
+        .write_value(&out_without_drv.store_path)
+        .await
+        .unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+    assert!(reader
+        .read_value::<Vec<StorePath<String>>>()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// Unsupported operations fail, but leave the connection usable if their
+/// arguments can be skipped over.
+#[rstest]
+#[tokio::test]
+async fn unsupported_operation(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let (mut reader, mut writer) =
+        connect(blob_service, directory_service, path_info_service).await;
+
+    writer
+        .write_number(Operation::AddSignatures as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer
+        .write_value(&vec!["foo:bar".to_string()])
+        .await
+        .unwrap();
+    writer.flush().await.unwrap();
+    match reader.read_value::<LogMessage>().await.unwrap() {
+        LogMessage::Error(err) => assert!(
+            err.msg.contains("AddSignatures is not supported"),
+            "unexpected error: {}",
+            err.msg
+        ),
+        msg => panic!("unexpected message: {msg:?}"),
+    }
+
+    writer
+        .write_number(Operation::IsValidPath as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+    assert!(!reader.read_value::<bool>().await.unwrap());
+
+    // Unknown operations are reported before closing the connection.
+    writer.write_number(1000).await.unwrap();
+    writer.flush().await.unwrap();
+    match reader.read_value::<LogMessage>().await.unwrap() {
+        LogMessage::Error(err) => assert!(
+            err.msg.contains("Invalid OP number 1000"),
+            "unexpected error: {}",
+            err.msg
+        ),
+        msg => panic!("unexpected message: {msg:?}"),
+    }
+}
+
+/// Queries a tvix nix-daemon with [NixDaemonPathInfoService], which ingests
+/// the path into its own blob and directory services.
+#[rstest]
+#[tokio::test]
+async fn path_info_service_client(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    // populate the daemon side.
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let (node, nar_sha256, nar_size) = ingest_nar_and_hash(
+        blob_service.clone(),
+        directory_service.clone(),
+        &mut std::io::Cursor::new(&*NAR_CONTENTS_HELLOWORLD),
+    )
+    .await
+    .unwrap();
+    let path_info = PathInfo {
+        store_path: DUMMY_PATH.clone(),
+        node,
+        references: vec![],
+        nar_size,
+        nar_sha256,
+        signatures: vec![],
+        deriver: None,
+        ca: None,
+    };
+    path_info_service.put(path_info.clone()).await.unwrap();
+
+    let tmpdir = tempfile::TempDir::new().unwrap();
+    let socket_path = tmpdir.path().join("socket");
+    let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+    tokio::spawn(
+        NixDaemon::new(blob_service, directory_service, path_info_service).serve(listener),
+    );
+
+    let client_blob_service: Arc<dyn BlobService> = Arc::new(MemoryBlobService::default());
+    let client_directory_service: Arc<dyn DirectoryService> =
+        Arc::new(MemoryDirectoryService::default());
+    let client = NixDaemonPathInfoService::new(
+        socket_path,
+        client_blob_service.clone(),
+        client_directory_service,
+    );
+
+    assert_eq!(
+        Some(&path_info),
+        client.get(*DUMMY_PATH.digest()).await.unwrap().as_ref()
+    );
+    assert!(client_blob_service
+        .has(&HELLOWORLD_BLOB_DIGEST)
+        .await
+        .unwrap());
+
+    assert_eq!(None, client.get([1; 20]).await.unwrap());
+
+    let listed: Vec<PathInfo> = client.list().try_collect().await.unwrap();
+    assert_eq!(vec![path_info], listed);
+}
+
+/// With [NixDaemonLayer] registered, the span of the operation is sent to the
+/// client as an activity, before the result.
+#[rstest]
+#[tokio::test]
+async fn forward_activities(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    // The test runtime is single-threaded, so this covers the daemon task.
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(NixDaemonLayer::default()),
+    );
+
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let (mut reader, mut writer) =
+        connect(blob_service, directory_service, path_info_service).await;
+
+    writer
+        .write_number(Operation::IsValidPath as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.flush().await.unwrap();
+
+    let id = match reader.read_value::<LogMessage>().await.unwrap() {
+        LogMessage::StartActivity(activity) => {
+            assert!(
+                activity.text.starts_with("handle_op"),
+                "unexpected activity: {activity:?}"
+            );
+            assert_eq!(0, activity.parent);
+            activity.id
+        }
+        msg => panic!("unexpected message: {msg:?}"),
+    };
+    assert_eq!(
+        LogMessage::StopActivity(id),
+        reader.read_value().await.unwrap()
+    );
+    assert_eq!(LogMessage::Last, reader.read_value().await.unwrap());
+    assert!(!reader.read_value::<bool>().await.unwrap());
+}
+
+/// Added paths are reported as CopyPath activities, with their progress.
+#[rstest]
+#[tokio::test]
+async fn forward_copy_path_progress(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(NixDaemonLayer::default()),
+    );
+
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let (mut reader, mut writer) =
+        connect(blob_service, directory_service, path_info_service).await;
+
+    writer
+        .write_number(Operation::AddToStoreNar as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.write_value(&helloworld_info()).await.unwrap();
+    writer.write_value(&false).await.unwrap(); // repair
+    writer.write_value(&true).await.unwrap(); // dontCheckSigs
+    writer
+        .write_number(NAR_CONTENTS_HELLOWORLD.len() as u64)
+        .await
+        .unwrap();
+    writer
+        .get_mut()
+        .write_all(&NAR_CONTENTS_HELLOWORLD)
+        .await
+        .unwrap();
+    writer.write_number(0).await.unwrap(); // end of frames
+    writer.flush().await.unwrap();
+
+    let mut messages = vec![];
+    loop {
+        match reader.read_value::<LogMessage>().await.unwrap() {
+            LogMessage::Last => break,
+            msg => messages.push(msg),
+        }
+    }
+
+    let activity = messages
+        .iter()
+        .find_map(|msg| match msg {
+            LogMessage::StartActivity(activity)
+                if activity.activity_type == ActivityType::CopyPath =>
+            {
+                Some(activity)
+            }
+            _ => None,
+        })
+        .expect("CopyPath activity must be started");
+    assert_eq!(
+        format!(
+            "copying path '{}' to tvix-store",
+            DUMMY_PATH.to_absolute_path()
+        ),
+        activity.text
+    );
+    assert_eq!(
+        vec![
+            LoggerField::String(DUMMY_PATH.to_absolute_path()),
+            LoggerField::String("client".to_string()),
+            LoggerField::String("tvix-store".to_string()),
+        ],
+        activity.fields
+    );
+
+    let nar_size = NAR_CONTENTS_HELLOWORLD.len() as u64;
+    let progress: Vec<_> = messages
+        .iter()
+        .filter_map(|msg| match msg {
+            LogMessage::Result(result) if result.id == activity.id => {
+                assert_eq!(ResultType::Progress, result.result_type);
+                Some(result.fields.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            [0, nar_size, 0, 0].map(LoggerField::Int).to_vec(),
+            [nar_size, nar_size, 0, 0].map(LoggerField::Int).to_vec(),
+        ],
+        progress
+    );
+    assert!(messages.contains(&LogMessage::StopActivity(activity.id)));
+}
+
+/// Nix 2.3 clients talk protocol version 1.21, and send NARs only when
+/// requested by the daemon.
+#[rstest]
+#[tokio::test]
+async fn add_and_query_nix_2_3(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let (mut reader, mut writer) = connect_version(
+        blob_service,
+        directory_service,
+        path_info_service.clone(),
+        ProtocolVersion::from_parts(1, 21),
+    )
+    .await;
+
+    writer
+        .write_number(Operation::AddToStoreNar as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.write_value(&helloworld_info()).await.unwrap();
+    writer.write_value(&false).await.unwrap(); // repair
+    writer.write_value(&true).await.unwrap(); // dontCheckSigs
+    writer.flush().await.unwrap();
+
+    let mut nar = &NAR_CONTENTS_HELLOWORLD[..];
+    loop {
+        match reader.read_value::<LogMessage>().await.unwrap() {
+            LogMessage::Read(len) => {
+                let (chunk, rest) = nar.split_at((len as usize).min(nar.len()));
+                nar = rest;
+                writer.write_slice(chunk).await.unwrap();
+                writer.flush().await.unwrap();
+            }
+            LogMessage::Last => break,
+            msg => panic!("unexpected message: {msg:?}"),
+        }
+    }
+    assert!(nar.is_empty(), "NAR must be read completely");
+
+    writer
+        .write_number(Operation::QueryPathInfo as u64)
+        .await
+        .unwrap();
+    writer.write_value(&*DUMMY_PATH).await.unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(STDERR_LAST, reader.read_number().await.unwrap());
+    assert!(reader.read_value::<bool>().await.unwrap());
+    assert_eq!(
+        helloworld_info(),
+        reader.read_value::<UnkeyedValidPathInfo>().await.unwrap()
+    );
+}
+
+/// Operations newer than the negotiated protocol version are rejected.
+#[rstest]
+#[tokio::test]
+async fn reject_newer_operation(
+    blob_service: Arc<dyn BlobService>,
+    directory_service: Arc<dyn DirectoryService>,
+) {
+    let path_info_service: Arc<dyn PathInfoService> = Arc::new(MemoryPathInfoService::default());
+    let (mut reader, mut writer) = connect_version(
+        blob_service,
+        directory_service,
+        path_info_service,
+        ProtocolVersion::from_parts(1, 21),
+    )
+    .await;
+
+    writer
+        .write_number(Operation::AddMultipleToStore as u64)
+        .await
+        .unwrap();
+    writer.flush().await.unwrap();
+
+    match reader.read_value::<LogMessage>().await.unwrap() {
+        LogMessage::Error(err) => assert!(
+            err.msg.contains("not supported for protocol version 1.21"),
+            "unexpected error: {}",
+            err.msg
+        ),
+        msg => panic!("unexpected message: {msg:?}"),
+    }
+}
+
+/// In composition configs, the `nix` type always describes a binary cache,
+/// and daemons need the `nix-daemon` type.
+#[rstest]
+#[case::daemon(serde_json::json!({
+    "type": "nix-daemon",
+    "socket_path": "/nix/var/nix/daemon-socket/socket",
+    "blob_service": "default",
+    "directory_service": "default",
+}), true)]
+#[case::daemon_as_nix(serde_json::json!({
+    "type": "nix",
+    "socket_path": "/nix/var/nix/daemon-socket/socket",
+    "blob_service": "default",
+    "directory_service": "default",
+}), false)]
+#[case::http(serde_json::json!({
+    "type": "nix",
+    "base_url": "https://cache.nixos.org",
+    "blob_service": "default",
+    "directory_service": "default",
+}), true)]
+#[case::http_as_daemon(serde_json::json!({
+    "type": "nix-daemon",
+    "base_url": "https://cache.nixos.org",
+    "blob_service": "default",
+    "directory_service": "default",
+}), false)]
+fn composition_config_type(#[case] config: serde_json::Value, #[case] exp_succeed: bool) {
+    let config: Result<
+        DeserializeWithRegistry<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>>,
+        _,
+    > = with_registry(&REG, || serde_json::from_value(config));
+    assert_eq!(exp_succeed, config.is_ok());
+}
@@ -6,14 +6,19 @@ use std::{
 };
 use tokio::io::{self, AsyncWrite};
 
-use tvix_castore::{blobservice::BlobService, directoryservice::DirectoryService};
+use tvix_castore::{
+    blobmetadataservice::BlobMetadataService, blobservice::BlobService, chunkservice::ChunkService,
+    directoryservice::DirectoryService,
+};
 use url::Url;
 
+use crate::buildlogservice::BuildLogService;
 use crate::composition::{
     with_registry, Composition, DeserializeWithRegistry, ServiceBuilder, REG,
 };
 use crate::nar::{NarCalculationService, SimpleRenderer};
 use crate::pathinfoservice::PathInfoService;
+use crate::realisationservice::RealisationService;
 
 #[derive(serde::Deserialize, Default)]
 pub struct CompositionConfigs {
@@ -27,6 +32,39 @@ pub struct CompositionConfigs {
         String,
         DeserializeWithRegistry<Box<dyn ServiceBuilder<Output = dyn PathInfoService>>>,
     >,
+    /// Only needed when composing a `chunked` blobservice.
+    #[serde(default)]
+    pub blobmetadataservices: HashMap<
+        String,
+        DeserializeWithRegistry<Box<dyn ServiceBuilder<Output = dyn BlobMetadataService>>>,
+    >,
+    /// Only needed when composing a `chunked` blobservice.
+    #[serde(default)]
+    pub chunkservices: HashMap<
+        String,
+        DeserializeWithRegistry<Box<dyn ServiceBuilder<Output = dyn ChunkService>>>,
+    >,
+    /// If a "default" entry exists, it's used to calculate NAR hashes and
+    /// sizes, otherwise this is picked based on the PathInfoService.
+    #[serde(default)]
+    pub narcalculationservices: HashMap<
+        String,
+        DeserializeWithRegistry<Box<dyn ServiceBuilder<Output = dyn NarCalculationService>>>,
+    >,
+    /// Only needed by nar-bridge, see
+    /// [construct_build_log_and_realisation_services].
+    #[serde(default)]
+    pub buildlogservices: HashMap<
+        String,
+        DeserializeWithRegistry<Box<dyn ServiceBuilder<Output = dyn BuildLogService>>>,
+    >,
+    /// Only needed by nar-bridge, see
+    /// [construct_build_log_and_realisation_services].
+    #[serde(default)]
+    pub realisationservices: HashMap<
+        String,
+        DeserializeWithRegistry<Box<dyn ServiceBuilder<Output = dyn RealisationService>>>,
+    >,
 }
 
 /// Provides a set clap arguments to configure tvix-[ca]store services.
@@ -103,6 +141,55 @@ pub struct ServiceUrlsMemory {
     experimental_store_composition: Option<String>,
 }
 
+/// Provides a set clap arguments to configure the tvix-[ca]store services to
+/// copy from.
+///
+/// The defaults match [ServiceUrls], accessing data directly locally.
+#[derive(clap::Parser, Clone)]
+pub struct ServiceUrlsFrom {
+    #[arg(
+        long,
+        env,
+        default_value = "objectstore+file:///var/lib/tvix-store/blobs.object_store"
+    )]
+    from_blob_service_addr: String,
+
+    #[arg(
+        long,
+        env,
+        default_value = "redb:///var/lib/tvix-store/directories.redb"
+    )]
+    from_directory_service_addr: String,
+
+    #[arg(long, env, default_value = "redb:///var/lib/tvix-store/pathinfo.redb")]
+    from_path_info_service_addr: String,
+
+    #[cfg(feature = "xp-store-composition")]
+    #[arg(long, env)]
+    from_experimental_store_composition: Option<String>,
+}
+
+/// Provides a set clap arguments to configure the tvix-[ca]store services to
+/// copy to.
+///
+/// The defaults match [ServiceUrlsGrpc], accessing data from another running
+/// tvix daemon.
+#[derive(clap::Parser, Clone)]
+pub struct ServiceUrlsTo {
+    #[arg(long, env, default_value = "grpc+http://[::1]:8000")]
+    to_blob_service_addr: String,
+
+    #[arg(long, env, default_value = "grpc+http://[::1]:8000")]
+    to_directory_service_addr: String,
+
+    #[arg(long, env, default_value = "grpc+http://[::1]:8000")]
+    to_path_info_service_addr: String,
+
+    #[cfg(feature = "xp-store-composition")]
+    #[arg(long, env)]
+    to_experimental_store_composition: Option<String>,
+}
+
 impl From<ServiceUrlsGrpc> for ServiceUrls {
     fn from(urls: ServiceUrlsGrpc) -> ServiceUrls {
         ServiceUrls {
@@ -127,6 +214,30 @@ impl From<ServiceUrlsMemory> for ServiceUrls {
     }
 }
 
+impl From<ServiceUrlsFrom> for ServiceUrls {
+    fn from(urls: ServiceUrlsFrom) -> ServiceUrls {
+        ServiceUrls {
+            blob_service_addr: urls.from_blob_service_addr,
+            directory_service_addr: urls.from_directory_service_addr,
+            path_info_service_addr: urls.from_path_info_service_addr,
+            #[cfg(feature = "xp-store-composition")]
+            experimental_store_composition: urls.from_experimental_store_composition,
+        }
+    }
+}
+
+impl From<ServiceUrlsTo> for ServiceUrls {
+    fn from(urls: ServiceUrlsTo) -> ServiceUrls {
+        ServiceUrls {
+            blob_service_addr: urls.to_blob_service_addr,
+            directory_service_addr: urls.to_directory_service_addr,
+            path_info_service_addr: urls.to_path_info_service_addr,
+            #[cfg(feature = "xp-store-composition")]
+            experimental_store_composition: urls.to_experimental_store_composition,
+        }
+    }
+}
+
 pub async fn addrs_to_configs(
     urls: impl Into<ServiceUrls>,
 ) -> Result<CompositionConfigs, Box<dyn std::error::Error + Send + Sync>> {
@@ -190,25 +301,36 @@ pub async fn construct_services_from_configs(
 > {
     let mut comp = Composition::new(&REG);
 
+    let has_nar_calculation_service = configs.narcalculationservices.contains_key("default");
+
     comp.extend(configs.blobservices);
     comp.extend(configs.directoryservices);
     comp.extend(configs.pathinfoservices);
+    comp.extend(configs.blobmetadataservices);
+    comp.extend(configs.chunkservices);
+    comp.extend(configs.narcalculationservices);
 
     let blob_service: Arc<dyn BlobService> = comp.build("default").await?;
     let directory_service: Arc<dyn DirectoryService> = comp.build("default").await?;
     let path_info_service: Arc<dyn PathInfoService> = comp.build("default").await?;
 
-    // HACK: The grpc client also implements NarCalculationService, and we
+    // If a NarCalculationService was configured explicitly, use that.
+    // HACK: Otherwise, the grpc client also implements NarCalculationService, and we
     // really want to use it (otherwise we'd need to fetch everything again for hashing).
     // Until we revamped store composition and config, detect this special case here.
-    let nar_calculation_service: Box<dyn NarCalculationService> = path_info_service
-        .nar_calculation_service()
-        .unwrap_or_else(|| {
-            Box::new(SimpleRenderer::new(
-                blob_service.clone(),
-                directory_service.clone(),
-            ))
-        });
+    let nar_calculation_service: Box<dyn NarCalculationService> = if has_nar_calculation_service {
+        let nar_calculation_service: Arc<dyn NarCalculationService> = comp.build("default").await?;
+        Box::new(nar_calculation_service)
+    } else {
+        path_info_service
+            .nar_calculation_service()
+            .unwrap_or_else(|| {
+                Box::new(SimpleRenderer::new(
+                    blob_service.clone(),
+                    directory_service.clone(),
+                ))
+            })
+    };
 
     Ok((
         blob_service,
@@ -218,6 +340,45 @@ pub async fn construct_services_from_configs(
     ))
 }
 
+/// Construct the BuildLogService and RealisationService named "default" in
+/// the configs, taking them out of it.
+/// If there's no such entry, the passed addrs are used instead.
+pub async fn construct_build_log_and_realisation_services(
+    configs: &mut CompositionConfigs,
+    build_log_service_addr: &str,
+    realisation_service_addr: &str,
+) -> Result<
+    (Arc<dyn BuildLogService>, Arc<dyn RealisationService>),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let mut buildlogservices = std::mem::take(&mut configs.buildlogservices);
+    if !buildlogservices.contains_key("default") {
+        let build_log_service_url = Url::parse(build_log_service_addr)?;
+        buildlogservices.insert(
+            "default".into(),
+            with_registry(&REG, || build_log_service_url.try_into())?,
+        );
+    }
+
+    let mut realisationservices = std::mem::take(&mut configs.realisationservices);
+    if !realisationservices.contains_key("default") {
+        let realisation_service_url = Url::parse(realisation_service_addr)?;
+        realisationservices.insert(
+            "default".into(),
+            with_registry(&REG, || realisation_service_url.try_into())?,
+        );
+    }
+
+    let mut comp = Composition::new(&REG);
+    comp.extend(buildlogservices);
+    comp.extend(realisationservices);
+
+    let build_log_service: Arc<dyn BuildLogService> = comp.build("default").await?;
+    let realisation_service: Arc<dyn RealisationService> = comp.build("default").await?;
+
+    Ok((build_log_service, realisation_service))
+}
+
 /// The inverse of [tokio_util::io::SyncIoBridge].
 /// Don't use this with anything that actually does blocking I/O.
 pub struct AsyncIoBridge<T>(pub T);
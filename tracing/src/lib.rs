@@ -3,7 +3,9 @@ use std::sync::LazyLock;
 use tokio::sync::{mpsc, oneshot};
 use tracing::Level;
 use tracing_indicatif::{filter::IndicatifFilter, writer, IndicatifLayer, IndicatifWriter};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+use tracing_subscriber::{
+    layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
 
 #[cfg(feature = "otlp")]
 use opentelemetry::{
@@ -146,6 +148,7 @@ impl TracingHandle {
 pub struct TracingBuilder {
     level: Level,
     progess_bar: bool,
+    layers: Vec<Box<dyn Layer<Registry> + Send + Sync>>,
 
     #[cfg(feature = "otlp")]
     service_name: Option<&'static str>,
@@ -156,6 +159,7 @@ impl Default for TracingBuilder {
         TracingBuilder {
             level: Level::INFO,
             progess_bar: false,
+            layers: Vec::new(),
 
             #[cfg(feature = "otlp")]
             service_name: None,
@@ -184,6 +188,15 @@ impl TracingBuilder {
         self
     }
 
+    /// Add an additional layer, receiving all spans and events passing the EnvFilter.
+    pub fn with_layer<L>(mut self, layer: L) -> TracingBuilder
+    where
+        L: Layer<Registry> + Send + Sync + 'static,
+    {
+        self.layers.push(layer.boxed());
+        self
+    }
+
     /// This will setup tracing based on the configuration passed in.
     /// It will setup a stderr writer output layer and a EnvFilter based on the provided log
     /// level (RUST_LOG still has a higher priority over the configured value).
@@ -197,6 +210,7 @@ impl TracingBuilder {
         let stdout_writer = indicatif_layer.get_stdout_writer();
         let stderr_writer = indicatif_layer.get_stderr_writer();
         let subscriber = tracing_subscriber::registry()
+            .with(self.layers)
             .with(
                 EnvFilter::builder()
                     .with_default_directive(self.level.into())